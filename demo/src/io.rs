@@ -74,12 +74,16 @@ pub fn load_obj<P: AsRef<Path>>(path: P) -> nih::render::MeshData {
         }
         let tris_count = (mesh.positions.len() - start) / 3;
         mesh.sections.push(MeshDataSection {
+            name: model.objects[0].geometry[geometry]
+                .material_name
+                .clone()
+                .unwrap_or_default(),
             start_index: start,
             num_triangles: tris_count,
             material_index: 0, // TODO: materials
         });
     }
-    mesh.aabb = AABB::from_points(&mesh.positions);
+    mesh.update_aabb();
     mesh
 }
 
@@ -95,6 +99,12 @@ pub fn load_texture<P: AsRef<Path>>(path: P) -> std::sync::Arc<nih::render::Text
         pixels[((x + (height - 1 - y) * width) * 3 + 1) as usize] = pixel.0[1];
         pixels[((x + (height - 1 - y) * width) * 3 + 2) as usize] = pixel.0[2];
     }
-    let src = TextureSource { width: width, height: height, format: TextureFormat::RGB, texels: &pixels };
+    let src = TextureSource {
+        width: width,
+        height: height,
+        format: TextureFormat::RGB,
+        texels: &pixels,
+        ..Default::default()
+    };
     Texture::new(&src)
 }
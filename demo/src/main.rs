@@ -1,6 +1,7 @@
 extern crate sdl3;
 
 use image::RgbaImage;
+use nih::geometry::LODGroup;
 use nih::math::*;
 use nih::render::*;
 use nih::util::*;
@@ -38,6 +39,7 @@ struct State {
     mesh: MeshData,
     mesh2: MeshData,
     meshes: HashMap<String, MeshData>,
+    teapot3_lods: LODGroup,
     textures: HashMap<String, std::sync::Arc<Texture>>,
     texture_filtering: SamplerFilter,
     display_mode: DisplayMode,
@@ -59,6 +61,7 @@ impl Default for State {
             mesh: MeshData::default(),
             mesh2: MeshData::default(),
             meshes: HashMap::new(),
+            teapot3_lods: LODGroup::default(),
             textures: HashMap::new(),
             texture_filtering: SamplerFilter::Bilinear,
             display_mode: DisplayMode::Color,
@@ -108,7 +111,7 @@ fn blit_depth_to_window(buffer: &Buffer<u16>, window: &sdl3::video::Window, even
             for x in 0..buffer.width {
                 let offset = y as usize * pitch + x as usize * 4;
                 let depth = buffer.at(x, y);
-                if depth == 65535 {
+                if depth == DEPTH_FAR {
                     // 255u8
                     pixels[offset + 0] = 255; // R
                     pixels[offset + 1] = 200; // G
@@ -164,9 +167,13 @@ fn render(state: &mut State) {
     // buf.fill(RGBA::new((tick % 256) as u8, 255, 0, 255));
 
     // (64, 224, 208)
-    state.color_buffer.fill(RGBA::new(64, 224, 208, 255).to_u32());
-    state.depth_buffer.fill(u16::MAX);
-    state.normal_buffer.fill(RGBA::new(127, 255, 127, 255).to_u32());
+    Framebuffer {
+        color_buffer: Some(&mut state.color_buffer),
+        depth_buffer: Some(&mut state.depth_buffer),
+        normal_buffer: Some(&mut state.normal_buffer),
+        ..Default::default()
+    }
+    .clear_all(&ClearValues { color: RGBA::new(64, 224, 208, 255), ..Default::default() });
 
     let viewport = Viewport { xmin: 0, ymin: 0, xmax: state.color_buffer.width(), ymax: state.color_buffer.height() };
     let rasterizer = &mut state.rasterizer;
@@ -178,6 +185,7 @@ fn render(state: &mut State) {
         width: 2,
         height: 2,
         format: TextureFormat::Grayscale,
+        ..Default::default()
     });
 
     // let lines = vec![
@@ -325,18 +333,19 @@ fn render(state: &mut State) {
             }
         }
         {
-            let mesh = state.meshes.get("Teapot3").unwrap();
+            cmd.model = Mat34::translate(Vec3::new(0.0, -3.0, -10.0))
+                // * Mat34::rotate_zx(state.t.as_secs_f32() / 1.10)
+                * Mat34::rotate_zx(state.t.as_secs_f32() / 4.10)
+                // * Mat34::translate(Vec3::new(0.0, 0.0, -state.t.as_secs_f32().cos() * 8.0 - 4.0) )
+                * Mat34::scale_uniform(0.08);
+            let mesh = &state.teapot3_lods.level_for(&cmd.model, &cmd.view, &cmd.projection).unwrap().mesh;
             cmd.world_positions = &mesh.positions;
             cmd.normals = &mesh.normals;
             cmd.tex_coords = &mesh.tex_coords;
             // cmd.texture = Some(texture.clone());
             cmd.texture = Some(state.textures.get("Teapot3").unwrap().clone());
             cmd.indices = &mesh.indices;
-            cmd.model = Mat34::translate(Vec3::new(0.0, -3.0, -10.0))
-                // * Mat34::rotate_zx(state.t.as_secs_f32() / 1.10)
-                * Mat34::rotate_zx(state.t.as_secs_f32() / 4.10)
-                // * Mat34::translate(Vec3::new(0.0, 0.0, -state.t.as_secs_f32().cos() * 8.0 - 4.0) )
-                * Mat34::scale_uniform(0.08);
+            cmd.aabb = Some(mesh.aabb);
             let _profile_commit_scope = profiler::ProfileScope::new("commit", &profiler);
             rasterizer.commit(&cmd);
         }
@@ -371,6 +380,8 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     state
         .meshes
         .insert("Teapot3".to_string(), io::load_obj(Path::new(env!("CARGO_MANIFEST_DIR")).join("res/Teapot3.obj")));
+    // The simplified versions of the teapot are drawn when it's far away
+    state.teapot3_lods = LODGroup::generate(state.meshes.get("Teapot3").unwrap().clone().into_shared(), 4);
     state
         .textures
         .insert("Teapot3".to_string(), io::load_texture(Path::new(env!("CARGO_MANIFEST_DIR")).join("res/Teapot3.jpg")));
@@ -401,7 +412,8 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
                     state.texture_filtering = match state.texture_filtering {
                         SamplerFilter::Nearest => SamplerFilter::Bilinear,
                         SamplerFilter::Bilinear => SamplerFilter::Trilinear,
-                        SamplerFilter::Trilinear => SamplerFilter::DebugMip,
+                        SamplerFilter::Trilinear => SamplerFilter::Anisotropic,
+                        SamplerFilter::Anisotropic => SamplerFilter::DebugMip,
                         _ => SamplerFilter::Nearest,
                     };
                 }
@@ -452,12 +464,14 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
             // profiler.reset();
             let size = window.size();
             let title = format!(
-                "({}x{})px, {} tiles, tri_comm: {}, tri_sched: {}, tri_binn: {}, rast_frags: {}, FPS: {:.0}",
+                "({}x{})px, {} tiles, tri_comm: {}, tri_sched: {}, tri_micro: {}, vc_hits: {:.0}%, tri_binn: {}, rast_frags: {}, FPS: {:.0}",
                 size.0,
                 size.1,
                 state.color_buffer.tiles_x() * state.color_buffer.tiles_y(),
                 state.rasterizer_stats.committed_triangles,
                 state.rasterizer_stats.scheduled_triangles,
+                state.rasterizer_stats.micro_triangles,
+                state.rasterizer_stats.vertex_cache_hit_rate() * 100.0,
                 state.rasterizer_stats.binned_triangles,
                 state.rasterizer_stats.fragments_drawn,
                 1.0 / state.dt.as_secs_f32()
@@ -29,7 +29,13 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         let width = image.width();
         let height = image.height();
         let texels: Vec<u8> = image.pixels().flat_map(|p| p.0[..4].iter().copied()).collect();
-        Texture::new(&TextureSource { width, height, format: TextureFormat::RGBA, texels: &texels })
+        Texture::new(&TextureSource {
+            width,
+            height,
+            format: TextureFormat::RGBA,
+            texels: &texels,
+            ..Default::default()
+        })
     };
     let ground_texture = {
         let image = image::open(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("res/ground.jpg"))
@@ -38,7 +44,13 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         let width = image.width();
         let height = image.height();
         let texels: Vec<u8> = image.pixels().flat_map(|p| p.0[..3].iter().copied()).collect();
-        Texture::new(&TextureSource { width, height, format: TextureFormat::RGB, texels: &texels })
+        Texture::new(&TextureSource {
+            width,
+            height,
+            format: TextureFormat::RGB,
+            texels: &texels,
+            ..Default::default()
+        })
     };
 
     let quad_positions = [
@@ -103,6 +115,34 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut t = 0.0;
     let mut apply_lighting: bool = true;
     let light_dir_neg = -(Vec3::new(-1.0, -1.0, -1.0).normalized());
+    let mut lighting = PostProcessChain::new();
+    lighting.add_fn(
+        &[FramebufferComponent::Depth, FramebufferComponent::Normals],
+        &[FramebufferComponent::Color],
+        false,
+        move |source, tile| {
+            let depth_buffer = source.depth_buffer.unwrap();
+            let normal_buffer = source.normal_buffer.unwrap();
+            let (origin_x, origin_y) = (tile.origin_x(), tile.origin_y());
+            let color_tile = tile.color_buffer.as_mut().unwrap();
+            for y in 0..color_tile.height as usize {
+                for x in 0..color_tile.width as usize {
+                    let (px, py) = (origin_x + x as u16, origin_y + y as u16);
+                    if depth_buffer.at(px, py) == DEPTH_FAR {
+                        continue;
+                    }
+                    let normal: Vec3 = decode_normal_from_color(RGBA::from_u32(normal_buffer.at(px, py)));
+                    let ambient: f32 = 0.6;
+                    let diffuse: f32 = 0.6 * dot(normal, light_dir_neg).max(0.0);
+                    let color_rgba: RGBA = RGBA::from_u32(color_tile.at_unchecked(x, y));
+                    let color_vec: Vec3 = Vec3::new(color_rgba.r as f32, color_rgba.g as f32, color_rgba.b as f32);
+                    let color_lit: Vec3 = (color_vec * (diffuse + ambient)).min(255.0);
+                    let final_color: RGBA = RGBA::new(color_lit.x as u8, color_lit.y as u8, color_lit.z as u8, 255);
+                    *color_tile.get_unchecked(x, y) = final_color.to_u32();
+                }
+            }
+        },
+    );
     let mut show_normals: bool = false;
     let mut show_wireframe: bool = false;
     let mut paused = false;
@@ -177,25 +217,31 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
             depth_buffer = TiledBuffer::<u16, 64, 64>::new(size.0 as u16, size.1 as u16);
             rasterizer.setup(Viewport::new(0, 0, size.0 as u16, size.1 as u16));
         }
-        color_buffer.fill(RGBA::new(102, 204, 255, 255).to_u32());
-        normal_buffer.fill(RGBA::new(127, 255, 127, 255).to_u32());
-        depth_buffer.fill(u16::MAX);
+        Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            normal_buffer: Some(&mut normal_buffer),
+            depth_buffer: Some(&mut depth_buffer),
+            ..Default::default()
+        }
+        .clear_all(&ClearValues { color: RGBA::new(102, 204, 255, 255), ..Default::default() });
         rasterizer.reset();
         rasterizer.set_draw_wireframe(show_wireframe);
 
         // Commit the draw commands
         let projection = Mat44::perspective(1.0, 100.0, std::f32::consts::PI / 3.0, size.0 as f32 / size.1 as f32);
         let view: Mat44 = Mat44::translate(Vec3::new(0.0, -2.0, -35.0)) * Mat44::rotate_yz(0.5);
+        let fog: Option<Fog> = Some(Fog::new(FogMode::Exp2 { density: 0.02 }, Vec3::new(0.4, 0.8, 1.0)));
 
         // Draw the ground plane
         rasterizer.commit(&RasterizationCommand {
             world_positions: &quad_positions,
             tex_coords: &quad_tex_coords,
             texture: Some(ground_texture.clone()),
-            sampling_filter: SamplerFilter::Bilinear,
+            sampling_filter: SamplerFilter::Anisotropic,
             projection,
             view,
             model: Mat34::translate(Vec3::new(0.0, 0.0, 0.0)) * Mat34::rotate_yz(-1.57) * Mat34::scale_uniform(50.0),
+            fog,
             ..Default::default()
         });
 
@@ -210,6 +256,7 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
             sampling_filter: SamplerFilter::Bilinear,
             projection,
             view,
+            fog,
             ..Default::default()
         });
 
@@ -218,31 +265,13 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
             color_buffer: Some(&mut color_buffer),
             normal_buffer: Some(&mut normal_buffer),
             depth_buffer: Some(&mut depth_buffer),
+            ..Default::default()
         };
         rasterizer.draw(&mut framebuffer);
 
         // Apply basic lighting
         if apply_lighting {
-            framebuffer.for_each_tile_mut_parallel(move |tile| {
-                let depth_tile = tile.depth_buffer.as_mut().unwrap();
-                let color_tile = tile.color_buffer.as_mut().unwrap();
-                let normal_tile = tile.normal_buffer.as_mut().unwrap();
-                for y in 0..depth_tile.height as usize {
-                    for x in 0..depth_tile.width as usize {
-                        if depth_tile.at_unchecked(x, y) == u16::MAX {
-                            continue;
-                        }
-                        let normal: Vec3 = decode_normal_from_color(RGBA::from_u32(normal_tile.at_unchecked(x, y)));
-                        let ambient: f32 = 0.6;
-                        let diffuse: f32 = 0.6 * dot(normal, light_dir_neg).max(0.0);
-                        let color_rgba: RGBA = RGBA::from_u32(color_tile.at_unchecked(x, y));
-                        let color_vec: Vec3 = Vec3::new(color_rgba.r as f32, color_rgba.g as f32, color_rgba.b as f32);
-                        let color_lit: Vec3 = (color_vec * (diffuse + ambient)).min(255.0);
-                        let final_color: RGBA = RGBA::new(color_lit.x as u8, color_lit.y as u8, color_lit.z as u8, 255);
-                        *color_tile.get_unchecked(x, y) = final_color.to_u32();
-                    }
-                }
-            });
+            lighting.run(&mut framebuffer);
         }
 
         // Blit the framebuffer to the window
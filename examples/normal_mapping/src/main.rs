@@ -27,7 +27,13 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         let width = image.width();
         let height = image.height();
         let texels: Vec<u8> = image.pixels().flat_map(|p| p.0[..3].iter().copied()).collect();
-        Texture::new(&TextureSource { width, height, format: TextureFormat::RGB, texels: &texels })
+        Texture::new(&TextureSource {
+            width,
+            height,
+            format: TextureFormat::RGB,
+            texels: &texels,
+            ..Default::default()
+        })
     };
     let normal_map = {
         let image = image::open(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("res/normals.png"))
@@ -36,7 +42,13 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         let width = image.width();
         let height = image.height();
         let texels: Vec<u8> = image.pixels().flat_map(|p| p.0[..3].iter().copied()).collect();
-        Texture::new(&TextureSource { width, height, format: TextureFormat::RGB, texels: &texels })
+        Texture::new(&TextureSource {
+            width,
+            height,
+            format: TextureFormat::RGB,
+            texels: &texels,
+            ..Default::default()
+        })
     };
 
     // Allocate the buffers and the rasterizer
@@ -99,9 +111,13 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
             normal_buffer = TiledBuffer::<u32, 64, 64>::new(size.0 as u16, size.1 as u16);
             depth_buffer = TiledBuffer::<u16, 64, 64>::new(size.0 as u16, size.1 as u16);
         }
-        color_buffer.fill(RGBA::new(64, 224, 208, 255).to_u32());
-        normal_buffer.fill(RGBA::new(127, 255, 127, 255).to_u32());
-        depth_buffer.fill(u16::MAX);
+        Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            normal_buffer: Some(&mut normal_buffer),
+            depth_buffer: Some(&mut depth_buffer),
+            ..Default::default()
+        }
+        .clear_all(&ClearValues { color: RGBA::new(64, 224, 208, 255), ..Default::default() });
         rasterizer.setup(Viewport::new(0, 0, size.0 as u16, size.1 as u16));
 
         // Commit the draw commands
@@ -128,17 +144,15 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
             color_buffer: Some(&mut color_buffer),
             normal_buffer: Some(&mut normal_buffer),
             depth_buffer: Some(&mut depth_buffer),
+            ..Default::default()
         });
 
         // Apply basic lighting
         let half: Vec3 = (view_dir_neg + light_dir_neg).normalized(); // cheat and use a uniform view direction
         for y in 0..size.1 as u16 {
             for x in 0..size.0 as u16 {
-                if depth_buffer.at(x, y) < u16::MAX {
-                    let normal_rgba: RGBA = RGBA::from_u32(normal_buffer.at(x, y));
-                    let normal: Vec3 = (Vec3::new(normal_rgba.r as f32, normal_rgba.g as f32, normal_rgba.b as f32)
-                        - Vec3::new(127.0, 127.0, 127.0))
-                        / 128.0;
+                if depth_buffer.at(x, y) != DEPTH_FAR {
+                    let normal: Vec3 = decode_normal_from_color(RGBA::from_u32(normal_buffer.at(x, y)));
                     let ambient: f32 = 0.4;
                     let diffuse: f32 = 0.6 * dot(normal, light_dir_neg).max(0.0);
                     let specular: f32 = 0.2 * dot(normal, half).max(0.0).powi(10);
@@ -1,23 +1,11 @@
 use nih::math::*;
 use nih::render::*;
-use rand::Rng;
 use sdl3::event::Event;
 use sdl3::keyboard::Keycode;
 use sdl3::pixels::PixelFormat;
 use sdl3::surface::Surface;
 
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Define the per-particle data
-    #[derive(Clone, Copy, Debug, Default)]
-    struct Particle {
-        color: Vec4,
-        color_dt: Vec4,
-        pos: Vec3,
-        pos_dt: Vec3,
-        rot_scale: Vec2,
-        rot_scale_dt: Vec2,
-    }
-
     // Init SDL and Window
     let sdl_context = sdl3::init()?;
     let video_subsystem = sdl_context.video()?;
@@ -35,35 +23,36 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         let width = image.width();
         let height = image.height();
         let texels: Vec<u8> = image.pixels().flat_map(|p| p.0[..4].iter().copied()).collect();
-        Texture::new(&TextureSource { width, height, format: TextureFormat::RGBA, texels: &texels })
+        Texture::new(&TextureSource {
+            width,
+            height,
+            format: TextureFormat::RGBA,
+            texels: &texels,
+            ..Default::default()
+        })
     };
 
-    // Initialize the particle storage
-    const MAX_PARTICLES: usize = 1000;
-    let mut particles: Vec<Particle> = vec![Particle::default(); MAX_PARTICLES];
-    let mut particles_vertices: Vec<Vec3> = vec![Vec3::default(); MAX_PARTICLES * 6];
-    let mut particles_colors: Vec<Vec4> = vec![Vec4::default(); MAX_PARTICLES * 6];
-    let particles_tex_coords = vec![
-        Vec2::new(0.0, 0.0),
-        Vec2::new(0.0, 1.0),
-        Vec2::new(1.0, 0.0),
-        Vec2::new(1.0, 0.0),
-        Vec2::new(0.0, 1.0),
-        Vec2::new(1.0, 1.0),
-    ]
-    .repeat(MAX_PARTICLES);
-    let base_positions = [
-        Vec3::new(-1.0, 1.0, 0.0),
-        Vec3::new(-1.0, -1.0, 0.0),
-        Vec3::new(1.0, 1.0, 0.0),
-        Vec3::new(1.0, 1.0, 0.0),
-        Vec3::new(-1.0, -1.0, 0.0),
-        Vec3::new(1.0, -1.0, 0.0),
-    ];
+    // Describe the emitter: tinted stars flying up, spinning, shrinking and fading out
+    let emitter = ParticleEmitter {
+        position: Vec3::new(0.0, -4.0, -8.0),
+        extent: Vec3::new(1.0, 0.0, 0.0),
+        spawn_rate: 500.0,
+        max_particles: 1000,
+        lifetime: 1.0..3.0,
+        velocity: Vec3::new(-1.0, 0.5, -1.0)..Vec3::new(1.0, 5.0, 1.0),
+        size: 1.0..2.0,
+        scale: ParticleCurve::linear(1.0, 0.5),
+        rotation: 0.0..6.0,
+        spin: -6.0..6.0,
+        tint: Vec4::new(0.7, 0.7, 0.7, 0.8)..Vec4::new(1.0, 1.0, 1.0, 1.0),
+        color: ParticleCurve::linear(Vec4::new(1.0, 1.0, 1.0, 1.0), Vec4::new(1.0, 1.0, 1.0, 0.0)),
+        texture: Some(texture),
+        alpha_blending: AlphaBlendingMode::Additive,
+        ..Default::default()
+    };
+    let mut particles = ParticleSystem::new(emitter, 0);
 
     // Initialize the rest of the state
-    let mut rand_gen = rand::rng();
-    let mut rand = |min: f32, max: f32| -> f32 { rand_gen.random_range(min..max) };
     let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(1, 1);
     let mut rasterizer = Rasterizer::new();
     let mut last = std::time::Instant::now();
@@ -96,49 +85,13 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
         rasterizer.setup(Viewport::new(0, 0, size.0 as u16, size.1 as u16));
 
-        // Emit / purge
-        particles.iter_mut().for_each(|p| {
-            if p.color.w <= 0.0 || p.rot_scale.y <= 0.0 {
-                p.color = Vec4::new(rand(0.7, 1.0), rand(0.7, 1.0), rand(0.7, 1.0), rand(0.8, 1.0));
-                p.color_dt = Vec4::new(0.0, 0.0, 0.0, rand(-1.0, -0.3));
-                p.pos = Vec3::new(rand(-1.0, 1.0), -4.0, -8.0);
-                p.pos_dt = Vec3::new(rand(-1.0, 1.0), rand(0.5, 5.0), rand(-1.0, 1.0));
-                p.rot_scale = Vec2::new(rand(0.0, 6.0), rand(0.5, 1.0));
-                p.rot_scale_dt = Vec2::new(rand(-6.0, 6.0), rand(-0.5, 0.0));
-            }
-        });
-
-        // Update
-        particles.iter_mut().for_each(|p| {
-            p.pos += p.pos_dt * dt;
-            p.color += p.color_dt * dt;
-            p.rot_scale += p.rot_scale_dt * dt;
-        });
-
-        // Sort by Z
-        particles.sort_by(|a, b| a.pos.z.partial_cmp(&b.pos.z).unwrap());
-
-        // Update per-vertex data
-        particles.iter().enumerate().for_each(|(i, p)| {
-            let m = Mat34::translate(p.pos) * Mat34::rotate_xy(p.rot_scale.x) * Mat34::scale_uniform(p.rot_scale.y);
-            for j in 0..6 {
-                particles_vertices[i * 6 + j] = m * base_positions[j];
-                particles_colors[i * 6 + j] = p.color;
-            }
-        });
-
-        // Commit the draw command
-        rasterizer.commit(&RasterizationCommand {
-            world_positions: &particles_vertices,
-            tex_coords: &particles_tex_coords,
-            colors: &particles_colors,
-            texture: Some(texture.clone()),
-            sampling_filter: SamplerFilter::Bilinear,
-            alpha_blending: AlphaBlendingMode::Additive,
-            alpha_test: 2u8,
-            projection: Mat44::perspective(1.0, 20.0, std::f32::consts::PI / 3.0, size.0 as f32 / size.1 as f32),
-            ..Default::default()
-        });
+        // Simulate and draw the particles
+        particles.step(dt);
+        particles.commit(
+            &mut rasterizer,
+            Mat44::identity(),
+            Mat44::perspective(1.0, 20.0, std::f32::consts::PI / 3.0, size.0 as f32 / size.1 as f32),
+        );
 
         // Render into the framebuffer
         rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
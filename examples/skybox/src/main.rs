@@ -6,12 +6,11 @@ use crate::reinhard_tone_mapper::ReinhardToneMapper;
 use nih::math::simd::F32x4;
 use nih::math::*;
 use nih::render::*;
+use nih::util::input::*;
 use noise::{NoiseFn, Seedable};
 use rand::{Rng, SeedableRng};
 use sdl3::event::Event;
 use sdl3::keyboard::Keycode;
-use sdl3::pixels::PixelFormat;
-use sdl3::surface::Surface;
 use std::sync::Arc;
 
 #[derive(PartialEq)]
@@ -24,17 +23,6 @@ enum Face {
     ZPos,
 }
 
-fn camera_to_mat34(orientation: Quat, position: Vec3) -> Mat34 {
-    let r: Mat33 = orientation.as_mat33();
-    let r_inv: Mat33 = r.transpose();
-    let t_inv: Vec3 = -(r_inv * position);
-    Mat34([
-        r_inv.0[0], r_inv.0[1], r_inv.0[2], t_inv.x, //
-        r_inv.0[3], r_inv.0[4], r_inv.0[5], t_inv.y, //
-        r_inv.0[6], r_inv.0[7], r_inv.0[8], t_inv.z, //
-    ])
-}
-
 fn build_face(sky: &HosekWilkieSky, face: Face, sun_dir: Vec3) -> Arc<Texture> {
     let width = 512;
     let height = 512;
@@ -153,6 +141,7 @@ fn build_face(sky: &HosekWilkieSky, face: Face, sun_dir: Vec3) -> Arc<Texture> {
         height: height as u32,
         format: TextureFormat::RGB,
         texels: &texels,
+        ..Default::default()
     })
 }
 
@@ -188,6 +177,7 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         width: 64,
         height: 64,
         format: TextureFormat::Grayscale,
+        ..Default::default()
     });
     let mut neg_x_tex = dummy_gray_texture.clone();
     let neg_y_tex = dummy_gray_texture.clone();
@@ -263,8 +253,9 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     // let mut sky_turbidity: f32 = 1.0;
     let mut ground_albedo: Vec3 = Vec3::new(0.0, 0.0, 0.5);
     let mut rebuild_skybox: bool = true;
-    let mut camera_orientation: Quat = Quat::from_axis_angle(Vec3::new(0.0, 0.0, -1.0), 0.0);
-    let camera_position: Vec3 = Vec3::new(0.0, 2.0, 35.0);
+    // Look around only, W is taken by the wireframe toggle
+    let mut camera = FirstPersonController::new(Vec3::new(0.0, 2.0, 35.0));
+    camera.speed = 0.0;
     let mut show_wireframe: bool = false;
     let mut paused = false;
     let mut event_pump = sdl_context.event_pump().map_err(|e| e.to_string())?;
@@ -273,6 +264,9 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     loop {
         // Poll for SDL events
         for event in event_pump.poll_iter() {
+            if let Some(input) = support::input_event(&event) {
+                camera.handle_event(&input);
+            }
             match event {
                 Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => return Ok(()),
                 Event::KeyDown { keycode: Some(Keycode::Space), .. } => paused = !paused,
@@ -312,16 +306,6 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
                     println!("ground albedo: {:.1}, {:.1}, {:.1}", ground_albedo.x, ground_albedo.y, ground_albedo.z);
                     rebuild_skybox = true;
                 }
-                Event::MouseMotion { xrel, yrel, mousestate, .. } => {
-                    if mousestate.left() {
-                        let sensitivity: f32 = 0.002;
-                        let angle_yaw: f32 = -xrel * sensitivity;
-                        let angle_pitch: f32 = -yrel * sensitivity;
-                        let yaw: Quat = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), angle_yaw);
-                        let pitch: Quat = Quat::from_axis_angle(camera_orientation * Vec3::new(1.0, 0.0, 0.0), angle_pitch);
-                        camera_orientation = (yaw * pitch * camera_orientation).normalized();
-                    }
-                }
                 _ => {}
             }
         }
@@ -370,7 +354,7 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         // Commit the draw commands
         let projection: Mat44 =
             Mat44::perspective(1.0, 100.0, std::f32::consts::PI / 3.0, size.0 as f32 / size.1 as f32);
-        let view: Mat44 = camera_to_mat34(camera_orientation, camera_position).as_mat44();
+        let view: Mat44 = camera.view();
         let view_orientation: Mat44 = view.as_mat33().as_mat44();
 
         // draw the skybox
@@ -395,11 +379,6 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
 
         // Blit the framebuffer to the window
-        let mut flat = color_buffer.as_flat_buffer();
-        let mut windows_surface = window.surface(&event_pump)?;
-        Surface::from_data(flat.as_u8_slice_mut(), size.0, size.1, size.0 * 4, PixelFormat::ABGR8888.into())
-            .unwrap()
-            .blit(None, &mut windows_surface, None)?;
-        windows_surface.finish()?;
+        support::present(&window, &event_pump, &color_buffer)?;
     }
 }
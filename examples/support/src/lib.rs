@@ -0,0 +1,66 @@
+// The glue shared by the SDL examples: translating SDL events into nih's input events for the camera controllers
+// and presenting the color buffer in the window.
+use nih::render::*;
+use nih::util::input::*;
+use sdl3::EventPump;
+use sdl3::event::Event;
+use sdl3::keyboard::Keycode;
+use sdl3::mouse::MouseWheelDirection;
+use sdl3::pixels::PixelFormat;
+use sdl3::surface::Surface;
+use sdl3::video::Window;
+
+// Maps WASD for the horizontal movement and E/Q for up/down.
+fn movement_key(keycode: Keycode) -> Option<MovementKey> {
+    match keycode {
+        Keycode::W => Some(MovementKey::Forward),
+        Keycode::S => Some(MovementKey::Backward),
+        Keycode::A => Some(MovementKey::Left),
+        Keycode::D => Some(MovementKey::Right),
+        Keycode::E => Some(MovementKey::Up),
+        Keycode::Q => Some(MovementKey::Down),
+        _ => None,
+    }
+}
+
+// Translates an SDL event into an input event for the camera controllers, None if it's irrelevant for them.
+// The event is still available to the example for its own key bindings.
+pub fn input_event(event: &Event) -> Option<InputEvent> {
+    match event {
+        Event::MouseMotion { xrel, yrel, mousestate, .. } => Some(InputEvent::MouseMotion {
+            dx: *xrel,
+            dy: *yrel,
+            buttons: MouseButtons { left: mousestate.left(), middle: mousestate.middle(), right: mousestate.right() },
+        }),
+        Event::MouseWheel { y, direction, .. } => {
+            let delta: f32 = if *direction == MouseWheelDirection::Flipped {
+                -*y
+            } else {
+                *y
+            };
+            Some(InputEvent::MouseWheel { delta })
+        }
+        Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => movement_key(*keycode).map(InputEvent::KeyDown),
+        Event::KeyUp { keycode: Some(keycode), .. } => movement_key(*keycode).map(InputEvent::KeyUp),
+        _ => None,
+    }
+}
+
+// Blits the color buffer into the window's surface, the buffer is expected to be of the window's size.
+pub fn present(
+    window: &Window,
+    event_pump: &EventPump,
+    color_buffer: &TiledBuffer<u32, 64, 64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let width: u32 = color_buffer.width() as u32;
+    let height: u32 = color_buffer.height() as u32;
+    let mut flat = color_buffer.as_flat_buffer();
+    let mut windows_surface = window.surface(event_pump)?;
+    Surface::from_data(flat.as_u8_slice_mut(), width, height, width * 4, PixelFormat::ABGR8888.into())?.blit(
+        None,
+        &mut windows_surface,
+        None,
+    )?;
+    windows_surface.finish()?;
+    Ok(())
+}
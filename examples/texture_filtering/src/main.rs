@@ -23,7 +23,13 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
         let width = image.width();
         let height = image.height();
         let texels: Vec<u8> = image.pixels().flat_map(|p| p.0[..3].iter().copied()).collect();
-        Texture::new(&TextureSource { width, height, format: TextureFormat::RGB, texels: &texels })
+        Texture::new(&TextureSource {
+            width,
+            height,
+            format: TextureFormat::RGB,
+            texels: &texels,
+            ..Default::default()
+        })
     };
 
     let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(1, 1);
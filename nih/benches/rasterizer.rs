@@ -185,6 +185,43 @@ fn criterion_benchmark(c: &mut Criterion) {
         })
     };
 
+    let fast_reciprocal = |bencher: &mut Bencher| {
+        bencher.iter(|| {
+            let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+            color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+            let mut rasterizer = Rasterizer::new();
+            rasterizer.setup(Viewport::new(0, 0, 64, 64));
+            rasterizer.set_opaque_fills_fast_path(false);
+            rasterizer.set_fast_reciprocal(true);
+            let command = RasterizationCommand {
+                world_positions: &tris_positions,
+                colors: &tris_varying_colors,
+                ..Default::default()
+            };
+            rasterizer.commit(&command);
+            rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Framebuffer::default() });
+            std::hint::black_box(color_buffer);
+        })
+    };
+
+    let generic_path = |bencher: &mut Bencher| {
+        bencher.iter(|| {
+            let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+            color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+            let mut rasterizer = Rasterizer::new();
+            rasterizer.setup(Viewport::new(0, 0, 64, 64));
+            rasterizer.set_opaque_fills_fast_path(false);
+            let command = RasterizationCommand {
+                world_positions: &tris_positions,
+                colors: &tris_varying_colors,
+                ..Default::default()
+            };
+            rasterizer.commit(&command);
+            rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Framebuffer::default() });
+            std::hint::black_box(color_buffer);
+        })
+    };
+
     let mut group = c.benchmark_group("Fill 10Mpx");
     group.bench_function(BenchmarkId::new("64x64", "0 plain"), &plain);
     group.bench_function(BenchmarkId::new("64x64", "1 fixed colors"), &fixed_colors);
@@ -192,6 +229,8 @@ fn criterion_benchmark(c: &mut Criterion) {
     group.bench_function(BenchmarkId::new("64x64", "3 alpha blend"), &alpha_blending);
     group.bench_function(BenchmarkId::new("64x64", "4 depth"), &depth);
     group.bench_function(BenchmarkId::new("64x64", "5 normals"), &normals);
+    group.bench_function(BenchmarkId::new("64x64", "6 varying colors, fast reciprocal"), &fast_reciprocal);
+    group.bench_function(BenchmarkId::new("64x64", "7 varying colors, generic path"), &generic_path);
     group.finish();
 }
 
@@ -15,19 +15,33 @@ fn criterion_benchmark(c: &mut Criterion) {
         width: 1024,
         height: 1024,
         format: TextureFormat::Grayscale,
+        ..Default::default()
     });
     let texture_rgb = Texture::new(&TextureSource {
         texels: &vec![255u8; 1024 * 1024 * 3],
         width: 1024,
         height: 1024,
         format: TextureFormat::RGB,
+        ..Default::default()
     });
+    let texture_rgba_bc3 = Texture::new_with_options(
+        &TextureSource {
+            texels: &vec![255u8; 1024 * 1024 * 4],
+            width: 1024,
+            height: 1024,
+            format: TextureFormat::RGBA,
+            ..Default::default()
+        },
+        &TextureOptions { compress: true, ..Default::default() },
+    );
     let sampler_nearest_grayscale = Sampler::new(&texture_grayscale, SamplerFilter::Nearest, 0.5);
     let sampler_nearest_rgb = Sampler::new(&texture_rgb, SamplerFilter::Nearest, 0.5);
     let sampler_bilinear_grayscale = Sampler::new(&texture_grayscale, SamplerFilter::Bilinear, 0.5);
     let sampler_bilinear_rgb = Sampler::new(&texture_rgb, SamplerFilter::Bilinear, 0.5);
     let sampler_trilinear_grayscale = Sampler::new(&texture_grayscale, SamplerFilter::Trilinear, 0.5);
     let sampler_trilinear_rgb = Sampler::new(&texture_rgb, SamplerFilter::Trilinear, 0.5);
+    let sampler_nearest_bc3 = Sampler::new(&texture_rgba_bc3, SamplerFilter::Nearest, 0.5);
+    let sampler_bilinear_bc3 = Sampler::new(&texture_rgba_bc3, SamplerFilter::Bilinear, 0.5);
     fn runner(bencher: &mut Bencher, sampler: &Sampler) {
         bencher.iter(|| {
             sample_1m(sampler);
@@ -38,6 +52,8 @@ fn criterion_benchmark(c: &mut Criterion) {
     group.bench_with_input(BenchmarkId::new("Nearest", "RGB"), &sampler_nearest_rgb, runner);
     group.bench_with_input(BenchmarkId::new("Bilinear", "Gray"), &sampler_bilinear_grayscale, runner);
     group.bench_with_input(BenchmarkId::new("Bilinear", "RGB"), &sampler_bilinear_rgb, runner);
+    group.bench_with_input(BenchmarkId::new("Nearest", "BC3"), &sampler_nearest_bc3, runner);
+    group.bench_with_input(BenchmarkId::new("Bilinear", "BC3"), &sampler_bilinear_bc3, runner);
     group.bench_with_input(BenchmarkId::new("Trilinear", "Gray"), &sampler_trilinear_grayscale, runner);
     group.bench_with_input(BenchmarkId::new("Trilinear", "RGB"), &sampler_trilinear_rgb, runner);
     group.finish();
@@ -0,0 +1,26 @@
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+
+#[path = "../tests/scene/mod.rs"]
+mod scene;
+
+use scene::Camera;
+use scene::*;
+
+// The whole frame of the composed scene: the shadow, geometry, lighting and post passes.
+fn criterion_benchmark(c: &mut Criterion) {
+    let scene = Scene::new();
+    let mut group = c.benchmark_group("Atrium");
+    for (width, height) in [(640u16, 360u16), (1280, 720)] {
+        let mut frame = Frame::new(width, height);
+        group.bench_function(BenchmarkId::new("frame", format!("{}x{}", width, height)), |bencher| {
+            bencher.iter(|| {
+                scene.render(&mut frame, &Camera::fixed());
+                std::hint::black_box(&frame.ldr);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
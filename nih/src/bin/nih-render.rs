@@ -0,0 +1,394 @@
+// Renders a scene file headlessly from several camera angles, writes the frames as PNGs and the statistics as JSON.
+// Built with the "cli" feature: cargo run --release --features cli --bin nih-render -- scene.ron --views 8
+//
+// The scene is a SceneDescription in RON or JSON, its meshes are .obj files and its textures are any images supported
+// by the image crate, both referred to by paths relative to the assets directory.
+// The first view is the scene's camera, the others orbit it around the vertical axis through the center of the scene.
+
+use nih::math::*;
+use nih::render::*;
+use nih::util::frame_pacing::*;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+const USAGE: &str = "usage: nih-render <scene.ron|scene.json> [options]
+  --output <dir>    where to write the images and statistics.json, default: the current directory
+  --assets <dir>    the directory the scene's paths are relative to, default: the scene's directory
+  --size <WxH>      the size of the images, default: 1280x720
+  --views <N>       the number of camera angles around the scene, default: 1
+  --frames <N>      the number of times each view is rendered for timing, default: 1";
+
+#[derive(Debug)]
+struct Options {
+    scene: PathBuf,
+    output: PathBuf,
+    assets: Option<PathBuf>,
+    width: u16,
+    height: u16,
+    views: u32,
+    frames: u32,
+}
+
+type Error = Box<dyn std::error::Error>;
+
+fn main() {
+    let options: Options = match parse_options(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n{}", error, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Err(error) = run(&options) {
+        eprintln!("nih-render: {}", error);
+        std::process::exit(1);
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, Error> {
+    let mut scene: Option<PathBuf> = None;
+    let mut options = Options {
+        scene: PathBuf::new(),
+        output: PathBuf::from("."),
+        assets: None,
+        width: 1280,
+        height: 720,
+        views: 1,
+        frames: 1,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} requires a value", arg));
+        match arg.as_str() {
+            "--output" => options.output = PathBuf::from(value()?),
+            "--assets" => options.assets = Some(PathBuf::from(value()?)),
+            "--size" => {
+                let size: String = value()?;
+                let (width, height) = size
+                    .split_once('x')
+                    .ok_or_else(|| format!("invalid size \"{}\"", size))?;
+                (options.width, options.height) = (width.parse()?, height.parse()?);
+            }
+            "--views" => options.views = value()?.parse()?,
+            "--frames" => options.frames = value()?.parse()?,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg).into()),
+            _ if scene.is_none() => scene = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument {}", arg).into()),
+        }
+    }
+    options.scene = scene.ok_or("no scene given")?;
+    if options.width == 0 || options.height == 0 || options.views == 0 || options.frames == 0 {
+        return Err("the size, the views and the frames must be positive".into());
+    }
+    Ok(options)
+}
+
+fn run(options: &Options) -> Result<(), Error> {
+    let text: String = std::fs::read_to_string(&options.scene)?;
+    let scene = SceneDescription::parse(&text, SceneFormat::from_path(&options.scene))?;
+    let assets_dir: PathBuf = match &options.assets {
+        Some(dir) => dir.clone(),
+        None => options.scene.parent().unwrap_or(Path::new(".")).to_path_buf(),
+    };
+    let mut assets = SceneAssets::new();
+    for path in &scene.meshes {
+        assets.add_mesh(path, load_obj(&assets_dir.join(path))?.into_shared());
+    }
+    for path in scene.texture_paths() {
+        assets.add_texture(path, load_texture(&assets_dir.join(path))?);
+    }
+    let mut world = RenderWorld::from_scene(&scene, &assets)?;
+    world.set_clear_values(Some(ClearValues::default()));
+    let center: Vec3 = match scene_center(&scene, &assets) {
+        Some(center) => center,
+        // A single view is the scene's camera as it is, there's nothing to orbit
+        None if options.views == 1 => Vec3::new(0.0, 0.0, 0.0),
+        None => return Err("the scene has no visible instances to orbit the views around".into()),
+    };
+
+    std::fs::create_dir_all(&options.output)?;
+    let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(options.width, options.height);
+    let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(options.width, options.height);
+    let mut normal_buffer = TiledBuffer::<u32, 64, 64>::new(options.width, options.height);
+    let mut rasterizer = Rasterizer::new();
+    let mut views: Vec<serde_json::Value> = Vec::new();
+    for view_index in 0..options.views {
+        // Rotates the world around the center before looking at it with the scene's camera
+        let angle: f32 = std::f32::consts::TAU * view_index as f32 / options.views as f32;
+        let view: Mat44 = scene.view * Mat44::translate(center) * Mat44::rotate_zx(angle) * Mat44::translate(-center);
+        world.set_camera(view, scene.projection);
+
+        let mut history = FrameTimeHistory::new(options.frames as usize);
+        for _ in 0..options.frames {
+            let start = Instant::now();
+            world.render(
+                &mut rasterizer,
+                &mut Framebuffer {
+                    color_buffer: Some(&mut color_buffer),
+                    depth_buffer: Some(&mut depth_buffer),
+                    normal_buffer: Some(&mut normal_buffer),
+                    ..Default::default()
+                },
+            );
+            history.push(start.elapsed().as_secs_f64() * 1000.0);
+        }
+
+        let file_name: String = format!("view_{:03}.png", view_index);
+        color_buffer.save_color(options.output.join(&file_name))?;
+
+        let pacing: FramePacing = history.pacing();
+        let world_stats: RenderWorldStatistics = world.statistics();
+        let stats: RasterizerStatistics = rasterizer.statistics();
+        println!("{}: {:.2}ms, {} triangles", file_name, pacing.median, stats.scheduled_triangles);
+        views.push(serde_json::json!({
+            "image": file_name,
+            "angle": angle,
+            "time_ms": {
+                "frames": pacing.samples,
+                "average": pacing.average,
+                "median": pacing.median,
+                "max": pacing.max,
+                "percentile_99": pacing.percentile_99,
+            },
+            "world": {
+                "visible_instances": world_stats.visible_instances,
+                "culled_instances": world_stats.culled_instances,
+                "committed_commands": world_stats.committed_commands,
+            },
+            "rasterizer": {
+                "committed_triangles": stats.committed_triangles,
+                "scheduled_triangles": stats.scheduled_triangles,
+                "culled_commands": stats.culled_commands,
+                "clipped_triangles": stats.clipped_triangles,
+                "binned_triangles": stats.binned_triangles,
+                "fragments_drawn": stats.fragments_drawn,
+            },
+        }));
+    }
+
+    let statistics = serde_json::json!({
+        "scene": options.scene.display().to_string(),
+        "width": options.width,
+        "height": options.height,
+        "views": views,
+    });
+    std::fs::write(options.output.join("statistics.json"), serde_json::to_string_pretty(&statistics)?)?;
+    Ok(())
+}
+
+// The center of the visible instances' bounding boxes in world space, None if there are no such instances.
+fn scene_center(scene: &SceneDescription, assets: &SceneAssets) -> Option<Vec3> {
+    let mut corners: Vec<Vec3> = Vec::new();
+    for instance in scene.instances.iter().filter(|instance| instance.visible) {
+        let Some(mesh) = scene.meshes.get(instance.mesh).and_then(|path| assets.mesh(path)) else {
+            continue;
+        };
+        let (min, max) = (mesh.aabb.min, mesh.aabb.max);
+        for i in 0..8 {
+            let corner = Vec3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            );
+            corners.push(instance.transform * corner);
+        }
+    }
+    if corners.is_empty() {
+        return None;
+    }
+    let aabb = AABB::from_points(&corners);
+    Some((aabb.min + aabb.max) * 0.5)
+}
+
+// Loads the triangles of all the objects in the file as an indexed mesh with a section per geometry group, as
+// RenderWorld expects. The vertices are not deduplicated, the missing texture coordinates and normals are set to zero.
+fn load_obj(path: &Path) -> Result<MeshData, Error> {
+    let text: String = std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+    let model = wavefront_obj::obj::parse(text).map_err(|error| format!("{}: {:?}", path.display(), error))?;
+    let mut mesh = MeshData::default();
+    for object in &model.objects {
+        for geometry in &object.geometry {
+            let start_index: usize = mesh.indices.len();
+            for shape in &geometry.shapes {
+                let wavefront_obj::obj::Primitive::Triangle(v0, v1, v2) = shape.primitive else {
+                    continue;
+                };
+                for (position, tex_coord, normal) in [v0, v1, v2] {
+                    mesh.indices.push(mesh.positions.len() as u32);
+                    let p = object.vertices[position];
+                    mesh.positions.push(Vec3::new(p.x as f32, p.y as f32, p.z as f32));
+                    mesh.tex_coords.push(match tex_coord {
+                        Some(index) => {
+                            Vec2::new(object.tex_vertices[index].u as f32, object.tex_vertices[index].v as f32)
+                        }
+                        None => Vec2::new(0.0, 0.0),
+                    });
+                    mesh.normals.push(match normal {
+                        Some(index) => {
+                            let n = object.normals[index];
+                            Vec3::new(n.x as f32, n.y as f32, n.z as f32).normalized()
+                        }
+                        None => Vec3::new(0.0, 0.0, 0.0),
+                    });
+                }
+            }
+            mesh.sections.push(MeshDataSection {
+                name: geometry.material_name.clone().unwrap_or_default(),
+                start_index,
+                num_triangles: (mesh.indices.len() - start_index) / 3,
+                material_index: mesh.sections.len(),
+            });
+        }
+    }
+    mesh.update_aabb();
+    Ok(mesh)
+}
+
+fn load_texture(path: &Path) -> Result<Arc<Texture>, Error> {
+    let image = image::open(path)
+        .map_err(|error| format!("{}: {}", path.display(), error))?
+        .into_rgba8();
+    let texture = Texture::try_new(&TextureSource {
+        texels: image.as_raw(),
+        width: image.width(),
+        height: image.height(),
+        format: TextureFormat::RGBA,
+        ..Default::default()
+    })
+    .map_err(|error| format!("{}: {}", path.display(), error))?;
+    Ok(texture)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, Error> {
+        parse_options(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn parse_error(args: &[&str]) -> String {
+        match parse(args) {
+            Ok(options) => panic!("{:?} parsed as {:?}", args, options),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_options() {
+        let options: Options = parse(&["scene.ron"]).unwrap();
+        assert_eq!(options.scene, PathBuf::from("scene.ron"));
+        assert_eq!(options.output, PathBuf::from("."));
+        assert!(options.assets.is_none());
+        assert_eq!((options.width, options.height, options.views, options.frames), (1280, 720, 1, 1));
+
+        let options: Options = parse(&[
+            "--size", "320x200", "--views", "8", "scene.json", "--frames", "3", "--output", "out", "--assets", "data",
+        ])
+        .unwrap();
+        assert_eq!(options.scene, PathBuf::from("scene.json"));
+        assert_eq!(options.output, PathBuf::from("out"));
+        assert_eq!(options.assets, Some(PathBuf::from("data")));
+        assert_eq!((options.width, options.height, options.views, options.frames), (320, 200, 8, 3));
+    }
+
+    #[test]
+    fn rejects_missing_values() {
+        assert_eq!(parse_error(&[]), "no scene given");
+        assert_eq!(parse_error(&["--views", "2"]), "no scene given");
+        assert_eq!(parse_error(&["scene.ron", "--size"]), "--size requires a value");
+        assert_eq!(parse_error(&["scene.ron", "--output"]), "--output requires a value");
+    }
+
+    #[test]
+    fn rejects_invalid_numbers() {
+        assert_eq!(parse_error(&["scene.ron", "--size", "320"]), "invalid size \"320\"");
+        parse_error(&["scene.ron", "--size", "widexhigh"]);
+        parse_error(&["scene.ron", "--size", "320x-1"]);
+        parse_error(&["scene.ron", "--size", "70000x200"]);
+        parse_error(&["scene.ron", "--views", "many"]);
+        parse_error(&["scene.ron", "--frames", "1.5"]);
+        for args in [["scene.ron", "--size", "0x200"], ["scene.ron", "--views", "0"], ["scene.ron", "--frames", "0"]] {
+            assert_eq!(parse_error(&args), "the size, the views and the frames must be positive");
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_arguments() {
+        assert_eq!(parse_error(&["scene.ron", "--fast"]), "unknown option --fast");
+        assert_eq!(parse_error(&["scene.ron", "other.ron"]), "unexpected argument other.ron");
+    }
+
+    fn scene_of(instances: Vec<InstanceDescription>) -> (SceneDescription, SceneAssets) {
+        let mut mesh = MeshData {
+            positions: vec![Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 0.0, -1.0), Vec3::new(0.0, 2.0, 1.0)],
+            indices: vec![0, 1, 2],
+            ..Default::default()
+        };
+        mesh.update_aabb();
+        let mut assets = SceneAssets::new();
+        assets.add_mesh("triangle.obj", mesh.into_shared());
+        let scene = SceneDescription {
+            meshes: vec!["triangle.obj".to_string()],
+            materials: Vec::new(),
+            instances,
+            lights: Vec::new(),
+            view: Mat44::identity(),
+            projection: Mat44::perspective(0.1, 100.0, std::f32::consts::FRAC_PI_2, 1.0),
+            ambient: Vec3::new(1.0, 1.0, 1.0),
+            light_units: LightUnits::Relative,
+        };
+        (scene, assets)
+    }
+
+    fn instance(offset: Vec3, visible: bool) -> InstanceDescription {
+        InstanceDescription { mesh: 0, materials: Vec::new(), transform: Mat34::translate(offset), visible }
+    }
+
+    #[test]
+    fn centers_on_visible_instances() {
+        let (scene, assets) = scene_of(vec![
+            instance(Vec3::new(10.0, 0.0, 0.0), true),
+            instance(Vec3::new(-100.0, 0.0, 0.0), false),
+            instance(Vec3::new(20.0, 0.0, 0.0), true),
+        ]);
+        assert_eq!(scene_center(&scene, &assets), Some(Vec3::new(15.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn no_center_without_visible_instances() {
+        let (scene, assets) = scene_of(vec![instance(Vec3::new(10.0, 0.0, 0.0), false)]);
+        assert_eq!(scene_center(&scene, &assets), None);
+        let (scene, assets) = scene_of(Vec::new());
+        assert_eq!(scene_center(&scene, &assets), None);
+        // The instances of the meshes missing from the assets are skipped too
+        let (scene, _) = scene_of(vec![instance(Vec3::new(10.0, 0.0, 0.0), true)]);
+        assert_eq!(scene_center(&scene, &SceneAssets::new()), None);
+    }
+
+    #[test]
+    fn orbiting_requires_visible_instances() {
+        let directory: PathBuf = std::env::temp_dir().join(format!("nih_render_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let (mut scene, _) = scene_of(Vec::new());
+        scene.meshes.clear();
+        let scene_path: PathBuf = directory.join("empty.ron");
+        std::fs::write(&scene_path, scene.serialize(SceneFormat::Ron).unwrap()).unwrap();
+        let options = |views: u32| Options {
+            scene: scene_path.clone(),
+            output: directory.clone(),
+            assets: None,
+            width: 32,
+            height: 32,
+            views,
+            frames: 1,
+        };
+        let error: String = run(&options(4)).unwrap_err().to_string();
+        assert_eq!(error, "the scene has no visible instances to orbit the views around");
+        // The scene's own camera doesn't orbit anything
+        run(&options(1)).unwrap();
+        assert!(directory.join("view_000.png").exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
+
@@ -0,0 +1,381 @@
+use crate::render::{Buffer, TiledBuffer};
+use bytemuck::{Pod, Zeroable};
+use std::marker::PhantomData;
+
+/// A rectangle of the dispatched domain processed by a single invocation of the closure, in elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DispatchRegion {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl DispatchRegion {
+    pub fn contains(&self, x: u16, y: u16) -> bool {
+        x >= self.x && y >= self.y && x - self.x < self.width && y - self.y < self.height
+    }
+}
+
+/// Where the element (x, y) of a buffer lives in its memory.
+#[derive(Debug, Clone, Copy)]
+enum Layout {
+    Linear {
+        stride: usize,
+    },
+    Tiled {
+        tile_width: usize,
+        tile_height: usize,
+        tiles_x: usize,
+    },
+}
+
+impl Layout {
+    #[inline(always)]
+    fn offset(&self, x: u16, y: u16) -> usize {
+        let (x, y): (usize, usize) = (x as usize, y as usize);
+        match *self {
+            Layout::Linear { stride } => y * stride + x,
+            Layout::Tiled { tile_width, tile_height, tiles_x } => {
+                let tile: usize = (y / tile_height) * tiles_x + x / tile_width;
+                tile * tile_width * tile_height + (y % tile_height) * tile_width + x % tile_width
+            }
+        }
+    }
+}
+
+/// Raw access to a buffer bound to a dispatch, shared by all the workers.
+pub struct BoundBuffer<T> {
+    ptr: *mut T,
+    width: u16,
+    height: u16,
+    layout: Layout,
+}
+
+// The workers only access the elements through the views, which keep the mutable access within disjoint regions.
+unsafe impl<T: Send> Send for BoundBuffer<T> {}
+unsafe impl<T: Send> Sync for BoundBuffer<T> {}
+
+/// Read-only view of an entire buffer bound to a dispatch, any element can be read, e.g. the neighbours of the ones
+/// being processed.
+pub struct BufferView<'a, T> {
+    ptr: *const T,
+    width: u16,
+    height: u16,
+    layout: Layout,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<T: Copy> BufferView<'_, T> {
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Returns the element at (x, y) in the buffer's coordinates.
+    /// Panics if (x, y) is outside of the buffer.
+    pub fn at(&self, x: u16, y: u16) -> T {
+        assert!(x < self.width && y < self.height, "({}, {}) is outside of {}x{}", x, y, self.width, self.height);
+        unsafe { *self.ptr.add(self.layout.offset(x, y)) }
+    }
+
+    /// Returns the element at (x, y) clamped to the buffer's edges.
+    pub fn at_clamped(&self, x: i32, y: i32) -> T {
+        self.at(x.clamp(0, self.width as i32 - 1) as u16, y.clamp(0, self.height as i32 - 1) as u16)
+    }
+}
+
+/// Mutable view of a buffer bound to a dispatch, limited to the region being processed.
+pub struct BufferRegionMut<'a, T> {
+    ptr: *mut T,
+    region: DispatchRegion,
+    layout: Layout,
+    _marker: PhantomData<&'a mut T>,
+}
+
+impl<T: Copy> BufferRegionMut<'_, T> {
+    pub fn region(&self) -> DispatchRegion {
+        self.region
+    }
+
+    /// Returns the element at (x, y) in the buffer's coordinates.
+    /// Panics if (x, y) is outside of the region.
+    pub fn at(&self, x: u16, y: u16) -> T {
+        assert!(self.region.contains(x, y), "({}, {}) is outside of {:?}", x, y, self.region);
+        unsafe { *self.ptr.add(self.layout.offset(x, y)) }
+    }
+
+    /// Returns a mutable reference to the element at (x, y) in the buffer's coordinates.
+    /// Panics if (x, y) is outside of the region.
+    pub fn at_mut(&mut self, x: u16, y: u16) -> &mut T {
+        assert!(self.region.contains(x, y), "({}, {}) is outside of {:?}", x, y, self.region);
+        unsafe { &mut *self.ptr.add(self.layout.offset(x, y)) }
+    }
+
+    /// Calls the function with the coordinates and a mutable reference of each element of the region, row by row.
+    pub fn for_each_mut(&mut self, mut f: impl FnMut(u16, u16, &mut T)) {
+        let region: DispatchRegion = self.region;
+        for y in region.y..region.y + region.height {
+            for x in region.x..region.x + region.width {
+                f(x, y, unsafe { &mut *self.ptr.add(self.layout.offset(x, y)) });
+            }
+        }
+    }
+}
+
+/// A buffer or a tuple of buffers which can be bound to dispatch_2d(). Shared references are bound as read-only views
+/// of the entire buffers, mutable references are bound as views of the region being processed, so that several
+/// buffers can be read and written by the same closure at once.
+///
+/// # Safety
+/// The views of the disjoint regions must not give mutable access to the same elements.
+pub unsafe trait DispatchBinding {
+    type Bound: Sync;
+
+    /// The views live only as long as the borrow of the bound buffers for a single call of the closure, so that the
+    /// closure can't keep them to alias the elements in another call.
+    type View<'r>;
+
+    /// Converts the borrows into raw access shared by the workers.
+    fn bind(self) -> Self::Bound;
+
+    /// Calls the function with the size of each of the mutably bound buffers.
+    fn mutable_sizes(bound: &Self::Bound, f: &mut dyn FnMut(u16, u16));
+
+    /// Creates the views for the region.
+    ///
+    /// # Safety
+    /// The regions of the views existing at the same time must be disjoint.
+    unsafe fn view<'r>(bound: &'r Self::Bound, region: DispatchRegion) -> Self::View<'r>;
+}
+
+unsafe impl<T: Copy + Zeroable + Pod + Default + Send + Sync, const W: usize, const H: usize> DispatchBinding
+    for &TiledBuffer<T, W, H>
+{
+    type Bound = BoundBuffer<T>;
+    type View<'r> = BufferView<'r, T>;
+
+    fn bind(self) -> Self::Bound {
+        BoundBuffer {
+            ptr: self.values().as_ptr() as *mut T,
+            width: self.width(),
+            height: self.height(),
+            layout: Layout::Tiled { tile_width: W, tile_height: H, tiles_x: self.tiles_x() as usize },
+        }
+    }
+
+    fn mutable_sizes(_bound: &Self::Bound, _f: &mut dyn FnMut(u16, u16)) {}
+
+    unsafe fn view<'r>(bound: &'r Self::Bound, _region: DispatchRegion) -> Self::View<'r> {
+        BufferView {
+            ptr: bound.ptr,
+            width: bound.width,
+            height: bound.height,
+            layout: bound.layout,
+            _marker: PhantomData,
+        }
+    }
+}
+
+unsafe impl<T: Copy + Zeroable + Pod + Default + Send + Sync, const W: usize, const H: usize> DispatchBinding
+    for &mut TiledBuffer<T, W, H>
+{
+    type Bound = BoundBuffer<T>;
+    type View<'r> = BufferRegionMut<'r, T>;
+
+    fn bind(self) -> Self::Bound {
+        let layout = Layout::Tiled { tile_width: W, tile_height: H, tiles_x: self.tiles_x() as usize };
+        let (width, height): (u16, u16) = (self.width(), self.height());
+        BoundBuffer { ptr: self.values_mut().as_mut_ptr(), width, height, layout }
+    }
+
+    fn mutable_sizes(bound: &Self::Bound, f: &mut dyn FnMut(u16, u16)) {
+        f(bound.width, bound.height);
+    }
+
+    unsafe fn view<'r>(bound: &'r Self::Bound, region: DispatchRegion) -> Self::View<'r> {
+        BufferRegionMut { ptr: bound.ptr, region, layout: bound.layout, _marker: PhantomData }
+    }
+}
+
+unsafe impl<T: Copy + Zeroable + Pod + Send + Sync> DispatchBinding for &Buffer<T> {
+    type Bound = BoundBuffer<T>;
+    type View<'r> = BufferView<'r, T>;
+
+    fn bind(self) -> Self::Bound {
+        BoundBuffer {
+            ptr: self.elems.as_ptr() as *mut T,
+            width: self.width,
+            height: self.height,
+            layout: Layout::Linear { stride: self.stride as usize },
+        }
+    }
+
+    fn mutable_sizes(_bound: &Self::Bound, _f: &mut dyn FnMut(u16, u16)) {}
+
+    unsafe fn view<'r>(bound: &'r Self::Bound, _region: DispatchRegion) -> Self::View<'r> {
+        BufferView {
+            ptr: bound.ptr,
+            width: bound.width,
+            height: bound.height,
+            layout: bound.layout,
+            _marker: PhantomData,
+        }
+    }
+}
+
+unsafe impl<T: Copy + Zeroable + Pod + Send + Sync> DispatchBinding for &mut Buffer<T> {
+    type Bound = BoundBuffer<T>;
+    type View<'r> = BufferRegionMut<'r, T>;
+
+    fn bind(self) -> Self::Bound {
+        BoundBuffer {
+            ptr: self.elems.as_mut_ptr(),
+            width: self.width,
+            height: self.height,
+            layout: Layout::Linear { stride: self.stride as usize },
+        }
+    }
+
+    fn mutable_sizes(bound: &Self::Bound, f: &mut dyn FnMut(u16, u16)) {
+        f(bound.width, bound.height);
+    }
+
+    unsafe fn view<'r>(bound: &'r Self::Bound, region: DispatchRegion) -> Self::View<'r> {
+        BufferRegionMut { ptr: bound.ptr, region, layout: bound.layout, _marker: PhantomData }
+    }
+}
+
+macro_rules! impl_dispatch_binding_for_tuple {
+    ($($name:ident),+) => {
+        unsafe impl<$($name: DispatchBinding),+> DispatchBinding for ($($name,)+) {
+            type Bound = ($($name::Bound,)+);
+            type View<'r> = ($($name::View<'r>,)+);
+
+            #[allow(non_snake_case)]
+            fn bind(self) -> Self::Bound {
+                let ($($name,)+) = self;
+                ($($name.bind(),)+)
+            }
+
+            #[allow(non_snake_case)]
+            fn mutable_sizes(bound: &Self::Bound, f: &mut dyn FnMut(u16, u16)) {
+                let ($($name,)+) = bound;
+                $($name::mutable_sizes($name, f);)+
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn view<'r>(bound: &'r Self::Bound, region: DispatchRegion) -> Self::View<'r> {
+                let ($($name,)+) = bound;
+                unsafe { ($($name::view($name, region),)+) }
+            }
+        }
+    };
+}
+
+impl_dispatch_binding_for_tuple!(A);
+impl_dispatch_binding_for_tuple!(A, B);
+impl_dispatch_binding_for_tuple!(A, B, C);
+impl_dispatch_binding_for_tuple!(A, B, C, D);
+impl_dispatch_binding_for_tuple!(A, B, C, D, E);
+impl_dispatch_binding_for_tuple!(A, B, C, D, E, F);
+
+/// Splits the domain of width x height elements into regions of tile x tile elements and calls the closure for each of
+/// them in parallel, with the views of the bound buffers: e.g. a custom pass like a fluid simulation step which reads
+/// the previous state and writes the next one along with a texture.
+/// The mutably bound buffers must cover the domain. A tile size matching the buffers' tiles, e.g. 64, keeps the regions
+/// within single tiles of TiledBuffers.
+pub fn dispatch_2d<B, F>(width: u16, height: u16, tile: u16, buffers: B, f: F)
+where
+    B: DispatchBinding,
+    F: for<'r> Fn(DispatchRegion, B::View<'r>) + Send + Sync,
+{
+    assert!(tile > 0, "the tile size must be positive");
+    let bound: B::Bound = buffers.bind();
+    B::mutable_sizes(&bound, &mut |buffer_width: u16, buffer_height: u16| {
+        assert!(
+            buffer_width >= width && buffer_height >= height,
+            "a {}x{} buffer doesn't cover the {}x{} domain",
+            buffer_width,
+            buffer_height,
+            width,
+            height
+        );
+    });
+
+    let mut regions: Vec<DispatchRegion> = Vec::new();
+    for y in (0..height).step_by(tile as usize) {
+        for x in (0..width).step_by(tile as usize) {
+            regions.push(DispatchRegion { x, y, width: tile.min(width - x), height: tile.min(height - y) });
+        }
+    }
+
+    use rayon::prelude::*;
+    regions.par_iter().for_each(|&region| {
+        // The regions are disjoint, so are the mutable views
+        let views: B::View<'_> = unsafe { B::view(&bound, region) };
+        f(region, views);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_cover_domain_once() {
+        let mut buffer = TiledBuffer::<u32, 64, 64>::new(150, 70);
+        dispatch_2d(150, 70, 32, (&mut buffer,), |region, (mut out,)| {
+            assert!(region.width <= 32 && region.height <= 32);
+            out.for_each_mut(|_, _, v| *v += 1);
+        });
+        for y in 0..70 {
+            for x in 0..150 {
+                assert_eq!(buffer.at(x, y), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn reads_neighbours_and_writes_several_buffers() {
+        let mut input = Buffer::<u32>::new(20, 10);
+        for y in 0..10 {
+            for x in 0..20 {
+                *input.at_mut(x, y) = x as u32 + y as u32 * 100;
+            }
+        }
+        let mut left = TiledBuffer::<u32, 64, 64>::new(20, 10);
+        let mut below = Buffer::<u32>::new(20, 10);
+        dispatch_2d(20, 10, 4, (&input, &mut left, &mut below), |region, (input, mut left, mut below)| {
+            for y in region.y..region.y + region.height {
+                for x in region.x..region.x + region.width {
+                    *left.at_mut(x, y) = input.at_clamped(x as i32 - 1, y as i32);
+                    *below.at_mut(x, y) = input.at_clamped(x as i32, y as i32 + 1);
+                }
+            }
+        });
+        assert_eq!(left.at(0, 0), 0);
+        assert_eq!(left.at(5, 3), 304);
+        assert_eq!(below.at(5, 3), 405);
+        assert_eq!(below.at(19, 9), 919);
+    }
+
+    #[test]
+    #[should_panic]
+    fn writes_outside_region_panic() {
+        let mut buffer = Buffer::<u32>::new(8, 8);
+        dispatch_2d(8, 8, 4, &mut buffer, |region, mut out| {
+            *out.at_mut(region.x + region.width, region.y) = 1;
+        });
+    }
+
+    #[test]
+    #[should_panic]
+    fn buffers_must_cover_domain() {
+        let mut buffer = Buffer::<u32>::new(8, 8);
+        dispatch_2d(16, 8, 4, &mut buffer, |_, _| {});
+    }
+}
@@ -0,0 +1,485 @@
+//! A bounding volume hierarchy over the triangles of a mesh, which speeds up finding the triangles hit by a ray from
+//! linear to roughly logarithmic in the number of triangles, e.g. for picking via `raycast_bvh()`.
+
+use super::picking::{MeshHit, mesh_hit};
+use crate::math::*;
+use crate::render::MeshData;
+
+/// A node of the hierarchy, either an inner one with two children or a leaf with a range of triangles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BvhNode {
+    /// The bounds of all the triangles under the node, in the mesh's space.
+    pub aabb: AABB,
+
+    /// For the inner nodes, the index of the first child, the second one follows it. For the leaves, the index of the
+    /// first triangle in `Bvh::triangles()`.
+    pub first: u32,
+
+    /// The number of the leaf's triangles, 0 for the inner nodes.
+    pub count: u32,
+}
+
+impl BvhNode {
+    pub fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+/// A binary tree of boxes over the mesh's triangles, built by the surface area heuristic.
+/// The hierarchy only stores the triangles' indices, so it's used together with the mesh it was built from. When the
+/// mesh's vertices move without changing the triangles, e.g. by an animation, `refit()` updates the boxes in place of
+/// rebuilding the tree. The tree can be stored via `to_bytes()` and loaded back via `from_bytes()`, e.g. to build it
+/// offline.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Bvh {
+    /// The root comes first, the children always come after their parents.
+    nodes: Vec<BvhNode>,
+
+    /// The indices of the mesh's triangles, ordered so that each leaf's ones are contiguous.
+    triangles: Vec<u32>,
+}
+
+/// The reason why bytes can't be loaded as a `Bvh`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BvhError {
+    /// The bytes don't start with the hierarchy's signature.
+    InvalidSignature,
+
+    /// The bytes were written by an incompatible version.
+    UnsupportedVersion(u32),
+
+    /// The bytes end before the data they declare.
+    Truncated { expected: usize, actual: usize },
+
+    /// A node refers to a child or to triangles outside of the hierarchy.
+    InvalidNode(usize),
+}
+
+impl std::fmt::Display for BvhError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            BvhError::InvalidSignature => write!(f, "the data is not a BVH"),
+            BvhError::UnsupportedVersion(version) => write!(f, "unsupported BVH version {}", version),
+            BvhError::Truncated { expected, actual } => {
+                write!(f, "the BVH data must have {} bytes, got {}", expected, actual)
+            }
+            BvhError::InvalidNode(node) => write!(f, "BVH node {} refers to non-existent nodes or triangles", node),
+        }
+    }
+}
+
+impl std::error::Error for BvhError {}
+
+impl Bvh {
+    /// The leaves are split while they have more triangles than this and the split is cheaper by the heuristic.
+    pub const MAX_LEAF_TRIANGLES: usize = 4;
+
+    /// The leaves are always split while they have more triangles than this.
+    const MAX_UNSPLIT_TRIANGLES: usize = 16;
+
+    /// The candidate split planes per axis.
+    const BINS: usize = 12;
+
+    const SIGNATURE: [u8; 4] = *b"NBVH";
+    const VERSION: u32 = 1;
+
+    pub fn build(mesh: &MeshData) -> Bvh {
+        let boxes: Vec<AABB> = (0..mesh.num_triangles())
+            .map(|triangle| triangle_aabb(mesh, triangle))
+            .collect();
+        let centers: Vec<Vec3> = boxes.iter().map(|aabb| aabb.center()).collect();
+        let mut bvh = Bvh { nodes: Vec::new(), triangles: (0..boxes.len() as u32).collect() };
+        if boxes.is_empty() {
+            return bvh;
+        }
+        bvh.nodes.push(BvhNode { aabb: AABB::default(), first: 0, count: 0 });
+        let mut stack: Vec<(usize, usize, usize)> = vec![(0, 0, boxes.len())];
+        while let Some((node, start, end)) = stack.pop() {
+            let range: &mut [u32] = &mut bvh.triangles[start..end];
+            let aabb: AABB = range
+                .iter()
+                .map(|&t| boxes[t as usize])
+                .reduce(|a, b| a.union(&b))
+                .unwrap();
+            bvh.nodes[node] = BvhNode { aabb, first: start as u32, count: (end - start) as u32 };
+            if range.len() <= Self::MAX_LEAF_TRIANGLES {
+                continue;
+            }
+            let Some(middle) = split(range, &boxes, &centers, aabb.surface_area()) else {
+                continue;
+            };
+            let first: usize = bvh.nodes.len();
+            bvh.nodes.push(BvhNode { aabb: AABB::default(), first: 0, count: 0 });
+            bvh.nodes.push(BvhNode { aabb: AABB::default(), first: 0, count: 0 });
+            bvh.nodes[node].first = first as u32;
+            bvh.nodes[node].count = 0;
+            stack.push((first + 1, start + middle, end));
+            stack.push((first, start, start + middle));
+        }
+        bvh
+    }
+
+    pub fn nodes(&self) -> &[BvhNode] {
+        &self.nodes
+    }
+
+    pub fn triangles(&self) -> &[u32] {
+        &self.triangles
+    }
+
+    /// The bounds of the whole mesh, or an empty box if it has no triangles.
+    pub fn aabb(&self) -> AABB {
+        self.nodes.first().map_or(AABB::default(), |root| root.aabb)
+    }
+
+    /// Updates the boxes after the mesh's vertices have moved. The mesh must have the same triangles the hierarchy was
+    /// built from. The tree isn't rebalanced, so the queries get slower as the triangles move far from their original
+    /// places, then it's better to rebuild it.
+    pub fn refit(&mut self, mesh: &MeshData) {
+        for index in (0..self.nodes.len()).rev() {
+            let node: BvhNode = self.nodes[index];
+            let aabb: AABB = if node.is_leaf() {
+                let range = node.first as usize..(node.first + node.count) as usize;
+                self.triangles[range]
+                    .iter()
+                    .map(|&triangle| triangle_aabb(mesh, triangle as usize))
+                    .reduce(|a, b| a.union(&b))
+                    .unwrap()
+            } else {
+                let first: usize = node.first as usize;
+                self.nodes[first].aabb.union(&self.nodes[first + 1].aabb)
+            };
+            self.nodes[index].aabb = aabb;
+        }
+    }
+
+    /// The closest triangle hit by the ray in the mesh's space, along with the hit.
+    pub fn raycast(&self, ray: &Ray, mesh: &MeshData) -> Option<(usize, TriangleHit)> {
+        let mut closest: Option<(usize, TriangleHit)> = None;
+        self.traverse(ray, |triangle| {
+            let [a, b, c] = mesh.triangle(triangle);
+            let p: &[Vec3] = &mesh.positions;
+            if let Some(hit) = ray.intersect_triangle(p[a], p[b], p[c])
+                && closest.is_none_or(|(_, closest)| hit.t < closest.t)
+            {
+                closest = Some((triangle, hit));
+            }
+            closest.map_or(f32::INFINITY, |(_, hit)| hit.t)
+        });
+        closest
+    }
+
+    /// Whether the ray hits any triangle closer than max_t, e.g. whether a point is in shadow. Stops at the first hit.
+    pub fn occluded(&self, ray: &Ray, mesh: &MeshData, max_t: f32) -> bool {
+        let mut occluded: bool = false;
+        self.traverse(ray, |triangle| {
+            let [a, b, c] = mesh.triangle(triangle);
+            let p: &[Vec3] = &mesh.positions;
+            occluded |= ray
+                .intersect_triangle(p[a], p[b], p[c])
+                .is_some_and(|hit| hit.t < max_t);
+            if occluded { -1.0 } else { max_t }
+        });
+        occluded
+    }
+
+    /// Writes the hierarchy as little-endian: the signature, the version, the numbers of the nodes and the triangles,
+    /// then the nodes as the boxes' corners and the two indices, then the triangles.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(16 + self.nodes.len() * 32 + self.triangles.len() * 4);
+        bytes.extend_from_slice(&Self::SIGNATURE);
+        for value in [Self::VERSION, self.nodes.len() as u32, self.triangles.len() as u32] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for node in &self.nodes {
+            let (min, max) = (node.aabb.min, node.aabb.max);
+            for value in [min.x, min.y, min.z, max.x, max.y, max.z] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes.extend_from_slice(&node.first.to_le_bytes());
+            bytes.extend_from_slice(&node.count.to_le_bytes());
+        }
+        for triangle in &self.triangles {
+            bytes.extend_from_slice(&triangle.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Loads the hierarchy written by `to_bytes()`, validating that the nodes only refer to the existing nodes and
+    /// triangles.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Bvh, BvhError> {
+        if bytes.len() < 16 {
+            return Err(BvhError::Truncated { expected: 16, actual: bytes.len() });
+        }
+        if bytes[0..4] != Self::SIGNATURE {
+            return Err(BvhError::InvalidSignature);
+        }
+        let u32_at = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let f32_at = |offset: usize| f32::from_bits(u32_at(offset));
+        let version: u32 = u32_at(4);
+        if version != Self::VERSION {
+            return Err(BvhError::UnsupportedVersion(version));
+        }
+        let (nodes_num, triangles_num) = (u32_at(8) as usize, u32_at(12) as usize);
+        let expected: usize = 16 + nodes_num * 32 + triangles_num * 4;
+        if bytes.len() != expected {
+            return Err(BvhError::Truncated { expected, actual: bytes.len() });
+        }
+        let nodes: Vec<BvhNode> = (0..nodes_num)
+            .map(|index| {
+                let offset: usize = 16 + index * 32;
+                let corner = |at: usize| Vec3::new(f32_at(at), f32_at(at + 4), f32_at(at + 8));
+                BvhNode {
+                    aabb: AABB::new(corner(offset), corner(offset + 12)),
+                    first: u32_at(offset + 24),
+                    count: u32_at(offset + 28),
+                }
+            })
+            .collect();
+        let triangles_offset: usize = 16 + nodes_num * 32;
+        let triangles: Vec<u32> = (0..triangles_num)
+            .map(|index| u32_at(triangles_offset + index * 4))
+            .collect();
+        for (index, node) in nodes.iter().enumerate() {
+            let valid: bool = if node.is_leaf() {
+                node.first as usize + node.count as usize <= triangles_num
+            } else {
+                node.first as usize > index && node.first as usize + 1 < nodes_num
+            };
+            if !valid {
+                return Err(BvhError::InvalidNode(index));
+            }
+        }
+        Ok(Bvh { nodes, triangles })
+    }
+
+    /// Visits the triangles of the leaves hit by the ray, nearer children first. The visitor returns the distance beyond
+    /// which the rest of the nodes can be skipped.
+    fn traverse(&self, ray: &Ray, mut visit: impl FnMut(usize) -> f32) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut max_t: f32 = f32::INFINITY;
+        let mut stack: Vec<(usize, f32)> = Vec::with_capacity(64);
+        if let Some((near, _)) = ray.intersect_aabb(&self.nodes[0].aabb) {
+            stack.push((0, near));
+        }
+        while let Some((index, near)) = stack.pop() {
+            if near > max_t {
+                continue;
+            }
+            let node: &BvhNode = &self.nodes[index];
+            if node.is_leaf() {
+                let range = node.first as usize..(node.first + node.count) as usize;
+                for &triangle in &self.triangles[range] {
+                    max_t = max_t.min(visit(triangle as usize));
+                }
+                continue;
+            }
+            let first: usize = node.first as usize;
+            let hits = [first, first + 1].map(|child| {
+                ray.intersect_aabb(&self.nodes[child].aabb)
+                    .filter(|&(near, _)| near <= max_t)
+                    .map(|(near, _)| (child, near))
+            });
+            match hits {
+                [Some(a), Some(b)] => {
+                    // The nearer child is popped first
+                    let (nearer, further) = if a.1 <= b.1 { (a, b) } else { (b, a) };
+                    stack.push(further);
+                    stack.push(nearer);
+                }
+                [Some(hit), None] | [None, Some(hit)] => stack.push(hit),
+                [None, None] => {}
+            }
+        }
+    }
+}
+
+/// The closest hit of the mesh placed in the world by the model matrix, found via the mesh's hierarchy. Gives the same
+/// result as `raycast_mesh()`.
+pub fn raycast_bvh(ray: &Ray, mesh: &MeshData, bvh: &Bvh, model: &Mat34) -> Option<MeshHit> {
+    let local: Ray = ray.transformed(&model.as_mat44().inverse().as_mat34());
+    bvh.raycast(&local, mesh)
+        .map(|(triangle, hit)| mesh_hit(ray, mesh, model, triangle, hit))
+}
+
+fn triangle_aabb(mesh: &MeshData, triangle: usize) -> AABB {
+    let [a, b, c] = mesh.triangle(triangle);
+    AABB::from_points(&[mesh.positions[a], mesh.positions[b], mesh.positions[c]])
+}
+
+/// Reorders the triangles into two groups by the cheapest split plane and returns the size of the first group, None if
+/// keeping them in a leaf is cheaper. Falls back to the median split if the triangles have to be split, but the binning
+/// can't separate them.
+fn split(triangles: &mut [u32], boxes: &[AABB], centers: &[Vec3], area: f32) -> Option<usize> {
+    let bounds: AABB = AABB::from_points(&triangles.iter().map(|&t| centers[t as usize]).collect::<Vec<Vec3>>());
+    let extent: Vec3 = bounds.max - bounds.min;
+    let component = |v: Vec3, axis: usize| [v.x, v.y, v.z][axis];
+    let mut best: Option<(f32, usize, usize)> = None; // (cost, axis, the first bin of the second group)
+    for axis in 0..3 {
+        let extent: f32 = component(extent, axis);
+        if extent <= 0.0 {
+            continue;
+        }
+        let bin_of = |t: u32| -> usize {
+            let offset: f32 = component(centers[t as usize], axis) - component(bounds.min, axis);
+            ((offset / extent * Bvh::BINS as f32) as usize).min(Bvh::BINS - 1)
+        };
+        let mut bins: [(Option<AABB>, usize); Bvh::BINS] = [(None, 0); Bvh::BINS];
+        for &t in triangles.iter() {
+            let bin = &mut bins[bin_of(t)];
+            bin.0 = Some(bin.0.map_or(boxes[t as usize], |aabb| aabb.union(&boxes[t as usize])));
+            bin.1 += 1;
+        }
+        // The areas and the counts of the bins to the right of each plane, accumulated from the right
+        let mut right: [(f32, usize); Bvh::BINS] = [(0.0, 0); Bvh::BINS];
+        let mut accumulated: (Option<AABB>, usize) = (None, 0);
+        for plane in (1..Bvh::BINS).rev() {
+            accumulated = merge(accumulated, bins[plane]);
+            right[plane] = (accumulated.0.map_or(0.0, |aabb| aabb.surface_area()), accumulated.1);
+        }
+        let mut left: (Option<AABB>, usize) = (None, 0);
+        for plane in 1..Bvh::BINS {
+            left = merge(left, bins[plane - 1]);
+            let (right_area, right_count) = right[plane];
+            if left.1 == 0 || right_count == 0 {
+                continue;
+            }
+            let left_area: f32 = left.0.map_or(0.0, |aabb| aabb.surface_area());
+            let cost: f32 =
+                1.0 + (left_area * left.1 as f32 + right_area * right_count as f32) / area.max(f32::MIN_POSITIVE);
+            if best.is_none_or(|(best, _, _)| cost < best) {
+                best = Some((cost, axis, plane));
+            }
+        }
+    }
+    let leaf_cost: f32 = triangles.len() as f32;
+    match best {
+        Some((cost, axis, plane)) if cost < leaf_cost || triangles.len() > Bvh::MAX_UNSPLIT_TRIANGLES => {
+            let extent: f32 = component(extent, axis);
+            let in_first = |t: &u32| -> bool {
+                let offset: f32 = component(centers[*t as usize], axis) - component(bounds.min, axis);
+                ((offset / extent * Bvh::BINS as f32) as usize).min(Bvh::BINS - 1) < plane
+            };
+            Some(partition(triangles, in_first))
+        }
+        Some(_) => None,
+        None if triangles.len() > Bvh::MAX_UNSPLIT_TRIANGLES => {
+            // All the centers coincide, any split is as good as another
+            Some(triangles.len() / 2)
+        }
+        None => None,
+    }
+}
+
+fn merge(a: (Option<AABB>, usize), b: (Option<AABB>, usize)) -> (Option<AABB>, usize) {
+    let aabb: Option<AABB> = match (a.0, b.0) {
+        (Some(a), Some(b)) => Some(a.union(&b)),
+        (a, b) => a.or(b),
+    };
+    (aabb, a.1 + b.1)
+}
+
+/// Moves the elements matching the predicate to the front and returns their number.
+fn partition(items: &mut [u32], predicate: impl Fn(&u32) -> bool) -> usize {
+    let mut first: usize = 0;
+    for index in 0..items.len() {
+        if predicate(&items[index]) {
+            items.swap(first, index);
+            first += 1;
+        }
+    }
+    first
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::*;
+
+    fn random_rays(count: usize) -> Vec<Ray> {
+        // A deterministic spread of rays towards the origin from around it
+        (0..count)
+            .map(|i| {
+                let (a, b) = (i as f32 * 2.399, i as f32 * 0.731);
+                let origin = Vec3::new(a.cos() * 4.0, b.sin() * 3.0, a.sin() * 4.0);
+                let target = Vec3::new((i % 7) as f32 * 0.2 - 0.6, (i % 5) as f32 * 0.2 - 0.4, 0.0);
+                Ray::new(origin, (target - origin).normalized())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mesh: MeshData = torus(1.0, 0.4, 32, 16);
+        let bvh = Bvh::build(&mesh);
+        assert!(bvh.nodes().len() > 1);
+        assert_eq!(bvh.aabb(), mesh.aabb);
+        let mut sorted: Vec<u32> = bvh.triangles().to_vec();
+        sorted.sort();
+        assert_eq!(sorted, (0..mesh.num_triangles() as u32).collect::<Vec<u32>>());
+        assert!(
+            bvh.nodes()
+                .iter()
+                .all(|node| !node.is_leaf() || node.count as usize <= Bvh::MAX_UNSPLIT_TRIANGLES)
+        );
+
+        let model: Mat34 = Mat34::translate(Vec3::new(0.0, 1.0, 0.0)) * Mat34::rotate_yz(0.3);
+        let mut hits: usize = 0;
+        for ray in random_rays(200) {
+            let expected: Option<MeshHit> = raycast_mesh(&ray, &mesh, &model);
+            let actual: Option<MeshHit> = raycast_bvh(&ray, &mesh, &bvh, &model);
+            assert_eq!(expected.map(|hit| hit.triangle), actual.map(|hit| hit.triangle));
+            hits += expected.is_some() as usize;
+        }
+        assert!(hits > 50, "{}", hits);
+
+        // Through the hole and onto the tube from above
+        let ray = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(!bvh.occluded(&ray, &mesh, 100.0));
+        let ray = Ray::new(Vec3::new(1.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(bvh.occluded(&ray, &mesh, 100.0));
+        assert!(!bvh.occluded(&ray, &mesh, 4.0));
+    }
+
+    #[test]
+    fn refit_follows_moved_vertices() {
+        let mut mesh: MeshData = icosphere(1.0, 2);
+        let mut bvh = Bvh::build(&mesh);
+        for position in &mut mesh.positions {
+            *position = *position * 2.0 + Vec3::new(5.0, 0.0, 0.0);
+        }
+        mesh.update_aabb();
+        bvh.refit(&mesh);
+        assert_eq!(bvh.aabb(), mesh.aabb);
+        for ray in random_rays(50) {
+            let ray = Ray::new(ray.origin * 2.0 + Vec3::new(5.0, 0.0, 0.0), ray.direction);
+            let expected = raycast_mesh(&ray, &mesh, &Mat34::identity()).map(|hit| hit.triangle);
+            assert_eq!(bvh.raycast(&ray, &mesh).map(|(triangle, _)| triangle), expected);
+        }
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mesh: MeshData = cuboid(Vec3::new(1.0, 2.0, 3.0));
+        let bvh = Bvh::build(&uv_sphere(1.0, 16, 8));
+        let bytes: Vec<u8> = bvh.to_bytes();
+        assert_eq!(Bvh::from_bytes(&bytes), Ok(bvh.clone()));
+        assert_eq!(Bvh::from_bytes(&Bvh::build(&mesh).to_bytes()), Ok(Bvh::build(&mesh)));
+        assert_eq!(Bvh::from_bytes(&Bvh::default().to_bytes()), Ok(Bvh::default()));
+
+        assert_eq!(
+            Bvh::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(BvhError::Truncated { expected: bytes.len(), actual: bytes.len() - 1 })
+        );
+        assert_eq!(Bvh::from_bytes(b"PNG!0000000000000000"), Err(BvhError::InvalidSignature));
+        let mut newer: Vec<u8> = bytes.clone();
+        newer[4] = 2;
+        assert_eq!(Bvh::from_bytes(&newer), Err(BvhError::UnsupportedVersion(2)));
+        // The root's first child pointing past the nodes
+        let mut broken: Vec<u8> = bytes.clone();
+        broken[16 + 24..16 + 28].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(Bvh::from_bytes(&broken), Err(BvhError::InvalidNode(0)));
+    }
+}
@@ -0,0 +1,128 @@
+use super::simplify::simplify;
+use crate::math::*;
+use crate::render::MeshData;
+use std::sync::Arc;
+
+/// A version of a mesh drawn while it's large enough on the screen.
+#[derive(Debug, Clone)]
+pub struct LODLevel {
+    pub mesh: Arc<MeshData>,
+
+    /// The smallest screen size the level is drawn at, see `screen_size()`.
+    pub screen_size: f32,
+}
+
+/// Versions of a mesh with decreasing numbers of triangles, one of which is picked to draw depending on how large the
+/// mesh is on the screen.
+#[derive(Debug, Clone, Default)]
+pub struct LODGroup {
+    /// From the most detailed level to the least detailed one, with decreasing screen sizes.
+    pub levels: Vec<LODLevel>,
+}
+
+impl LODGroup {
+    /// Builds up to `levels` levels of the mesh by simplifying it, each one with a quarter of the previous level's
+    /// triangles and used at a half of its screen size, so that the triangles keep their size on the screen.
+    /// The original mesh is drawn while it covers at least half of the viewport's height and the last level is never
+    /// culled. Stops early if the mesh can't be simplified any further.
+    pub fn generate(mesh: Arc<MeshData>, levels: usize) -> LODGroup {
+        let mut group = LODGroup { levels: vec![LODLevel { mesh, screen_size: 0.5 }] };
+        while group.levels.len() < levels {
+            let last: &LODLevel = group.levels.last().unwrap();
+            let triangles: usize = last.mesh.num_triangles();
+            if triangles < 4 {
+                break;
+            }
+            let simplified: MeshData = simplify(&last.mesh, triangles / 4);
+            if simplified.num_triangles() * 10 > triangles * 9 {
+                break;
+            }
+            let screen_size: f32 = last.screen_size * 0.5;
+            group
+                .levels
+                .push(LODLevel { mesh: simplified.into_shared(), screen_size });
+        }
+        if let Some(last) = group.levels.last_mut() {
+            last.screen_size = 0.0;
+        }
+        group
+    }
+
+    /// The index of the most detailed level the screen size is enough for, None if it's smaller than all of them, i.e.
+    /// the mesh shouldn't be drawn.
+    pub fn select(&self, screen_size: f32) -> Option<usize> {
+        self.levels.iter().position(|level| screen_size >= level.screen_size)
+    }
+
+    /// The level to draw the mesh with, measured by the most detailed level's bounding box.
+    pub fn level_for(&self, model: &Mat34, view: &Mat44, projection: &Mat44) -> Option<&LODLevel> {
+        let aabb: AABB = self.levels.first()?.mesh.aabb;
+        self.select(screen_size(&aabb, model, view, projection))
+            .map(|index| &self.levels[index])
+    }
+}
+
+/// The height of the bounding box's bounding sphere on the screen as a fraction of the viewport's height, e.g. 1.0 when
+/// the sphere exactly fits in the viewport vertically. Returns infinity if the camera is inside the sphere.
+pub fn screen_size(aabb: &AABB, model: &Mat34, view: &Mat44, projection: &Mat44) -> f32 {
+    let m: &[f32; 12] = &model.0;
+    let scale: f32 = [0, 1, 2]
+        .map(|column| Vec3::new(m[column], m[column + 4], m[column + 8]).length())
+        .into_iter()
+        .fold(0.0, f32::max);
+    let radius: f32 = (aabb.max - aabb.min).length() * 0.5 * scale;
+    let center: Vec4 = *view * (model * ((aabb.min + aabb.max) * 0.5)).as_point4();
+    // The distance along the view direction for the perspective projections, 1 for the orthographic ones
+    let p: &[f32; 16] = &projection.0;
+    let w: f32 = p[12] * center.x + p[13] * center.y + p[14] * center.z + p[15];
+    if w <= radius * p[14].abs() {
+        return f32::INFINITY;
+    }
+    radius * p[5] / w
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::*;
+
+    #[test]
+    fn levels_halve_the_screen_size() {
+        // Without the texture coordinates, so that there's no seam to keep
+        let sphere: MeshData = icosphere(1.0, 4);
+        let mesh = MeshData { positions: sphere.positions, indices: sphere.indices, ..Default::default() };
+        let group = LODGroup::generate(mesh.into_shared(), 4);
+        assert_eq!(group.levels.len(), 4);
+        let triangles: Vec<usize> = group.levels.iter().map(|level| level.mesh.num_triangles()).collect();
+        assert_eq!(triangles[0], 5120);
+        assert!(triangles.windows(2).all(|pair| pair[1] <= pair[0] / 4));
+        let sizes: Vec<f32> = group.levels.iter().map(|level| level.screen_size).collect();
+        assert_eq!(sizes, vec![0.5, 0.25, 0.125, 0.0]);
+        assert_eq!(group.select(2.0), Some(0));
+        assert_eq!(group.select(0.3), Some(1));
+        assert_eq!(group.select(0.01), Some(3));
+    }
+
+    #[test]
+    fn generation_stops_when_nothing_is_left() {
+        let group = LODGroup::generate(plane(Vec2::new(1.0, 1.0), 2, 2).into_shared(), 8);
+        assert_eq!(group.levels.len(), 2);
+        assert_eq!(group.levels[1].mesh.num_triangles(), 2);
+    }
+
+    #[test]
+    fn screen_size_of_sphere() {
+        // A unit cube has a bounding sphere of sqrt(3), seen from 10 units away with a vertical field of view of 90°
+        let aabb = AABB::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        let projection = Mat44::perspective(1.0, 100.0, std::f32::consts::FRAC_PI_2, 2.0);
+        let view = Mat44::translate(Vec3::new(0.0, 0.0, -10.0));
+        let size: f32 = screen_size(&aabb, &Mat34::identity(), &view, &projection);
+        assert!((size - 3.0f32.sqrt() / 10.0).abs() < 1e-5);
+        let scaled: f32 = screen_size(&aabb, &Mat34::scale_uniform(2.0), &view, &projection);
+        assert!((scaled - size * 2.0).abs() < 1e-5);
+        assert_eq!(screen_size(&aabb, &Mat34::identity(), &Mat44::identity(), &projection), f32::INFINITY);
+        let orthographic = Mat44::orthographic(-4.0, 4.0, -4.0, 4.0, 1.0, 100.0);
+        let size: f32 = screen_size(&aabb, &Mat34::identity(), &view, &orthographic);
+        assert!((size - 3.0f32.sqrt() / 4.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,11 @@
+pub mod bvh;
+pub mod lod;
+pub mod picking;
+pub mod primitives;
+pub mod simplify;
+
+pub use bvh::*;
+pub use lod::*;
+pub use picking::*;
+pub use primitives::*;
+pub use simplify::*;
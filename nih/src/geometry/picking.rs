@@ -0,0 +1,151 @@
+//! Picking of meshes by rays, e.g. finding the mesh under the mouse cursor: `screen_ray()` turns a pixel into a ray in
+//! the world and `raycast_mesh()` or `pick()` find the closest triangle it hits. For the large meshes, `raycast_bvh()`
+//! does the same via the mesh's `Bvh`.
+
+use crate::math::*;
+use crate::render::{MeshData, Viewport};
+
+/// Where a ray hits a mesh.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshHit {
+    /// The distance along the ray, in the lengths of its direction.
+    pub t: f32,
+
+    /// The index of the triangle hit, see `MeshData::triangle()`.
+    pub triangle: usize,
+
+    /// The barycentric coordinates of the hit within the triangle, see `TriangleHit`.
+    pub u: f32,
+    pub v: f32,
+
+    /// The hit point in the world space.
+    pub position: Vec3,
+
+    /// The unit normal at the hit point in the world space, interpolated from the vertices' normals or the triangle's
+    /// counter-clockwise face normal if the mesh has no normals.
+    pub normal: Vec3,
+
+    /// The texture coordinates at the hit point, (0, 0) if the mesh has none.
+    pub tex_coord: Vec2,
+}
+
+/// The ray from the camera through the point of the viewport, e.g. the mouse cursor's position in pixels with (0, 0) at
+/// the top-left corner of the framebuffer. Pass the pixel's center, i.e. +0.5, to match the rasterized pixel.
+/// The ray starts at the near plane and its direction is normalized. Works with both perspective and orthographic
+/// projections.
+pub fn screen_ray(x: f32, y: f32, viewport: &Viewport, view: &Mat44, projection: &Mat44) -> Ray {
+    let width: f32 = (viewport.xmax - viewport.xmin) as f32;
+    let height: f32 = (viewport.ymax - viewport.ymin) as f32;
+    let ndc_x: f32 = (x - viewport.xmin as f32) / width * 2.0 - 1.0;
+    let ndc_y: f32 = 1.0 - (y - viewport.ymin as f32) / height * 2.0;
+    let inverse: Mat44 = (*projection * *view).inverse();
+    let unproject = |z: f32| -> Vec3 {
+        let p: Vec4 = inverse * Vec4::new(ndc_x, ndc_y, z, 1.0);
+        p.xyz() / p.w
+    };
+    let (near, far) = (unproject(-1.0), unproject(1.0));
+    Ray::new(near, (far - near).normalized())
+}
+
+/// The closest hit of the mesh placed in the world by the model matrix, both sides of the triangles are hit.
+/// The ray is first tested against the mesh's bounding box, which must be up to date.
+pub fn raycast_mesh(ray: &Ray, mesh: &MeshData, model: &Mat34) -> Option<MeshHit> {
+    let local: Ray = ray.transformed(&model.as_mat44().inverse().as_mat34());
+    if mesh.aabb.min != mesh.aabb.max {
+        local.intersect_aabb(&mesh.aabb)?;
+    }
+    let mut closest: Option<(usize, TriangleHit)> = None;
+    for triangle in 0..mesh.num_triangles() {
+        let [a, b, c] = mesh.triangle(triangle);
+        let positions: &[Vec3] = &mesh.positions;
+        if let Some(hit) = local.intersect_triangle(positions[a], positions[b], positions[c])
+            && closest.is_none_or(|(_, closest)| hit.t < closest.t)
+        {
+            closest = Some((triangle, hit));
+        }
+    }
+    closest.map(|(triangle, hit)| mesh_hit(ray, mesh, model, triangle, hit))
+}
+
+/// The closest hit among the meshes placed by their model matrices, along with the index of the mesh hit.
+pub fn pick(ray: &Ray, meshes: &[(&MeshData, Mat34)]) -> Option<(usize, MeshHit)> {
+    meshes
+        .iter()
+        .enumerate()
+        .filter_map(|(index, (mesh, model))| raycast_mesh(ray, mesh, model).map(|hit| (index, hit)))
+        .min_by(|(_, a), (_, b)| a.t.total_cmp(&b.t))
+}
+
+// Fills in the attributes of the hit found in the mesh's space.
+pub(crate) fn mesh_hit(ray: &Ray, mesh: &MeshData, model: &Mat34, triangle: usize, hit: TriangleHit) -> MeshHit {
+    let [a, b, c] = mesh.triangle(triangle);
+    let w: f32 = 1.0 - hit.u - hit.v;
+    let normal: Vec3 = if mesh.normals.is_empty() {
+        let p: &[Vec3] = &mesh.positions;
+        cross(p[b] - p[a], p[c] - p[a])
+    } else {
+        mesh.normals[a] * w + mesh.normals[b] * hit.u + mesh.normals[c] * hit.v
+    };
+    let tex_coord: Vec2 = if mesh.tex_coords.is_empty() {
+        Vec2::new(0.0, 0.0)
+    } else {
+        mesh.tex_coords[a] * w + mesh.tex_coords[b] * hit.u + mesh.tex_coords[c] * hit.v
+    };
+    let normal_matrix: Mat33 = model.as_mat33().inverse().transpose();
+    MeshHit {
+        t: hit.t,
+        triangle,
+        u: hit.u,
+        v: hit.v,
+        position: ray.at(hit.t),
+        normal: (normal_matrix * normal).normalized(),
+        tex_coord,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::*;
+
+    fn assert_vec3_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn screen_center_looks_forward() {
+        let viewport = Viewport::new(0, 0, 64, 32);
+        let projection = Mat44::perspective(1.0, 10.0, std::f32::consts::FRAC_PI_2, 2.0);
+        let view = Mat44::translate(Vec3::new(0.0, 0.0, -5.0));
+        let ray: Ray = screen_ray(32.0, 16.0, &viewport, &view, &projection);
+        assert_vec3_near(ray.origin, Vec3::new(0.0, 0.0, 4.0));
+        assert_vec3_near(ray.direction, Vec3::new(0.0, 0.0, -1.0));
+        // The top-left corner is at 45° up and at atan(2) to the left
+        let corner: Ray = screen_ray(0.0, 0.0, &viewport, &view, &projection);
+        assert_vec3_near(corner.direction, Vec3::new(-2.0, 1.0, -1.0).normalized());
+        let orthographic = Mat44::orthographic(-2.0, 2.0, -1.0, 1.0, 1.0, 10.0);
+        let corner: Ray = screen_ray(0.0, 32.0, &viewport, &view, &orthographic);
+        assert_vec3_near(corner.origin, Vec3::new(-2.0, -1.0, 4.0));
+        assert_vec3_near(corner.direction, Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn closest_triangle_is_hit() {
+        let cube: MeshData = cuboid(Vec3::new(2.0, 2.0, 2.0));
+        let model: Mat34 = Mat34::translate(Vec3::new(0.0, 0.0, -5.0));
+        let ray = Ray::new(Vec3::new(0.5, 0.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit: MeshHit = raycast_mesh(&ray, &cube, &model).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-5);
+        assert_vec3_near(hit.position, Vec3::new(0.5, 0.5, -4.0));
+        assert_vec3_near(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+        let missing = Ray::new(Vec3::new(1.5, 0.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(raycast_mesh(&missing, &cube, &model), None);
+
+        // The nearer of the two cubes is picked regardless of the order
+        let near: Mat34 = Mat34::translate(Vec3::new(0.0, 0.0, -3.0)) * Mat34::scale_uniform(0.5);
+        let (index, hit) = pick(&ray, &[(&cube, model), (&cube, near)]).unwrap();
+        assert_eq!(index, 1);
+        assert!((hit.t - 2.5).abs() < 1e-5);
+        assert_vec3_near(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+}
@@ -0,0 +1,355 @@
+//! All the primitives are centered at the origin with +Y up, indexed, and have a single section.
+//! The triangles are counter-clockwise when seen from the outside, the normals and the tangents are unit vectors and the
+//! textures are laid out as seen from the outside: U goes right and V goes down, so the tangents point right.
+//! The round primitives start U at -Z, i.e. their texture seam is at the back.
+
+use crate::math::*;
+use crate::render::{MeshData, MeshDataSection};
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+
+/// A sphere made of `segments` slices around the Y axis and `rings` stacks from the north pole to the south one.
+/// The texture is mapped equirectangularly.
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> MeshData {
+    assert!(segments >= 3 && rings >= 2);
+    let mut mesh = MeshData::default();
+    append_surface(&mut mesh, segments, rings, (true, true), |uv| {
+        let (radial, tangent) = around(uv.x);
+        let (sin, cos) = (PI * uv.y).sin_cos();
+        let normal: Vec3 = radial * sin + Vec3::new(0.0, cos, 0.0);
+        (normal * radius, normal, tangent)
+    });
+    finish(mesh)
+}
+
+/// A subdivided icosahedron projected onto the sphere, its triangles are more uniform than the ones of `uv_sphere()`.
+/// Every subdivision quadruples the 20 triangles of the icosahedron. The texture is mapped the same way as on
+/// `uv_sphere()`, the vertices along the seam and at the poles are duplicated.
+pub fn icosphere(radius: f32, subdivisions: u32) -> MeshData {
+    let t: f32 = (1.0 + 5.0f32.sqrt()) * 0.5;
+    let mut points: Vec<Vec3> = [
+        (-1.0, t, 0.0),
+        (1.0, t, 0.0),
+        (-1.0, -t, 0.0),
+        (1.0, -t, 0.0),
+        (0.0, -1.0, t),
+        (0.0, 1.0, t),
+        (0.0, -1.0, -t),
+        (0.0, 1.0, -t),
+        (t, 0.0, -1.0),
+        (t, 0.0, 1.0),
+        (-t, 0.0, -1.0),
+        (-t, 0.0, 1.0),
+    ]
+    .iter()
+    .map(|&(x, y, z)| Vec3::new(x, y, z).normalized())
+    .collect();
+    #[rustfmt::skip]
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+    for _ in 0..subdivisions {
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| -> u32 {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                points.push((points[a as usize] + points[b as usize]).normalized());
+                points.len() as u32 - 1
+            })
+        };
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    // The points are shared by the triangles unless their U coordinates differ
+    let mut mesh = MeshData::default();
+    let mut vertices: HashMap<(u32, u32), u32> = HashMap::new();
+    for triangle in &triangles {
+        let normals: [Vec3; 3] = triangle.map(|index| points[index as usize]);
+        let poles: [bool; 3] = normals.map(|normal| normal.x.abs() < 1e-6 && normal.z.abs() < 1e-6);
+        let angles: [f32; 3] = normals.map(|normal| (normal.x.atan2(normal.z) + PI) / TAU);
+        let sides = || (0..3).filter(|&i| !poles[i]).map(|i| angles[i]);
+        // The triangles crossing the seam continue past U = 1 instead of going all the way back to 0
+        let (min, max) = sides().fold((f32::MAX, f32::MIN), |(min, max), u| (min.min(u), max.max(u)));
+        let wrap = |u: f32| if max - min > 0.5 && u < 0.5 { u + 1.0 } else { u };
+        // The poles take the U of the triangle's middle, so that they don't shear the texture
+        let middle: f32 = sides().map(wrap).sum::<f32>() / sides().count() as f32;
+        for (i, &point) in triangle.iter().enumerate() {
+            let (normal, u): (Vec3, f32) = (normals[i], if poles[i] { middle } else { wrap(angles[i]) });
+            let index: u32 = *vertices.entry((point, u.to_bits())).or_insert_with(|| {
+                mesh.positions.push(normal * radius);
+                mesh.normals.push(normal);
+                mesh.tangents.push(around(u).1);
+                mesh.tex_coords
+                    .push(Vec2::new(u, normal.y.clamp(-1.0, 1.0).acos() / PI));
+                mesh.positions.len() as u32 - 1
+            });
+            mesh.indices.push(index);
+        }
+    }
+    finish(mesh)
+}
+
+/// A box with the given width, height and depth, every face shows the entire texture.
+/// Named so because `box` is a reserved word.
+pub fn cuboid(size: Vec3) -> MeshData {
+    let x = Vec3::new(1.0, 0.0, 0.0);
+    let y = Vec3::new(0.0, 1.0, 0.0);
+    let z = Vec3::new(0.0, 0.0, 1.0);
+    // The faces' normals, right and down directions
+    let faces: [(Vec3, Vec3, Vec3); 6] = [(z, x, -y), (-z, -x, -y), (x, -z, -y), (-x, z, -y), (y, x, z), (-y, x, -z)];
+    let mut mesh = MeshData::default();
+    for (normal, right, down) in faces {
+        append_surface(&mut mesh, 1, 1, (false, false), |uv| {
+            let position: Vec3 = normal + right * (uv.x * 2.0 - 1.0) + down * (uv.y * 2.0 - 1.0);
+            (position * size * 0.5, normal, right)
+        });
+    }
+    finish(mesh)
+}
+
+/// A cylinder around the Y axis made of `segments` slices, with the caps.
+/// The side is mapped onto the entire texture from the top down, the caps are mapped as seen from above and below.
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> MeshData {
+    assert!(segments >= 3);
+    let mut mesh = MeshData::default();
+    append_surface(&mut mesh, segments, 1, (false, false), |uv| {
+        let (radial, tangent) = around(uv.x);
+        (radial * radius + Vec3::new(0.0, (0.5 - uv.y) * height, 0.0), radial, tangent)
+    });
+    append_cap(&mut mesh, radius, height * 0.5, segments, true);
+    append_cap(&mut mesh, radius, -height * 0.5, segments, false);
+    finish(mesh)
+}
+
+/// A cone around the Y axis made of `segments` slices, with the apex at the top and the base cap at the bottom.
+/// The side is mapped onto the entire texture from the apex down, the apex has a vertex per slice to keep its normals
+/// smooth along the side.
+pub fn cone(radius: f32, height: f32, segments: u32) -> MeshData {
+    assert!(segments >= 3);
+    let mut mesh = MeshData::default();
+    append_surface(&mut mesh, segments, 1, (true, false), |uv| {
+        let (radial, tangent) = around(uv.x);
+        let position: Vec3 = radial * (radius * uv.y) + Vec3::new(0.0, (0.5 - uv.y) * height, 0.0);
+        let normal: Vec3 = (radial * height + Vec3::new(0.0, radius, 0.0)).normalized();
+        (position, normal, tangent)
+    });
+    append_cap(&mut mesh, radius, -height * 0.5, segments, false);
+    finish(mesh)
+}
+
+/// A torus lying on the XZ plane, `major_radius` away from the origin to the center of its tube of `minor_radius`.
+/// U goes around the Y axis over `segments` slices, V goes around the tube over `sides` ones starting at its top.
+pub fn torus(major_radius: f32, minor_radius: f32, segments: u32, sides: u32) -> MeshData {
+    assert!(segments >= 3 && sides >= 3);
+    let mut mesh = MeshData::default();
+    append_surface(&mut mesh, segments, sides, (false, false), |uv| {
+        let (radial, tangent) = around(uv.x);
+        // Starts at the top of the tube and goes down its outer side
+        let (sin, cos) = (PI * 0.5 - TAU * uv.y.fract()).sin_cos();
+        let normal: Vec3 = radial * cos + Vec3::new(0.0, sin, 0.0);
+        (radial * major_radius + normal * minor_radius, normal, tangent)
+    });
+    finish(mesh)
+}
+
+/// A subdivided plane facing +Y, the same as `MeshData::grid()` but with the tangents.
+pub fn plane(size: Vec2, columns: u32, rows: u32) -> MeshData {
+    let mut mesh = MeshData::grid(columns, rows, size);
+    mesh.tangents = vec![Vec3::new(1.0, 0.0, 0.0); mesh.positions.len()];
+    mesh
+}
+
+/// The outward direction and the tangent at the angle `u` of a full turn around the Y axis, starting at -Z.
+/// The whole turns are dropped first, so that both sides of the seams get exactly the same positions.
+fn around(u: f32) -> (Vec3, Vec3) {
+    let (sin, cos) = (TAU * u.fract() - PI).sin_cos();
+    (Vec3::new(sin, 0.0, cos), Vec3::new(cos, 0.0, -sin))
+}
+
+/// Appends a parametric surface of `columns` x `rows` quads, `vertex` maps the texture coordinates to the position, the
+/// normal and the tangent. The `poles` are the top and the bottom rows collapsed into a point, e.g. the apex of a cone:
+/// they have a vertex per column in its middle and a triangle per quad.
+fn append_surface(
+    mesh: &mut MeshData,
+    columns: u32,
+    rows: u32,
+    poles: (bool, bool),
+    vertex: impl Fn(Vec2) -> (Vec3, Vec3, Vec3),
+) {
+    let is_pole = |row: u32| (row == 0 && poles.0) || (row == rows && poles.1);
+    let mut row_starts: Vec<u32> = Vec::with_capacity(rows as usize + 1);
+    for j in 0..=rows {
+        row_starts.push(mesh.positions.len() as u32);
+        let (offset, count) = if is_pole(j) { (0.5, columns) } else { (0.0, columns + 1) };
+        for i in 0..count {
+            let uv = Vec2::new((i as f32 + offset) / columns as f32, j as f32 / rows as f32);
+            let (position, normal, tangent) = vertex(uv);
+            mesh.positions.push(position);
+            mesh.normals.push(normal);
+            mesh.tangents.push(tangent);
+            mesh.tex_coords.push(uv);
+        }
+    }
+    for j in 0..rows {
+        for i in 0..columns {
+            let (a, b) = (row_starts[j as usize] + i, row_starts[j as usize + 1] + i);
+            if is_pole(j) {
+                mesh.indices.extend_from_slice(&[a, b, b + 1]);
+            } else if is_pole(j + 1) {
+                mesh.indices.extend_from_slice(&[a, b, a + 1]);
+            } else {
+                mesh.indices.extend_from_slice(&[a, b, a + 1, a + 1, b, b + 1]);
+            }
+        }
+    }
+}
+
+/// Appends a disk on the XZ plane at the height `y`, facing up or down.
+fn append_cap(mesh: &mut MeshData, radius: f32, y: f32, segments: u32, up: bool) {
+    let center: u32 = mesh.positions.len() as u32;
+    let (normal, down) = if up {
+        (Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0))
+    } else {
+        (Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 0.0, -1.0))
+    };
+    for i in 0..=segments {
+        let offset: Vec3 = if i == 0 {
+            Vec3::new(0.0, 0.0, 0.0)
+        } else {
+            around(i as f32 / segments as f32).0
+        };
+        mesh.positions.push(offset * radius + Vec3::new(0.0, y, 0.0));
+        mesh.normals.push(normal);
+        mesh.tangents.push(Vec3::new(1.0, 0.0, 0.0));
+        mesh.tex_coords
+            .push(Vec2::new(0.5 + offset.x * 0.5, 0.5 + dot(offset, down) * 0.5));
+    }
+    for i in 0..segments {
+        let (a, b) = (center + 1 + i, center + 1 + (i + 1) % segments);
+        mesh.indices
+            .extend_from_slice(&if up { [center, a, b] } else { [center, b, a] });
+    }
+}
+
+fn finish(mut mesh: MeshData) -> MeshData {
+    mesh.sections
+        .push(MeshDataSection { num_triangles: mesh.indices.len() / 3, ..Default::default() });
+    mesh.update_aabb();
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn primitives() -> Vec<(&'static str, MeshData)> {
+        vec![
+            ("uv_sphere", uv_sphere(2.0, 16, 8)),
+            ("icosphere", icosphere(2.0, 2)),
+            ("cuboid", cuboid(Vec3::new(1.0, 2.0, 3.0))),
+            ("cylinder", cylinder(1.0, 2.0, 12)),
+            ("cone", cone(1.0, 2.0, 12)),
+            ("torus", torus(2.0, 0.5, 16, 8)),
+            ("plane", plane(Vec2::new(2.0, 2.0), 4, 3)),
+        ]
+    }
+
+    #[test]
+    fn attributes_are_complete() {
+        for (name, mesh) in primitives() {
+            let count: usize = mesh.positions.len();
+            assert!(count > 0, "{}", name);
+            assert_eq!(mesh.normals.len(), count, "{}", name);
+            assert_eq!(mesh.tangents.len(), count, "{}", name);
+            assert_eq!(mesh.tex_coords.len(), count, "{}", name);
+            assert!(mesh.indices.iter().all(|&index| (index as usize) < count), "{}", name);
+            assert_eq!(mesh.sections.len(), 1);
+            assert_eq!(mesh.sections[0].num_triangles, mesh.num_triangles());
+            for i in 0..count {
+                assert!((mesh.normals[i].length() - 1.0).abs() < 1e-4, "{}", name);
+                assert!((mesh.tangents[i].length() - 1.0).abs() < 1e-4, "{}", name);
+                assert!(dot(mesh.normals[i], mesh.tangents[i]).abs() < 1e-4, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn triangles_face_along_normals_and_tangents_follow_u() {
+        for (name, mesh) in primitives() {
+            for triangle in mesh.indices.chunks(3) {
+                let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| index as usize);
+                let (e1, e2) = (mesh.positions[b] - mesh.positions[a], mesh.positions[c] - mesh.positions[a]);
+                let face: Vec3 = cross(e1, e2);
+                assert!(face.length() > 1e-6, "{}: degenerate triangle {:?}", name, triangle);
+                let normal: Vec3 = mesh.normals[a] + mesh.normals[b] + mesh.normals[c];
+                assert!(dot(face, normal) > 0.0, "{}: triangle {:?} faces inwards", name, triangle);
+
+                // dP/dU, the same way the rasterizer derives it
+                let uv1: Vec2 = mesh.tex_coords[b] - mesh.tex_coords[a];
+                let uv2: Vec2 = mesh.tex_coords[c] - mesh.tex_coords[a];
+                let tangent: Vec3 = (e1 * uv2.y - e2 * uv1.y) * (1.0 / (uv1.x * uv2.y - uv1.y * uv2.x));
+                for index in [a, b, c] {
+                    assert!(dot(tangent, mesh.tangents[index]) > 0.0, "{}: triangle {:?}", name, triangle);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bounds() {
+        let near = |a: AABB, b: AABB| (a.min - b.min).length() < 1e-4 && (a.max - b.max).length() < 1e-4;
+        let cube = |half: f32| AABB::new(Vec3::new(-half, -half, -half), Vec3::new(half, half, half));
+        assert!(near(icosphere(2.0, 1).aabb, cube(2.0)));
+        assert!(near(uv_sphere(2.0, 4, 2).aabb, cube(2.0)));
+        assert!(near(cylinder(1.0, 2.0, 4).aabb, cube(1.0)));
+        assert!(near(
+            cuboid(Vec3::new(1.0, 2.0, 3.0)).aabb,
+            AABB::new(Vec3::new(-0.5, -1.0, -1.5), Vec3::new(0.5, 1.0, 1.5))
+        ));
+        assert!(near(torus(2.0, 0.5, 4, 4).aabb, AABB::new(Vec3::new(-2.5, -0.5, -2.5), Vec3::new(2.5, 0.5, 2.5))));
+    }
+
+    #[test]
+    fn seams_are_closed() {
+        for mesh in [uv_sphere(1.0, 7, 5), cylinder(1.0, 1.0, 7), cone(1.0, 1.0, 7), torus(2.0, 0.5, 7, 5)] {
+            for (position, uv) in mesh
+                .positions
+                .iter()
+                .zip(&mesh.tex_coords)
+                .filter(|(_, uv)| uv.x == 1.0)
+            {
+                let other: Option<usize> =
+                    (0..mesh.positions.len()).find(|&i| mesh.tex_coords[i] == Vec2::new(0.0, uv.y));
+                assert_eq!(other.map(|i| mesh.positions[i]), Some(*position));
+            }
+        }
+        let mesh: MeshData = torus(2.0, 0.5, 7, 5);
+        for (position, uv) in mesh
+            .positions
+            .iter()
+            .zip(&mesh.tex_coords)
+            .filter(|(_, uv)| uv.y == 1.0)
+        {
+            let other: Option<usize> = (0..mesh.positions.len()).find(|&i| mesh.tex_coords[i] == Vec2::new(uv.x, 0.0));
+            assert_eq!(other.map(|i| mesh.positions[i]), Some(*position));
+        }
+    }
+
+    #[test]
+    fn icosphere_subdivisions() {
+        assert_eq!(icosphere(1.0, 0).num_triangles(), 20);
+        let mesh: MeshData = icosphere(1.0, 3);
+        assert_eq!(mesh.num_triangles(), 20 * 64);
+        assert!(mesh.positions.iter().all(|p| (p.length() - 1.0).abs() < 1e-5));
+        // The seam continues past U = 1 rather than wrapping around
+        assert!(mesh.tex_coords.iter().all(|uv| uv.x >= 0.0 && uv.x < 1.5));
+    }
+}
@@ -0,0 +1,440 @@
+use crate::math::*;
+use crate::render::{MeshData, MeshDataSection};
+use std::collections::HashMap;
+
+/// Reduces the mesh to about `target_triangles` by collapsing its edges in the order of the smallest quadric error,
+/// i.e. the collapses which move the surface the least go first. The vertices are collapsed into their neighbours,
+/// so the result keeps a subset of the original vertices with their attributes and is always indexed.
+///
+/// The vertices along the texture and normal seams, i.e. the positions shared by the vertices with different
+/// attributes, along the sections' boundaries and at non-manifold edges are kept in place, the open borders are only
+/// shortened along themselves. So the result can have more triangles than requested when little else is left.
+/// The identical vertices are merged beforehand, so the meshes loaded without an index buffer are simplified as well.
+pub fn simplify(mesh: &MeshData, target_triangles: usize) -> MeshData {
+    if mesh.num_triangles() <= target_triangles {
+        return mesh.clone();
+    }
+    let mut simplifier = Simplifier::new(mesh);
+    while simplifier.live_triangles > target_triangles && simplifier.collapse_pass(target_triangles) > 0 {}
+    simplifier.build(mesh)
+}
+
+/// The borders get a plane perpendicular to their faces, weighted this many times heavier than the faces' planes, so
+/// that their silhouettes are preserved.
+const BORDER_WEIGHT: f64 = 10.0;
+
+/// The error of a point as the sum of the squared distances to a set of planes, stored as a symmetric 4x4 matrix.
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn from_plane(normal: Vec3, point: Vec3, weight: f64) -> Quadric {
+        let (a, b, c) = (normal.x as f64, normal.y as f64, normal.z as f64);
+        let d: f64 = -dot(normal, point) as f64;
+        Quadric([a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d].map(|value| value * weight))
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (value, other) in self.0.iter_mut().zip(other.0.iter()) {
+            *value += other;
+        }
+    }
+
+    fn error(&self, point: Vec3) -> f64 {
+        let (x, y, z) = (point.x as f64, point.y as f64, point.z as f64);
+        let q: &[f64; 10] = &self.0;
+        (q[0] * x * x + q[4] * y * y + q[7] * z * z + q[9])
+            + 2.0 * (q[1] * x * y + q[2] * x * z + q[3] * x + q[5] * y * z + q[6] * y + q[8] * z)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum VertexKind {
+    Manifold,
+    Border,
+    Locked,
+}
+
+struct Simplifier {
+    /// The corners of the triangles, indices of the merged vertices.
+    triangles: Vec<[u32; 3]>,
+    sections: Vec<u32>,
+    alive: Vec<bool>,
+    live_triangles: usize,
+    /// The vertex representing the position of each vertex, i.e. the first one with the same position.
+    position_of: Vec<u32>,
+    /// Indexed by the position vertices.
+    quadrics: Vec<Quadric>,
+    positions: Vec<Vec3>,
+}
+
+impl Simplifier {
+    fn new(mesh: &MeshData) -> Simplifier {
+        let bits = |v: &[f32]| -> Vec<u32> { v.iter().map(|value| value.to_bits()).collect() };
+        let mut merged: HashMap<Vec<u32>, u32> = HashMap::new();
+        let mut by_position: HashMap<[u32; 3], u32> = HashMap::new();
+        let mut remap: Vec<u32> = Vec::with_capacity(mesh.positions.len());
+        let mut position_of: Vec<u32> = Vec::with_capacity(mesh.positions.len());
+        for (index, p) in mesh.positions.iter().enumerate() {
+            let mut key: Vec<u32> = bits(&[p.x, p.y, p.z]);
+            if let Some(n) = mesh.normals.get(index) {
+                key.extend(bits(&[n.x, n.y, n.z]));
+            }
+            if let Some(uv) = mesh.tex_coords.get(index) {
+                key.extend(bits(&[uv.x, uv.y]));
+            }
+            if let Some(c) = mesh.colors.get(index) {
+                key.extend(bits(&[c.x, c.y, c.z, c.w]));
+            }
+            if let Some(t) = mesh.tangents.get(index) {
+                key.extend(bits(&[t.x, t.y, t.z]));
+            }
+            remap.push(*merged.entry(key).or_insert(index as u32));
+            position_of.push(
+                *by_position
+                    .entry([p.x, p.y, p.z].map(f32::to_bits))
+                    .or_insert(index as u32),
+            );
+        }
+
+        let index_at = |i: usize| -> u32 { if mesh.is_indexed() { mesh.indices[i] } else { i as u32 } };
+        let default_section = [MeshDataSection { num_triangles: mesh.num_triangles(), ..Default::default() }];
+        let sections: &[MeshDataSection] = if mesh.sections.is_empty() {
+            &default_section
+        } else {
+            &mesh.sections
+        };
+        let mut simplifier = Simplifier {
+            triangles: Vec::with_capacity(mesh.num_triangles()),
+            sections: Vec::with_capacity(mesh.num_triangles()),
+            alive: Vec::new(),
+            live_triangles: 0,
+            position_of,
+            quadrics: vec![Quadric::default(); mesh.positions.len()],
+            positions: mesh.positions.clone(),
+        };
+        for (section_index, section) in sections.iter().enumerate() {
+            for start in section.index_range().step_by(3) {
+                let triangle: [u32; 3] = [0, 1, 2].map(|i| remap[index_at(start + i) as usize]);
+                let [a, b, c] = triangle.map(|vertex| simplifier.position_of[vertex as usize]);
+                if a != b && b != c && c != a {
+                    simplifier.triangles.push(triangle);
+                    simplifier.sections.push(section_index as u32);
+                }
+            }
+        }
+        simplifier.alive = vec![true; simplifier.triangles.len()];
+        simplifier.live_triangles = simplifier.triangles.len();
+
+        // The faces' planes weighted by their areas
+        for triangle in &simplifier.triangles {
+            let [a, b, c] = triangle.map(|vertex| simplifier.position_of[vertex as usize]);
+            let normal: Vec3 = simplifier.face_normal([a, b, c]);
+            let quadric =
+                Quadric::from_plane(normal.normalized(), simplifier.positions[a as usize], normal.length() as f64);
+            for vertex in [a, b, c] {
+                simplifier.quadrics[vertex as usize].add(&quadric);
+            }
+        }
+        let edges: HashMap<(u32, u32), Edge> = simplifier.edges();
+        for (&(a, b), edge) in edges.iter().filter(|(_, edge)| edge.triangles == 1) {
+            let (pa, pb) = (simplifier.positions[a as usize], simplifier.positions[b as usize]);
+            let normal: Vec3 = simplifier.face_normal(simplifier.positions_of(edge.triangle));
+            let side: Vec3 = cross(pb - pa, normal);
+            if dot(side, side) > 0.0 {
+                let weight: f64 = dot(pb - pa, pb - pa) as f64 * BORDER_WEIGHT;
+                let quadric = Quadric::from_plane(side.normalized(), pa, weight);
+                simplifier.quadrics[a as usize].add(&quadric);
+                simplifier.quadrics[b as usize].add(&quadric);
+            }
+        }
+        simplifier
+    }
+
+    fn positions_of(&self, triangle: usize) -> [u32; 3] {
+        self.triangles[triangle].map(|vertex| self.position_of[vertex as usize])
+    }
+
+    fn face_normal(&self, [a, b, c]: [u32; 3]) -> Vec3 {
+        let (pa, pb, pc) = (self.positions[a as usize], self.positions[b as usize], self.positions[c as usize]);
+        cross(pb - pa, pc - pa)
+    }
+
+    /// The live triangles' edges between the positions, keyed by the ordered pairs of the positions.
+    fn edges(&self) -> HashMap<(u32, u32), Edge> {
+        let mut edges: HashMap<(u32, u32), Edge> = HashMap::new();
+        for triangle in (0..self.triangles.len()).filter(|&t| self.alive[t]) {
+            let [a, b, c] = self.positions_of(triangle);
+            for (from, to) in [(a, b), (b, c), (c, a)] {
+                let edge = edges
+                    .entry((from.min(to), from.max(to)))
+                    .or_insert(Edge { triangles: 0, triangle });
+                edge.triangles += 1;
+            }
+        }
+        edges
+    }
+
+    /// Collapses the cheapest edges, each vertex is touched by at most one collapse, so that the costs computed at the
+    /// start of the pass stay valid. Returns the number of collapses.
+    fn collapse_pass(&mut self, target_triangles: usize) -> usize {
+        let vertices_num: usize = self.positions.len();
+        let mut adjacency: Vec<Vec<usize>> = vec![Vec::new(); vertices_num];
+        for triangle in (0..self.triangles.len()).filter(|&t| self.alive[t]) {
+            for vertex in self.positions_of(triangle) {
+                adjacency[vertex as usize].push(triangle);
+            }
+        }
+        let edges: HashMap<(u32, u32), Edge> = self.edges();
+
+        // The seams, the sections' boundaries and the non-manifold edges are locked, as well as the positions unused now
+        let mut kinds: Vec<VertexKind> = vec![VertexKind::Manifold; vertices_num];
+        for (vertex, triangles) in adjacency.iter().enumerate() {
+            let Some(&first) = triangles.first() else {
+                kinds[vertex] = VertexKind::Locked;
+                continue;
+            };
+            let (corner, section) = (self.corner(first, vertex as u32), self.sections[first]);
+            if triangles
+                .iter()
+                .any(|&t| self.corner(t, vertex as u32) != corner || self.sections[t] != section)
+            {
+                kinds[vertex] = VertexKind::Locked;
+            }
+        }
+        for (&(a, b), edge) in &edges {
+            for vertex in [a as usize, b as usize] {
+                kinds[vertex] = match (edge.triangles, kinds[vertex]) {
+                    (1, VertexKind::Manifold) => VertexKind::Border,
+                    (1 | 2, kind) => kind,
+                    _ => VertexKind::Locked,
+                };
+            }
+        }
+
+        // The cheaper direction of every edge allowed to collapse
+        let mut candidates: Vec<(f64, u32, u32)> = Vec::new();
+        for (&(a, b), edge) in &edges {
+            let allowed = |from: u32| match kinds[from as usize] {
+                VertexKind::Manifold => true,
+                VertexKind::Border => edge.triangles == 1,
+                VertexKind::Locked => false,
+            };
+            let cost = |from: u32, to: u32| -> f64 {
+                let mut quadric: Quadric = self.quadrics[from as usize];
+                quadric.add(&self.quadrics[to as usize]);
+                quadric.error(self.positions[to as usize])
+            };
+            let best: Option<(f64, u32, u32)> = [(a, b), (b, a)]
+                .into_iter()
+                .filter(|&(from, _)| allowed(from))
+                .map(|(from, to)| (cost(from, to), from, to))
+                .min_by(|x, y| x.0.total_cmp(&y.0));
+            candidates.extend(best);
+        }
+        candidates.sort_by(|x, y| x.0.total_cmp(&y.0).then((x.1, x.2).cmp(&(y.1, y.2))));
+
+        let mut touched: Vec<bool> = vec![false; vertices_num];
+        let mut collapses: usize = 0;
+        for (_, from, to) in candidates {
+            if self.live_triangles <= target_triangles {
+                break;
+            }
+            if touched[from as usize] || touched[to as usize] || !self.can_collapse(&adjacency, from, to, &edges) {
+                continue;
+            }
+            // All the triangles around the vertex share its corner, the ones along the edge tell the corner to use instead
+            let from_triangles: &[usize] = &adjacency[from as usize];
+            let from_corner: u32 = self.corner(from_triangles[0], from);
+            let shared: usize = *from_triangles
+                .iter()
+                .find(|&&t| self.positions_of(t).contains(&to))
+                .unwrap();
+            let to_corner: u32 = self.corner(shared, to);
+            for &triangle in from_triangles {
+                let positions: [u32; 3] = self.positions_of(triangle);
+                for vertex in positions {
+                    touched[vertex as usize] = true;
+                }
+                if positions.contains(&to) {
+                    self.alive[triangle] = false;
+                    self.live_triangles -= 1;
+                } else {
+                    for corner in self.triangles[triangle]
+                        .iter_mut()
+                        .filter(|corner| **corner == from_corner)
+                    {
+                        *corner = to_corner;
+                    }
+                }
+            }
+            let quadric: Quadric = self.quadrics[from as usize];
+            self.quadrics[to as usize].add(&quadric);
+            collapses += 1;
+        }
+        collapses
+    }
+
+    /// The merged vertex at the position in the triangle.
+    fn corner(&self, triangle: usize, position: u32) -> u32 {
+        *self.triangles[triangle]
+            .iter()
+            .find(|&&vertex| self.position_of[vertex as usize] == position)
+            .unwrap()
+    }
+
+    /// Checks that collapsing the edge doesn't pinch the surface or turn any of the triangles around.
+    fn can_collapse(&self, adjacency: &[Vec<usize>], from: u32, to: u32, edges: &HashMap<(u32, u32), Edge>) -> bool {
+        let neighbours = |vertex: u32| -> Vec<u32> {
+            let mut neighbours: Vec<u32> = adjacency[vertex as usize]
+                .iter()
+                .flat_map(|&t| self.positions_of(t))
+                .filter(|&other| other != vertex)
+                .collect();
+            neighbours.sort_unstable();
+            neighbours.dedup();
+            neighbours
+        };
+        let to_neighbours: Vec<u32> = neighbours(to);
+        let common: usize = neighbours(from)
+            .iter()
+            .filter(|vertex| to_neighbours.binary_search(vertex).is_ok())
+            .count();
+        if common != edges[&(from.min(to), from.max(to))].triangles {
+            return false;
+        }
+        adjacency[from as usize].iter().all(|&triangle| {
+            let positions: [u32; 3] = self.positions_of(triangle);
+            if positions.contains(&to) {
+                return true;
+            }
+            let moved: [u32; 3] = positions.map(|vertex| if vertex == from { to } else { vertex });
+            dot(self.face_normal(positions), self.face_normal(moved)) > 0.0
+        })
+    }
+
+    /// The mesh of the live triangles with the vertices they use.
+    fn build(&self, mesh: &MeshData) -> MeshData {
+        let mut remap: Vec<u32> = vec![u32::MAX; mesh.positions.len()];
+        let mut vertices: Vec<usize> = Vec::new();
+        let mut result = MeshData::default();
+        let sections_num: usize = mesh.sections.len().max(1);
+        for section_index in 0..sections_num {
+            let start_index: usize = result.indices.len();
+            for triangle in
+                (0..self.triangles.len()).filter(|&t| self.alive[t] && self.sections[t] == section_index as u32)
+            {
+                for vertex in self.triangles[triangle] {
+                    if remap[vertex as usize] == u32::MAX {
+                        remap[vertex as usize] = vertices.len() as u32;
+                        vertices.push(vertex as usize);
+                    }
+                    result.indices.push(remap[vertex as usize]);
+                }
+            }
+            if let Some(section) = mesh.sections.get(section_index) {
+                result.sections.push(MeshDataSection {
+                    start_index,
+                    num_triangles: (result.indices.len() - start_index) / 3,
+                    ..section.clone()
+                });
+            }
+        }
+        fn gather<T: Copy>(attribute: &[T], vertices: &[usize]) -> Vec<T> {
+            if attribute.is_empty() {
+                Vec::new()
+            } else {
+                vertices.iter().map(|&vertex| attribute[vertex]).collect()
+            }
+        }
+        result.positions = gather(&mesh.positions, &vertices);
+        result.normals = gather(&mesh.normals, &vertices);
+        result.tex_coords = gather(&mesh.tex_coords, &vertices);
+        result.colors = gather(&mesh.colors, &vertices);
+        result.tangents = gather(&mesh.tangents, &vertices);
+        result.update_aabb();
+        result
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    /// The number of the live triangles sharing the edge.
+    triangles: usize,
+    /// Any of them.
+    triangle: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::*;
+
+    #[test]
+    fn plane_is_simplified_to_its_corners() {
+        let mesh: MeshData = plane(Vec2::new(2.0, 2.0), 8, 8);
+        let simplified: MeshData = simplify(&mesh, 2);
+        assert_eq!(simplified.num_triangles(), 2);
+        assert_eq!(simplified.positions.len(), 4);
+        assert_eq!(simplified.aabb, mesh.aabb);
+        assert_eq!(simplified.sections[0].num_triangles, 2);
+        // Still facing up
+        let [a, b, c] = [0, 1, 2].map(|i| simplified.positions[simplified.indices[i] as usize]);
+        assert!(cross(b - a, c - a).y > 0.0);
+    }
+
+    #[test]
+    fn sphere_keeps_its_shape() {
+        let mesh: MeshData = icosphere(1.0, 4);
+        let simplified: MeshData = simplify(&mesh, 500);
+        assert!(simplified.num_triangles() <= 500 && simplified.num_triangles() > 400);
+        assert!(simplified.positions.iter().all(|p| (p.length() - 1.0).abs() < 1e-5));
+        // The volume of a closed mesh as the sum of the tetrahedra from the origin, the triangles face outwards
+        let volume: f32 = simplified
+            .indices
+            .chunks(3)
+            .map(|t| {
+                let [a, b, c] = [t[0], t[1], t[2]].map(|i| simplified.positions[i as usize]);
+                dot(a, cross(b, c)) / 6.0
+            })
+            .sum();
+        assert!(volume > 3.8 && volume < 4.0 * std::f32::consts::PI / 3.0);
+    }
+
+    #[test]
+    fn unindexed_mesh_is_merged_and_seams_are_kept() {
+        let mesh: MeshData = uv_sphere(1.0, 32, 16);
+        let unindexed = MeshData {
+            positions: mesh.indices.iter().map(|&i| mesh.positions[i as usize]).collect(),
+            tex_coords: mesh.indices.iter().map(|&i| mesh.tex_coords[i as usize]).collect(),
+            ..Default::default()
+        };
+        let simplified: MeshData = simplify(&unindexed, 200);
+        assert!(simplified.num_triangles() <= 200);
+        assert!(simplified.positions.len() < 200);
+        assert!(simplified.normals.is_empty() && simplified.tangents.is_empty());
+        // The vertices of the seam at U = 0 and U = 1 remain
+        let seam = |u: f32| simplified.tex_coords.iter().filter(|uv| uv.x == u).count();
+        assert_eq!(seam(0.0), 15);
+        assert_eq!(seam(1.0), 15);
+    }
+
+    #[test]
+    fn sections_are_kept_apart() {
+        let mut mesh: MeshData = plane(Vec2::new(2.0, 2.0), 8, 8);
+        mesh.sections = vec![
+            MeshDataSection { name: "near".to_string(), start_index: 0, num_triangles: 64, material_index: 0 },
+            MeshDataSection { name: "far".to_string(), start_index: 192, num_triangles: 64, material_index: 1 },
+        ];
+        let simplified: MeshData = simplify(&mesh, 4);
+        assert_eq!(simplified.sections.len(), 2);
+        assert_eq!(simplified.sections[1].name, "far");
+        assert_eq!(simplified.sections[1].start_index, simplified.sections[0].num_triangles * 3);
+        assert_eq!(
+            simplified.num_triangles(),
+            simplified.sections[0].num_triangles + simplified.sections[1].num_triangles
+        );
+        // The row of vertices between the sections stays straight
+        assert!(simplified.positions.iter().filter(|p| p.z == 0.0).count() >= 9);
+    }
+}
@@ -1,3 +1,7 @@
+pub mod compute;
+pub mod geometry;
 pub mod math;
+pub mod raytrace;
 pub mod render;
+pub mod testing;
 pub mod util;
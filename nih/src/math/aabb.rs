@@ -30,6 +30,23 @@ impl AABB {
 
         Self { min, max }
     }
+
+    // The smallest box containing both boxes.
+    pub fn union(&self, other: &AABB) -> AABB {
+        AABB {
+            min: Vec3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            max: Vec3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d: Vec3 = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
 }
 
 impl Default for AABB {
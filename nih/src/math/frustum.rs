@@ -0,0 +1,97 @@
+use crate::math::*;
+
+// The six planes bounding the volume visible through a projection, extracted from a (model-)view-projection matrix.
+// Each plane is (a, b, c, d) with the normal (a, b, c) pointing inside, i.e. a point p is on the inner side when
+// a*p.x + b*p.y + c*p.z + d >= 0. The normals are normalized, so that the value is the distance to the plane.
+// The planes are in the space the matrix transforms from: the world space for a view-projection matrix, the model
+// space for a model-view-projection one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    pub const LEFT: usize = 0;
+    pub const RIGHT: usize = 1;
+    pub const BOTTOM: usize = 2;
+    pub const TOP: usize = 3;
+    pub const NEAR: usize = 4;
+    pub const FAR: usize = 5;
+
+    pub fn from_matrix(m: &Mat44) -> Self {
+        let row = |r: usize| Vec4::new(m.0[r * 4], m.0[r * 4 + 1], m.0[r * 4 + 2], m.0[r * 4 + 3]);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        // -w <= x, y, z <= w in the clip space
+        let planes: [Vec4; 6] = [w + x, w - x, w + y, w - y, w + z, w - z].map(|plane| {
+            let length: f32 = plane.xyz().length();
+            if length > 0.0 { plane / length } else { plane }
+        });
+        Self { planes }
+    }
+
+    // The signed distance from the plane to the point, positive on the inner side.
+    pub fn distance(&self, plane: usize, point: Vec3) -> f32 {
+        let p: Vec4 = self.planes[plane];
+        p.x * point.x + p.y * point.y + p.z * point.z + p.w
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        (0..6).all(|plane| self.distance(plane, point) >= 0.0)
+    }
+
+    // Checks whether the sphere is entirely outside one of the planes.
+    pub fn is_sphere_outside(&self, center: Vec3, radius: f32) -> bool {
+        (0..6).any(|plane| self.distance(plane, center) < -radius)
+    }
+
+    // Checks whether the box is entirely outside one of the planes. With skip_far the far plane is ignored, e.g. for the
+    // depth clamp.
+    // Conservative: a box outside the frustum but not entirely behind a single plane, e.g. near its corner, is kept.
+    pub fn is_aabb_outside(&self, aabb: &AABB, skip_far: bool) -> bool {
+        let planes: usize = if skip_far { 5 } else { 6 };
+        (0..planes).any(|plane| {
+            // The corner furthest along the plane's normal
+            let p: Vec4 = self.planes[plane];
+            let corner: Vec3 = Vec3::new(
+                if p.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if p.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if p.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+            self.distance(plane, corner) < 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn planes_of_perspective_projection() {
+        let projection = Mat44::perspective(1.0, 10.0, std::f32::consts::FRAC_PI_2, 1.0);
+        let frustum = Frustum::from_matrix(&projection);
+        assert!((frustum.distance(Frustum::NEAR, Vec3::new(0.0, 0.0, -3.0)) - 2.0).abs() < 1e-4);
+        assert!((frustum.distance(Frustum::FAR, Vec3::new(0.0, 0.0, -3.0)) - 7.0).abs() < 1e-4);
+        // With 90° the side planes go diagonally through the origin
+        assert!(frustum.distance(Frustum::LEFT, Vec3::new(-1.0, 0.0, -1.0)).abs() < 1e-4);
+        assert!(frustum.distance(Frustum::TOP, Vec3::new(0.0, 1.0, -1.0)).abs() < 1e-4);
+        assert!(frustum.contains_point(Vec3::new(0.5, -0.5, -5.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -0.5)));
+        assert!(!frustum.contains_point(Vec3::new(6.0, 0.0, -5.0)));
+    }
+
+    #[test]
+    fn culling_boxes_and_spheres() {
+        let projection = Mat44::perspective(1.0, 10.0, std::f32::consts::FRAC_PI_2, 1.0);
+        let frustum = Frustum::from_matrix(&projection);
+        let unit = |center: Vec3| AABB::new(center - Vec3::new(0.5, 0.5, 0.5), center + Vec3::new(0.5, 0.5, 0.5));
+        assert!(!frustum.is_aabb_outside(&unit(Vec3::new(0.0, 0.0, -5.0)), false));
+        assert!(!frustum.is_aabb_outside(&unit(Vec3::new(5.2, 0.0, -5.0)), false));
+        assert!(frustum.is_aabb_outside(&unit(Vec3::new(6.5, 0.0, -5.0)), false));
+        assert!(frustum.is_aabb_outside(&unit(Vec3::new(0.0, 0.0, 2.0)), false));
+        assert!(frustum.is_aabb_outside(&unit(Vec3::new(0.0, 0.0, -12.0)), false));
+        assert!(!frustum.is_aabb_outside(&unit(Vec3::new(0.0, 0.0, -12.0)), true));
+        assert!(!frustum.is_sphere_outside(Vec3::new(0.0, 0.0, -0.5), 1.0));
+        assert!(frustum.is_sphere_outside(Vec3::new(0.0, 0.0, 1.0), 1.0));
+    }
+}
@@ -1,6 +1,7 @@
 use crate::math::*;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Mat34(pub [f32; 12]);
 
 impl Mat34 {
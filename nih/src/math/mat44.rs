@@ -1,6 +1,7 @@
 use crate::math::*;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Mat44(pub [f32; 16]);
 
 impl Mat44 {
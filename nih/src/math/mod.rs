@@ -1,11 +1,13 @@
 pub mod aabb;
 pub mod dot;
+pub mod frustum;
 pub mod geom;
 pub mod mat22;
 pub mod mat33;
 pub mod mat34;
 pub mod mat44;
 pub mod quat;
+pub mod ray;
 pub mod simd;
 pub mod vec2;
 pub mod vec3;
@@ -13,12 +15,14 @@ pub mod vec4;
 
 pub use aabb::*;
 pub use dot::*;
+pub use frustum::*;
 pub use geom::*;
 pub use mat22::*;
 pub use mat33::*;
 pub use mat34::*;
 pub use mat44::*;
 pub use quat::*;
+pub use ray::*;
 pub use vec2::*;
 pub use vec3::*;
 pub use vec4::*;
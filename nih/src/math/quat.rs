@@ -68,6 +68,32 @@ impl Quat {
         }
     }
 
+    // Spherical interpolation along the shorter arc, t of 0 gives self and 1 gives other.
+    pub fn slerp(self, other: Quat, t: f32) -> Quat {
+        let mut cos = self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w;
+        let other = if cos < 0.0 {
+            cos = -cos;
+            Quat { x: -other.x, y: -other.y, z: -other.z, w: -other.w }
+        } else {
+            other
+        };
+        // Nearly parallel quaternions are interpolated linearly to avoid dividing by a vanishing sine
+        let (a, b) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let angle = cos.acos();
+            let sin = angle.sin();
+            (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+        };
+        Quat {
+            x: self.x * a + other.x * b,
+            y: self.y * a + other.y * b,
+            z: self.z * a + other.z * b,
+            w: self.w * a + other.w * b,
+        }
+        .normalized()
+    }
+
     pub fn as_mat33(self) -> Mat33 {
         Mat33([
             1.0 - 2.0 * self.y * self.y - 2.0 * self.z * self.z,
@@ -551,4 +577,20 @@ mod tests {
             Vec3Approx(Vec3 { x: 1.0, y: 1.0, z: -1.0 })
         );
     }
+
+    #[test]
+    fn test_slerp() {
+        let a = Quat::from_axis_angle(Vec3 { x: 0.0, y: 0.0, z: 1.0 }, 0.0);
+        let b = Quat::from_axis_angle(Vec3 { x: 0.0, y: 0.0, z: 1.0 }, PI_2);
+        assert_eq!(a.slerp(b, 0.0), QuatApprox(a));
+        assert_eq!(a.slerp(b, 1.0), QuatApprox(b));
+        assert_eq!(a.slerp(b, 0.5), QuatApprox(Quat::from_axis_angle(Vec3 { x: 0.0, y: 0.0, z: 1.0 }, PI_4)));
+
+        // The negated quaternion is the same rotation, the interpolation takes the shorter arc anyway
+        let negated = Quat { x: -b.x, y: -b.y, z: -b.z, w: -b.w };
+        assert_eq!(
+            a.slerp(negated, 0.5) * Vec3 { x: 1.0, y: 0.0, z: 0.0 },
+            Vec3Approx(Vec3 { x: 0.7071067811865475, y: 0.7071067811865475, z: 0.0 })
+        );
+    }
 }
@@ -0,0 +1,140 @@
+use crate::math::*;
+
+// A half-line starting at the origin, the points along it are origin + direction * t for t >= 0.
+// The direction doesn't have to be normalized, the distances t are then measured in its lengths, which keeps them valid
+// for a ray transformed by a scaling matrix.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+// Where a ray hits a triangle: the distance along the ray and the barycentric coordinates of the hit point, which is
+// a * (1 - u - v) + b * u + c * v.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TriangleHit {
+    pub t: f32,
+    pub u: f32,
+    pub v: f32,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self { origin, direction }
+    }
+
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+
+    // The ray in another space, e.g. in the model space of a mesh via the inverse of its model matrix.
+    // The direction isn't renormalized, so the distances along both rays are the same.
+    pub fn transformed(&self, m: &Mat34) -> Ray {
+        Ray { origin: m * self.origin, direction: m.as_mat33() * self.direction }
+    }
+
+    // The distances along the ray where it enters and exits the box, None if it misses the box or the box is behind it.
+    // The entry is 0 if the origin is inside the box.
+    pub fn intersect_aabb(&self, aabb: &AABB) -> Option<(f32, f32)> {
+        let mut near: f32 = 0.0;
+        let mut far: f32 = f32::INFINITY;
+        for (origin, direction, min, max) in [
+            (self.origin.x, self.direction.x, aabb.min.x, aabb.max.x),
+            (self.origin.y, self.direction.y, aabb.min.y, aabb.max.y),
+            (self.origin.z, self.direction.z, aabb.min.z, aabb.max.z),
+        ] {
+            if direction == 0.0 {
+                // Parallel to the slab, either always within it or never
+                if origin < min || origin > max {
+                    return None;
+                }
+                continue;
+            }
+            let inverse: f32 = 1.0 / direction;
+            let (t0, t1) = ((min - origin) * inverse, (max - origin) * inverse);
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+            if near > far {
+                return None;
+            }
+        }
+        Some((near, far))
+    }
+
+    // The hit of the triangle in front of the ray, both sides of the triangle are hit. Möller–Trumbore.
+    pub fn intersect_triangle(&self, a: Vec3, b: Vec3, c: Vec3) -> Option<TriangleHit> {
+        let ab: Vec3 = b - a;
+        let ac: Vec3 = c - a;
+        let p: Vec3 = cross(self.direction, ac);
+        let determinant: f32 = ab.dot(p);
+        if determinant.abs() < f32::EPSILON * ab.length() * ac.length() * self.direction.length() {
+            return None; // Parallel to the triangle's plane or a degenerate triangle
+        }
+        let inverse: f32 = 1.0 / determinant;
+        let ao: Vec3 = self.origin - a;
+        let u: f32 = ao.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q: Vec3 = cross(ao, ab);
+        let v: f32 = self.direction.dot(q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t: f32 = ac.dot(q) * inverse;
+        if t < 0.0 {
+            return None;
+        }
+        Some(TriangleHit { t, u, v })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ray_hits_box() {
+        let aabb = AABB::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(ray.intersect_aabb(&aabb), Some((4.0, 6.0)));
+        let inside = Ray::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(inside.intersect_aabb(&aabb), Some((0.0, 0.5)));
+        let behind = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(behind.intersect_aabb(&aabb), None);
+        let beside = Ray::new(Vec3::new(2.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(beside.intersect_aabb(&aabb), None);
+        let diagonal = Ray::new(Vec3::new(-3.0, 0.0, 3.0), Vec3::new(1.0, 0.0, -1.0));
+        assert_eq!(diagonal.intersect_aabb(&aabb), Some((2.0, 4.0)));
+    }
+
+    #[test]
+    fn ray_hits_triangle() {
+        let (a, b, c) = (Vec3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0));
+        let ray = Ray::new(Vec3::new(0.5, 1.0, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit: TriangleHit = ray.intersect_triangle(a, b, c).unwrap();
+        assert_eq!(hit, TriangleHit { t: 3.0, u: 0.25, v: 0.5 });
+        assert_eq!(ray.at(hit.t), Vec3::new(0.5, 1.0, 0.0));
+
+        // The back side is hit too, the triangle behind the ray isn't
+        let back = Ray::new(Vec3::new(0.5, 1.0, -3.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(back.intersect_triangle(a, b, c).map(|hit| hit.t), Some(3.0));
+        let away = Ray::new(Vec3::new(0.5, 1.0, 3.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(away.intersect_triangle(a, b, c), None);
+        let outside = Ray::new(Vec3::new(1.5, 1.5, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(outside.intersect_triangle(a, b, c), None);
+        let parallel = Ray::new(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(parallel.intersect_triangle(a, b, c), None);
+    }
+
+    #[test]
+    fn transformed_ray_keeps_distances() {
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0));
+        let model: Mat34 = Mat34::translate(Vec3::new(0.0, 0.0, 2.0)) * Mat34::scale_uniform(2.0);
+        let local: Ray = ray.transformed(&model.as_mat44().inverse().as_mat34());
+        let aabb = AABB::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        // The box scaled twice spans z in [0, 4] in the world
+        let (near, far) = local.intersect_aabb(&aabb).unwrap();
+        assert!((near - 6.0).abs() < 1e-5 && (far - 10.0).abs() < 1e-5);
+    }
+}
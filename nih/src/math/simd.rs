@@ -47,38 +47,98 @@ impl U32x4 {
         out
     }
 
-    /// Add two vectors
+    /// Construct with all lanes set to the same value
     #[inline(always)]
-    pub fn add(self, other: Self) -> Self {
+    pub fn splat(value: u32) -> Self {
         unsafe {
             #[cfg(target_arch = "x86_64")]
             {
                 use core::arch::x86_64::*;
-                Self { inner: _mm_add_epi32(self.inner, other.inner) }
+                Self { inner: _mm_set1_epi32(value as i32) }
             }
 
             #[cfg(target_arch = "aarch64")]
             {
                 use core::arch::aarch64::*;
-                Self { inner: vaddq_u32(self.inner, other.inner) }
+                Self { inner: vdupq_n_u32(value) }
             }
         }
     }
 
-    /// Bitwise AND
+    /// Store into 4 consecutive values at the pointer, which doesn't have to be aligned
+    ///
+    /// # Safety
+    /// The pointer must be valid for writing 4 consecutive u32 values.
     #[inline(always)]
-    pub fn bitand(self, other: Self) -> Self {
+    pub unsafe fn store_to_ptr(self, ptr: *mut u32) {
         unsafe {
             #[cfg(target_arch = "x86_64")]
             {
                 use core::arch::x86_64::*;
-                Self { inner: _mm_and_si128(self.inner, other.inner) }
+                _mm_storeu_si128(ptr as *mut __m128i, self.inner);
             }
 
             #[cfg(target_arch = "aarch64")]
             {
                 use core::arch::aarch64::*;
-                Self { inner: vandq_u32(self.inner, other.inner) }
+                vst1q_u32(ptr, self.inner);
+            }
+        }
+    }
+
+    /// Shift left each lane by a constant
+    #[inline(always)]
+    pub fn shl<const BITS: i32>(self) -> Self {
+        unsafe {
+            #[cfg(target_arch = "x86_64")]
+            {
+                use core::arch::x86_64::*;
+                Self { inner: _mm_slli_epi32::<BITS>(self.inner) }
+            }
+
+            #[cfg(target_arch = "aarch64")]
+            {
+                use core::arch::aarch64::*;
+                Self { inner: vshlq_n_u32::<BITS>(self.inner) }
+            }
+        }
+    }
+
+    /// Shift right each lane by a constant, treating the lanes as signed, i.e. replicating the sign bit
+    #[inline(always)]
+    pub fn shr_signed<const BITS: i32>(self) -> Self {
+        unsafe {
+            #[cfg(target_arch = "x86_64")]
+            {
+                use core::arch::x86_64::*;
+                Self { inner: _mm_srai_epi32::<BITS>(self.inner) }
+            }
+
+            #[cfg(target_arch = "aarch64")]
+            {
+                use core::arch::aarch64::*;
+                Self { inner: vreinterpretq_u32_s32(vshrq_n_s32::<BITS>(vreinterpretq_s32_u32(self.inner))) }
+            }
+        }
+    }
+
+    /// Clamp each lane between lo and hi, treating the lanes as signed
+    #[inline(always)]
+    pub fn clamp_signed(self, lo: Self, hi: Self) -> Self {
+        unsafe {
+            #[cfg(target_arch = "x86_64")]
+            {
+                use core::arch::x86_64::*;
+                Self { inner: _mm_min_epi32(_mm_max_epi32(self.inner, lo.inner), hi.inner) }
+            }
+
+            #[cfg(target_arch = "aarch64")]
+            {
+                use core::arch::aarch64::*;
+                let value: int32x4_t = vreinterpretq_s32_u32(self.inner);
+                let lo: int32x4_t = vreinterpretq_s32_u32(lo.inner);
+                let hi: int32x4_t = vreinterpretq_s32_u32(hi.inner);
+                Self { inner: vreinterpretq_u32_s32(vminq_s32(vmaxq_s32(value, lo), hi)) }
             }
         }
     }
@@ -316,6 +376,9 @@ impl F32x4 {
     /// Calculates x * a + b
     #[inline(always)]
     pub fn fma(self, a: Self, b: Self) -> Self {
+        if cfg!(feature = "strict-determinism") {
+            return self.mul(a).add(b);
+        }
         unsafe {
             #[cfg(target_arch = "x86_64")]
             {
@@ -352,6 +415,9 @@ impl F32x4 {
     /// Calculates a reciprocal square root approximation
     #[inline(always)]
     pub fn rsqrt(self) -> Self {
+        if cfg!(feature = "strict-determinism") {
+            return Self::splat(1.0).div(self.sqrt());
+        }
         unsafe {
             #[cfg(target_arch = "x86_64")]
             {
@@ -369,18 +435,41 @@ impl F32x4 {
         }
     }
 
+    /// Calculates a reciprocal approximation refined by a single Newton-Raphson step
+    #[inline(always)]
+    pub fn rcp(self) -> Self {
+        if cfg!(feature = "strict-determinism") {
+            return Self::splat(1.0).div(self);
+        }
+        unsafe {
+            #[cfg(target_arch = "x86_64")]
+            {
+                use core::arch::x86_64::*;
+                let estimate: __m128 = _mm_rcp_ps(self.inner);
+                let correction: __m128 = _mm_sub_ps(_mm_set1_ps(2.0), _mm_mul_ps(self.inner, estimate));
+                Self { inner: _mm_mul_ps(estimate, correction) }
+            }
+
+            #[cfg(target_arch = "aarch64")]
+            {
+                use core::arch::aarch64::*;
+                let estimate: float32x4_t = vrecpeq_f32(self.inner);
+                Self { inner: vmulq_f32(vrecpsq_f32(self.inner, estimate), estimate) }
+            }
+        }
+    }
+
     /// Calculates an exponent function
     #[inline(always)]
     pub fn exp(self) -> Self {
+        if cfg!(feature = "strict-determinism") {
+            return self.map_lanes(f32::exp);
+        }
+
         #[cfg(target_arch = "x86_64")]
         {
             // dummy for now
-            let mut v: [f32; 4] = self.store();
-            v[0] = v[0].exp();
-            v[1] = v[1].exp();
-            v[2] = v[2].exp();
-            v[3] = v[3].exp();
-            Self::load(v)
+            self.map_lanes(f32::exp)
         }
 
         #[cfg(target_arch = "aarch64")]
@@ -392,15 +481,14 @@ impl F32x4 {
     /// Calculates a natural logarithm function
     #[inline(always)]
     pub fn log(self) -> Self {
+        if cfg!(feature = "strict-determinism") {
+            return self.map_lanes(f32::ln);
+        }
+
         #[cfg(target_arch = "x86_64")]
         {
             // dummy for now
-            let mut v: [f32; 4] = self.store();
-            v[0] = v[0].ln();
-            v[1] = v[1].ln();
-            v[2] = v[2].ln();
-            v[3] = v[3].ln();
-            Self::load(v)
+            self.map_lanes(f32::ln)
         }
 
         #[cfg(target_arch = "aarch64")]
@@ -409,6 +497,13 @@ impl F32x4 {
         }
     }
 
+    // Applies a scalar function to every lane
+    #[inline(always)]
+    fn map_lanes(self, f: fn(f32) -> f32) -> Self {
+        let v: [f32; 4] = self.store();
+        Self::load([f(v[0]), f(v[1]), f(v[2]), f(v[3])])
+    }
+
     // Calculates arccosine of x: [-1,1]
     // https://developer.download.nvidia.com/cg/acos.html
     #[inline(always)]
@@ -513,6 +608,40 @@ impl F32x4 {
     }
 }
 
+/// Calculates a scalar reciprocal approximation refined by a single Newton-Raphson step, i.e. ~22 bits of precision
+#[inline(always)]
+pub fn fast_reciprocal(x: f32) -> f32 {
+    if cfg!(feature = "strict-determinism") {
+        return 1.0 / x;
+    }
+    unsafe {
+        #[cfg(target_arch = "x86_64")]
+        {
+            use core::arch::x86_64::*;
+            let value: __m128 = _mm_set_ss(x);
+            let estimate: __m128 = _mm_rcp_ss(value);
+            let correction: __m128 = _mm_sub_ss(_mm_set_ss(2.0), _mm_mul_ss(value, estimate));
+            _mm_cvtss_f32(_mm_mul_ss(estimate, correction))
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            use core::arch::aarch64::*;
+            let estimate: f32 = vrecpes_f32(x);
+            vrecpss_f32(x, estimate) * estimate
+        }
+    }
+}
+
+/// Calculates x * a + b, fused into a single rounding unless the "strict-determinism" feature is enabled
+#[inline(always)]
+pub fn mul_add(x: f32, a: f32, b: f32) -> f32 {
+    if cfg!(feature = "strict-determinism") {
+        x * a + b
+    } else {
+        x.mul_add(a, b)
+    }
+}
 
 // https://github.com/ARM-software/EndpointAI/blob/master/Kernels/Migrating_to_Helium_from_Neon_Companion_SW/vmath.c
 #[cfg(target_arch = "aarch64")]
@@ -634,4 +763,162 @@ impl std::ops::AddAssign for F32x4 {
     fn add_assign(&mut self, other: F32x4) {
         *self = self.add(other);
     }
-}
\ No newline at end of file
+}
+
+// U32x4 + U32x4
+impl std::ops::Add for U32x4 {
+    type Output = U32x4;
+    #[inline(always)]
+    fn add(self, other: U32x4) -> U32x4 {
+        unsafe {
+            #[cfg(target_arch = "x86_64")]
+            {
+                use core::arch::x86_64::*;
+                Self { inner: _mm_add_epi32(self.inner, other.inner) }
+            }
+
+            #[cfg(target_arch = "aarch64")]
+            {
+                use core::arch::aarch64::*;
+                Self { inner: vaddq_u32(self.inner, other.inner) }
+            }
+        }
+    }
+}
+
+// U32x4 += U32x4
+impl std::ops::AddAssign for U32x4 {
+    #[inline(always)]
+    fn add_assign(&mut self, other: U32x4) {
+        *self = *self + other;
+    }
+}
+
+// U32x4 - U32x4
+impl std::ops::Sub for U32x4 {
+    type Output = U32x4;
+    #[inline(always)]
+    fn sub(self, other: U32x4) -> U32x4 {
+        unsafe {
+            #[cfg(target_arch = "x86_64")]
+            {
+                use core::arch::x86_64::*;
+                Self { inner: _mm_sub_epi32(self.inner, other.inner) }
+            }
+
+            #[cfg(target_arch = "aarch64")]
+            {
+                use core::arch::aarch64::*;
+                Self { inner: vsubq_u32(self.inner, other.inner) }
+            }
+        }
+    }
+}
+
+// U32x4 & U32x4
+impl std::ops::BitAnd for U32x4 {
+    type Output = U32x4;
+    #[inline(always)]
+    fn bitand(self, other: U32x4) -> U32x4 {
+        unsafe {
+            #[cfg(target_arch = "x86_64")]
+            {
+                use core::arch::x86_64::*;
+                Self { inner: _mm_and_si128(self.inner, other.inner) }
+            }
+
+            #[cfg(target_arch = "aarch64")]
+            {
+                use core::arch::aarch64::*;
+                Self { inner: vandq_u32(self.inner, other.inner) }
+            }
+        }
+    }
+}
+
+// U32x4 | U32x4
+impl std::ops::BitOr for U32x4 {
+    type Output = U32x4;
+    #[inline(always)]
+    fn bitor(self, other: U32x4) -> U32x4 {
+        unsafe {
+            #[cfg(target_arch = "x86_64")]
+            {
+                use core::arch::x86_64::*;
+                Self { inner: _mm_or_si128(self.inner, other.inner) }
+            }
+
+            #[cfg(target_arch = "aarch64")]
+            {
+                use core::arch::aarch64::*;
+                Self { inner: vorrq_u32(self.inner, other.inner) }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fast_reciprocal_is_close_to_division() {
+        for x in [1.0e-4f32, 0.001, 0.1, 0.5, 1.0, 1.5, 3.0, 7.77, 100.0, 12345.0] {
+            let relative_error: f32 = (fast_reciprocal(x) * x - 1.0).abs();
+            assert!(relative_error < 1.0e-6, "x={x}, error={relative_error}");
+        }
+    }
+
+    #[test]
+    fn integer_lanes_arithmetic() {
+        let v: U32x4 = U32x4::load([(-512i32) as u32, 0, 300 << 16, 1 << 16]);
+        assert_eq!(v.shr_signed::<8>().store(), [(-2i32) as u32, 0, 300 << 8, 1 << 8]);
+        assert_eq!(v.shr_signed::<16>().clamp_signed(U32x4::splat(0), U32x4::splat(256)).store(), [0, 0, 256, 1]);
+        assert_eq!((U32x4::splat(3).shl::<8>() - U32x4::splat(3)).store(), [765; 4]);
+        assert_eq!((U32x4::splat(0xF0) | U32x4::load([1, 2, 3, 4])).store(), [0xF1, 0xF2, 0xF3, 0xF4]);
+        assert_eq!((U32x4::splat(0xF3) & U32x4::load([1, 2, 4, 8])).store(), [1, 2, 0, 0]);
+        let mut sum: U32x4 = U32x4::splat(1);
+        sum += U32x4::load([1, 2, 3, 4]);
+        assert_eq!((sum + U32x4::splat(10)).store(), [12, 13, 14, 15]);
+        let mut out = [0u32; 6];
+        unsafe { U32x4::load([1, 2, 3, 4]).store_to_ptr(out.as_mut_ptr().add(1)) };
+        assert_eq!(out, [0, 1, 2, 3, 4, 0]);
+    }
+
+    #[test]
+    fn rcp_is_close_to_division() {
+        let values: [f32; 4] = [0.25, 3.0, 42.0, 1000.0];
+        let reciprocals: [f32; 4] = F32x4::load(values).rcp().store();
+        for i in 0..4 {
+            let relative_error: f32 = (reciprocals[i] * values[i] - 1.0).abs();
+            assert!(relative_error < 1.0e-6, "x={}, error={relative_error}", values[i]);
+        }
+    }
+
+    #[test]
+    fn mul_add_rounding_follows_determinism_feature() {
+        // 0.1 * 10.0 rounds to exactly 1.0, while the fused version keeps the residual
+        let result: f32 = mul_add(0.1, 10.0, -1.0);
+        let fused: [f32; 4] = F32x4::splat(0.1).fma(F32x4::splat(10.0), F32x4::splat(-1.0)).store();
+        if cfg!(feature = "strict-determinism") {
+            assert_eq!(result, 0.0);
+            assert_eq!(fused, [0.0; 4]);
+        } else {
+            assert_ne!(result, 0.0);
+            assert_ne!(fused, [0.0; 4]);
+        }
+    }
+
+    #[cfg(feature = "strict-determinism")]
+    #[test]
+    fn strict_reciprocals_are_exact() {
+        let values: [f32; 4] = [0.25, 3.0, 7.0, 1000.0];
+        let reciprocals: [f32; 4] = F32x4::load(values).rcp().store();
+        let inverse_roots: [f32; 4] = F32x4::load(values).rsqrt().store();
+        for i in 0..4 {
+            assert_eq!(reciprocals[i], 1.0 / values[i]);
+            assert_eq!(inverse_roots[i], 1.0 / values[i].sqrt());
+            assert_eq!(fast_reciprocal(values[i]), 1.0 / values[i]);
+        }
+    }
+}
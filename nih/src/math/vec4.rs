@@ -1,6 +1,7 @@
 use crate::math::*;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Vec4 {
     pub x: f32,
     pub y: f32,
@@ -0,0 +1,3 @@
+pub mod tracer;
+
+pub use tracer::*;
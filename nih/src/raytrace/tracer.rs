@@ -0,0 +1,386 @@
+//! A software ray tracer rendering the same meshes, textures and lights as the rasterizer, e.g. to produce the
+//! reference images of the rasterizer's lighting features or screenshots with the effects it can't do, like shadows and
+//! interreflections. It's meant for validation rather than speed: every mesh is traced via its `Bvh` and the image is
+//! rendered on the CPU row by row in parallel.
+
+use crate::geometry::{Bvh, MeshHit, raycast_bvh, screen_ray};
+use crate::math::*;
+use crate::render::{
+    Buffer, Camera, Light, LightingMaterial, MeshData, RGBA, Sampler, SamplerFilter, Texture, Viewport,
+};
+use std::sync::Arc;
+
+/// The surface of a traced object, mirroring the parameters of a rasterization command with vertex lighting.
+#[derive(Debug, Clone)]
+pub struct RaytraceMaterial {
+    /// Multiplies the vertices' colors and the texels, the alpha is ignored.
+    /// Default: (1, 1, 1, 1).
+    pub color: Vec4,
+
+    /// Sampled bilinearly from the top mip level with the mesh's texture coordinates.
+    /// Default: None.
+    pub texture: Option<Arc<Texture>>,
+
+    /// The response to the lights, the same as with `VertexLighting` but evaluated per ray.
+    /// Default: `LightingMaterial::default()`.
+    pub lighting: LightingMaterial,
+
+    /// The fraction of the light reflected by a perfect mirror, the rest is lit by the material.
+    /// Default: 0.0.
+    pub reflectivity: f32,
+
+    /// The light emitted by the surface itself, linear.
+    /// Default: (0, 0, 0).
+    pub emission: Vec3,
+}
+
+impl Default for RaytraceMaterial {
+    fn default() -> Self {
+        Self {
+            color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            texture: None,
+            lighting: LightingMaterial::default(),
+            reflectivity: 0.0,
+            emission: Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// A mesh placed in the world. The hierarchy must be built from the mesh, see `Bvh::build()`.
+#[derive(Debug, Clone)]
+pub struct RaytraceObject<'a> {
+    pub mesh: &'a MeshData,
+    pub bvh: &'a Bvh,
+    pub model: Mat34,
+    pub material: RaytraceMaterial,
+}
+
+/// Everything the rays can hit or be lit by. The lights use the relative units, see `LightUnits::Relative`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RaytraceScene<'a> {
+    pub objects: &'a [RaytraceObject<'a>],
+    pub lights: &'a [Light],
+}
+
+/// How the light reaching the camera is gathered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RaytraceMode {
+    /// The direct light with hard shadows plus the mirror reflections, one ray through the center of each pixel.
+    Whitted,
+
+    /// Also the diffuse light bounced between the surfaces, averaged over the number of random paths per pixel.
+    /// The material's ambient term is still added, set it to zero for the physically based result.
+    PathTracing { samples: u32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaytraceOptions {
+    /// Default: `RaytraceMode::Whitted`.
+    pub mode: RaytraceMode,
+
+    /// The maximum number of bounces after the camera ray, the light of deeper paths is dropped.
+    /// Default: 4.
+    pub max_depth: u32,
+
+    /// Whether the lights are blocked by the objects between them and the lit points.
+    /// Default: true.
+    pub shadows: bool,
+
+    /// The light of the rays missing every object, linear.
+    /// Default: (0, 0, 0).
+    pub background: Vec3,
+
+    /// Seeds the random paths of the path tracing, the same seed renders the same image regardless of the threads.
+    /// Default: 0.
+    pub seed: u32,
+}
+
+impl Default for RaytraceOptions {
+    fn default() -> Self {
+        Self { mode: RaytraceMode::Whitted, max_depth: 4, shadows: true, background: Vec3::new(0.0, 0.0, 0.0), seed: 0 }
+    }
+}
+
+/// Renders the scene seen by the camera into the whole target as opaque colors, quantized the same way the rasterizer
+/// writes them. The camera's aspect ratio should match the target's one.
+pub fn raytrace(scene: &RaytraceScene, camera: &Camera, options: &RaytraceOptions, target: &mut Buffer<u32>) {
+    use rayon::prelude::*;
+    let (width, height, stride) = (target.width, target.height, target.stride as usize);
+    if width == 0 || height == 0 {
+        return;
+    }
+    let viewport = Viewport::new(0, 0, width, height);
+    let (view, projection) = (camera.view(), camera.projection_matrix());
+    target
+        .elems
+        .par_chunks_mut(stride)
+        .take(height as usize)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, pixel) in row.iter_mut().take(width as usize).enumerate() {
+                let mut random = Random::new(options.seed, (y * width as usize + x) as u32);
+                let color: Vec3 = match options.mode {
+                    RaytraceMode::Whitted => {
+                        let ray: Ray = screen_ray(x as f32 + 0.5, y as f32 + 0.5, &viewport, &view, &projection);
+                        trace(scene, options, &ray, 0, &mut random)
+                    }
+                    RaytraceMode::PathTracing { samples } => {
+                        let samples: u32 = samples.max(1);
+                        let mut sum = Vec3::new(0.0, 0.0, 0.0);
+                        for _ in 0..samples {
+                            let (sx, sy) = (x as f32 + random.next(), y as f32 + random.next());
+                            let ray: Ray = screen_ray(sx, sy, &viewport, &view, &projection);
+                            sum += trace(scene, options, &ray, 0, &mut random);
+                        }
+                        sum / samples as f32
+                    }
+                };
+                *pixel = quantize(color).to_u32();
+            }
+        });
+}
+
+// How far the secondary rays start off the surface, so they don't hit the triangle they leave.
+const SURFACE_OFFSET: f32 = 1e-4;
+
+// The light coming back along the ray.
+fn trace(scene: &RaytraceScene, options: &RaytraceOptions, ray: &Ray, depth: u32, random: &mut Random) -> Vec3 {
+    let Some((object, hit)) = closest_hit(scene, ray) else {
+        return options.background;
+    };
+    let material: &RaytraceMaterial = &object.material;
+    let direction: Vec3 = ray.direction.normalized();
+    // The triangles are two-sided, so they're lit from the side the ray comes from
+    let normal: Vec3 = if dot(hit.normal, direction) > 0.0 {
+        -hit.normal
+    } else {
+        hit.normal
+    };
+    let origin: Vec3 = hit.position + normal * SURFACE_OFFSET;
+    let albedo: Vec3 = surface_color(object, &hit);
+
+    let mut diffuse: Vec3 = material.lighting.ambient;
+    let mut specular = Vec3::new(0.0, 0.0, 0.0);
+    let to_eye: Vec3 = -direction;
+    for light in scene.lights {
+        let Some((to_light, light_color)) = light.incident(hit.position) else {
+            continue;
+        };
+        let n_dot_l: f32 = dot(normal, to_light);
+        if n_dot_l <= 0.0 {
+            continue;
+        }
+        if options.shadows {
+            let max_t: f32 = match *light {
+                Light::Directional { .. } => f32::INFINITY,
+                Light::Point { position, .. } => (position - origin).length(),
+            };
+            if is_occluded(scene, &Ray::new(origin, to_light), max_t) {
+                continue;
+            }
+        }
+        diffuse += material.lighting.diffuse * light_color * n_dot_l;
+        let half: Vec3 = (to_light + to_eye).normalized();
+        specular +=
+            material.lighting.specular * light_color * dot(normal, half).max(0.0).powf(material.lighting.shininess);
+    }
+    let lit: Vec3 = albedo * diffuse + specular;
+
+    let mut radiance: Vec3 = material.emission + lit * (1.0 - material.reflectivity);
+    if depth < options.max_depth {
+        if material.reflectivity > 0.0 {
+            let reflected: Vec3 = direction - normal * (2.0 * dot(direction, normal));
+            let bounce: Vec3 = trace(scene, options, &Ray::new(origin, reflected), depth + 1, random);
+            radiance += bounce * material.reflectivity;
+        }
+        if let RaytraceMode::PathTracing { .. } = options.mode {
+            // The cosine-weighted sampling cancels out the Lambertian cosine, leaving the albedo as the weight
+            let bounced: Vec3 = cosine_weighted_direction(normal, random);
+            let bounce: Vec3 = trace(scene, options, &Ray::new(origin, bounced), depth + 1, random);
+            radiance += bounce * albedo * material.lighting.diffuse * (1.0 - material.reflectivity);
+        }
+    }
+    radiance
+}
+
+fn closest_hit<'a>(scene: &'a RaytraceScene, ray: &Ray) -> Option<(&'a RaytraceObject<'a>, MeshHit)> {
+    scene
+        .objects
+        .iter()
+        .filter_map(|object| raycast_bvh(ray, object.mesh, object.bvh, &object.model).map(|hit| (object, hit)))
+        .min_by(|(_, a), (_, b)| a.t.total_cmp(&b.t))
+}
+
+// Whether any object is hit by the ray closer than max_t.
+fn is_occluded(scene: &RaytraceScene, ray: &Ray, max_t: f32) -> bool {
+    scene.objects.iter().any(|object| {
+        let local: Ray = ray.transformed(&object.model.as_mat44().inverse().as_mat34());
+        object.bvh.occluded(&local, object.mesh, max_t)
+    })
+}
+
+// The material's color multiplied by the vertices' colors and the texel at the hit point.
+fn surface_color(object: &RaytraceObject, hit: &MeshHit) -> Vec3 {
+    let mut color: Vec4 = object.material.color;
+    let mesh: &MeshData = object.mesh;
+    if !mesh.colors.is_empty() {
+        let [a, b, c] = mesh.triangle(hit.triangle);
+        let w: f32 = 1.0 - hit.u - hit.v;
+        let vertex: Vec4 = mesh.colors[a] * w + mesh.colors[b] * hit.u + mesh.colors[c] * hit.v;
+        color = Vec4::new(color.x * vertex.x, color.y * vertex.y, color.z * vertex.z, color.w * vertex.w);
+    }
+    if let Some(texture) = &object.material.texture {
+        let texel: RGBA = Sampler::new(texture, SamplerFilter::Bilinear, 0.0).sample(hit.tex_coord.x, hit.tex_coord.y);
+        color = Vec4::new(
+            color.x * texel.r as f32 / 255.0,
+            color.y * texel.g as f32 / 255.0,
+            color.z * texel.b as f32 / 255.0,
+            color.w * texel.a as f32 / 255.0,
+        );
+    }
+    color.xyz()
+}
+
+// A random direction in the hemisphere around the unit normal, distributed proportionally to the cosine to the normal.
+fn cosine_weighted_direction(normal: Vec3, random: &mut Random) -> Vec3 {
+    let (r1, r2) = (random.next(), random.next());
+    let phi: f32 = 2.0 * std::f32::consts::PI * r1;
+    let radius: f32 = r2.sqrt();
+    let helper: Vec3 = if normal.x.abs() > 0.9 {
+        Vec3::new(0.0, 1.0, 0.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let tangent: Vec3 = cross(helper, normal).normalized();
+    let bitangent: Vec3 = cross(normal, tangent);
+    (tangent * (radius * phi.cos()) + bitangent * (radius * phi.sin()) + normal * (1.0 - r2).max(0.0).sqrt())
+        .normalized()
+}
+
+fn quantize(color: Vec3) -> RGBA {
+    let channel = |c: f32| -> u8 { (c * 255.0 + 0.5).clamp(0.0, 255.0) as u8 };
+    RGBA::new(channel(color.x), channel(color.y), channel(color.z), 255)
+}
+
+// A xorshift generator seeded per pixel, so the image doesn't depend on how the rows are spread across the threads.
+struct Random(u32);
+
+impl Random {
+    fn new(seed: u32, pixel: u32) -> Self {
+        // Scrambles the seed and the pixel's index, the state must not be zero
+        let mut state: u32 = seed.wrapping_mul(0x9E37_79B9) ^ pixel.wrapping_mul(0x85EB_CA6B);
+        state ^= state >> 16;
+        state = state.wrapping_mul(0x7FEB_352D);
+        state ^= state >> 15;
+        Self(state | 1)
+    }
+
+    // A uniformly distributed number in [0, 1).
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::*;
+
+    fn camera() -> Camera {
+        let mut camera = Camera::perspective(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        camera.position = Vec3::new(0.0, 0.0, 3.0);
+        camera
+    }
+
+    fn pixel(buffer: &Buffer<u32>, x: u16, y: u16) -> RGBA {
+        RGBA::from_u32(buffer.at(x, y))
+    }
+
+    #[test]
+    fn lit_cube_over_background() {
+        let cube: MeshData = cuboid(Vec3::new(2.0, 2.0, 2.0));
+        let bvh: Bvh = Bvh::build(&cube);
+        let material = RaytraceMaterial { color: Vec4::new(1.0, 0.5, 0.0, 1.0), ..Default::default() };
+        let objects = [RaytraceObject { mesh: &cube, bvh: &bvh, model: Mat34::identity(), material }];
+        let lights = [Light::Directional { direction: Vec3::new(0.0, 0.0, -1.0), color: Vec3::new(1.0, 1.0, 1.0) }];
+        let scene = RaytraceScene { objects: &objects, lights: &lights };
+        let options = RaytraceOptions { background: Vec3::new(0.0, 0.0, 1.0), ..Default::default() };
+        let mut buffer = Buffer::<u32>::new(16, 16);
+        raytrace(&scene, &camera(), &options, &mut buffer);
+        // The front face is lit head-on, the corners of the image see the background
+        assert_eq!(pixel(&buffer, 8, 8), RGBA::new(255, 128, 0, 255));
+        assert_eq!(pixel(&buffer, 0, 0), RGBA::new(0, 0, 255, 255));
+
+        // Without the light only the ambient term remains
+        let dark = RaytraceScene { objects: &objects, lights: &[] };
+        raytrace(&dark, &camera(), &options, &mut buffer);
+        assert_eq!(pixel(&buffer, 8, 8), RGBA::new(0, 0, 0, 255));
+    }
+
+    #[test]
+    fn occluder_casts_shadow() {
+        let floor: MeshData = MeshData::grid(1, 1, Vec2::new(20.0, 20.0));
+        let floor_bvh: Bvh = Bvh::build(&floor);
+        let cube: MeshData = cuboid(Vec3::new(1.0, 1.0, 1.0));
+        let cube_bvh: Bvh = Bvh::build(&cube);
+        let objects = [
+            RaytraceObject { mesh: &floor, bvh: &floor_bvh, model: Mat34::identity(), material: Default::default() },
+            RaytraceObject {
+                mesh: &cube,
+                bvh: &cube_bvh,
+                model: Mat34::translate(Vec3::new(0.0, 2.0, 0.0)),
+                material: Default::default(),
+            },
+        ];
+        let lights = [Light::Directional { direction: Vec3::new(0.0, -1.0, 0.0), color: Vec3::new(1.0, 1.0, 1.0) }];
+        let scene = RaytraceScene { objects: &objects, lights: &lights };
+        let mut camera = Camera::perspective(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        camera.position = Vec3::new(0.0, 10.0, 0.01);
+        camera.look_at(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let mut buffer = Buffer::<u32>::new(32, 32);
+
+        // The floor around the cube is lit, the cube's top too, but its shadow is only seen without the cube itself
+        raytrace(&scene, &camera, &RaytraceOptions::default(), &mut buffer);
+        assert_eq!(pixel(&buffer, 2, 2), RGBA::new(255, 255, 255, 255));
+        assert_eq!(pixel(&buffer, 16, 16), RGBA::new(255, 255, 255, 255));
+        let raised = Mat34::translate(Vec3::new(0.0, 20.0, 0.0));
+        let objects_above = [objects[0].clone(), RaytraceObject { model: raised, ..objects[1].clone() }];
+        let above = RaytraceScene { objects: &objects_above, lights: &lights };
+        raytrace(&above, &camera, &RaytraceOptions::default(), &mut buffer);
+        assert_eq!(pixel(&buffer, 16, 16), RGBA::new(0, 0, 0, 255));
+        let unshadowed = RaytraceOptions { shadows: false, ..Default::default() };
+        raytrace(&above, &camera, &unshadowed, &mut buffer);
+        assert_eq!(pixel(&buffer, 16, 16), RGBA::new(255, 255, 255, 255));
+    }
+
+    #[test]
+    fn mirror_and_path_tracing() {
+        let cube: MeshData = cuboid(Vec3::new(2.0, 2.0, 2.0));
+        let bvh: Bvh = Bvh::build(&cube);
+        let mirror = RaytraceMaterial { reflectivity: 1.0, ..Default::default() };
+        let objects = [RaytraceObject { mesh: &cube, bvh: &bvh, model: Mat34::identity(), material: mirror }];
+        let scene = RaytraceScene { objects: &objects, lights: &[] };
+        let options = RaytraceOptions { background: Vec3::new(0.0, 1.0, 0.0), ..Default::default() };
+        let mut buffer = Buffer::<u32>::new(8, 8);
+        // The perfect mirror shows the background behind the camera
+        raytrace(&scene, &camera(), &options, &mut buffer);
+        assert_eq!(pixel(&buffer, 4, 4), RGBA::new(0, 255, 0, 255));
+
+        // A white diffuse cube under a white sky gathers the sky's light, the same seed renders the same image
+        let diffuse = [RaytraceObject { material: RaytraceMaterial::default(), ..objects[0].clone() }];
+        let scene = RaytraceScene { objects: &diffuse, lights: &[] };
+        let options = RaytraceOptions {
+            mode: RaytraceMode::PathTracing { samples: 4 },
+            background: Vec3::new(1.0, 1.0, 1.0),
+            ..Default::default()
+        };
+        raytrace(&scene, &camera(), &options, &mut buffer);
+        assert_eq!(pixel(&buffer, 4, 4), RGBA::new(255, 255, 255, 255));
+        let mut again = Buffer::<u32>::new(8, 8);
+        raytrace(&scene, &camera(), &options, &mut again);
+        assert_eq!(buffer.elems, again.elems);
+    }
+}
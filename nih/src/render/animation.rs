@@ -0,0 +1,375 @@
+use super::super::math::*;
+
+// A joint's placement relative to its parent as separate translation, rotation and scale, which, unlike matrices, can be
+// interpolated between the keyframes and blended between the clips.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JointTransform {
+    // Default: (0, 0, 0).
+    pub translation: Vec3,
+
+    // Default: identity.
+    pub rotation: Quat,
+
+    // Default: (1, 1, 1).
+    pub scale: Vec3,
+}
+
+impl Default for JointTransform {
+    fn default() -> Self {
+        Self { translation: Vec3::new(0.0, 0.0, 0.0), rotation: Quat::identity(), scale: Vec3::new(1.0, 1.0, 1.0) }
+    }
+}
+
+impl JointTransform {
+    // Scales, then rotates, then translates.
+    pub fn as_mat34(&self) -> Mat34 {
+        let r: [f32; 9] = self.rotation.as_mat33().0;
+        let (s, t) = (self.scale, self.translation);
+        Mat34([
+            r[0] * s.x,
+            r[1] * s.y,
+            r[2] * s.z,
+            t.x, //
+            r[3] * s.x,
+            r[4] * s.y,
+            r[5] * s.z,
+            t.y, //
+            r[6] * s.x,
+            r[7] * s.y,
+            r[8] * s.z,
+            t.z, //
+        ])
+    }
+
+    // Interpolates the components separately, t of 0 gives self and 1 gives other.
+    pub fn blend(&self, other: &JointTransform, t: f32) -> JointTransform {
+        JointTransform {
+            translation: lerp(self.translation, other.translation, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: lerp(self.scale, other.scale, t),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Joint {
+    pub name: String,
+
+    // The index of the parent joint, which always precedes the joint in the skeleton, None for the roots.
+    pub parent: Option<usize>,
+
+    // The joint's placement relative to its parent when no clip animates it.
+    pub rest: JointTransform,
+
+    // The transform from the model space into the joint's space at the bind pose, i.e. the inverse of the joint's
+    // model-space transform at the time the mesh was bound to the skeleton.
+    pub inverse_bind: Mat34,
+}
+
+// A hierarchy of joints, e.g. of a character, which moves the vertices bound to them via Skinning.
+// The joints are ordered so that the parents precede their children, which lets the model-space transforms be
+// calculated in a single pass.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skeleton {
+    joints: Vec<Joint>,
+}
+
+impl Skeleton {
+    // Panics if a joint's parent doesn't precede it.
+    pub fn new(joints: Vec<Joint>) -> Self {
+        for (index, joint) in joints.iter().enumerate() {
+            assert!(joint.parent.is_none_or(|parent| parent < index), "the parent of joint {} must precede it", index);
+        }
+        Self { joints }
+    }
+
+    // Builds the skeleton bound at its rest pose, i.e. the inverse bind matrices are calculated from the rest
+    // transforms. The joints are given as (name, parent, rest).
+    pub fn from_rest_pose(joints: &[(&str, Option<usize>, JointTransform)]) -> Self {
+        let mut skeleton = Skeleton::new(
+            joints
+                .iter()
+                .map(|&(name, parent, rest)| Joint {
+                    name: name.to_string(),
+                    parent,
+                    rest,
+                    inverse_bind: Mat34::identity(),
+                })
+                .collect(),
+        );
+        let mut model: Vec<Mat34> = Vec::new();
+        skeleton.model_transforms(&skeleton.rest_pose(), &mut model);
+        for (joint, transform) in skeleton.joints.iter_mut().zip(model) {
+            joint.inverse_bind = transform.as_mat44().inverse().as_mat34();
+        }
+        skeleton
+    }
+
+    pub fn joints(&self) -> &[Joint] {
+        &self.joints
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+
+    pub fn rest_pose(&self) -> Pose {
+        Pose { joints: self.joints.iter().map(|joint| joint.rest).collect() }
+    }
+
+    // Calculates the model-space transform of each joint of the pose.
+    pub fn model_transforms(&self, pose: &Pose, transforms: &mut Vec<Mat34>) {
+        assert_eq!(pose.joints.len(), self.joints.len(), "the pose must have a transform per joint");
+        transforms.clear();
+        for (joint, local) in self.joints.iter().zip(&pose.joints) {
+            let local: Mat34 = local.as_mat34();
+            let transform: Mat34 = match joint.parent {
+                Some(parent) => transforms[parent] * local,
+                None => local,
+            };
+            transforms.push(transform);
+        }
+    }
+
+    // Calculates the bone palette of the pose, i.e. the transform of each joint from the bind pose into the pose, to be
+    // used as Skinning::palette.
+    pub fn palette(&self, pose: &Pose, palette: &mut Vec<Mat34>) {
+        self.model_transforms(pose, palette);
+        for (transform, joint) in palette.iter_mut().zip(&self.joints) {
+            *transform = *transform * joint.inverse_bind;
+        }
+    }
+}
+
+// The local transforms of all the joints of a skeleton, in the same order.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Pose {
+    pub joints: Vec<JointTransform>,
+}
+
+impl Pose {
+    // Blends the other pose over this one by the weight, e.g. to cross-fade between two clips. The poses must be of the
+    // same skeleton.
+    pub fn blend(&mut self, other: &Pose, weight: f32) {
+        assert_eq!(self.joints.len(), other.joints.len(), "the poses must have the same joints");
+        for (joint, other) in self.joints.iter_mut().zip(&other.joints) {
+            *joint = joint.blend(other, weight);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe<T> {
+    // In seconds since the clip's start.
+    pub time: f32,
+    pub value: T,
+}
+
+// The keyframes animating a single joint. Each of the components is keyed separately, the keyframes must be sorted by
+// time. An empty component keeps the joint's rest value.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct JointTrack {
+    pub joint: usize,
+    pub translations: Vec<Keyframe<Vec3>>,
+    pub rotations: Vec<Keyframe<Quat>>,
+    pub scales: Vec<Keyframe<Vec3>>,
+}
+
+// A keyframed animation of a skeleton's joints, e.g. a walk cycle. The values between the keyframes are interpolated
+// linearly, the rotations spherically, and are held before the first and after the last keyframe.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AnimationClip {
+    pub name: String,
+
+    // In seconds.
+    pub duration: f32,
+
+    pub tracks: Vec<JointTrack>,
+}
+
+impl AnimationClip {
+    // Sets the pose of the skeleton at the time in seconds: the joints without tracks are at their rest transforms.
+    // When looping, the time wraps around the duration, otherwise it's clamped to it.
+    pub fn sample(&self, skeleton: &Skeleton, time: f32, looping: bool, pose: &mut Pose) {
+        let time: f32 = if looping && self.duration > 0.0 {
+            time.rem_euclid(self.duration)
+        } else {
+            time.clamp(0.0, self.duration.max(0.0))
+        };
+        pose.joints.clear();
+        pose.joints.extend(skeleton.joints.iter().map(|joint| joint.rest));
+        for track in &self.tracks {
+            let Some(joint) = pose.joints.get_mut(track.joint) else {
+                continue;
+            };
+            if let Some(translation) = interpolate(&track.translations, time, lerp) {
+                joint.translation = translation;
+            }
+            if let Some(rotation) = interpolate(&track.rotations, time, Quat::slerp) {
+                joint.rotation = rotation;
+            }
+            if let Some(scale) = interpolate(&track.scales, time, lerp) {
+                joint.scale = scale;
+            }
+        }
+    }
+}
+
+// The value of the keyframes at the time, None if there are no keyframes.
+fn interpolate<T: Copy>(keyframes: &[Keyframe<T>], time: f32, mix: impl Fn(T, T, f32) -> T) -> Option<T> {
+    let next: usize = keyframes.partition_point(|keyframe| keyframe.time <= time);
+    if next == 0 || next == keyframes.len() {
+        return keyframes
+            .get(next.min(keyframes.len().saturating_sub(1)))
+            .map(|keyframe| keyframe.value);
+    }
+    let (a, b) = (&keyframes[next - 1], &keyframes[next]);
+    let t: f32 = (time - a.time) / (b.time - a.time);
+    Some(mix(a.value, b.value, t))
+}
+
+// The deformation of a command's vertices by the joints of a skeleton, a.k.a. linear blend skinning: each vertex is
+// transformed by up to four of the palette's matrices and the results are mixed by the vertex's weights. The normals are
+// transformed the same way without the translation, which assumes the joints are scaled uniformly.
+// The skinned vertices replace the original ones after the vertex animation and the morph targets, and before the
+// displacement and the vertex hook. The bounding box, if there's one, must cover the skinned positions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Skinning<'a> {
+    // The transform of each joint from the bind pose into the current one, see Skeleton::palette().
+    pub palette: &'a [Mat34],
+
+    // The indices into the palette of the joints each vertex is bound to.
+    pub joints: &'a [[u16; 4]],
+
+    // The weights of each vertex's joints, usually summing to 1. The joints with zero weights are skipped.
+    pub weights: &'a [Vec4],
+}
+
+impl<'a> Skinning<'a> {
+    pub fn new(palette: &'a [Mat34], joints: &'a [[u16; 4]], weights: &'a [Vec4]) -> Self {
+        Self { palette, joints, weights }
+    }
+
+    // The skinned position and normal of the command's vertex, None if it isn't bound to any joint of the palette.
+    pub(crate) fn apply(&self, vertex: usize, position: Vec3, normal: Vec3) -> Option<(Vec3, Vec3)> {
+        let (joints, weights) = (self.joints.get(vertex)?, self.weights.get(vertex)?);
+        let mut skinned_position = Vec3::new(0.0, 0.0, 0.0);
+        let mut skinned_normal = Vec3::new(0.0, 0.0, 0.0);
+        let mut total: f32 = 0.0;
+        for (&joint, weight) in joints.iter().zip([weights.x, weights.y, weights.z, weights.w]) {
+            if weight == 0.0 {
+                continue;
+            }
+            let Some(transform) = self.palette.get(joint as usize) else {
+                continue;
+            };
+            skinned_position += (transform * position) * weight;
+            skinned_normal += (transform.as_mat33() * normal) * weight;
+            total += weight;
+        }
+        if total == 0.0 {
+            return None;
+        }
+        Some((skinned_position / total, skinned_normal.normalized()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn assert_vec3_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    // A root at the origin and an arm one unit up
+    fn arm() -> Skeleton {
+        let up = JointTransform { translation: Vec3::new(0.0, 1.0, 0.0), ..Default::default() };
+        Skeleton::from_rest_pose(&[("root", None, JointTransform::default()), ("arm", Some(0), up)])
+    }
+
+    // Bends the arm by 90° around Z over a second
+    fn bend() -> AnimationClip {
+        let z = Vec3::new(0.0, 0.0, 1.0);
+        AnimationClip {
+            name: "bend".to_string(),
+            duration: 1.0,
+            tracks: vec![JointTrack {
+                joint: 1,
+                rotations: vec![
+                    Keyframe { time: 0.0, value: Quat::identity() },
+                    Keyframe { time: 1.0, value: Quat::from_axis_angle(z, FRAC_PI_2) },
+                ],
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn rest_pose_has_identity_palette() {
+        let skeleton = arm();
+        let mut palette: Vec<Mat34> = Vec::new();
+        skeleton.palette(&skeleton.rest_pose(), &mut palette);
+        assert_eq!(palette.len(), 2);
+        for transform in &palette {
+            assert_vec3_near(transform * Vec3::new(1.0, 2.0, 3.0), Vec3::new(1.0, 2.0, 3.0));
+        }
+        assert_eq!(skeleton.find("arm"), Some(1));
+    }
+
+    #[test]
+    fn sampling_interpolates_and_loops() {
+        let skeleton = arm();
+        let clip = bend();
+        let mut pose = Pose::default();
+        let mut palette: Vec<Mat34> = Vec::new();
+
+        // The point at the arm's tip swings around the arm's joint
+        let tip = Vec3::new(0.0, 2.0, 0.0);
+        clip.sample(&skeleton, 1.0, false, &mut pose);
+        skeleton.palette(&pose, &mut palette);
+        assert_vec3_near(palette[1] * tip, Vec3::new(-1.0, 1.0, 0.0));
+        assert_vec3_near(palette[0] * tip, tip);
+
+        clip.sample(&skeleton, 0.5, false, &mut pose);
+        skeleton.palette(&pose, &mut palette);
+        let half: f32 = std::f32::consts::FRAC_1_SQRT_2;
+        assert_vec3_near(palette[1] * tip, Vec3::new(-half, 1.0 + half, 0.0));
+
+        // Looping wraps 1.5 to 0.5, clamping holds the last keyframe
+        let mut looped = Pose::default();
+        clip.sample(&skeleton, 1.5, true, &mut looped);
+        assert_eq!(looped, pose);
+        clip.sample(&skeleton, 3.0, false, &mut looped);
+        skeleton.palette(&looped, &mut palette);
+        assert_vec3_near(palette[1] * tip, Vec3::new(-1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn poses_are_blended() {
+        let skeleton = arm();
+        let mut pose = skeleton.rest_pose();
+        let mut bent = Pose::default();
+        bend().sample(&skeleton, 1.0, false, &mut bent);
+        pose.blend(&bent, 0.5);
+        let mut halfway = Pose::default();
+        bend().sample(&skeleton, 0.5, false, &mut halfway);
+        for (a, b) in pose.joints.iter().zip(&halfway.joints) {
+            assert_vec3_near(a.rotation * Vec3::new(1.0, 0.0, 0.0), b.rotation * Vec3::new(1.0, 0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn skinning_mixes_joints() {
+        let palette = [Mat34::identity(), Mat34::translate(Vec3::new(2.0, 0.0, 0.0))];
+        let joints = [[0, 1, 0, 0], [1, 0, 0, 0], [7, 0, 0, 0]];
+        let weights = [Vec4::new(0.5, 0.5, 0.0, 0.0), Vec4::new(1.0, 0.0, 0.0, 0.0), Vec4::new(1.0, 0.0, 0.0, 0.0)];
+        let skinning = Skinning::new(&palette, &joints, &weights);
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        assert_eq!(skinning.apply(0, Vec3::new(0.0, 0.0, 0.0), normal), Some((Vec3::new(1.0, 0.0, 0.0), normal)));
+        assert_eq!(skinning.apply(1, Vec3::new(0.0, 0.0, 0.0), normal), Some((Vec3::new(2.0, 0.0, 0.0), normal)));
+        assert_eq!(skinning.apply(2, Vec3::new(0.0, 0.0, 0.0), normal), None);
+        assert_eq!(skinning.apply(3, Vec3::new(0.0, 0.0, 0.0), normal), None);
+    }
+}
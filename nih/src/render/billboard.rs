@@ -0,0 +1,219 @@
+use super::super::math::*;
+use super::*;
+
+// The way the billboards are turned towards the camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BillboardMode {
+    // Parallel to the view plane, e.g. particles and impostors.
+    Spherical,
+
+    // Spun around the axis in world space to face the camera, the quads' up direction stays along the axis, e.g.
+    // grass, trees and flames. The axis doesn't have to be normalized.
+    Cylindrical(Vec3),
+}
+
+// Camera-facing quads, e.g. particles or grass, given by their centers in world space. The corners are derived from
+// the view matrix in Rasterizer::commit_billboards() and committed as regular textured triangles, so they're depth
+// tested, ordered and blended the same way as the other geometry.
+#[derive(Debug, Clone)]
+pub struct BillboardCommand<'a> {
+    pub centers: &'a [Vec3],
+
+    // Optional per-billboard width and height in world units.
+    // Default: empty, i.e. the size is used for all.
+    pub sizes: &'a [Vec2],
+
+    // Default: (1, 1).
+    pub size: Vec2,
+
+    // Optional per-billboard rotation in radians around the direction towards the camera, counter-clockwise as seen by
+    // the camera. Rotating the cylindrical billboards tilts them away from their axis.
+    // Default: empty, i.e. no rotation.
+    pub rotations: &'a [f32],
+
+    // Optional per-billboard color, multiplied by the command's color.
+    // Default: empty.
+    pub colors: &'a [Vec4],
+
+    // Default: (1, 1, 1, 1).
+    pub color: Vec4,
+
+    // Default: Spherical.
+    pub mode: BillboardMode,
+
+    pub view: Mat44,
+    pub projection: Mat44,
+
+    // Default: None.
+    pub texture: Option<std::sync::Arc<Texture>>,
+
+    // The part of the texture shown on the quads, e.g. a cell of an atlas.
+    // Default: None, i.e. the entire texture.
+    pub region: Option<TextureRegion>,
+
+    // Default: bilinear.
+    pub sampling_filter: SamplerFilter,
+
+    // Default: None.
+    pub alpha_blending: AlphaBlendingMode,
+
+    // See RasterizationCommand::alpha_test.
+    // Default: 0, i.e. disabled.
+    pub alpha_test: u8,
+
+    // Blended billboards usually don't write their depth, so that the ones behind them aren't cut off.
+    // Default: true.
+    pub depth_write: bool,
+}
+
+impl Default for BillboardCommand<'_> {
+    fn default() -> Self {
+        Self {
+            centers: &[],
+            sizes: &[],
+            size: Vec2::new(1.0, 1.0),
+            rotations: &[],
+            colors: &[],
+            color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            mode: BillboardMode::Spherical,
+            view: Mat44::identity(),
+            projection: Mat44::identity(),
+            texture: None,
+            region: None,
+            sampling_filter: SamplerFilter::Bilinear,
+            alpha_blending: AlphaBlendingMode::None,
+            alpha_test: 0,
+            depth_write: true,
+        }
+    }
+}
+
+impl BillboardCommand<'_> {
+    // The number of vertices appended by append_geometry() per billboard: two triangles.
+    pub const VERTICES_PER_BILLBOARD: usize = 6;
+
+    // Appends the triangles of the quads in world space facing the camera of the view matrix, with the normals pointing
+    // towards the camera and the texture coordinates from (0, 0) at the top-left corner to (1, 1) at the bottom-right.
+    pub fn append_geometry(
+        &self,
+        positions: &mut Vec<Vec3>,
+        normals: &mut Vec<Vec3>,
+        tex_coords: &mut Vec<Vec2>,
+        colors: &mut Vec<Vec4>,
+    ) {
+        let m: &[f32; 16] = &self.view.0;
+        // The view's rows are the camera's axes in world space
+        let camera_right = Vec3::new(m[0], m[1], m[2]).normalized();
+        let camera_up = Vec3::new(m[4], m[5], m[6]).normalized();
+        let camera_back = Vec3::new(m[8], m[9], m[10]).normalized();
+        let inverse_view: Mat44 = self.view.inverse();
+        let eye = Vec3::new(inverse_view.0[3], inverse_view.0[7], inverse_view.0[11]);
+        for (index, &center) in self.centers.iter().enumerate() {
+            let (right, up, normal): (Vec3, Vec3, Vec3) = match self.mode {
+                BillboardMode::Spherical => (camera_right, camera_up, camera_back),
+                BillboardMode::Cylindrical(axis) => {
+                    let up: Vec3 = axis.normalized();
+                    // Towards the camera, falling back to the view direction when looking along the axis
+                    let to_eye: Vec3 = eye - center;
+                    let side: Vec3 = cross(up, to_eye);
+                    let right: Vec3 = if dot(side, side) > 1e-12 {
+                        side.normalized()
+                    } else {
+                        camera_right
+                    };
+                    (right, up, cross(right, up))
+                }
+            };
+            let size: Vec2 = self.sizes.get(index).copied().unwrap_or(self.size);
+            let (sin, cos): (f32, f32) = self.rotations.get(index).copied().unwrap_or(0.0).sin_cos();
+            let half_right: Vec3 = (right * cos + up * sin) * (size.x * 0.5);
+            let half_up: Vec3 = (up * cos - right * sin) * (size.y * 0.5);
+            let top_left: Vec3 = center - half_right + half_up;
+            let top_right: Vec3 = center + half_right + half_up;
+            let bottom_right: Vec3 = center + half_right - half_up;
+            let bottom_left: Vec3 = center - half_right - half_up;
+            positions.extend_from_slice(&[top_left, bottom_left, bottom_right, top_left, bottom_right, top_right]);
+            normals.extend_from_slice(&[normal; Self::VERTICES_PER_BILLBOARD]);
+            tex_coords.extend_from_slice(&[
+                Vec2::new(0.0, 0.0),
+                Vec2::new(0.0, 1.0),
+                Vec2::new(1.0, 1.0),
+                Vec2::new(0.0, 0.0),
+                Vec2::new(1.0, 1.0),
+                Vec2::new(1.0, 0.0),
+            ]);
+            let color: Vec4 = match self.colors.get(index) {
+                Some(&color) => color * self.color,
+                None => self.color,
+            };
+            colors.extend_from_slice(&[color; Self::VERTICES_PER_BILLBOARD]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn geometry(command: &BillboardCommand) -> (Vec<Vec3>, Vec<Vec3>) {
+        let mut positions: Vec<Vec3> = Vec::new();
+        let mut normals: Vec<Vec3> = Vec::new();
+        let mut tex_coords: Vec<Vec2> = Vec::new();
+        let mut colors: Vec<Vec4> = Vec::new();
+        command.append_geometry(&mut positions, &mut normals, &mut tex_coords, &mut colors);
+        assert_eq!(positions.len(), command.centers.len() * BillboardCommand::VERTICES_PER_BILLBOARD);
+        assert_eq!(tex_coords.len(), positions.len());
+        assert_eq!(colors.len(), positions.len());
+        (positions, normals)
+    }
+
+    fn assert_near(actual: Vec3, expected: Vec3) {
+        assert!((actual - expected).length() < 1e-4, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn spherical_faces_view_plane() {
+        // The camera at (10, 0, 0) looking towards -X, i.e. its right is -Z
+        let view: Mat44 = Mat44::rotate_zx(-std::f32::consts::FRAC_PI_2) * Mat44::translate(Vec3::new(-10.0, 0.0, 0.0));
+        let (positions, normals) = geometry(&BillboardCommand {
+            centers: &[Vec3::new(0.0, 1.0, 0.0)],
+            size: Vec2::new(2.0, 4.0),
+            view,
+            ..Default::default()
+        });
+        assert_near(positions[0], Vec3::new(0.0, 3.0, 1.0));
+        assert_near(positions[2], Vec3::new(0.0, -1.0, -1.0));
+        assert_near(normals[0], Vec3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn rotation_is_counter_clockwise() {
+        let (positions, _) = geometry(&BillboardCommand {
+            centers: &[Vec3::new(0.0, 0.0, 0.0)],
+            sizes: &[Vec2::new(2.0, 2.0)],
+            rotations: &[std::f32::consts::FRAC_PI_2],
+            ..Default::default()
+        });
+        // The top-left corner goes down to the bottom-left
+        assert_near(positions[0], Vec3::new(-1.0, -1.0, 0.0));
+    }
+
+    #[test]
+    fn cylindrical_keeps_axis() {
+        // The camera above and to the side, looking down at the origin
+        let eye = Vec3::new(10.0, 10.0, 0.0);
+        let view: Mat44 = Mat44::rotate_yz(std::f32::consts::FRAC_PI_4)
+            * Mat44::rotate_zx(-std::f32::consts::FRAC_PI_2)
+            * Mat44::translate(-eye);
+        let (positions, normals) = geometry(&BillboardCommand {
+            centers: &[Vec3::new(0.0, 0.0, 0.0)],
+            size: Vec2::new(2.0, 2.0),
+            mode: BillboardMode::Cylindrical(Vec3::new(0.0, 2.0, 0.0)),
+            view,
+            ..Default::default()
+        });
+        assert_near(positions[0], Vec3::new(0.0, 1.0, 1.0));
+        assert_near(positions[2], Vec3::new(0.0, -1.0, -1.0));
+        assert_near(normals[0], Vec3::new(1.0, 0.0, 0.0));
+    }
+}
@@ -80,6 +80,14 @@ impl<T: Copy + Zeroable + Pod> Buffer<T> {
         }
     }
 
+    // The rows of usable elements, without the padding up to the stride.
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        self.elems
+            .chunks(self.stride.max(1) as usize)
+            .take(self.height as usize)
+            .map(|row| &row[..self.width as usize])
+    }
+
     pub fn split_into_tiles<'a>(&'a mut self, tile_width: u16, tile_height: u16) -> Vec<BufferTile<'a, T>> {
         assert!(tile_width > 0 && tile_height > 0);
         let mut tiles = Vec::new();
@@ -0,0 +1,205 @@
+use super::super::math::*;
+use super::super::util::input::{CameraController, view_matrix};
+
+// How the camera's view volume is projected onto the screen, in the conventions of Mat44::perspective() and
+// Mat44::orthographic().
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    // The vertical field of view in radians and the distances to the clipping planes.
+    Perspective { fov_y: f32, near: f32, far: f32 },
+
+    // The height of the view volume in world units and the distances to the clipping planes.
+    Orthographic { height: f32, near: f32, far: f32 },
+}
+
+impl Projection {
+    // The aspect ratio is the width of the view divided by its height.
+    pub fn matrix(&self, aspect_ratio: f32) -> Mat44 {
+        match *self {
+            Projection::Perspective { fov_y, near, far } => Mat44::perspective(near, far, fov_y, aspect_ratio),
+            Projection::Orthographic { height, near, far } => {
+                let (half_width, half_height) = (height * aspect_ratio * 0.5, height * 0.5);
+                Mat44::orthographic(-half_width, half_width, -half_height, half_height, near, far)
+            }
+        }
+    }
+}
+
+// The point of view to render from: the camera's placement in the world and its projection.
+// The camera looks along its local -Z axis, with +Y being up and +X being right, the same as CameraController.
+// The position and the orientation can be changed directly, by the fly() and orbit() helpers taking the input deltas,
+// or copied from a controller with follow().
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    // Default: (0, 0, 0).
+    pub position: Vec3,
+
+    // Default: identity, i.e. looking along -Z.
+    pub orientation: Quat,
+
+    // Default: Perspective with fov_y of 60°, near of 1.0 and far of 100.0.
+    pub projection: Projection,
+
+    // The width of the view divided by its height, usually of the viewport.
+    // Default: 1.0.
+    pub aspect_ratio: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: Vec3::new(0.0, 0.0, 0.0),
+            orientation: Quat::identity(),
+            projection: Projection::Perspective { fov_y: std::f32::consts::PI / 3.0, near: 1.0, far: 100.0 },
+            aspect_ratio: 1.0,
+        }
+    }
+}
+
+impl Camera {
+    // Keeps the pitch off the poles, where the yaw becomes undefined.
+    const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+    pub fn perspective(fov_y: f32, aspect_ratio: f32, near: f32, far: f32) -> Self {
+        Self { projection: Projection::Perspective { fov_y, near, far }, aspect_ratio, ..Default::default() }
+    }
+
+    pub fn orthographic(height: f32, aspect_ratio: f32, near: f32, far: f32) -> Self {
+        Self { projection: Projection::Orthographic { height, near, far }, aspect_ratio, ..Default::default() }
+    }
+
+    // The world-to-camera transform, to be used as RasterizationCommand::view.
+    pub fn view(&self) -> Mat44 {
+        view_matrix(self.orientation, self.position).as_mat44()
+    }
+
+    // To be used as RasterizationCommand::projection.
+    pub fn projection_matrix(&self) -> Mat44 {
+        self.projection.matrix(self.aspect_ratio)
+    }
+
+    pub fn view_projection(&self) -> Mat44 {
+        self.projection_matrix() * self.view()
+    }
+
+    // The planes of the view volume in the world space.
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(&self.view_projection())
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        self.orientation * Vec3::new(0.0, 0.0, -1.0)
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.orientation * Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.orientation * Vec3::new(0.0, 1.0, 0.0)
+    }
+
+    // Turns the camera to look at the target, keeping it upright relative to the up direction.
+    // Does nothing if the target is at the camera's position.
+    pub fn look_at(&mut self, target: Vec3, up: Vec3) {
+        let backward: Vec3 = self.position - target;
+        if backward.length() > 0.0 {
+            self.orientation = Quat::from_look_rotation(backward, up).normalized();
+        }
+    }
+
+    // Free flight: turns the camera by yaw radians around the world's up axis (positive turns left) and by pitch radians
+    // around its right axis (positive looks up), then moves it by the offset given in the camera's space, e.g.
+    // (0, 0, -speed * dt) to move forward.
+    // The pitch stops short of looking straight up or down.
+    pub fn fly(&mut self, yaw: f32, pitch: f32, movement: Vec3) {
+        let current_pitch: f32 = self.forward().y.clamp(-1.0, 1.0).asin();
+        let pitch: f32 = (current_pitch + pitch).clamp(-Self::MAX_PITCH, Self::MAX_PITCH) - current_pitch;
+        let yaw: Quat = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), yaw);
+        let pitch: Quat = Quat::from_axis_angle(self.right(), pitch);
+        self.orientation = (yaw * pitch * self.orientation).normalized();
+        self.position += self.orientation * movement;
+    }
+
+    // Orbiting around the target: rotates the camera's position by yaw radians around the target's vertical axis and
+    // raises it by pitch radians above the target's horizon, multiplies the distance by zoom, e.g. 0.9 to come closer,
+    // and turns the camera to look at the target.
+    // The pitch stops short of the poles. Does nothing if the camera is at the target.
+    pub fn orbit(&mut self, target: Vec3, yaw: f32, pitch: f32, zoom: f32) {
+        let offset: Vec3 = self.position - target;
+        let distance: f32 = offset.length();
+        if distance <= 0.0 {
+            return;
+        }
+        let current_yaw: f32 = offset.x.atan2(offset.z);
+        let current_pitch: f32 = (offset.y / distance).clamp(-1.0, 1.0).asin();
+        let yaw: f32 = current_yaw + yaw;
+        let pitch: f32 = (current_pitch + pitch).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+        let direction = Vec3::new(yaw.sin() * pitch.cos(), pitch.sin(), yaw.cos() * pitch.cos());
+        self.position = target + direction * (distance * zoom);
+        self.look_at(target, Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    // Takes the position and the orientation from the controller, keeping the projection.
+    pub fn follow(&mut self, controller: &dyn CameraController) {
+        self.position = controller.position();
+        self.orientation = controller.orientation();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_vec3_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn looking_at_target() {
+        let mut camera = Camera { position: Vec3::new(3.0, 4.0, 5.0), ..Default::default() };
+        camera.look_at(Vec3::new(3.0, 4.0, -5.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_vec3_near(camera.forward(), Vec3::new(0.0, 0.0, -1.0));
+        camera.look_at(Vec3::new(-7.0, 4.0, 5.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_vec3_near(camera.forward(), Vec3::new(-1.0, 0.0, 0.0));
+        assert_vec3_near(camera.up(), Vec3::new(0.0, 1.0, 0.0));
+        // The target ends up in the middle of the screen
+        let clip: Vec4 = camera.view_projection() * Vec3::new(-7.0, 4.0, 5.0).as_point4();
+        assert!(clip.x.abs() < 1e-4 && clip.y.abs() < 1e-4 && clip.w > 0.0);
+    }
+
+    #[test]
+    fn flying_turns_and_moves() {
+        let mut camera = Camera::default();
+        camera.fly(std::f32::consts::FRAC_PI_2, 0.0, Vec3::new(0.0, 0.0, -2.0));
+        assert_vec3_near(camera.forward(), Vec3::new(-1.0, 0.0, 0.0));
+        assert_vec3_near(camera.position, Vec3::new(-2.0, 0.0, 0.0));
+        // Looking up stops before the zenith
+        camera.fly(0.0, 10.0, Vec3::new(0.0, 0.0, 0.0));
+        assert!(camera.forward().y > 0.99 && camera.forward().y < 1.0);
+        assert!(camera.right().y.abs() < 1e-4);
+    }
+
+    #[test]
+    fn orbiting_keeps_target_in_front() {
+        let target = Vec3::new(1.0, 0.0, 0.0);
+        let mut camera = Camera { position: Vec3::new(1.0, 0.0, 10.0), ..Default::default() };
+        camera.orbit(target, std::f32::consts::FRAC_PI_2, 0.0, 0.5);
+        assert_vec3_near(camera.position, Vec3::new(6.0, 0.0, 0.0));
+        assert_vec3_near(camera.forward(), Vec3::new(-1.0, 0.0, 0.0));
+        camera.orbit(target, 0.0, std::f32::consts::FRAC_PI_4, 1.0);
+        assert!(((camera.position - target).length() - 5.0).abs() < 1e-4);
+        assert_vec3_near(camera.forward(), (target - camera.position).normalized());
+        assert!(camera.position.y > 3.5);
+    }
+
+    #[test]
+    fn orthographic_projection_covers_height() {
+        let camera = Camera::orthographic(4.0, 2.0, 1.0, 10.0);
+        let clip: Vec4 = camera.view_projection() * Vec3::new(4.0, 2.0, -5.0).as_point4();
+        assert_vec3_near(clip.xyz() / clip.w, Vec3::new(1.0, 1.0, clip.z / clip.w));
+        let frustum: Frustum = camera.frustum();
+        assert!(frustum.contains_point(Vec3::new(3.9, -1.9, -9.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 2.1, -5.0)));
+    }
+}
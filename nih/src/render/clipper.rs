@@ -3,15 +3,73 @@ use super::vertex::Vertex;
 use arrayvec::ArrayVec;
 use std::mem::swap;
 
+const CLIP_PLANES: [Vec4; 6] = [
+    Vec4::new(1.0, 0.0, 0.0, 1.0),  // Left
+    Vec4::new(-1.0, 0.0, 0.0, 1.0), // Right
+    Vec4::new(0.0, 1.0, 0.0, 1.0),  // Bottom
+    Vec4::new(0.0, -1.0, 0.0, 1.0), // Top
+    Vec4::new(0.0, 0.0, 1.0, 1.0),  // Near
+    Vec4::new(0.0, 0.0, -1.0, 1.0), // Far
+];
+
+// How a triangle in clip space relates to the view volume with its sides pushed out to the guard band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuardBandTest {
+    // Entirely outside one of the view volume's planes, there's nothing to draw.
+    Outside,
+
+    // Within the guard band and between the near and the far planes, can be rasterized without clipping.
+    Inside,
+
+    // Within the guard band, but crossing the near or the far plane, only needs clipping against those.
+    // With the depth clamp only the near plane is considered.
+    CrossesDepthRange,
+
+    // Exceeds the guard band, needs clipping against all the planes.
+    ExceedsGuardBand,
+}
+
+// Tests the triangle against the view volume and the guard band, which is given as the extents of the band in NDC,
+// i.e. (1, 1) for the band matching the viewport. The vertices within the band satisfy |x| <= band.x * w and
+// |y| <= band.y * w, so are in front of the camera.
+// With the depth clamp the far plane is ignored, the triangles beyond it are kept and their depth is clamped later.
+pub fn guard_band_test(positions: &[Vec4; 3], band: Vec2, depth_clamp: bool) -> GuardBandTest {
+    for plane in clip_planes(depth_clamp) {
+        if positions.iter().all(|&p| dot(p, *plane) < 0.0) {
+            return GuardBandTest::Outside;
+        }
+    }
+    let in_band = |p: &Vec4| p.x.abs() <= band.x * p.w && p.y.abs() <= band.y * p.w;
+    if !positions.iter().all(in_band) {
+        return GuardBandTest::ExceedsGuardBand;
+    }
+    if positions.iter().all(|p| p.z >= -p.w && (depth_clamp || p.z <= p.w)) {
+        GuardBandTest::Inside
+    } else {
+        GuardBandTest::CrossesDepthRange
+    }
+}
+
 pub fn clip_triangle(input_vertices: &[Vertex; 3]) -> ArrayVec<Vertex, 7> {
-    const CLIP_PLANES: [Vec4; 6] = [
-        Vec4::new(1.0, 0.0, 0.0, 1.0),  // Left
-        Vec4::new(-1.0, 0.0, 0.0, 1.0), // Right
-        Vec4::new(0.0, 1.0, 0.0, 1.0),  // Bottom
-        Vec4::new(0.0, -1.0, 0.0, 1.0), // Top
-        Vec4::new(0.0, 0.0, 1.0, 1.0),  // Near
-        Vec4::new(0.0, 0.0, -1.0, 1.0), // Far
-    ];
+    clip_triangle_against(input_vertices, &CLIP_PLANES)
+}
+
+// Clips the triangle against the view volume, without the far plane if the depth is clamped.
+pub fn clip_triangle_with(input_vertices: &[Vertex; 3], depth_clamp: bool) -> ArrayVec<Vertex, 7> {
+    clip_triangle_against(input_vertices, clip_planes(depth_clamp))
+}
+
+// Clips the triangle against the near and the far planes only, for the triangles within the guard band.
+// Only against the near plane if the depth is clamped.
+pub fn clip_triangle_depth_range(input_vertices: &[Vertex; 3], depth_clamp: bool) -> ArrayVec<Vertex, 7> {
+    clip_triangle_against(input_vertices, &clip_planes(depth_clamp)[4..])
+}
+
+fn clip_planes(depth_clamp: bool) -> &'static [Vec4] {
+    if depth_clamp { &CLIP_PLANES[..5] } else { &CLIP_PLANES }
+}
+
+fn clip_triangle_against(input_vertices: &[Vertex; 3], planes: &[Vec4]) -> ArrayVec<Vertex, 7> {
     let mut buffer_b: [Vertex; 7] = [Vertex::default(); 7];
     let mut buffer_a: [Vertex; 7] = [Vertex::default(); 7];
     buffer_a[..3].clone_from_slice(input_vertices);
@@ -20,7 +78,7 @@ pub fn clip_triangle(input_vertices: &[Vertex; 3]) -> ArrayVec<Vertex, 7> {
 
     let mut in_count = 3;
 
-    for &plane in &CLIP_PLANES {
+    for &plane in planes {
         if in_count == 0 {
             break;
         }
@@ -66,18 +124,12 @@ fn interpolate_vertex(v0: &Vertex, v1: &Vertex, t: f32) -> Vertex {
         tangent: t1 * v0.tangent + t * v1.tangent,
         color: t1 * v0.color + t * v1.color,
         tex_coord: t1 * v0.tex_coord + t * v1.tex_coord,
+        tex_coord2: t1 * v0.tex_coord2 + t * v1.tex_coord2,
+        world_position: t1 * v0.world_position + t * v1.world_position,
     }
 }
 
 pub fn clip_line(input_points: &[Vec4; 2]) -> ArrayVec<Vec4, 2> {
-    const CLIP_PLANES: [Vec4; 6] = [
-        Vec4::new(1.0, 0.0, 0.0, 1.0),  // Left
-        Vec4::new(-1.0, 0.0, 0.0, 1.0), // Right
-        Vec4::new(0.0, 1.0, 0.0, 1.0),  // Bottom
-        Vec4::new(0.0, -1.0, 0.0, 1.0), // Top
-        Vec4::new(0.0, 0.0, 1.0, 1.0),  // Near
-        Vec4::new(0.0, 0.0, -1.0, 1.0), // Far
-    ];
     let mut p0 = input_points[0];
     let mut p1 = input_points[1];
     for &plane in &CLIP_PLANES {
@@ -343,4 +395,88 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_guard_band() {
+        let band: Vec2 = Vec2::new(2.0, 3.0);
+        let test = |a: Vec4, b: Vec4, c: Vec4| guard_band_test(&[a, b, c], band, false);
+        // On the screen
+        assert_eq!(
+            test(Vec4::new(0.0, 0.0, 0.0, 1.0), Vec4::new(0.5, 0.0, 0.0, 1.0), Vec4::new(0.0, 0.5, 0.0, 1.0)),
+            GuardBandTest::Inside
+        );
+        // Crossing the right side, but within the band
+        assert_eq!(
+            test(Vec4::new(0.0, 0.0, 0.0, 1.0), Vec4::new(3.8, 0.0, 0.0, 2.0), Vec4::new(0.0, 2.9, 0.0, 1.0)),
+            GuardBandTest::Inside
+        );
+        // Beyond the band
+        assert_eq!(
+            test(Vec4::new(0.0, 0.0, 0.0, 1.0), Vec4::new(2.1, 0.0, 0.0, 1.0), Vec4::new(0.0, 0.5, 0.0, 1.0)),
+            GuardBandTest::ExceedsGuardBand
+        );
+        // Behind the camera
+        assert_eq!(
+            test(Vec4::new(0.0, 0.0, 0.0, 1.0), Vec4::new(0.0, 0.0, 0.5, -1.0), Vec4::new(0.0, 0.5, 0.0, 1.0)),
+            GuardBandTest::ExceedsGuardBand
+        );
+        // Beyond the far plane
+        assert_eq!(
+            test(Vec4::new(0.0, 0.0, 0.0, 1.0), Vec4::new(0.5, 0.0, 1.5, 1.0), Vec4::new(0.0, 0.5, 0.0, 1.0)),
+            GuardBandTest::CrossesDepthRange
+        );
+        // Beyond the far plane with the depth clamp, it's not clipped against
+        assert_eq!(
+            guard_band_test(
+                &[Vec4::new(0.0, 0.0, 0.0, 1.0), Vec4::new(0.5, 0.0, 1.5, 1.0), Vec4::new(0.0, 0.5, 0.0, 1.0)],
+                band,
+                true
+            ),
+            GuardBandTest::Inside
+        );
+        assert_eq!(
+            guard_band_test(
+                &[Vec4::new(0.0, 0.0, 2.0, 1.0), Vec4::new(0.5, 0.0, 1.5, 1.0), Vec4::new(0.0, 0.5, 3.0, 1.0)],
+                band,
+                true
+            ),
+            GuardBandTest::Inside
+        );
+        // Entirely to the left of the screen, even if within the band
+        assert_eq!(
+            test(Vec4::new(-1.5, 0.0, 0.0, 1.0), Vec4::new(-1.1, 0.0, 0.0, 1.0), Vec4::new(-1.5, 0.5, 0.0, 1.0)),
+            GuardBandTest::Outside
+        );
+    }
+
+    #[test]
+    fn test_clip_triangle_depth_range() {
+        // Crossing the far plane and the right side, only the far plane is clipped
+        let input = [
+            Vertex { position: Vec4::new(0.0, 0.0, 0.0, 1.0), ..Default::default() },
+            Vertex { position: Vec4::new(1.5, 0.0, 0.0, 1.0), ..Default::default() },
+            Vertex { position: Vec4::new(0.0, 0.0, 2.0, 1.0), ..Default::default() },
+        ];
+        let clipped = clip_triangle_depth_range(&input, false);
+        assert_eq!(clipped.len(), 4);
+        assert!(clipped.iter().all(|v| v.position.z <= v.position.w));
+        assert!(clipped.iter().any(|v| v.position.x == 1.5));
+        assert_eq!(clip_triangle(&input).iter().filter(|v| v.position.x > 1.0).count(), 0);
+
+        // With the depth clamp nothing is clipped, but the near plane still is
+        assert_eq!(clip_triangle_depth_range(&input, true).len(), 3);
+        assert_eq!(
+            clip_triangle_with(&input, true)
+                .iter()
+                .filter(|v| v.position.z > 1.0)
+                .count(),
+            1
+        );
+        let behind = [input[0], input[1], Vertex { position: Vec4::new(0.0, 0.0, -2.0, 1.0), ..Default::default() }];
+        assert!(
+            clip_triangle_depth_range(&behind, true)
+                .iter()
+                .all(|v| v.position.z >= -v.position.w)
+        );
+    }
 }
@@ -0,0 +1,125 @@
+use super::super::math::*;
+use super::*;
+use std::sync::Arc;
+
+// Displacement of a grid mesh lying on the XZ plane by a heightmap, e.g. a terrain or a water surface, see
+// RasterizationCommand::displacement and MeshData::grid(). The vertices are moved along +Y by the height sampled from
+// the heightmap at their texture coordinates, so the mesh doesn't need to be rebuilt each time the heightmap changes.
+#[derive(Debug, Clone)]
+pub struct HeightmapDisplacement {
+    // The heights are read from the red channel, i.e. a grayscale texture works as is.
+    pub texture: Arc<Texture>,
+
+    // Nearest gives a blocky terrain, the others interpolate between the texels of the first mip level.
+    // Default: Bilinear.
+    pub filter: SamplerFilter,
+
+    // Default: ClampToEdge.
+    pub address_mode_u: SamplerAddressMode,
+    pub address_mode_v: SamplerAddressMode,
+
+    // The height at the texel value of 0.
+    // Default: 0.
+    pub base: f32,
+
+    // The difference between the heights at the texel values of 255 and 0.
+    // Default: 1.
+    pub scale: f32,
+
+    // Added to the vertices' texture coordinates before sampling, e.g. to scroll the waves over a water surface.
+    // Default: (0, 0).
+    pub tex_coord_offset: Vec2,
+
+    // The size in object space the heightmap's [0, 1] texture coordinates cover along X and Z, e.g. the grid's size.
+    // Defines how steep the slopes are when the normals are recomputed.
+    // Default: (1, 1).
+    pub extent: Vec2,
+
+    // Replaces the vertices' normals with the ones derived from the heightmap's gradients.
+    // Default: true.
+    pub recompute_normals: bool,
+}
+
+impl HeightmapDisplacement {
+    pub fn new(texture: Arc<Texture>) -> Self {
+        Self {
+            texture,
+            filter: SamplerFilter::Bilinear,
+            address_mode_u: SamplerAddressMode::ClampToEdge,
+            address_mode_v: SamplerAddressMode::ClampToEdge,
+            base: 0.0,
+            scale: 1.0,
+            tex_coord_offset: Vec2::new(0.0, 0.0),
+            extent: Vec2::new(1.0, 1.0),
+            recompute_normals: true,
+        }
+    }
+
+    // The sampler of the heightmap's first mip level.
+    pub(crate) fn sampler(&self) -> Sampler {
+        Sampler::new_with_address_modes(&self.texture, self.filter, 0.0, self.address_mode_u, self.address_mode_v)
+    }
+
+    // The height at the vertex's texture coordinates, the offset is added here.
+    pub(crate) fn height(&self, sampler: &Sampler, tex_coord: Vec2) -> f32 {
+        let uv: Vec2 = tex_coord + self.tex_coord_offset;
+        self.base + self.scale * sampler.sample(uv.x, uv.y).r as f32 / 255.0
+    }
+
+    // The normal of the displaced surface from the central differences of the heights one texel apart.
+    pub(crate) fn normal(&self, sampler: &Sampler, tex_coord: Vec2) -> Vec3 {
+        let step: f32 = 1.0 / self.texture.mips[0].width as f32;
+        let du = Vec2::new(step, 0.0);
+        let dv = Vec2::new(0.0, step);
+        let dx: f32 = (self.height(sampler, tex_coord + du) - self.height(sampler, tex_coord - du)) / (2.0 * step);
+        let dz: f32 = (self.height(sampler, tex_coord + dv) - self.height(sampler, tex_coord - dv)) / (2.0 * step);
+        Vec3::new(-dx / self.extent.x, 1.0, -dz / self.extent.y).normalized()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 4x4 heightmap rising from 0 at the left column to 255 at the right one
+    fn ramp() -> Arc<Texture> {
+        let texels: Vec<u8> = (0..16).map(|i| [0, 85, 170, 255][i % 4]).collect();
+        Texture::new(&TextureSource {
+            texels: &texels,
+            width: 4,
+            height: 4,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn heights_are_scaled_and_offset() {
+        let displacement = HeightmapDisplacement {
+            base: -1.0,
+            scale: 3.0,
+            filter: SamplerFilter::Nearest,
+            ..HeightmapDisplacement::new(ramp())
+        };
+        let sampler: Sampler = displacement.sampler();
+        assert_eq!(displacement.height(&sampler, Vec2::new(0.1, 0.5)), -1.0);
+        assert_eq!(displacement.height(&sampler, Vec2::new(0.9, 0.5)), 2.0);
+        let scrolled = HeightmapDisplacement { tex_coord_offset: Vec2::new(0.75, 0.0), ..displacement };
+        assert_eq!(scrolled.height(&sampler, Vec2::new(0.1, 0.5)), 2.0);
+    }
+
+    #[test]
+    fn normals_follow_the_slope() {
+        // The heights rise by 1 between the centers of the first and the last texels, i.e. by 4/3 along X over the unit
+        // extent
+        let displacement = HeightmapDisplacement::new(ramp());
+        let sampler: Sampler = displacement.sampler();
+        let normal: Vec3 = displacement.normal(&sampler, Vec2::new(0.5, 0.5));
+        assert!((normal - Vec3::new(-0.8, 0.6, 0.0)).length() < 0.02, "{:?}", normal);
+
+        // A wider extent makes the slope gentler
+        let wide = HeightmapDisplacement { extent: Vec2::new(100.0, 1.0), ..displacement };
+        let normal: Vec3 = wide.normal(&sampler, Vec2::new(0.5, 0.5));
+        assert!(normal.x < 0.0 && normal.y > 0.99, "{:?}", normal);
+    }
+}
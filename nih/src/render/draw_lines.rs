@@ -63,6 +63,20 @@ fn blend(src: RGBA, dst: RGBA) -> RGBA {
     }
 }
 
+// Writes the line's color into the pixel, blending by alpha if the color is translucent.
+fn write_pixel(dst: &mut u32, rgba: RGBA, color: Vec4, color_format: ColorBufferFormat) {
+    if color_format == ColorBufferFormat::Rgb9e5 {
+        let a = color.w.clamp(0.0, 1.0);
+        let d = decode_rgb9e5(*dst);
+        *dst =
+            encode_rgb9e5(color.x * a + d.x * (1.0 - a), color.y * a + d.y * (1.0 - a), color.z * a + d.z * (1.0 - a));
+    } else if rgba.a == 255 {
+        *dst = rgba.to_u32();
+    } else {
+        *dst = blend(rgba, RGBA::from_u32(*dst)).to_u32();
+    }
+}
+
 pub fn draw_lines(framebuffer: &mut Framebuffer, viewport: &Viewport, command: &DrawLinesCommand) {
     let lines = command.lines;
     let len = lines.len();
@@ -73,6 +87,7 @@ pub fn draw_lines(framebuffer: &mut Framebuffer, viewport: &Viewport, command: &
 
     let view_projection = &command.projection * &command.view;
     let rgba = vec4_to_rgba(command.color);
+    let color_format = framebuffer.color_format;
     let mut color_buf_opt = framebuffer.color_buffer.as_deref_mut();
 
     let mut i = 0;
@@ -165,12 +180,7 @@ pub fn draw_lines(framebuffer: &mut Framebuffer, viewport: &Viewport, command: &
             // }
 
             if let Some(ref mut buf) = color_buf_opt {
-                let dst = buf.at_mut(screen_x as u16, screen_y as u16);
-                if rgba.a == 255 {
-                    *dst = rgba.to_u32();
-                } else {
-                    *dst = blend(rgba, RGBA::from_u32(*dst)).to_u32();
-                }
+                write_pixel(buf.at_mut(screen_x as u16, screen_y as u16), rgba, command.color, color_format);
             }
 
             error -= dy;
@@ -196,6 +206,7 @@ pub fn draw_screen_lines_unclipped(framebuffer: &mut Framebuffer, lines: &[Vec2]
     }
 
     let rgba = vec4_to_rgba(color);
+    let color_format = framebuffer.color_format;
     let color_buf = framebuffer.color_buffer.as_deref_mut().unwrap();
 
     let width = color_buf.width();
@@ -231,12 +242,7 @@ pub fn draw_screen_lines_unclipped(framebuffer: &mut Framebuffer, lines: &[Vec2]
             let screen_y = if steep { x } else { y };
 
             if screen_x >= 0 && screen_x < width as i32 && screen_y >= 0 && screen_y < height as i32 {
-                let dst = color_buf.at_mut(screen_x as u16, screen_y as u16);
-                if rgba.a == 255 {
-                    *dst = rgba.to_u32();
-                } else {
-                    *dst = blend(rgba, RGBA::from_u32(*dst)).to_u32();
-                }
+                write_pixel(color_buf.at_mut(screen_x as u16, screen_y as u16), rgba, color, color_format);
             }
 
             error -= dy;
@@ -285,3 +291,78 @@ pub fn aabb_to_lines(aabb: AABB) -> ArrayVec<Vec3, 24> {
 
     lines
 }
+
+// Debug geometry for draw_lines(): the volumes given by view-projection matrices, in world space.
+// Both the perspective and the orthographic matrices are supported, i.e. the camera's frustum as well as the boxes of
+// the shadow cascades built from their light-space matrices.
+
+// The 12 edges of a box given by its 8 corners: the near face, the far face and the ones connecting them.
+const BOX_EDGES: [(usize, usize); 12] =
+    [(0, 1), (1, 2), (2, 3), (3, 0), (4, 5), (5, 6), (6, 7), (7, 4), (0, 4), (1, 5), (2, 6), (3, 7)];
+
+// The corners of the volume the view-projection maps onto the NDC cube: (-1, -1), (1, -1), (1, 1), (-1, 1) on the near
+// plane, followed by the same ones on the far plane.
+pub fn frustum_corners(view_projection: &Mat44) -> [Vec3; 8] {
+    ndc_slice_corners(&view_projection.inverse(), -1.0, 1.0)
+}
+
+// The corners of the part of the camera's frustum between the view-space distances, e.g. of a shadow cascade's split,
+// in the same order as frustum_corners().
+pub fn frustum_slice_corners(view: &Mat44, projection: &Mat44, near: f32, far: f32) -> [Vec3; 8] {
+    let inverse: Mat44 = (*projection * *view).inverse();
+    ndc_slice_corners(&inverse, distance_to_ndc_z(projection, near), distance_to_ndc_z(projection, far))
+}
+
+// The edges of the volume as the pairs of points expected by DrawLinesCommand::lines.
+pub fn frustum_lines(view_projection: &Mat44) -> [Vec3; 24] {
+    box_lines(&frustum_corners(view_projection))
+}
+
+// The camera's frustum with a rectangle across it at each of the view-space distances, e.g. at the cascade splits.
+// The lines are appended.
+pub fn frustum_split_lines(view: &Mat44, projection: &Mat44, splits: &[f32], lines: &mut Vec<Vec3>) {
+    let inverse: Mat44 = (*projection * *view).inverse();
+    let corners: [Vec3; 8] = ndc_slice_corners(&inverse, -1.0, 1.0);
+    lines.extend_from_slice(&box_lines(&corners));
+    for &split in splits {
+        let z: f32 = distance_to_ndc_z(projection, split);
+        let rectangle: [Vec3; 8] = ndc_slice_corners(&inverse, z, z);
+        for &(a, b) in &BOX_EDGES[..4] {
+            lines.push(rectangle[a]);
+            lines.push(rectangle[b]);
+        }
+    }
+}
+
+// The boxes of the shadow cascades given by their view-projection matrices. The lines are appended.
+pub fn cascade_lines(cascades: &[Mat44], lines: &mut Vec<Vec3>) {
+    for cascade in cascades {
+        lines.extend_from_slice(&frustum_lines(cascade));
+    }
+}
+
+fn box_lines(corners: &[Vec3; 8]) -> [Vec3; 24] {
+    let mut lines: [Vec3; 24] = [Vec3::new(0.0, 0.0, 0.0); 24];
+    for (i, &(a, b)) in BOX_EDGES.iter().enumerate() {
+        lines[i * 2] = corners[a];
+        lines[i * 2 + 1] = corners[b];
+    }
+    lines
+}
+
+fn ndc_slice_corners(inverse_view_projection: &Mat44, near_z: f32, far_z: f32) -> [Vec3; 8] {
+    let xy: [(f32, f32); 4] = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+    let mut corners: [Vec3; 8] = [Vec3::new(0.0, 0.0, 0.0); 8];
+    for (i, corner) in corners.iter_mut().enumerate() {
+        let (x, y) = xy[i % 4];
+        let z: f32 = if i < 4 { near_z } else { far_z };
+        *corner = perspective_divide_to_vec3(*inverse_view_projection * Vec4::new(x, y, z, 1.0));
+    }
+    corners
+}
+
+// NDC Z of a point at the distance in front of the camera, i.e. at view-space Z = -distance.
+fn distance_to_ndc_z(projection: &Mat44, distance: f32) -> f32 {
+    let projected: Vec4 = *projection * Vec4::new(0.0, 0.0, -distance, 1.0);
+    projected.z / projected.w
+}
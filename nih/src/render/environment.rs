@@ -0,0 +1,505 @@
+use super::super::math::*;
+use super::*;
+use std::sync::Arc;
+
+// The faces of a cube map, in the order of CubeMap::faces.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubeFace {
+    PositiveX = 0,
+    NegativeX = 1,
+    PositiveY = 2,
+    NegativeY = 3,
+    PositiveZ = 4,
+    NegativeZ = 5,
+}
+
+impl CubeFace {
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PositiveX,
+        CubeFace::NegativeX,
+        CubeFace::PositiveY,
+        CubeFace::NegativeY,
+        CubeFace::PositiveZ,
+        CubeFace::NegativeZ,
+    ];
+
+    // The face's right and up axes and the direction from the center of the cube towards the face, see CubeMap.
+    pub fn axes(self) -> (Vec3, Vec3, Vec3) {
+        match self {
+            CubeFace::PositiveX => (Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+            CubeFace::NegativeX => (Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)),
+            CubeFace::PositiveY => (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 1.0, 0.0)),
+            CubeFace::NegativeY => (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, -1.0, 0.0)),
+            CubeFace::PositiveZ => (Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)),
+            CubeFace::NegativeZ => (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, -1.0)),
+        }
+    }
+
+    // The world-to-camera transform of a camera at the position looking at the face, with the face's up being the
+    // camera's up. Combined with a square 90-degree perspective projection, it draws exactly the face, upside down
+    // relative to the texture, as the v axis points up.
+    pub fn view(self, position: Vec3) -> Mat44 {
+        let (right, up, forward): (Vec3, Vec3, Vec3) = self.axes();
+        Mat44([
+            right.x,
+            right.y,
+            right.z,
+            -dot(right, position), //
+            up.x,
+            up.y,
+            up.z,
+            -dot(up, position), //
+            -forward.x,
+            -forward.y,
+            -forward.z,
+            dot(forward, position), //
+            0.0,
+            0.0,
+            0.0,
+            1.0,
+        ])
+    }
+}
+
+// The surroundings seen from a point as six square textures, one per face of a cube around it.
+// Each face is seen from the center of the cube with u pointing right and v pointing up. The side faces are upright,
+// i.e. their up is +Y, the top face's up is +Z and the bottom face's up is -Z, so that both adjoin the -Z face like in
+// an unfolded cross.
+#[derive(Debug, Clone)]
+pub struct CubeMap {
+    pub faces: [Arc<Texture>; 6],
+}
+
+impl CubeMap {
+    pub fn new(faces: [Arc<Texture>; 6]) -> Self {
+        Self { faces }
+    }
+
+    pub fn face(&self, face: CubeFace) -> &Arc<Texture> {
+        &self.faces[face as usize]
+    }
+
+    // The face the direction points at and the texture coordinates of the point on that face.
+    pub fn project(direction: Vec3) -> (CubeFace, Vec2) {
+        let (ax, ay, az): (f32, f32, f32) = (direction.x.abs(), direction.y.abs(), direction.z.abs());
+        // The face, the coordinates along its right and up axes, and the major axis' magnitude
+        let (face, right, up, major): (CubeFace, f32, f32, f32) = if ax >= ay && ax >= az {
+            if direction.x > 0.0 {
+                (CubeFace::PositiveX, direction.z, direction.y, ax)
+            } else {
+                (CubeFace::NegativeX, -direction.z, direction.y, ax)
+            }
+        } else if ay >= az {
+            if direction.y > 0.0 {
+                (CubeFace::PositiveY, direction.x, direction.z, ay)
+            } else {
+                (CubeFace::NegativeY, direction.x, -direction.z, ay)
+            }
+        } else if direction.z > 0.0 {
+            (CubeFace::PositiveZ, -direction.x, direction.y, az)
+        } else {
+            (CubeFace::NegativeZ, direction.x, direction.y, az)
+        };
+        if major <= f32::EPSILON {
+            return (CubeFace::NegativeZ, Vec2::new(0.5, 0.5));
+        }
+        let inv_major: f32 = 0.5 / major;
+        (face, Vec2::new(right * inv_major + 0.5, up * inv_major + 0.5))
+    }
+
+    // The direction towards the point with the texture coordinates on the face, the inverse of project(). Not normalized.
+    pub fn direction(face: CubeFace, uv: Vec2) -> Vec3 {
+        let (right, up, forward): (Vec3, Vec3, Vec3) = face.axes();
+        right * (uv.x * 2.0 - 1.0) + up * (uv.y * 2.0 - 1.0) + forward
+    }
+}
+
+// Parameters of capturing the surroundings into an EnvironmentProbe, see RenderWorld::bake_probe().
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProbeBaking {
+    // The side of the captured faces in pixels, must be a power of two.
+    // Default: 32.
+    pub size: u16,
+
+    // The number of the prefiltered levels, from the sharp capture to the roughness of 1.0. Clamped to [1, log2(size)+1].
+    // Default: 5.
+    pub levels: u32,
+
+    // The number of the GGX samples per texel of the prefiltered levels.
+    // Default: 64.
+    pub samples: u32,
+
+    // The range of distances from the probe the geometry is captured within.
+    // Default: 0.05.
+    pub near: f32,
+
+    // Default: 100.0.
+    pub far: f32,
+}
+
+impl Default for ProbeBaking {
+    fn default() -> Self {
+        Self { size: 32, levels: 5, samples: 64, near: 0.05, far: 100.0 }
+    }
+}
+
+// The surroundings captured at a point and prefiltered for the surfaces of different roughness, the image-based
+// counterpart of the ambient light. Level 0 is the sharp capture, the next levels are blurred by the GGX distribution
+// of the increasing roughness, up to 1.0 at the last level, and each one is half the size of the previous one.
+#[derive(Debug, Clone)]
+pub struct EnvironmentProbe {
+    pub position: Vec3,
+    pub levels: Vec<Arc<CubeMap>>,
+}
+
+impl EnvironmentProbe {
+    // Builds the probe from the captured faces, the level 0, by prefiltering the rest of the levels.
+    // The levels are computed in parallel.
+    pub fn prefilter(position: Vec3, faces: [Arc<Texture>; 6], parameters: &ProbeBaking) -> Self {
+        let size: u32 = faces[0].mips[0].width as u32;
+        let count: u32 = parameters.levels.clamp(1, size.max(1).ilog2() + 1);
+        let source: Arc<CubeMap> = Arc::new(CubeMap::new(faces));
+        let environment: EnvironmentMap = EnvironmentMap::Cube(source.clone());
+        let samples: u32 = parameters.samples.max(1);
+        let mut levels: Vec<Arc<CubeMap>> = vec![source];
+        use rayon::prelude::*;
+        let prefiltered: Vec<Arc<CubeMap>> = (1..count)
+            .into_par_iter()
+            .map(|level| {
+                let roughness: f32 = level as f32 / (count - 1) as f32;
+                let level_size: u32 = (size >> level).max(1);
+                let sampler = EnvironmentSampler::new(&environment);
+                let faces: [Arc<Texture>; 6] = std::array::from_fn(|face| {
+                    let mut texels: Vec<u8> = Vec::with_capacity((level_size * level_size * 4) as usize);
+                    for y in 0..level_size {
+                        for x in 0..level_size {
+                            let uv: Vec2 =
+                                Vec2::new((x as f32 + 0.5) / level_size as f32, (y as f32 + 0.5) / level_size as f32);
+                            let normal: Vec3 = CubeMap::direction(CubeFace::ALL[face], uv).normalized();
+                            let color: Vec3 = prefilter_ggx(&sampler, normal, roughness, samples);
+                            let quantize = |c: f32| -> u8 { (c * 255.0 + 0.5).clamp(0.0, 255.0) as u8 };
+                            texels.extend_from_slice(&[quantize(color.x), quantize(color.y), quantize(color.z), 255]);
+                        }
+                    }
+                    Texture::new(&TextureSource {
+                        texels: &texels,
+                        width: level_size,
+                        height: level_size,
+                        format: TextureFormat::RGBA,
+                        ..Default::default()
+                    })
+                });
+                Arc::new(CubeMap::new(faces))
+            })
+            .collect();
+        levels.extend(prefiltered);
+        Self { position, levels }
+    }
+
+    // The roughness the level was prefiltered for.
+    pub fn roughness(&self, level: usize) -> f32 {
+        if self.levels.len() <= 1 {
+            0.0
+        } else {
+            level as f32 / (self.levels.len() - 1) as f32
+        }
+    }
+
+    // The level closest to the roughness, e.g. for the reflections of a surface via EnvironmentMap::Cube.
+    pub fn level(&self, roughness: f32) -> &Arc<CubeMap> {
+        let index: f32 = roughness.clamp(0.0, 1.0) * (self.levels.len() - 1) as f32;
+        &self.levels[(index + 0.5) as usize]
+    }
+
+    // The color of the surroundings in the direction as seen by a surface of the roughness, in [0, 1], interpolated
+    // between the two closest levels. Sets up the samplers on each call, so is meant for the occasional queries.
+    pub fn sample(&self, direction: Vec3, roughness: f32) -> Vec3 {
+        let index: f32 = roughness.clamp(0.0, 1.0) * (self.levels.len() - 1) as f32;
+        let (lower, t): (usize, f32) = (index as usize, index.fract());
+        let sample_level = |level: usize| -> Vec3 {
+            EnvironmentSampler::new(&EnvironmentMap::Cube(self.levels[level].clone())).sample(direction)
+        };
+        if t <= 0.0 || lower + 1 >= self.levels.len() {
+            sample_level(lower)
+        } else {
+            lerp(sample_level(lower), sample_level(lower + 1), t)
+        }
+    }
+}
+
+// The surroundings convolved with the GGX distribution of the roughness around the normal, assuming the view direction
+// along the normal. The samples are importance-sampled from the Hammersley sequence.
+fn prefilter_ggx(sampler: &EnvironmentSampler, normal: Vec3, roughness: f32, samples: u32) -> Vec3 {
+    let a: f32 = roughness * roughness;
+    let helper: Vec3 = if normal.z.abs() < 0.999 {
+        Vec3::new(0.0, 0.0, 1.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let tangent: Vec3 = cross(helper, normal).normalized();
+    let bitangent: Vec3 = cross(normal, tangent);
+    let mut sum: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+    let mut total_weight: f32 = 0.0;
+    for i in 0..samples {
+        let (u, v): (f32, f32) = (i as f32 / samples as f32, (i.reverse_bits() as f64 / 4294967296.0) as f32);
+        let phi: f32 = 2.0 * std::f32::consts::PI * u;
+        let cos_theta: f32 = ((1.0 - v) / (1.0 + (a * a - 1.0) * v)).sqrt();
+        let sin_theta: f32 = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let half: Vec3 = tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + normal * cos_theta;
+        let light: Vec3 = half * (2.0 * dot(normal, half)) - normal;
+        let n_dot_l: f32 = dot(normal, light);
+        if n_dot_l > 0.0 {
+            sum += sampler.sample(light) * n_dot_l;
+            total_weight += n_dot_l;
+        }
+    }
+    if total_weight > 0.0 {
+        sum / total_weight
+    } else {
+        sampler.sample(normal)
+    }
+}
+
+// The surroundings reflected by the shiny surfaces, given in world space.
+#[derive(Debug, Clone)]
+pub enum EnvironmentMap {
+    // A picture of a mirrored ball taken along -Z, i.e. the direction pointing back at the camera, +Z, is reflected at
+    // the center of the texture and -Z is smeared along its rim.
+    Sphere(Arc<Texture>),
+
+    Cube(Arc<CubeMap>),
+}
+
+impl EnvironmentMap {
+    // The texture coordinates of the direction in a sphere map.
+    pub fn sphere_uv(direction: Vec3) -> Vec2 {
+        let d: Vec3 = direction.normalized();
+        let m: f32 = 2.0 * (d.x * d.x + d.y * d.y + (d.z + 1.0) * (d.z + 1.0)).sqrt();
+        if m <= f32::EPSILON {
+            return Vec2::new(0.5, 1.0);
+        }
+        Vec2::new(d.x / m + 0.5, d.y / m + 0.5)
+    }
+}
+
+impl PartialEq for EnvironmentMap {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (EnvironmentMap::Sphere(a), EnvironmentMap::Sphere(b)) => Arc::ptr_eq(a, b),
+            (EnvironmentMap::Cube(a), EnvironmentMap::Cube(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+// Reflections of the environment mixed into the fragments' colors before the tint.
+// The reflected direction is calculated per fragment from the interpolated world position and normal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reflection {
+    pub environment: EnvironmentMap,
+
+    // The fraction of the color replaced by the reflected environment, in [0, 1].
+    pub reflectivity: f32,
+
+    // The position of the camera in world space, the fragments are seen from it.
+    pub camera_position: Vec3,
+}
+
+impl Reflection {
+    pub fn new(environment: EnvironmentMap, reflectivity: f32, camera_position: Vec3) -> Self {
+        Self { environment, reflectivity, camera_position }
+    }
+
+    // The direction the camera's ray towards the position is reflected in by a surface with the normal.
+    pub fn reflect(&self, position: Vec3, normal: Vec3) -> Vec3 {
+        let incident: Vec3 = position - self.camera_position;
+        incident - normal * (2.0 * dot(incident, normal))
+    }
+
+    // Mixes the reflected environment into the color, which is premultiplied by its alpha or not.
+    #[inline(always)]
+    pub(crate) fn apply(&self, color: Vec4, environment: Vec3, premultiplied: bool) -> Vec4 {
+        let k: f32 = self.reflectivity;
+        let environment: Vec3 = if premultiplied {
+            environment * (color.w * k)
+        } else {
+            environment * k
+        };
+        Vec4::new(
+            color.x * (1.0 - k) + environment.x,
+            color.y * (1.0 - k) + environment.y,
+            color.z * (1.0 - k) + environment.z,
+            color.w,
+        )
+    }
+
+    // Same as apply(), but for the 8-bit color components.
+    #[inline(always)]
+    pub(crate) fn apply_rgba8(
+        &self,
+        r: u8,
+        g: u8,
+        b: u8,
+        a: u8,
+        environment: Vec3,
+        premultiplied: bool,
+    ) -> (u8, u8, u8, u8) {
+        let color: Vec4 = Vec4::new(r as f32, g as f32, b as f32, a as f32) * (1.0 / 255.0);
+        let reflected: Vec4 = self.apply(color, environment, premultiplied);
+        let quantize = |c: f32| -> u8 { (c * 255.0 + 0.5).clamp(0.0, 255.0) as u8 };
+        (quantize(reflected.x), quantize(reflected.y), quantize(reflected.z), a)
+    }
+}
+
+// Samples the environment map bilinearly from its top mip level, as the reflections have no derivatives to pick one.
+pub(crate) struct EnvironmentSampler {
+    samplers: [Sampler; 6],
+    cube: bool,
+}
+
+impl EnvironmentSampler {
+    pub(crate) fn new(environment: &EnvironmentMap) -> Self {
+        let sampler = |texture: &Arc<Texture>| -> Sampler {
+            Sampler::new_with_address_modes(
+                texture,
+                SamplerFilter::Bilinear,
+                0.0,
+                SamplerAddressMode::ClampToEdge,
+                SamplerAddressMode::ClampToEdge,
+            )
+        };
+        match environment {
+            EnvironmentMap::Sphere(texture) => Self {
+                samplers: std::array::from_fn(|i| if i == 0 { sampler(texture) } else { Sampler::default() }),
+                cube: false,
+            },
+            EnvironmentMap::Cube(cube_map) => {
+                Self { samplers: std::array::from_fn(|i| sampler(&cube_map.faces[i])), cube: true }
+            }
+        }
+    }
+
+    // The color of the environment in the direction, in [0, 1].
+    #[inline(always)]
+    pub(crate) fn sample(&self, direction: Vec3) -> Vec3 {
+        let (sampler, uv): (&Sampler, Vec2) = if self.cube {
+            let (face, uv) = CubeMap::project(direction);
+            (&self.samplers[face as usize], uv)
+        } else {
+            (&self.samplers[0], EnvironmentMap::sphere_uv(direction))
+        };
+        let texel: RGBA = sampler.sample(uv.x, uv.y);
+        Vec3::new(texel.r as f32, texel.g as f32, texel.b as f32) * (1.0 / 255.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cube_map_projection() {
+        let (face, uv) = CubeMap::project(Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(face, CubeFace::NegativeZ);
+        assert_eq!(uv, Vec2::new(0.5, 0.5));
+
+        // Right and up on the front face
+        let (face, uv) = CubeMap::project(Vec3::new(0.5, 0.5, -1.0));
+        assert_eq!(face, CubeFace::NegativeZ);
+        assert_eq!(uv, Vec2::new(0.75, 0.75));
+
+        // The right face continues the front one to the right
+        let (face, uv) = CubeMap::project(Vec3::new(1.0, 0.0, -0.99));
+        assert_eq!(face, CubeFace::PositiveX);
+        assert!(uv.x < 0.01);
+
+        // The top face adjoins the front one with its bottom edge
+        let (face, uv) = CubeMap::project(Vec3::new(0.0, 1.0, -0.99));
+        assert_eq!(face, CubeFace::PositiveY);
+        assert!(uv.y < 0.01);
+
+        // The bottom face adjoins the front one with its top edge
+        let (face, uv) = CubeMap::project(Vec3::new(0.0, -1.0, -0.99));
+        assert_eq!(face, CubeFace::NegativeY);
+        assert!(uv.y > 0.99);
+
+        let (face, _) = CubeMap::project(Vec3::new(0.1, 0.0, 1.0));
+        assert_eq!(face, CubeFace::PositiveZ);
+        let (face, _) = CubeMap::project(Vec3::new(-1.0, 0.2, 0.1));
+        assert_eq!(face, CubeFace::NegativeX);
+    }
+
+    #[test]
+    fn cube_map_directions_invert_projection() {
+        for face in CubeFace::ALL {
+            for uv in [Vec2::new(0.5, 0.5), Vec2::new(0.25, 0.75), Vec2::new(0.9, 0.1)] {
+                let (projected_face, projected_uv) = CubeMap::project(CubeMap::direction(face, uv));
+                assert_eq!(projected_face, face);
+                assert!((projected_uv.x - uv.x).abs() < 1e-6 && (projected_uv.y - uv.y).abs() < 1e-6);
+            }
+            // The face's camera looks at the face's center with the face's right and up
+            let (right, up, forward) = face.axes();
+            let position: Vec3 = Vec3::new(1.0, 2.0, 3.0);
+            let view: Mat44 = face.view(position);
+            assert_eq!((view * (position + forward).as_point4()).xyz(), Vec3::new(0.0, 0.0, -1.0));
+            assert_eq!((view * (position + right).as_point4()).xyz(), Vec3::new(1.0, 0.0, 0.0));
+            assert_eq!((view * (position + up).as_point4()).xyz(), Vec3::new(0.0, 1.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn probe_levels_are_prefiltered() {
+        let texels: Vec<u8> = [200u8, 100, 50, 255].repeat(64);
+        let face = || {
+            Texture::new(&TextureSource {
+                texels: &texels,
+                width: 8,
+                height: 8,
+                format: TextureFormat::RGBA,
+                ..Default::default()
+            })
+        };
+        let probe = EnvironmentProbe::prefilter(
+            Vec3::new(0.0, 0.0, 0.0),
+            std::array::from_fn(|_| face()),
+            &ProbeBaking { levels: 10, samples: 16, ..Default::default() },
+        );
+        // Limited by the size of the faces
+        assert_eq!(probe.levels.len(), 4);
+        assert_eq!(probe.levels[3].faces[0].mips[0].width, 1);
+        assert_eq!(probe.roughness(3), 1.0);
+        assert!(Arc::ptr_eq(probe.level(0.9), &probe.levels[3]));
+        for roughness in [0.0, 0.4, 1.0] {
+            let color: Vec3 = probe.sample(Vec3::new(0.3, -1.0, 0.2), roughness);
+            assert!((color.x - 200.0 / 255.0).abs() < 0.01 && (color.z - 50.0 / 255.0).abs() < 0.01, "{:?}", color);
+        }
+    }
+
+    #[test]
+    fn sphere_map_coordinates() {
+        assert_eq!(EnvironmentMap::sphere_uv(Vec3::new(0.0, 0.0, 1.0)), Vec2::new(0.5, 0.5));
+        // Perpendicular to the view, half-way to the rim
+        let uv: Vec2 = EnvironmentMap::sphere_uv(Vec3::new(1.0, 0.0, 0.0));
+        assert!((uv.x - (0.5 + 0.5 / 2.0f32.sqrt())).abs() < 1e-6);
+        let uv: Vec2 = EnvironmentMap::sphere_uv(Vec3::new(0.0, 0.1, -1.0));
+        assert!(uv.y > 0.99);
+    }
+
+    #[test]
+    fn reflected_direction() {
+        let texels: [u8; 1] = [0];
+        let texture: Arc<Texture> = Texture::new(&TextureSource {
+            texels: &texels,
+            width: 1,
+            height: 1,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        });
+        let reflection = Reflection::new(EnvironmentMap::Sphere(texture), 1.0, Vec3::new(0.0, 0.0, 5.0));
+        let direction: Vec3 = reflection.reflect(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(direction, Vec3::new(0.0, 0.0, 5.0));
+        let direction: Vec3 = reflection.reflect(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(direction, Vec3::new(-5.0, 0.0, 5.0));
+    }
+}
@@ -0,0 +1,336 @@
+use super::super::math::*;
+
+// Paint of a screen-space fill, all positions are in pixels relative to the viewport's top-left corner.
+// Colors are not premultiplied by alpha.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FillPaint {
+    // A uniform color.
+    Solid(Vec4),
+
+    // Colors change linearly from `from` to `to` along the line between them and stay constant beyond its ends.
+    LinearGradient {
+        from: Vec2,
+        to: Vec2,
+        color_from: Vec4,
+        color_to: Vec4,
+    },
+
+    // Colors change linearly with the distance from the center and stay constant beyond the radius.
+    RadialGradient {
+        center: Vec2,
+        radius: f32,
+        color_inner: Vec4,
+        color_outer: Vec4,
+    },
+}
+
+// A screen-space fill of an axis-aligned rectangle with optionally rounded corners and a border, e.g. a UI panel.
+// The edges are anti-aliased: partially covered pixels are blended with the framebuffer regardless of the alpha
+// blending mode. Bypasses the geometry processing, the depth and normal buffers are not affected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FillCommand {
+    // The rectangle's corners in pixels, relative to the viewport's top-left corner.
+    pub min: Vec2,
+    pub max: Vec2,
+
+    // The radius of the corners in pixels, clamped to half of the rectangle's smaller side.
+    // Default: 0.0, i.e. sharp corners.
+    pub corner_radius: f32,
+
+    // The width of the border in pixels, drawn inside the rectangle.
+    // Default: 0.0, i.e. no border.
+    pub border_width: f32,
+
+    // The color of the border, not premultiplied by alpha.
+    // Default: (1, 1, 1, 1).
+    pub border_color: Vec4,
+
+    // The paint of the area inside the border.
+    // Default: solid white.
+    pub paint: FillPaint,
+
+    // Sets whether the fill should be alpha-blended with the framebuffer, the same way as RasterizationCommand does.
+    // Default: None.
+    pub alpha_blending: super::AlphaBlendingMode,
+}
+
+impl Default for FillCommand {
+    fn default() -> Self {
+        Self {
+            min: Vec2::new(0.0, 0.0),
+            max: Vec2::new(0.0, 0.0),
+            corner_radius: 0.0,
+            border_width: 0.0,
+            border_color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            paint: FillPaint::Solid(Vec4::new(1.0, 1.0, 1.0, 1.0)),
+            alpha_blending: super::AlphaBlendingMode::None,
+        }
+    }
+}
+
+// A screen-space fill of a convex polygon, e.g. a minimap shape or a selection marquee, which doesn't have to be
+// triangulated by the caller. The points can be in either winding order. Concave polygons are not supported: those are
+// filled as the intersection of their edges' half-planes. Bypasses the geometry processing, the depth and normal buffers
+// are not affected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PolygonFillCommand<'a> {
+    // The polygon's vertices in pixels, relative to the viewport's top-left corner.
+    pub points: &'a [Vec2],
+
+    // The fill color, not premultiplied by alpha.
+    // Default: (1, 1, 1, 1).
+    pub color: Vec4,
+
+    // Sets whether the edges should be anti-aliased: partially covered pixels are blended with the framebuffer by
+    // their coverage regardless of the alpha blending mode. Otherwise, pixels are filled if their centers are inside.
+    // Default: true.
+    pub antialiased: bool,
+
+    // Sets whether the fill should be alpha-blended with the framebuffer, the same way as RasterizationCommand does.
+    // Default: None.
+    pub alpha_blending: super::AlphaBlendingMode,
+}
+
+impl Default for PolygonFillCommand<'_> {
+    fn default() -> Self {
+        Self {
+            points: &[],
+            color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            antialiased: true,
+            alpha_blending: super::AlphaBlendingMode::None,
+        }
+    }
+}
+
+impl PolygonFillCommand<'_> {
+    // Appends the polygon's edges as the lines (a, b, c) with a * x + b * y + c being the signed distance to the edge,
+    // positive inside. Returns the number of edges appended, zero if the polygon is degenerate.
+    pub fn append_edges(&self, edges: &mut Vec<Vec3>) -> usize {
+        let points: &[Vec2] = self.points;
+        if points.len() < 3 {
+            return 0;
+        }
+
+        // The sign of the area defines the winding order, i.e. on which side of the edges the inside is
+        let mut area_x_2: f32 = 0.0;
+        for i in 0..points.len() {
+            let p0: Vec2 = points[i];
+            let p1: Vec2 = points[(i + 1) % points.len()];
+            area_x_2 += p0.x * p1.y - p1.x * p0.y;
+        }
+        if area_x_2.abs() < 0.0001 {
+            return 0;
+        }
+        let orientation: f32 = area_x_2.signum();
+
+        let edges_start: usize = edges.len();
+        for i in 0..points.len() {
+            let p0: Vec2 = points[i];
+            let p1: Vec2 = points[(i + 1) % points.len()];
+            let d: Vec2 = p1 - p0;
+            let length: f32 = d.length();
+            if length <= 0.0 {
+                continue; // skip the coincident points
+            }
+            let a: f32 = -d.y * orientation / length;
+            let b: f32 = d.x * orientation / length;
+            edges.push(Vec3::new(a, b, -(a * p0.x + b * p0.y)));
+        }
+        edges.len() - edges_start
+    }
+
+    // The bounding box of the polygon's points.
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        let mut min: Vec2 = Vec2::new(f32::MAX, f32::MAX);
+        let mut max: Vec2 = Vec2::new(f32::MIN, f32::MIN);
+        for p in self.points {
+            min = Vec2::new(min.x.min(p.x), min.y.min(p.y));
+            max = Vec2::new(max.x.max(p.x), max.y.max(p.y));
+        }
+        (min, max)
+    }
+}
+
+// Signed distance from the point to the convex polygon defined by its edges: positive inside, negative outside.
+// Exact near the edges, which is what the anti-aliasing needs, and underestimated near the vertices outside.
+#[inline(always)]
+pub fn polygon_distance(edges: &[Vec3], p: Vec2) -> f32 {
+    let mut distance: f32 = f32::MAX;
+    for edge in edges {
+        distance = distance.min(edge.x * p.x + edge.y * p.y + edge.z);
+    }
+    distance
+}
+
+impl FillPaint {
+    // Evaluates the paint at the point.
+    #[inline(always)]
+    pub fn at(&self, p: Vec2) -> Vec4 {
+        match *self {
+            FillPaint::Solid(color) => color,
+            FillPaint::LinearGradient { from, to, color_from, color_to } => {
+                let axis: Vec2 = to - from;
+                let length_squared: f32 = dot(axis, axis);
+                let t: f32 = if length_squared > 0.0 {
+                    (dot(p - from, axis) / length_squared).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                color_from + (color_to - color_from) * t
+            }
+            FillPaint::RadialGradient { center, radius, color_inner, color_outer } => {
+                let t: f32 = if radius > 0.0 {
+                    ((p - center).length() / radius).min(1.0)
+                } else {
+                    1.0
+                };
+                color_inner + (color_outer - color_inner) * t
+            }
+        }
+    }
+
+    // Returns the color if the paint is the same everywhere.
+    pub fn uniform_color(&self) -> Option<Vec4> {
+        match *self {
+            FillPaint::Solid(color) => Some(color),
+            _ => None,
+        }
+    }
+}
+
+impl FillCommand {
+    // The corner radius actually used for the rectangle.
+    pub fn effective_corner_radius(&self) -> f32 {
+        let half: Vec2 = (self.max - self.min) * 0.5;
+        self.corner_radius.clamp(0.0, half.x.min(half.y))
+    }
+
+    // Signed distance from the point to the rectangle's outline: negative inside, positive outside.
+    #[inline(always)]
+    pub fn distance(&self, p: Vec2, corner_radius: f32) -> f32 {
+        let center: Vec2 = (self.min + self.max) * 0.5;
+        let half: Vec2 = (self.max - self.min) * 0.5;
+        let qx: f32 = (p.x - center.x).abs() - half.x + corner_radius;
+        let qy: f32 = (p.y - center.y).abs() - half.y + corner_radius;
+        let outside: f32 = Vec2::new(qx.max(0.0), qy.max(0.0)).length();
+        let inside: f32 = qx.max(qy).min(0.0);
+        outside + inside - corner_radius
+    }
+
+    // Evaluates the fill at the pixel's center: returns the color and the pixel's coverage in [0, 1].
+    // The coverage is approximated by the signed distance to the outline, which is exact for the axis-aligned edges.
+    #[inline(always)]
+    pub fn shade(&self, p: Vec2, corner_radius: f32) -> (Vec4, f32) {
+        let d: f32 = self.distance(p, corner_radius);
+        let coverage: f32 = (0.5 - d).clamp(0.0, 1.0);
+        if self.border_width <= 0.0 {
+            return (self.paint.at(p), coverage);
+        }
+        let inner: f32 = (0.5 - (d + self.border_width)).clamp(0.0, 1.0);
+        if inner <= 0.0 {
+            (self.border_color, coverage)
+        } else {
+            let paint: Vec4 = self.paint.at(p);
+            (self.border_color + (paint - self.border_color) * inner, coverage)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_to_rounded_rect() {
+        let fill = FillCommand {
+            min: Vec2::new(10.0, 20.0),
+            max: Vec2::new(50.0, 40.0),
+            corner_radius: 5.0,
+            ..Default::default()
+        };
+        let r: f32 = fill.effective_corner_radius();
+        assert_eq!(fill.distance(Vec2::new(30.0, 30.0), r), -10.0);
+        assert_eq!(fill.distance(Vec2::new(8.0, 30.0), r), 2.0);
+        assert_eq!(fill.distance(Vec2::new(30.0, 41.0), r), 1.0);
+        // The corner is rounded: the corner point of the rectangle itself is outside
+        let corner: f32 = fill.distance(Vec2::new(10.0, 20.0), r);
+        assert!((corner - (50.0f32.sqrt() - 5.0)).abs() < 0.0001);
+    }
+
+    #[test]
+    fn corner_radius_is_clamped() {
+        let fill = FillCommand {
+            min: Vec2::new(0.0, 0.0),
+            max: Vec2::new(40.0, 10.0),
+            corner_radius: 100.0,
+            ..Default::default()
+        };
+        assert_eq!(fill.effective_corner_radius(), 5.0);
+    }
+
+    #[test]
+    fn linear_gradient() {
+        let paint = FillPaint::LinearGradient {
+            from: Vec2::new(10.0, 0.0),
+            to: Vec2::new(20.0, 0.0),
+            color_from: Vec4::new(0.0, 0.0, 0.0, 1.0),
+            color_to: Vec4::new(1.0, 0.5, 0.0, 1.0),
+        };
+        assert_eq!(paint.at(Vec2::new(0.0, 7.0)), Vec4::new(0.0, 0.0, 0.0, 1.0));
+        assert_eq!(paint.at(Vec2::new(15.0, 3.0)), Vec4::new(0.5, 0.25, 0.0, 1.0));
+        assert_eq!(paint.at(Vec2::new(30.0, 0.0)), Vec4::new(1.0, 0.5, 0.0, 1.0));
+    }
+
+    #[test]
+    fn radial_gradient() {
+        let paint = FillPaint::RadialGradient {
+            center: Vec2::new(10.0, 10.0),
+            radius: 4.0,
+            color_inner: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            color_outer: Vec4::new(0.0, 0.0, 0.0, 0.0),
+        };
+        assert_eq!(paint.at(Vec2::new(10.0, 10.0)), Vec4::new(1.0, 1.0, 1.0, 1.0));
+        assert_eq!(paint.at(Vec2::new(10.0, 12.0)), Vec4::new(0.5, 0.5, 0.5, 0.5));
+        assert_eq!(paint.at(Vec2::new(0.0, 0.0)), Vec4::new(0.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn border_is_blended_into_the_paint() {
+        let fill = FillCommand {
+            min: Vec2::new(0.0, 0.0),
+            max: Vec2::new(20.0, 20.0),
+            border_width: 2.0,
+            border_color: Vec4::new(1.0, 0.0, 0.0, 1.0),
+            paint: FillPaint::Solid(Vec4::new(0.0, 0.0, 1.0, 1.0)),
+            ..Default::default()
+        };
+        assert_eq!(fill.shade(Vec2::new(0.5, 10.5), 0.0), (Vec4::new(1.0, 0.0, 0.0, 1.0), 1.0));
+        assert_eq!(fill.shade(Vec2::new(2.0, 10.5), 0.0), (Vec4::new(0.5, 0.0, 0.5, 1.0), 1.0));
+        assert_eq!(fill.shade(Vec2::new(10.5, 10.5), 0.0), (Vec4::new(0.0, 0.0, 1.0, 1.0), 1.0));
+        assert_eq!(fill.shade(Vec2::new(20.0, 10.5), 0.0).1, 0.5);
+    }
+
+    #[test]
+    fn polygon_edges_in_either_winding() {
+        let clockwise = [Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0), Vec2::new(10.0, 10.0), Vec2::new(0.0, 10.0)];
+        let counter_clockwise = [clockwise[3], clockwise[2], clockwise[1], clockwise[0]];
+        for points in [&clockwise, &counter_clockwise] {
+            let mut edges: Vec<Vec3> = Vec::new();
+            let polygon = PolygonFillCommand { points, ..Default::default() };
+            assert_eq!(polygon.append_edges(&mut edges), 4);
+            assert_eq!(polygon_distance(&edges, Vec2::new(5.0, 5.0)), 5.0);
+            assert_eq!(polygon_distance(&edges, Vec2::new(2.0, 5.0)), 2.0);
+            assert_eq!(polygon_distance(&edges, Vec2::new(5.0, 11.0)), -1.0);
+        }
+    }
+
+    #[test]
+    fn degenerate_polygons_have_no_edges() {
+        let mut edges: Vec<Vec3> = Vec::new();
+        let line = [Vec2::new(0.0, 0.0), Vec2::new(5.0, 5.0), Vec2::new(10.0, 10.0)];
+        assert_eq!(PolygonFillCommand { points: &line, ..Default::default() }.append_edges(&mut edges), 0);
+        let two_points = [Vec2::new(0.0, 0.0), Vec2::new(5.0, 5.0)];
+        assert_eq!(PolygonFillCommand { points: &two_points, ..Default::default() }.append_edges(&mut edges), 0);
+        assert!(edges.is_empty());
+    }
+}
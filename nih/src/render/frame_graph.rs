@@ -0,0 +1,771 @@
+use super::*;
+
+// A layer orchestrating the passes of a frame, e.g. a depth prepass followed by the main pass and the post-processing.
+// The graph owns the buffers the passes draw into, each pass declares which of them it reads and writes, and the graph
+// runs the passes in the order of these dependencies rather than in the order they are given:
+// - the passes writing a buffer run in the given order, each of them sees what the previous ones wrote;
+// - the passes reading a buffer without writing it run after all the passes writing it.
+// The draw passes run one at a time on the calling thread, e.g. the rasterizer parallelizes its own work. The tile
+// passes which are next to each other in that order and don't depend on each other are fused into a single parallel
+// sweep over the tiles.
+
+// Identifies a buffer created by FrameGraph::create_buffer().
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GraphBufferId(usize);
+
+// What a buffer of the graph holds, defines which slot of a framebuffer it can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphBufferKind {
+    Color(ColorBufferFormat),
+
+    // The 16-bit depth, see Framebuffer::depth_buffer.
+    Depth,
+
+    // The floating-point reverse-Z depth, see Framebuffer::depth_buffer_f32.
+    DepthF32,
+
+    Normals,
+
+    Motion,
+}
+
+// The buffers of the graph making up the framebuffer of a pass. The depth can be either of the depth kinds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GraphTarget {
+    pub color: Option<GraphBufferId>,
+    pub depth: Option<GraphBufferId>,
+    pub normals: Option<GraphBufferId>,
+    pub motion: Option<GraphBufferId>,
+}
+
+// The reason why the passes can't be run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameGraphError {
+    // The pass refers to a buffer which was not created by the graph.
+    UnknownBuffer {
+        pass: String,
+        buffer: GraphBufferId,
+    },
+
+    // The buffer is bound to a slot of a different kind, e.g. a depth buffer as the color.
+    KindMismatch {
+        pass: String,
+        buffer: GraphBufferId,
+    },
+
+    // The tile pass reads or writes a component which is not bound in its target.
+    MissingBinding {
+        pass: String,
+        component: FramebufferComponent,
+    },
+
+    // The draw pass reads a buffer of its own target, it has the buffer in the framebuffer already.
+    ReadsTarget {
+        pass: String,
+        buffer: GraphBufferId,
+    },
+
+    // The passes depend on each other, e.g. each reads what the other one writes.
+    Cycle {
+        passes: Vec<String>,
+    },
+}
+
+impl std::fmt::Display for FrameGraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameGraphError::UnknownBuffer { pass, buffer } => {
+                write!(f, "pass '{}' refers to unknown buffer {:?}", pass, buffer)
+            }
+            FrameGraphError::KindMismatch { pass, buffer } => {
+                write!(f, "pass '{}' binds buffer {:?} to a slot of a different kind", pass, buffer)
+            }
+            FrameGraphError::MissingBinding { pass, component } => {
+                write!(f, "pass '{}' uses the {:?} buffer which is not bound", pass, component)
+            }
+            FrameGraphError::ReadsTarget { pass, buffer } => {
+                write!(f, "pass '{}' reads buffer {:?} of its own target", pass, buffer)
+            }
+            FrameGraphError::Cycle { passes } => write!(f, "passes {:?} depend on each other", passes),
+        }
+    }
+}
+
+impl std::error::Error for FrameGraphError {}
+
+// A pass of the frame, see GraphPass::draw() and GraphPass::tiles().
+pub struct GraphPass<'a> {
+    name: String,
+    target: GraphTarget,
+    reads: Vec<GraphBufferId>,
+    work: PassWork<'a>,
+}
+
+type DrawFn<'a> = dyn FnMut(&mut Framebuffer, &GraphSources) + 'a;
+
+enum PassWork<'a> {
+    Draw(Box<DrawFn<'a>>),
+    Tiles(Box<dyn PostProcessPass + 'a>),
+}
+
+impl<'a> GraphPass<'a> {
+    // A pass drawing into the target's buffers, e.g. via Rasterizer::draw(). The buffers keep what the previous passes
+    // drew into them. The other buffers listed in `reads` are available via the sources, e.g. to make a texture of.
+    pub fn draw<F>(name: &str, target: GraphTarget, reads: &[GraphBufferId], draw: F) -> Self
+    where
+        F: FnMut(&mut Framebuffer, &GraphSources) + 'a,
+    {
+        Self { name: name.to_string(), target, reads: reads.to_vec(), work: PassWork::Draw(Box::new(draw)) }
+    }
+
+    // A pass processing the target's buffers one tile at a time, the components the pass reads and writes are mapped to
+    // the buffers bound in the target.
+    pub fn tiles(name: &str, target: GraphTarget, pass: impl PostProcessPass + 'a) -> Self {
+        Self { name: name.to_string(), target, reads: Vec::new(), work: PassWork::Tiles(Box::new(pass)) }
+    }
+
+    // Same as tiles() with the pass defined by a closure, see PostProcessChain::add_fn().
+    pub fn tiles_fn<F>(
+        name: &str,
+        target: GraphTarget,
+        reads: &[FramebufferComponent],
+        writes: &[FramebufferComponent],
+        reads_neighbors: bool,
+        f: F,
+    ) -> Self
+    where
+        F: Fn(&PostProcessSource, &mut FramebufferTile) + Send + Sync + 'a,
+    {
+        let pass = FnPostProcessPass { reads: reads.to_vec(), writes: writes.to_vec(), reads_neighbors, f };
+        Self::tiles(name, target, pass)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+// The read-only buffers given to a draw pass, only the ones listed in its reads are present.
+pub struct GraphSources<'a> {
+    buffers: Vec<Option<&'a GraphStorage>>,
+}
+
+impl GraphSources<'_> {
+    // The color, normals or motion buffer. Panics if the buffer is not among the pass's reads or is of another kind.
+    pub fn buffer_u32(&self, id: GraphBufferId) -> &TiledBuffer<u32, 64, 64> {
+        match self.buffers.get(id.0).copied().flatten() {
+            Some(GraphStorage::U32(buffer)) => buffer,
+            _ => panic!("buffer {:?} is not a readable u32 buffer", id),
+        }
+    }
+
+    // The 16-bit depth buffer. Panics if the buffer is not among the pass's reads or is of another kind.
+    pub fn buffer_u16(&self, id: GraphBufferId) -> &TiledBuffer<u16, 64, 64> {
+        match self.buffers.get(id.0).copied().flatten() {
+            Some(GraphStorage::U16(buffer)) => buffer,
+            _ => panic!("buffer {:?} is not a readable u16 buffer", id),
+        }
+    }
+
+    // The floating-point depth buffer. Panics if the buffer is not among the pass's reads or is of another kind.
+    pub fn buffer_f32(&self, id: GraphBufferId) -> &TiledBuffer<f32, 64, 64> {
+        match self.buffers.get(id.0).copied().flatten() {
+            Some(GraphStorage::F32(buffer)) => buffer,
+            _ => panic!("buffer {:?} is not a readable f32 buffer", id),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum GraphStorage {
+    U32(TiledBuffer<u32, 64, 64>),
+    U16(TiledBuffer<u16, 64, 64>),
+    F32(TiledBuffer<f32, 64, 64>),
+}
+
+struct GraphBuffer {
+    kind: GraphBufferKind,
+    clear: bool,
+    storage: GraphStorage,
+    // The copy made for the tile passes which read the neighbours of the pixels they write, kept to reuse the memory
+    copy: Option<GraphStorage>,
+}
+
+// The buffers a pass reads and writes, by their indices.
+#[derive(Debug, Default)]
+struct PassAccess {
+    // The buffers read and not written
+    reads: Vec<usize>,
+    writes: Vec<usize>,
+    // The written buffers a tile pass reads the neighbours in, they are read via their copies made before the pass
+    copied: Vec<usize>,
+    tiles: bool,
+}
+
+impl PassAccess {
+    // Whether the passes can run at the same time, i.e. neither writes what the other one reads or writes.
+    fn is_independent_of(&self, other: &PassAccess) -> bool {
+        let touches =
+            |access: &PassAccess, buffer: &usize| access.reads.contains(buffer) || access.writes.contains(buffer);
+        !self.writes.iter().any(|buffer| touches(other, buffer))
+            && !other.writes.iter().any(|buffer| touches(self, buffer))
+    }
+}
+
+// Owns the buffers of the frame and runs the passes over them, see the top of the file.
+pub struct FrameGraph {
+    width: u16,
+    height: u16,
+    clear_values: ClearValues,
+    buffers: Vec<GraphBuffer>,
+}
+
+impl FrameGraph {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self { width, height, clear_values: ClearValues::default(), buffers: Vec::new() }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    // Reallocates all the buffers with the new size, their contents are lost.
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.width = width;
+        self.height = height;
+        for buffer in &mut self.buffers {
+            buffer.storage = Self::allocate(buffer.kind, width, height);
+            buffer.copy = None;
+        }
+    }
+
+    // Allocates a buffer of the graph's size. A buffer which is cleared is filled with its clear value at the start of
+    // each execute(), the other buffers keep their contents between the frames, e.g. the history of a temporal filter.
+    pub fn create_buffer(&mut self, kind: GraphBufferKind, clear: bool) -> GraphBufferId {
+        let storage: GraphStorage = Self::allocate(kind, self.width, self.height);
+        self.buffers.push(GraphBuffer { kind, clear, storage, copy: None });
+        GraphBufferId(self.buffers.len() - 1)
+    }
+
+    // Sets the values the cleared buffers are filled with, the motion buffers are cleared to zero motion.
+    // Default: ClearValues::default().
+    pub fn set_clear_values(&mut self, values: ClearValues) {
+        self.clear_values = values;
+    }
+
+    // The color, normals or motion buffer. Panics if the buffer is of another kind.
+    pub fn buffer_u32(&self, id: GraphBufferId) -> &TiledBuffer<u32, 64, 64> {
+        match &self.buffers[id.0].storage {
+            GraphStorage::U32(buffer) => buffer,
+            _ => panic!("buffer {:?} is not a u32 buffer", id),
+        }
+    }
+
+    pub fn buffer_u32_mut(&mut self, id: GraphBufferId) -> &mut TiledBuffer<u32, 64, 64> {
+        match &mut self.buffers[id.0].storage {
+            GraphStorage::U32(buffer) => buffer,
+            _ => panic!("buffer {:?} is not a u32 buffer", id),
+        }
+    }
+
+    // The 16-bit depth buffer. Panics if the buffer is of another kind.
+    pub fn buffer_u16(&self, id: GraphBufferId) -> &TiledBuffer<u16, 64, 64> {
+        match &self.buffers[id.0].storage {
+            GraphStorage::U16(buffer) => buffer,
+            _ => panic!("buffer {:?} is not a u16 buffer", id),
+        }
+    }
+
+    pub fn buffer_u16_mut(&mut self, id: GraphBufferId) -> &mut TiledBuffer<u16, 64, 64> {
+        match &mut self.buffers[id.0].storage {
+            GraphStorage::U16(buffer) => buffer,
+            _ => panic!("buffer {:?} is not a u16 buffer", id),
+        }
+    }
+
+    // The floating-point depth buffer. Panics if the buffer is of another kind.
+    pub fn buffer_f32(&self, id: GraphBufferId) -> &TiledBuffer<f32, 64, 64> {
+        match &self.buffers[id.0].storage {
+            GraphStorage::F32(buffer) => buffer,
+            _ => panic!("buffer {:?} is not an f32 buffer", id),
+        }
+    }
+
+    pub fn buffer_f32_mut(&mut self, id: GraphBufferId) -> &mut TiledBuffer<f32, 64, 64> {
+        match &mut self.buffers[id.0].storage {
+            GraphStorage::F32(buffer) => buffer,
+            _ => panic!("buffer {:?} is not an f32 buffer", id),
+        }
+    }
+
+    // The steps execute() would run the passes in, each step is either a single draw pass or the tile passes swept over
+    // the tiles together. The passes are given by their indices.
+    pub fn schedule(&self, passes: &[GraphPass]) -> Result<Vec<Vec<usize>>, FrameGraphError> {
+        let accesses: Vec<PassAccess> = passes.iter().map(|pass| self.access(pass)).collect::<Result<_, _>>()?;
+        Self::steps(passes, &accesses)
+    }
+
+    // Clears the buffers which are cleared each frame and runs the passes, see schedule().
+    pub fn execute(&mut self, passes: &mut [GraphPass]) -> Result<(), FrameGraphError> {
+        let accesses: Vec<PassAccess> = passes.iter().map(|pass| self.access(pass)).collect::<Result<_, _>>()?;
+        let steps: Vec<Vec<usize>> = Self::steps(passes, &accesses)?;
+
+        for buffer in self.buffers.iter_mut().filter(|buffer| buffer.clear) {
+            let mut framebuffer = Framebuffer::default();
+            bind(buffer.kind, &mut buffer.storage, &mut framebuffer);
+            framebuffer.clear_all(&self.clear_values);
+        }
+        for step in steps {
+            if accesses[step[0]].tiles {
+                self.run_tiles(passes, &accesses, &step);
+            } else {
+                self.run_draw(&mut passes[step[0]], &accesses[step[0]]);
+            }
+        }
+        Ok(())
+    }
+
+    fn allocate(kind: GraphBufferKind, width: u16, height: u16) -> GraphStorage {
+        match kind {
+            GraphBufferKind::Color(_) | GraphBufferKind::Normals | GraphBufferKind::Motion => {
+                GraphStorage::U32(TiledBuffer::new(width, height))
+            }
+            GraphBufferKind::Depth => GraphStorage::U16(TiledBuffer::new(width, height)),
+            GraphBufferKind::DepthF32 => GraphStorage::F32(TiledBuffer::new(width, height)),
+        }
+    }
+
+    // Validates the pass's target and maps the buffers it reads and writes.
+    fn access(&self, pass: &GraphPass) -> Result<PassAccess, FrameGraphError> {
+        let known = |buffer: GraphBufferId| -> Result<usize, FrameGraphError> {
+            if buffer.0 < self.buffers.len() {
+                Ok(buffer.0)
+            } else {
+                Err(FrameGraphError::UnknownBuffer { pass: pass.name.clone(), buffer })
+            }
+        };
+        let slots = [
+            (FramebufferComponent::Color, pass.target.color),
+            (FramebufferComponent::Depth, pass.target.depth),
+            (FramebufferComponent::Normals, pass.target.normals),
+            (FramebufferComponent::Motion, pass.target.motion),
+        ];
+        for (component, buffer) in slots {
+            let Some(buffer) = buffer else {
+                continue;
+            };
+            let fits: bool = matches!(
+                (component, self.buffers[known(buffer)?].kind),
+                (FramebufferComponent::Color, GraphBufferKind::Color(_))
+                    | (FramebufferComponent::Depth, GraphBufferKind::Depth | GraphBufferKind::DepthF32)
+                    | (FramebufferComponent::Normals, GraphBufferKind::Normals)
+                    | (FramebufferComponent::Motion, GraphBufferKind::Motion)
+            );
+            if !fits {
+                return Err(FrameGraphError::KindMismatch { pass: pass.name.clone(), buffer });
+            }
+        }
+
+        let mut access = PassAccess::default();
+        match &pass.work {
+            PassWork::Draw(_) => {
+                access.writes = slots
+                    .iter()
+                    .filter_map(|(_, buffer)| buffer.map(|buffer| buffer.0))
+                    .collect();
+                for &buffer in &pass.reads {
+                    let index: usize = known(buffer)?;
+                    if access.writes.contains(&index) {
+                        return Err(FrameGraphError::ReadsTarget { pass: pass.name.clone(), buffer });
+                    }
+                    access.reads.push(index);
+                }
+            }
+            PassWork::Tiles(work) => {
+                access.tiles = true;
+                let bound = |component: FramebufferComponent| -> Result<usize, FrameGraphError> {
+                    match slots
+                        .iter()
+                        .find(|(slot, _)| *slot == component)
+                        .and_then(|(_, buffer)| *buffer)
+                    {
+                        Some(buffer) => Ok(buffer.0),
+                        None => Err(FrameGraphError::MissingBinding { pass: pass.name.clone(), component }),
+                    }
+                };
+                for &component in work.writes() {
+                    access.writes.push(bound(component)?);
+                }
+                for &component in work.reads() {
+                    let index: usize = bound(component)?;
+                    if !access.writes.contains(&index) {
+                        access.reads.push(index);
+                    } else if work.reads_neighbors() {
+                        access.copied.push(index);
+                    }
+                }
+            }
+        }
+        Ok(access)
+    }
+
+    // Orders the passes by their dependencies, keeping the given order where they don't depend on each other, and
+    // groups the independent tile passes next to each other into the steps.
+    fn steps(passes: &[GraphPass], accesses: &[PassAccess]) -> Result<Vec<Vec<usize>>, FrameGraphError> {
+        let passes_num: usize = passes.len();
+        let mut successors: Vec<Vec<usize>> = vec![Vec::new(); passes_num];
+        let mut predecessors_num: Vec<usize> = vec![0; passes_num];
+        let buffers_num: usize = accesses
+            .iter()
+            .flat_map(|access| access.reads.iter().chain(&access.writes))
+            .map(|&buffer| buffer + 1)
+            .max()
+            .unwrap_or(0);
+        for buffer in 0..buffers_num {
+            let mut last_writer: Option<usize> = None;
+            for (index, access) in accesses.iter().enumerate() {
+                if access.writes.contains(&buffer) {
+                    if let Some(writer) = last_writer {
+                        successors[writer].push(index);
+                        predecessors_num[index] += 1;
+                    }
+                    last_writer = Some(index);
+                }
+            }
+            let Some(writer) = last_writer else {
+                continue;
+            };
+            for (index, access) in accesses.iter().enumerate() {
+                if access.reads.contains(&buffer) {
+                    successors[writer].push(index);
+                    predecessors_num[index] += 1;
+                }
+            }
+        }
+
+        let mut done: Vec<bool> = vec![false; passes_num];
+        let mut steps: Vec<Vec<usize>> = Vec::new();
+        for _ in 0..passes_num {
+            let Some(next) = (0..passes_num).find(|&index| !done[index] && predecessors_num[index] == 0) else {
+                let passes: Vec<String> = (0..passes_num)
+                    .filter(|&index| !done[index])
+                    .map(|index| passes[index].name.clone())
+                    .collect();
+                return Err(FrameGraphError::Cycle { passes });
+            };
+            done[next] = true;
+            for &successor in &successors[next] {
+                predecessors_num[successor] -= 1;
+            }
+            let fused: bool = accesses[next].tiles
+                && steps.last().is_some_and(|step| {
+                    step.iter()
+                        .all(|&other| accesses[other].tiles && accesses[next].is_independent_of(&accesses[other]))
+                });
+            match steps.last_mut() {
+                Some(step) if fused => step.push(next),
+                _ => steps.push(vec![next]),
+            }
+        }
+        Ok(steps)
+    }
+
+    fn run_draw(&mut self, pass: &mut GraphPass, access: &PassAccess) {
+        let (readable, mut writable) = split_buffers(&mut self.buffers, &access.reads, &access.writes, &[]);
+        let mut framebuffer = Framebuffer::default();
+        for (kind, storage) in writable.iter_mut().filter_map(|writable| writable.take()) {
+            bind(kind, storage, &mut framebuffer);
+        }
+        let sources = GraphSources {
+            buffers: readable
+                .iter()
+                .map(|readable| readable.map(|(_, storage)| storage))
+                .collect(),
+        };
+        if let PassWork::Draw(draw) = &mut pass.work {
+            draw(&mut framebuffer, &sources);
+        }
+    }
+
+    fn run_tiles(&mut self, passes: &[GraphPass], accesses: &[PassAccess], step: &[usize]) {
+        let reads: Vec<usize> = step.iter().flat_map(|&index| accesses[index].reads.clone()).collect();
+        let writes: Vec<usize> = step.iter().flat_map(|&index| accesses[index].writes.clone()).collect();
+        let copied: Vec<usize> = step.iter().flat_map(|&index| accesses[index].copied.clone()).collect();
+        for &index in &copied {
+            let buffer: &mut GraphBuffer = &mut self.buffers[index];
+            copy_storage(&buffer.storage, &mut buffer.copy);
+        }
+        let kinds: Vec<GraphBufferKind> = self.buffers.iter().map(|buffer| buffer.kind).collect();
+        let (readable, mut writable) = split_buffers(&mut self.buffers, &reads, &writes, &copied);
+
+        // The tiles of each pass's written buffers, regrouped by the tiles so that each job runs all the passes
+        let tiles_x: u16 = self.width.div_ceil(Framebuffer::TILE_WITH);
+        let tiles_y: u16 = self.height.div_ceil(Framebuffer::TILE_HEIGHT);
+        let mut jobs: Vec<Vec<FramebufferTile>> = (0..tiles_x as usize * tiles_y as usize)
+            .map(|_| Vec::with_capacity(step.len()))
+            .collect();
+        let mut sources: Vec<PostProcessSource> = Vec::with_capacity(step.len());
+        for &index in step {
+            let access: &PassAccess = &accesses[index];
+            let mut framebuffer = Framebuffer::default();
+            for &buffer in &access.writes {
+                let (kind, storage) = writable[buffer].take().unwrap();
+                bind(kind, storage, &mut framebuffer);
+            }
+            let mut source = PostProcessSource {
+                color_buffer: None,
+                color_format: ColorBufferFormat::Rgba8,
+                depth_buffer: None,
+                depth_buffer_f32: None,
+                normal_buffer: None,
+                motion_buffer: None,
+            };
+            if let Some(GraphBufferKind::Color(format)) = passes[index].target.color.map(|color| kinds[color.0]) {
+                source.color_format = format;
+            }
+            for &buffer in access.reads.iter().chain(&access.copied) {
+                if let Some((kind, storage)) = readable[buffer] {
+                    bind_source(kind, storage, &mut source);
+                }
+            }
+            for (tile_index, job) in jobs.iter_mut().enumerate() {
+                job.push(framebuffer.tile(tile_index as u16 % tiles_x, tile_index as u16 / tiles_x));
+            }
+            sources.push(source);
+        }
+        let works: Vec<&dyn PostProcessPass> = step
+            .iter()
+            .map(|&index| match &passes[index].work {
+                PassWork::Tiles(work) => work.as_ref(),
+                PassWork::Draw(_) => unreachable!("only the tile passes are fused"),
+            })
+            .collect();
+
+        use rayon::prelude::*;
+        jobs.par_iter_mut().for_each(|job| {
+            for ((work, source), tile) in works.iter().zip(&sources).zip(job.iter_mut()) {
+                work.process(source, tile);
+            }
+        });
+    }
+}
+
+type Readable<'a> = Option<(GraphBufferKind, &'a GraphStorage)>;
+type Writable<'a> = Option<(GraphBufferKind, &'a mut GraphStorage)>;
+
+// Splits the buffers into the read-only and the writable ones, both indexed by the buffers. The written buffers which
+// are copied are readable via their copies.
+fn split_buffers<'a>(
+    buffers: &'a mut [GraphBuffer],
+    reads: &[usize],
+    writes: &[usize],
+    copied: &[usize],
+) -> (Vec<Readable<'a>>, Vec<Writable<'a>>) {
+    let mut readable: Vec<Readable> = Vec::with_capacity(buffers.len());
+    let mut writable: Vec<Writable> = Vec::with_capacity(buffers.len());
+    for (index, buffer) in buffers.iter_mut().enumerate() {
+        let GraphBuffer { kind, storage, copy, .. } = buffer;
+        if writes.contains(&index) {
+            let copy: Option<&GraphStorage> = if copied.contains(&index) { copy.as_ref() } else { None };
+            readable.push(copy.map(|copy| (*kind, copy)));
+            writable.push(Some((*kind, storage)));
+        } else {
+            readable.push(reads.contains(&index).then_some((*kind, &*storage)));
+            writable.push(None);
+        }
+    }
+    (readable, writable)
+}
+
+// Puts the buffer into the framebuffer's slot for its kind.
+fn bind<'a>(kind: GraphBufferKind, storage: &'a mut GraphStorage, framebuffer: &mut Framebuffer<'a>) {
+    match (kind, storage) {
+        (GraphBufferKind::Color(format), GraphStorage::U32(buffer)) => {
+            framebuffer.color_buffer = Some(buffer);
+            framebuffer.color_format = format;
+        }
+        (GraphBufferKind::Normals, GraphStorage::U32(buffer)) => framebuffer.normal_buffer = Some(buffer),
+        (GraphBufferKind::Motion, GraphStorage::U32(buffer)) => framebuffer.motion_buffer = Some(buffer),
+        (GraphBufferKind::Depth, GraphStorage::U16(buffer)) => framebuffer.depth_buffer = Some(buffer),
+        (GraphBufferKind::DepthF32, GraphStorage::F32(buffer)) => framebuffer.depth_buffer_f32 = Some(buffer),
+        _ => unreachable!("the storage is allocated for its kind"),
+    }
+}
+
+// Puts the buffer into the post-processing source's slot for its kind.
+fn bind_source<'a>(kind: GraphBufferKind, storage: &'a GraphStorage, source: &mut PostProcessSource<'a>) {
+    match (kind, storage) {
+        (GraphBufferKind::Color(_), GraphStorage::U32(buffer)) => source.color_buffer = Some(buffer),
+        (GraphBufferKind::Normals, GraphStorage::U32(buffer)) => source.normal_buffer = Some(buffer),
+        (GraphBufferKind::Motion, GraphStorage::U32(buffer)) => source.motion_buffer = Some(buffer),
+        (GraphBufferKind::Depth, GraphStorage::U16(buffer)) => source.depth_buffer = Some(buffer),
+        (GraphBufferKind::DepthF32, GraphStorage::F32(buffer)) => source.depth_buffer_f32 = Some(buffer),
+        _ => unreachable!("the storage is allocated for its kind"),
+    }
+}
+
+// Makes the copy an owned copy of the storage, reusing its memory when possible.
+fn copy_storage(storage: &GraphStorage, copy: &mut Option<GraphStorage>) {
+    match (storage, copy.as_mut()) {
+        (GraphStorage::U32(from), Some(GraphStorage::U32(to))) => copy_buffer(from, to),
+        (GraphStorage::U16(from), Some(GraphStorage::U16(to))) => copy_buffer(from, to),
+        (GraphStorage::F32(from), Some(GraphStorage::F32(to))) => copy_buffer(from, to),
+        _ => *copy = Some(storage.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill_color(value: RGBA) -> impl Fn(&PostProcessSource, &mut FramebufferTile) + Send + Sync {
+        move |_, tile| {
+            let color = tile.color_buffer.as_mut().unwrap();
+            for y in 0..color.height as usize {
+                for x in 0..color.width as usize {
+                    *color.get_unchecked(x, y) = value.to_u32();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn readers_run_after_writers() {
+        let mut graph = FrameGraph::new(100, 70);
+        let color: GraphBufferId = graph.create_buffer(GraphBufferKind::Color(ColorBufferFormat::Rgba8), true);
+        let normals: GraphBufferId = graph.create_buffer(GraphBufferKind::Normals, true);
+        let target = GraphTarget { color: Some(color), normals: Some(normals), ..Default::default() };
+        let mut passes = vec![
+            // Copies the color into the normals, given before the pass drawing the color
+            GraphPass::tiles_fn(
+                "resolve",
+                target,
+                &[FramebufferComponent::Color],
+                &[FramebufferComponent::Normals],
+                false,
+                |source, tile| {
+                    let color = source.color_buffer.unwrap();
+                    let (origin_x, origin_y): (u16, u16) = (tile.origin_x(), tile.origin_y());
+                    let normals = tile.normal_buffer.as_mut().unwrap();
+                    for y in 0..normals.height as usize {
+                        for x in 0..normals.width as usize {
+                            *normals.get_unchecked(x, y) = color.at(origin_x + x as u16, origin_y + y as u16);
+                        }
+                    }
+                },
+            ),
+            GraphPass::draw("main", GraphTarget { color: Some(color), ..Default::default() }, &[], |framebuffer, _| {
+                framebuffer
+                    .color_buffer
+                    .as_mut()
+                    .unwrap()
+                    .fill(RGBA::new(255, 0, 0, 255).to_u32());
+            }),
+        ];
+        assert_eq!(graph.schedule(&passes), Ok(vec![vec![1], vec![0]]));
+        graph.execute(&mut passes).unwrap();
+        for (x, y) in [(0, 0), (99, 0), (0, 69), (99, 69), (64, 64)] {
+            assert_eq!(RGBA::from_u32(graph.buffer_u32(normals).at(x, y)), RGBA::new(255, 0, 0, 255));
+        }
+    }
+
+    #[test]
+    fn independent_tile_passes_are_fused() {
+        let mut graph = FrameGraph::new(64, 64);
+        let color: GraphBufferId = graph.create_buffer(GraphBufferKind::Color(ColorBufferFormat::Rgba8), true);
+        let normals: GraphBufferId = graph.create_buffer(GraphBufferKind::Normals, true);
+        let motion: GraphBufferId = graph.create_buffer(GraphBufferKind::Motion, true);
+        let target =
+            GraphTarget { color: Some(color), normals: Some(normals), motion: Some(motion), ..Default::default() };
+        let mut passes = vec![
+            GraphPass::tiles_fn(
+                "color",
+                target,
+                &[],
+                &[FramebufferComponent::Color],
+                false,
+                fill_color(RGBA::new(255, 255, 255, 255)),
+            ),
+            GraphPass::tiles_fn("normals", target, &[], &[FramebufferComponent::Normals], false, |_, _| {}),
+            GraphPass::tiles_fn(
+                "motion",
+                target,
+                &[FramebufferComponent::Color],
+                &[FramebufferComponent::Motion],
+                false,
+                |_, _| {},
+            ),
+            GraphPass::tiles_fn("invert", target, &[], &[FramebufferComponent::Normals], false, |_, _| {}),
+        ];
+        assert_eq!(graph.schedule(&passes), Ok(vec![vec![0, 1], vec![2, 3]]));
+        graph.execute(&mut passes).unwrap();
+        assert_eq!(RGBA::from_u32(graph.buffer_u32(color).at(63, 63)), RGBA::new(255, 255, 255, 255));
+    }
+
+    #[test]
+    fn invalid_passes_are_reported() {
+        let mut graph = FrameGraph::new(16, 16);
+        let color: GraphBufferId = graph.create_buffer(GraphBufferKind::Color(ColorBufferFormat::Rgba8), true);
+        let depth: GraphBufferId = graph.create_buffer(GraphBufferKind::Depth, true);
+        let normals: GraphBufferId = graph.create_buffer(GraphBufferKind::Normals, true);
+        let unknown = GraphBufferId(3);
+        let schedule = |pass: GraphPass| graph.schedule(&[pass]);
+
+        let target = GraphTarget { color: Some(depth), ..Default::default() };
+        assert_eq!(
+            schedule(GraphPass::draw("main", target, &[], |_, _| {})),
+            Err(FrameGraphError::KindMismatch { pass: "main".to_string(), buffer: depth })
+        );
+        let target = GraphTarget { color: Some(color), ..Default::default() };
+        assert_eq!(
+            schedule(GraphPass::draw("main", target, &[unknown], |_, _| {})),
+            Err(FrameGraphError::UnknownBuffer { pass: "main".to_string(), buffer: unknown })
+        );
+        assert_eq!(
+            schedule(GraphPass::draw("main", target, &[color], |_, _| {})),
+            Err(FrameGraphError::ReadsTarget { pass: "main".to_string(), buffer: color })
+        );
+        assert_eq!(
+            schedule(GraphPass::tiles_fn(
+                "fog",
+                target,
+                &[FramebufferComponent::Depth],
+                &[FramebufferComponent::Color],
+                false,
+                |_, _| {}
+            )),
+            Err(FrameGraphError::MissingBinding { pass: "fog".to_string(), component: FramebufferComponent::Depth })
+        );
+
+        let passes = [
+            GraphPass::draw("a", GraphTarget { color: Some(color), ..Default::default() }, &[normals], |_, _| {}),
+            GraphPass::draw("b", GraphTarget { normals: Some(normals), ..Default::default() }, &[color], |_, _| {}),
+        ];
+        assert_eq!(
+            graph.schedule(&passes),
+            Err(FrameGraphError::Cycle { passes: vec!["a".to_string(), "b".to_string()] })
+        );
+    }
+
+    #[test]
+    fn only_cleared_buffers_are_cleared() {
+        let mut graph = FrameGraph::new(16, 16);
+        let color: GraphBufferId = graph.create_buffer(GraphBufferKind::Color(ColorBufferFormat::Rgba8), true);
+        let history: GraphBufferId = graph.create_buffer(GraphBufferKind::Color(ColorBufferFormat::Rgba8), false);
+        let depth: GraphBufferId = graph.create_buffer(GraphBufferKind::DepthF32, true);
+        graph.set_clear_values(ClearValues { color: RGBA::new(0, 0, 255, 255), ..Default::default() });
+        graph.buffer_u32_mut(color).fill(0);
+        graph.buffer_u32_mut(history).fill(RGBA::new(0, 255, 0, 255).to_u32());
+        graph.execute(&mut []).unwrap();
+        assert_eq!(RGBA::from_u32(graph.buffer_u32(color).at(5, 5)), RGBA::new(0, 0, 255, 255));
+        assert_eq!(RGBA::from_u32(graph.buffer_u32(history).at(5, 5)), RGBA::new(0, 255, 0, 255));
+        assert_eq!(graph.buffer_f32(depth).at(5, 5), DEPTH_F32_FAR);
+
+        graph.resize(100, 70);
+        assert_eq!((graph.buffer_u32(history).width(), graph.buffer_u32(history).height()), (100, 70));
+    }
+}
@@ -1,22 +1,153 @@
+use super::super::math::*;
 use super::*;
 
+// Defines how the values in the color buffer are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorBufferFormat {
+    // 8-bit RGBA, see RGBA::to_u32().
+    Rgba8,
+
+    // Shared-exponent HDR, see encode_rgb9e5(). Colors are not clamped to [0, 1] and are blended in floating point.
+    // Requires resolving via tone_map() before presenting.
+    Rgb9e5,
+}
+
+// Identifies one of the framebuffer's buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramebufferComponent {
+    Color,
+    Depth,
+    Normals,
+    Motion,
+}
+
+// The depth of the far plane, which the depth buffer is normally cleared with. The pixels keeping it weren't covered by
+// any depth-tested geometry.
+pub const DEPTH_FAR: u16 = u16::MAX;
+
+// The depth of the near plane.
+pub const DEPTH_NEAR: u16 = 0;
+
+// Maps the depth in normalized device coordinates, [-1, 1], to the value stored in the depth buffer, the same way the
+// rasterizer does it. Values outside the range are clamped, in debug builds they panic.
+pub fn encode_depth(ndc_z: f32) -> u16 {
+    debug_assert!((-1.0..=1.0).contains(&ndc_z), "the depth {} is outside of [-1, 1]", ndc_z);
+    ((ndc_z * 0.5 + 0.5) * 65535.0).clamp(0.0, 65535.0) as u16
+}
+
+// Maps the value stored in the depth buffer back to the depth in normalized device coordinates, [-1, 1].
+pub fn decode_depth(depth: u16) -> f32 {
+    depth as f32 / 65535.0 * 2.0 - 1.0
+}
+
+// The depth of the far plane in the floating-point depth buffer, which uses the reverse-Z mapping: the far plane is at 0.0
+// and the near plane is at 1.0. With the perspective projections the rasterizer computes the depth before the perspective
+// divide, as 0.5 * (w - z) / w, so the distant depths get the precision of floats around zero, which balances the
+// precision lost by the perspective projection far away from the camera.
+pub const DEPTH_F32_FAR: f32 = 0.0;
+
+// The depth of the near plane in the floating-point depth buffer.
+pub const DEPTH_F32_NEAR: f32 = 1.0;
+
+// Maps the depth in normalized device coordinates, [-1, 1], to the value stored in the floating-point depth buffer. The
+// rasterizer computes the same value, but from the clip space with the perspective projections, i.e. more precisely than
+// the NDC depth allows far away from the camera.
+pub fn encode_depth_f32(ndc_z: f32) -> f32 {
+    0.5 - ndc_z * 0.5
+}
+
+// Maps the value stored in the floating-point depth buffer back to the depth in normalized device coordinates, [-1, 1].
+pub fn decode_depth_f32(depth: f32) -> f32 {
+    1.0 - depth * 2.0
+}
+
+// The precision of the motion vectors stored in the motion buffer: 1/32 of a pixel, up to 1024 pixels along each axis.
+pub const MOTION_SUBPIXELS: f32 = 32.0;
+
+// Packs the screen-space motion in pixels into the value stored in the motion buffer: x and y as signed 16-bit fixed
+// point numbers in the low and the high halves. Motions beyond the range are clamped, zero motion is encoded as 0.
+pub fn encode_motion(motion: Vec2) -> u32 {
+    let x: i16 = (motion.x * MOTION_SUBPIXELS)
+        .round()
+        .clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+    let y: i16 = (motion.y * MOTION_SUBPIXELS)
+        .round()
+        .clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+    (x as u16 as u32) | ((y as u16 as u32) << 16)
+}
+
+// Unpacks the value stored in the motion buffer back into the screen-space motion in pixels.
+pub fn decode_motion(motion: u32) -> Vec2 {
+    Vec2::new(
+        (motion as u16 as i16) as f32 / MOTION_SUBPIXELS,
+        ((motion >> 16) as u16 as i16) as f32 / MOTION_SUBPIXELS,
+    )
+}
+
+// The values Framebuffer::clear_all() fills the buffers with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClearValues {
+    // Not premultiplied. Converted to the shared-exponent HDR when the color buffer is Rgb9e5.
+    // Default: opaque black.
+    pub color: RGBA,
+
+    // Default: DEPTH_FAR.
+    pub depth: u16,
+
+    // The value for the floating-point depth buffer.
+    // Default: DEPTH_F32_FAR.
+    pub depth_f32: f32,
+
+    // The normal encoded as by encode_normal_as_color(), NORMAL_NONE marks the pixels without geometry.
+    // Default: NORMAL_UP.
+    pub normal: RGBA,
+}
+
+impl Default for ClearValues {
+    fn default() -> Self {
+        Self { color: RGBA::new(0, 0, 0, 255), depth: DEPTH_FAR, depth_f32: DEPTH_F32_FAR, normal: NORMAL_UP }
+    }
+}
+
 pub struct Framebuffer<'a> {
     pub color_buffer: Option<&'a mut TiledBuffer<u32, 64, 64>>,
+    pub color_format: ColorBufferFormat,
     pub depth_buffer: Option<&'a mut TiledBuffer<u16, 64, 64>>,
 
+    // An alternative floating-point depth buffer with the reverse-Z mapping, see DEPTH_F32_FAR. Avoids the z-fighting of
+    // the 16-bit depth far away from the camera, e.g. on large ground planes. The depth tests keep their meaning: Less
+    // passes the fragments nearer to the camera. The hierarchical Z is not used with this buffer.
+    // Only one of the depth buffers can be present, Rasterizer::draw() panics otherwise.
+    pub depth_buffer_f32: Option<&'a mut TiledBuffer<f32, 64, 64>>,
+
     // NB! Normals might be not normalized!
     pub normal_buffer: Option<&'a mut TiledBuffer<u32, 64, 64>>,
+
+    // The screen-space motion of the pixels since the previous frame, see encode_motion(). The motion points from the
+    // pixel's position in the previous frame to the current one, given by RasterizationCommand::previous_model and
+    // previous_view_projection. Written by the triangles the same way as the normals, cleared to zero motion.
+    pub motion_buffer: Option<&'a mut TiledBuffer<u32, 64, 64>>,
 }
 
 pub struct FramebufferTile {
     pub color_buffer: Option<TiledBufferTileMut<u32, 64, 64>>,
+    pub color_format: ColorBufferFormat,
     pub depth_buffer: Option<TiledBufferTileMut<u16, 64, 64>>,
+    pub depth_buffer_f32: Option<TiledBufferTileMut<f32, 64, 64>>,
     pub normal_buffer: Option<TiledBufferTileMut<u32, 64, 64>>,
+    pub motion_buffer: Option<TiledBufferTileMut<u32, 64, 64>>,
 }
 
 impl Default for Framebuffer<'_> {
     fn default() -> Self {
-        Self { color_buffer: None, depth_buffer: None, normal_buffer: None }
+        Self {
+            color_buffer: None,
+            color_format: ColorBufferFormat::Rgba8,
+            depth_buffer: None,
+            depth_buffer_f32: None,
+            normal_buffer: None,
+            motion_buffer: None,
+        }
     }
 }
 
@@ -31,6 +162,15 @@ impl Framebuffer<'_> {
         if let Some(buffer) = &self.depth_buffer {
             return buffer.width();
         }
+        if let Some(buffer) = &self.depth_buffer_f32 {
+            return buffer.width();
+        }
+        if let Some(buffer) = &self.normal_buffer {
+            return buffer.width();
+        }
+        if let Some(buffer) = &self.motion_buffer {
+            return buffer.width();
+        }
         return 0;
     }
 
@@ -41,6 +181,15 @@ impl Framebuffer<'_> {
         if let Some(buffer) = &self.depth_buffer {
             return buffer.height();
         }
+        if let Some(buffer) = &self.depth_buffer_f32 {
+            return buffer.height();
+        }
+        if let Some(buffer) = &self.normal_buffer {
+            return buffer.height();
+        }
+        if let Some(buffer) = &self.motion_buffer {
+            return buffer.height();
+        }
         return 0;
     }
 
@@ -51,6 +200,15 @@ impl Framebuffer<'_> {
         if let Some(buffer) = &self.depth_buffer {
             return buffer.tiles_x();
         }
+        if let Some(buffer) = &self.depth_buffer_f32 {
+            return buffer.tiles_x();
+        }
+        if let Some(buffer) = &self.normal_buffer {
+            return buffer.tiles_x();
+        }
+        if let Some(buffer) = &self.motion_buffer {
+            return buffer.tiles_x();
+        }
         return 0;
     }
 
@@ -61,9 +219,44 @@ impl Framebuffer<'_> {
         if let Some(buffer) = &self.depth_buffer {
             return buffer.tiles_y();
         }
+        if let Some(buffer) = &self.depth_buffer_f32 {
+            return buffer.tiles_y();
+        }
+        if let Some(buffer) = &self.normal_buffer {
+            return buffer.tiles_y();
+        }
+        if let Some(buffer) = &self.motion_buffer {
+            return buffer.tiles_y();
+        }
         return 0;
     }
 
+    // Fills each of the present buffers with its clear value.
+    pub fn clear_all(&mut self, values: &ClearValues) {
+        if let Some(buffer) = self.color_buffer.as_mut() {
+            buffer.fill(match self.color_format {
+                ColorBufferFormat::Rgba8 => values.color.to_u32(),
+                ColorBufferFormat::Rgb9e5 => encode_rgb9e5(
+                    values.color.r as f32 / 255.0,
+                    values.color.g as f32 / 255.0,
+                    values.color.b as f32 / 255.0,
+                ),
+            });
+        }
+        if let Some(buffer) = self.depth_buffer.as_mut() {
+            buffer.fill(values.depth);
+        }
+        if let Some(buffer) = self.depth_buffer_f32.as_mut() {
+            buffer.fill(values.depth_f32);
+        }
+        if let Some(buffer) = self.normal_buffer.as_mut() {
+            buffer.fill(values.normal.to_u32());
+        }
+        if let Some(buffer) = self.motion_buffer.as_mut() {
+            buffer.fill(0);
+        }
+    }
+
     pub fn tile(&mut self, x: u16, y: u16) -> FramebufferTile {
         FramebufferTile {
             color_buffer: if let Some(buffer) = self.color_buffer.as_mut() {
@@ -71,16 +264,19 @@ impl Framebuffer<'_> {
             } else {
                 None
             },
+            color_format: self.color_format,
             depth_buffer: if let Some(buffer) = self.depth_buffer.as_mut() {
                 Some(buffer.tile_mut(x, y))
             } else {
                 None
             },
+            depth_buffer_f32: self.depth_buffer_f32.as_mut().map(|buffer| buffer.tile_mut(x, y)),
             normal_buffer: if let Some(buffer) = self.normal_buffer.as_mut() {
                 Some(buffer.tile_mut(x, y))
             } else {
                 None
             },
+            motion_buffer: self.motion_buffer.as_mut().map(|buffer| buffer.tile_mut(x, y)),
         }
     }
 
@@ -119,6 +315,15 @@ impl FramebufferTile {
         if let Some(buffer) = &self.depth_buffer {
             return buffer.width;
         }
+        if let Some(buffer) = &self.depth_buffer_f32 {
+            return buffer.width;
+        }
+        if let Some(buffer) = &self.normal_buffer {
+            return buffer.width;
+        }
+        if let Some(buffer) = &self.motion_buffer {
+            return buffer.width;
+        }
         return 0;
     }
 
@@ -129,6 +334,15 @@ impl FramebufferTile {
         if let Some(buffer) = &self.depth_buffer {
             return buffer.height;
         }
+        if let Some(buffer) = &self.depth_buffer_f32 {
+            return buffer.height;
+        }
+        if let Some(buffer) = &self.normal_buffer {
+            return buffer.height;
+        }
+        if let Some(buffer) = &self.motion_buffer {
+            return buffer.height;
+        }
         return 0;
     }
 
@@ -139,6 +353,15 @@ impl FramebufferTile {
         if let Some(buffer) = &self.depth_buffer {
             return buffer.origin_x;
         }
+        if let Some(buffer) = &self.depth_buffer_f32 {
+            return buffer.origin_x;
+        }
+        if let Some(buffer) = &self.normal_buffer {
+            return buffer.origin_x;
+        }
+        if let Some(buffer) = &self.motion_buffer {
+            return buffer.origin_x;
+        }
         return 0;
     }
 
@@ -149,6 +372,15 @@ impl FramebufferTile {
         if let Some(buffer) = &self.depth_buffer {
             return buffer.origin_y;
         }
+        if let Some(buffer) = &self.depth_buffer_f32 {
+            return buffer.origin_y;
+        }
+        if let Some(buffer) = &self.normal_buffer {
+            return buffer.origin_y;
+        }
+        if let Some(buffer) = &self.motion_buffer {
+            return buffer.origin_y;
+        }
         return 0;
     }
 }
@@ -0,0 +1,383 @@
+use super::super::math::*;
+use super::*;
+
+// Shared-exponent RGB9E5 packing: 9-bit mantissas for each of r, g and b and a 5-bit exponent common to all three.
+// The layout is r | g << 9 | b << 18 | e << 27, same as in EXT_texture_shared_exponent.
+// Negative values are clamped to zero, values above RGB9E5_MAX are clamped to RGB9E5_MAX.
+const RGB9E5_MANTISSA_BITS: i32 = 9;
+const RGB9E5_EXPONENT_BIAS: i32 = 15;
+const RGB9E5_MAX_EXPONENT: i32 = 31;
+pub const RGB9E5_MAX: f32 = 65408.0; // (2^9 - 1) / 2^9 * 2^(31 - 15)
+
+// Returns 2^e for the exponents representable by normal f32 values.
+#[inline(always)]
+fn exp2i(e: i32) -> f32 {
+    f32::from_bits(((e + 127) as u32) << 23)
+}
+
+#[inline(always)]
+pub fn encode_rgb9e5(r: f32, g: f32, b: f32) -> u32 {
+    // NB! NaNs survive clamping, but are ignored by max() and become zeros when converted to integers
+    let r: f32 = r.clamp(0.0, RGB9E5_MAX);
+    let g: f32 = g.clamp(0.0, RGB9E5_MAX);
+    let b: f32 = b.clamp(0.0, RGB9E5_MAX);
+    let max_rgb: f32 = r.max(g).max(b);
+
+    // floor(log2(max_rgb)) straight from the float's exponent, denormals are way below the smallest exponent anyway
+    let max_rgb_log2: i32 = ((max_rgb.to_bits() >> 23) & 0xFF) as i32 - 127;
+    let mut exponent: i32 = max_rgb_log2.max(-RGB9E5_EXPONENT_BIAS - 1) + 1 + RGB9E5_EXPONENT_BIAS;
+
+    // Rounding can push the largest component up to 2^9, in which case the next exponent is needed
+    let mut scale: f32 = exp2i(RGB9E5_MANTISSA_BITS - (exponent - RGB9E5_EXPONENT_BIAS));
+    if (max_rgb * scale + 0.5) as u32 >= (1 << RGB9E5_MANTISSA_BITS) {
+        exponent += 1;
+        scale *= 0.5;
+    }
+    debug_assert!((0..=RGB9E5_MAX_EXPONENT).contains(&exponent));
+
+    let rm: u32 = ((r * scale + 0.5) as u32).min(511);
+    let gm: u32 = ((g * scale + 0.5) as u32).min(511);
+    let bm: u32 = ((b * scale + 0.5) as u32).min(511);
+    rm | (gm << 9) | (bm << 18) | ((exponent as u32) << 27)
+}
+
+#[inline(always)]
+pub fn decode_rgb9e5(v: u32) -> Vec3 {
+    let exponent: i32 = (v >> 27) as i32;
+    let scale: f32 = exp2i(exponent - RGB9E5_EXPONENT_BIAS - RGB9E5_MANTISSA_BITS);
+    Vec3::new(
+        (v & 0x1FF) as f32 * scale,
+        ((v >> 9) & 0x1FF) as f32 * scale,
+        ((v >> 18) & 0x1FF) as f32 * scale,
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMappingOperator {
+    // Luminance-based Reinhard with a white point: L * (1 + L / white^2) / (1 + L).
+    Reinhard,
+
+    // Narkowicz's fit of the ACES filmic curve, applied per channel. The white point is ignored.
+    Aces,
+}
+
+// The noise added to the colors before they are quantized to 8 bits. Breaks the banding of smooth gradients, e.g. of the
+// sky, into a fine grain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dithering {
+    None,
+
+    // The 4x4 Bayer matrix, the cheapest one but leaves a visible regular pattern.
+    Bayer4x4,
+
+    // A precomputed 16x16 void-and-cluster blue-noise table, the grain lacks the low frequencies and is hard to notice.
+    BlueNoise,
+}
+
+const BAYER_4X4: [u8; 16] = [0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5];
+
+const BLUE_NOISE_16X16: [u8; 256] = [
+    120, 61, 134, 223, 84, 33, 168, 12, 113, 225, 63, 246, 185, 233, 88, 169, //
+    23, 206, 181, 17, 109, 214, 58, 140, 201, 24, 161, 93, 34, 133, 14, 221, //
+    144, 73, 250, 49, 158, 187, 81, 251, 100, 51, 142, 210, 172, 57, 191, 106, //
+    42, 167, 101, 126, 220, 3, 121, 40, 170, 231, 82, 8, 114, 255, 80, 232, //
+    212, 11, 195, 31, 72, 239, 152, 196, 16, 127, 188, 222, 45, 157, 26, 128, //
+    154, 87, 235, 143, 179, 94, 54, 108, 237, 65, 29, 105, 139, 207, 184, 66, //
+    248, 47, 115, 62, 209, 20, 164, 217, 79, 146, 178, 243, 69, 90, 1, 118, //
+    30, 190, 173, 6, 131, 254, 41, 136, 10, 204, 43, 159, 22, 229, 162, 218, //
+    77, 148, 99, 226, 74, 182, 117, 192, 86, 247, 119, 97, 197, 130, 53, 103, //
+    242, 19, 198, 44, 155, 96, 59, 230, 28, 165, 60, 5, 240, 39, 175, 202, //
+    137, 64, 122, 238, 25, 211, 0, 149, 104, 224, 135, 183, 151, 71, 112, 9, //
+    91, 213, 166, 85, 186, 111, 249, 174, 48, 75, 208, 32, 89, 205, 236, 160, //
+    37, 252, 18, 55, 138, 38, 78, 123, 194, 13, 107, 253, 124, 15, 56, 189, //
+    76, 145, 110, 228, 203, 163, 219, 21, 241, 141, 171, 50, 156, 227, 102, 129, //
+    2, 199, 176, 68, 7, 98, 52, 150, 92, 36, 215, 83, 200, 27, 177, 216, //
+    244, 95, 35, 153, 245, 125, 193, 234, 70, 180, 132, 4, 116, 67, 147, 46, //
+];
+
+impl Dithering {
+    // The offset in [-0.5, 0.5) of the quantization step added to the pixel at (x, y), the pattern tiles the screen.
+    #[inline(always)]
+    pub fn offset(&self, x: u16, y: u16) -> f32 {
+        match self {
+            Dithering::None => 0.0,
+            Dithering::Bayer4x4 => (BAYER_4X4[(y as usize % 4) * 4 + x as usize % 4] as f32 + 0.5) * (1.0 / 16.0) - 0.5,
+            Dithering::BlueNoise => {
+                (BLUE_NOISE_16X16[(y as usize % 16) * 16 + x as usize % 16] as f32 + 0.5) * (1.0 / 256.0) - 0.5
+            }
+        }
+    }
+}
+
+// Parameters of resolving an HDR color buffer into a displayable 8-bit one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapping {
+    // The curve used to map the exposed radiance into [0, 1].
+    // Default: Reinhard.
+    pub operator: ToneMappingOperator,
+
+    // The radiance is multiplied by the exposure before being mapped.
+    // Default: 1.0.
+    pub exposure: f32,
+
+    // The smallest exposed luminance mapped to pure white by the Reinhard operator.
+    // Default: 4.0.
+    pub white_point: f32,
+
+    // The mapped color is raised to 1/gamma before being quantized to 8 bits.
+    // Default: 2.2.
+    pub gamma: f32,
+
+    // The noise added to the mapped colors by tone_map() before they are quantized.
+    // Default: None.
+    pub dithering: Dithering,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            operator: ToneMappingOperator::Reinhard,
+            exposure: 1.0,
+            white_point: 4.0,
+            gamma: 2.2,
+            dithering: Dithering::None,
+        }
+    }
+}
+
+impl ToneMapping {
+    // The same tone mapping with the exposure of the physical camera, for the colors being the luminance in cd/m².
+    pub fn with_camera_exposure(self, camera: &CameraExposure) -> Self {
+        Self { exposure: camera.scale(), ..self }
+    }
+
+    // Maps the linear HDR color into a display-ready gamma-corrected color in [0, 1].
+    #[inline(always)]
+    pub fn map(&self, color: Vec3) -> Vec3 {
+        let exposed: Vec3 = color * self.exposure;
+        let mapped: Vec3 = match self.operator {
+            ToneMappingOperator::Reinhard => {
+                let luma: f32 = dot(exposed, Vec3::new(0.2126, 0.7152, 0.0722));
+                let scale: f32 = (1.0 + luma / (self.white_point * self.white_point)) / (1.0 + luma);
+                exposed * scale
+            }
+            ToneMappingOperator::Aces => {
+                let aces = |x: f32| -> f32 { (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14) };
+                Vec3::new(aces(exposed.x), aces(exposed.y), aces(exposed.z))
+            }
+        };
+        let inv_gamma: f32 = 1.0 / self.gamma;
+        Vec3::new(
+            mapped.x.clamp(0.0, 1.0).powf(inv_gamma),
+            mapped.y.clamp(0.0, 1.0).powf(inv_gamma),
+            mapped.z.clamp(0.0, 1.0).powf(inv_gamma),
+        )
+    }
+
+    // Maps the RGB9E5-encoded color into an opaque RGBA8 color, without dithering.
+    #[inline(always)]
+    pub fn map_rgb9e5(&self, hdr: u32) -> u32 {
+        self.map_rgb9e5_dithered(hdr, 0.0)
+    }
+
+    // Maps the RGB9E5-encoded color into an opaque RGBA8 color, the offset of the quantization step is given by
+    // Dithering::offset().
+    #[inline(always)]
+    pub fn map_rgb9e5_dithered(&self, hdr: u32, offset: f32) -> u32 {
+        let color: Vec3 = self.map(decode_rgb9e5(hdr));
+        let quantize = |c: f32| -> u8 { (c * 255.0 + 0.5 + offset).clamp(0.0, 255.0) as u8 };
+        RGBA::new(quantize(color.x), quantize(color.y), quantize(color.z), 255).to_u32()
+    }
+}
+
+// The settings of a physical camera, which define how much of the scene's light reaches the image. Turns the luminance
+// in cd/m² into the relative values around 1.0, e.g. for a sunlit scene in the photometric light units, see LightUnits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CameraExposure {
+    // The f-number of the lens, the larger the less light passes through.
+    // Default: 16.0.
+    pub aperture: f32,
+
+    // In seconds.
+    // Default: 0.01.
+    pub shutter_time: f32,
+
+    // The sensitivity of the sensor.
+    // Default: 100.0.
+    pub iso: f32,
+
+    // The exposure compensation in stops, the positive values brighten the image.
+    // Default: 0.0.
+    pub compensation: f32,
+}
+
+impl Default for CameraExposure {
+    // The "sunny 16" rule, suits the scenes lit by the sun.
+    fn default() -> Self {
+        Self { aperture: 16.0, shutter_time: 0.01, iso: 100.0, compensation: 0.0 }
+    }
+}
+
+impl CameraExposure {
+    // The camera with the exposure value at ISO 100, e.g. about 15 for a sunny day and about 7 for a lit interior.
+    pub fn from_ev100(ev100: f32) -> Self {
+        Self { aperture: 1.0, shutter_time: (-ev100).exp2(), iso: 100.0, compensation: 0.0 }
+    }
+
+    // The exposure value at ISO 100 the camera's settings are equivalent to.
+    pub fn ev100(&self) -> f32 {
+        (self.aperture * self.aperture / self.shutter_time * 100.0 / self.iso).log2()
+    }
+
+    // The multiplier of the luminance in cd/m², which maps the luminance saturating the sensor to 1.0.
+    pub fn scale(&self) -> f32 {
+        1.0 / (1.2 * (self.ev100() - self.compensation).exp2())
+    }
+}
+
+// Resolves the RGB9E5 color buffer into the RGBA8 one of the same size, tiles are processed in parallel.
+pub fn tone_map(hdr: &TiledBuffer<u32, 64, 64>, ldr: &mut TiledBuffer<u32, 64, 64>, tone_mapping: &ToneMapping) {
+    assert_eq!(hdr.width(), ldr.width());
+    assert_eq!(hdr.height(), ldr.height());
+    let mut tiles: Vec<(TiledBufferTile<u32, 64, 64>, TiledBufferTileMut<u32, 64, 64>)> = Vec::new();
+    for y in 0..hdr.tiles_y() {
+        for x in 0..hdr.tiles_x() {
+            tiles.push((hdr.tile(x, y), ldr.tile_mut(x, y)));
+        }
+    }
+    let tone_mapping: ToneMapping = *tone_mapping;
+    use rayon::prelude::*;
+    tiles.par_iter_mut().for_each(|(src, dst)| {
+        for y in 0..src.height as usize {
+            for x in 0..src.width as usize {
+                let idx: usize = y * Framebuffer::TILE_WITH as usize + x;
+                let offset: f32 = tone_mapping
+                    .dithering
+                    .offset(src.origin_x + x as u16, src.origin_y + y as u16);
+                unsafe {
+                    *dst.ptr.add(idx) = tone_mapping.map_rgb9e5_dithered(*src.ptr.add(idx), offset);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgb9e5_roundtrip() {
+        let cases: [Vec3; 6] = [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(0.5, 0.25, 0.125),
+            Vec3::new(10.0, 2.0, 0.5),
+            Vec3::new(1000.0, 0.0, 1.0),
+            Vec3::new(0.001, 0.002, 0.003),
+        ];
+        for c in cases {
+            let decoded: Vec3 = decode_rgb9e5(encode_rgb9e5(c.x, c.y, c.z));
+            // The precision is relative to the largest component
+            let tolerance: f32 = c.x.max(c.y).max(c.z) / 256.0;
+            assert!((decoded - c).x.abs() <= tolerance, "{:?} -> {:?}", c, decoded);
+            assert!((decoded - c).y.abs() <= tolerance, "{:?} -> {:?}", c, decoded);
+            assert!((decoded - c).z.abs() <= tolerance, "{:?} -> {:?}", c, decoded);
+        }
+    }
+
+    #[test]
+    fn rgb9e5_clamping() {
+        assert_eq!(decode_rgb9e5(encode_rgb9e5(-1.0, f32::NAN, 0.0)), Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(decode_rgb9e5(encode_rgb9e5(1.0e9, 0.0, 0.0)).x, RGB9E5_MAX);
+        // Rounding up to the next exponent
+        assert_eq!(decode_rgb9e5(encode_rgb9e5(0.9999, 0.0, 0.0)).x, 1.0);
+    }
+
+    #[test]
+    fn tone_mapping_operators() {
+        let linear = ToneMapping { gamma: 1.0, ..Default::default() };
+        assert_eq!(linear.map(Vec3::new(0.0, 0.0, 0.0)), Vec3::new(0.0, 0.0, 0.0));
+        // Reinhard maps the white point to 1.0
+        let white: Vec3 = linear.map(Vec3::new(4.0, 4.0, 4.0));
+        assert!((white.x - 1.0).abs() < 0.0001);
+        // ... and keeps the values below monotonic and under 1.0
+        let a: Vec3 = linear.map(Vec3::new(0.5, 0.5, 0.5));
+        let b: Vec3 = linear.map(Vec3::new(2.0, 2.0, 2.0));
+        assert!(a.x < b.x && b.x < 1.0);
+
+        let aces = ToneMapping { operator: ToneMappingOperator::Aces, gamma: 1.0, ..Default::default() };
+        assert_eq!(aces.map(Vec3::new(0.0, 0.0, 0.0)), Vec3::new(0.0, 0.0, 0.0));
+        assert!(aces.map(Vec3::new(100.0, 100.0, 100.0)).x > 0.99);
+        assert!(aces.map(Vec3::new(0.18, 0.18, 0.18)).x < 0.3);
+    }
+
+    #[test]
+    fn camera_exposure() {
+        let sunny = CameraExposure::default();
+        assert!((sunny.ev100() - 14.64).abs() < 0.01);
+        let camera = CameraExposure::from_ev100(15.0);
+        assert!((camera.ev100() - 15.0).abs() < 1e-4);
+        // A white surface under the sun of 100000 lux reflects 100000 / pi cd/m²
+        let exposed: f32 = 100000.0 / std::f32::consts::PI * camera.scale();
+        assert!((exposed - 0.81).abs() < 0.01);
+        // A stop of compensation doubles the exposure, as does the doubled sensitivity
+        let brighter = CameraExposure { compensation: 1.0, ..camera };
+        assert!((brighter.scale() / camera.scale() - 2.0).abs() < 1e-4);
+        let sensitive = CameraExposure { iso: 200.0, ..sunny };
+        assert!((sensitive.scale() / sunny.scale() - 2.0).abs() < 1e-4);
+        assert_eq!(ToneMapping::default().with_camera_exposure(&camera).exposure, camera.scale());
+    }
+
+    #[test]
+    fn tone_map_buffer() {
+        let mut hdr = TiledBuffer::<u32, 64, 64>::new(100, 70);
+        hdr.fill(encode_rgb9e5(0.0, 0.0, 0.0));
+        *hdr.at_mut(99, 69) = encode_rgb9e5(100.0, 100.0, 100.0);
+        let mut ldr = TiledBuffer::<u32, 64, 64>::new(100, 70);
+        tone_map(&hdr, &mut ldr, &ToneMapping::default());
+        assert_eq!(RGBA::from_u32(ldr.at(0, 0)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(ldr.at(99, 69)), RGBA::new(255, 255, 255, 255));
+    }
+
+    #[test]
+    fn dithering_preserves_the_average() {
+        for (dithering, period) in [(Dithering::Bayer4x4, 4u16), (Dithering::BlueNoise, 16u16)] {
+            let mut offsets: Vec<f32> = Vec::new();
+            for y in 0..period {
+                for x in 0..period {
+                    offsets.push(dithering.offset(x, y));
+                    assert_eq!(dithering.offset(x, y), dithering.offset(x + period, y + 3 * period));
+                }
+            }
+            // Each of the steps is taken exactly once
+            offsets.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let step: f32 = 1.0 / offsets.len() as f32;
+            for (i, offset) in offsets.iter().enumerate() {
+                assert!((offset - ((i as f32 + 0.5) * step - 0.5)).abs() < 1e-6);
+            }
+        }
+        assert_eq!(Dithering::None.offset(5, 7), 0.0);
+
+        // A flat color between two 8-bit levels is resolved into a mix of both, averaging to the exact value
+        let color: u32 = encode_rgb9e5(0.3, 0.3, 0.3);
+        let mut hdr = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        hdr.fill(color);
+        let mut ldr = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        for (dithering, levels) in [(Dithering::Bayer4x4, 16.0), (Dithering::BlueNoise, 256.0)] {
+            let tone_mapping = ToneMapping { dithering, ..Default::default() };
+            tone_map(&hdr, &mut ldr, &tone_mapping);
+            let expected: f32 = tone_mapping.map(decode_rgb9e5(color)).x * 255.0;
+            let mut values: Vec<u8> = Vec::new();
+            for y in 0..64 {
+                for x in 0..64 {
+                    values.push(RGBA::from_u32(ldr.at(x, y)).r);
+                }
+            }
+            let average: f32 = values.iter().map(|v| *v as f32).sum::<f32>() / values.len() as f32;
+            assert!((average - expected).abs() <= 0.5 / levels, "{:?}: {} vs {}", dithering, average, expected);
+            assert!(values.iter().all(|v| (*v as f32 - expected).abs() < 1.0));
+        }
+    }
+}
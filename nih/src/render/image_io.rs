@@ -0,0 +1,249 @@
+use super::*;
+use std::path::Path;
+
+// Saving the rendered buffers into image files and loading them back, e.g. for the reference images of the tests or
+// for the tools inspecting the frames. The file format is picked by the path's extension, e.g. PNG or EXR.
+
+// The reason why a buffer can't be saved or loaded.
+#[derive(Debug)]
+pub enum ImageIoError {
+    // The file can't be read, written, decoded or encoded in the format given by its extension.
+    Image(image::ImageError),
+
+    // The image is larger than a buffer can be.
+    TooLarge { width: u32, height: u32 },
+}
+
+impl std::fmt::Display for ImageIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageIoError::Image(error) => write!(f, "{}", error),
+            ImageIoError::TooLarge { width, height } => {
+                write!(f, "the {}x{} image is too large for a buffer", width, height)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImageIoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageIoError::Image(error) => Some(error),
+            ImageIoError::TooLarge { .. } => None,
+        }
+    }
+}
+
+impl From<image::ImageError> for ImageIoError {
+    fn from(error: image::ImageError) -> Self {
+        ImageIoError::Image(error)
+    }
+}
+
+impl Buffer<u32> {
+    // Saves the 8-bit RGBA colors, see RGBA::to_u32(), as they are.
+    pub fn save_color<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageIoError> {
+        let rgba: Vec<u8> = self.rows().flatten().flat_map(|pixel| pixel.to_le_bytes()).collect();
+        image::save_buffer(path, &rgba, self.width as u32, self.height as u32, image::ColorType::Rgba8)?;
+        Ok(())
+    }
+
+    // Loads the image as 8-bit RGBA colors, converting it from any other color type.
+    pub fn load_color<P: AsRef<Path>>(path: P) -> Result<Buffer<u32>, ImageIoError> {
+        let image: image::RgbaImage = image::open(path)?.into_rgba8();
+        let mut buffer: Buffer<u32> = new_buffer(image.width(), image.height())?;
+        for (pixel, texel) in buffer.elems.iter_mut().zip(image.pixels()) {
+            *pixel = RGBA::new(texel[0], texel[1], texel[2], texel[3]).to_u32();
+        }
+        Ok(buffer)
+    }
+
+    // Saves the normals encoded the way the rasterizer writes them, see encode_normal_as_color(), as RGB colors.
+    // Their alpha is always zero, so it's dropped to keep the image viewable.
+    pub fn save_normals<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageIoError> {
+        let rgb: Vec<u8> = self
+            .rows()
+            .flatten()
+            .flat_map(|pixel| {
+                let [r, g, b, _] = pixel.to_le_bytes();
+                [r, g, b]
+            })
+            .collect();
+        image::save_buffer(path, &rgb, self.width as u32, self.height as u32, image::ColorType::Rgb8)?;
+        Ok(())
+    }
+
+    // Loads the normals saved by save_normals(), with the alpha set back to zero.
+    pub fn load_normals<P: AsRef<Path>>(path: P) -> Result<Buffer<u32>, ImageIoError> {
+        let image: image::RgbImage = image::open(path)?.into_rgb8();
+        let mut buffer: Buffer<u32> = new_buffer(image.width(), image.height())?;
+        for (pixel, texel) in buffer.elems.iter_mut().zip(image.pixels()) {
+            *pixel = RGBA::new(texel[0], texel[1], texel[2], 0).to_u32();
+        }
+        Ok(buffer)
+    }
+}
+
+impl Buffer<u16> {
+    // Saves the 16-bit depth values, see encode_depth(). The formats with 16-bit channels, e.g. PNG, keep the values
+    // as they are, the floating-point ones, e.g. EXR, get them mapped to [0, 1], i.e. value / 65535.
+    pub fn save_depth<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageIoError> {
+        let path: &Path = path.as_ref();
+        if is_floating_point_format(path) {
+            let values: Vec<f32> = self
+                .rows()
+                .flatten()
+                .map(|&depth| depth as f32 / u16::MAX as f32)
+                .collect();
+            return save_floats(path, self.width, self.height, &values);
+        }
+        let gray: Vec<u8> = self.rows().flatten().flat_map(|depth| depth.to_ne_bytes()).collect();
+        image::save_buffer(path, &gray, self.width as u32, self.height as u32, image::ColorType::L16)?;
+        Ok(())
+    }
+
+    // Loads the depth values saved by save_depth().
+    pub fn load_depth<P: AsRef<Path>>(path: P) -> Result<Buffer<u16>, ImageIoError> {
+        let path: &Path = path.as_ref();
+        let image: image::DynamicImage = image::open(path)?;
+        let mut buffer: Buffer<u16> = new_buffer(image.width(), image.height())?;
+        if is_floating_point_format(path) {
+            let values: image::Rgb32FImage = image.into_rgb32f();
+            for (depth, value) in buffer.elems.iter_mut().zip(values.pixels()) {
+                *depth = (value[0].clamp(0.0, 1.0) * u16::MAX as f32).round() as u16;
+            }
+        } else {
+            buffer.elems.copy_from_slice(image.into_luma16().as_raw());
+        }
+        Ok(buffer)
+    }
+}
+
+impl Buffer<f32> {
+    // Saves the reverse-Z depth values, see encode_depth_f32(), as they are. Requires a floating-point format, e.g. EXR.
+    pub fn save_depth<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageIoError> {
+        let values: Vec<f32> = self.rows().flatten().copied().collect();
+        save_floats(path.as_ref(), self.width, self.height, &values)
+    }
+
+    // Loads the depth values saved by save_depth().
+    pub fn load_depth<P: AsRef<Path>>(path: P) -> Result<Buffer<f32>, ImageIoError> {
+        let image: image::Rgb32FImage = image::open(path)?.into_rgb32f();
+        let mut buffer: Buffer<f32> = new_buffer(image.width(), image.height())?;
+        for (depth, value) in buffer.elems.iter_mut().zip(image.pixels()) {
+            *depth = value[0];
+        }
+        Ok(buffer)
+    }
+}
+
+// The same for the tiled buffers, via their flat copies.
+impl<const W: usize, const H: usize> TiledBuffer<u32, W, H> {
+    pub fn save_color<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageIoError> {
+        self.as_flat_buffer().save_color(path)
+    }
+
+    pub fn load_color<P: AsRef<Path>>(path: P) -> Result<Self, ImageIoError> {
+        Ok(Self::from_flat_buffer(&Buffer::<u32>::load_color(path)?))
+    }
+
+    pub fn save_normals<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageIoError> {
+        self.as_flat_buffer().save_normals(path)
+    }
+
+    pub fn load_normals<P: AsRef<Path>>(path: P) -> Result<Self, ImageIoError> {
+        Ok(Self::from_flat_buffer(&Buffer::<u32>::load_normals(path)?))
+    }
+}
+
+impl<const W: usize, const H: usize> TiledBuffer<u16, W, H> {
+    pub fn save_depth<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageIoError> {
+        self.as_flat_buffer().save_depth(path)
+    }
+
+    pub fn load_depth<P: AsRef<Path>>(path: P) -> Result<Self, ImageIoError> {
+        Ok(Self::from_flat_buffer(&Buffer::<u16>::load_depth(path)?))
+    }
+}
+
+impl<const W: usize, const H: usize> TiledBuffer<f32, W, H> {
+    pub fn save_depth<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageIoError> {
+        self.as_flat_buffer().save_depth(path)
+    }
+
+    pub fn load_depth<P: AsRef<Path>>(path: P) -> Result<Self, ImageIoError> {
+        Ok(Self::from_flat_buffer(&Buffer::<f32>::load_depth(path)?))
+    }
+}
+
+fn new_buffer<T: Copy + bytemuck::Zeroable + bytemuck::Pod>(
+    width: u32,
+    height: u32,
+) -> Result<Buffer<T>, ImageIoError> {
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(ImageIoError::TooLarge { width, height });
+    }
+    Ok(Buffer::new(width as u16, height as u16))
+}
+
+fn is_floating_point_format(path: &Path) -> bool {
+    matches!(image::ImageFormat::from_path(path), Ok(image::ImageFormat::OpenExr | image::ImageFormat::Hdr))
+}
+
+// Saves the values as a grayscale image with 32-bit float channels, replicated into RGB for the formats like EXR which
+// don't have grayscale.
+fn save_floats(path: &Path, width: u16, height: u16, values: &[f32]) -> Result<(), ImageIoError> {
+    let rgb: Vec<f32> = values.iter().flat_map(|&value| [value, value, value]).collect();
+    let image = image::Rgb32FImage::from_raw(width as u32, height as u32, rgb).unwrap();
+    image::DynamicImage::ImageRgb32F(image).save(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("nih_image_io_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn buffers_round_trip() {
+        let mut tiled = TiledBuffer::<u32, 4, 4>::new(6, 5);
+        let mut depth = Buffer::<u16>::new(6, 5);
+        let mut depth_f32 = Buffer::<f32>::new(6, 5);
+        for y in 0..5u16 {
+            for x in 0..6u16 {
+                *tiled.at_mut(x, y) = RGBA::new(x as u8 * 40, y as u8 * 50, 7, 200).to_u32();
+                *depth.at_mut(x, y) = x * 10_000 + y * 7;
+                *depth_f32.at_mut(x, y) = x as f32 * 0.125 + y as f32 / 3.0;
+            }
+        }
+
+        let path = temp_path("color.png");
+        tiled.save_color(&path).unwrap();
+        let loaded = TiledBuffer::<u32, 4, 4>::load_color(&path).unwrap();
+        assert_eq!((loaded.width(), loaded.height()), (6, 5));
+        assert_eq!(loaded.as_flat_buffer().elems, tiled.as_flat_buffer().elems);
+
+        // The normals lose nothing but their zero alpha
+        let path = temp_path("normals.png");
+        tiled.save_normals(&path).unwrap();
+        let normals = Buffer::<u32>::load_normals(&path).unwrap();
+        assert_eq!(RGBA::from_u32(normals.at(5, 4)), RGBA::new(200, 200, 7, 0));
+
+        for name in ["depth.png", "depth.exr"] {
+            let path = temp_path(name);
+            depth.save_depth(&path).unwrap();
+            assert_eq!(Buffer::<u16>::load_depth(&path).unwrap().elems, depth.elems, "{}", name);
+        }
+        let path = temp_path("depth_f32.exr");
+        depth_f32.save_depth(&path).unwrap();
+        assert_eq!(TiledBuffer::<f32, 4, 4>::load_depth(&path).unwrap().as_flat_buffer().elems, depth_f32.elems);
+
+        assert!(matches!(Buffer::<u32>::load_color(temp_path("missing.png")), Err(ImageIoError::Image(_))));
+        for name in ["color.png", "normals.png", "depth.png", "depth.exr", "depth_f32.exr"] {
+            std::fs::remove_file(temp_path(name)).unwrap();
+        }
+    }
+}
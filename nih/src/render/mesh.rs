@@ -1,18 +1,239 @@
 use super::super::math::*;
+use super::*;
+use std::ops::Range;
+use std::sync::Arc;
 
+// A named range of the mesh's triangles drawn with a single material, e.g. a group of an .obj file.
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct MeshDataSection {
+    // Optional name to look the section up by, e.g. "wheels".
+    pub name: String,
+
+    // The first index of the section in MeshData::indices, or the first vertex if the mesh isn't indexed.
     pub start_index: usize,
     pub num_triangles: usize,
+
+    // The slot of the section's material in the list of materials the mesh is drawn with.
     pub material_index: usize,
 }
 
-#[derive(Default)]
+impl MeshDataSection {
+    // The range of the mesh's indices (or vertices, if the mesh isn't indexed) covered by the section.
+    pub fn index_range(&self) -> Range<usize> {
+        self.start_index..self.start_index + self.num_triangles * 3
+    }
+}
+
+// Geometry of a loaded model, meant to be built once and then shared as Arc<MeshData> between the draws, so that
+// every section can be committed with its own material without copying the vertex attributes.
+#[derive(Debug, Clone, Default)]
 pub struct MeshData {
     pub positions: Vec<Vec3>,
     pub normals: Vec<Vec3>,
     pub tex_coords: Vec<Vec2>,
     pub colors: Vec<Vec4>, // empty if absent
+
+    // Unit vectors along the direction of the increasing U texture coordinate, empty if absent.
+    // Not consumed by the rasterizer yet, it derives the tangents from the texture coordinates on its own.
+    pub tangents: Vec<Vec3>,
+
     pub indices: Vec<u32>,
     pub sections: Vec<MeshDataSection>,
+
+    // The bounding box of the positions in object space, see update_aabb().
     pub aabb: AABB,
 }
+
+impl MeshData {
+    // A flat grid of columns x rows cells on the XZ plane centered at the origin, facing +Y, e.g. a terrain or a water
+    // surface to displace with a heightmap, see HeightmapDisplacement. The texture coordinates go from (0, 0) at the
+    // -X-Z corner to (1, 1) at the +X+Z one. The triangles are counter-clockwise when seen from above.
+    pub fn grid(columns: u32, rows: u32, size: Vec2) -> MeshData {
+        assert!(columns > 0 && rows > 0);
+        let mut mesh = MeshData::default();
+        for j in 0..=rows {
+            for i in 0..=columns {
+                let uv = Vec2::new(i as f32 / columns as f32, j as f32 / rows as f32);
+                mesh.positions
+                    .push(Vec3::new((uv.x - 0.5) * size.x, 0.0, (uv.y - 0.5) * size.y));
+                mesh.normals.push(Vec3::new(0.0, 1.0, 0.0));
+                mesh.tex_coords.push(uv);
+            }
+        }
+        let vertex = |i: u32, j: u32| -> u32 { j * (columns + 1) + i };
+        for j in 0..rows {
+            for i in 0..columns {
+                let (a, b, c, d) = (vertex(i, j), vertex(i, j + 1), vertex(i + 1, j), vertex(i + 1, j + 1));
+                mesh.indices.extend_from_slice(&[a, b, c, c, b, d]);
+            }
+        }
+        mesh.sections
+            .push(MeshDataSection { num_triangles: (columns * rows * 2) as usize, ..Default::default() });
+        mesh.update_aabb();
+        mesh
+    }
+
+    // Recalculates the bounding box from the positions.
+    pub fn update_aabb(&mut self) {
+        self.aabb = AABB::from_points(&self.positions);
+    }
+
+    // Finishes building the mesh: calculates the bounding box and makes the mesh shareable and immutable.
+    pub fn into_shared(mut self) -> Arc<MeshData> {
+        self.update_aabb();
+        Arc::new(self)
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        !self.indices.is_empty()
+    }
+
+    pub fn num_triangles(&self) -> usize {
+        if self.is_indexed() {
+            self.indices.len() / 3
+        } else {
+            self.positions.len() / 3
+        }
+    }
+
+    // The indices of the triangle's vertices in the attributes.
+    pub fn triangle(&self, index: usize) -> [usize; 3] {
+        if self.is_indexed() {
+            let i: &[u32] = &self.indices[index * 3..index * 3 + 3];
+            [i[0] as usize, i[1] as usize, i[2] as usize]
+        } else {
+            [index * 3, index * 3 + 1, index * 3 + 2]
+        }
+    }
+
+    // The first section with the given name, if any.
+    pub fn section(&self, name: &str) -> Option<&MeshDataSection> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    // A command drawing the entire mesh with its attributes and bounding box, the rest of the command's fields are
+    // default and can be filled with the material via the struct update syntax:
+    // RasterizationCommand { texture, ..mesh.command() }
+    pub fn command(&self) -> RasterizationCommand<'_> {
+        RasterizationCommand {
+            world_positions: &self.positions,
+            normals: &self.normals,
+            tex_coords: &self.tex_coords,
+            colors: &self.colors,
+            indices: &self.indices,
+            aabb: self.bounds(),
+            ..Default::default()
+        }
+    }
+
+    // Same as command(), but only draws the section's triangles.
+    // The section's vertices are sliced out of the non-indexed meshes, the indexed ones share all vertices.
+    pub fn section_command(&self, section: &MeshDataSection) -> RasterizationCommand<'_> {
+        let range: Range<usize> = section.index_range();
+        if self.is_indexed() {
+            return RasterizationCommand { indices: &self.indices[range], ..self.command() };
+        }
+        fn slice<T>(attribute: &[T], range: Range<usize>) -> &[T] {
+            if attribute.is_empty() {
+                attribute
+            } else {
+                &attribute[range]
+            }
+        }
+        RasterizationCommand {
+            world_positions: slice(&self.positions, range.clone()),
+            normals: slice(&self.normals, range.clone()),
+            tex_coords: slice(&self.tex_coords, range.clone()),
+            colors: slice(&self.colors, range),
+            ..self.command()
+        }
+    }
+
+    // The bounding box unless it wasn't calculated.
+    fn bounds(&self) -> Option<AABB> {
+        if self.aabb.min == self.aabb.max {
+            None
+        } else {
+            Some(self.aabb)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two triangles: "left" with material 0 and "right" with material 1
+    fn quad(indexed: bool) -> MeshData {
+        let corners: [Vec3; 4] = [
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(-1.0, 1.0, 0.0),
+        ];
+        let order: [u32; 6] = [0, 2, 3, 0, 1, 2];
+        let mut mesh = if indexed {
+            MeshData { positions: corners.to_vec(), indices: order.to_vec(), ..Default::default() }
+        } else {
+            MeshData { positions: order.iter().map(|&i| corners[i as usize]).collect(), ..Default::default() }
+        };
+        mesh.sections.push(MeshDataSection {
+            name: "left".to_string(),
+            start_index: 0,
+            num_triangles: 1,
+            material_index: 0,
+        });
+        mesh.sections.push(MeshDataSection {
+            name: "right".to_string(),
+            start_index: 3,
+            num_triangles: 1,
+            material_index: 1,
+        });
+        mesh
+    }
+
+    #[test]
+    fn shared_mesh_has_bounds() {
+        let mesh: Arc<MeshData> = quad(true).into_shared();
+        assert_eq!(mesh.aabb, AABB::new(Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 0.0)));
+        assert_eq!(mesh.command().aabb, Some(mesh.aabb));
+        assert_eq!(quad(true).command().aabb, None);
+        assert_eq!(mesh.num_triangles(), 2);
+    }
+
+    #[test]
+    fn sections_are_looked_up_by_name() {
+        let mesh: MeshData = quad(true);
+        assert_eq!(mesh.section("right").unwrap().material_index, 1);
+        assert_eq!(mesh.section("right").unwrap().index_range(), 3..6);
+        assert!(mesh.section("top").is_none());
+    }
+
+    #[test]
+    fn section_commands_share_the_attributes() {
+        let mesh: Arc<MeshData> = quad(true).into_shared();
+        let command = mesh.section_command(mesh.section("right").unwrap());
+        assert_eq!(command.indices, &[0, 1, 2]);
+        assert_eq!(command.world_positions.as_ptr(), mesh.positions.as_ptr());
+        assert_eq!(command.world_positions.len(), 4);
+
+        let mesh: Arc<MeshData> = quad(false).into_shared();
+        let command = mesh.section_command(mesh.section("right").unwrap());
+        assert!(command.indices.is_empty());
+        assert_eq!(command.world_positions, &mesh.positions[3..6]);
+        assert!(command.normals.is_empty());
+    }
+
+    #[test]
+    fn grid_faces_up() {
+        let mesh: MeshData = MeshData::grid(4, 2, Vec2::new(8.0, 2.0));
+        assert_eq!((mesh.positions.len(), mesh.num_triangles()), (15, 16));
+        assert_eq!(mesh.aabb, AABB::new(Vec3::new(-4.0, 0.0, -1.0), Vec3::new(4.0, 0.0, 1.0)));
+        assert_eq!(mesh.tex_coords[14], Vec2::new(1.0, 1.0));
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|k| mesh.positions[triangle[k] as usize]);
+            let normal: Vec3 = cross(b - a, c - a);
+            assert!(normal.y > 0.0 && normal.x == 0.0 && normal.z == 0.0);
+        }
+    }
+}
@@ -1,25 +1,75 @@
+pub mod animation;
+pub mod billboard;
 pub mod buffer;
+pub mod camera;
 pub mod clipper;
+pub mod displacement;
 pub mod draw_lines;
+pub mod environment;
+pub mod fill;
+pub mod frame_graph;
 pub mod framebuffer;
+pub mod hdr;
+pub mod image_io;
 pub mod mesh;
+pub mod morph_targets;
+pub mod nine_patch;
+pub mod occlusion;
+pub mod particles;
+pub mod pipeline;
+pub mod post;
 pub mod rasterizer;
+pub mod recording;
 pub mod rgba;
 pub mod sampler;
+pub mod scene_graph;
+pub mod sprite;
+pub mod stroke;
+pub mod surface;
+pub mod terrain;
+pub mod text;
 pub mod texture;
+pub mod texture_compression;
 pub mod tiled_buffer;
 pub mod vertex;
+pub mod vertex_animation;
 pub mod viewport;
+pub mod world;
 
+pub use animation::*;
+pub use billboard::*;
 pub use buffer::*;
+pub use camera::*;
 pub use clipper::*;
+pub use displacement::*;
 pub use draw_lines::*;
+pub use environment::*;
+pub use fill::*;
+pub use frame_graph::*;
 pub use framebuffer::*;
+pub use hdr::*;
+pub use image_io::*;
 pub use mesh::*;
+pub use morph_targets::*;
+pub use nine_patch::*;
+pub use occlusion::*;
+pub use particles::*;
+pub use pipeline::*;
+pub use post::*;
 pub use rasterizer::*;
+pub use recording::*;
 pub use rgba::*;
 pub use sampler::*;
+pub use scene_graph::*;
+pub use sprite::*;
+pub use stroke::*;
+pub use surface::*;
+pub use terrain::*;
+pub use text::*;
 pub use texture::*;
+pub use texture_compression::*;
 pub use tiled_buffer::*;
 pub use vertex::*;
+pub use vertex_animation::*;
 pub use viewport::*;
+pub use world::*;
@@ -0,0 +1,93 @@
+use super::super::math::*;
+
+// A blend shape of a mesh, e.g. a smile or a blink of a face: the offsets of the vertices from the base mesh at the
+// shape's full weight.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct MorphTarget<'a> {
+    // The offsets of the positions, indexed the same as the command's world_positions. Can be shorter than the
+    // positions, the rest of the vertices aren't moved by the target.
+    pub position_deltas: &'a [Vec3],
+
+    // The offsets of the normals laid out the same way, empty if the target doesn't change the normals.
+    pub normal_deltas: &'a [Vec3],
+}
+
+// The blending of a command's vertices between morph targets: each vertex is offset by the sum of the targets' deltas
+// multiplied by their weights, the normals are offset the same way and renormalized.
+// The blended vertices replace the original ones after the vertex animation and before the skinning. The bounding box,
+// if there's one, must cover the blended positions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MorphTargets<'a> {
+    pub targets: &'a [MorphTarget<'a>],
+
+    // The weight of each target, usually in [0, 1]. The targets without a weight or with a zero weight are skipped.
+    pub weights: &'a [f32],
+}
+
+impl<'a> MorphTargets<'a> {
+    pub fn new(targets: &'a [MorphTarget<'a>], weights: &'a [f32]) -> Self {
+        Self { targets, weights }
+    }
+
+    // Whether any of the targets has a non-zero weight, i.e. the blending changes the vertices.
+    pub fn is_active(&self) -> bool {
+        self.targets.iter().zip(self.weights).any(|(_, &weight)| weight != 0.0)
+    }
+
+    // Adds the weighted deltas of the targets to the positions and the normals in place, the normals are renormalized.
+    // The normals can be empty.
+    pub(crate) fn apply(&self, positions: &mut [Vec3], normals: &mut [Vec3]) {
+        let mut moves_normals: bool = false;
+        for (target, &weight) in self.targets.iter().zip(self.weights) {
+            if weight == 0.0 {
+                continue;
+            }
+            for (position, &delta) in positions.iter_mut().zip(target.position_deltas) {
+                *position += delta * weight;
+            }
+            for (normal, &delta) in normals.iter_mut().zip(target.normal_deltas) {
+                *normal += delta * weight;
+                moves_normals = true;
+            }
+        }
+        if moves_normals {
+            for normal in normals.iter_mut() {
+                *normal = normal.normalized();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weighted_deltas_are_summed() {
+        let up = [Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 2.0, 0.0)];
+        let right = [Vec3::new(1.0, 0.0, 0.0)];
+        let tilt = [Vec3::new(1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)];
+        let targets = [
+            MorphTarget { position_deltas: &up, normal_deltas: &[] },
+            MorphTarget { position_deltas: &right, normal_deltas: &tilt },
+        ];
+        let mut positions = [Vec3::new(0.0, 0.0, 0.0), Vec3::new(5.0, 0.0, 0.0)];
+        let mut normals = [Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)];
+
+        let morph = MorphTargets::new(&targets, &[0.5, 0.0]);
+        assert!(morph.is_active());
+        morph.apply(&mut positions, &mut normals);
+        assert_eq!(positions, [Vec3::new(0.0, 0.5, 0.0), Vec3::new(5.0, 1.0, 0.0)]);
+        assert_eq!(normals, [Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)]);
+
+        // The shorter deltas leave the rest of the vertices as they are
+        let morph = MorphTargets::new(&targets, &[0.0, 1.0]);
+        morph.apply(&mut positions, &mut normals);
+        assert_eq!(positions, [Vec3::new(1.0, 0.5, 0.0), Vec3::new(5.0, 1.0, 0.0)]);
+        let half: f32 = std::f32::consts::FRAC_1_SQRT_2;
+        assert!((normals[0] - Vec3::new(half, half, 0.0)).length() < 1e-5);
+        assert!((normals[1] - Vec3::new(half, 0.0, half)).length() < 1e-5);
+
+        assert!(!MorphTargets::new(&targets, &[0.0]).is_active());
+    }
+}
@@ -0,0 +1,204 @@
+use super::super::math::*;
+use super::*;
+
+// A scalable UI panel drawn from an image split into 3x3 cells, a.k.a. nine-slice: the corners keep their size, the
+// edges are stretched along one axis and the center along both. The image can be a part of a texture atlas.
+// Committed as regular textured triangles via Rasterizer::commit_nine_patch(), so it's ordered and blended the same
+// way as the other geometry.
+#[derive(Debug, Clone)]
+pub struct NinePatchCommand {
+    // The panel's rectangle in pixels, relative to the viewport's top-left corner.
+    pub min: Vec2,
+    pub max: Vec2,
+
+    // The panel's image. Without a texture the panel is filled with the color.
+    // Default: None.
+    pub texture: Option<std::sync::Arc<Texture>>,
+
+    // The part of the texture containing the image, e.g. a cell of an atlas. Sampling is always clamped within it.
+    // Default: None, i.e. the entire texture.
+    pub region: Option<TextureRegion>,
+
+    // The widths of the image's borders which are not stretched, in texels.
+    // Default: 0.0, i.e. the whole image is stretched.
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+
+    // The size of a border's texel on the screen, in pixels, e.g. 2.0 for high-DPI displays.
+    // The borders are scaled down further if the panel is too small to fit them.
+    // Default: 1.0.
+    pub scale: f32,
+
+    // The color multiplied by the image, not premultiplied by alpha.
+    // Default: (1, 1, 1, 1).
+    pub color: Vec4,
+
+    // Set the filter to be used when sampling the texture.
+    // Default: nearest.
+    pub sampling_filter: SamplerFilter,
+
+    // Sets whether the panel should be alpha-blended with the framebuffer, the same way as RasterizationCommand does.
+    // Default: None.
+    pub alpha_blending: AlphaBlendingMode,
+}
+
+impl Default for NinePatchCommand {
+    fn default() -> Self {
+        Self {
+            min: Vec2::new(0.0, 0.0),
+            max: Vec2::new(0.0, 0.0),
+            texture: None,
+            region: None,
+            left: 0.0,
+            top: 0.0,
+            right: 0.0,
+            bottom: 0.0,
+            scale: 1.0,
+            color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            sampling_filter: SamplerFilter::Nearest,
+            alpha_blending: AlphaBlendingMode::None,
+        }
+    }
+}
+
+impl NinePatchCommand {
+    // The number of vertices appended by append_geometry(): two triangles per each of the 3x3 cells.
+    pub const VERTICES_NUM: usize = 54;
+
+    // Appends the triangles of the 3x3 cells, with positions in pixels relative to the viewport's top-left corner and
+    // texture coordinates in [0, 1] over the region, i.e. to be sampled with the region set as the texture_region.
+    // Cells collapsed to zero width or height are still emitted and are discarded by the rasterizer as degenerate.
+    pub fn append_geometry(&self, positions: &mut Vec<Vec2>, tex_coords: &mut Vec<Vec2>) {
+        let (region_width, region_height): (f32, f32) = match self.region.as_ref() {
+            Some(region) => ((region.u1 - region.u0).abs(), (region.v1 - region.v0).abs()),
+            None => (1.0, 1.0),
+        };
+        let (texture_width, texture_height): (f32, f32) = match self.texture.as_ref() {
+            Some(texture) => (texture.mips[0].width as f32, texture.mips[0].height as f32),
+            None => (1.0, 1.0),
+        };
+        // The region's size in texels
+        let image_width: f32 = (region_width * texture_width).max(1.0);
+        let image_height: f32 = (region_height * texture_height).max(1.0);
+
+        // Scale the borders down uniformly along an axis if they don't fit
+        let width: f32 = (self.max.x - self.min.x).max(0.0);
+        let height: f32 = (self.max.y - self.min.y).max(0.0);
+        let borders_x: f32 = (self.left + self.right) * self.scale;
+        let borders_y: f32 = (self.top + self.bottom) * self.scale;
+        let fit_x: f32 = if borders_x > width { width / borders_x } else { 1.0 };
+        let fit_y: f32 = if borders_y > height { height / borders_y } else { 1.0 };
+
+        let xs: [f32; 4] = [
+            self.min.x,
+            self.min.x + self.left * self.scale * fit_x,
+            self.max.x - self.right * self.scale * fit_x,
+            self.max.x,
+        ];
+        let ys: [f32; 4] = [
+            self.min.y,
+            self.min.y + self.top * self.scale * fit_y,
+            self.max.y - self.bottom * self.scale * fit_y,
+            self.max.y,
+        ];
+        let us: [f32; 4] = [0.0, self.left / image_width, 1.0 - self.right / image_width, 1.0];
+        let vs: [f32; 4] = [0.0, self.top / image_height, 1.0 - self.bottom / image_height, 1.0];
+
+        for row in 0..3 {
+            for column in 0..3 {
+                let p00: Vec2 = Vec2::new(xs[column], ys[row]);
+                let p10: Vec2 = Vec2::new(xs[column + 1], ys[row]);
+                let p01: Vec2 = Vec2::new(xs[column], ys[row + 1]);
+                let p11: Vec2 = Vec2::new(xs[column + 1], ys[row + 1]);
+                let t00: Vec2 = Vec2::new(us[column], vs[row]);
+                let t10: Vec2 = Vec2::new(us[column + 1], vs[row]);
+                let t01: Vec2 = Vec2::new(us[column], vs[row + 1]);
+                let t11: Vec2 = Vec2::new(us[column + 1], vs[row + 1]);
+                positions.extend_from_slice(&[p00, p10, p11, p00, p11, p01]);
+                tex_coords.extend_from_slice(&[t00, t10, t11, t00, t11, t01]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn borders_keep_their_size() {
+        let command = NinePatchCommand {
+            min: Vec2::new(10.0, 20.0),
+            max: Vec2::new(110.0, 70.0),
+            left: 4.0,
+            top: 5.0,
+            right: 6.0,
+            bottom: 7.0,
+            scale: 2.0,
+            ..Default::default()
+        };
+        let mut positions: Vec<Vec2> = Vec::new();
+        let mut tex_coords: Vec<Vec2> = Vec::new();
+        command.append_geometry(&mut positions, &mut tex_coords);
+        assert_eq!(positions.len(), NinePatchCommand::VERTICES_NUM);
+        assert_eq!(tex_coords.len(), NinePatchCommand::VERTICES_NUM);
+        // The top-left corner
+        assert_eq!(positions[0], Vec2::new(10.0, 20.0));
+        assert_eq!(positions[2], Vec2::new(18.0, 30.0));
+        // The bottom-right corner
+        assert_eq!(positions[8 * 6], Vec2::new(98.0, 56.0));
+        assert_eq!(positions[8 * 6 + 2], Vec2::new(110.0, 70.0));
+    }
+
+    #[test]
+    fn borders_are_fit_into_small_panels() {
+        let command = NinePatchCommand {
+            min: Vec2::new(0.0, 0.0),
+            max: Vec2::new(10.0, 100.0),
+            left: 10.0,
+            right: 10.0,
+            top: 10.0,
+            bottom: 10.0,
+            ..Default::default()
+        };
+        let mut positions: Vec<Vec2> = Vec::new();
+        let mut tex_coords: Vec<Vec2> = Vec::new();
+        command.append_geometry(&mut positions, &mut tex_coords);
+        // Squeezed horizontally, the center column collapses
+        assert_eq!(positions[2], Vec2::new(5.0, 10.0));
+        assert_eq!(positions[6 + 2], Vec2::new(5.0, 10.0));
+        // Intact vertically
+        assert_eq!(positions[8 * 6], Vec2::new(5.0, 90.0));
+    }
+
+    #[test]
+    fn region_of_an_atlas() {
+        let texture = Texture::new(&TextureSource {
+            texels: &[255u8; 64 * 64],
+            width: 64,
+            height: 64,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        });
+        let command = NinePatchCommand {
+            max: Vec2::new(100.0, 100.0),
+            texture: Some(texture),
+            region: Some(TextureRegion { u0: 0.5, v0: 0.0, u1: 1.0, v1: 0.25, clamp: true }),
+            left: 8.0,
+            top: 4.0,
+            right: 8.0,
+            bottom: 4.0,
+            ..Default::default()
+        };
+        let mut positions: Vec<Vec2> = Vec::new();
+        let mut tex_coords: Vec<Vec2> = Vec::new();
+        command.append_geometry(&mut positions, &mut tex_coords);
+        // The region is 32x16 texels
+        assert_eq!(tex_coords[0], Vec2::new(0.0, 0.0));
+        assert_eq!(tex_coords[2], Vec2::new(0.25, 0.25));
+        assert_eq!(tex_coords[8 * 6], Vec2::new(0.75, 0.75));
+        assert_eq!(tex_coords[8 * 6 + 2], Vec2::new(1.0, 1.0));
+    }
+}
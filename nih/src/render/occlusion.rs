@@ -0,0 +1,301 @@
+use super::super::math::*;
+use super::*;
+
+// A low-resolution software depth buffer for culling whole objects on the CPU before they are committed, similar to
+// Intel's Masked Occlusion Culling.
+// Each frame, big occluders (walls, floors, buildings) are rasterized into the buffer as simplified proxies, then the
+// bounding boxes of the objects are tested against it, and only the potentially visible objects are committed.
+// This is independent from the rasterizer's per-tile hierarchical Z test, which can only reject the triangles that
+// were already transformed, clipped and binned.
+// The occluders are sampled at the pixel centers, so their proxies should lie within the actual geometry, otherwise
+// an object peeking through a gap narrower than a pixel of the buffer can be culled.
+pub struct OcclusionBuffer {
+    width: u16,
+    height: u16,
+    view_projection: Mat44,
+
+    // NDC depth per pixel, [-1, 1], 1.0 being the far plane.
+    depth: Vec<f32>,
+
+    // The farthest depth of each 8x8 block of pixels, kept up to date after each occluder.
+    blocks: Vec<f32>,
+    blocks_x: usize,
+}
+
+impl OcclusionBuffer {
+    const BLOCK_SIZE: usize = 8;
+
+    // Creates the buffer of the given size in pixels, e.g. 256x128 for a 16:9 view. The aspect ratio should match
+    // the one of the view, the resolution trades the culling precision for the speed.
+    pub fn new(width: u16, height: u16) -> Self {
+        assert!(width > 0 && height > 0);
+        let blocks_x: usize = (width as usize).div_ceil(Self::BLOCK_SIZE);
+        let blocks_y: usize = (height as usize).div_ceil(Self::BLOCK_SIZE);
+        Self {
+            width,
+            height,
+            view_projection: Mat44::identity(),
+            depth: vec![1.0; width as usize * height as usize],
+            blocks: vec![1.0; blocks_x * blocks_y],
+            blocks_x,
+        }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    // Resets the buffer to the far plane and sets the view-projection of the frame, i.e. projection * view.
+    pub fn clear(&mut self, view_projection: Mat44) {
+        self.view_projection = view_projection;
+        self.depth.fill(1.0);
+        self.blocks.fill(1.0);
+    }
+
+    // The NDC depth stored at the pixel, with (0, 0) being the top-left corner.
+    pub fn depth_at(&self, x: u16, y: u16) -> f32 {
+        self.depth[y as usize * self.width as usize + x as usize]
+    }
+
+    // Rasterizes the occluder's triangles into the buffer, both the front and the back faces.
+    // Indices are optional, same as in RasterizationCommand.
+    pub fn add_occluder(&mut self, positions: &[Vec3], indices: &[u32], model: Mat34) {
+        let triangles_num: usize = if indices.is_empty() {
+            positions.len() / 3
+        } else {
+            indices.len() / 3
+        };
+        let mvp: Mat44 = self.view_projection * model.as_mat44();
+        let (mut dirty_min, mut dirty_max): ((usize, usize), (usize, usize)) = ((usize::MAX, usize::MAX), (0, 0));
+        for i in 0..triangles_num {
+            let index = |n: usize| -> usize {
+                if indices.is_empty() {
+                    i * 3 + n
+                } else {
+                    indices[i * 3 + n] as usize
+                }
+            };
+            let mut vertices: [Vertex; 3] = [Vertex::default(); 3];
+            for (n, vertex) in vertices.iter_mut().enumerate() {
+                vertex.position = mvp * positions[index(n)].as_point4();
+            }
+            let clipped = clip_triangle(&vertices);
+            if clipped.is_empty() {
+                continue;
+            }
+            let screen: Vec<Vec3> = clipped.iter().map(|v| self.to_screen(v.position)).collect();
+            for j in 1..screen.len() - 1 {
+                if let Some((min, max)) = self.rasterize_triangle(screen[0], screen[j], screen[j + 1]) {
+                    dirty_min = (dirty_min.0.min(min.0), dirty_min.1.min(min.1));
+                    dirty_max = (dirty_max.0.max(max.0), dirty_max.1.max(max.1));
+                }
+            }
+        }
+        if dirty_min.0 <= dirty_max.0 {
+            self.update_blocks(dirty_min, dirty_max);
+        }
+    }
+
+    // Tests whether any part of the box transformed by the model matrix can be visible, i.e. is in front of the
+    // occluders rasterized so far. Boxes crossing the camera's plane are always considered visible, boxes entirely
+    // outside the view are not.
+    pub fn is_visible(&self, aabb: &AABB, model: Mat34) -> bool {
+        let mvp: Mat44 = self.view_projection * model.as_mat44();
+        let mut min: Vec3 = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max: Vec3 = Vec3::new(f32::MIN, f32::MIN, f32::MIN);
+        let mut behind: usize = 0;
+        for corner in 0..8 {
+            let point: Vec3 = Vec3::new(
+                if corner & 1 == 0 { aabb.min.x } else { aabb.max.x },
+                if corner & 2 == 0 { aabb.min.y } else { aabb.max.y },
+                if corner & 4 == 0 { aabb.min.z } else { aabb.max.z },
+            );
+            let clip: Vec4 = mvp * point.as_point4();
+            if clip.w <= f32::EPSILON {
+                behind += 1;
+                continue;
+            }
+            let screen: Vec3 = self.to_screen(clip);
+            min = Vec3::new(min.x.min(screen.x), min.y.min(screen.y), min.z.min(screen.z));
+            max = Vec3::new(max.x.max(screen.x), max.y.max(screen.y), max.z.max(screen.z));
+        }
+        match behind {
+            0 => {}
+            8 => return false,
+            _ => return true,
+        }
+        if min.z < -1.0 {
+            return true;
+        }
+        if min.z > 1.0 || max.x <= 0.0 || max.y <= 0.0 || min.x >= self.width as f32 || min.y >= self.height as f32 {
+            return false;
+        }
+
+        // Every pixel touched by the box's screen bounds must be closer than the box's nearest point
+        let xmin: usize = min.x.floor().max(0.0) as usize;
+        let ymin: usize = min.y.floor().max(0.0) as usize;
+        let xmax: usize = (max.x.ceil() as usize).clamp(xmin + 1, self.width as usize);
+        let ymax: usize = (max.y.ceil() as usize).clamp(ymin + 1, self.height as usize);
+        let nearest: f32 = min.z;
+        for block_y in ymin / Self::BLOCK_SIZE..=(ymax - 1) / Self::BLOCK_SIZE {
+            for block_x in xmin / Self::BLOCK_SIZE..=(xmax - 1) / Self::BLOCK_SIZE {
+                if self.blocks[block_y * self.blocks_x + block_x] < nearest {
+                    continue;
+                }
+                let y_range = (block_y * Self::BLOCK_SIZE).max(ymin)..((block_y + 1) * Self::BLOCK_SIZE).min(ymax);
+                let x_range = (block_x * Self::BLOCK_SIZE).max(xmin)..((block_x + 1) * Self::BLOCK_SIZE).min(xmax);
+                for y in y_range {
+                    let row: &[f32] = &self.depth[y * self.width as usize..(y + 1) * self.width as usize];
+                    if row[x_range.clone()].iter().any(|&depth| depth >= nearest) {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    // Clip space -> the buffer's pixels and NDC depth.
+    fn to_screen(&self, clip: Vec4) -> Vec3 {
+        let inv_w: f32 = 1.0 / clip.w;
+        Vec3::new(
+            (clip.x * inv_w * 0.5 + 0.5) * self.width as f32,
+            (0.5 - clip.y * inv_w * 0.5) * self.height as f32,
+            clip.z * inv_w,
+        )
+    }
+
+    // Writes the nearest depth of the pixels whose centers are inside the triangle.
+    // Returns the range of the touched pixels, inclusive, if any.
+    fn rasterize_triangle(&mut self, v0: Vec3, v1: Vec3, v2: Vec3) -> Option<((usize, usize), (usize, usize))> {
+        let area: f32 = (v1.x - v0.x) * (v2.y - v0.y) - (v1.y - v0.y) * (v2.x - v0.x);
+        if area.abs() <= f32::EPSILON {
+            return None;
+        }
+        let inv_area: f32 = 1.0 / area;
+        let xmin: f32 = (v0.x.min(v1.x).min(v2.x) - 0.5).ceil().max(0.0);
+        let ymin: f32 = (v0.y.min(v1.y).min(v2.y) - 0.5).ceil().max(0.0);
+        let xmax: f32 = (v0.x.max(v1.x).max(v2.x) - 0.5).floor().min(self.width as f32 - 1.0);
+        let ymax: f32 = (v0.y.max(v1.y).max(v2.y) - 0.5).floor().min(self.height as f32 - 1.0);
+        if xmin > xmax || ymin > ymax {
+            return None;
+        }
+        let (xmin, ymin, xmax, ymax) = (xmin as usize, ymin as usize, xmax as usize, ymax as usize);
+        for y in ymin..=ymax {
+            let py: f32 = y as f32 + 0.5;
+            for x in xmin..=xmax {
+                let px: f32 = x as f32 + 0.5;
+                // Barycentric weights, normalized by the signed area so that both windings are accepted
+                let w0: f32 = ((v1.x - px) * (v2.y - py) - (v1.y - py) * (v2.x - px)) * inv_area;
+                let w1: f32 = ((v2.x - px) * (v0.y - py) - (v2.y - py) * (v0.x - px)) * inv_area;
+                let w2: f32 = 1.0 - w0 - w1;
+                if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                    continue;
+                }
+                let z: f32 = w0 * v0.z + w1 * v1.z + w2 * v2.z;
+                let depth: &mut f32 = &mut self.depth[y * self.width as usize + x];
+                *depth = depth.min(z);
+            }
+        }
+        Some(((xmin, ymin), (xmax, ymax)))
+    }
+
+    // Recalculates the blocks overlapping the range of pixels, inclusive.
+    fn update_blocks(&mut self, min: (usize, usize), max: (usize, usize)) {
+        for block_y in min.1 / Self::BLOCK_SIZE..=max.1 / Self::BLOCK_SIZE {
+            for block_x in min.0 / Self::BLOCK_SIZE..=max.0 / Self::BLOCK_SIZE {
+                let mut farthest: f32 = f32::MIN;
+                for y in block_y * Self::BLOCK_SIZE..((block_y + 1) * Self::BLOCK_SIZE).min(self.height as usize) {
+                    for x in block_x * Self::BLOCK_SIZE..((block_x + 1) * Self::BLOCK_SIZE).min(self.width as usize) {
+                        farthest = farthest.max(self.depth[y * self.width as usize + x]);
+                    }
+                }
+                self.blocks[block_y * self.blocks_x + block_x] = farthest;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A camera at the origin looking along -Z
+    fn occlusion_buffer() -> OcclusionBuffer {
+        let mut buffer = OcclusionBuffer::new(64, 32);
+        buffer.clear(Mat44::perspective(0.1, 100.0, std::f32::consts::PI / 2.0, 2.0));
+        buffer
+    }
+
+    // A wall of the given half-width in the XY plane at the depth
+    fn wall(half_width: f32, z: f32) -> [Vec3; 6] {
+        [
+            Vec3::new(-half_width, -half_width, z),
+            Vec3::new(half_width, -half_width, z),
+            Vec3::new(half_width, half_width, z),
+            Vec3::new(-half_width, -half_width, z),
+            Vec3::new(half_width, half_width, z),
+            Vec3::new(-half_width, half_width, z),
+        ]
+    }
+
+    fn unit_box_at(center: Vec3) -> AABB {
+        AABB::new(center - Vec3::new(0.5, 0.5, 0.5), center + Vec3::new(0.5, 0.5, 0.5))
+    }
+
+    #[test]
+    fn everything_is_visible_without_occluders() {
+        let buffer = occlusion_buffer();
+        assert!(buffer.is_visible(&unit_box_at(Vec3::new(0.0, 0.0, -10.0)), Mat34::identity()));
+        assert!(buffer.is_visible(&unit_box_at(Vec3::new(0.0, 0.0, -99.0)), Mat34::identity()));
+        // Behind the camera and outside the view
+        assert!(!buffer.is_visible(&unit_box_at(Vec3::new(0.0, 0.0, 10.0)), Mat34::identity()));
+        assert!(!buffer.is_visible(&unit_box_at(Vec3::new(100.0, 0.0, -10.0)), Mat34::identity()));
+        // Crossing the near plane
+        assert!(buffer.is_visible(&unit_box_at(Vec3::new(0.0, 0.0, 0.0)), Mat34::identity()));
+    }
+
+    #[test]
+    fn boxes_behind_occluder_are_culled() {
+        let mut buffer = occlusion_buffer();
+        buffer.add_occluder(&wall(5.0, 0.0), &[], Mat34::translate(Vec3::new(0.0, 0.0, -5.0)));
+        assert!(buffer.depth_at(32, 16) < 1.0);
+        assert_eq!(buffer.depth_at(0, 0), 1.0);
+
+        // Behind the wall
+        assert!(!buffer.is_visible(&unit_box_at(Vec3::new(0.0, 0.0, -10.0)), Mat34::identity()));
+        assert!(!buffer.is_visible(&unit_box_at(Vec3::new(1.0, 1.0, -7.0)), Mat34::identity()));
+        // In front of the wall
+        assert!(buffer.is_visible(&unit_box_at(Vec3::new(0.0, 0.0, -3.0)), Mat34::identity()));
+        // Intersecting the wall
+        assert!(buffer.is_visible(&unit_box_at(Vec3::new(0.0, 0.0, -5.0)), Mat34::identity()));
+        // Behind the wall, but sticking out of its side
+        assert!(buffer.is_visible(&unit_box_at(Vec3::new(9.5, 0.0, -9.0)), Mat34::identity()));
+        // Moved behind the wall by the model matrix
+        let model: Mat34 = Mat34::translate(Vec3::new(0.0, 0.0, -20.0));
+        assert!(!buffer.is_visible(&unit_box_at(Vec3::new(0.0, 0.0, 0.0)), model));
+
+        // Cleared for the next frame
+        buffer.clear(Mat44::perspective(0.1, 100.0, std::f32::consts::PI / 2.0, 2.0));
+        assert!(buffer.is_visible(&unit_box_at(Vec3::new(0.0, 0.0, -10.0)), Mat34::identity()));
+    }
+
+    #[test]
+    fn indexed_occluders_of_either_winding() {
+        let positions: [Vec3; 4] = [
+            Vec3::new(-5.0, -5.0, -5.0),
+            Vec3::new(5.0, -5.0, -5.0),
+            Vec3::new(5.0, 5.0, -5.0),
+            Vec3::new(-5.0, 5.0, -5.0),
+        ];
+        for indices in [[0u32, 1, 2, 0, 2, 3], [0u32, 2, 1, 0, 3, 2]] {
+            let mut buffer = occlusion_buffer();
+            buffer.add_occluder(&positions, &indices, Mat34::identity());
+            assert!(!buffer.is_visible(&unit_box_at(Vec3::new(0.0, 0.0, -10.0)), Mat34::identity()));
+        }
+    }
+}
@@ -0,0 +1,427 @@
+use super::super::math::*;
+use super::*;
+use std::ops::Range;
+use std::sync::Arc;
+
+// A value changing over the life of a particle, given by keys at the normalized ages from 0 (spawned) to 1 (expired)
+// and linearly interpolated between them. The value of the first key is held before it, the value of the last one
+// after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParticleCurve<T> {
+    // Sorted by the age.
+    keys: Vec<(f32, T)>,
+}
+
+impl<T> ParticleCurve<T>
+where
+    T: Copy + std::ops::Add<Output = T> + std::ops::Mul<f32, Output = T>,
+{
+    // Panics if there are no keys.
+    pub fn new(keys: &[(f32, T)]) -> Self {
+        assert!(!keys.is_empty());
+        let mut keys: Vec<(f32, T)> = keys.to_vec();
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { keys }
+    }
+
+    pub fn constant(value: T) -> Self {
+        Self { keys: vec![(0.0, value)] }
+    }
+
+    // From the value at the spawn to the value at the expiration.
+    pub fn linear(from: T, to: T) -> Self {
+        Self { keys: vec![(0.0, from), (1.0, to)] }
+    }
+
+    pub fn sample(&self, age: f32) -> T {
+        let next: usize = self.keys.partition_point(|key| key.0 <= age);
+        if next == 0 {
+            return self.keys[0].1;
+        }
+        if next == self.keys.len() {
+            return self.keys[next - 1].1;
+        }
+        let (age0, value0) = self.keys[next - 1];
+        let (age1, value1) = self.keys[next];
+        let t: f32 = (age - age0) / (age1 - age0);
+        value0 * (1.0 - t) + value1 * t
+    }
+}
+
+// The description of how the particles of a ParticleSystem are spawned, moved and drawn.
+// The ranges are sampled uniformly per particle at its spawn, the vector ones per component.
+#[derive(Debug, Clone)]
+pub struct ParticleEmitter {
+    // The center of the box the particles are spawned in, in world space.
+    // Default: (0, 0, 0).
+    pub position: Vec3,
+
+    // The half-size of the spawning box around the position.
+    // Default: (0, 0, 0), i.e. a point.
+    pub extent: Vec3,
+
+    // The particles spawned per second, see also ParticleSystem::emit() for the bursts.
+    // Default: 10.
+    pub spawn_rate: f32,
+
+    // The particles above this number are not spawned until the older ones expire.
+    // Default: 1000.
+    pub max_particles: usize,
+
+    // In seconds.
+    // Default: 1..1.
+    pub lifetime: Range<f32>,
+
+    // The initial velocity in world units per second.
+    // Default: (0, 1, 0)..(0, 1, 0).
+    pub velocity: Range<Vec3>,
+
+    // Added to the velocities every second, e.g. gravity.
+    // Default: (0, 0, 0).
+    pub acceleration: Vec3,
+
+    // Multiplies the velocity over the life, e.g. to slow the particles down.
+    // Default: constant 1.
+    pub speed: ParticleCurve<f32>,
+
+    // The initial width and height of the billboards in world units.
+    // Default: 1..1.
+    pub size: Range<f32>,
+
+    // Multiplies the size over the life.
+    // Default: constant 1.
+    pub scale: ParticleCurve<f32>,
+
+    // The initial rotation of the billboards in radians, see BillboardCommand::rotations.
+    // Default: 0..0.
+    pub rotation: Range<f32>,
+
+    // The rotation speed in radians per second.
+    // Default: 0..0.
+    pub spin: Range<f32>,
+
+    // The per-particle color multiplier, not premultiplied by alpha.
+    // Default: (1, 1, 1, 1)..(1, 1, 1, 1).
+    pub tint: Range<Vec4>,
+
+    // The color over the life, multiplied by the tint.
+    // Default: constant (1, 1, 1, 1).
+    pub color: ParticleCurve<Vec4>,
+
+    // Default: None.
+    pub texture: Option<Arc<Texture>>,
+
+    // Default: Additive, which doesn't depend on the order of the particles.
+    pub alpha_blending: AlphaBlendingMode,
+
+    // Default: Spherical.
+    pub mode: BillboardMode,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        Self {
+            position: Vec3::new(0.0, 0.0, 0.0),
+            extent: Vec3::new(0.0, 0.0, 0.0),
+            spawn_rate: 10.0,
+            max_particles: 1000,
+            lifetime: 1.0..1.0,
+            velocity: Vec3::new(0.0, 1.0, 0.0)..Vec3::new(0.0, 1.0, 0.0),
+            acceleration: Vec3::new(0.0, 0.0, 0.0),
+            speed: ParticleCurve::constant(1.0),
+            size: 1.0..1.0,
+            scale: ParticleCurve::constant(1.0),
+            rotation: 0.0..0.0,
+            spin: 0.0..0.0,
+            tint: Vec4::new(1.0, 1.0, 1.0, 1.0)..Vec4::new(1.0, 1.0, 1.0, 1.0),
+            color: ParticleCurve::constant(Vec4::new(1.0, 1.0, 1.0, 1.0)),
+            texture: None,
+            alpha_blending: AlphaBlendingMode::Additive,
+            mode: BillboardMode::Spherical,
+        }
+    }
+}
+
+// The live particles of an emitter, simulated on the CPU by step() and drawn as billboards by commit().
+// The particles are stored as a structure of arrays, which is what BillboardCommand takes, so nothing is copied when
+// drawing. The random numbers come from the seed only, so the same steps always produce the same particles.
+#[derive(Debug, Clone)]
+pub struct ParticleSystem {
+    pub emitter: ParticleEmitter,
+
+    positions: Vec<Vec3>,
+    velocities: Vec<Vec3>,
+    ages: Vec<f32>,
+    lifetimes: Vec<f32>,
+    base_sizes: Vec<f32>,
+    rotations: Vec<f32>,
+    spins: Vec<f32>,
+    tints: Vec<Vec4>,
+
+    // Derived from the above by step() for drawing
+    sizes: Vec<Vec2>,
+    colors: Vec<Vec4>,
+
+    // The fraction of a particle left to be spawned by the next step
+    pending: f32,
+    random: ParticleRandom,
+}
+
+impl ParticleSystem {
+    pub fn new(emitter: ParticleEmitter, seed: u32) -> Self {
+        Self {
+            emitter,
+            positions: Vec::new(),
+            velocities: Vec::new(),
+            ages: Vec::new(),
+            lifetimes: Vec::new(),
+            base_sizes: Vec::new(),
+            rotations: Vec::new(),
+            spins: Vec::new(),
+            tints: Vec::new(),
+            sizes: Vec::new(),
+            colors: Vec::new(),
+            pending: 0.0,
+            random: ParticleRandom { state: seed },
+        }
+    }
+
+    // The number of live particles.
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    // The centers of the live particles in world space, in no particular order.
+    pub fn positions(&self) -> &[Vec3] {
+        &self.positions
+    }
+
+    // The colors of the live particles as of the last step, in the order of positions().
+    pub fn colors(&self) -> &[Vec4] {
+        &self.colors
+    }
+
+    // Removes all the particles, keeping the random sequence going.
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.velocities.clear();
+        self.ages.clear();
+        self.lifetimes.clear();
+        self.base_sizes.clear();
+        self.rotations.clear();
+        self.spins.clear();
+        self.tints.clear();
+        self.sizes.clear();
+        self.colors.clear();
+        self.pending = 0.0;
+    }
+
+    // Spawns up to count particles at once, e.g. for an explosion, limited by ParticleEmitter::max_particles.
+    // They're moved by the next step() along with the others.
+    pub fn emit(&mut self, count: usize) {
+        let count: usize = count.min(self.emitter.max_particles.saturating_sub(self.len()));
+        let emitter: &ParticleEmitter = &self.emitter;
+        let random: &mut ParticleRandom = &mut self.random;
+        for _ in 0..count {
+            self.positions
+                .push(emitter.position + random.vec3(&(-emitter.extent..emitter.extent)));
+            self.velocities.push(random.vec3(&emitter.velocity));
+            self.ages.push(0.0);
+            self.lifetimes
+                .push(random.range(&emitter.lifetime).max(f32::MIN_POSITIVE));
+            self.base_sizes.push(random.range(&emitter.size));
+            self.rotations.push(random.range(&emitter.rotation));
+            self.spins.push(random.range(&emitter.spin));
+            self.tints.push(random.vec4(&emitter.tint));
+        }
+        self.update_appearance();
+    }
+
+    // Advances the simulation by dt seconds: ages and moves the particles, removes the expired ones and spawns the new
+    // ones per the spawn rate.
+    pub fn step(&mut self, dt: f32) {
+        let mut index: usize = 0;
+        while index < self.len() {
+            self.ages[index] += dt;
+            if self.ages[index] >= self.lifetimes[index] {
+                self.swap_remove(index);
+                continue;
+            }
+            let age: f32 = self.ages[index] / self.lifetimes[index];
+            self.velocities[index] += self.emitter.acceleration * dt;
+            self.positions[index] += self.velocities[index] * (self.emitter.speed.sample(age) * dt);
+            self.rotations[index] += self.spins[index] * dt;
+            index += 1;
+        }
+
+        self.pending += self.emitter.spawn_rate.max(0.0) * dt;
+        let count: f32 = self.pending.floor();
+        self.pending -= count;
+        // Also updates the sizes and the colors of the moved particles
+        self.emit(count as usize);
+    }
+
+    // Draws the particles as billboards facing the camera, see Rasterizer::commit_billboards().
+    pub fn commit(&self, rasterizer: &mut Rasterizer, view: Mat44, projection: Mat44) {
+        rasterizer.commit_billboards(&BillboardCommand {
+            centers: &self.positions,
+            sizes: &self.sizes,
+            rotations: &self.rotations,
+            colors: &self.colors,
+            mode: self.emitter.mode,
+            view,
+            projection,
+            texture: self.emitter.texture.clone(),
+            alpha_blending: self.emitter.alpha_blending,
+            depth_write: self.emitter.alpha_blending == AlphaBlendingMode::None,
+            ..Default::default()
+        });
+    }
+
+    fn update_appearance(&mut self) {
+        self.sizes.clear();
+        self.colors.clear();
+        for index in 0..self.len() {
+            let age: f32 = self.ages[index] / self.lifetimes[index];
+            let size: f32 = self.base_sizes[index] * self.emitter.scale.sample(age);
+            self.sizes.push(Vec2::new(size, size));
+            self.colors.push(self.tints[index] * self.emitter.color.sample(age));
+        }
+    }
+
+    fn swap_remove(&mut self, index: usize) {
+        self.positions.swap_remove(index);
+        self.velocities.swap_remove(index);
+        self.ages.swap_remove(index);
+        self.lifetimes.swap_remove(index);
+        self.base_sizes.swap_remove(index);
+        self.rotations.swap_remove(index);
+        self.spins.swap_remove(index);
+        self.tints.swap_remove(index);
+    }
+}
+
+// The random numbers of a ParticleSystem: a hashed counter, cheap and reproducible.
+#[derive(Debug, Clone)]
+struct ParticleRandom {
+    state: u32,
+}
+
+impl ParticleRandom {
+    // Uniformly distributed in [0, 1).
+    fn next(&mut self) -> f32 {
+        self.state = self.state.wrapping_add(0x9E37_79B9);
+        let mut x: u32 = self.state;
+        x ^= x >> 16;
+        x = x.wrapping_mul(0x7feb352d);
+        x ^= x >> 15;
+        x = x.wrapping_mul(0x846ca68b);
+        x ^= x >> 16;
+        (x >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    fn range(&mut self, range: &Range<f32>) -> f32 {
+        range.start + (range.end - range.start) * self.next()
+    }
+
+    fn vec3(&mut self, range: &Range<Vec3>) -> Vec3 {
+        let (start, end) = (range.start, range.end);
+        Vec3::new(self.range(&(start.x..end.x)), self.range(&(start.y..end.y)), self.range(&(start.z..end.z)))
+    }
+
+    fn vec4(&mut self, range: &Range<Vec4>) -> Vec4 {
+        let (start, end) = (range.start, range.end);
+        Vec4::new(
+            self.range(&(start.x..end.x)),
+            self.range(&(start.y..end.y)),
+            self.range(&(start.z..end.z)),
+            self.range(&(start.w..end.w)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curve_interpolates_between_keys() {
+        let curve = ParticleCurve::new(&[(1.0, 0.0), (0.0, 1.0), (0.5, 2.0)]);
+        assert_eq!(curve.sample(-1.0), 1.0);
+        assert_eq!(curve.sample(0.25), 1.5);
+        assert_eq!(curve.sample(0.75), 1.0);
+        assert_eq!(curve.sample(2.0), 0.0);
+        assert_eq!(
+            ParticleCurve::linear(Vec3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 4.0, 6.0)).sample(0.5),
+            Vec3::new(1.0, 2.0, 3.0)
+        );
+        assert_eq!(ParticleCurve::constant(3.0).sample(0.5), 3.0);
+    }
+
+    #[test]
+    fn spawns_at_rate_and_expires() {
+        let mut system =
+            ParticleSystem::new(ParticleEmitter { spawn_rate: 10.0, lifetime: 1.0..1.0, ..Default::default() }, 0);
+        system.step(0.25);
+        assert_eq!(system.len(), 2);
+        system.step(0.25);
+        assert_eq!(system.len(), 5);
+        // The first two particles have lived for 0.85s, then expire during the next step
+        system.step(0.6);
+        assert_eq!(system.len(), 11);
+        system.emitter.spawn_rate = 0.0;
+        system.step(0.2);
+        assert_eq!(system.len(), 9);
+        system.step(1.0);
+        assert!(system.is_empty());
+    }
+
+    #[test]
+    fn moves_and_respects_limit() {
+        let emitter = ParticleEmitter {
+            spawn_rate: 0.0,
+            max_particles: 3,
+            lifetime: 10.0..10.0,
+            velocity: Vec3::new(1.0, 0.0, 0.0)..Vec3::new(1.0, 0.0, 0.0),
+            acceleration: Vec3::new(0.0, -2.0, 0.0),
+            scale: ParticleCurve::linear(1.0, 0.0),
+            color: ParticleCurve::linear(Vec4::new(1.0, 1.0, 1.0, 1.0), Vec4::new(1.0, 1.0, 1.0, 0.0)),
+            ..Default::default()
+        };
+        let mut system = ParticleSystem::new(emitter, 0);
+        system.emit(5);
+        assert_eq!(system.len(), 3);
+        system.step(1.0);
+        assert_eq!(system.positions()[0], Vec3::new(1.0, -2.0, 0.0));
+        assert!((system.sizes[0].x - 0.9).abs() < 1e-6);
+        assert!((system.colors()[0].w - 0.9).abs() < 1e-6);
+    }
+
+    #[test]
+    fn same_seed_same_particles() {
+        let emitter = ParticleEmitter {
+            extent: Vec3::new(1.0, 1.0, 1.0),
+            spawn_rate: 100.0,
+            lifetime: 0.5..2.0,
+            velocity: Vec3::new(-1.0, 0.0, -1.0)..Vec3::new(1.0, 5.0, 1.0),
+            spin: -6.0..6.0,
+            ..Default::default()
+        };
+        let run = |seed: u32| -> Vec<Vec3> {
+            let mut system = ParticleSystem::new(emitter.clone(), seed);
+            for _ in 0..30 {
+                system.step(1.0 / 30.0);
+            }
+            system.positions().to_vec()
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+        for position in run(7) {
+            assert!(position.x.abs() <= 2.0 && position.z.abs() <= 2.0);
+        }
+    }
+}
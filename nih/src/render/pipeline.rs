@@ -0,0 +1,201 @@
+use super::*;
+use std::sync::mpsc::{Receiver, channel};
+
+// Runs the rendering as a two-stage pipeline: the application commits the next frame on its thread while the current
+// frame is drawn in the background by the rayon workers. There are two rasterizers with their own command and vertex
+// buffers, which trade places at each submit():
+//   let mut pipeline = PipelinedRasterizer::new();
+//   loop {
+//       pipeline.rasterizer().setup(viewport);
+//       // ... commit the frame N+1 while the frame N is drawn
+//       if let Some(frame) = pipeline.sync() {
+//           // ... present the frame N, reuse its targets
+//       }
+//       pipeline.submit(targets);
+//   }
+// The frame is drawn into the buffers owned by the pipeline while it is in flight and handed back by sync(), so that
+// the application can't touch them meanwhile. Using two sets of targets lets it present one of them while the other
+// one is drawn.
+pub struct PipelinedRasterizer {
+    // The rasterizer the next frame is committed into
+    committing: Rasterizer,
+
+    // The rasterizer of the last synced frame, it becomes the committing one at the next submit()
+    idle: Option<Rasterizer>,
+
+    // The frame being drawn, if any, the drawing task sends back its rasterizer and targets, or the panic it ran into
+    drawing: Option<Receiver<std::thread::Result<(Rasterizer, FrameTargets)>>>,
+}
+
+// The owned buffers a frame is drawn into by PipelinedRasterizer, the counterpart of Framebuffer.
+pub struct FrameTargets {
+    pub color_buffer: Option<TiledBuffer<u32, 64, 64>>,
+    pub color_format: ColorBufferFormat,
+    pub depth_buffer: Option<TiledBuffer<u16, 64, 64>>,
+    pub depth_buffer_f32: Option<TiledBuffer<f32, 64, 64>>,
+    pub normal_buffer: Option<TiledBuffer<u32, 64, 64>>,
+    pub motion_buffer: Option<TiledBuffer<u32, 64, 64>>,
+}
+
+// A frame drawn by PipelinedRasterizer, returned by sync().
+pub struct DrawnFrame {
+    pub targets: FrameTargets,
+
+    // The statistics of the rasterizer which committed and drew the frame.
+    pub statistics: RasterizerStatistics,
+}
+
+impl Default for FrameTargets {
+    fn default() -> Self {
+        Self {
+            color_buffer: None,
+            color_format: ColorBufferFormat::Rgba8,
+            depth_buffer: None,
+            depth_buffer_f32: None,
+            normal_buffer: None,
+            motion_buffer: None,
+        }
+    }
+}
+
+impl FrameTargets {
+    // The framebuffer made of the present buffers, e.g. to clear them or to draw into them directly.
+    pub fn framebuffer(&mut self) -> Framebuffer<'_> {
+        Framebuffer {
+            color_buffer: self.color_buffer.as_mut(),
+            color_format: self.color_format,
+            depth_buffer: self.depth_buffer.as_mut(),
+            depth_buffer_f32: self.depth_buffer_f32.as_mut(),
+            normal_buffer: self.normal_buffer.as_mut(),
+            motion_buffer: self.motion_buffer.as_mut(),
+        }
+    }
+}
+
+impl Default for PipelinedRasterizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PipelinedRasterizer {
+    pub fn new() -> Self {
+        Self { committing: Rasterizer::new(), idle: Some(Rasterizer::new()), drawing: None }
+    }
+
+    // The rasterizer to set up and commit the next frame into.
+    pub fn rasterizer(&mut self) -> &mut Rasterizer {
+        &mut self.committing
+    }
+
+    // Applies the settings to both rasterizers, e.g. set_multithreading() or set_hierarchical_z(), as they take turns
+    // drawing the frames.
+    // Panics if a frame is being drawn.
+    pub fn configure(&mut self, f: impl Fn(&mut Rasterizer)) {
+        assert!(self.drawing.is_none(), "the frame being drawn must be synced before configuring the rasterizers");
+        f(&mut self.committing);
+        f(self.idle.as_mut().unwrap());
+    }
+
+    // Whether a frame was submitted and not yet synced.
+    pub fn is_drawing(&self) -> bool {
+        self.drawing.is_some()
+    }
+
+    // Starts drawing the committed frame into the targets in the background, the rasterizer of the previously synced
+    // frame becomes the one to commit the next frame into. The targets are drawn into as they are, i.e. it's up to the
+    // caller to clear them.
+    // Panics if the previous frame was not synced.
+    pub fn submit(&mut self, mut targets: FrameTargets) {
+        assert!(self.drawing.is_none(), "the previous frame must be synced before submitting the next one");
+        let next: Rasterizer = self.idle.take().unwrap();
+        let mut rasterizer: Rasterizer = std::mem::replace(&mut self.committing, next);
+        let (sender, receiver) = channel();
+        rayon::spawn(move || {
+            // A panic would abort the process if not caught, it's resumed by sync() instead
+            let drawn = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+                rasterizer.draw(&mut targets.framebuffer());
+                (rasterizer, targets)
+            }));
+            // The frame is discarded if the pipeline was dropped meanwhile
+            let _ = sender.send(drawn);
+        });
+        self.drawing = Some(receiver);
+    }
+
+    // Waits until the submitted frame is drawn and returns it, None if no frame is being drawn.
+    // Resumes the panic of the drawing task, if any. The frame's targets are lost then, but the pipeline can go on with
+    // the next frame.
+    // Blocks the calling thread, so it mustn't be called from a rayon task, e.g. with a single-threaded pool the frame
+    // would never be drawn.
+    pub fn sync(&mut self) -> Option<DrawnFrame> {
+        let receiver = self.drawing.take()?;
+        let (rasterizer, targets) = match receiver.recv().expect("the drawing task always sends the frame back") {
+            Ok(result) => result,
+            Err(panic) => {
+                // The rasterizer is lost with the panicked task, a new one takes its place so that the pipeline stays
+                // usable if the panic is caught
+                self.idle = Some(Rasterizer::with_settings_of(&self.committing));
+                std::panic::resume_unwind(panic)
+            }
+        };
+        let statistics: RasterizerStatistics = rasterizer.statistics();
+        self.idle = Some(rasterizer);
+        Some(DrawnFrame { targets, statistics })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets() -> FrameTargets {
+        let mut targets = FrameTargets {
+            color_buffer: Some(TiledBuffer::new(100, 70)),
+            depth_buffer: Some(TiledBuffer::new(100, 70)),
+            ..Default::default()
+        };
+        targets.framebuffer().clear_all(&ClearValues::default());
+        targets
+    }
+
+    #[test]
+    fn sync_without_frame_returns_nothing() {
+        let mut pipeline = PipelinedRasterizer::new();
+        assert!(pipeline.sync().is_none());
+        pipeline.submit(targets());
+        assert!(pipeline.sync().is_some());
+        assert!(pipeline.sync().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "synced")]
+    fn submit_requires_sync() {
+        let mut pipeline = PipelinedRasterizer::new();
+        pipeline.submit(targets());
+        pipeline.submit(targets());
+    }
+
+    #[test]
+    fn pipeline_recovers_from_panicked_draw() {
+        let mut pipeline = PipelinedRasterizer::new();
+        pipeline.configure(|rasterizer| rasterizer.set_recording(true));
+        pipeline.rasterizer().setup(Viewport::new(0, 0, 100, 70));
+        // Both depth buffers make draw() panic
+        let mut conflicting: FrameTargets = targets();
+        conflicting.depth_buffer_f32 = Some(TiledBuffer::new(100, 70));
+        pipeline.submit(conflicting);
+        let synced = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| pipeline.sync()));
+        assert!(synced.is_err());
+        assert!(!pipeline.is_drawing());
+
+        // The replacement rasterizer keeps the settings, and both rasterizers take turns again
+        pipeline.configure(|rasterizer| rasterizer.set_hierarchical_z(false));
+        for _ in 0..2 {
+            pipeline.rasterizer().setup(Viewport::new(0, 0, 100, 70));
+            assert!(pipeline.rasterizer().take_recorded_frame().is_some());
+            pipeline.submit(targets());
+            assert!(pipeline.sync().is_some());
+        }
+    }
+}
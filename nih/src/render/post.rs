@@ -0,0 +1,1323 @@
+use super::super::math::*;
+use super::*;
+
+// Parameters of the screen-space ambient occlusion computed from the depth and the normal buffers, see
+// ambient_occlusion().
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AmbientOcclusion {
+    // The camera the depth and the normal buffers were drawn with.
+    // Default: identity.
+    pub view: Mat44,
+
+    // Default: identity.
+    pub projection: Mat44,
+
+    // The radius of the hemisphere around each pixel where the geometry occludes it, in world units. The occluders
+    // farther away along the view direction fade out.
+    // Default: 0.5.
+    pub radius: f32,
+
+    // The strength of the occlusion, 0.0 disables it and the values above 1.0 exaggerate it.
+    // Default: 1.0.
+    pub intensity: f32,
+
+    // The number of samples taken per pixel, more samples give less noise at a higher cost. Clamped to [1, 64].
+    // Default: 16.
+    pub samples: u32,
+
+    // How much closer to the camera the geometry must be than a sample to occlude it, in world units. Prevents the
+    // surfaces from occluding themselves due to the limited precision of the depth buffer.
+    // Default: 0.02.
+    pub bias: f32,
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        Self {
+            view: Mat44::identity(),
+            projection: Mat44::identity(),
+            radius: 0.5,
+            intensity: 1.0,
+            samples: 16,
+            bias: 0.02,
+        }
+    }
+}
+
+impl AmbientOcclusion {
+    const MAX_SAMPLES: usize = 64;
+
+    // The side of the square pattern of the per-pixel rotations of the samples, which is averaged out by the blur.
+    const NOISE_SIZE: usize = 4;
+
+    // A 4x4 Bayer matrix, gives the rotations of the samples spread evenly over each 4x4 block of pixels.
+    const NOISE: [u8; 16] = [0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5];
+
+    // The sample offsets within the unit hemisphere around +Z, denser closer to the center, where the occluders matter
+    // the most. The sequence is fixed, so the results are reproducible.
+    fn kernel(samples: usize) -> Vec<Vec3> {
+        let hash = |mut x: u32| -> f32 {
+            x ^= x >> 16;
+            x = x.wrapping_mul(0x7feb352d);
+            x ^= x >> 15;
+            x = x.wrapping_mul(0x846ca68b);
+            x ^= x >> 16;
+            (x >> 8) as f32 / (1u32 << 24) as f32
+        };
+        (0..samples)
+            .map(|i| {
+                // Uniformly over the hemisphere's surface, then scaled towards the center
+                let phi: f32 = hash(i as u32 * 3) * std::f32::consts::TAU;
+                let cos_theta: f32 = hash(i as u32 * 3 + 1).max(0.05);
+                let sin_theta: f32 = (1.0 - cos_theta * cos_theta).sqrt();
+                let t: f32 = (i as f32 + hash(i as u32 * 3 + 2)) / samples as f32;
+                let length: f32 = 0.1 + 0.9 * t * t;
+                Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta) * length
+            })
+            .collect()
+    }
+}
+
+// Computes the ambient occlusion of each pixel from the depth and the normal buffers, drawn with the parameters' camera
+// over the whole buffers: 255 for the fully open pixels and 0 for the fully occluded ones. The pixels without any
+// geometry, i.e. keeping DEPTH_FAR or NORMAL_NONE, are left open.
+// The samples are taken over the hemisphere around each pixel's normal, rotated differently in each pixel of a 4x4
+// block, and the result is smoothed by a 4x4 blur. The tiles are processed in parallel.
+pub fn ambient_occlusion(
+    depth: &TiledBuffer<u16, 64, 64>,
+    normals: &TiledBuffer<u32, 64, 64>,
+    parameters: &AmbientOcclusion,
+    occlusion: &mut TiledBuffer<u8, 64, 64>,
+) {
+    assert_eq!(depth.width(), normals.width());
+    assert_eq!(depth.height(), normals.height());
+    assert_eq!(depth.width(), occlusion.width());
+    assert_eq!(depth.height(), occlusion.height());
+    let parameters: AmbientOcclusion = *parameters;
+    let kernel: Vec<Vec3> =
+        AmbientOcclusion::kernel((parameters.samples as usize).clamp(1, AmbientOcclusion::MAX_SAMPLES));
+    let inverse_view_projection: Mat44 = (parameters.projection * parameters.view).inverse();
+    let inverse_projection: Mat44 = parameters.projection.inverse();
+    let (width, height): (u16, u16) = (depth.width(), depth.height());
+    let has_geometry =
+        |x: u16, y: u16| -> bool { depth.at(x, y) != DEPTH_FAR && !is_normal_none(RGBA::from_u32(normals.at(x, y))) };
+    let ndc = |x: u16, y: u16| -> Vec4 {
+        Vec4::new(
+            (x as f32 + 0.5) / width as f32 * 2.0 - 1.0,
+            1.0 - (y as f32 + 0.5) / height as f32 * 2.0,
+            decode_depth(depth.at(x, y)),
+            1.0,
+        )
+    };
+    let view_z = |x: u16, y: u16| -> f32 {
+        let position: Vec4 = inverse_projection * ndc(x, y);
+        position.z / position.w
+    };
+
+    // The raw occlusion, noisy due to the rotated samples
+    let mut raw = TiledBuffer::<u8, 64, 64>::new(width, height);
+    for_each_tile_parallel(&mut raw, |tile| {
+        for y in 0..tile.height as usize {
+            for x in 0..tile.width as usize {
+                let (px, py): (u16, u16) = (tile.origin_x + x as u16, tile.origin_y + y as u16);
+                let value: &mut u8 = tile.get_unchecked(x, y);
+                *value = 255;
+                if !has_geometry(px, py) {
+                    continue;
+                }
+                let normal: Vec3 = decode_normal_from_color(RGBA::from_u32(normals.at(px, py))).normalized();
+                let world: Vec4 = inverse_view_projection * ndc(px, py);
+                let position: Vec3 = world.xyz() / world.w;
+                let pixel_z: f32 = view_z(px, py);
+
+                // The tangent basis around the normal, rotated by the pixel's place in the noise pattern
+                let axis: Vec3 = if normal.y.abs() < 0.99 {
+                    Vec3::new(0.0, 1.0, 0.0)
+                } else {
+                    Vec3::new(1.0, 0.0, 0.0)
+                };
+                let tangent: Vec3 = cross(axis, normal).normalized();
+                let bitangent: Vec3 = cross(normal, tangent);
+                let noise_idx: usize = (py as usize % AmbientOcclusion::NOISE_SIZE) * AmbientOcclusion::NOISE_SIZE
+                    + px as usize % AmbientOcclusion::NOISE_SIZE;
+                let angle: f32 = AmbientOcclusion::NOISE[noise_idx] as f32 * (std::f32::consts::TAU / 16.0);
+                let (sin, cos): (f32, f32) = angle.sin_cos();
+                let tangent: Vec3 = tangent * cos + bitangent * sin;
+                let bitangent: Vec3 = cross(normal, tangent);
+
+                let mut occluded: f32 = 0.0;
+                for offset in &kernel {
+                    let sample: Vec3 =
+                        position + (tangent * offset.x + bitangent * offset.y + normal * offset.z) * parameters.radius;
+                    let sample_view: Vec4 = parameters.view * sample.as_point4();
+                    let clip: Vec4 = parameters.projection * sample_view;
+                    if clip.w <= 0.0 {
+                        continue;
+                    }
+                    let (sx, sy): (f32, f32) = (clip.x / clip.w, clip.y / clip.w);
+                    let sample_x: f32 = (sx * 0.5 + 0.5) * width as f32;
+                    let sample_y: f32 = (0.5 - sy * 0.5) * height as f32;
+                    if sample_x < 0.0 || sample_y < 0.0 || sample_x >= width as f32 || sample_y >= height as f32 {
+                        continue;
+                    }
+                    let scene_depth: u16 = depth.at(sample_x as u16, sample_y as u16);
+                    if scene_depth == DEPTH_FAR {
+                        continue;
+                    }
+                    let scene: Vec4 = inverse_projection * Vec4::new(sx, sy, decode_depth(scene_depth), 1.0);
+                    let scene_z: f32 = scene.z / scene.w;
+                    // The view space looks along -Z, i.e. the closer geometry has the larger Z
+                    if scene_z >= sample_view.z + parameters.bias {
+                        let distance: f32 = (pixel_z - scene_z).abs();
+                        occluded += if distance < parameters.radius {
+                            1.0
+                        } else {
+                            parameters.radius / distance
+                        };
+                    }
+                }
+                let open: f32 = 1.0 - parameters.intensity * occluded / kernel.len() as f32;
+                *value = (open.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+            }
+        }
+    });
+
+    // Averages the 4x4 blocks of the noise pattern, only over the pixels of the same surface, i.e. with the geometry at
+    // about the same depth
+    let raw: &TiledBuffer<u8, 64, 64> = &raw;
+    let max_depth_difference: f32 = parameters.radius * 0.25;
+    let half: i32 = AmbientOcclusion::NOISE_SIZE as i32 / 2;
+    for_each_tile_parallel(occlusion, |tile| {
+        for y in 0..tile.height as usize {
+            for x in 0..tile.width as usize {
+                let (px, py): (i32, i32) = (tile.origin_x as i32 + x as i32, tile.origin_y as i32 + y as i32);
+                let value: &mut u8 = tile.get_unchecked(x, y);
+                if !has_geometry(px as u16, py as u16) {
+                    *value = 255;
+                    continue;
+                }
+                let pixel_z: f32 = view_z(px as u16, py as u16);
+                let (mut sum, mut count): (u32, u32) = (0, 0);
+                for ny in (py - half).max(0)..(py + half).min(height as i32) {
+                    for nx in (px - half).max(0)..(px + half).min(width as i32) {
+                        if has_geometry(nx as u16, ny as u16)
+                            && (view_z(nx as u16, ny as u16) - pixel_z).abs() <= max_depth_difference
+                        {
+                            sum += raw.at(nx as u16, ny as u16) as u32;
+                            count += 1;
+                        }
+                    }
+                }
+                *value = ((sum + count / 2) / count) as u8;
+            }
+        }
+    });
+}
+
+// Darkens the color buffer by the ambient occlusion computed by ambient_occlusion(), the alpha is kept.
+// The tiles are processed in parallel.
+pub fn apply_ambient_occlusion(
+    color: &mut TiledBuffer<u32, 64, 64>,
+    color_format: ColorBufferFormat,
+    occlusion: &TiledBuffer<u8, 64, 64>,
+) {
+    assert_eq!(color.width(), occlusion.width());
+    assert_eq!(color.height(), occlusion.height());
+    for_each_tile_parallel(color, |tile| {
+        let occlusion_tile = occlusion.tile(tile.origin_x / 64, tile.origin_y / 64);
+        for y in 0..tile.height as usize {
+            for x in 0..tile.width as usize {
+                let open: u32 = occlusion_tile.get_unchecked(x, y) as u32;
+                let pixel: &mut u32 = tile.get_unchecked(x, y);
+                *pixel = match color_format {
+                    ColorBufferFormat::Rgba8 => {
+                        let color: RGBA = RGBA::from_u32(*pixel);
+                        let modulate = |c: u8| -> u8 { ((c as u32 * open + 127) / 255) as u8 };
+                        RGBA::new(modulate(color.r), modulate(color.g), modulate(color.b), color.a).to_u32()
+                    }
+                    ColorBufferFormat::Rgb9e5 => {
+                        let color: Vec3 = decode_rgb9e5(*pixel) * (open as f32 / 255.0);
+                        encode_rgb9e5(color.x, color.y, color.z)
+                    }
+                };
+            }
+        }
+    });
+}
+
+// Parameters of the thumbnail-quality downscale of a color buffer, see downscale().
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Downscale {
+    // The 8-bit colors are raised to this power to be averaged in linear space, and the results are raised back to
+    // 1/gamma. Ignored for the HDR color buffers, which are already linear.
+    // Default: 2.2.
+    pub gamma: f32,
+
+    // Whether the 8-bit colors are stored premultiplied by alpha, e.g. when drawn with the premultiplied blending modes.
+    // The result is then premultiplied as well.
+    // Default: false.
+    pub premultiplied: bool,
+}
+
+impl Default for Downscale {
+    fn default() -> Self {
+        Self { gamma: 2.2, premultiplied: false }
+    }
+}
+
+// Produces a smaller copy of the color buffer, e.g. for the thumbnails of the rendered frames, use as_flat_buffer() on
+// the result to get a flat image.
+// Each resulting pixel is the area-weighted average of the source pixels it covers. The colors are averaged in linear
+// space and weighted by their alpha, so the fully transparent pixels don't bleed their color into the neighbours.
+// The width and the height must be in [1, source size], upscaling is not supported.
+// The tiles are processed in parallel.
+pub fn downscale(
+    color: &TiledBuffer<u32, 64, 64>,
+    color_format: ColorBufferFormat,
+    width: u16,
+    height: u16,
+    parameters: &Downscale,
+) -> TiledBuffer<u32, 64, 64> {
+    assert!(width > 0 && width <= color.width());
+    assert!(height > 0 && height <= color.height());
+    let mut result = TiledBuffer::<u32, 64, 64>::new(width, height);
+
+    let to_linear: [f32; 256] = std::array::from_fn(|i| (i as f32 / 255.0).powf(parameters.gamma));
+    let inv_gamma: f32 = 1.0 / parameters.gamma;
+    let from_linear = |c: f32| -> u8 { (c.clamp(0.0, 1.0).powf(inv_gamma) * 255.0 + 0.5) as u8 };
+
+    // The first covered source pixel and the coverage of each covered source pixel, per resulting column and row
+    let spans = |source: u16, target: u16| -> Vec<(u16, Vec<f32>)> {
+        let scale: f64 = source as f64 / target as f64;
+        (0..target)
+            .map(|i| {
+                let (start, end): (f64, f64) = (i as f64 * scale, ((i + 1) as f64 * scale).min(source as f64));
+                let first: u16 = start.floor() as u16;
+                let last: u16 = (end.ceil() as u16).min(source);
+                let weights: Vec<f32> = (first..last)
+                    .map(|s| ((s as f64 + 1.0).min(end) - (s as f64).max(start)) as f32)
+                    .collect();
+                (first, weights)
+            })
+            .collect()
+    };
+    let columns: Vec<(u16, Vec<f32>)> = spans(color.width(), width);
+    let rows: Vec<(u16, Vec<f32>)> = spans(color.height(), height);
+
+    for_each_tile_parallel(&mut result, |tile| {
+        for y in 0..tile.height as usize {
+            let (row_start, row_weights): &(u16, Vec<f32>) = &rows[tile.origin_y as usize + y];
+            for x in 0..tile.width as usize {
+                let (column_start, column_weights): &(u16, Vec<f32>) = &columns[tile.origin_x as usize + x];
+                let mut sum: Vec4 = Vec4::new(0.0, 0.0, 0.0, 0.0);
+                let mut total_weight: f32 = 0.0;
+                for (sy, wy) in row_weights.iter().enumerate() {
+                    for (sx, wx) in column_weights.iter().enumerate() {
+                        let weight: f32 = wx * wy;
+                        let packed: u32 = color.at(column_start + sx as u16, row_start + sy as u16);
+                        match color_format {
+                            ColorBufferFormat::Rgba8 => {
+                                let c: RGBA = RGBA::from_u32(packed);
+                                let unpremultiply = |v: u8| -> u8 {
+                                    if parameters.premultiplied && c.a > 0 {
+                                        ((v as u32 * 255 + c.a as u32 / 2) / c.a as u32).min(255) as u8
+                                    } else {
+                                        v
+                                    }
+                                };
+                                let alpha: f32 = c.a as f32 / 255.0;
+                                let linear: Vec3 = Vec3::new(
+                                    to_linear[unpremultiply(c.r) as usize],
+                                    to_linear[unpremultiply(c.g) as usize],
+                                    to_linear[unpremultiply(c.b) as usize],
+                                );
+                                sum += (linear * alpha).as_vector4() * weight;
+                                sum.w += alpha * weight;
+                            }
+                            ColorBufferFormat::Rgb9e5 => {
+                                sum += decode_rgb9e5(packed).as_vector4() * weight;
+                            }
+                        }
+                        total_weight += weight;
+                    }
+                }
+                *tile.get_unchecked(x, y) = match color_format {
+                    ColorBufferFormat::Rgba8 => {
+                        let alpha: f32 = sum.w / total_weight;
+                        let linear: Vec3 = if sum.w > 0.0 {
+                            sum.xyz() / sum.w
+                        } else {
+                            Vec3::new(0.0, 0.0, 0.0)
+                        };
+                        let a: u8 = (alpha.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+                        let premultiply = |v: u8| -> u8 {
+                            if parameters.premultiplied {
+                                ((v as u32 * a as u32 + 127) / 255) as u8
+                            } else {
+                                v
+                            }
+                        };
+                        RGBA::new(
+                            premultiply(from_linear(linear.x)),
+                            premultiply(from_linear(linear.y)),
+                            premultiply(from_linear(linear.z)),
+                            a,
+                        )
+                        .to_u32()
+                    }
+                    ColorBufferFormat::Rgb9e5 => {
+                        let linear: Vec3 = sum.xyz() / total_weight;
+                        encode_rgb9e5(linear.x, linear.y, linear.z)
+                    }
+                };
+            }
+        }
+    });
+    result
+}
+
+// Parameters of the glow around the bright parts of the image, see bloom().
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bloom {
+    // The brightness, i.e. the largest of the color components, above which the pixels glow. The HDR colors are
+    // linear and can exceed 1.0, the 8-bit colors are taken as [0, 1].
+    // Default: 0.8.
+    pub threshold: f32,
+
+    // The multiplier of the glow added to the image.
+    // Default: 1.0.
+    pub intensity: f32,
+
+    // The radius of the glow in pixels, i.e. three standard deviations of the gaussian blur.
+    // Default: 16.0.
+    pub radius: f32,
+
+    // The bright parts are blurred at the resolution reduced by this factor, the larger factors are cheaper and give
+    // the softer glow. Clamped to [1, 16].
+    // Default: 4.
+    pub downsample: u16,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self { threshold: 0.8, intensity: 1.0, radius: 16.0, downsample: 4 }
+    }
+}
+
+// Adds the glow around the pixels brighter than the threshold: the bright parts are extracted at a reduced resolution,
+// blurred by a separable gaussian and added back on top of the image. The alpha is kept, the 8-bit colors saturate.
+// The work is done in parallel.
+pub fn bloom(color: &mut TiledBuffer<u32, 64, 64>, color_format: ColorBufferFormat, parameters: &Bloom) {
+    use rayon::prelude::*;
+    let decode = |packed: u32| -> Vec3 {
+        match color_format {
+            ColorBufferFormat::Rgba8 => {
+                let c: RGBA = RGBA::from_u32(packed);
+                Vec3::new(c.r as f32, c.g as f32, c.b as f32) / 255.0
+            }
+            ColorBufferFormat::Rgb9e5 => decode_rgb9e5(packed),
+        }
+    };
+    let downsample: usize = parameters.downsample.clamp(1, 16) as usize;
+    let (width, height): (usize, usize) = (color.width() as usize, color.height() as usize);
+    let (low_width, low_height): (usize, usize) = (width.div_ceil(downsample), height.div_ceil(downsample));
+    if width == 0 || height == 0 || parameters.intensity <= 0.0 {
+        return;
+    }
+
+    // The bright pass, averaged over the blocks of the reduced resolution
+    let source: &TiledBuffer<u32, 64, 64> = color;
+    let mut bright: Vec<Vec3> = vec![Vec3::new(0.0, 0.0, 0.0); low_width * low_height];
+    bright.par_chunks_mut(low_width).enumerate().for_each(|(ly, row)| {
+        for (lx, value) in row.iter_mut().enumerate() {
+            let (x0, y0): (usize, usize) = (lx * downsample, ly * downsample);
+            let (x1, y1): (usize, usize) = ((x0 + downsample).min(width), (y0 + downsample).min(height));
+            let mut sum: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let c: Vec3 = decode(source.at(x as u16, y as u16));
+                    let brightness: f32 = c.x.max(c.y).max(c.z);
+                    if brightness > parameters.threshold {
+                        sum += c * ((brightness - parameters.threshold) / brightness);
+                    }
+                }
+            }
+            *value = sum / ((x1 - x0) * (y1 - y0)) as f32;
+        }
+    });
+
+    // The separable gaussian blur, horizontal then vertical
+    let sigma: f32 = (parameters.radius / 3.0 / downsample as f32).max(0.01);
+    let half: i32 = (sigma * 3.0).ceil() as i32;
+    let mut weights: Vec<f32> = (-half..=half)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = weights.iter().sum();
+    weights.iter_mut().for_each(|w| *w /= total);
+    let mut horizontal: Vec<Vec3> = vec![Vec3::new(0.0, 0.0, 0.0); low_width * low_height];
+    horizontal.par_chunks_mut(low_width).enumerate().for_each(|(y, row)| {
+        for (x, value) in row.iter_mut().enumerate() {
+            let mut sum: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+            for (i, weight) in weights.iter().enumerate() {
+                let sx: i32 = x as i32 + i as i32 - half;
+                if sx >= 0 && sx < low_width as i32 {
+                    sum += bright[y * low_width + sx as usize] * *weight;
+                }
+            }
+            *value = sum;
+        }
+    });
+    bright.par_chunks_mut(low_width).enumerate().for_each(|(y, row)| {
+        for (x, value) in row.iter_mut().enumerate() {
+            let mut sum: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+            for (i, weight) in weights.iter().enumerate() {
+                let sy: i32 = y as i32 + i as i32 - half;
+                if sy >= 0 && sy < low_height as i32 {
+                    sum += horizontal[sy as usize * low_width + x] * *weight;
+                }
+            }
+            *value = sum;
+        }
+    });
+    let blurred: &Vec<Vec3> = &bright;
+
+    // The additive composite, the reduced image is upsampled bilinearly
+    let glow_at = |x: usize, y: usize| -> Vec3 {
+        let fx: f32 = ((x as f32 + 0.5) / downsample as f32 - 0.5).clamp(0.0, (low_width - 1) as f32);
+        let fy: f32 = ((y as f32 + 0.5) / downsample as f32 - 0.5).clamp(0.0, (low_height - 1) as f32);
+        let (x0, y0): (usize, usize) = (fx as usize, fy as usize);
+        let (x1, y1): (usize, usize) = ((x0 + 1).min(low_width - 1), (y0 + 1).min(low_height - 1));
+        let (tx, ty): (f32, f32) = (fx - x0 as f32, fy - y0 as f32);
+        let top: Vec3 = blurred[y0 * low_width + x0] * (1.0 - tx) + blurred[y0 * low_width + x1] * tx;
+        let bottom: Vec3 = blurred[y1 * low_width + x0] * (1.0 - tx) + blurred[y1 * low_width + x1] * tx;
+        (top * (1.0 - ty) + bottom * ty) * parameters.intensity
+    };
+    for_each_tile_parallel(color, |tile| {
+        for y in 0..tile.height as usize {
+            for x in 0..tile.width as usize {
+                let glow: Vec3 = glow_at(tile.origin_x as usize + x, tile.origin_y as usize + y);
+                let pixel: &mut u32 = tile.get_unchecked(x, y);
+                *pixel = match color_format {
+                    ColorBufferFormat::Rgba8 => {
+                        let c: RGBA = RGBA::from_u32(*pixel);
+                        let add = |v: u8, g: f32| -> u8 { (v as f32 + g * 255.0 + 0.5).min(255.0) as u8 };
+                        RGBA::new(add(c.r, glow.x), add(c.g, glow.y), add(c.b, glow.z), c.a).to_u32()
+                    }
+                    ColorBufferFormat::Rgb9e5 => {
+                        let c: Vec3 = decode_rgb9e5(*pixel) + glow;
+                        encode_rgb9e5(c.x, c.y, c.z)
+                    }
+                };
+            }
+        }
+    });
+}
+
+// The read-only view of the framebuffer given to a post-processing pass. It covers the whole frame, so the pass can look at
+// the pixels around the ones it writes. A buffer the pass writes is present here only if the pass reads the neighbours,
+// then it holds the values from before the pass. Otherwise the written buffers are accessed in place via the tile.
+pub struct PostProcessSource<'a> {
+    pub color_buffer: Option<&'a TiledBuffer<u32, 64, 64>>,
+    pub color_format: ColorBufferFormat,
+    pub depth_buffer: Option<&'a TiledBuffer<u16, 64, 64>>,
+    pub depth_buffer_f32: Option<&'a TiledBuffer<f32, 64, 64>>,
+    pub normal_buffer: Option<&'a TiledBuffer<u32, 64, 64>>,
+    pub motion_buffer: Option<&'a TiledBuffer<u32, 64, 64>>,
+}
+
+// A step of PostProcessChain, processes the framebuffer one tile at a time.
+pub trait PostProcessPass: Send + Sync {
+    // The buffers the pass reads. FramebufferComponent::Depth stands for whichever of the depth buffers is present.
+    fn reads(&self) -> &[FramebufferComponent];
+
+    // The buffers the pass writes, only these are present in the tiles given to process().
+    fn writes(&self) -> &[FramebufferComponent];
+
+    // Whether the pass reads the pixels around the ones it writes, e.g. a blur. The buffers such a pass both reads and
+    // writes are copied before the pass and given via the source, so the results don't depend on the order of the tiles.
+    fn reads_neighbors(&self) -> bool {
+        false
+    }
+
+    // Called for each tile of the framebuffer, the tiles are processed in parallel.
+    fn process(&self, source: &PostProcessSource, tile: &mut FramebufferTile);
+}
+
+// A pass defined by a closure, see PostProcessChain::add_fn() and GraphPass::tiles_fn().
+pub(crate) struct FnPostProcessPass<F> {
+    pub(crate) reads: Vec<FramebufferComponent>,
+    pub(crate) writes: Vec<FramebufferComponent>,
+    pub(crate) reads_neighbors: bool,
+    pub(crate) f: F,
+}
+
+impl<F> PostProcessPass for FnPostProcessPass<F>
+where
+    F: Fn(&PostProcessSource, &mut FramebufferTile) + Send + Sync,
+{
+    fn reads(&self) -> &[FramebufferComponent] {
+        &self.reads
+    }
+
+    fn writes(&self) -> &[FramebufferComponent] {
+        &self.writes
+    }
+
+    fn reads_neighbors(&self) -> bool {
+        self.reads_neighbors
+    }
+
+    fn process(&self, source: &PostProcessSource, tile: &mut FramebufferTile) {
+        (self.f)(source, tile)
+    }
+}
+
+// A sequence of post-processing passes run over a framebuffer one after another, e.g. lighting followed by a blur.
+// Each pass is run over all the tiles in parallel before the next one starts.
+#[derive(Default)]
+pub struct PostProcessChain {
+    passes: Vec<Box<dyn PostProcessPass>>,
+
+    // The copies of the buffers read by the passes that read the neighbours, kept to reuse the memory between the frames
+    color_copy: TiledBuffer<u32, 64, 64>,
+    depth_copy: TiledBuffer<u16, 64, 64>,
+    depth_f32_copy: TiledBuffer<f32, 64, 64>,
+    normal_copy: TiledBuffer<u32, 64, 64>,
+    motion_copy: TiledBuffer<u32, 64, 64>,
+}
+
+impl PostProcessChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Appends the pass to the end of the chain.
+    pub fn add(&mut self, pass: impl PostProcessPass + 'static) {
+        self.passes.push(Box::new(pass));
+    }
+
+    // Appends a pass defined by a closure to the end of the chain, see PostProcessPass for the meaning of the parameters.
+    pub fn add_fn<F>(
+        &mut self,
+        reads: &[FramebufferComponent],
+        writes: &[FramebufferComponent],
+        reads_neighbors: bool,
+        f: F,
+    ) where
+        F: Fn(&PostProcessSource, &mut FramebufferTile) + Send + Sync + 'static,
+    {
+        self.add(FnPostProcessPass { reads: reads.to_vec(), writes: writes.to_vec(), reads_neighbors, f });
+    }
+
+    pub fn len(&self) -> usize {
+        self.passes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    // Removes all the passes, the memory of the buffer copies is kept.
+    pub fn clear(&mut self) {
+        self.passes.clear();
+    }
+
+    // Runs the passes over the framebuffer in the order they were added.
+    // Panics if the framebuffer lacks a buffer a pass reads or writes.
+    pub fn run(&mut self, framebuffer: &mut Framebuffer) {
+        for pass in &self.passes {
+            let pass: &dyn PostProcessPass = pass.as_ref();
+            for component in pass.reads().iter().chain(pass.writes()) {
+                let present: bool = match component {
+                    FramebufferComponent::Color => framebuffer.color_buffer.is_some(),
+                    FramebufferComponent::Depth => {
+                        framebuffer.depth_buffer.is_some() || framebuffer.depth_buffer_f32.is_some()
+                    }
+                    FramebufferComponent::Normals => framebuffer.normal_buffer.is_some(),
+                    FramebufferComponent::Motion => framebuffer.motion_buffer.is_some(),
+                };
+                assert!(present, "the post-processing pass requires the {:?} buffer", component);
+            }
+            let written = |component: FramebufferComponent| pass.writes().contains(&component);
+            let copied = |component: FramebufferComponent| {
+                pass.reads_neighbors() && written(component) && pass.reads().contains(&component)
+            };
+
+            if copied(FramebufferComponent::Color) {
+                copy_buffer(framebuffer.color_buffer.as_deref().unwrap(), &mut self.color_copy);
+            }
+            if copied(FramebufferComponent::Depth) {
+                if let Some(buffer) = framebuffer.depth_buffer.as_deref() {
+                    copy_buffer(buffer, &mut self.depth_copy);
+                }
+                if let Some(buffer) = framebuffer.depth_buffer_f32.as_deref() {
+                    copy_buffer(buffer, &mut self.depth_f32_copy);
+                }
+            }
+            if copied(FramebufferComponent::Normals) {
+                copy_buffer(framebuffer.normal_buffer.as_deref().unwrap(), &mut self.normal_copy);
+            }
+            if copied(FramebufferComponent::Motion) {
+                copy_buffer(framebuffer.motion_buffer.as_deref().unwrap(), &mut self.motion_copy);
+            }
+
+            let (tiles_x, tiles_y): (u16, u16) = (framebuffer.tiles_x(), framebuffer.tiles_y());
+            let (color_source, color_target) = split_buffer(
+                framebuffer.color_buffer.as_deref_mut(),
+                written(FramebufferComponent::Color),
+                copied(FramebufferComponent::Color).then_some(&self.color_copy),
+            );
+            let (depth_source, depth_target) = split_buffer(
+                framebuffer.depth_buffer.as_deref_mut(),
+                written(FramebufferComponent::Depth),
+                copied(FramebufferComponent::Depth).then_some(&self.depth_copy),
+            );
+            let (depth_f32_source, depth_f32_target) = split_buffer(
+                framebuffer.depth_buffer_f32.as_deref_mut(),
+                written(FramebufferComponent::Depth),
+                copied(FramebufferComponent::Depth).then_some(&self.depth_f32_copy),
+            );
+            let (normal_source, normal_target) = split_buffer(
+                framebuffer.normal_buffer.as_deref_mut(),
+                written(FramebufferComponent::Normals),
+                copied(FramebufferComponent::Normals).then_some(&self.normal_copy),
+            );
+            let (motion_source, motion_target) = split_buffer(
+                framebuffer.motion_buffer.as_deref_mut(),
+                written(FramebufferComponent::Motion),
+                copied(FramebufferComponent::Motion).then_some(&self.motion_copy),
+            );
+            let source = PostProcessSource {
+                color_buffer: color_source,
+                color_format: framebuffer.color_format,
+                depth_buffer: depth_source,
+                depth_buffer_f32: depth_f32_source,
+                normal_buffer: normal_source,
+                motion_buffer: motion_source,
+            };
+            let mut target = Framebuffer {
+                color_buffer: color_target,
+                color_format: framebuffer.color_format,
+                depth_buffer: depth_target,
+                depth_buffer_f32: depth_f32_target,
+                normal_buffer: normal_target,
+                motion_buffer: motion_target,
+            };
+            let mut tiles: Vec<FramebufferTile> = Vec::with_capacity(tiles_x as usize * tiles_y as usize);
+            for y in 0..tiles_y {
+                for x in 0..tiles_x {
+                    tiles.push(target.tile(x, y));
+                }
+            }
+            use rayon::prelude::*;
+            tiles.par_iter_mut().for_each(|tile| pass.process(&source, tile));
+        }
+    }
+}
+
+// Makes the destination an owned copy of the source, reusing its memory when the sizes match.
+pub(crate) fn copy_buffer<T>(from: &TiledBuffer<T, 64, 64>, to: &mut TiledBuffer<T, 64, 64>)
+where
+    T: Copy + bytemuck::Zeroable + bytemuck::Pod + Default,
+{
+    if to.width() != from.width() || to.height() != from.height() || to.is_external() {
+        *to = TiledBuffer::new(from.width(), from.height());
+    }
+    to.values_mut().copy_from_slice(from.values());
+}
+
+// Splits an optional framebuffer's buffer into the read-only view for PostProcessSource and the writable one for the
+// tiles. A written buffer is visible in the source only via its copy made for the pass, if any.
+fn split_buffer<'a, T>(
+    buffer: Option<&'a mut TiledBuffer<T, 64, 64>>,
+    written: bool,
+    copy: Option<&'a TiledBuffer<T, 64, 64>>,
+) -> (Option<&'a TiledBuffer<T, 64, 64>>, Option<&'a mut TiledBuffer<T, 64, 64>>)
+where
+    T: Copy + bytemuck::Zeroable + bytemuck::Pod + Default,
+{
+    match buffer {
+        Some(buffer) if written => (copy, Some(buffer)),
+        Some(buffer) => (Some(buffer), None),
+        None => (None, None),
+    }
+}
+
+// Screen-space anti-aliasing of the color buffer in the style of FXAA: the edges are found by the contrast of the
+// pixels' luma, traced along to find their ends, and the pixels along them are blended with the neighbours across the
+// edge in proportion to their position on the stair step. A cheap alternative to MSAA, run via PostProcessChain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fxaa {
+    // The minimal contrast of luma around a pixel, relative to the brightest of the neighbours, to treat it as an edge.
+    // Default: 0.125.
+    pub edge_threshold: f32,
+
+    // The minimal absolute contrast of luma to treat a pixel as an edge, which keeps the dark areas intact.
+    // Default: 0.0312.
+    pub edge_threshold_min: f32,
+
+    // How much the single-pixel details are smoothed out, 0.0 keeps them sharp.
+    // Default: 0.75.
+    pub subpixel: f32,
+}
+
+impl Default for Fxaa {
+    fn default() -> Self {
+        Self { edge_threshold: 0.125, edge_threshold_min: 0.0312, subpixel: 0.75 }
+    }
+}
+
+impl Fxaa {
+    // The distances along the edge at which its ends are looked for, growing to reach far while keeping the cost low
+    const SEARCH_STEPS: [f32; 10] = [1.0, 1.0, 1.0, 1.0, 1.5, 2.0, 2.0, 2.0, 4.0, 8.0];
+}
+
+impl PostProcessPass for Fxaa {
+    fn reads(&self) -> &[FramebufferComponent] {
+        &[FramebufferComponent::Color]
+    }
+
+    fn writes(&self) -> &[FramebufferComponent] {
+        &[FramebufferComponent::Color]
+    }
+
+    fn reads_neighbors(&self) -> bool {
+        true
+    }
+
+    fn process(&self, source: &PostProcessSource, tile: &mut FramebufferTile) {
+        let (origin_x, origin_y): (i32, i32) = (tile.origin_x() as i32, tile.origin_y() as i32);
+        let color_format: ColorBufferFormat = source.color_format;
+        let (Some(source), Some(target)) = (source.color_buffer, tile.color_buffer.as_mut()) else {
+            return;
+        };
+        let (width, height): (i32, i32) = (source.width() as i32, source.height() as i32);
+        // The colors outside the buffer repeat its border
+        let fetch = |x: i32, y: i32| -> Vec4 {
+            let packed: u32 = source.at(x.clamp(0, width - 1) as u16, y.clamp(0, height - 1) as u16);
+            match color_format {
+                ColorBufferFormat::Rgba8 => {
+                    let c: RGBA = RGBA::from_u32(packed);
+                    Vec4::new(c.r as f32, c.g as f32, c.b as f32, c.a as f32) / 255.0
+                }
+                ColorBufferFormat::Rgb9e5 => decode_rgb9e5(packed).as_point4(),
+            }
+        };
+        // The HDR luma is compressed into [0, 1) so the thresholds keep their meaning
+        let luma_of = |c: Vec4| -> f32 {
+            let luma: f32 = c.x * 0.299 + c.y * 0.587 + c.z * 0.114;
+            match color_format {
+                ColorBufferFormat::Rgba8 => luma,
+                ColorBufferFormat::Rgb9e5 => luma / (1.0 + luma),
+            }
+        };
+        let luma = |x: i32, y: i32| -> f32 { luma_of(fetch(x, y)) };
+        // Bilinear sampling at the position in pixels, the centers of the pixels are at the halves
+        let sample = |px: f32, py: f32| -> Vec4 {
+            let (fx, fy): (f32, f32) = (px - 0.5, py - 0.5);
+            let (x0, y0): (i32, i32) = (fx.floor() as i32, fy.floor() as i32);
+            let (tx, ty): (f32, f32) = (fx - x0 as f32, fy - y0 as f32);
+            let top: Vec4 = fetch(x0, y0) * (1.0 - tx) + fetch(x0 + 1, y0) * tx;
+            let bottom: Vec4 = fetch(x0, y0 + 1) * (1.0 - tx) + fetch(x0 + 1, y0 + 1) * tx;
+            top * (1.0 - ty) + bottom * ty
+        };
+
+        for y in 0..target.height as usize {
+            for x in 0..target.width as usize {
+                let (px, py): (i32, i32) = (origin_x + x as i32, origin_y + y as i32);
+                let m: f32 = luma(px, py);
+                let (n, s, w, e): (f32, f32, f32, f32) =
+                    (luma(px, py - 1), luma(px, py + 1), luma(px - 1, py), luma(px + 1, py));
+                let max: f32 = m.max(n).max(s).max(w).max(e);
+                let range: f32 = max - m.min(n).min(s).min(w).min(e);
+                if range < self.edge_threshold_min.max(max * self.edge_threshold) {
+                    continue;
+                }
+                let (nw, ne, sw, se): (f32, f32, f32, f32) =
+                    (luma(px - 1, py - 1), luma(px + 1, py - 1), luma(px - 1, py + 1), luma(px + 1, py + 1));
+
+                // The blending of the single-pixel details, by the contrast with the average of the neighbours
+                let average: f32 = (2.0 * (n + s + w + e) + nw + ne + sw + se) / 12.0;
+                let contrast: f32 = ((average - m).abs() / range).clamp(0.0, 1.0);
+                let smooth: f32 = contrast * contrast * (3.0 - 2.0 * contrast);
+                let subpixel_offset: f32 = smooth * smooth * self.subpixel;
+
+                // A horizontal edge separates the rows, i.e. is crossed vertically
+                let horizontal_edge: f32 =
+                    (nw + sw - 2.0 * w).abs() + 2.0 * (n + s - 2.0 * m).abs() + (ne + se - 2.0 * e).abs();
+                let vertical_edge: f32 =
+                    (nw + ne - 2.0 * n).abs() + 2.0 * (w + e - 2.0 * m).abs() + (sw + se - 2.0 * s).abs();
+                let horizontal: bool = horizontal_edge >= vertical_edge;
+                let (before, after): (f32, f32) = if horizontal { (n, s) } else { (w, e) };
+                let (gradient_before, gradient_after): (f32, f32) = ((before - m).abs(), (after - m).abs());
+                let (step, neighbor): (f32, f32) = if gradient_before >= gradient_after {
+                    (-1.0, before)
+                } else {
+                    (1.0, after)
+                };
+                let gradient: f32 = gradient_before.max(gradient_after) * 0.25;
+                let edge_luma: f32 = (m + neighbor) * 0.5;
+
+                // Trace the edge in both directions until the luma differs from the edge's one
+                let (center_x, center_y): (f32, f32) = (px as f32 + 0.5, py as f32 + 0.5);
+                let (edge_x, edge_y): (f32, f32) = if horizontal {
+                    (center_x, center_y + step * 0.5)
+                } else {
+                    (center_x + step * 0.5, center_y)
+                };
+                let (along_x, along_y): (f32, f32) = if horizontal { (1.0, 0.0) } else { (0.0, 1.0) };
+                let trace = |direction: f32| -> (f32, f32) {
+                    let mut distance: f32 = 0.0;
+                    let mut end_luma: f32 = 0.0;
+                    for stride in Fxaa::SEARCH_STEPS {
+                        distance += stride;
+                        let c: Vec4 =
+                            sample(edge_x + along_x * distance * direction, edge_y + along_y * distance * direction);
+                        end_luma = luma_of(c) - edge_luma;
+                        if end_luma.abs() >= gradient {
+                            break;
+                        }
+                    }
+                    (distance, end_luma)
+                };
+                let (distance_negative, end_negative): (f32, f32) = trace(-1.0);
+                let (distance_positive, end_positive): (f32, f32) = trace(1.0);
+
+                // The pixel is blended only on the side of the step where the luma goes the other way than at its end
+                let closer_end: f32 = if distance_negative < distance_positive {
+                    end_negative
+                } else {
+                    end_positive
+                };
+                let edge_offset: f32 = if (closer_end < 0.0) != (m - edge_luma < 0.0) {
+                    0.5 - distance_negative.min(distance_positive) / (distance_negative + distance_positive)
+                } else {
+                    0.0
+                };
+                let offset: f32 = edge_offset.max(subpixel_offset) * step;
+                let blended: Vec4 = if horizontal {
+                    sample(center_x, center_y + offset)
+                } else {
+                    sample(center_x + offset, center_y)
+                };
+                *target.get_unchecked(x, y) = match color_format {
+                    ColorBufferFormat::Rgba8 => {
+                        let quantize = |c: f32| -> u8 { (c * 255.0 + 0.5).clamp(0.0, 255.0) as u8 };
+                        RGBA::new(quantize(blended.x), quantize(blended.y), quantize(blended.z), quantize(blended.w))
+                            .to_u32()
+                    }
+                    ColorBufferFormat::Rgb9e5 => encode_rgb9e5(blended.x, blended.y, blended.z),
+                };
+            }
+        }
+    }
+}
+
+// Runs the function over each tile of the buffer, the tiles are processed in parallel.
+fn for_each_tile_parallel<T, F>(buffer: &mut TiledBuffer<T, 64, 64>, f: F)
+where
+    T: Copy + bytemuck::Zeroable + bytemuck::Pod + Default,
+    F: Fn(&mut TiledBufferTileMut<T, 64, 64>) + Send + Sync,
+{
+    let mut tiles: Vec<TiledBufferTileMut<T, 64, 64>> = Vec::new();
+    for y in 0..buffer.tiles_y() {
+        for x in 0..buffer.tiles_x() {
+            tiles.push(buffer.tile_mut(x, y));
+        }
+    }
+    use rayon::prelude::*;
+    tiles.par_iter_mut().for_each(f);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 16x16 view of the floor at y = -1 seen from above, optionally with a wall at x = 0 rising from it
+    fn g_buffer(with_wall: bool) -> (TiledBuffer<u16, 64, 64>, TiledBuffer<u32, 64, 64>, AmbientOcclusion) {
+        let mut depth = TiledBuffer::<u16, 64, 64>::new(16, 16);
+        let mut normals = TiledBuffer::<u32, 64, 64>::new(16, 16);
+        depth.fill(DEPTH_FAR);
+        normals.fill(NORMAL_NONE.to_u32());
+        // Looking down -Y from y = 1
+        let view: Mat44 = Mat44::rotate_yz(std::f32::consts::FRAC_PI_2) * Mat44::translate(Vec3::new(0.0, -1.0, 0.0));
+        let projection: Mat44 = Mat44::orthographic(-2.0, 2.0, -2.0, 2.0, 0.0, 4.0);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 16, 16));
+        let floor: [Vec3; 4] = [
+            Vec3::new(-2.0, -1.0, 2.0),
+            Vec3::new(2.0, -1.0, 2.0),
+            Vec3::new(2.0, -1.0, -2.0),
+            Vec3::new(-2.0, -1.0, -2.0),
+        ];
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &floor,
+            normals: &[Vec3::new(0.0, 1.0, 0.0); 4],
+            indices: &[0, 1, 2, 0, 2, 3],
+            view,
+            projection,
+            ..Default::default()
+        });
+        if with_wall {
+            // A thin block along Z covering the columns around x = 0
+            let top: [Vec3; 4] = [
+                Vec3::new(-0.25, 0.0, 2.0),
+                Vec3::new(0.25, 0.0, 2.0),
+                Vec3::new(0.25, 0.0, -2.0),
+                Vec3::new(-0.25, 0.0, -2.0),
+            ];
+            rasterizer.commit(&RasterizationCommand {
+                world_positions: &top,
+                normals: &[Vec3::new(0.0, 1.0, 0.0); 4],
+                indices: &[0, 1, 2, 0, 2, 3],
+                view,
+                projection,
+                ..Default::default()
+            });
+        }
+        rasterizer.draw(&mut Framebuffer {
+            depth_buffer: Some(&mut depth),
+            normal_buffer: Some(&mut normals),
+            ..Default::default()
+        });
+        let parameters = AmbientOcclusion { view, projection, radius: 1.5, ..Default::default() };
+        (depth, normals, parameters)
+    }
+
+    #[test]
+    fn flat_floor_is_open() {
+        let (depth, normals, parameters) = g_buffer(false);
+        let mut occlusion = TiledBuffer::<u8, 64, 64>::new(16, 16);
+        ambient_occlusion(&depth, &normals, &parameters, &mut occlusion);
+        for y in 0..16 {
+            for x in 0..16 {
+                assert_eq!(occlusion.at(x, y), 255);
+            }
+        }
+    }
+
+    #[test]
+    fn floor_next_to_block_is_occluded() {
+        let (depth, normals, parameters) = g_buffer(true);
+        let mut occlusion = TiledBuffer::<u8, 64, 64>::new(16, 16);
+        ambient_occlusion(&depth, &normals, &parameters, &mut occlusion);
+        // The floor right next to the block is darker than the one far from it
+        assert!(occlusion.at(6, 8) < occlusion.at(0, 8));
+        assert!(occlusion.at(9, 8) < occlusion.at(15, 8));
+        assert_eq!(occlusion.at(0, 8), 255);
+        // The block's top has nothing above it
+        assert_eq!(occlusion.at(8, 8), 255);
+    }
+
+    #[test]
+    fn zero_intensity_disables() {
+        let (depth, normals, parameters) = g_buffer(true);
+        let mut occlusion = TiledBuffer::<u8, 64, 64>::new(16, 16);
+        ambient_occlusion(&depth, &normals, &AmbientOcclusion { intensity: 0.0, ..parameters }, &mut occlusion);
+        assert_eq!(occlusion.at(6, 8), 255);
+    }
+
+    #[test]
+    fn pixels_without_geometry_are_open() {
+        let mut depth = TiledBuffer::<u16, 64, 64>::new(8, 8);
+        let mut normals = TiledBuffer::<u32, 64, 64>::new(8, 8);
+        depth.fill(DEPTH_FAR);
+        normals.fill(NORMAL_NONE.to_u32());
+        let mut occlusion = TiledBuffer::<u8, 64, 64>::new(8, 8);
+        ambient_occlusion(&depth, &normals, &AmbientOcclusion::default(), &mut occlusion);
+        assert_eq!(occlusion.at(4, 4), 255);
+    }
+
+    #[test]
+    fn applying_modulates_color() {
+        let mut occlusion = TiledBuffer::<u8, 64, 64>::new(2, 1);
+        *occlusion.at_mut(0, 0) = 255;
+        *occlusion.at_mut(1, 0) = 128;
+        let mut color = TiledBuffer::<u32, 64, 64>::new(2, 1);
+        color.fill(RGBA::new(200, 100, 50, 255).to_u32());
+        apply_ambient_occlusion(&mut color, ColorBufferFormat::Rgba8, &occlusion);
+        assert_eq!(RGBA::from_u32(color.at(0, 0)), RGBA::new(200, 100, 50, 255));
+        assert_eq!(RGBA::from_u32(color.at(1, 0)), RGBA::new(100, 50, 25, 255));
+
+        let mut hdr = TiledBuffer::<u32, 64, 64>::new(2, 1);
+        hdr.fill(encode_rgb9e5(2.0, 1.0, 0.5));
+        apply_ambient_occlusion(&mut hdr, ColorBufferFormat::Rgb9e5, &occlusion);
+        let darkened: Vec3 = decode_rgb9e5(hdr.at(1, 0));
+        assert!((darkened.x - 1.0).abs() < 0.02);
+        assert!((darkened.z - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn downscale_averages_in_linear_space() {
+        let mut color = TiledBuffer::<u32, 64, 64>::new(2, 2);
+        color.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        *color.at_mut(0, 0) = RGBA::new(255, 255, 255, 255).to_u32();
+        *color.at_mut(1, 1) = RGBA::new(255, 255, 255, 255).to_u32();
+        let thumbnail = downscale(&color, ColorBufferFormat::Rgba8, 1, 1, &Downscale::default());
+        // 0.5 ^ (1 / 2.2) * 255, rather than the naive 128
+        assert_eq!(RGBA::from_u32(thumbnail.at(0, 0)), RGBA::new(186, 186, 186, 255));
+
+        let linear = downscale(&color, ColorBufferFormat::Rgba8, 1, 1, &Downscale { gamma: 1.0, ..Default::default() });
+        assert_eq!(RGBA::from_u32(linear.at(0, 0)), RGBA::new(128, 128, 128, 255));
+    }
+
+    #[test]
+    fn downscale_to_same_size_is_identity() {
+        let mut color = TiledBuffer::<u32, 64, 64>::new(130, 70);
+        for y in 0..70u16 {
+            for x in 0..130u16 {
+                *color.at_mut(x, y) = RGBA::new((x * 2) as u8, (y * 3) as u8, (x + y) as u8, 255).to_u32();
+            }
+        }
+        let copy = downscale(&color, ColorBufferFormat::Rgba8, 130, 70, &Downscale::default());
+        assert_eq!(copy.as_flat_buffer().as_u32_slice(), color.as_flat_buffer().as_u32_slice());
+    }
+
+    #[test]
+    fn downscale_with_fractional_ratio_keeps_uniform_color() {
+        let mut color = TiledBuffer::<u32, 64, 64>::new(200, 150);
+        color.fill(RGBA::new(10, 120, 240, 200).to_u32());
+        let thumbnail = downscale(&color, ColorBufferFormat::Rgba8, 70, 45, &Downscale::default());
+        assert_eq!((thumbnail.width(), thumbnail.height()), (70, 45));
+        for y in 0..45 {
+            for x in 0..70 {
+                assert_eq!(RGBA::from_u32(thumbnail.at(x, y)), RGBA::new(10, 120, 240, 200), "x={x}, y={y}");
+            }
+        }
+    }
+
+    #[test]
+    fn downscale_transparent_pixels_dont_bleed() {
+        let mut color = TiledBuffer::<u32, 64, 64>::new(2, 1);
+        *color.at_mut(0, 0) = RGBA::new(255, 0, 0, 255).to_u32();
+        *color.at_mut(1, 0) = RGBA::new(0, 255, 0, 0).to_u32();
+        let straight = downscale(&color, ColorBufferFormat::Rgba8, 1, 1, &Downscale::default());
+        assert_eq!(RGBA::from_u32(straight.at(0, 0)), RGBA::new(255, 0, 0, 128));
+
+        *color.at_mut(1, 0) = RGBA::new(0, 0, 0, 0).to_u32();
+        let premultiplied =
+            downscale(&color, ColorBufferFormat::Rgba8, 1, 1, &Downscale { premultiplied: true, ..Default::default() });
+        assert_eq!(RGBA::from_u32(premultiplied.at(0, 0)), RGBA::new(128, 0, 0, 128));
+    }
+
+    #[test]
+    fn downscale_hdr_averages_linear_colors() {
+        let mut hdr = TiledBuffer::<u32, 64, 64>::new(2, 1);
+        *hdr.at_mut(0, 0) = encode_rgb9e5(1.0, 0.0, 4.0);
+        *hdr.at_mut(1, 0) = encode_rgb9e5(3.0, 0.5, 0.0);
+        let thumbnail = downscale(&hdr, ColorBufferFormat::Rgb9e5, 1, 1, &Downscale::default());
+        let average: Vec3 = decode_rgb9e5(thumbnail.at(0, 0));
+        assert!((average.x - 2.0).abs() < 0.02);
+        assert!((average.y - 0.25).abs() < 0.02);
+        assert!((average.z - 2.0).abs() < 0.02);
+    }
+
+    struct InvertColor;
+
+    impl PostProcessPass for InvertColor {
+        fn reads(&self) -> &[FramebufferComponent] {
+            &[FramebufferComponent::Color]
+        }
+
+        fn writes(&self) -> &[FramebufferComponent] {
+            &[FramebufferComponent::Color]
+        }
+
+        fn process(&self, _source: &PostProcessSource, tile: &mut FramebufferTile) {
+            let color = tile.color_buffer.as_mut().unwrap();
+            for y in 0..color.height as usize {
+                for x in 0..color.width as usize {
+                    *color.get_unchecked(x, y) ^= 0x00FFFFFF;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chain_runs_passes_in_order() {
+        let mut color = TiledBuffer::<u32, 64, 64>::new(100, 70);
+        let mut chain = PostProcessChain::new();
+        chain.add_fn(&[], &[FramebufferComponent::Color], false, |_, tile| {
+            let color = tile.color_buffer.as_mut().unwrap();
+            for y in 0..color.height as usize {
+                for x in 0..color.width as usize {
+                    *color.get_unchecked(x, y) = RGBA::new(255, 0, 0, 255).to_u32();
+                }
+            }
+        });
+        chain.add(InvertColor);
+        assert_eq!(chain.len(), 2);
+        chain.run(&mut Framebuffer { color_buffer: Some(&mut color), ..Default::default() });
+        for (x, y) in [(0, 0), (99, 0), (0, 69), (99, 69), (64, 64)] {
+            assert_eq!(RGBA::from_u32(color.at(x, y)), RGBA::new(0, 255, 255, 255));
+        }
+    }
+
+    #[test]
+    fn chain_gives_neighbors_from_before_pass() {
+        let mut color = TiledBuffer::<u32, 64, 64>::new(130, 1);
+        color.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        *color.at_mut(63, 0) = RGBA::new(255, 255, 255, 255).to_u32();
+        let mut chain = PostProcessChain::new();
+        // Shifts the image one pixel to the right, the in-place shift would smear the white pixel over the whole row
+        chain.add_fn(&[FramebufferComponent::Color], &[FramebufferComponent::Color], true, |source, tile| {
+            let previous = source.color_buffer.unwrap();
+            let origin_x: u16 = tile.origin_x();
+            let color = tile.color_buffer.as_mut().unwrap();
+            for x in 0..color.width as usize {
+                let source_x: u16 = (origin_x + x as u16).saturating_sub(1);
+                *color.get_unchecked(x, 0) = previous.at(source_x, 0);
+            }
+        });
+        chain.run(&mut Framebuffer { color_buffer: Some(&mut color), ..Default::default() });
+        chain.run(&mut Framebuffer { color_buffer: Some(&mut color), ..Default::default() });
+        for x in 0..130 {
+            let expected: u8 = if x == 65 { 255 } else { 0 };
+            assert_eq!(RGBA::from_u32(color.at(x, 0)).r, expected, "x={x}");
+        }
+    }
+
+    #[test]
+    fn chain_reads_buffers_not_written() {
+        let mut color = TiledBuffer::<u32, 64, 64>::new(70, 2);
+        let mut depth = TiledBuffer::<f32, 64, 64>::new(70, 2);
+        depth.fill(DEPTH_F32_FAR);
+        *depth.at_mut(66, 1) = 0.5;
+        let mut normals = TiledBuffer::<u32, 64, 64>::new(70, 2);
+        let mut chain = PostProcessChain::new();
+        chain.add_fn(&[FramebufferComponent::Depth], &[FramebufferComponent::Color], false, |source, tile| {
+            assert!(source.color_buffer.is_none());
+            let depth = source.depth_buffer_f32.unwrap();
+            let (origin_x, origin_y): (u16, u16) = (tile.origin_x(), tile.origin_y());
+            let color = tile.color_buffer.as_mut().unwrap();
+            for y in 0..color.height as usize {
+                for x in 0..color.width as usize {
+                    let d: f32 = depth.at(origin_x + x as u16, origin_y + y as u16);
+                    *color.get_unchecked(x, y) = RGBA::new((d * 255.0) as u8, 0, 0, 255).to_u32();
+                }
+            }
+        });
+        chain.add_fn(&[], &[FramebufferComponent::Normals], false, |_, tile| {
+            assert!(tile.width() > 0 && tile.color_buffer.is_none());
+            *tile.normal_buffer.as_mut().unwrap().get_unchecked(0, 0) = NORMAL_NONE.to_u32();
+        });
+        chain.run(&mut Framebuffer {
+            color_buffer: Some(&mut color),
+            depth_buffer_f32: Some(&mut depth),
+            normal_buffer: Some(&mut normals),
+            ..Default::default()
+        });
+        assert_eq!(RGBA::from_u32(color.at(66, 1)).r, 127);
+        assert_eq!(RGBA::from_u32(color.at(65, 1)).r, 0);
+        assert_eq!(normals.at(64, 0), NORMAL_NONE.to_u32());
+    }
+
+    #[test]
+    #[should_panic(expected = "Normals")]
+    fn chain_panics_on_missing_buffer() {
+        let mut color = TiledBuffer::<u32, 64, 64>::new(4, 4);
+        let mut chain = PostProcessChain::new();
+        chain.add_fn(&[FramebufferComponent::Normals], &[FramebufferComponent::Color], false, |_, _| {});
+        chain.run(&mut Framebuffer { color_buffer: Some(&mut color), ..Default::default() });
+    }
+
+    #[test]
+    fn bloom_keeps_dark_image() {
+        let mut color = TiledBuffer::<u32, 64, 64>::new(40, 30);
+        color.fill(RGBA::new(200, 100, 50, 255).to_u32());
+        let original = color.clone();
+        bloom(&mut color, ColorBufferFormat::Rgba8, &Bloom::default());
+        assert_eq!(color.values(), original.values());
+    }
+
+    #[test]
+    fn bloom_spreads_bright_pixels() {
+        let mut hdr = TiledBuffer::<u32, 64, 64>::new(100, 100);
+        hdr.fill(encode_rgb9e5(0.1, 0.1, 0.1));
+        for y in 48..52 {
+            for x in 48..52 {
+                *hdr.at_mut(x, y) = encode_rgb9e5(20.0, 10.0, 5.0);
+            }
+        }
+        bloom(&mut hdr, ColorBufferFormat::Rgb9e5, &Bloom::default());
+        let near_left: Vec3 = decode_rgb9e5(hdr.at(42, 50));
+        let near_right: Vec3 = decode_rgb9e5(hdr.at(57, 50));
+        let far: Vec3 = decode_rgb9e5(hdr.at(5, 5));
+        assert!(near_left.x > 0.5 && near_left.x > near_left.y && near_left.y > near_left.z);
+        assert!((near_left.x - near_right.x).abs() < near_left.x * 0.05);
+        assert!((far.x - 0.1).abs() < 0.01);
+        assert!(decode_rgb9e5(hdr.at(50, 50)).x >= 20.0);
+    }
+
+    #[test]
+    fn bloom_saturates_rgba8_and_keeps_alpha() {
+        let mut color = TiledBuffer::<u32, 64, 64>::new(80, 80);
+        color.fill(RGBA::new(0, 0, 0, 128).to_u32());
+        for y in 32..48 {
+            for x in 32..48 {
+                *color.at_mut(x, y) = RGBA::new(255, 255, 255, 255).to_u32();
+            }
+        }
+        bloom(&mut color, ColorBufferFormat::Rgba8, &Bloom { threshold: 0.5, intensity: 2.0, ..Default::default() });
+        assert_eq!(RGBA::from_u32(color.at(40, 40)), RGBA::new(255, 255, 255, 255));
+        let glow: RGBA = RGBA::from_u32(color.at(52, 40));
+        assert!(glow.r > 0 && glow.r == glow.g && glow.g == glow.b && glow.a == 128);
+        assert_eq!(RGBA::from_u32(color.at(2, 2)), RGBA::new(0, 0, 0, 128));
+
+        let original = color.clone();
+        bloom(&mut color, ColorBufferFormat::Rgba8, &Bloom { intensity: 0.0, ..Default::default() });
+        assert_eq!(color.values(), original.values());
+    }
+
+    fn run_fxaa(color: &mut TiledBuffer<u32, 64, 64>) {
+        let mut chain = PostProcessChain::new();
+        chain.add(Fxaa::default());
+        chain.run(&mut Framebuffer { color_buffer: Some(color), ..Default::default() });
+    }
+
+    #[test]
+    fn fxaa_keeps_flat_areas() {
+        let mut color = TiledBuffer::<u32, 64, 64>::new(70, 40);
+        color.fill(RGBA::new(10, 200, 30, 255).to_u32());
+        // Below the minimal contrast
+        *color.at_mut(20, 20) = RGBA::new(12, 202, 32, 255).to_u32();
+        let original = color.clone();
+        run_fxaa(&mut color);
+        assert_eq!(color.values(), original.values());
+    }
+
+    #[test]
+    fn fxaa_smooths_stair_steps() {
+        // A shallow edge with the steps 8 pixels long, crossing the tiles' border
+        let mut color = TiledBuffer::<u32, 64, 64>::new(96, 16);
+        for y in 0..16u16 {
+            for x in 0..96u16 {
+                let lit: bool = y * 8 > x;
+                *color.at_mut(x, y) = if lit {
+                    RGBA::new(255, 255, 255, 255)
+                } else {
+                    RGBA::new(0, 0, 0, 255)
+                }
+                .to_u32();
+            }
+        }
+        let original = color.clone();
+        run_fxaa(&mut color);
+        let mut blended: usize = 0;
+        for y in 0..16u16 {
+            for x in 0..96u16 {
+                let pixel: RGBA = RGBA::from_u32(color.at(x, y));
+                assert_eq!(pixel.a, 255);
+                if pixel.r > 16 && pixel.r < 240 {
+                    blended += 1;
+                    // Only the pixels next to the edge are touched
+                    assert!((y as i32 * 8 - x as i32).abs() <= 16, "x={x}, y={y}");
+                }
+            }
+        }
+        assert!(blended >= 20, "{blended}");
+        // Far from the edge
+        assert_eq!(color.at(90, 2), original.at(90, 2));
+        assert_eq!(color.at(2, 14), original.at(2, 14));
+    }
+}
@@ -1,6 +1,6 @@
 use super::super::math::*;
 use super::*;
-use crate::math::simd::U32x4;
+use crate::math::simd::{F32x4, U32x4, fast_reciprocal, mul_add};
 use arrayvec::ArrayVec;
 use std::cmp::{max, min};
 use std::ops::Add;
@@ -8,6 +8,7 @@ use std::ptr;
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CullMode {
     /// No culling — all triangles are rendered.
     None = 0,
@@ -21,6 +22,7 @@ pub enum CullMode {
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AlphaBlendingMode {
     /// Dc = Sc
     None = 0,
@@ -30,6 +32,417 @@ pub enum AlphaBlendingMode {
 
     /// D = Sc * Sa + Dc
     Additive = 2,
+
+    /// D = Sc * Sa * Dc + (1 - Sa) * Dc
+    Multiply = 3,
+
+    /// D = Sc * Sa + (1 - Sc * Sa) * Dc
+    Screen = 4,
+
+    /// D = Dc - Sc * Sa
+    Subtract = 5,
+
+    /// D = Sa * min(Sc, Dc) + (1 - Sa) * Dc
+    Min = 6,
+
+    /// D = Sa * max(Sc, Dc) + (1 - Sa) * Dc
+    Max = 7,
+
+    /// D = Sc + (1 - Sa) * Dc
+    /// Same as Normal, but the command's and the vertices' colors are already premultiplied by alpha.
+    PremultipliedNormal = 8,
+}
+
+impl AlphaBlendingMode {
+    // Whether the command's and the vertices' colors are premultiplied by alpha when committed.
+    fn premultiplies_colors(self) -> bool {
+        !matches!(self, AlphaBlendingMode::None | AlphaBlendingMode::PremultipliedNormal)
+    }
+
+    // Blends the source color premultiplied by alpha into the destination one, in floating point.
+    #[inline(always)]
+    fn blend(self, src: Vec4, dest: Vec3) -> Vec3 {
+        let a: f32 = src.w.clamp(0.0, 1.0);
+        let inv_a: f32 = 1.0 - a;
+        let channel = |s: f32, d: f32| -> f32 {
+            match self {
+                AlphaBlendingMode::None => s,
+                AlphaBlendingMode::Normal | AlphaBlendingMode::PremultipliedNormal => s + d * inv_a,
+                AlphaBlendingMode::Additive => s + d,
+                AlphaBlendingMode::Multiply => s * d + d * inv_a,
+                AlphaBlendingMode::Screen => s + d - s * d,
+                AlphaBlendingMode::Subtract => (d - s).max(0.0),
+                AlphaBlendingMode::Min => s.min(d * a) + d * inv_a,
+                AlphaBlendingMode::Max => s.max(d * a) + d * inv_a,
+            }
+        };
+        Vec3::new(channel(src.x, dest.x), channel(src.y, dest.y), channel(src.z, dest.z))
+    }
+}
+
+// The comparison of the fragment's depth against the one in the depth buffer, the fragment passes if it holds.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DepthTest {
+    /// No fragment passes.
+    Never = 0,
+
+    /// Passes if fragment < buffer.
+    Less = 1,
+
+    /// Passes if fragment <= buffer.
+    LEqual = 2,
+
+    /// Passes if fragment > buffer.
+    Greater = 3,
+
+    /// Passes if fragment >= buffer.
+    GEqual = 4,
+
+    /// Passes if fragment == buffer.
+    Equal = 5,
+
+    /// Every fragment passes.
+    Always = 6,
+}
+
+impl DepthTest {
+    #[inline(always)]
+    pub fn passes(self, fragment: u16, buffer: u16) -> bool {
+        match self {
+            DepthTest::Never => false,
+            DepthTest::Less => fragment < buffer,
+            DepthTest::LEqual => fragment <= buffer,
+            DepthTest::Greater => fragment > buffer,
+            DepthTest::GEqual => fragment >= buffer,
+            DepthTest::Equal => fragment == buffer,
+            DepthTest::Always => true,
+        }
+    }
+
+    // Same as passes(), but for the reverse-Z depth, where the nearer fragments have the greater values.
+    #[inline(always)]
+    pub fn passes_reversed(self, fragment: f32, buffer: f32) -> bool {
+        match self {
+            DepthTest::Never => false,
+            DepthTest::Less => fragment > buffer,
+            DepthTest::LEqual => fragment >= buffer,
+            DepthTest::Greater => fragment < buffer,
+            DepthTest::GEqual => fragment <= buffer,
+            DepthTest::Equal => fragment == buffer,
+            DepthTest::Always => true,
+        }
+    }
+}
+
+// Offset of the depth, with the same semantics as glPolygonOffset(): the offset is slope * DZ + constant * r, where DZ
+// is the triangle's maximum depth slope across a pixel in screen space and r is the smallest resolvable depth
+// difference, i.e. a single step of the 16-bit depth or the float precision at the triangle's depth.
+// Positive values push the fragments away from the camera, negative ones pull them closer.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DepthBias {
+    pub constant: f32,
+    pub slope: f32,
+}
+
+impl DepthBias {
+    pub fn new(constant: f32, slope: f32) -> Self {
+        Self { constant, slope }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.constant == 0.0 && self.slope == 0.0
+    }
+
+    // The offset in the depth's units given the triangle's depth slopes along X and Y and the resolvable difference.
+    #[inline(always)]
+    pub fn offset(&self, dz_dx: f32, dz_dy: f32, r: f32) -> f32 {
+        self.slope * dz_dx.abs().max(dz_dy.abs()) + self.constant * r
+    }
+}
+
+// Uniform color terms applied to the fragments' colors after the texture sampling: color * multiply + add.
+// Animating them tints, fades or flashes a whole command without touching its vertices, e.g. multiply by
+// (1, 1, 1, t) fades it out and add (t, t, t, 0) flashes it white.
+// With alpha blending the colors are premultiplied, the terms are applied to the colors as if they were not.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorTint {
+    pub multiply: Vec4,
+    pub add: Vec4,
+}
+
+impl ColorTint {
+    pub const IDENTITY: Self = Self { multiply: Vec4::new(1.0, 1.0, 1.0, 1.0), add: Vec4::new(0.0, 0.0, 0.0, 0.0) };
+
+    pub fn new(multiply: Vec4, add: Vec4) -> Self {
+        Self { multiply, add }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    // Applies the terms to the color, which is premultiplied by its alpha or not.
+    #[inline(always)]
+    fn apply(&self, color: Vec4, premultiplied: bool) -> Vec4 {
+        let a: f32 = (color.w * self.multiply.w + self.add.w).clamp(0.0, 1.0);
+        if !premultiplied {
+            return Vec4::new(
+                color.x * self.multiply.x + self.add.x,
+                color.y * self.multiply.y + self.add.y,
+                color.z * self.multiply.z + self.add.z,
+                a,
+            );
+        }
+        let unpremultiply: f32 = if color.w > 0.0 { 1.0 / color.w } else { 0.0 };
+        Vec4::new(
+            (color.x * unpremultiply * self.multiply.x + self.add.x) * a,
+            (color.y * unpremultiply * self.multiply.y + self.add.y) * a,
+            (color.z * unpremultiply * self.multiply.z + self.add.z) * a,
+            a,
+        )
+    }
+
+    // Same as apply(), but for the 8-bit color components.
+    #[inline(always)]
+    fn apply_rgba8(&self, r: u8, g: u8, b: u8, a: u8, premultiplied: bool) -> (u8, u8, u8, u8) {
+        let color: Vec4 = Vec4::new(r as f32, g as f32, b as f32, a as f32) * (1.0 / 255.0);
+        let tinted: Vec4 = self.apply(color, premultiplied);
+        let quantize = |c: f32| -> u8 { (c * 255.0 + 0.5).clamp(0.0, 255.0) as u8 };
+        (quantize(tinted.x), quantize(tinted.y), quantize(tinted.z), quantize(tinted.w))
+    }
+}
+
+impl Default for ColorTint {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+// The falloff of the fog with the distance from the camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FogMode {
+    /// The fog grows linearly from none at the start distance to full at the end one.
+    Linear { start: f32, end: f32 },
+
+    /// The fragments keep exp(-density * distance) of their color.
+    Exp { density: f32 },
+
+    /// The fragments keep exp(-(density * distance)^2) of their color.
+    Exp2 { density: f32 },
+}
+
+// Distance fog blended into the fragments' colors before they are written.
+// The distance is the fragment's view-space depth, i.e. the W of its clip-space position, which is constant for
+// orthographic projections.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fog {
+    pub mode: FogMode,
+    pub color: Vec3,
+}
+
+impl Fog {
+    pub fn new(mode: FogMode, color: Vec3) -> Self {
+        Self { mode, color }
+    }
+
+    // The fraction of the fragment's color which remains at the distance, in [0, 1].
+    #[inline(always)]
+    pub fn factor(&self, distance: f32) -> f32 {
+        let factor: f32 = match self.mode {
+            FogMode::Linear { start, end } => {
+                if end > start {
+                    (end - distance) / (end - start)
+                } else if distance < start {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            FogMode::Exp { density } => (-density * distance).exp(),
+            FogMode::Exp2 { density } => (-(density * distance) * (density * distance)).exp(),
+        };
+        factor.clamp(0.0, 1.0)
+    }
+
+    // Blends the fog into the color, which is premultiplied by its alpha or not.
+    #[inline(always)]
+    fn apply(&self, color: Vec4, distance: f32, premultiplied: bool) -> Vec4 {
+        let factor: f32 = self.factor(distance);
+        let fog: Vec3 = if premultiplied {
+            self.color * (color.w * (1.0 - factor))
+        } else {
+            self.color * (1.0 - factor)
+        };
+        Vec4::new(color.x * factor + fog.x, color.y * factor + fog.y, color.z * factor + fog.z, color.w)
+    }
+
+    // Same as apply(), but for the 8-bit color components.
+    #[inline(always)]
+    fn apply_rgba8(&self, r: u8, g: u8, b: u8, a: u8, distance: f32, premultiplied: bool) -> (u8, u8, u8, u8) {
+        let color: Vec4 = Vec4::new(r as f32, g as f32, b as f32, a as f32) * (1.0 / 255.0);
+        let fogged: Vec4 = self.apply(color, distance, premultiplied);
+        let quantize = |c: f32| -> u8 { (c * 255.0 + 0.5).clamp(0.0, 255.0) as u8 };
+        (quantize(fogged.x), quantize(fogged.y), quantize(fogged.z), a)
+    }
+}
+
+// The surface's response to the lights evaluated per vertex, the colors are linear.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightingMaterial {
+    // Default: (0, 0, 0).
+    pub ambient: Vec3,
+
+    // Default: (1, 1, 1).
+    pub diffuse: Vec3,
+
+    // Default: (0, 0, 0), i.e. no highlights.
+    pub specular: Vec3,
+
+    // The Blinn-Phong exponent, the higher the smaller the highlights are.
+    // Default: 32.
+    pub shininess: f32,
+}
+
+impl Default for LightingMaterial {
+    fn default() -> Self {
+        Self {
+            ambient: Vec3::new(0.0, 0.0, 0.0),
+            diffuse: Vec3::new(1.0, 1.0, 1.0),
+            specular: Vec3::new(0.0, 0.0, 0.0),
+            shininess: 32.0,
+        }
+    }
+}
+
+// Lighting evaluated once per vertex in commit(), i.e. Gouraud shading, and baked into the vertices' colors, which are
+// then interpolated across the triangles as usual. A cheap alternative to the per-pixel deferred lighting.
+// The lights are in world space, the vertices without normals are lit by the normals of their triangles' faces.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct VertexLighting<'a> {
+    pub lights: &'a [Light],
+    pub material: LightingMaterial,
+}
+
+impl VertexLighting<'_> {
+    // The vertex's color, which is premultiplied by its alpha or not, lit at the position facing the normal and viewed
+    // from the eye: color * (ambient + diffuse) + specular.
+    fn apply(&self, color: Vec4, position: Vec3, normal: Vec3, eye: Vec3, premultiplied: bool) -> Vec4 {
+        let to_eye: Vec3 = (eye - position).normalized();
+        let mut diffuse: Vec3 = self.material.ambient;
+        let mut specular: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+        for light in self.lights {
+            let Some((direction, light_color)) = light.incident(position) else {
+                continue;
+            };
+            let n_dot_l: f32 = dot(normal, direction);
+            if n_dot_l <= 0.0 {
+                continue;
+            }
+            diffuse += self.material.diffuse * light_color * n_dot_l;
+            let half: Vec3 = (direction + to_eye).normalized();
+            specular += self.material.specular * light_color * dot(normal, half).max(0.0).powf(self.material.shininess);
+        }
+        if premultiplied {
+            specular = specular * color.w;
+        }
+        Vec4::new(
+            color.x * diffuse.x + specular.x,
+            color.y * diffuse.y + specular.y,
+            color.z * diffuse.z + specular.z,
+            color.w,
+        )
+    }
+}
+
+// The channels of the color buffer the fragments write into, the others keep their values.
+// Blending reads the destination as usual, only the masked-out channels of the result are discarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorWriteMask {
+    pub r: bool,
+    pub g: bool,
+    pub b: bool,
+    pub a: bool,
+}
+
+impl ColorWriteMask {
+    pub const ALL: Self = Self { r: true, g: true, b: true, a: true };
+    pub const NONE: Self = Self { r: false, g: false, b: false, a: false };
+
+    pub fn new(r: bool, g: bool, b: bool, a: bool) -> Self {
+        Self { r, g, b, a }
+    }
+
+    // The mask of the written bits of an RGBA8 pixel.
+    fn rgba8_bits(&self) -> u32 {
+        (if self.r { 0x0000_00FF } else { 0 })
+            | (if self.g { 0x0000_FF00 } else { 0 })
+            | (if self.b { 0x00FF_0000 } else { 0 })
+            | (if self.a { 0xFF00_0000 } else { 0 })
+    }
+
+    // Takes the written channels from the color and the rest from the destination.
+    fn select(&self, color: Vec3, dest: Vec3) -> Vec3 {
+        Vec3::new(
+            if self.r { color.x } else { dest.x },
+            if self.g { color.y } else { dest.y },
+            if self.b { color.z } else { dest.z },
+        )
+    }
+}
+
+impl Default for ColorWriteMask {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+// Where the alpha of the fragments comes from, see RasterizationCommand::alpha_source. The color channels are always
+// the product of the texel's and the vertex's ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AlphaSource {
+    // The texel's alpha times the vertex's one.
+    #[default]
+    Modulate,
+
+    // The texel's alpha, the vertex's one is ignored, e.g. for alpha-tested cutouts with faded vertex colors.
+    Texture,
+
+    // The vertex's alpha, the texel's one is ignored, e.g. for blended glass with an opaque-looking texture.
+    Vertex,
+
+    // The fixed alpha, both the texel's and the vertex's ones are ignored.
+    Constant(u8),
+}
+
+impl AlphaSource {
+    // The alpha replacing the command's and the vertices' ones, if any.
+    fn vertex_alpha(self) -> Option<f32> {
+        match self {
+            AlphaSource::Modulate | AlphaSource::Vertex => None,
+            AlphaSource::Texture => Some(1.0),
+            AlphaSource::Constant(alpha) => Some(alpha as f32 / 255.0),
+        }
+    }
+
+    // Whether the texels are made opaque before being multiplied by the vertices' colors.
+    fn ignores_texture_alpha(self) -> bool {
+        matches!(self, AlphaSource::Vertex | AlphaSource::Constant(_))
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpacityHint {
+    /// The triangles are drawn in the submission order.
+    Ordered = 0,
+
+    /// The triangles are depth-tested and don't blend, they are drawn front-to-back when depth sorting is enabled.
+    Opaque = 1,
+
+    /// The triangles are blended, they are drawn back-to-front when depth sorting is enabled.
+    Translucent = 2,
 }
 
 #[repr(u8)]
@@ -45,6 +458,20 @@ enum VerticesColorInterpolationMode {
     PerVertex = 2,
 }
 
+// The matrices placing the geometry of a command, see RasterizationCommand::model, view and projection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transforms {
+    pub model: Mat34,
+    pub view: Mat44,
+    pub projection: Mat44,
+}
+
+impl Default for Transforms {
+    fn default() -> Self {
+        Self { model: Mat34::identity(), view: Mat44::identity(), projection: Mat44::identity() }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RasterizationCommand<'a> {
     pub world_positions: &'a [Vec3],
@@ -59,6 +486,10 @@ pub struct RasterizationCommand<'a> {
     pub tex_coords: &'a [Vec2], // empty if absent
     pub colors: &'a [Vec4],     // empty if absent, .color will be used
 
+    // The secondary texture coordinates the lightmap is sampled with, e.g. the baked lightmap's unique unwrapping.
+    // Default: empty, i.e. zeroes.
+    pub tex_coords2: &'a [Vec2],
+
     /// Triangle indices: [t0v0, t0v1, t0v2, t1v0, t1v1, t1v2, ...].
     /// Optional, monotonic indices to cover all world positions will be assumed if none is provided
     pub indices: &'a [u32],
@@ -71,10 +502,28 @@ pub struct RasterizationCommand<'a> {
 
     pub normal_map: Option<std::sync::Arc<Texture>>,
 
+    // Optional lightmap sampled with the secondary texture coordinates, its color multiplies the texel's one. The mip
+    // level is selected independently of the texture's, by the secondary coordinates' footprint. Sampled with the
+    // command's filter, clamped to the edges and not affected by the texture region. The anisotropic filter falls back
+    // to the trilinear one.
+    // Default: None.
+    pub lightmap: Option<std::sync::Arc<Texture>>,
+
+    // Optional sub-region of the texture and the normal map to be used, e.g. a cell of a texture atlas.
+    // Texture coordinates [0, 1] cover the region, sampling is wrapped or clamped within it.
+    // Default: None, i.e. the entire texture.
+    pub texture_region: Option<TextureRegion>,
+
     // Set the filter to be used when sampling the texture.
     // Default: nearest.
     pub sampling_filter: SamplerFilter,
 
+    // Set how the texture coordinates outside of [0, 1] are addressed, for U and V independently.
+    // Applies to both the texture and the normal map, ignored when a texture region is set.
+    // Default: repeat.
+    pub address_mode_u: SamplerAddressMode,
+    pub address_mode_v: SamplerAddressMode,
+
     // Sets whether the rasterizer should use alpha blending when writing fragments to the framebuffer.
     // If disabled, the fragment color will be written as is.
     // Default: None.
@@ -86,658 +535,3876 @@ pub struct RasterizationCommand<'a> {
     // The comparison function is fixed to "greater than or equal to".
     // Zero value (default) effectively disables the test.
     pub alpha_test: u8,
-}
 
-#[derive(Debug, Clone)]
-struct ScheduledCommand {
-    texture: Option<std::sync::Arc<Texture>>,
-    normal_map: Option<std::sync::Arc<Texture>>,
-    sampling_filter: SamplerFilter,
-    alpha_blending: AlphaBlendingMode,
-    alpha_test: u8,
-    color_interpolation: VerticesColorInterpolationMode,
-}
+    // Sets where the fragments' alpha comes from: the texture, the vertices' and the command's colors, both or neither.
+    // The alpha test still compares the texel's alpha. With PremultipliedNormal the vertices' colors are taken as is.
+    // Default: Modulate.
+    pub alpha_source: AlphaSource,
 
-#[derive(Debug, Clone, Copy)]
-struct ScheduledTriangle {
-    // index of a rasterization command
-    cmd: u16,
+    // Enables the "performance" pipeline variant for this command.
+    // Triangles whose w varies less than the rasterizer's threshold are interpolated affinely in screen space, skipping
+    // the per-fragment perspective correction, and their per-vertex colors are interpolated in fixed-point.
+    // Can also be enabled for all commands via Rasterizer::set_fast_math().
+    // Default: false.
+    pub fast_math: bool,
 
-    // index of the triangle's first vertex
-    tri_start: u16,
-}
+    // Optional scissor rectangle in framebuffer pixels, same as the viewport. Only the pixels inside both the viewport
+    // and the scissor rectangle are rasterized, e.g. to clip a UI panel or to split the screen without separate buffers.
+    // Default: None.
+    pub scissor: Option<Viewport>,
 
-#[derive(Debug, Clone, Copy)]
-struct TileBinningBounds {
-    xmin_24_8: i32,
-    ymin_24_8: i32,
-    xmax_24_8: i32,
-    ymax_24_8: i32,
-}
+    // Optional interpretation of the texture as a signed distance field, e.g. for text and icons from an SDF atlas.
+    // Only the red channel of the texture is used. The field is turned into the fragment's coverage, which is meant to
+    // be used with the Normal alpha blending, or with the alpha test for hard edges.
+    // Default: None.
+    pub sdf: Option<SdfSampling>,
 
-struct Tile {
-    triangles: Vec<ScheduledTriangle>,
-    local_viewport: Viewport,
-    binning_bounds: TileBinningBounds,
-}
+    // Optional analytic pattern evaluated over the texture coordinates, which replaces the texture if there's one.
+    // E.g. an editor-style ground plane without any textures, which stays crisp up close and doesn't alias far away.
+    // Default: None.
+    pub pattern: Option<ProceduralPattern>,
 
-struct TiledJob {
-    framebuffer_tile: FramebufferTile,
-    render_tile: *const Tile,
-    statistics: PerTileStatistics,
-}
-unsafe impl Send for TiledJob {}
-unsafe impl Sync for TiledJob {}
+    // Values passed to the vertex and fragment hooks, e.g. the time to animate a procedural effect.
+    // Default: zeroes.
+    pub uniforms: Uniforms,
 
-#[derive(Debug, Clone, Copy)]
-pub struct RasterizerStatistics {
-    // The number of triangles that were requested to be rasterized.
-    pub committed_triangles: usize,
+    // Optional playback of a baked vertex animation, which replaces the positions and the normals of the vertices
+    // before the vertex hook runs. The bounding box, if there's one, must cover all the frames, see
+    // VertexAnimationTexture::aabb().
+    // Default: None.
+    pub vertex_animation: Option<VertexAnimation<'a>>,
 
-    // The number of triangles that were scheduled for rasterization after culling and clipping.
-    pub scheduled_triangles: usize,
+    // Optional blending of the vertices between blend shapes, applied after the vertex animation and before the
+    // skinning, see MorphTargets. The bounding box, if there's one, must cover the blended positions.
+    // Default: None.
+    pub morph_targets: Option<MorphTargets<'a>>,
 
-    // The number of triangles rasterized across all tiles.
-    // (the same triangle can be rasterized multiple times if it is visible in multiple tiles)
-    pub binned_triangles: usize,
+    // Optional deformation of the vertices by the joints of a skeleton, applied after the vertex animation and the morph
+    // targets and before the displacement, see Skinning. The bounding box, if there's one, must cover the skinned positions.
+    // Default: None.
+    pub skinning: Option<Skinning<'a>>,
 
-    // The number of factual rasterized pixels.
-    // Gathered only in Debug builds.
-    pub fragments_drawn: usize,
-}
+    // Optional displacement of the vertices along +Y by a heightmap sampled at their texture coordinates, applied after
+    // the vertex animation and before the vertex hook, see HeightmapDisplacement. The command must have the texture
+    // coordinates, and the bounding box, if there's one, must cover the displaced positions.
+    // Default: None.
+    pub displacement: Option<HeightmapDisplacement>,
 
-#[derive(Debug, Clone, Copy)]
-struct PerTileStatistics {
-    pub fragments_drawn: usize,
-}
+    // Optional function called for each of the command's vertices before they are transformed, e.g. to displace them.
+    // Default: None.
+    pub vertex_hook: Option<VertexHook>,
 
-#[repr(u8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum NormalsProcessingMode {
-    // Normals are not interpolated nor written into a normals buffer.
-    // Normals buffer is not available.
-    None = 0,
+    // Optional function called for each of the fragments, its output replaces the texel.
+    // Default: None.
+    pub fragment_hook: Option<FragmentHook>,
+
+    // Tells how the command's triangles can be reordered by depth when depth sorting is enabled via
+    // Rasterizer::set_depth_sorting(). Triangles are only reordered among the consecutive triangles of a tile whose
+    // commands have the same hint, the other commands keep their place in the submission order.
+    // Default: Ordered.
+    pub opacity: OpacityHint,
+
+    // Optional bounding box of the positions in object space, e.g. MeshData::aabb. If the box transformed by the model,
+    // view and projection matrices is entirely outside the view frustum, commit() skips the command without processing
+    // its vertices and triangles, and counts it in RasterizerStatistics::culled_commands.
+    // The command is never culled while the geometry is retained for draw_views(), as the views can see it.
+    // Default: None.
+    pub aabb: Option<AABB>,
+
+    // Optional model matrices of the instances the command is drawn as, e.g. the same bush scattered over a field
+    // without duplicating its vertices. Each instance is placed by the command's model times its own matrix and is
+    // culled separately by the bounding box, counting in RasterizerStatistics::culled_commands. The vertex animation,
+    // the displacement and the vertex hook run once for all the instances. The previous model, if any, is combined the
+    // same way, i.e. the instances are assumed to keep their place relative to the command's model.
+    // Default: empty, i.e. a single instance placed by the model.
+    pub instance_models: &'a [Mat34],
+
+    // Optional colors of the instances multiplied by the command's color, either empty or one per instance model.
+    // Default: empty.
+    pub instance_colors: &'a [Vec4],
+
+    // Clamps the fragments' depth to the [near, far] range instead of clipping the triangles against the far plane, like
+    // the hardware depth clamp does. The triangles are still clipped against the near plane. Useful for skyboxes and
+    // light volumes which must not be cut off by the far plane, those are drawn at the far depth instead.
+    // The clamped depth is the one right before DEPTH_FAR, so that the fragments beyond the far plane still pass the
+    // default Less test against a cleared depth buffer.
+    // The command is not culled by its bounding box against the far plane either.
+    // Default: false.
+    pub depth_clamp: bool,
+
+    // Sets the comparison of the fragments' depth against the depth buffer, only performed when there's one.
+    // The triangles are only rejected by the hierarchical Z with Less and LEqual.
+    // Default: Less.
+    pub depth_test: DepthTest,
+
+    // Sets whether the fragments which passed the tests write their depth into the depth buffer, e.g. translucent
+    // geometry is usually tested against the depth buffer, but doesn't write into it.
+    // Default: true.
+    pub depth_write: bool,
+
+    // Offset of the fragments' depth applied before the depth test, e.g. to draw decals over coplanar walls without
+    // z-fighting, or to nudge shadow casters.
+    // Default: no offset.
+    pub depth_bias: DepthBias,
+
+    // Sets the channels of the color buffer the fragments write into, e.g. NONE lays down the depth only.
+    // The HDR color buffers have no alpha, its flag is ignored there.
+    // Default: all channels.
+    pub color_write_mask: ColorWriteMask,
+
+    // Sets the uniform multiplicative and additive terms applied to the fragments' colors after the texture sampling.
+    // Default: identity.
+    pub tint: ColorTint,
+
+    // Sets the distance fog blended into the fragments' colors after the tint.
+    // Default: None.
+    pub fog: Option<Fog>,
 
-    // Per-vertex normals are interpolated and written into a normals buffer.
-    // Normals buffer is available.
-    Vertex = 1,
+    // Lights the vertices in commit() and multiplies their colors by the result.
+    // Default: None.
+    pub lighting: Option<VertexLighting<'a>>,
 
-    // Per-vertex normals and tangents are interpolated, a normal map is sampled, multiplied by TBN and written into the normals buffer.
-    // Normals buffer is available.
-    NormalMapping = 2,
+    // Mixes the reflections of an environment map into the fragments' colors before the tint.
+    // Default: None.
+    pub reflection: Option<Reflection>,
+
+    // The model matrix of the previous frame. When the framebuffer has a motion buffer, the fragments write their
+    // screen-space motion from where the previous model and view-projection placed them, so that the moving meshes
+    // are reprojected correctly, not only the static ones seen by a moving camera.
+    // Default: None, i.e. the same as the model.
+    pub previous_model: Option<Mat34>,
+
+    // The projection times the view matrix of the previous frame, see previous_model.
+    // Default: None, i.e. the same as the current ones.
+    pub previous_view_projection: Option<Mat44>,
 }
 
-pub struct Rasterizer {
-    viewport: Viewport,
-    viewport_scale: ViewportScale,
-    vertices: Vec<Vertex>,
-    commands: Vec<ScheduledCommand>,
-    tiles: Vec<Tile>,
-    tiles_x: u16,
-    tiles_y: u16,
-    stats: RasterizerStatistics,
-    debug_coloring: bool,
-    draw_wireframe: bool,
+// Parameters of turning a distance field sampled from a single-channel texture into coverage.
+// The coverage is a smoothstep of the field's value around the threshold, it replaces the texel and is multiplied by
+// the command's and the vertices' colors as premultiplied alpha.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SdfSampling {
+    // The field's value at the shape's edge, in [0, 1]. Lower values make shapes bolder.
+    // Default: 0.5.
+    pub threshold: f32,
+
+    // The distance in texels between the field's values 0 and 1, as produced by the atlas generator.
+    // Used to keep the edges about a pixel wide at any scale.
+    // Default: 8.0.
+    pub range: f32,
+
+    // Explicit half-width of the smoothstep in the field's units, overrides the one derived from the range if positive.
+    // Larger values give softer edges, e.g. for glows and shadows.
+    // Default: 0.0.
+    pub smoothing: f32,
 }
 
-impl Default for Tile {
+impl Default for SdfSampling {
     fn default() -> Self {
-        Self {
-            triangles: Vec::new(),
-            local_viewport: Viewport::new(0, 0, 1, 1),
-            binning_bounds: TileBinningBounds { xmin_24_8: 0, ymin_24_8: 0, xmax_24_8: 0, ymax_24_8: 0 },
-        }
+        Self { threshold: 0.5, range: 8.0, smoothing: 0.0 }
     }
 }
 
-impl Rasterizer {
-    pub const TILE_WIDTH: usize = 64;
-    pub const TILE_HEIGHT: usize = 64;
+impl SdfSampling {
+    // The half-width of the smoothstep when the texture is sampled with the given level of detail,
+    // i.e. with 2^lod texels per pixel.
+    pub fn smoothing_at_lod(&self, lod: f32) -> f32 {
+        if self.smoothing > 0.0 {
+            self.smoothing
+        } else {
+            0.5 * lod.exp2() / self.range.max(0.001)
+        }
+    }
 
-    pub fn new() -> Self {
-        return Rasterizer {
-            viewport: Viewport::new(0, 0, 1, 1),
-            viewport_scale: ViewportScale::default(),
-            vertices: Vec::new(),
-            commands: Vec::new(),
-            tiles: Vec::new(),
-            tiles_x: 1,
-            tiles_y: 1,
-            stats: RasterizerStatistics::new(),
-            debug_coloring: false,
-            draw_wireframe: false,
-        };
+    // Maps the field's value in [0, 1] into coverage in [0, 1].
+    #[inline(always)]
+    pub fn coverage(&self, value: f32, smoothing: f32) -> f32 {
+        let t: f32 = ((value - self.threshold + smoothing) / (2.0 * smoothing)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
     }
+}
 
-    // Sets up tiling, scaling.
-    // Reset draw commands and statistics.
-    pub fn setup(&mut self, viewport: Viewport) {
-        assert!(viewport.xmax > viewport.xmin);
-        assert!(viewport.ymax > viewport.ymin);
-        let width_px = (viewport.xmax - viewport.xmin) as usize;
-        let height_px = (viewport.ymax - viewport.ymin) as usize;
-        let tiles_x = (width_px + Self::TILE_WIDTH - 1) / Self::TILE_WIDTH;
-        let tiles_y = (height_px + Self::TILE_HEIGHT - 1) / Self::TILE_HEIGHT;
-        let tiles_num = tiles_x * tiles_y;
+// An analytic pattern with cells of 1x1 in the texture coordinates, evaluated per fragment instead of sampling a texture.
+// The pattern is filtered by the screen-space derivatives of the texture coordinates: the edges are anti-aliased and
+// the cells smaller than a pixel fade into the pattern's average color.
+// The color is multiplied by the command's and the vertices' colors, same as a texel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProceduralPattern {
+    // Alternating cells of two colors, `even` is the one of the cell at [0, 1)x[0, 1).
+    Checker { even: Vec4, odd: Vec4 },
+
+    // Lines along the cells' borders over the background, `width` is the lines' width in pixels.
+    Grid { line: Vec4, background: Vec4, width: f32 },
+}
 
-        self.tiles_x = tiles_x as u16;
-        self.tiles_y = tiles_y as u16;
-        self.tiles.resize_with(tiles_num, Tile::default);
-        for y in 0..tiles_y {
-            for x in 0..tiles_x {
-                let tile = &mut self.tiles[y * tiles_x + x];
-                tile.triangles.clear();
-                tile.local_viewport = Viewport {
-                    xmin: viewport.xmin + x as u16 * Self::TILE_WIDTH as u16,
-                    ymin: viewport.ymin + y as u16 * Self::TILE_HEIGHT as u16,
-                    xmax: (viewport.xmin + (x as u16 + 1) * Self::TILE_WIDTH as u16).min(viewport.xmax),
-                    ymax: (viewport.ymin + (y as u16 + 1) * Self::TILE_HEIGHT as u16).min(viewport.ymax),
+impl ProceduralPattern {
+    // Evaluates the pattern at the texture coordinates (u, v), given their changes over a pixel along the screen's axes.
+    #[inline(always)]
+    pub fn evaluate(&self, u: f32, v: f32, uv_dx: Vec2, uv_dy: Vec2) -> RGBA {
+        // The texture coordinates' footprint of the pixel
+        let fw_u: f32 = uv_dx.x.abs().max(uv_dy.x.abs()).max(1e-6);
+        let fw_v: f32 = uv_dx.y.abs().max(uv_dy.y.abs()).max(1e-6);
+        let fract = |x: f32| -> f32 { x - x.floor() };
+        let color: Vec4 = match *self {
+            ProceduralPattern::Checker { even, odd } => {
+                // The integral of the square wave over the footprint, i.e. a box-filtered checker
+                let filtered = |x: f32, w: f32| -> f32 {
+                    let tri = |x: f32| -> f32 { (fract(x * 0.5) - 0.5).abs() };
+                    (2.0 * (tri(x - 0.5 * w) - tri(x + 0.5 * w)) / w).clamp(-1.0, 1.0)
                 };
-                tile.binning_bounds = TileBinningBounds {
-                    xmin_24_8: (x * Self::TILE_WIDTH) as i32 * 256,
-                    ymin_24_8: (y * Self::TILE_HEIGHT) as i32 * 256,
-                    xmax_24_8: (x * Self::TILE_WIDTH + Self::TILE_WIDTH - 1) as i32 * 256 + 255,
-                    ymax_24_8: (y * Self::TILE_HEIGHT + Self::TILE_HEIGHT - 1) as i32 * 256 + 255,
+                let t: f32 = 0.5 - 0.5 * filtered(u, fw_u) * filtered(v, fw_v);
+                even + (odd - even) * t
+            }
+            ProceduralPattern::Grid { line, background, width } => {
+                // Coverage of a line by the pixel along an axis, faded into the average once the cells are only a few
+                // pixels wide
+                let coverage = |x: f32, w: f32| -> f32 {
+                    let distance: f32 = (fract(x + 0.5) - 0.5).abs() / w;
+                    let sharp: f32 = (0.5 * width - distance + 0.5).clamp(0.0, 1.0);
+                    let average: f32 = (width * w).min(1.0);
+                    let fade: f32 = ((w - 0.25) * 4.0).clamp(0.0, 1.0);
+                    sharp + (average - sharp) * fade
                 };
+                let t: f32 = 1.0 - (1.0 - coverage(u, fw_u)) * (1.0 - coverage(v, fw_v));
+                background + (line - background) * t
+            }
+        };
+        RGBA::new(
+            (color.x.clamp(0.0, 1.0) * 255.0 + 0.5) as u8,
+            (color.y.clamp(0.0, 1.0) * 255.0 + 0.5) as u8,
+            (color.z.clamp(0.0, 1.0) * 255.0 + 0.5) as u8,
+            (color.w.clamp(0.0, 1.0) * 255.0 + 0.5) as u8,
+        )
+    }
+}
+
+// Values shared by all the vertices and the fragments of a command, passed to its hooks. Animated effects only need to
+// update these per frame instead of rebuilding the vertex data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Uniforms {
+    // Typically the time in seconds since the start of the animation.
+    pub time: f32,
+
+    // An arbitrary seed, e.g. for a per-object variation of a noise.
+    pub seed: u32,
+
+    // Arbitrary user values.
+    pub user: [Vec4; 4],
+}
+
+impl Default for Uniforms {
+    fn default() -> Self {
+        Self { time: 0.0, seed: 0, user: [Vec4::new(0.0, 0.0, 0.0, 0.0); 4] }
+    }
+}
+
+// A vertex of a command in object space, as seen and modified by the vertex hook.
+// The attributes absent in the command have their default values, and the hook's changes to them are ignored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HookVertex {
+    // The vertex's index in the command's world_positions.
+    pub index: u32,
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub tex_coord: Vec2,
+    pub color: Vec4,
+}
+
+// Modifies a vertex before it's transformed.
+pub type VertexHook = fn(&mut HookVertex, &Uniforms);
+
+// A fragment as seen by the fragment hook.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HookFragment {
+    // The fragment's position in the framebuffer, in pixels.
+    pub x: u16,
+    pub y: u16,
+
+    // The interpolated texture coordinates.
+    pub tex_coord: Vec2,
+
+    // The texel sampled from the texture or the pattern, (1, 1, 1, 1) if there's neither.
+    pub texel: Vec4,
+}
+
+// Computes the fragment's color, which replaces the texel: it's multiplied by the command's and the vertices' colors.
+pub type FragmentHook = fn(&HookFragment, &Uniforms) -> Vec4;
+
+// A fill of the entire viewport that bypasses the geometry processing, e.g. a background, a fade or a composite pass.
+// The depth and normal buffers are not affected.
+#[derive(Debug, Clone)]
+pub struct FullscreenCommand {
+    // The fill color, multiplied by the texture if there's one.
+    // Default: (1, 1, 1, 1).
+    pub color: Vec4,
+
+    // Optional texture stretched over the viewport, with (0, 0) at the top-left corner and (1, 1) at the bottom-right.
+    // Texture coordinates are clamped to the edges.
+    pub texture: Option<std::sync::Arc<Texture>>,
+
+    // Set the filter to be used when sampling the texture.
+    // Default: nearest.
+    pub sampling_filter: SamplerFilter,
+
+    // Sets whether the fill should be alpha-blended with the framebuffer, the same way as RasterizationCommand does.
+    // Default: None.
+    pub alpha_blending: AlphaBlendingMode,
+}
+
+// A texture projected onto the geometry already drawn, e.g. a bullet hole or a blob shadow, without adding any new
+// geometry. The positions of the pixels are reconstructed from the depth buffer, the pixels inside the projector's box
+// are painted with the texture. The pixels without depth, i.e. DEPTH_FAR, are skipped.
+// Requires a depth buffer, the normal buffer is optional. The depth and normal buffers are not affected.
+#[derive(Debug, Clone)]
+pub struct DecalCommand {
+    // The camera the depth buffer was drawn with, i.e. the same as of the commands drawn before the decal.
+    // Default: identity.
+    pub view: Mat44,
+
+    // Default: identity.
+    pub projection: Mat44,
+
+    // Transforms the world space into the projector's space, where the decal's box is [-1, 1] on each axis, e.g. an
+    // orthographic projection multiplied by the projector's view. The texture is mapped onto the box's X and Y with
+    // (0, 0) at the top-left corner. Same as with the camera's projection, Z = -1 is the side nearest to the projector.
+    // Default: identity.
+    pub projector: Mat44,
+
+    // The decal's color, multiplied by the texture if there's one.
+    // Default: (1, 1, 1, 1).
+    pub color: Vec4,
+
+    // Default: None.
+    pub texture: Option<std::sync::Arc<Texture>>,
+
+    // Set the filter to be used when sampling the texture.
+    // Default: bilinear.
+    pub sampling_filter: SamplerFilter,
+
+    // The minimal cosine of the angle between the surface's normal and the direction towards the projector, the pixels
+    // facing away further are not painted. Prevents the decal from smearing over the sides of the geometry it's
+    // projected on. Requires the normal buffer, -1.0 disables the rejection.
+    // Default: 0.0, i.e. only the surfaces facing the projector are painted.
+    pub min_facing: f32,
+
+    // Default: Normal.
+    pub alpha_blending: AlphaBlendingMode,
+}
+
+// A command committed via commit_decal().
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ScheduledDecal {
+    // Maps the normalized device coordinates of the pixels into the projector's space
+    ndc_to_decal: Mat44,
+    // The direction from the surfaces towards the projector in world space, normalized
+    facing: Vec3,
+    min_facing: f32,
+    // The decal's color, not premultiplied
+    color: Vec4,
+    lod: f32,
+}
+
+// The reverse-Z depth of the floating-point depth buffer as an affine function of the fragment's 1/w. It's derived from
+// the projection's z and w rows before the perspective divide, i.e. 0.5 * (w - z) / w, so the depth of the distant
+// fragments keeps the float precision near zero instead of being resolved around the NDC depth of 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ReversedDepth {
+    constant: f32,
+    per_inv_w: f32,
+}
+
+impl ReversedDepth {
+    // None unless the projection's z and w depend on the view-space z alone, as with Mat44::perspective(). The other
+    // projections, e.g. the orthographic ones, keep w = 1 and lose nothing by encoding the NDC depth.
+    fn of(projection: &Mat44) -> Option<ReversedDepth> {
+        let m: &[f32; 16] = &projection.0;
+        if m[8] != 0.0 || m[9] != 0.0 || m[12] != 0.0 || m[13] != 0.0 || m[14] == 0.0 || m[15] != 0.0 {
+            return None;
+        }
+        // z = m[10] * z_view + m[11] and w = m[14] * z_view, the difference is taken before it's scaled by z_view
+        Some(ReversedDepth { constant: 0.5 * (m[14] - m[10]) / m[14], per_inv_w: -0.5 * m[11] })
+    }
+
+    #[inline(always)]
+    fn at(self, inv_w: f32) -> f32 {
+        mul_add(self.per_inv_w, inv_w, self.constant)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ScheduledCommand {
+    texture: Option<std::sync::Arc<Texture>>,
+    normal_map: Option<std::sync::Arc<Texture>>,
+    lightmap: Option<std::sync::Arc<Texture>>,
+    texture_region: Option<TextureRegion>,
+    sampling_filter: SamplerFilter,
+    address_mode_u: SamplerAddressMode,
+    address_mode_v: SamplerAddressMode,
+    alpha_blending: AlphaBlendingMode,
+    alpha_test: u8,
+    alpha_source: AlphaSource,
+    color_interpolation: VerticesColorInterpolationMode,
+    fast_math: bool,
+    // The scissor rectangle clamped to the viewport, if any.
+    scissor: Option<Viewport>,
+    sdf: Option<SdfSampling>,
+    pattern: Option<ProceduralPattern>,
+    uniforms: Uniforms,
+    fragment_hook: Option<FragmentHook>,
+    opacity: OpacityHint,
+    depth_clamp: bool,
+    depth_test: DepthTest,
+    depth_write: bool,
+    depth_bias: DepthBias,
+    // The precise reverse-Z depth for the floating-point depth buffer, None to encode the NDC depth.
+    reversed_depth: Option<ReversedDepth>,
+    color_write_mask: ColorWriteMask,
+    tint: ColorTint,
+    fog: Option<Fog>,
+    reflection: Option<Reflection>,
+    // Maps the world positions of the fragments into the previous frame's clip space, see
+    // RasterizationCommand::previous_model. None if neither the model nor the view-projection changed.
+    motion: Option<Mat44>,
+    // The fill color of a command committed via commit_fullscreen(), premultiplied by alpha if blending is enabled.
+    // Such commands have no vertices and are binned into every tile.
+    fullscreen_color: Option<Vec4>,
+    // The screen-space fill of a command committed via commit_fill(), such commands have no vertices either.
+    fill: Option<FillCommand>,
+    // The convex polygon of a command committed via commit_polygon(), its edges are stored in polygon_edges.
+    polygon: Option<ScheduledPolygon>,
+    // The tessellated stroke of a command committed via commit_stroke(), its shapes are stored in stroke_shapes.
+    stroke: Option<ScheduledStroke>,
+    // The projection of a command committed via commit_decal(), binned into the tiles covered by the decal's box.
+    decal: Option<ScheduledDecal>,
+}
+
+impl ScheduledCommand {
+    // Whether the triangles' sampling or interpolation is set up per triangle from its projected extents: the mip
+    // levels of the textures and the affine fast math.
+    fn has_per_triangle_setup(&self) -> bool {
+        self.texture.is_some() || self.normal_map.is_some() || self.lightmap.is_some() || self.fast_math
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ScheduledPolygon {
+    // the range of the polygon's edges in Rasterizer::polygon_edges
+    edges_start: u32,
+    edges_count: u32,
+    color: Vec4,
+    antialiased: bool,
+    // the bounds of the polygon's points, relative to the viewport
+    min: Vec2,
+    max: Vec2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ScheduledStroke {
+    // the range of the stroke's shapes in Rasterizer::stroke_shapes
+    shapes_start: u32,
+    shapes_count: u32,
+    color: Vec4,
+    antialiased: bool,
+    // the bounds of the stroke's shapes, relative to the viewport
+    min: Vec2,
+    max: Vec2,
+}
+
+// A command kept by the rasterizer with geometry retention enabled, to be scheduled again for each of draw_views().
+#[derive(Debug, Clone)]
+struct RetainedCommand {
+    command: ScheduledCommand,
+    // The command's scissor rectangle as given, it's clamped to each view's viewport separately.
+    scissor: Option<Viewport>,
+    kind: RetainedCommandKind,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum RetainedCommandKind {
+    // Triangles with all attributes already processed and positions in world space, i.e. only the view and the
+    // projection are left to be applied. Those are the range of Rasterizer::retained_vertices.
+    Geometry {
+        vertices_start: usize,
+        vertices_end: usize,
+        culling: CullMode,
+    },
+
+    // A command committed via commit_fullscreen(), binned into every tile.
+    Fullscreen,
+
+    // A screen-space fill, polygon or stroke with its bounds relative to the viewport.
+    ScreenSpace {
+        min: Vec2,
+        max: Vec2,
+    },
+
+    // A decal committed via commit_decal(), projected into each view anew.
+    Decal {
+        projector: Mat44,
+    },
+}
+
+// One of the views to draw the committed geometry into via Rasterizer::draw_views(), e.g. a player's half of a split
+// screen. The view and the projection replace those of the committed commands, while the screen-space commands are
+// placed relative to the view's viewport.
+#[derive(Debug, Clone, Copy)]
+pub struct ViewTarget {
+    // The part of the framebuffer to draw the view into.
+    pub viewport: Viewport,
+    pub view: Mat44,
+    pub projection: Mat44,
+
+    // Filled by draw_views() with the statistics of drawing this view.
+    pub statistics: RasterizerStatistics,
+}
+
+impl ViewTarget {
+    pub fn new(viewport: Viewport, view: Mat44, projection: Mat44) -> Self {
+        Self { viewport, view, projection, statistics: RasterizerStatistics::new() }
+    }
+}
+
+// Geometry of a command processed by Rasterizer::bake() up to and including the binning: its triangles are transformed,
+// clipped, projected onto the viewport and distributed between the tiles. Committing it via Rasterizer::commit_baked()
+// skips all of that work, e.g. for static scenery seen from a still camera. Valid only for the viewport it was baked
+// with.
+#[derive(Debug, Clone)]
+pub struct BakedMesh {
+    viewport: Viewport,
+    committed_triangles: usize,
+    // The scheduled triangles, in viewport space
+    geometry: TriangleList,
+    command: Option<ScheduledCommand>,
+    // The triangles binned into each tile, as their indices in the geometry
+    bins: Vec<Vec<u32>>,
+}
+
+impl BakedMesh {
+    // The viewport the mesh was baked for, the rasterizer must be set up with the same one to commit it.
+    pub fn viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    // The number of triangles left after culling and clipping.
+    pub fn scheduled_triangles(&self) -> usize {
+        self.geometry.triangles_num()
+    }
+}
+
+// A vertex after the per-vertex part of commit(). The vertices of an indexed command are cached by their indices and
+// reused by all the triangles sharing them within the command.
+#[derive(Debug, Clone, Copy, Default)]
+struct TransformedVertex {
+    world_position: Vec3,
+    // in clip space
+    position: Vec4,
+    // rotated by the normal matrix, zero if the command has no normals
+    normal: Vec3,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ScheduledTriangle {
+    // index of a rasterization command
+    cmd: u16,
+
+    // index of the triangle in the scheduled geometry
+    tri: u16,
+}
+
+// The scheduled triangles in viewport space, as a list of vertices and three indices of them per triangle.
+// The identical vertices of a command's triangles, e.g. the shared corners of the fans emitted by the clipper or the
+// vertices of the adjacent triangles of a mesh, are stored once, so the vertices stay compact and the triangles drawn
+// together by a tile refer to the same memory.
+#[derive(Debug, Clone)]
+struct TriangleList {
+    vertices: Vec<Vertex>,
+    // counter-clockwise in viewport space
+    indices: Vec<u32>,
+    // A direct-mapped cache of the recently added vertices, indexed by their hashes. The vertices before command_start
+    // belong to the previous commands and are never reused.
+    recent: [u32; TriangleList::RECENT_VERTICES],
+    command_start: u32,
+}
+
+impl TriangleList {
+    // The number of entries in the cache of the recently added vertices, a power of two.
+    const RECENT_VERTICES: usize = 64;
+
+    fn new() -> Self {
+        Self { vertices: Vec::new(), indices: Vec::new(), recent: [u32::MAX; Self::RECENT_VERTICES], command_start: 0 }
+    }
+
+    fn clear(&mut self) {
+        self.vertices.clear();
+        self.indices.clear();
+        self.command_start = 0;
+    }
+
+    // Starts the triangles of the next command, which don't share vertices with the ones added before.
+    fn start_command(&mut self) {
+        self.command_start = self.vertices.len() as u32;
+    }
+
+    fn triangles_num(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn triangle(&self, index: usize) -> [Vertex; 3] {
+        let indices: &[u32] = &self.indices[index * 3..index * 3 + 3];
+        [self.vertices[indices[0] as usize], self.vertices[indices[1] as usize], self.vertices[indices[2] as usize]]
+    }
+
+    // Appends the triangle, reusing the vertices identical to the recently added ones of the same command.
+    fn push_triangle(&mut self, vertices: &[Vertex; 3]) {
+        for vertex in vertices {
+            let index: u32 = self.push_vertex(vertex);
+            self.indices.push(index);
+        }
+    }
+
+    // Replaces the triangle's vertices with new ones, not shared with any other triangle.
+    fn replace_triangle(&mut self, index: usize, vertices: &[Vertex; 3]) {
+        for (n, vertex) in vertices.iter().enumerate() {
+            self.indices[index * 3 + n] = self.vertices.len() as u32;
+            self.vertices.push(*vertex);
+        }
+    }
+
+    // Appends all triangles of the other list, its vertices are not shared with the ones added before.
+    fn append(&mut self, other: &TriangleList) {
+        let vertices_start: u32 = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices
+            .extend(other.indices.iter().map(|&index| vertices_start + index));
+        self.command_start = self.vertices.len() as u32;
+    }
+
+    fn push_vertex(&mut self, vertex: &Vertex) -> u32 {
+        let position: Vec4 = vertex.position;
+        let hash: u32 =
+            (position.x.to_bits() ^ position.y.to_bits().rotate_left(11) ^ position.z.to_bits().rotate_left(22))
+                .wrapping_mul(0x9E37_79B1);
+        let slot: &mut u32 = &mut self.recent[(hash >> (32 - Self::RECENT_VERTICES.trailing_zeros())) as usize];
+        if *slot >= self.command_start
+            && (*slot as usize) < self.vertices.len()
+            && self.vertices[*slot as usize] == *vertex
+        {
+            return *slot;
+        }
+        *slot = self.vertices.len() as u32;
+        self.vertices.push(*vertex);
+        *slot
+    }
+}
+
+impl Default for TriangleList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TileBinningBounds {
+    xmin_24_8: i32,
+    ymin_24_8: i32,
+    xmax_24_8: i32,
+    ymax_24_8: i32,
+}
+
+impl TileBinningBounds {
+    // The bounds of the tile at the given column and row of the whole tile grid, not only of the viewport's tiles.
+    fn of_tile(tile_x: u32, tile_y: u32) -> Self {
+        let tile_xmin = (tile_x as usize * Rasterizer::TILE_WIDTH) as i32;
+        let tile_ymin = (tile_y as usize * Rasterizer::TILE_HEIGHT) as i32;
+        TileBinningBounds {
+            xmin_24_8: tile_xmin * 256,
+            ymin_24_8: tile_ymin * 256,
+            xmax_24_8: (tile_xmin + Rasterizer::TILE_WIDTH as i32 - 1) * 256 + 255,
+            ymax_24_8: (tile_ymin + Rasterizer::TILE_HEIGHT as i32 - 1) * 256 + 255,
+        }
+    }
+}
+
+struct Tile {
+    triangles: Vec<ScheduledTriangle>,
+    local_viewport: Viewport,
+}
+
+struct TiledJob {
+    framebuffer_tile: FramebufferTile,
+    render_tile: *const Tile,
+    statistics: PerTileStatistics,
+}
+unsafe impl Send for TiledJob {}
+unsafe impl Sync for TiledJob {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RasterizerStatistics {
+    // The number of triangles that were requested to be rasterized.
+    pub committed_triangles: usize,
+
+    // The number of triangles that were scheduled for rasterization after culling and clipping.
+    pub scheduled_triangles: usize,
+
+    // The number of vertices stored for the scheduled triangles, the identical vertices of a command's triangles are
+    // stored once.
+    pub scheduled_vertices: usize,
+
+    // The number of commands skipped as a whole because their bounding boxes were outside the view frustum, their
+    // triangles are still counted as committed.
+    pub culled_commands: usize,
+
+    // The number of triangles passed through the clipper, i.e. the ones exceeding the guard band or crossing the near or
+    // the far plane, or all the triangles if the guard band clipping is disabled.
+    pub clipped_triangles: usize,
+
+    // The number of clipped triangles which were within the guard band, but were clipped against the viewport's sides
+    // anyway, as they're textured or drawn with the affine fast math or the perspective spans, see
+    // Rasterizer::set_guard_band_clipping(). Included in clipped_triangles.
+    pub side_clipped_triangles: usize,
+
+    // The number of triangles discarded after the projection because their area on the screen was zero.
+    pub zero_area_triangles: usize,
+
+    // The number of triangles discarded after the projection because they were too small to be rasterized: either no
+    // pixel center is within their bounds, so they can't cover any pixel, or their area is below the threshold set by
+    // Rasterizer::set_micro_triangle_area_threshold().
+    pub micro_triangles: usize,
+
+    // The number of vertices transformed by commit(), i.e. the unique vertices of the indexed commands and all the
+    // vertices of the non-indexed ones.
+    pub transformed_vertices: usize,
+
+    // The number of triangles' vertices of the indexed commands which reused an already transformed vertex.
+    pub vertex_cache_hits: usize,
+
+    // The number of triangles rasterized across all tiles.
+    // (the same triangle can be rasterized multiple times if it is visible in multiple tiles)
+    pub binned_triangles: usize,
+
+    // The number of factual rasterized pixels.
+    // Gathered only in Debug builds.
+    pub fragments_drawn: usize,
+
+    // The number of binned triangles skipped in their tiles because they were entirely behind the depth buffer's
+    // contents, as found by the hierarchical Z test. Counted per tile, same as binned_triangles.
+    pub hiz_rejected_triangles: usize,
+
+    // The number of tiles drawn as several sub-tiles in separate jobs because they had more binned triangles than the
+    // splitting threshold, see Rasterizer::set_tile_splitting_threshold().
+    pub split_tiles: usize,
+
+    // The number of times the rasterizer allocated memory during the frame: either one of its buffers grew, e.g. the
+    // scheduled vertices, the commands, the tiles' triangles or the draw jobs, or a temporary buffer was created, e.g.
+    // by the parallel assembly of a large command. The buffers are reused between the frames, so once they're warmed
+    // up, the same workload shouldn't allocate anything.
+    // Updated by draw().
+    pub allocations: usize,
+
+    // The number of bytes allocated during the frame, same as counted by allocations.
+    pub allocated_bytes: usize,
+
+    // The memory held by the rasterizer's buffers after the frame, in bytes. The buffers never shrink, so it's the
+    // high-water mark of all the frames drawn so far.
+    pub reserved_bytes: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PerTileStatistics {
+    pub fragments_drawn: usize,
+    pub hiz_rejected_triangles: usize,
+}
+
+// A difference between the single-threaded and the parallel outputs found by Rasterizer::draw_checking_determinism().
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeterminismMismatch {
+    pub component: FramebufferComponent,
+    pub mismatched_pixels: usize,
+    // The first mismatched pixel in the row-major order, in framebuffer coordinates.
+    pub first_x: u16,
+    pub first_y: u16,
+}
+
+// A coarse copy of a framebuffer tile's depth: the farthest depth of each 8x8 block of pixels.
+// A fragment passes the depth test only if it's closer than the stored depth, so a triangle which is not closer than
+// the farthest depth of every block it overlaps can't produce any visible fragments.
+// The blocks are refreshed from the depth buffer after each batch of triangles. Until then they can only be farther
+// than the actual depth, which keeps the rejection conservative.
+struct HiZTile {
+    blocks: [u16; HiZTile::BLOCKS_PER_SIDE * HiZTile::BLOCKS_PER_SIDE],
+}
+
+impl HiZTile {
+    const BLOCK_SIZE: usize = 8;
+    const BLOCKS_PER_SIDE: usize = Rasterizer::TILE_WIDTH / HiZTile::BLOCK_SIZE;
+
+    // The interpolated depth can undershoot the vertices' minimum by a fraction of a unit due to the fixed-point steps.
+    const DEPTH_MARGIN: u32 = 2;
+
+    // Reads the blocks overlapping the rectangle given in the tile's pixels, the ones outside never occlude anything.
+    fn new(depth_buffer: &TiledBufferTileMut<u16, 64, 64>, rect: Viewport) -> Self {
+        let mut hiz = HiZTile { blocks: [u16::MAX; HiZTile::BLOCKS_PER_SIDE * HiZTile::BLOCKS_PER_SIDE] };
+        hiz.update(depth_buffer, rect);
+        hiz
+    }
+
+    // Recalculates the blocks overlapping the rectangle given in the tile's pixels.
+    fn update(&mut self, depth_buffer: &TiledBufferTileMut<u16, 64, 64>, rect: Viewport) {
+        for block_y in rect.ymin as usize / Self::BLOCK_SIZE..=(rect.ymax as usize - 1) / Self::BLOCK_SIZE {
+            for block_x in rect.xmin as usize / Self::BLOCK_SIZE..=(rect.xmax as usize - 1) / Self::BLOCK_SIZE {
+                let mut farthest: u16 = 0;
+                let y_end: usize = (block_y * Self::BLOCK_SIZE + Self::BLOCK_SIZE).min(depth_buffer.height as usize);
+                let x_end: usize = (block_x * Self::BLOCK_SIZE + Self::BLOCK_SIZE).min(depth_buffer.width as usize);
+                for y in block_y * Self::BLOCK_SIZE..y_end {
+                    for x in block_x * Self::BLOCK_SIZE..x_end {
+                        let depth: u16 = unsafe { *depth_buffer.ptr.add(y * Framebuffer::TILE_WITH as usize + x) };
+                        farthest = farthest.max(depth);
+                    }
+                }
+                self.blocks[block_y * Self::BLOCKS_PER_SIDE + block_x] = farthest;
+            }
+        }
+    }
+
+    // Checks whether a triangle with the given minimum depth is hidden in every block overlapping its bounds, which
+    // are given in the tile's pixels.
+    fn occludes(&self, min_depth: u16, bounds: Viewport) -> bool {
+        for block_y in bounds.ymin as usize / Self::BLOCK_SIZE..=(bounds.ymax as usize - 1) / Self::BLOCK_SIZE {
+            for block_x in bounds.xmin as usize / Self::BLOCK_SIZE..=(bounds.xmax as usize - 1) / Self::BLOCK_SIZE {
+                let farthest: u16 = self.blocks[block_y * Self::BLOCKS_PER_SIDE + block_x];
+                if (min_depth as u32) < farthest as u32 + Self::DEPTH_MARGIN {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AlphaBlendingProcessingMode {
+    // The fragments overwrite the color buffer.
+    None = 0,
+
+    // The Normal or PremultipliedNormal blending, in integers for the 8-bit color buffer.
+    Normal = 1,
+
+    // The Additive blending, in integers for the 8-bit color buffer.
+    Additive = 2,
+
+    // Any other blending mode, chosen per fragment and evaluated in floating point.
+    Generic = 3,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DepthProcessingMode {
+    // The 16-bit depth buffer with the Less test and the depth write, or no depth buffer at all.
+    Default = 0,
+
+    // The 16-bit depth buffer with any other test or without the depth write.
+    Configurable = 1,
+
+    // The floating-point reverse-Z depth buffer with any test.
+    Float = 2,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NormalsProcessingMode {
+    // Normals are not interpolated nor written into a normals buffer.
+    // Normals buffer is not available.
+    None = 0,
+
+    // Per-vertex normals are interpolated and written into a normals buffer.
+    // Normals buffer is available.
+    Vertex = 1,
+
+    // Per-vertex normals and tangents are interpolated, a normal map is sampled, multiplied by TBN and written into the normals buffer.
+    // Normals buffer is available.
+    NormalMapping = 2,
+}
+
+pub struct Rasterizer {
+    viewport: Viewport,
+    viewport_scale: ViewportScale,
+    geometry: TriangleList,
+    commands: Vec<ScheduledCommand>,
+    polygon_edges: Vec<Vec3>,
+    stroke_shapes: Vec<StrokeShape>,
+    tiles: Vec<Tile>,
+    tiles_x: u16,
+    tiles_y: u16,
+    // The framebuffer's tile the first (top-left) tile corresponds to, the tiles are aligned with the framebuffer's ones
+    first_tile_x: u16,
+    first_tile_y: u16,
+    stats: RasterizerStatistics,
+    debug_coloring: bool,
+    draw_wireframe: bool,
+    fast_math: bool,
+    fast_math_w_threshold: f32,
+    perspective_span: u32,
+    fast_reciprocal: bool,
+    opaque_fills_fast_path: bool,
+    hierarchical_z: bool,
+    tile_splitting_threshold: usize,
+    micro_triangle_area_threshold: f32,
+    guard_band_clipping: bool,
+    // The extents of the guard band in NDC for the current viewport, see Rasterizer::guard_band()
+    guard_band_extents: Vec2,
+    depth_sorting: bool,
+    multithreading: bool,
+    deterministic_scheduling: bool,
+    retain_geometry: bool,
+    // The frame being recorded since the last setup(), if the recording is enabled
+    recording: Option<RecordedFrame>,
+    retained_vertices: Vec<Vertex>,
+    retained_commands: Vec<RetainedCommand>,
+    // The post-transform vertex cache of the indexed command being committed, indexed by the vertex index.
+    // An entry is valid only if its tag matches the current one, which is bumped for each command.
+    vertex_cache: Vec<TransformedVertex>,
+    vertex_cache_tags: Vec<u32>,
+    vertex_cache_tag: u32,
+    // The vertices of the command being committed transformed in batches before clipping, and their indices.
+    transform_staging: Vec<TransformedVertex>,
+    transform_indices: Vec<u32>,
+    // The vertices of the command being committed as modified by its vertex hook.
+    hooked_positions: Vec<Vec3>,
+    hooked_normals: Vec<Vec3>,
+    hooked_tex_coords: Vec<Vec2>,
+    hooked_colors: Vec<Vec4>,
+    // The vertices of the command being committed as played back from its vertex animation.
+    animated_positions: Vec<Vec3>,
+    animated_normals: Vec<Vec3>,
+    // The vertices of the command being committed as blended by its morph targets.
+    morphed_positions: Vec<Vec3>,
+    morphed_normals: Vec<Vec3>,
+    // The vertices of the command being committed as deformed by its skinning.
+    skinned_positions: Vec<Vec3>,
+    skinned_normals: Vec<Vec3>,
+    // The vertices of the command being committed as displaced by its heightmap.
+    displaced_positions: Vec<Vec3>,
+    displaced_normals: Vec<Vec3>,
+    // The draw jobs and the mask of the split tiles, kept between the frames to reuse their memory
+    jobs: Vec<TiledJob>,
+    split_tiles_mask: Vec<bool>,
+    // The capacities of the buffers and of the tiles' triangles after the last draw(), in bytes
+    buffers_capacity: [usize; Rasterizer::TRACKED_BUFFERS],
+    tiles_capacity: Vec<usize>,
+}
+
+impl Default for Tile {
+    fn default() -> Self {
+        Self { triangles: Vec::new(), local_viewport: Viewport::new(0, 0, 1, 1) }
+    }
+}
+
+impl Rasterizer {
+    pub const TILE_WIDTH: usize = 64;
+    pub const TILE_HEIGHT: usize = 64;
+
+    // The size of the sub-tiles an overloaded tile is split into, a multiple of the hierarchical Z blocks' size.
+    pub const SUB_TILE_WIDTH: usize = 32;
+    pub const SUB_TILE_HEIGHT: usize = 32;
+
+    // The number of binned triangles above which a tile is split into sub-tiles drawn as separate jobs.
+    pub const DEFAULT_TILE_SPLITTING_THRESHOLD: usize = 2048;
+
+    // The maximum relative variation of w across a triangle that still allows skipping the perspective correction
+    // in the fast math mode, i.e. max(w) / min(w) - 1.
+    pub const DEFAULT_FAST_MATH_W_THRESHOLD: f32 = 0.02;
+
+    // The number of triangles of a command assembled and binned by a single task when commit() runs in parallel.
+    // Commands with fewer triangles are assembled and binned on the calling thread.
+    const COMMIT_CHUNK_TRIANGLES: usize = 2048;
+
+    // The number of the buffers listed by buffers_capacity().
+    const TRACKED_BUFFERS: usize = 26;
+
+    // The area in pixels of the smallest triangle the tiles rasterize, smaller ones are skipped as degenerate.
+    const MIN_TRIANGLE_AREA: f32 = 0.5;
+
+    // The largest area in pixels of a triangle's bounds the tiles can rasterize: the edge functions in 24.8 fixed point
+    // reach 512 times the area and must fit into i32. Bounds the guard band, the clipped triangles are always within it.
+    const MAX_TRIANGLE_BOUNDS_AREA: f32 = 4_000_000.0;
+
+    pub fn new() -> Self {
+        return Rasterizer {
+            viewport: Viewport::new(0, 0, 1, 1),
+            viewport_scale: ViewportScale::default(),
+            geometry: TriangleList::new(),
+            commands: Vec::new(),
+            polygon_edges: Vec::new(),
+            stroke_shapes: Vec::new(),
+            tiles: Vec::new(),
+            tiles_x: 1,
+            tiles_y: 1,
+            first_tile_x: 0,
+            first_tile_y: 0,
+            stats: RasterizerStatistics::new(),
+            debug_coloring: false,
+            draw_wireframe: false,
+            fast_math: false,
+            fast_math_w_threshold: Self::DEFAULT_FAST_MATH_W_THRESHOLD,
+            perspective_span: 1,
+            fast_reciprocal: false,
+            opaque_fills_fast_path: true,
+            hierarchical_z: true,
+            tile_splitting_threshold: Self::DEFAULT_TILE_SPLITTING_THRESHOLD,
+            micro_triangle_area_threshold: Self::MIN_TRIANGLE_AREA,
+            guard_band_clipping: true,
+            guard_band_extents: Vec2::new(1.0, 1.0),
+            depth_sorting: false,
+            multithreading: true,
+            deterministic_scheduling: false,
+            retain_geometry: false,
+            recording: None,
+            retained_vertices: Vec::new(),
+            retained_commands: Vec::new(),
+            vertex_cache: Vec::new(),
+            vertex_cache_tags: Vec::new(),
+            vertex_cache_tag: 0,
+            transform_staging: Vec::new(),
+            transform_indices: Vec::new(),
+            hooked_positions: Vec::new(),
+            hooked_normals: Vec::new(),
+            hooked_tex_coords: Vec::new(),
+            hooked_colors: Vec::new(),
+            animated_positions: Vec::new(),
+            animated_normals: Vec::new(),
+            morphed_positions: Vec::new(),
+            morphed_normals: Vec::new(),
+            skinned_positions: Vec::new(),
+            skinned_normals: Vec::new(),
+            displaced_positions: Vec::new(),
+            displaced_normals: Vec::new(),
+            jobs: Vec::new(),
+            split_tiles_mask: Vec::new(),
+            buffers_capacity: [0; Self::TRACKED_BUFFERS],
+            tiles_capacity: Vec::new(),
+        };
+    }
+
+    // A new rasterizer with the same settings as the other one, i.e. the ones changed by the set_*() methods, but
+    // without its committed frame and buffers.
+    pub(crate) fn with_settings_of(other: &Rasterizer) -> Self {
+        Rasterizer {
+            debug_coloring: other.debug_coloring,
+            draw_wireframe: other.draw_wireframe,
+            fast_math: other.fast_math,
+            fast_math_w_threshold: other.fast_math_w_threshold,
+            perspective_span: other.perspective_span,
+            fast_reciprocal: other.fast_reciprocal,
+            opaque_fills_fast_path: other.opaque_fills_fast_path,
+            hierarchical_z: other.hierarchical_z,
+            tile_splitting_threshold: other.tile_splitting_threshold,
+            micro_triangle_area_threshold: other.micro_triangle_area_threshold,
+            guard_band_clipping: other.guard_band_clipping,
+            depth_sorting: other.depth_sorting,
+            multithreading: other.multithreading,
+            deterministic_scheduling: other.deterministic_scheduling,
+            retain_geometry: other.retain_geometry,
+            recording: other.recording.as_ref().map(|_| RecordedFrame::new(Viewport::new(0, 0, 1, 1))),
+            ..Rasterizer::new()
+        }
+    }
+
+    // Sets up tiling, scaling.
+    // Reset draw commands and statistics.
+    pub fn setup(&mut self, viewport: Viewport) {
+        self.setup_tiles(viewport);
+        self.geometry.clear();
+        self.commands.clear();
+        self.polygon_edges.clear();
+        self.stroke_shapes.clear();
+        self.retained_vertices.clear();
+        self.retained_commands.clear();
+        self.stats = RasterizerStatistics::new();
+        if self.recording.is_some() {
+            self.recording = Some(RecordedFrame::new(viewport));
+        }
+    }
+
+    // Sets up the tiles covering the viewport and the viewport scaling, the tiles are left empty.
+    // The tiles are aligned with the framebuffer's ones, so a viewport which doesn't start at a multiple of the tile
+    // size has partial tiles on its left and top sides.
+    // Runs the vertex hook over the command's vertices and commits the modified ones in place of the original.
+    fn commit_hooked(&mut self, command: &RasterizationCommand, vertex_hook: VertexHook) {
+        let mut positions: Vec<Vec3> = std::mem::take(&mut self.hooked_positions);
+        let mut normals: Vec<Vec3> = std::mem::take(&mut self.hooked_normals);
+        let mut tex_coords: Vec<Vec2> = std::mem::take(&mut self.hooked_tex_coords);
+        let mut colors: Vec<Vec4> = std::mem::take(&mut self.hooked_colors);
+        positions.clear();
+        normals.clear();
+        tex_coords.clear();
+        colors.clear();
+        for (index, &position) in command.world_positions.iter().enumerate() {
+            let mut vertex = HookVertex {
+                index: index as u32,
+                position,
+                normal: command.normals.get(index).copied().unwrap_or(Vec3::new(0.0, 0.0, 0.0)),
+                tex_coord: command.tex_coords.get(index).copied().unwrap_or(Vec2::new(0.0, 0.0)),
+                color: command
+                    .colors
+                    .get(index)
+                    .copied()
+                    .unwrap_or(Vec4::new(1.0, 1.0, 1.0, 1.0)),
+            };
+            vertex_hook(&mut vertex, &command.uniforms);
+            positions.push(vertex.position);
+            if !command.normals.is_empty() {
+                normals.push(vertex.normal);
+            }
+            if !command.tex_coords.is_empty() {
+                tex_coords.push(vertex.tex_coord);
+            }
+            if !command.colors.is_empty() {
+                colors.push(vertex.color);
+            }
+        }
+        self.commit(&RasterizationCommand {
+            world_positions: &positions,
+            normals: &normals,
+            tex_coords: &tex_coords,
+            colors: &colors,
+            vertex_hook: None,
+            ..command.clone()
+        });
+        self.hooked_positions = positions;
+        self.hooked_normals = normals;
+        self.hooked_tex_coords = tex_coords;
+        self.hooked_colors = colors;
+    }
+
+    // Samples the vertex animation for the command's vertices and commits them in place of the original ones.
+    fn commit_animated(&mut self, command: &RasterizationCommand, animation: &VertexAnimation) {
+        let mut positions: Vec<Vec3> = std::mem::take(&mut self.animated_positions);
+        let mut normals: Vec<Vec3> = std::mem::take(&mut self.animated_normals);
+        positions.clear();
+        normals.clear();
+        let rows: (u32, u32, f32) = animation.rows();
+        let animates_normals: bool = animation.texture.has_normals();
+        for (index, &position) in command.world_positions.iter().enumerate() {
+            let normal: Vec3 = command.normals.get(index).copied().unwrap_or(Vec3::new(0.0, 0.0, 0.0));
+            let (position, normal): (Vec3, Vec3) = match animation.sample(index, rows) {
+                Some((animated, animated_normal)) => (animated, animated_normal.unwrap_or(normal)),
+                None => (position, normal),
+            };
+            positions.push(position);
+            if animates_normals || !command.normals.is_empty() {
+                normals.push(normal);
+            }
+        }
+        self.commit(&RasterizationCommand {
+            world_positions: &positions,
+            normals: &normals,
+            vertex_animation: None,
+            ..command.clone()
+        });
+        self.animated_positions = positions;
+        self.animated_normals = normals;
+    }
+
+    // Blends the command's vertices by the morph targets and commits them in place of the original ones.
+    fn commit_morphed(&mut self, command: &RasterizationCommand, morph_targets: &MorphTargets) {
+        let mut positions: Vec<Vec3> = std::mem::take(&mut self.morphed_positions);
+        let mut normals: Vec<Vec3> = std::mem::take(&mut self.morphed_normals);
+        positions.clear();
+        normals.clear();
+        positions.extend_from_slice(command.world_positions);
+        normals.extend_from_slice(command.normals);
+        morph_targets.apply(&mut positions, &mut normals);
+        self.commit(&RasterizationCommand {
+            world_positions: &positions,
+            normals: &normals,
+            morph_targets: None,
+            ..command.clone()
+        });
+        self.morphed_positions = positions;
+        self.morphed_normals = normals;
+    }
+
+    // Deforms the command's vertices by the skinning and commits them in place of the original ones.
+    fn commit_skinned(&mut self, command: &RasterizationCommand, skinning: &Skinning) {
+        let mut positions: Vec<Vec3> = std::mem::take(&mut self.skinned_positions);
+        let mut normals: Vec<Vec3> = std::mem::take(&mut self.skinned_normals);
+        positions.clear();
+        normals.clear();
+        for (index, &position) in command.world_positions.iter().enumerate() {
+            let normal: Vec3 = command.normals.get(index).copied().unwrap_or(Vec3::new(0.0, 0.0, 0.0));
+            let (position, normal): (Vec3, Vec3) =
+                skinning.apply(index, position, normal).unwrap_or((position, normal));
+            positions.push(position);
+            if !command.normals.is_empty() {
+                normals.push(normal);
+            }
+        }
+        self.commit(&RasterizationCommand {
+            world_positions: &positions,
+            normals: &normals,
+            skinning: None,
+            ..command.clone()
+        });
+        self.skinned_positions = positions;
+        self.skinned_normals = normals;
+    }
+
+    // Displaces the command's vertices by the heightmap and commits them in place of the original ones.
+    fn commit_displaced(&mut self, command: &RasterizationCommand, displacement: &HeightmapDisplacement) {
+        let mut positions: Vec<Vec3> = std::mem::take(&mut self.displaced_positions);
+        let mut normals: Vec<Vec3> = std::mem::take(&mut self.displaced_normals);
+        positions.clear();
+        normals.clear();
+        let sampler: Sampler = displacement.sampler();
+        for (&position, &tex_coord) in command.world_positions.iter().zip(command.tex_coords) {
+            let height: f32 = displacement.height(&sampler, tex_coord);
+            positions.push(position + Vec3::new(0.0, height, 0.0));
+            if displacement.recompute_normals {
+                normals.push(displacement.normal(&sampler, tex_coord));
+            }
+        }
+        self.commit(&RasterizationCommand {
+            world_positions: &positions,
+            normals: if displacement.recompute_normals {
+                &normals
+            } else {
+                command.normals
+            },
+            displacement: None,
+            ..command.clone()
+        });
+        self.displaced_positions = positions;
+        self.displaced_normals = normals;
+    }
+
+    // Commits the command once per instance, placed by the instance's model matrix and tinted by its color.
+    fn commit_instanced(&mut self, command: &RasterizationCommand) {
+        debug_assert!(
+            command.instance_colors.is_empty() || command.instance_colors.len() == command.instance_models.len(),
+            "the instance colors must be either absent or given for each instance"
+        );
+        for (index, &instance_model) in command.instance_models.iter().enumerate() {
+            let color: Vec4 = match command.instance_colors.get(index) {
+                Some(&instance_color) => command.color * instance_color,
+                None => command.color,
+            };
+            self.commit(&RasterizationCommand {
+                model: command.model * instance_model,
+                previous_model: command.previous_model.map(|previous| previous * instance_model),
+                color,
+                instance_models: &[],
+                instance_colors: &[],
+                ..command.clone()
+            });
+        }
+    }
+
+    fn setup_tiles(&mut self, viewport: Viewport) {
+        assert!(viewport.xmax > viewport.xmin);
+        assert!(viewport.ymax > viewport.ymin);
+        let first_tile_x = viewport.xmin as usize / Self::TILE_WIDTH;
+        let first_tile_y = viewport.ymin as usize / Self::TILE_HEIGHT;
+        let tiles_x = (viewport.xmax as usize - 1) / Self::TILE_WIDTH + 1 - first_tile_x;
+        let tiles_y = (viewport.ymax as usize - 1) / Self::TILE_HEIGHT + 1 - first_tile_y;
+        let tiles_num = tiles_x * tiles_y;
+
+        self.tiles_x = tiles_x as u16;
+        self.tiles_y = tiles_y as u16;
+        self.first_tile_x = first_tile_x as u16;
+        self.first_tile_y = first_tile_y as u16;
+        self.tiles.resize_with(tiles_num, Tile::default);
+        for y in 0..tiles_y {
+            for x in 0..tiles_x {
+                let tile = &mut self.tiles[y * tiles_x + x];
+                let tile_xmin = (first_tile_x + x) * Self::TILE_WIDTH;
+                let tile_ymin = (first_tile_y + y) * Self::TILE_HEIGHT;
+                tile.triangles.clear();
+                tile.local_viewport = Viewport {
+                    xmin: viewport.xmin.max(tile_xmin as u16),
+                    ymin: viewport.ymin.max(tile_ymin as u16),
+                    xmax: viewport.xmax.min((tile_xmin + Self::TILE_WIDTH) as u16),
+                    ymax: viewport.ymax.min((tile_ymin + Self::TILE_HEIGHT) as u16),
+                };
+            }
+        }
+
+        self.viewport = viewport;
+        self.viewport_scale = ViewportScale::new(viewport);
+        self.guard_band_extents = Self::guard_band_extents(viewport);
+    }
+
+    // Reset draw commands and statistics.
+    pub fn reset(&mut self) {
+        for tile in &mut self.tiles {
+            tile.triangles.clear();
+        }
+        self.geometry.clear();
+        self.commands.clear();
+        self.polygon_edges.clear();
+        self.stroke_shapes.clear();
+        self.retained_vertices.clear();
+        self.retained_commands.clear();
+        self.stats = RasterizerStatistics::new();
+    }
+
+    pub fn commit(&mut self, command: &RasterizationCommand) {
+        if let Some(animation) = &command.vertex_animation {
+            self.commit_animated(command, animation);
+            return;
+        }
+        if let Some(morph_targets) = &command.morph_targets
+            && morph_targets.is_active()
+        {
+            self.commit_morphed(command, morph_targets);
+            return;
+        }
+        if let Some(skinning) = &command.skinning {
+            self.commit_skinned(command, skinning);
+            return;
+        }
+        if let Some(displacement) = &command.displacement
+            && command.tex_coords.len() >= command.world_positions.len()
+        {
+            self.commit_displaced(command, displacement);
+            return;
+        }
+        if let Some(vertex_hook) = command.vertex_hook {
+            self.commit_hooked(command, vertex_hook);
+            return;
+        }
+        if !command.instance_models.is_empty() {
+            self.commit_instanced(command);
+            return;
+        }
+        if let Some(recording) = self.recording.as_mut() {
+            recording.record(command);
+        }
+
+        let use_explicit_indices = !command.indices.is_empty();
+        let input_triangles_num = if use_explicit_indices {
+            command.indices.len() / 3
+        } else {
+            command.world_positions.len() / 3
+        };
+
+        if input_triangles_num == 0 {
+            return;
+        }
+
+        self.stats.committed_triangles += input_triangles_num;
+
+        // Nothing to draw if the scissor rectangle is entirely outside the viewport, unless the geometry is retained
+        // for the other views
+        let scissor: Option<Viewport> = command.scissor.and_then(|scissor| scissor.intersection(&self.viewport));
+        let scissored_out: bool = command.scissor.is_some() && scissor.is_none();
+        if scissored_out && !self.retain_geometry {
+            return;
+        }
+        let retained_vertices_start = self.retained_vertices.len();
+
+        let view_projection = command.projection * command.view;
+        if let Some(aabb) = command.aabb.as_ref()
+            && !self.retain_geometry
+            && is_outside_frustum(aabb, &(view_projection * command.model.as_mat44()), command.depth_clamp)
+        {
+            self.stats.culled_commands += 1;
+            return;
+        }
+        let normal_matrix = command.model.as_mat33().inverse().transpose();
+        let viewport_scale = self.viewport_scale;
+        self.geometry.start_command();
+        let scheduled_triangles_start: usize = self.geometry.triangles_num();
+        let scheduled_vertices_start: usize = self.geometry.vertices.len();
+
+        // Command color - uniformly applied to all committed triangles, conditionally premultiplied by alpha if alpha_blending is enabled.
+        // Its alpha is replaced first if the alpha source ignores it.
+        let command_alpha: f32 = command.alpha_source.vertex_alpha().unwrap_or(command.color.w);
+        let command_color: Vec4 = if !command.alpha_blending.premultiplies_colors() {
+            Vec4::new(command.color.x, command.color.y, command.color.z, command_alpha)
+        } else {
+            Vec4::new(
+                command.color.x * command_alpha,
+                command.color.y * command_alpha,
+                command.color.z * command_alpha,
+                command_alpha,
+            )
+        };
+        // If the command color is (1, 1, 1, 1) - it can be safely ignored.
+        let is_command_color_defined: bool = (command_color.x - 1.0).abs() > 0.005
+            || (command_color.y - 1.0).abs() > 0.005
+            || (command_color.z - 1.0).abs() > 0.005
+            || (command_color.w - 1.0).abs() > 0.005;
+
+        // Transform the vertices up front, 4 at a time.
+        // An indexed command transforms each of its unique vertices once into the vertex cache, which starts over empty
+        // as the cached vertices are only valid within the command.
+        self.transform_staging.clear();
+        if use_explicit_indices {
+            self.vertex_cache_tag = self.vertex_cache_tag.wrapping_add(1);
+            if self.vertex_cache_tag == 0 {
+                self.vertex_cache_tags.fill(0);
+                self.vertex_cache_tag = 1;
+            }
+            if self.vertex_cache.len() < command.world_positions.len() {
+                self.vertex_cache
+                    .resize(command.world_positions.len(), TransformedVertex::default());
+                self.vertex_cache_tags.resize(command.world_positions.len(), 0);
+            }
+            self.transform_indices.clear();
+            for &vertex_index in &command.indices[..input_triangles_num * 3] {
+                if self.vertex_cache_tags[vertex_index as usize] != self.vertex_cache_tag {
+                    self.vertex_cache_tags[vertex_index as usize] = self.vertex_cache_tag;
+                    self.transform_indices.push(vertex_index);
+                }
+            }
+            let indices: &[u32] = &self.transform_indices;
+            transform_vertices(
+                &command.model,
+                &view_projection,
+                &normal_matrix,
+                command.world_positions,
+                command.normals,
+                indices.len(),
+                |n| indices[n] as usize,
+                &mut self.transform_staging,
+            );
+            for (&vertex_index, vertex) in self.transform_indices.iter().zip(self.transform_staging.iter()) {
+                self.vertex_cache[vertex_index as usize] = *vertex;
+            }
+            self.stats.vertex_cache_hits += input_triangles_num * 3 - self.transform_indices.len();
+        } else {
+            transform_vertices(
+                &command.model,
+                &view_projection,
+                &normal_matrix,
+                command.world_positions,
+                command.normals,
+                input_triangles_num * 3,
+                |n| n,
+                &mut self.transform_staging,
+            );
+        }
+        self.stats.transformed_vertices += self.transform_staging.len();
+
+        // When debug triangle coloring is enabled, textures are disabled.
+        let command_texture = if self.debug_coloring {
+            None
+        } else {
+            command.texture.clone()
+        };
+
+        // The command to schedule the triangles with, the color interpolation is known once they're assembled
+        let mut required_scheduled_command = ScheduledCommand {
+            texture: command_texture,
+            normal_map: command.normal_map.clone(),
+            lightmap: if self.debug_coloring {
+                None
+            } else {
+                command.lightmap.clone()
+            },
+            texture_region: command.texture_region,
+            sampling_filter: command.sampling_filter,
+            address_mode_u: command.address_mode_u,
+            address_mode_v: command.address_mode_v,
+            alpha_blending: command.alpha_blending,
+            alpha_test: command.alpha_test,
+            alpha_source: command.alpha_source,
+            color_interpolation: VerticesColorInterpolationMode::None,
+            fast_math: command.fast_math || self.fast_math,
+            scissor,
+            sdf: command.sdf,
+            pattern: if self.debug_coloring { None } else { command.pattern },
+            uniforms: command.uniforms,
+            fragment_hook: if self.debug_coloring {
+                None
+            } else {
+                command.fragment_hook
+            },
+            opacity: command.opacity,
+            depth_clamp: command.depth_clamp,
+            depth_test: command.depth_test,
+            depth_write: command.depth_write,
+            depth_bias: command.depth_bias,
+            reversed_depth: ReversedDepth::of(&command.projection),
+            color_write_mask: command.color_write_mask,
+            tint: command.tint,
+            fog: command.fog,
+            reflection: command.reflection.clone(),
+            motion: if command.previous_model.is_some() || command.previous_view_projection.is_some() {
+                let previous_model: Mat34 = command.previous_model.unwrap_or(command.model);
+                let previous_view_projection: Mat44 = command.previous_view_projection.unwrap_or(view_projection);
+                Some(previous_view_projection * previous_model.as_mat44() * command.model.as_mat44().inverse())
+            } else {
+                None
+            },
+            fullscreen_color: None,
+            fill: None,
+            polygon: None,
+            stroke: None,
+            decal: None,
+        };
+
+        // Assemble the triangles from the transformed vertices, clip them and project onto the viewport.
+        // Large commands are split into chunks assembled in parallel, each into its own triangle list and vertex buffer,
+        // which are then concatenated in the submission order, so the binning is the same as if the triangles were
+        // assembled one by one.
+        let assembly = TriangleAssembly {
+            command,
+            transformed: if use_explicit_indices {
+                &self.vertex_cache
+            } else {
+                &self.transform_staging
+            },
+            command_color,
+            is_command_color_defined,
+            viewport_scale,
+            scissored_out,
+            retain_geometry: self.retain_geometry,
+            micro_triangle_area_threshold: self.micro_triangle_area_threshold,
+            guard_band: self.guard_band(&required_scheduled_command),
+            eye: if command.lighting.is_some() {
+                (command.view.inverse() * Vec4::new(0.0, 0.0, 0.0, 1.0)).xyz()
+            } else {
+                Vec3::new(0.0, 0.0, 0.0)
+            },
+        };
+        // The deterministic scheduling assembles large commands in chunks even on a single thread, so that the
+        // statistics don't depend on whether the multithreading is enabled
+        let chunked: bool = (self.multithreading || self.deterministic_scheduling)
+            && input_triangles_num > Self::COMMIT_CHUNK_TRIANGLES;
+        let (colors, counters): (AssembledColors, SchedulingCounters) = if chunked {
+            use rayon::prelude::*;
+            let assemble_chunk = |&start: &usize| {
+                let end: usize = (start + Self::COMMIT_CHUNK_TRIANGLES).min(input_triangles_num);
+                let mut geometry = TriangleList::new();
+                let mut retained_vertices: Vec<Vertex> = Vec::new();
+                let (colors, counters) = assembly.assemble(start..end, &mut geometry, &mut retained_vertices);
+                (geometry, retained_vertices, colors, counters)
+            };
+            let starts: Vec<usize> = (0..input_triangles_num).step_by(Self::COMMIT_CHUNK_TRIANGLES).collect();
+            // Sized up front, as rayon's collect() may reserve more than the single-threaded one
+            let mut chunks: Vec<(TriangleList, Vec<Vertex>, AssembledColors, SchedulingCounters)> =
+                Vec::with_capacity(starts.len());
+            if self.multithreading {
+                chunks.par_extend(starts.par_iter().map(assemble_chunk));
+            } else {
+                chunks.extend(starts.iter().map(assemble_chunk));
+            }
+            let mut colors = AssembledColors::default();
+            let mut counters = SchedulingCounters::default();
+            // The chunks are assembled into the temporary buffers, allocated anew for each command
+            self.stats.allocations += 2;
+            self.stats.allocated_bytes += input_triangles_num.div_ceil(Self::COMMIT_CHUNK_TRIANGLES)
+                * std::mem::size_of::<usize>()
+                + vec_bytes(&chunks);
+            for (geometry, retained_vertices, chunk_colors, chunk_counters) in chunks {
+                for bytes in
+                    [vec_bytes(&geometry.vertices), vec_bytes(&geometry.indices), vec_bytes(&retained_vertices)]
+                {
+                    if bytes > 0 {
+                        self.stats.allocations += 1;
+                        self.stats.allocated_bytes += bytes;
+                    }
+                }
+                self.geometry.append(&geometry);
+                self.retained_vertices.extend_from_slice(&retained_vertices);
+                colors = colors.merge(chunk_colors);
+                counters = counters.merge(chunk_counters);
+            }
+            (colors, counters)
+        } else {
+            assembly.assemble(0..input_triangles_num, &mut self.geometry, &mut self.retained_vertices)
+        };
+        required_scheduled_command.color_interpolation = colors.color_interpolation_mode();
+        counters.add_to(&mut self.stats);
+
+        self.stats.scheduled_triangles += self.geometry.triangles_num() - scheduled_triangles_start;
+        self.stats.scheduled_vertices += self.geometry.vertices.len() - scheduled_vertices_start;
+
+        // When debug triangle coloring is enabled, color the triangles using their indices.
+        // Each triangle gets its own vertices, as the shared ones can't have the colors of all their triangles.
+        if self.debug_coloring {
+            for tri_idx in scheduled_triangles_start..self.geometry.triangles_num() {
+                let color = debug_color((tri_idx * 3) as u32);
+                let mut vertices: [Vertex; 3] = self.geometry.triangle(tri_idx);
+                vertices[0].color = color;
+                vertices[1].color = color;
+                vertices[2].color = color;
+                self.geometry.replace_triangle(tri_idx, &vertices);
+            }
+        }
+
+        if self.retain_geometry {
+            self.retained_commands.push(RetainedCommand {
+                command: required_scheduled_command.clone(),
+                scissor: command.scissor,
+                kind: RetainedCommandKind::Geometry {
+                    vertices_start: retained_vertices_start,
+                    vertices_end: self.retained_vertices.len(),
+                    culling: command.culling,
+                },
+            });
+        }
+
+        if scheduled_triangles_start == self.geometry.triangles_num() {
+            return;
+        }
+        // Reuse the last command or create a new one
+        if self.commands.is_empty() || self.commands.last().unwrap() != &required_scheduled_command {
+            self.commands.push(required_scheduled_command);
+        }
+        self.bin_triangles(scheduled_triangles_start, scissor);
+    }
+
+    // Clips the triangle given in the clip space, projects it onto the viewport and schedules the visible parts.
+    // Clips the triangle, projects the pieces onto the viewport and appends the ones which can be rasterized to the
+    // output. With the guard band given, the triangles within it are only clipped against the near and the far planes,
+    // if at all, and are rasterized partially outside the viewport.
+    fn schedule_triangle(
+        input_vertices: &[Vertex; 3],
+        viewport_scale: ViewportScale,
+        culling: CullMode,
+        micro_triangle_area_threshold: f32,
+        guard_band: Option<GuardBand>,
+        depth_clamp: bool,
+        output: &mut TriangleList,
+    ) -> SchedulingCounters {
+        let mut counters = SchedulingCounters::default();
+
+        let clipped_vertices: ArrayVec<Vertex, 7> = match guard_band {
+            Some(band) => {
+                let positions: [Vec4; 3] =
+                    [input_vertices[0].position, input_vertices[1].position, input_vertices[2].position];
+                match guard_band_test(&positions, band.extents, depth_clamp) {
+                    GuardBandTest::Outside => return counters,
+                    GuardBandTest::Inside | GuardBandTest::CrossesDepthRange
+                        if band.clip_sides
+                            && guard_band_test(&positions, Vec2::new(1.0, 1.0), depth_clamp)
+                                == GuardBandTest::ExceedsGuardBand =>
+                    {
+                        counters.clipped += 1;
+                        counters.side_clipped += 1;
+                        clip_triangle_with(input_vertices, depth_clamp)
+                    }
+                    GuardBandTest::Inside => ArrayVec::from_iter(input_vertices.iter().copied()),
+                    GuardBandTest::CrossesDepthRange => {
+                        counters.clipped += 1;
+                        clip_triangle_depth_range(input_vertices, depth_clamp)
+                    }
+                    GuardBandTest::ExceedsGuardBand => {
+                        counters.clipped += 1;
+                        clip_triangle_with(input_vertices, depth_clamp)
+                    }
+                }
+            }
+            None => {
+                counters.clipped += 1;
+                clip_triangle_with(input_vertices, depth_clamp)
+            }
+        };
+        if clipped_vertices.is_empty() {
+            return counters;
+        }
+
+        for clipped_vertex_idx in 1..clipped_vertices.len() - 1 {
+            let mut vertices = [
+                clipped_vertices[0],                      //
+                clipped_vertices[clipped_vertex_idx],     //
+                clipped_vertices[clipped_vertex_idx + 1], //
+            ];
+
+            vertices[0].position = perspective_divide(vertices[0].position);
+            vertices[1].position = perspective_divide(vertices[1].position);
+            vertices[2].position = perspective_divide(vertices[2].position);
+            vertices[0].position = viewport_scale.apply(vertices[0].position);
+            vertices[1].position = viewport_scale.apply(vertices[1].position);
+            vertices[2].position = viewport_scale.apply(vertices[2].position);
+
+            let v01 = vertices[1].position.xy() - vertices[0].position.xy();
+            let v02 = vertices[2].position.xy() - vertices[0].position.xy();
+            let area_x_2: f32 = Mat22([v01.x, v02.x, v01.y, v02.y]).det();
+            if area_x_2 == 0.0 {
+                counters.zero_area += 1;
+                continue;
+            }
+            let ccw = area_x_2 < 0.0;
+
+            if (culling == CullMode::CW && !ccw) || (culling == CullMode::CCW && ccw) {
+                continue;
+            }
+
+            if Self::is_micro_triangle(&vertices, area_x_2.abs() * 0.5, micro_triangle_area_threshold) {
+                counters.micro += 1;
+                continue;
+            }
+
+            if ccw {
+                vertices.swap(2, 1);
+            }
+
+            output.push_triangle(&vertices);
+        }
+        counters
+    }
+
+    // Checks whether the projected triangle is too small to be worth rasterizing: no pixel center is within its bounds,
+    // or its area is below the threshold.
+    #[inline(always)]
+    fn is_micro_triangle(vertices: &[Vertex; 3], area: f32, area_threshold: f32) -> bool {
+        if area < area_threshold {
+            return true;
+        }
+        // The vertices are snapped to 24.8 fixed point when rasterized, the bounds are widened by that precision to
+        // never discard a triangle which touches a pixel center after the snapping.
+        const SNAP: f32 = 1.0 / 256.0;
+        let (p0, p1, p2) = (vertices[0].position, vertices[1].position, vertices[2].position);
+        let xmin: f32 = p0.x.min(p1.x).min(p2.x) - 0.5 - SNAP;
+        let xmax: f32 = p0.x.max(p1.x).max(p2.x) - 0.5 + SNAP;
+        let ymin: f32 = p0.y.min(p1.y).min(p2.y) - 0.5 - SNAP;
+        let ymax: f32 = p0.y.max(p1.y).max(p2.y) - 0.5 + SNAP;
+        xmin.ceil() > xmax.floor() || ymin.ceil() > ymax.floor()
+    }
+
+    // Bins the scheduled triangles starting from the given one into the tiles they overlap, for the last command.
+    // Bins the scheduled triangles starting at triangles_start into the tiles they may overlap, under the last command.
+    // Large batches are split into chunks binned in parallel, each into its own bins, which are then appended to the
+    // tiles in the submission order, so the tiles' triangles are the same as if they were binned one by one.
+    fn bin_triangles(&mut self, triangles_start: usize, scissor: Option<Viewport>) {
+        let binning = TriangleBinning {
+            geometry: &self.geometry,
+            command: (self.commands.len() - 1) as u16,
+            scissor,
+            first_tile_x: self.first_tile_x as u32,
+            first_tile_y: self.first_tile_y as u32,
+            tiles_x: self.tiles_x as u32,
+            tiles_y: self.tiles_y as u32,
+        };
+        let triangles_end: usize = self.geometry.triangles_num();
+        // Same as the assembly, the deterministic scheduling bins large batches in chunks even on a single thread
+        let chunked: bool = (self.multithreading || self.deterministic_scheduling)
+            && triangles_end - triangles_start > Self::COMMIT_CHUNK_TRIANGLES;
+        if !chunked {
+            let tiles: &mut Vec<Tile> = &mut self.tiles;
+            self.stats.binned_triangles +=
+                binning.bin(triangles_start..triangles_end, |tile, triangle| tiles[tile].triangles.push(triangle));
+            return;
+        }
+
+        use rayon::prelude::*;
+        let tiles_num: usize = self.tiles.len();
+        let bin_chunk = |&start: &usize| {
+            let end: usize = (start + Self::COMMIT_CHUNK_TRIANGLES).min(triangles_end);
+            let mut bins: Vec<Vec<ScheduledTriangle>> = vec![Vec::new(); tiles_num];
+            let binned: usize = binning.bin(start..end, |tile, triangle| bins[tile].push(triangle));
+            (bins, binned)
+        };
+        let starts: Vec<usize> = (triangles_start..triangles_end)
+            .step_by(Self::COMMIT_CHUNK_TRIANGLES)
+            .collect();
+        // Sized up front, as rayon's collect() may reserve more than the single-threaded one
+        let mut chunks: Vec<(Vec<Vec<ScheduledTriangle>>, usize)> = Vec::with_capacity(starts.len());
+        if self.multithreading {
+            chunks.par_extend(starts.par_iter().map(bin_chunk));
+        } else {
+            chunks.extend(starts.iter().map(bin_chunk));
+        }
+
+        // The chunks are binned into the temporary buffers, allocated anew for each batch
+        self.stats.allocations += 2 + chunks.len();
+        self.stats.allocated_bytes += vec_bytes(&starts) + vec_bytes(&chunks);
+        for (bins, binned) in &chunks {
+            self.stats.allocated_bytes += vec_bytes(bins);
+            for bin in bins.iter().filter(|bin| bin.capacity() > 0) {
+                self.stats.allocations += 1;
+                self.stats.allocated_bytes += vec_bytes(bin);
+            }
+            self.stats.binned_triangles += binned;
+        }
+
+        let merge = |(idx, tile): (usize, &mut Tile)| {
+            for (bins, _) in &chunks {
+                tile.triangles.extend_from_slice(&bins[idx]);
+            }
+        };
+        if self.multithreading {
+            self.tiles.par_iter_mut().enumerate().for_each(merge);
+        } else {
+            self.tiles.iter_mut().enumerate().for_each(merge);
+        }
+    }
+
+    // Processes the command the same way commit() does, but keeps the result in a BakedMesh instead of scheduling it for
+    // the next draw(). The rasterizer's committed commands and statistics are left intact.
+    // The geometry is baked for the current viewport and isn't retained for draw_views().
+    pub fn bake(&mut self, command: &RasterizationCommand) -> BakedMesh {
+        let geometry: TriangleList = std::mem::take(&mut self.geometry);
+        let commands: Vec<ScheduledCommand> = std::mem::take(&mut self.commands);
+        let triangles: Vec<Vec<ScheduledTriangle>> = self
+            .tiles
+            .iter_mut()
+            .map(|tile| std::mem::take(&mut tile.triangles))
+            .collect();
+        let stats: RasterizerStatistics = self.stats;
+        let retain_geometry: bool = self.retain_geometry;
+        self.retain_geometry = false;
+
+        self.commit(command);
+        let baked = BakedMesh {
+            viewport: self.viewport,
+            committed_triangles: self.stats.committed_triangles - stats.committed_triangles,
+            geometry: std::mem::replace(&mut self.geometry, geometry),
+            command: std::mem::replace(&mut self.commands, commands).pop(),
+            bins: self
+                .tiles
+                .iter_mut()
+                .zip(triangles)
+                .map(|(tile, triangles)| {
+                    let baked: Vec<ScheduledTriangle> = std::mem::replace(&mut tile.triangles, triangles);
+                    baked.iter().map(|triangle| triangle.tri as u32).collect()
+                })
+                .collect(),
+        };
+
+        self.stats = stats;
+        self.retain_geometry = retain_geometry;
+        baked
+    }
+
+    // Commits each section of the mesh with the material at the section's material index, the sections without one are
+    // drawn with the default material. A mesh without sections is committed as a whole with the first material.
+    // The consecutive sections with the same material are scheduled as a single command.
+    pub fn commit_mesh_with_materials(&mut self, mesh: &MeshData, materials: &[Material], transforms: &Transforms) {
+        let default_material: Material = Material::default();
+        let material = |index: usize| -> &Material { materials.get(index).unwrap_or(&default_material) };
+        let mut commit = |material: &Material, command: RasterizationCommand| {
+            self.commit(&material.apply(RasterizationCommand {
+                model: transforms.model,
+                view: transforms.view,
+                projection: transforms.projection,
+                ..command
+            }));
+        };
+        if mesh.sections.is_empty() {
+            commit(material(0), mesh.command());
+            return;
+        }
+        for section in &mesh.sections {
+            commit(material(section.material_index), mesh.section_command(section));
+        }
+    }
+
+    // Schedules the baked geometry for the next draw(), the same way as committing its command again would, but without
+    // transforming, clipping and binning the triangles.
+    // The transform delta, if any, is applied to the baked vertices in the viewport space, i.e. to the x and y in pixels
+    // and to the z being the depth in [-1, 1], e.g. to scroll the geometry by a few pixels. The moved triangles are
+    // binned again, but they are not clipped, so they should stay within the viewport.
+    // The rasterizer must be set up with the viewport the mesh was baked for.
+    pub fn commit_baked(&mut self, baked: &BakedMesh, transform_delta: Option<Mat34>) {
+        if let Some(recording) = self.recording.as_mut() {
+            recording.skipped_commands += 1;
+        }
+        assert!(
+            baked.viewport == self.viewport,
+            "the mesh was baked for the viewport {:?}, the rasterizer is set up for {:?}",
+            baked.viewport,
+            self.viewport
+        );
+        self.stats.committed_triangles += baked.committed_triangles;
+        let Some(command) = baked.command.as_ref() else {
+            return;
+        };
+        // The delta moving the depth isn't reflected by the vertices' 1/w, such meshes fall back to the NDC depth
+        let moved_command: ScheduledCommand;
+        let command: &ScheduledCommand = match transform_delta {
+            Some(transform) if transform.0[8..12] != [0.0, 0.0, 1.0, 0.0] && command.reversed_depth.is_some() => {
+                moved_command = ScheduledCommand { reversed_depth: None, ..command.clone() };
+                &moved_command
+            }
+            _ => command,
+        };
+        let triangles_start: usize = self.geometry.triangles_num();
+        let vertices_start: usize = self.geometry.vertices.len();
+        self.stats.scheduled_triangles += baked.scheduled_triangles();
+        self.stats.scheduled_vertices += baked.geometry.vertices.len();
+        if self.commands.is_empty() || self.commands.last().unwrap() != command {
+            self.commands.push(command.clone());
+        }
+        self.geometry.append(&baked.geometry);
+
+        match transform_delta {
+            None => {
+                let scheduled_command_index = (self.commands.len() - 1) as u16;
+                for (tile, bin) in self.tiles.iter_mut().zip(baked.bins.iter()) {
+                    tile.triangles.extend(bin.iter().map(|&tri| ScheduledTriangle {
+                        cmd: scheduled_command_index,
+                        tri: (triangles_start + tri as usize) as u16,
+                    }));
+                    self.stats.binned_triangles += bin.len();
+                }
+            }
+            Some(transform) => {
+                for vertex in &mut self.geometry.vertices[vertices_start..] {
+                    let position: Vec3 = transform * vertex.position.xyz();
+                    vertex.position = Vec4::new(position.x, position.y, position.z, vertex.position.w);
+                }
+                // Mirroring flips the winding, which must stay counter-clockwise in the viewport space
+                let flip: bool = transform.0[0] * transform.0[5] - transform.0[1] * transform.0[4] < 0.0;
+                if flip {
+                    for triangle in self.geometry.indices[triangles_start * 3..].chunks_exact_mut(3) {
+                        triangle.swap(1, 2);
+                    }
+                }
+                self.bin_triangles(triangles_start, command.scissor);
+            }
+        }
+    }
+
+    // Fills the entire viewport with a color or a texture, without rasterizing any triangles.
+    // The fill is ordered with the other commands as usual, i.e. it's drawn over everything committed before it.
+    pub fn commit_fullscreen(&mut self, command: &FullscreenCommand) {
+        if let Some(recording) = self.recording.as_mut() {
+            recording.record_fullscreen(command);
+        }
+        let color: Vec4 = if !command.alpha_blending.premultiplies_colors() {
+            command.color
+        } else {
+            Vec4::new(
+                command.color.x * command.color.w,
+                command.color.y * command.color.w,
+                command.color.z * command.color.w,
+                command.color.w,
+            )
+        };
+        let required_scheduled_command = ScheduledCommand {
+            texture: if self.debug_coloring {
+                None
+            } else {
+                command.texture.clone()
+            },
+            sampling_filter: command.sampling_filter,
+            address_mode_u: SamplerAddressMode::ClampToEdge,
+            address_mode_v: SamplerAddressMode::ClampToEdge,
+            alpha_blending: command.alpha_blending,
+            color_interpolation: VerticesColorInterpolationMode::Fixed,
+            fullscreen_color: Some(color),
+            ..Default::default()
+        };
+        self.retain(&required_scheduled_command, RetainedCommandKind::Fullscreen);
+        if self.commands.is_empty() || self.commands.last().unwrap() != &required_scheduled_command {
+            self.commands.push(required_scheduled_command);
+        }
+        let scheduled_command_index = (self.commands.len() - 1) as u16;
+        for tile in &mut self.tiles {
+            tile.triangles
+                .push(ScheduledTriangle { cmd: scheduled_command_index, tri: 0 });
+        }
+    }
+
+    // Draws a screen-space fill: a rectangle with optionally rounded corners and a border, painted with a color or a
+    // gradient. The fill is ordered with the other commands as usual.
+    pub fn commit_fill(&mut self, command: &FillCommand) {
+        if let Some(recording) = self.recording.as_mut() {
+            recording.record_fill(command);
+        }
+        if command.max.x <= command.min.x || command.max.y <= command.min.y {
+            return;
+        }
+        let required_scheduled_command = ScheduledCommand {
+            alpha_blending: command.alpha_blending,
+            color_interpolation: VerticesColorInterpolationMode::Fixed,
+            fill: Some(*command),
+            ..Default::default()
+        };
+        self.retain(
+            &required_scheduled_command,
+            RetainedCommandKind::ScreenSpace { min: command.min, max: command.max },
+        );
+        if self.commands.is_empty() || self.commands.last().unwrap() != &required_scheduled_command {
+            self.commands.push(required_scheduled_command);
+        }
+        self.bin_screen_space_command(command.min, command.max);
+    }
+
+    // Draws a screen-space convex polygon, optionally with anti-aliased edges, without having to triangulate it.
+    // The fill is ordered with the other commands as usual.
+    pub fn commit_polygon(&mut self, command: &PolygonFillCommand) {
+        if let Some(recording) = self.recording.as_mut() {
+            recording.skipped_commands += 1;
+        }
+        let edges_start: usize = self.polygon_edges.len();
+        let edges_count: usize = command.append_edges(&mut self.polygon_edges);
+        if edges_count == 0 {
+            return;
+        }
+        let (min, max): (Vec2, Vec2) = command.bounds();
+        let required_scheduled_command = ScheduledCommand {
+            alpha_blending: command.alpha_blending,
+            color_interpolation: VerticesColorInterpolationMode::Fixed,
+            polygon: Some(ScheduledPolygon {
+                edges_start: edges_start as u32,
+                edges_count: edges_count as u32,
+                color: command.color,
+                antialiased: command.antialiased,
+                min,
+                max,
+            }),
+            ..Default::default()
+        };
+        self.retain(&required_scheduled_command, RetainedCommandKind::ScreenSpace { min, max });
+        self.commands.push(required_scheduled_command);
+        self.bin_screen_space_command(min, max);
+    }
+
+    // Draws a screen-space stroke of a path with the given width, joins and caps.
+    // The stroke is ordered with the other commands as usual.
+    pub fn commit_stroke(&mut self, command: &StrokeCommand) {
+        if let Some(recording) = self.recording.as_mut() {
+            recording.skipped_commands += 1;
+        }
+        let shapes_start: usize = self.stroke_shapes.len();
+        let shapes_count: usize = command.append_shapes(&mut self.stroke_shapes, &mut self.polygon_edges);
+        if shapes_count == 0 {
+            return;
+        }
+        let mut min: Vec2 = Vec2::new(f32::MAX, f32::MAX);
+        let mut max: Vec2 = Vec2::new(f32::MIN, f32::MIN);
+        for shape in &self.stroke_shapes[shapes_start..] {
+            let (shape_min, shape_max): (Vec2, Vec2) = shape.bounds();
+            min = Vec2::new(min.x.min(shape_min.x), min.y.min(shape_min.y));
+            max = Vec2::new(max.x.max(shape_max.x), max.y.max(shape_max.y));
+        }
+        let required_scheduled_command = ScheduledCommand {
+            alpha_blending: command.alpha_blending,
+            color_interpolation: VerticesColorInterpolationMode::Fixed,
+            stroke: Some(ScheduledStroke {
+                shapes_start: shapes_start as u32,
+                shapes_count: shapes_count as u32,
+                color: command.color,
+                antialiased: command.antialiased,
+                min,
+                max,
+            }),
+            ..Default::default()
+        };
+        self.retain(&required_scheduled_command, RetainedCommandKind::ScreenSpace { min, max });
+        self.commands.push(required_scheduled_command);
+        self.bin_screen_space_command(min, max);
+    }
+
+    // Projects a decal onto the geometry drawn before it, reading the depth and the normal buffers, see DecalCommand.
+    // The decal is ordered with the other commands as usual.
+    pub fn commit_decal(&mut self, command: &DecalCommand) {
+        if let Some(recording) = self.recording.as_mut() {
+            recording.skipped_commands += 1;
+        }
+        let texture: Option<std::sync::Arc<Texture>> = if self.debug_coloring {
+            None
+        } else {
+            command.texture.clone()
+        };
+        let mut decal = ScheduledDecal {
+            ndc_to_decal: Mat44::identity(),
+            facing: Vec3::new(0.0, 0.0, 0.0),
+            min_facing: command.min_facing,
+            color: command.color,
+            lod: 0.0,
+        };
+        let bounds: Option<(Vec2, Vec2)> = self.project_decal(
+            &command.projector,
+            &(command.projection * command.view),
+            texture.as_deref(),
+            &mut decal,
+        );
+        let required_scheduled_command = ScheduledCommand {
+            texture,
+            sampling_filter: command.sampling_filter,
+            address_mode_u: SamplerAddressMode::ClampToEdge,
+            address_mode_v: SamplerAddressMode::ClampToEdge,
+            alpha_blending: command.alpha_blending,
+            color_interpolation: VerticesColorInterpolationMode::Fixed,
+            decal: Some(decal),
+            ..Default::default()
+        };
+        self.retain(&required_scheduled_command, RetainedCommandKind::Decal { projector: command.projector });
+        self.commands.push(required_scheduled_command);
+        self.bin_decal(bounds);
+    }
+
+    // Sets up the decal's mapping of the pixels of the viewport seen with the view and the projection into its box.
+    // Returns the box's bounds relative to the viewport if it's entirely in front of the camera.
+    fn project_decal(
+        &self,
+        projector: &Mat44,
+        view_projection: &Mat44,
+        texture: Option<&Texture>,
+        decal: &mut ScheduledDecal,
+    ) -> Option<(Vec2, Vec2)> {
+        let width: f32 = (self.viewport.xmax - self.viewport.xmin) as f32;
+        let height: f32 = (self.viewport.ymax - self.viewport.ymin) as f32;
+        let decal_to_world: Mat44 = projector.inverse();
+        let unproject = |x: f32, y: f32, z: f32| -> Vec3 {
+            let position: Vec4 = decal_to_world * Vec4::new(x, y, z, 1.0);
+            position.xyz() / position.w
+        };
+
+        let mut bounds: Option<(Vec2, Vec2)> = Some((Vec2::new(f32::MAX, f32::MAX), Vec2::new(f32::MIN, f32::MIN)));
+        for corner in 0..8 {
+            let x: f32 = if corner & 1 == 0 { -1.0 } else { 1.0 };
+            let y: f32 = if corner & 2 == 0 { -1.0 } else { 1.0 };
+            let z: f32 = if corner & 4 == 0 { -1.0 } else { 1.0 };
+            let clip: Vec4 = *view_projection * unproject(x, y, z).as_point4();
+            if clip.w <= 0.0 {
+                // The box crosses the camera's plane, its projection is unbounded
+                bounds = None;
+                break;
+            }
+            let screen: Vec2 = Vec2::new((clip.x / clip.w * 0.5 + 0.5) * width, (0.5 - clip.y / clip.w * 0.5) * height);
+            bounds = bounds.map(|(min, max)| {
+                (
+                    Vec2::new(min.x.min(screen.x), min.y.min(screen.y)),
+                    Vec2::new(max.x.max(screen.x), max.y.max(screen.y)),
+                )
+            });
+        }
+
+        // The texture is stretched over the box's projection, which gives its LOD
+        decal.lod = match (texture, bounds) {
+            (Some(texture), Some((min, max))) => {
+                let area: f32 = ((max.x - min.x) * (max.y - min.y)).max(1.0);
+                0.5 * (texture.mips[0].width as f32 * texture.mips[0].height as f32 / area).log2()
+            }
+            _ => 0.0,
+        };
+        decal.ndc_to_decal = *projector * view_projection.inverse();
+        decal.facing = (unproject(0.0, 0.0, -1.0) - unproject(0.0, 0.0, 1.0)).normalized();
+        bounds
+    }
+
+    // Bins the last command, a decal, into the tiles overlapping its bounds or into every tile if it's unbounded.
+    fn bin_decal(&mut self, bounds: Option<(Vec2, Vec2)>) {
+        match bounds {
+            Some((min, max)) => self.bin_screen_space_command(min, max),
+            None => {
+                let scheduled_command_index = (self.commands.len() - 1) as u16;
+                for tile in &mut self.tiles {
+                    tile.triangles
+                        .push(ScheduledTriangle { cmd: scheduled_command_index, tri: 0 });
+                }
+            }
+        }
+    }
+
+    // Draws a screen-space nine-patch UI panel as two triangles per cell, see NinePatchCommand.
+    // The panel is placed at the middle of the depth range and is depth-tested as usual if there's a depth buffer.
+    pub fn commit_nine_patch(&mut self, command: &NinePatchCommand) {
+        if command.max.x <= command.min.x || command.max.y <= command.min.y {
+            return;
+        }
+        let mut positions: Vec<Vec2> = Vec::with_capacity(NinePatchCommand::VERTICES_NUM);
+        let mut tex_coords: Vec<Vec2> = Vec::with_capacity(NinePatchCommand::VERTICES_NUM);
+        command.append_geometry(&mut positions, &mut tex_coords);
+        let world_positions: Vec<Vec3> = positions.iter().map(|p| Vec3::new(p.x, p.y, 0.0)).collect();
+        let width: f32 = (self.viewport.xmax - self.viewport.xmin) as f32;
+        let height: f32 = (self.viewport.ymax - self.viewport.ymin) as f32;
+        self.commit(&RasterizationCommand {
+            world_positions: &world_positions,
+            tex_coords: &tex_coords,
+            projection: Mat44::orthographic(0.0, width, height, 0.0, -1.0, 1.0),
+            color: command.color,
+            texture: command.texture.clone(),
+            texture_region: command.region.map(|region| TextureRegion { clamp: true, ..region }),
+            sampling_filter: command.sampling_filter,
+            address_mode_u: SamplerAddressMode::ClampToEdge,
+            address_mode_v: SamplerAddressMode::ClampToEdge,
+            alpha_blending: command.alpha_blending,
+            ..Default::default()
+        });
+    }
+
+    // Draws camera-facing quads around the given centers as regular triangles, see BillboardCommand.
+    pub fn commit_billboards(&mut self, command: &BillboardCommand) {
+        if command.centers.is_empty() {
+            return;
+        }
+        let vertices_num: usize = command.centers.len() * BillboardCommand::VERTICES_PER_BILLBOARD;
+        let mut positions: Vec<Vec3> = Vec::with_capacity(vertices_num);
+        let mut normals: Vec<Vec3> = Vec::with_capacity(vertices_num);
+        let mut tex_coords: Vec<Vec2> = Vec::with_capacity(vertices_num);
+        let mut colors: Vec<Vec4> = Vec::with_capacity(vertices_num);
+        command.append_geometry(&mut positions, &mut normals, &mut tex_coords, &mut colors);
+        self.commit(&RasterizationCommand {
+            world_positions: &positions,
+            normals: &normals,
+            tex_coords: &tex_coords,
+            colors: &colors,
+            view: command.view,
+            projection: command.projection,
+            culling: CullMode::None,
+            texture: command.texture.clone(),
+            texture_region: command.region.map(|region| TextureRegion { clamp: true, ..region }),
+            sampling_filter: command.sampling_filter,
+            address_mode_u: SamplerAddressMode::ClampToEdge,
+            address_mode_v: SamplerAddressMode::ClampToEdge,
+            alpha_blending: command.alpha_blending,
+            alpha_test: command.alpha_test,
+            depth_write: command.depth_write,
+            ..Default::default()
+        });
+    }
+
+    fn retain(&mut self, command: &ScheduledCommand, kind: RetainedCommandKind) {
+        if self.retain_geometry {
+            self.retained_commands
+                .push(RetainedCommand { command: command.clone(), scissor: None, kind });
+        }
+    }
+
+    // Bins the last command into the tiles overlapping its bounds relative to the viewport, with an extra pixel for
+    // the anti-aliased edges.
+    fn bin_screen_space_command(&mut self, min: Vec2, max: Vec2) {
+        let scheduled_command_index = (self.commands.len() - 1) as u16;
+        let xmin: f32 = self.viewport.xmin as f32 + min.x - 1.0;
+        let ymin: f32 = self.viewport.ymin as f32 + min.y - 1.0;
+        let xmax: f32 = self.viewport.xmin as f32 + max.x + 1.0;
+        let ymax: f32 = self.viewport.ymin as f32 + max.y + 1.0;
+        for tile in &mut self.tiles {
+            let viewport: Viewport = tile.local_viewport;
+            if xmax <= viewport.xmin as f32
+                || ymax <= viewport.ymin as f32
+                || xmin >= viewport.xmax as f32
+                || ymin >= viewport.ymax as f32
+            {
+                continue;
+            }
+            tile.triangles
+                .push(ScheduledTriangle { cmd: scheduled_command_index, tri: 0 });
+        }
+    }
+
+    pub fn draw(&mut self, framebuffer: &mut Framebuffer) {
+        assert!(
+            framebuffer.depth_buffer.is_none() || framebuffer.depth_buffer_f32.is_none(),
+            "only one of the depth buffers can be present"
+        );
+        if self.commands.is_empty() {
+            return;
+        }
+
+        if self.depth_sorting {
+            use rayon::prelude::*;
+            let (geometry, commands) = (&self.geometry, &self.commands);
+            if self.multithreading {
+                self.tiles
+                    .par_iter_mut()
+                    .for_each(|tile| Self::sort_tile_triangles(&mut tile.triangles, geometry, commands));
+            } else {
+                for tile in &mut self.tiles {
+                    Self::sort_tile_triangles(&mut tile.triangles, geometry, commands);
+                }
+            }
+        }
+
+        let overloaded: bool = self
+            .tiles
+            .iter()
+            .any(|tile| tile.triangles.len() > self.tile_splitting_threshold);
+        if self.tiles_x > 1 || self.tiles_y > 1 || overloaded {
+            // The overloaded tiles are replaced by their sub-tiles, which are drawn as separate jobs
+            let sub_tiles: Vec<(usize, Tile)> = if overloaded {
+                self.split_overloaded_tiles()
+            } else {
+                Vec::new()
+            };
+            if !sub_tiles.is_empty() {
+                self.stats.allocations += 1 + sub_tiles.len();
+                self.stats.allocated_bytes += vec_bytes(&sub_tiles)
+                    + sub_tiles
+                        .iter()
+                        .map(|(_, sub_tile)| vec_bytes(&sub_tile.triangles))
+                        .sum::<usize>();
+            }
+            let mut split: Vec<bool> = std::mem::take(&mut self.split_tiles_mask);
+            split.clear();
+            split.resize(self.tiles.len(), false);
+            for (idx, _) in &sub_tiles {
+                split[*idx] = true;
+            }
+            self.stats.split_tiles += split.iter().filter(|&&split| split).count();
+
+            // Draw tiles in parallel using rayon, or one by one in the row-major order if multithreading is disabled
+            let mut jobs: Vec<TiledJob> = std::mem::take(&mut self.jobs);
+            for y in 0..self.tiles_y {
+                for x in 0..self.tiles_x {
+                    let idx = (y * self.tiles_x + x) as usize;
+                    if !self.tiles[idx].triangles.is_empty() && !split[idx] {
+                        let render_tile: *const Tile = &mut self.tiles[idx];
+                        let framebuffer_tile = framebuffer.tile(self.first_tile_x + x, self.first_tile_y + y);
+                        jobs.push(TiledJob { framebuffer_tile, render_tile, statistics: PerTileStatistics::default() });
+                    }
+                }
+            }
+            for (idx, sub_tile) in &sub_tiles {
+                let x: u16 = (*idx % self.tiles_x as usize) as u16;
+                let y: u16 = (*idx / self.tiles_x as usize) as u16;
+                let render_tile: *const Tile = sub_tile;
+                let framebuffer_tile = framebuffer.tile(self.first_tile_x + x, self.first_tile_y + y);
+                jobs.push(TiledJob { framebuffer_tile, render_tile, statistics: PerTileStatistics::default() });
+            }
+            if self.deterministic_scheduling {
+                // Keep the row-major order of the tiles, and of the sub-tiles within each tile, for both the execution
+                // and the reduction of the per-tile statistics below
+                jobs.sort_by_key(|job| {
+                    let viewport: Viewport = unsafe { job.render_tile.as_ref().unwrap_unchecked() }.local_viewport;
+                    (job.framebuffer_tile.origin_y(), job.framebuffer_tile.origin_x(), viewport.ymin, viewport.xmin)
+                });
+            } else {
+                // Order the tiles with the most triangles first
+                jobs.sort_unstable_by(|job1, job2| {
+                    let tile1_triangles_len = unsafe { job1.render_tile.as_ref().unwrap_unchecked() }.triangles.len();
+                    let tile2_triangles_len = unsafe { job2.render_tile.as_ref().unwrap_unchecked() }.triangles.len();
+                    tile2_triangles_len.cmp(&tile1_triangles_len) // NB! This is the reverse order, because we want the most triangles first
+                });
+            }
+            if self.multithreading {
+                use rayon::prelude::*;
+                jobs.par_iter_mut().for_each(|job| {
+                    self.draw_tile(job);
+                });
+            } else {
+                if !self.deterministic_scheduling {
+                    jobs.sort_unstable_by_key(|job| (job.framebuffer_tile.origin_y(), job.framebuffer_tile.origin_x()));
+                }
+                for job in &mut jobs {
+                    self.draw_tile(job);
+                }
+            }
+            for job in &jobs {
+                self.stats.fragments_drawn += job.statistics.fragments_drawn;
+                self.stats.hiz_rejected_triangles += job.statistics.hiz_rejected_triangles;
+            }
+            jobs.clear();
+            self.jobs = jobs;
+            self.split_tiles_mask = split;
+        } else {
+            // Draw the single tile directly, don't bother with multithreading
+            let render_tile: *const Tile = &mut self.tiles[0];
+            let framebuffer_tile = framebuffer.tile(self.first_tile_x, self.first_tile_y);
+            let mut job = TiledJob { framebuffer_tile, render_tile, statistics: PerTileStatistics::default() };
+            self.draw_tile(&mut job);
+            self.stats.fragments_drawn += job.statistics.fragments_drawn;
+            self.stats.hiz_rejected_triangles += job.statistics.hiz_rejected_triangles;
+        }
+
+        if self.draw_wireframe {
+            self.draw_wireframe(framebuffer);
+        }
+        self.track_allocations();
+    }
+
+    // The capacities of the rasterizer's buffers in bytes, except for the tiles' triangles.
+    fn buffers_capacity(&self) -> [usize; Self::TRACKED_BUFFERS] {
+        [
+            vec_bytes(&self.geometry.vertices),
+            vec_bytes(&self.geometry.indices),
+            vec_bytes(&self.commands),
+            vec_bytes(&self.polygon_edges),
+            vec_bytes(&self.stroke_shapes),
+            vec_bytes(&self.tiles),
+            vec_bytes(&self.retained_vertices),
+            vec_bytes(&self.retained_commands),
+            vec_bytes(&self.vertex_cache),
+            vec_bytes(&self.vertex_cache_tags),
+            vec_bytes(&self.transform_staging),
+            vec_bytes(&self.transform_indices),
+            vec_bytes(&self.hooked_positions),
+            vec_bytes(&self.hooked_normals),
+            vec_bytes(&self.hooked_tex_coords),
+            vec_bytes(&self.hooked_colors),
+            vec_bytes(&self.animated_positions),
+            vec_bytes(&self.animated_normals),
+            vec_bytes(&self.morphed_positions),
+            vec_bytes(&self.morphed_normals),
+            vec_bytes(&self.skinned_positions),
+            vec_bytes(&self.skinned_normals),
+            vec_bytes(&self.displaced_positions),
+            vec_bytes(&self.displaced_normals),
+            vec_bytes(&self.jobs),
+            vec_bytes(&self.split_tiles_mask),
+        ]
+    }
+
+    // Counts the growth of the buffers since the last draw() as allocations and remembers their current capacities.
+    fn track_allocations(&mut self) {
+        let capacity: [usize; Self::TRACKED_BUFFERS] = self.buffers_capacity();
+        self.tiles_capacity.resize(self.tiles.len(), 0);
+        let buffers = capacity.iter().copied().zip(self.buffers_capacity.iter_mut());
+        let tiles = self
+            .tiles
+            .iter()
+            .map(|tile| vec_bytes(&tile.triangles))
+            .zip(self.tiles_capacity.iter_mut());
+        let mut reserved_bytes: usize = 0;
+        for (now, before) in buffers.chain(tiles) {
+            if now > *before {
+                self.stats.allocations += 1;
+                self.stats.allocated_bytes += now - *before;
+            }
+            *before = now;
+            reserved_bytes += now;
+        }
+        self.stats.reserved_bytes = reserved_bytes;
+    }
+
+    // Remembers the current capacities of the buffers without counting their growth.
+    fn reset_allocations_tracking(&mut self) {
+        self.buffers_capacity = self.buffers_capacity();
+        self.tiles_capacity.clear();
+        self.tiles_capacity
+            .extend(self.tiles.iter().map(|tile| vec_bytes(&tile.triangles)));
+    }
+
+    // Draws the committed geometry twice: single-threaded into a copy of the framebuffer's buffers and then in parallel
+    // into the framebuffer itself, and compares the results bit by bit. Returns the mismatches per buffer, an empty
+    // vector means the outputs are identical. Meant for tests guarding against races and order dependencies between
+    // the tiles, costs a copy of the buffers and more than twice the time of draw().
+    // The statistics are accumulated only once, as if draw() was called.
+    pub fn draw_checking_determinism(&mut self, framebuffer: &mut Framebuffer) -> Vec<DeterminismMismatch> {
+        let mut color_buffer: Option<TiledBuffer<u32, 64, 64>> = framebuffer.color_buffer.as_deref().cloned();
+        let mut depth_buffer: Option<TiledBuffer<u16, 64, 64>> = framebuffer.depth_buffer.as_deref().cloned();
+        let mut depth_buffer_f32: Option<TiledBuffer<f32, 64, 64>> = framebuffer.depth_buffer_f32.as_deref().cloned();
+        let mut normal_buffer: Option<TiledBuffer<u32, 64, 64>> = framebuffer.normal_buffer.as_deref().cloned();
+        let mut motion_buffer: Option<TiledBuffer<u32, 64, 64>> = framebuffer.motion_buffer.as_deref().cloned();
+
+        let multithreading: bool = self.multithreading;
+        let stats: RasterizerStatistics = self.stats;
+        self.multithreading = false;
+        self.draw(&mut Framebuffer {
+            color_buffer: color_buffer.as_mut(),
+            color_format: framebuffer.color_format,
+            depth_buffer: depth_buffer.as_mut(),
+            depth_buffer_f32: depth_buffer_f32.as_mut(),
+            normal_buffer: normal_buffer.as_mut(),
+            motion_buffer: motion_buffer.as_mut(),
+        });
+        self.stats = stats;
+        self.multithreading = true;
+        self.draw(framebuffer);
+        self.multithreading = multithreading;
+
+        let mut mismatches: Vec<DeterminismMismatch> = Vec::new();
+        if let (Some(expected), Some(actual)) = (color_buffer.as_ref(), framebuffer.color_buffer.as_deref()) {
+            mismatches.extend(Self::compare_buffers(FramebufferComponent::Color, expected, actual));
+        }
+        if let (Some(expected), Some(actual)) = (depth_buffer.as_ref(), framebuffer.depth_buffer.as_deref()) {
+            mismatches.extend(Self::compare_buffers(FramebufferComponent::Depth, expected, actual));
+        }
+        if let (Some(expected), Some(actual)) = (depth_buffer_f32.as_ref(), framebuffer.depth_buffer_f32.as_deref()) {
+            mismatches.extend(Self::compare_buffers(FramebufferComponent::Depth, expected, actual));
+        }
+        if let (Some(expected), Some(actual)) = (normal_buffer.as_ref(), framebuffer.normal_buffer.as_deref()) {
+            mismatches.extend(Self::compare_buffers(FramebufferComponent::Normals, expected, actual));
+        }
+        if let (Some(expected), Some(actual)) = (motion_buffer.as_ref(), framebuffer.motion_buffer.as_deref()) {
+            mismatches.extend(Self::compare_buffers(FramebufferComponent::Motion, expected, actual));
+        }
+        mismatches
+    }
+
+    fn compare_buffers<T: bytemuck::Pod + Default + PartialEq>(
+        component: FramebufferComponent,
+        expected: &TiledBuffer<T, 64, 64>,
+        actual: &TiledBuffer<T, 64, 64>,
+    ) -> Option<DeterminismMismatch> {
+        let mut mismatch: Option<DeterminismMismatch> = None;
+        for y in 0..expected.height() {
+            for x in 0..expected.width() {
+                if expected.at(x, y) == actual.at(x, y) {
+                    continue;
+                }
+                match mismatch.as_mut() {
+                    Some(mismatch) => mismatch.mismatched_pixels += 1,
+                    None => {
+                        mismatch = Some(DeterminismMismatch { component, mismatched_pixels: 1, first_x: x, first_y: y })
+                    }
+                }
+            }
+        }
+        mismatch
+    }
+
+    // Draws the committed geometry into each of the views, e.g. for a split screen, without committing it again.
+    // The world-space geometry retained by commit() is projected with each view's view and projection matrices, the
+    // rest of the per-vertex processing is done once. Screen-space commands are drawn relative to each view's viewport.
+    // Requires the geometry retention to be enabled before committing, see set_retain_geometry().
+    // The commands scheduled for draw() are kept intact. The views have no previous view-projection, their fragments write
+    // zero motion into the motion buffer.
+    pub fn draw_views(&mut self, framebuffer: &mut Framebuffer, views: &mut [ViewTarget]) {
+        assert!(self.retain_geometry, "draw_views() requires set_retain_geometry(true) before committing");
+        let viewport: Viewport = self.viewport;
+        let geometry: TriangleList = std::mem::take(&mut self.geometry);
+        let commands: Vec<ScheduledCommand> = std::mem::take(&mut self.commands);
+        let tiles: Vec<Tile> = std::mem::take(&mut self.tiles);
+        let stats: RasterizerStatistics = self.stats;
+        let retained_commands: Vec<RetainedCommand> = std::mem::take(&mut self.retained_commands);
+
+        for view in views.iter_mut() {
+            self.setup_tiles(view.viewport);
+            self.geometry.clear();
+            self.commands.clear();
+            self.stats = RasterizerStatistics::new();
+            let view_projection: Mat44 = view.projection * view.view;
+            for retained in &retained_commands {
+                let mut command: ScheduledCommand = retained.command.clone();
+                // The motion was set up for the command's own view-projection, the views' fragments don't move
+                command.motion = None;
+                command.reversed_depth = ReversedDepth::of(&view.projection);
+                command.scissor = match retained.scissor {
+                    Some(scissor) => match scissor.intersection(&view.viewport) {
+                        Some(scissor) => Some(scissor),
+                        None => continue,
+                    },
+                    None => None,
+                };
+                match retained.kind {
+                    RetainedCommandKind::Geometry { vertices_start, vertices_end, culling } => {
+                        self.stats.committed_triangles += (vertices_end - vertices_start) / 3;
+                        self.geometry.start_command();
+                        let scheduled_triangles_start: usize = self.geometry.triangles_num();
+                        let scheduled_vertices_start: usize = self.geometry.vertices.len();
+                        for tri_start in (vertices_start..vertices_end).step_by(3) {
+                            let mut input_vertices: [Vertex; 3] = [
+                                self.retained_vertices[tri_start],
+                                self.retained_vertices[tri_start + 1],
+                                self.retained_vertices[tri_start + 2],
+                            ];
+                            input_vertices[0].position = view_projection * input_vertices[0].position;
+                            input_vertices[1].position = view_projection * input_vertices[1].position;
+                            input_vertices[2].position = view_projection * input_vertices[2].position;
+                            let counters: SchedulingCounters = Self::schedule_triangle(
+                                &input_vertices,
+                                self.viewport_scale,
+                                culling,
+                                self.micro_triangle_area_threshold,
+                                self.guard_band(&command),
+                                command.depth_clamp,
+                                &mut self.geometry,
+                            );
+                            counters.add_to(&mut self.stats);
+                        }
+                        if scheduled_triangles_start == self.geometry.triangles_num() {
+                            continue;
+                        }
+                        self.stats.scheduled_triangles += self.geometry.triangles_num() - scheduled_triangles_start;
+                        self.stats.scheduled_vertices += self.geometry.vertices.len() - scheduled_vertices_start;
+                        let scissor: Option<Viewport> = command.scissor;
+                        if self.commands.last() != Some(&command) {
+                            self.commands.push(command);
+                        }
+                        self.bin_triangles(scheduled_triangles_start, scissor);
+                    }
+                    RetainedCommandKind::Fullscreen => {
+                        if self.commands.last() != Some(&command) {
+                            self.commands.push(command);
+                        }
+                        let scheduled_command_index = (self.commands.len() - 1) as u16;
+                        for tile in &mut self.tiles {
+                            tile.triangles
+                                .push(ScheduledTriangle { cmd: scheduled_command_index, tri: 0 });
+                        }
+                    }
+                    RetainedCommandKind::ScreenSpace { min, max } => {
+                        if self.commands.last() != Some(&command) {
+                            self.commands.push(command);
+                        }
+                        self.bin_screen_space_command(min, max);
+                    }
+                    RetainedCommandKind::Decal { projector } => {
+                        let mut decal: ScheduledDecal = command.decal.unwrap();
+                        let bounds: Option<(Vec2, Vec2)> =
+                            self.project_decal(&projector, &view_projection, command.texture.as_deref(), &mut decal);
+                        command.decal = Some(decal);
+                        self.commands.push(command);
+                        self.bin_decal(bounds);
+                    }
+                }
             }
+            self.draw(framebuffer);
+            view.statistics = self.stats;
         }
 
-        self.viewport = viewport;
-        self.viewport_scale = ViewportScale::new(viewport);
-        self.vertices.clear();
-        self.commands.clear();
-        self.stats = RasterizerStatistics::new();
+        self.retained_commands = retained_commands;
+        self.setup_tiles(viewport);
+        self.tiles = tiles;
+        self.geometry = geometry;
+        self.commands = commands;
+        self.stats = stats;
+        self.reset_allocations_tracking();
     }
 
-    // Reset draw commands and statistics.
-    pub fn reset(&mut self) {
-        for tile in &mut self.tiles {
-            tile.triangles.clear();
+    // Splits the tiles with more binned triangles than the threshold into the sub-tiles, each with the triangles which
+    // may overlap it. Returns the sub-tiles along with the indices of their tiles.
+    fn split_overloaded_tiles(&self) -> Vec<(usize, Tile)> {
+        let split = |(idx, tile): (usize, &Tile)| -> Vec<(usize, Tile)> {
+            if tile.triangles.len() <= self.tile_splitting_threshold {
+                return Vec::new();
+            }
+            let tile_xmin: usize = (self.first_tile_x as usize + idx % self.tiles_x as usize) * Self::TILE_WIDTH;
+            let tile_ymin: usize = (self.first_tile_y as usize + idx / self.tiles_x as usize) * Self::TILE_HEIGHT;
+            let mut sub_tiles: Vec<(usize, Tile)> = Vec::new();
+            for sub_ymin in (tile_ymin..tile_ymin + Self::TILE_HEIGHT).step_by(Self::SUB_TILE_HEIGHT) {
+                for sub_xmin in (tile_xmin..tile_xmin + Self::TILE_WIDTH).step_by(Self::SUB_TILE_WIDTH) {
+                    let sub_viewport = Viewport::new(
+                        sub_xmin as u16,
+                        sub_ymin as u16,
+                        (sub_xmin + Self::SUB_TILE_WIDTH) as u16,
+                        (sub_ymin + Self::SUB_TILE_HEIGHT) as u16,
+                    );
+                    let Some(local_viewport) = tile.local_viewport.intersection(&sub_viewport) else {
+                        continue;
+                    };
+                    let triangles: Vec<ScheduledTriangle> = tile
+                        .triangles
+                        .iter()
+                        .filter(|tri| self.may_overlap(tri, local_viewport))
+                        .copied()
+                        .collect();
+                    if !triangles.is_empty() {
+                        sub_tiles.push((idx, Tile { triangles, local_viewport }));
+                    }
+                }
+            }
+            sub_tiles
+        };
+        let per_tile: Vec<Vec<(usize, Tile)>> = if self.multithreading {
+            use rayon::prelude::*;
+            self.tiles.par_iter().enumerate().map(split).collect()
+        } else {
+            self.tiles.iter().enumerate().map(split).collect()
+        };
+        // Moved into an exactly sized vector, so that its capacity doesn't depend on the multithreading
+        let mut sub_tiles: Vec<(usize, Tile)> = Vec::with_capacity(per_tile.iter().map(Vec::len).sum());
+        for tile_sub_tiles in per_tile {
+            sub_tiles.extend(tile_sub_tiles);
         }
-        self.vertices.clear();
-        self.commands.clear();
-        self.stats = RasterizerStatistics::new();
+        sub_tiles
     }
 
-    pub fn commit(&mut self, command: &RasterizationCommand) {
-        let use_explicit_indices = !command.indices.is_empty();
-        let input_triangles_num = if use_explicit_indices {
-            command.indices.len() / 3
-        } else {
-            command.world_positions.len() / 3
-        };
+    // Checks whether the scheduled triangle's bounds overlap the viewport, conservatively. The commands drawn without
+    // the triangles, e.g. fills and strokes, are drawn in any viewport.
+    fn may_overlap(&self, tri: &ScheduledTriangle, viewport: Viewport) -> bool {
+        let command: &ScheduledCommand = &self.commands[tri.cmd as usize];
+        if command.fullscreen_color.is_some()
+            || command.fill.is_some()
+            || command.stroke.is_some()
+            || command.polygon.is_some()
+            || command.decal.is_some()
+        {
+            return true;
+        }
+        let [v0, v1, v2] = self.geometry.triangle(tri.tri as usize);
+        let xmin: f32 = v0.position.x.min(v1.position.x).min(v2.position.x).floor();
+        let ymin: f32 = v0.position.y.min(v1.position.y).min(v2.position.y).floor();
+        let xmax: f32 = v0.position.x.max(v1.position.x).max(v2.position.x).ceil();
+        let ymax: f32 = v0.position.y.max(v1.position.y).max(v2.position.y).ceil();
+        xmax >= viewport.xmin as f32
+            && xmin <= viewport.xmax as f32
+            && ymax >= viewport.ymin as f32
+            && ymin <= viewport.ymax as f32
+    }
 
-        if input_triangles_num == 0 {
+    fn draw_tile(&self, job: &mut TiledJob) {
+        let render_tile = unsafe { &*job.render_tile };
+        if render_tile.triangles.is_empty() {
             return;
         }
 
-        self.stats.committed_triangles += input_triangles_num;
-
-        let view_projection = command.projection * command.view;
-        let normal_matrix = command.model.as_mat33().inverse().transpose();
-        let viewport_scale = self.viewport_scale;
-        let scheduled_vertices_start = self.vertices.len();
+        let viewport = render_tile.local_viewport;
 
-        // Command color - uniformly applied to all committed triangles, conditionally premultiplied by alpha if alpha_blending is enabled.
-        let command_color: Vec4 = if command.alpha_blending == AlphaBlendingMode::None {
-            command.color
-        } else {
-            Vec4::new(
-                command.color.x * command.color.w,
-                command.color.y * command.color.w,
-                command.color.z * command.color.w,
-                command.color.w,
-            )
-        };
-        // If the command color is (1, 1, 1, 1) - it can be safely ignored.
-        let is_command_color_defined: bool = (command_color.x - 1.0).abs() > 0.005
-            || (command_color.y - 1.0).abs() > 0.005
-            || (command_color.z - 1.0).abs() > 0.005
-            || (command_color.w - 1.0).abs() > 0.005;
+        let mut tile_verts = ArrayVec::<Vertex, 384>::new(); // up to 128 triangles
+        let mut cmd_idx = render_tile.triangles.first().unwrap().cmd;
 
-        // Gather per-batch color interpolation mode.
-        // That's conservative, i.e. a single triangle with color information will cause the whole batch to be color interpolated.
-        let mut color_interpolation_mode: VerticesColorInterpolationMode = VerticesColorInterpolationMode::None;
+        // Built lazily on the first triangle, the bounds of the batch's triangles tell which blocks to refresh after it.
+        // Only the blocks within the tile's viewport are read, the rest of a split tile is drawn by the other jobs.
+        let use_hiz: bool = self.hierarchical_z && job.framebuffer_tile.depth_buffer.is_some();
+        let hiz_rect = Viewport::new(
+            viewport.xmin - job.framebuffer_tile.origin_x(),
+            viewport.ymin - job.framebuffer_tile.origin_y(),
+            viewport.xmax - job.framebuffer_tile.origin_x(),
+            viewport.ymax - job.framebuffer_tile.origin_y(),
+        );
+        let mut hiz: Option<HiZTile> = None;
+        let mut hiz_dirty: Option<Viewport> = None;
 
-        for i in 0..input_triangles_num {
-            let index = |n: usize| {
-                if use_explicit_indices {
-                    command.indices[i * 3 + n] as usize
-                } else {
-                    i * 3 + n
+        for tri in &render_tile.triangles {
+            if tile_verts.is_full() || tri.cmd != cmd_idx {
+                let command: &ScheduledCommand = &self.commands[cmd_idx as usize];
+                if let Some(viewport) = Self::scissored_viewport(viewport, command) {
+                    let call_stats =
+                        self.draw_triangles_dispatch(&mut job.framebuffer_tile, viewport, &tile_verts, command);
+                    job.statistics = job.statistics + call_stats;
                 }
-            };
-            let i0: usize = index(0);
-            let i1: usize = index(1);
-            let i2: usize = index(2);
+                if let (Some(hiz), Some(dirty)) = (hiz.as_mut(), hiz_dirty.take()) {
+                    hiz.update(job.framebuffer_tile.depth_buffer.as_ref().unwrap(), dirty);
+                }
+                tile_verts.clear();
+                cmd_idx = tri.cmd;
+            }
 
-            // Fill world positions of the triangle vertices.
-            let world_positions: [Vec3; 3] = [
-                command.model * command.world_positions[i0],
-                command.model * command.world_positions[i1],
-                command.model * command.world_positions[i2],
-            ];
+            let command: &ScheduledCommand = &self.commands[tri.cmd as usize];
+            if command.fullscreen_color.is_some() {
+                let call_stats = self.draw_fullscreen(&mut job.framebuffer_tile, viewport, command);
+                job.statistics = job.statistics + call_stats;
+                continue;
+            }
+            if let Some(fill) = command.fill.as_ref() {
+                let call_stats = self.draw_fill(&mut job.framebuffer_tile, viewport, fill);
+                job.statistics = job.statistics + call_stats;
+                continue;
+            }
+            if let Some(stroke) = command.stroke.as_ref() {
+                let call_stats = self.draw_stroke(&mut job.framebuffer_tile, viewport, stroke, command.alpha_blending);
+                job.statistics = job.statistics + call_stats;
+                continue;
+            }
+            if let Some(polygon) = command.polygon.as_ref() {
+                let call_stats =
+                    self.draw_polygon(&mut job.framebuffer_tile, viewport, polygon, command.alpha_blending);
+                job.statistics = job.statistics + call_stats;
+                continue;
+            }
+            if let Some(decal) = command.decal.as_ref() {
+                let call_stats = self.draw_decal(&mut job.framebuffer_tile, viewport, decal, command);
+                job.statistics = job.statistics + call_stats;
+                continue;
+            }
 
-            let mut input_vertices: [Vertex; 3] = [Vertex::default(); 3];
+            let triangle: [Vertex; 3] = self.geometry.triangle(tri.tri as usize);
+            if use_hiz {
+                let depth_buffer = job.framebuffer_tile.depth_buffer.as_ref().unwrap();
+                let (bounds, min_depth): (Viewport, u16) = Self::triangle_tile_bounds(&triangle, depth_buffer);
+                let hiz: &mut HiZTile = hiz.get_or_insert_with(|| HiZTile::new(depth_buffer, hiz_rect));
+                // Only the part of the triangle within the tile's viewport is drawn here
+                if let Some(bounds) = bounds.intersection(&hiz_rect) {
+                    // Only the nearer-passing tests can be rejected by the farthest depth of the blocks, and only when
+                    // the depth bias doesn't pull the fragments closer than the vertices
+                    let nearer_test: bool = matches!(command.depth_test, DepthTest::Less | DepthTest::LEqual)
+                        && command.depth_bias.constant >= 0.0
+                        && command.depth_bias.slope >= 0.0;
+                    if nearer_test && hiz.occludes(min_depth, bounds) {
+                        job.statistics.hiz_rejected_triangles += 1;
+                        continue;
+                    }
+                    if command.depth_write {
+                        hiz_dirty = Some(match hiz_dirty {
+                            Some(dirty) => Viewport::new(
+                                dirty.xmin.min(bounds.xmin),
+                                dirty.ymin.min(bounds.ymin),
+                                dirty.xmax.max(bounds.xmax),
+                                dirty.ymax.max(bounds.ymax),
+                            ),
+                            None => bounds,
+                        });
+                    }
+                }
+            }
+            tile_verts.extend(triangle);
+        }
 
-            // Fill projected positions in NDC space [-1, 1].
-            input_vertices[0].position = view_projection * world_positions[0].as_point4();
-            input_vertices[1].position = view_projection * world_positions[1].as_point4();
-            input_vertices[2].position = view_projection * world_positions[2].as_point4();
+        if !tile_verts.is_empty() {
+            let command: &ScheduledCommand = &self.commands[cmd_idx as usize];
+            if let Some(viewport) = Self::scissored_viewport(viewport, command) {
+                let call_stats =
+                    self.draw_triangles_dispatch(&mut job.framebuffer_tile, viewport, &tile_verts, command);
+                job.statistics = job.statistics + call_stats;
+            }
+        }
+    }
 
-            // Fill per-vertex texture coordinates.
-            if command.tex_coords.is_empty() {
-                input_vertices[0].tex_coord = Vec2::new(0.0, 0.0);
-                input_vertices[1].tex_coord = Vec2::new(0.0, 0.0);
-                input_vertices[2].tex_coord = Vec2::new(0.0, 0.0);
-            } else {
-                input_vertices[0].tex_coord = command.tex_coords[i0];
-                input_vertices[1].tex_coord = command.tex_coords[i1];
-                input_vertices[2].tex_coord = command.tex_coords[i2];
+    // Reorders the runs of consecutive triangles whose commands have the same Opaque or Translucent hint by the depth
+    // of their centroids: front-to-back for the opaque ones and back-to-front for the translucent ones.
+    // The sorting is stable, so the triangles at the same depth keep the submission order.
+    fn sort_tile_triangles(
+        triangles: &mut [ScheduledTriangle],
+        geometry: &TriangleList,
+        commands: &[ScheduledCommand],
+    ) {
+        let depth = |tri: &ScheduledTriangle| -> f32 {
+            let [v0, v1, v2] = geometry.triangle(tri.tri as usize);
+            v0.position.z + v1.position.z + v2.position.z
+        };
+        let mut run_start: usize = 0;
+        while run_start < triangles.len() {
+            let opacity: OpacityHint = commands[triangles[run_start].cmd as usize].opacity;
+            let mut run_end: usize = run_start + 1;
+            while run_end < triangles.len() && commands[triangles[run_end].cmd as usize].opacity == opacity {
+                run_end += 1;
+            }
+            let run: &mut [ScheduledTriangle] = &mut triangles[run_start..run_end];
+            match opacity {
+                OpacityHint::Ordered => {}
+                OpacityHint::Opaque => run.sort_by(|a, b| depth(a).total_cmp(&depth(b))),
+                OpacityHint::Translucent => run.sort_by(|a, b| depth(b).total_cmp(&depth(a))),
             }
+            run_start = run_end;
+        }
+    }
 
-            // Fill normals, either with rotated input normals or derived from the triangle face.
-            if command.normals.is_empty() {
-                // Derive a uniform non-smooth normal vector from the triangle's vertices.
-                let edge1 = world_positions[1] - world_positions[0];
-                let edge2 = world_positions[2] - world_positions[0];
-                let face_normal = cross(edge1, edge2).normalized();
-                input_vertices[0].normal = face_normal;
-                input_vertices[1].normal = face_normal;
-                input_vertices[2].normal = face_normal;
+    // The triangle's bounds in the tile's pixels, clamped to the tile, and its minimum depth in the depth buffer's units.
+    fn triangle_tile_bounds(triangle: &[Vertex], depth_buffer: &TiledBufferTileMut<u16, 64, 64>) -> (Viewport, u16) {
+        let (v0, v1, v2) = (&triangle[0].position, &triangle[1].position, &triangle[2].position);
+        let origin_x: f32 = depth_buffer.origin_x as f32;
+        let origin_y: f32 = depth_buffer.origin_y as f32;
+        let max_x: f32 = (depth_buffer.width - 1) as f32;
+        let max_y: f32 = (depth_buffer.height - 1) as f32;
+        let xmin: f32 = (v0.x.min(v1.x).min(v2.x) - origin_x).floor().clamp(0.0, max_x);
+        let ymin: f32 = (v0.y.min(v1.y).min(v2.y) - origin_y).floor().clamp(0.0, max_y);
+        let xmax: f32 = (v0.x.max(v1.x).max(v2.x) - origin_x).floor().clamp(0.0, max_x);
+        let ymax: f32 = (v0.y.max(v1.y).max(v2.y) - origin_y).floor().clamp(0.0, max_y);
+        let min_z: f32 = v0.z.min(v1.z).min(v2.z);
+        let min_depth: u16 = ((min_z * 0.5 + 0.5) * 65535.0).clamp(0.0, 65535.0) as u16;
+        (Viewport::new(xmin as u16, ymin as u16, xmax as u16 + 1, ymax as u16 + 1), min_depth)
+    }
+
+    // The part of the tile's viewport inside the command's scissor rectangle, None if there's nothing to draw.
+    fn scissored_viewport(tile_viewport: Viewport, command: &ScheduledCommand) -> Option<Viewport> {
+        match command.scissor {
+            Some(scissor) => tile_viewport.intersection(&scissor),
+            None => Some(tile_viewport),
+        }
+    }
+
+    // fn idx_to_color_hash(mut x: u32) -> u32 {
+    //     // Mix the bits using a few bitwise operations and multiplications
+    //     x ^= x >> 16;
+    //     x = x.wrapping_mul(0x85ebca6b);
+    //     x ^= x >> 13;
+    //     x = x.wrapping_mul(0xc2b2ae35);
+    //     x ^= x >> 16;
+    //
+    //     x | 0xFF
+    // }
+
+    fn encode_normal_as_u32(nx: f32, ny: f32, nz: f32) -> u32 {
+        unsafe {
+            let x8: u8 = (nx * 127.5 + 127.5).to_int_unchecked();
+            let y8: u8 = (ny * 127.5 + 127.5).to_int_unchecked();
+            let z8: u8 = (nz * 127.5 + 127.5).to_int_unchecked();
+            (x8 as u32) | ((y8 as u32) << 8) | ((z8 as u32) << 16)
+        }
+    }
+
+    // Calculates the level of detail for sampling a texture over a triangle, based on the ratio of texel to pixel areas.
+    // When a texture region is used, the texture coordinates are relative to the region and so is the texel area.
+    fn texture_lod(
+        texture: &Texture,
+        region: Option<&TextureRegion>,
+        v0: &Vertex,
+        v1: &Vertex,
+        v2: &Vertex,
+        area_x_2: f32,
+    ) -> f32 {
+        let (width, height): (f32, f32) = match region {
+            Some(region) => region.size_in_texels(texture),
+            None => (texture.mips[0].width as f32, texture.mips[0].height as f32),
+        };
+        Self::tex_coords_lod(width, height, v0.tex_coord, v1.tex_coord, v2.tex_coord, area_x_2)
+    }
+
+    // Same as texture_lod(), but for the lightmap sampled with the secondary texture coordinates.
+    fn lightmap_lod(lightmap: &Texture, v0: &Vertex, v1: &Vertex, v2: &Vertex, area_x_2: f32) -> f32 {
+        let (width, height): (f32, f32) = (lightmap.mips[0].width as f32, lightmap.mips[0].height as f32);
+        Self::tex_coords_lod(width, height, v0.tex_coord2, v1.tex_coord2, v2.tex_coord2, area_x_2)
+    }
+
+    fn tex_coords_lod(width: f32, height: f32, t0: Vec2, t1: Vec2, t2: Vec2, area_x_2: f32) -> f32 {
+        let t01: Vec2 = t1 - t0;
+        let t02: Vec2 = t2 - t0;
+        let texel_area_x_2: f32 = (t01.x * t02.y - t02.x * t01.y).abs() * width * height;
+        let rho2: f32 = texel_area_x_2 / area_x_2;
+        0.5 * rho2.log2()
+    }
+
+    // Derivatives of the texture coordinates along the screen's X and Y axes, assuming affine mapping over the triangle.
+    fn texture_derivatives(v0: &Vertex, v1: &Vertex, v2: &Vertex, v01: Vec2, v02: Vec2, area_x_2: f32) -> (Vec2, Vec2) {
+        let t01: Vec2 = v1.tex_coord - v0.tex_coord;
+        let t02: Vec2 = v2.tex_coord - v0.tex_coord;
+        let uv_dx: Vec2 = (t01 * v02.y - t02 * v01.y) / area_x_2;
+        let uv_dy: Vec2 = (t02 * v01.x - t01 * v02.x) / area_x_2;
+        (uv_dx, uv_dy)
+    }
+
+    fn is_top_left_24_8(edge_x: i32, edge_y: i32) -> bool {
+        (edge_y < 0) || // left edge
+            (edge_y == 0 && edge_x > 0) // top edge
+        // NB!
+        // This says "an edge that is exactly horizontal", but perhaps some epsilon is still needed...
+        // https://learn.microsoft.com/en-us/windows/win32/direct3d11/d3d10-graphics-programming-guide-rasterizer-stage-rules
+    }
+
+    fn draw_triangles_dispatch(
+        &self,
+        framebuffer: &mut FramebufferTile,
+        local_viewport: Viewport,
+        vertices: &[Vertex],
+        command: &ScheduledCommand,
+    ) -> PerTileStatistics {
+        let has_color: bool = framebuffer.color_buffer.is_some();
+        let has_depth: bool = framebuffer.depth_buffer.is_some();
+        let has_depth_f32: bool = framebuffer.depth_buffer_f32.is_some();
+        let has_normal_buffer: bool = framebuffer.normal_buffer.is_some();
+        let has_texture: bool = command.texture.is_some();
+        let has_pattern: bool = command.pattern.is_some() || command.fragment_hook.is_some();
+        let has_normal_map: bool = command.normal_map.is_some();
+        let has_lightmap: bool = command.lightmap.is_some();
+        let alpha_blending_mode: u8 = match command.alpha_blending {
+            AlphaBlendingMode::None => AlphaBlendingProcessingMode::None as u8,
+            AlphaBlendingMode::Normal | AlphaBlendingMode::PremultipliedNormal => {
+                AlphaBlendingProcessingMode::Normal as u8
+            }
+            AlphaBlendingMode::Additive => AlphaBlendingProcessingMode::Additive as u8,
+            _ => AlphaBlendingProcessingMode::Generic as u8,
+        };
+        let normal_processing_mode: u8 = if has_normal_buffer {
+            if has_normal_map && has_texture && !has_pattern {
+                NormalsProcessingMode::NormalMapping as u8
             } else {
-                input_vertices[0].normal = (normal_matrix * command.normals[i0]).normalized();
-                input_vertices[1].normal = (normal_matrix * command.normals[i1]).normalized();
-                input_vertices[2].normal = (normal_matrix * command.normals[i2]).normalized();
+                NormalsProcessingMode::Vertex as u8
             }
+        } else {
+            NormalsProcessingMode::None as u8
+        };
+        let alpha_test_enabled: bool = command.alpha_test > 0u8;
+        let color_interpolation_mode: u8 = command.color_interpolation as u8;
+        if (has_depth || has_depth_f32) && command.depth_test == DepthTest::Never {
+            return PerTileStatistics::default();
+        }
+        // The default Less test with the depth write is instantiated separately from the configurable ones
+        let depth_configurable: bool = has_depth && (command.depth_test != DepthTest::Less || !command.depth_write);
 
-            // TODO: support pre-defined smooth per-vertex tangents
-            {
-                // Derive a uniform non-smooth tangent vector from the triangle's vertices.
-                let uv1: Vec2 = input_vertices[1].tex_coord - input_vertices[0].tex_coord;
-                let uv2: Vec2 = input_vertices[2].tex_coord - input_vertices[0].tex_coord;
-                let e1: Vec3 = world_positions[1] - world_positions[0];
-                let e2: Vec3 = world_positions[2] - world_positions[0];
-                let denom: f32 = uv1.x * uv2.y - uv1.y * uv2.x;
-                let tangent: Vec3 = if denom.abs() > 0.000001 {
-                    let r: f32 = 1.0 / denom;
-                    (e1 * uv2.y - e2 * uv1.y) * r
-                } else {
-                    Vec3::new(1.0, 0.0, 0.0)
-                };
-                let n0 = input_vertices[0].normal;
-                let n1 = input_vertices[1].normal;
-                let n2 = input_vertices[2].normal;
-                input_vertices[0].tangent = (tangent - n0 * n0.dot(tangent)).normalized();
-                input_vertices[1].tangent = (tangent - n1 * n1.dot(tangent)).normalized();
-                input_vertices[2].tangent = (tangent - n2 * n2.dot(tangent)).normalized();
-            }
+        let mut idx = 0;
+        idx += has_color as usize;
+        idx *= 4; // four options for depth: none, default, configurable, floating-point
+        idx += if has_depth_f32 {
+            3
+        } else {
+            has_depth as usize + depth_configurable as usize
+        };
+        idx *= 3; // three options for normals processing
+        idx += normal_processing_mode as usize;
+        idx *= 2; // two options for texture, the pattern and the fragment hook are evaluated in place of the texture
+        idx += (has_texture || has_pattern || has_lightmap) as usize;
+        idx *= 4; // four options for alpha blending: none, normal, additive, any other mode
+        idx += alpha_blending_mode as usize;
+        idx *= 2; // two options for alpha test
+        idx += alpha_test_enabled as usize;
+        idx *= 3; // three options for color interpolation
+        idx += color_interpolation_mode as usize;
 
-            // Fill per-vertex colors.
-            if command.colors.is_empty() {
-                input_vertices[0].color = command_color;
-                input_vertices[1].color = command_color;
-                input_vertices[2].color = command_color;
-            } else {
-                input_vertices[0].color = command.colors[i0];
-                input_vertices[1].color = command.colors[i1];
-                input_vertices[2].color = command.colors[i2];
-                if is_command_color_defined {
-                    input_vertices[0].color *= command_color;
-                    input_vertices[1].color *= command_color;
-                    input_vertices[2].color *= command_color;
-                }
-                if command.alpha_blending != AlphaBlendingMode::None {
-                    input_vertices[0].color.x *= input_vertices[0].color.w;
-                    input_vertices[0].color.y *= input_vertices[0].color.w;
-                    input_vertices[0].color.z *= input_vertices[0].color.w;
-                    input_vertices[1].color.x *= input_vertices[1].color.w;
-                    input_vertices[1].color.y *= input_vertices[1].color.w;
-                    input_vertices[1].color.z *= input_vertices[1].color.w;
-                    input_vertices[2].color.x *= input_vertices[2].color.w;
-                    input_vertices[2].color.y *= input_vertices[2].color.w;
-                    input_vertices[2].color.z *= input_vertices[2].color.w;
+        // Opaque untextured fills without the depth and normal buffers (UI panels, debug fills) take a specialized
+        // path that skips the full interpolation machinery.
+        if self.opaque_fills_fast_path
+            && has_color
+            && framebuffer.color_format == ColorBufferFormat::Rgba8
+            && !has_depth
+            && !has_depth_f32
+            && !has_normal_buffer
+            && framebuffer.motion_buffer.is_none()
+            && !has_texture
+            && !has_pattern
+            && !has_lightmap
+            && alpha_blending_mode == AlphaBlendingProcessingMode::None as u8
+            && !alpha_test_enabled
+            && command.color_write_mask == ColorWriteMask::ALL
+            && command.tint.is_identity()
+            && command.fog.is_none()
+            && command.reflection.is_none()
+        {
+            return match command.color_interpolation {
+                VerticesColorInterpolationMode::None => {
+                    Self::draw_opaque_fills::<{ VerticesColorInterpolationMode::None as u8 }>
+                }
+                VerticesColorInterpolationMode::Fixed => {
+                    Self::draw_opaque_fills::<{ VerticesColorInterpolationMode::Fixed as u8 }>
                 }
+                VerticesColorInterpolationMode::PerVertex => {
+                    Self::draw_opaque_fills::<{ VerticesColorInterpolationMode::PerVertex as u8 }>
+                }
+            }(self, framebuffer, local_viewport, vertices, command);
+        }
+
+        DRAW_TRIANGLE_FUNCTIONS[idx](self, framebuffer, local_viewport, vertices, command)
+    }
+
+    // Fills the part of the viewport covered by the tile with the command's color, optionally multiplied by the texture
+    // stretched over the whole viewport.
+    fn draw_fullscreen(
+        &self,
+        framebuffer: &mut FramebufferTile,
+        local_viewport: Viewport,
+        command: &ScheduledCommand,
+    ) -> PerTileStatistics {
+        let mut statistics = PerTileStatistics::default();
+        if framebuffer.color_buffer.is_none() {
+            return statistics;
+        }
+
+        let rt_xmin = (max(local_viewport.xmin, framebuffer.origin_x()) - framebuffer.origin_x()) as i32;
+        let rt_xmax = (min(local_viewport.xmax, framebuffer.origin_x() + framebuffer.width())
+            - framebuffer.origin_x()
+            - 1) as i32;
+        let rt_ymin = (max(local_viewport.ymin, framebuffer.origin_y()) - framebuffer.origin_y()) as i32;
+        let rt_ymax = (min(local_viewport.ymax, framebuffer.origin_y() + framebuffer.height())
+            - framebuffer.origin_y()
+            - 1) as i32;
+        if rt_xmin > rt_xmax || rt_ymin > rt_ymax {
+            return statistics;
+        }
+
+        // NB! Same as the triangles, the color is multiplied by 256 instead of 255 to use binary shift later.
+        let fill_color: Vec4 = command.fullscreen_color.unwrap();
+        let color_r: u32 = (fill_color.x * 256.0) as u32;
+        let color_g: u32 = (fill_color.y * 256.0) as u32;
+        let color_b: u32 = (fill_color.z * 256.0) as u32;
+        let color_a: u32 = (fill_color.w * 256.0) as u32;
+
+        // Map the viewport onto the texture: the texture coordinates step by 1/width and 1/height per pixel, which also
+        // gives the texture's LOD.
+        let viewport_width: f32 = (self.viewport.xmax - self.viewport.xmin) as f32;
+        let viewport_height: f32 = (self.viewport.ymax - self.viewport.ymin) as f32;
+        let sampler: Sampler = match command.texture.as_ref() {
+            Some(texture) => {
+                let texel_area: f32 =
+                    (texture.mips[0].width as f32 * texture.mips[0].height as f32) / (viewport_width * viewport_height);
+                Sampler::new_with_address_modes(
+                    texture,
+                    command.sampling_filter,
+                    0.5 * texel_area.log2(),
+                    command.address_mode_u,
+                    command.address_mode_v,
+                )
             }
+            None => Sampler::default(),
+        };
+        let uv_scale: SamplerUVScale = sampler.uv_scale();
+        let u_dx: f32 = uv_scale.scale / viewport_width;
+        let v_dy: f32 = uv_scale.scale / viewport_height;
+        let u_min: f32 = ((framebuffer.origin_x() as i32 + rt_xmin - self.viewport.xmin as i32) as f32 + 0.5) * u_dx
+            + uv_scale.bias * uv_scale.scale;
+        let v_min: f32 = ((framebuffer.origin_y() as i32 + rt_ymin - self.viewport.ymin as i32) as f32 + 0.5) * v_dy
+            + uv_scale.bias * uv_scale.scale;
 
-            // Check if we need to pessimize the color interpolation mode up to Fixed
-            if color_interpolation_mode == VerticesColorInterpolationMode::None {
-                if (input_vertices[0].color - Vec4::new(1.0, 1.0, 1.0, 1.0)).length_squared() > 0.01
-                    || (input_vertices[1].color - Vec4::new(1.0, 1.0, 1.0, 1.0)).length_squared() > 0.01
-                    || (input_vertices[2].color - Vec4::new(1.0, 1.0, 1.0, 1.0)).length_squared() > 0.01
-                {
-                    color_interpolation_mode = VerticesColorInterpolationMode::Fixed;
+        let has_texture: bool = command.texture.is_some();
+        let hdr: bool = framebuffer.color_format == ColorBufferFormat::Rgb9e5;
+        let constant_color: u32 = if hdr {
+            encode_rgb9e5(fill_color.x, fill_color.y, fill_color.z)
+        } else {
+            RGBA::new(((color_r * 255) >> 8) as u8, ((color_g * 255) >> 8) as u8, ((color_b * 255) >> 8) as u8, 255)
+                .to_u32()
+        };
+        let constant_color_x4: U32x4 = U32x4::splat(constant_color);
+        let color_buffer_ptr: *mut u32 = unsafe { framebuffer.color_buffer.as_mut().unwrap_unchecked().ptr };
+        let row_steps: i32 = rt_xmax - rt_xmin + 1;
+        let mut v: f32 = v_min;
+        for y in rt_ymin..=rt_ymax {
+            let mut color_ptr: *mut u32 =
+                unsafe { color_buffer_ptr.add((y * Framebuffer::TILE_WITH as i32 + rt_xmin) as usize) };
+            if !has_texture && command.alpha_blending == AlphaBlendingMode::None {
+                // An opaque fill of a constant color - just store the same value 4 pixels at a time
+                let mut left: i32 = row_steps;
+                while left >= 4 {
+                    unsafe {
+                        constant_color_x4.store_to_ptr(color_ptr);
+                        color_ptr = color_ptr.add(4);
+                    }
+                    left -= 4;
                 }
-            }
-            // Check if we need to pessimize the color interpolation mode up to Per-Vertex
-            if color_interpolation_mode == VerticesColorInterpolationMode::Fixed {
-                if (input_vertices[0].color - input_vertices[1].color).length_squared() > 0.01
-                    || (input_vertices[0].color - input_vertices[2].color).length_squared() > 0.01
-                {
-                    color_interpolation_mode = VerticesColorInterpolationMode::PerVertex;
+                while left > 0 {
+                    unsafe {
+                        *color_ptr = constant_color;
+                        color_ptr = color_ptr.add(1);
+                    }
+                    left -= 1;
+                }
+            } else {
+                let mut u: f32 = u_min;
+                for _x in 0..row_steps {
+                    let texel: RGBA = if has_texture {
+                        sampler.sample_prescaled(u, v)
+                    } else {
+                        RGBA::new(255, 255, 255, 255)
+                    };
+                    let r: u8 = ((color_r * texel.r as u32) >> 8) as u8;
+                    let g: u8 = ((color_g * texel.g as u32) >> 8) as u8;
+                    let b: u8 = ((color_b * texel.b as u32) >> 8) as u8;
+                    let a: u8 = ((color_a * texel.a as u32) >> 8) as u8;
+                    let color: u32 = if hdr {
+                        let src: Vec4 = fill_color
+                            * Vec4::new(texel.r as f32, texel.g as f32, texel.b as f32, texel.a as f32)
+                            * (1.0 / 255.0);
+                        let blended: Vec3 = command.alpha_blending.blend(src, decode_rgb9e5(unsafe { *color_ptr }));
+                        encode_rgb9e5(blended.x, blended.y, blended.z)
+                    } else if matches!(
+                        command.alpha_blending,
+                        AlphaBlendingMode::Normal | AlphaBlendingMode::PremultipliedNormal
+                    ) {
+                        let dest: RGBA = RGBA::from_u32(unsafe { *color_ptr });
+                        let inv_a: u32 = (255 - a) as u32;
+                        RGBA::new(
+                            r + ((dest.r as u32 * inv_a) / 255) as u8,
+                            g + ((dest.g as u32 * inv_a) / 255) as u8,
+                            b + ((dest.b as u32 * inv_a) / 255) as u8,
+                            255,
+                        )
+                        .to_u32()
+                    } else if command.alpha_blending == AlphaBlendingMode::Additive {
+                        let dest: RGBA = RGBA::from_u32(unsafe { *color_ptr });
+                        RGBA::new(
+                            (r as u32 + dest.r as u32).min(255) as u8,
+                            (g as u32 + dest.g as u32).min(255) as u8,
+                            (b as u32 + dest.b as u32).min(255) as u8,
+                            255,
+                        )
+                        .to_u32()
+                    } else if command.alpha_blending != AlphaBlendingMode::None {
+                        blend_rgba8(command.alpha_blending, RGBA::new(r, g, b, a), unsafe { *color_ptr })
+                    } else {
+                        RGBA::new(r, g, b, 255).to_u32()
+                    };
+                    unsafe {
+                        *color_ptr = color;
+                        color_ptr = color_ptr.add(1);
+                    }
+                    u += u_dx;
                 }
             }
+            v += v_dy;
+        }
+        if cfg!(debug_assertions) {
+            statistics.fragments_drawn += (row_steps * (rt_ymax - rt_ymin + 1)) as usize;
+        }
+        statistics
+    }
 
-            // TODO: cull earlier????
-            // Why try clipping the triangle if it's not visible?
-
-            let clipped_vertices = clip_triangle(&input_vertices);
-            if clipped_vertices.is_empty() {
-                continue;
-            }
-
-            for clipped_vertex_idx in 1..clipped_vertices.len() - 1 {
-                let mut vertices = [
-                    clipped_vertices[0],                      //
-                    clipped_vertices[clipped_vertex_idx],     //
-                    clipped_vertices[clipped_vertex_idx + 1], //
-                ];
-
-                vertices[0].position = perspective_divide(vertices[0].position);
-                vertices[1].position = perspective_divide(vertices[1].position);
-                vertices[2].position = perspective_divide(vertices[2].position);
-                vertices[0].position = viewport_scale.apply(vertices[0].position);
-                vertices[1].position = viewport_scale.apply(vertices[1].position);
-                vertices[2].position = viewport_scale.apply(vertices[2].position);
+    // Draws the part of a decal covered by the tile: the pixels' positions are reconstructed from the depth buffer and
+    // those inside the decal's box and facing its projector are painted with the decal's color and texture.
+    fn draw_decal(
+        &self,
+        framebuffer: &mut FramebufferTile,
+        local_viewport: Viewport,
+        decal: &ScheduledDecal,
+        command: &ScheduledCommand,
+    ) -> PerTileStatistics {
+        let mut statistics = PerTileStatistics::default();
+        if framebuffer.color_buffer.is_none()
+            || (framebuffer.depth_buffer.is_none() && framebuffer.depth_buffer_f32.is_none())
+        {
+            return statistics;
+        }
 
-                let v01 = vertices[1].position.xy() - vertices[0].position.xy();
-                let v02 = vertices[2].position.xy() - vertices[0].position.xy();
-                let ccw = Mat22([v01.x, v02.x, v01.y, v02.y]).det() < 0.0;
+        let rt_xmin = (max(local_viewport.xmin, framebuffer.origin_x()) - framebuffer.origin_x()) as i32;
+        let rt_xmax = (min(local_viewport.xmax, framebuffer.origin_x() + framebuffer.width())
+            - framebuffer.origin_x()
+            - 1) as i32;
+        let rt_ymin = (max(local_viewport.ymin, framebuffer.origin_y()) - framebuffer.origin_y()) as i32;
+        let rt_ymax = (min(local_viewport.ymax, framebuffer.origin_y() + framebuffer.height())
+            - framebuffer.origin_y()
+            - 1) as i32;
+        if rt_xmin > rt_xmax || rt_ymin > rt_ymax {
+            return statistics;
+        }
 
-                if (command.culling == CullMode::CW && !ccw) || (command.culling == CullMode::CCW && ccw) {
+        let sampler: Sampler = match command.texture.as_ref() {
+            Some(texture) => Sampler::new_with_address_modes(
+                texture,
+                command.sampling_filter,
+                decal.lod,
+                SamplerAddressMode::ClampToEdge,
+                SamplerAddressMode::ClampToEdge,
+            ),
+            None => Sampler::default(),
+        };
+        let has_texture: bool = command.texture.is_some();
+        let hdr: bool = framebuffer.color_format == ColorBufferFormat::Rgb9e5;
+        let origin_x: f32 = framebuffer.origin_x() as f32 - self.viewport.xmin as f32;
+        let origin_y: f32 = framebuffer.origin_y() as f32 - self.viewport.ymin as f32;
+        let viewport_width: f32 = (self.viewport.xmax - self.viewport.xmin) as f32;
+        let viewport_height: f32 = (self.viewport.ymax - self.viewport.ymin) as f32;
+        let quantize = |c: f32| -> u8 { (c * 255.0 + 0.5).clamp(0.0, 255.0) as u8 };
+        for y in rt_ymin as usize..=rt_ymax as usize {
+            for x in rt_xmin as usize..=rt_xmax as usize {
+                // Only the pixels with some geometry drawn are painted
+                let ndc_z: f32 = if let Some(depth_buffer) = framebuffer.depth_buffer.as_ref() {
+                    let depth: u16 = depth_buffer.at_unchecked(x, y);
+                    if depth == DEPTH_FAR {
+                        continue;
+                    }
+                    decode_depth(depth)
+                } else {
+                    let depth: f32 = framebuffer.depth_buffer_f32.as_ref().unwrap().at_unchecked(x, y);
+                    if depth == DEPTH_F32_FAR {
+                        continue;
+                    }
+                    decode_depth_f32(depth)
+                };
+                let ndc = Vec4::new(
+                    (origin_x + x as f32 + 0.5) / viewport_width * 2.0 - 1.0,
+                    1.0 - (origin_y + y as f32 + 0.5) / viewport_height * 2.0,
+                    ndc_z,
+                    1.0,
+                );
+                let position: Vec4 = decal.ndc_to_decal * ndc;
+                if position.w <= 0.0 {
                     continue;
                 }
-
-                if ccw {
-                    vertices.swap(2, 1);
+                let position: Vec3 = position.xyz() / position.w;
+                if position.x.abs() > 1.0 || position.y.abs() > 1.0 || position.z.abs() > 1.0 {
+                    continue;
+                }
+                if let Some(normal_buffer) = framebuffer.normal_buffer.as_ref() {
+                    let encoded_normal: RGBA = RGBA::from_u32(normal_buffer.at_unchecked(x, y));
+                    if !is_normal_none(encoded_normal)
+                        && dot(decode_normal_from_color(encoded_normal).normalized(), decal.facing) < decal.min_facing
+                    {
+                        continue;
+                    }
                 }
 
-                self.vertices.extend_from_slice(&vertices);
+                let texel: RGBA = if has_texture {
+                    sampler.sample(position.x * 0.5 + 0.5, 0.5 - position.y * 0.5)
+                } else {
+                    RGBA::new(255, 255, 255, 255)
+                };
+                let mut src: Vec4 = decal.color
+                    * Vec4::new(texel.r as f32, texel.g as f32, texel.b as f32, texel.a as f32)
+                    * (1.0 / 255.0);
+                if command.alpha_blending.premultiplies_colors() {
+                    src = Vec4::new(src.x * src.w, src.y * src.w, src.z * src.w, src.w);
+                }
+                let pixel: &mut u32 = framebuffer.color_buffer.as_mut().unwrap().get_unchecked(x, y);
+                *pixel = if hdr {
+                    let blended: Vec3 = command.alpha_blending.blend(src, decode_rgb9e5(*pixel));
+                    encode_rgb9e5(blended.x, blended.y, blended.z)
+                } else if command.alpha_blending == AlphaBlendingMode::None {
+                    RGBA::new(quantize(src.x), quantize(src.y), quantize(src.z), 255).to_u32()
+                } else {
+                    let src = RGBA::new(quantize(src.x), quantize(src.y), quantize(src.z), quantize(src.w));
+                    blend_rgba8(command.alpha_blending, src, *pixel)
+                };
+                if cfg!(debug_assertions) {
+                    statistics.fragments_drawn += 1;
+                }
             }
         }
+        statistics
+    }
 
-        if scheduled_vertices_start == self.vertices.len() {
-            return;
+    // Draws the part of a screen-space fill covered by the tile.
+    // Pixels well inside the rectangle are fully covered, with an opaque solid paint those are filled 4 at a time.
+    // The rest are shaded per-pixel and blended with the framebuffer by their coverage.
+    fn draw_fill(
+        &self,
+        framebuffer: &mut FramebufferTile,
+        local_viewport: Viewport,
+        fill: &FillCommand,
+    ) -> PerTileStatistics {
+        let mut statistics = PerTileStatistics::default();
+        if framebuffer.color_buffer.is_none() {
+            return statistics;
         }
-        self.stats.scheduled_triangles += (self.vertices.len() - scheduled_vertices_start) / 3;
-
-        // When debug triangle coloring is enabled, textures are disabled.
-        let command_texture = if self.debug_coloring {
-            None
-        } else {
-            command.texture.clone()
-        };
 
-        // When debug triangle coloring is enabled, color the triangles using their indices.
-        if self.debug_coloring {
-            for vert_idx in (scheduled_vertices_start..self.vertices.len()).step_by(3) {
-                let color = debug_color(vert_idx as u32);
-                self.vertices[vert_idx + 0].color = color;
-                self.vertices[vert_idx + 1].color = color;
-                self.vertices[vert_idx + 2].color = color;
-            }
+        // The fill's bounds in the tile's pixels, expanded by a pixel for the anti-aliased edges
+        let origin_x: f32 = framebuffer.origin_x() as f32 - self.viewport.xmin as f32;
+        let origin_y: f32 = framebuffer.origin_y() as f32 - self.viewport.ymin as f32;
+        let rt_xmin = (max(local_viewport.xmin, framebuffer.origin_x()) - framebuffer.origin_x()) as i32;
+        let rt_xmax = (min(local_viewport.xmax, framebuffer.origin_x() + framebuffer.width())
+            - framebuffer.origin_x()
+            - 1) as i32;
+        let rt_ymin = (max(local_viewport.ymin, framebuffer.origin_y()) - framebuffer.origin_y()) as i32;
+        let rt_ymax = (min(local_viewport.ymax, framebuffer.origin_y() + framebuffer.height())
+            - framebuffer.origin_y()
+            - 1) as i32;
+        let xmin: i32 = rt_xmin.max((fill.min.x - origin_x).floor() as i32 - 1);
+        let xmax: i32 = rt_xmax.min((fill.max.x - origin_x).ceil() as i32);
+        let ymin: i32 = rt_ymin.max((fill.min.y - origin_y).floor() as i32 - 1);
+        let ymax: i32 = rt_ymax.min((fill.max.y - origin_y).ceil() as i32);
+        if xmin > xmax || ymin > ymax {
+            return statistics;
         }
 
-        // Reuse the last command or create a new one
-        let required_scheduled_command = ScheduledCommand {
-            texture: command_texture,
-            normal_map: command.normal_map.clone(),
-            sampling_filter: command.sampling_filter,
-            alpha_blending: command.alpha_blending,
-            alpha_test: command.alpha_test,
-            color_interpolation: color_interpolation_mode,
+        // The interior where the pixels are fully covered and are not affected by the corners and the border
+        let corner_radius: f32 = fill.effective_corner_radius();
+        let inset: f32 = corner_radius.max(fill.border_width) + 1.0;
+        let interior_xmin: i32 = (fill.min.x + inset - 0.5 - origin_x).ceil() as i32;
+        let interior_xmax: i32 = (fill.max.x - inset - 0.5 - origin_x).floor() as i32;
+        let interior_ymin: i32 = (fill.min.y + inset - 0.5 - origin_y).ceil() as i32;
+        let interior_ymax: i32 = (fill.max.y - inset - 0.5 - origin_y).floor() as i32;
+        let hdr: bool = framebuffer.color_format == ColorBufferFormat::Rgb9e5;
+        let opaque_color: Option<u32> = match fill.paint.uniform_color() {
+            Some(color) if fill.alpha_blending == AlphaBlendingMode::None && hdr => {
+                Some(encode_rgb9e5(color.x, color.y, color.z))
+            }
+            Some(color) if fill.alpha_blending == AlphaBlendingMode::None => Some(
+                RGBA::new(
+                    (color.x * 255.0 + 0.5).clamp(0.0, 255.0) as u8,
+                    (color.y * 255.0 + 0.5).clamp(0.0, 255.0) as u8,
+                    (color.z * 255.0 + 0.5).clamp(0.0, 255.0) as u8,
+                    255,
+                )
+                .to_u32(),
+            ),
+            _ => None,
         };
-        if self.commands.is_empty() || self.commands.last().unwrap() != &required_scheduled_command {
-            self.commands.push(required_scheduled_command);
-        }
-        let scheduled_command_index = (self.commands.len() - 1) as u16;
 
-        // Now bin each scheduled triangle
-        let xmin = self.viewport.xmin as u32;
-        let ymin = self.viewport.ymin as u32;
-        for vert_idx in (scheduled_vertices_start..self.vertices.len()).step_by(3) {
-            let v0 = &self.vertices[vert_idx + 0];
-            let v1 = &self.vertices[vert_idx + 1];
-            let v2 = &self.vertices[vert_idx + 2];
-            let v_xmin = v0.position.x.min(v1.position.x).min(v2.position.x) as u32;
-            let v_xmax = v0.position.x.max(v1.position.x).max(v2.position.x) as u32;
-            let v_ymin = v0.position.y.min(v1.position.y).min(v2.position.y) as u32;
-            let v_ymax = v0.position.y.max(v1.position.y).max(v2.position.y) as u32;
-            // TODO: add less crude discarding by running simple edge functions
-            // TODO: check if this min() is required
-            let ind_xmin = ((v_xmin - xmin) / Self::TILE_WIDTH as u32).min(self.tiles_x as u32 - 1);
-            let ind_ymin = ((v_ymin - ymin) / Self::TILE_HEIGHT as u32).min(self.tiles_y as u32 - 1);
-            let ind_xmax = ((v_xmax - xmin) / Self::TILE_WIDTH as u32).min(self.tiles_x as u32 - 1);
-            let ind_ymax = ((v_ymax - ymin) / Self::TILE_HEIGHT as u32).min(self.tiles_y as u32 - 1);
-            if ind_xmin == ind_xmax || ind_ymin == ind_ymax {
-                // The triangle is fully contained in a single tile or it a horizontal or vertical line, bin it in the appropriate tiles.
-                // No additional overlap checks are required.
-                for ind_y in ind_ymin..=ind_ymax {
-                    for ind_x in ind_xmin..=ind_xmax {
-                        let tile = &mut self.tiles[ind_y as usize * self.tiles_x as usize + ind_x as usize];
-                        tile.triangles
-                            .push(ScheduledTriangle { cmd: scheduled_command_index, tri_start: vert_idx as u16 });
-                        self.stats.binned_triangles += 1;
+        let color_buffer_ptr: *mut u32 = unsafe { framebuffer.color_buffer.as_mut().unwrap_unchecked().ptr };
+        for y in ymin..=ymax {
+            let row_ptr: *mut u32 = unsafe { color_buffer_ptr.add((y * Framebuffer::TILE_WITH as i32) as usize) };
+            let (span_xmin, span_xmax): (i32, i32) =
+                if opaque_color.is_some() && y >= interior_ymin && y <= interior_ymax {
+                    (interior_xmin.max(xmin), interior_xmax.min(xmax))
+                } else {
+                    (xmax + 1, xmax)
+                };
+            let mut x: i32 = xmin;
+            while x <= xmax {
+                if x == span_xmin && span_xmin <= span_xmax {
+                    // Fill the fully covered interior span of a uniform opaque color
+                    let color: u32 = opaque_color.unwrap();
+                    let color_x4: U32x4 = U32x4::splat(color);
+                    while x + 3 <= span_xmax {
+                        unsafe { color_x4.store_to_ptr(row_ptr.add(x as usize)) };
+                        x += 4;
+                    }
+                    while x <= span_xmax {
+                        unsafe { *row_ptr.add(x as usize) = color };
+                        x += 1;
                     }
+                    continue;
                 }
-            } else {
-                // The triangle spans 2x2 or more tiles, bin in the appropriate tiles, but only after running simple edge functions check
-                let iv0_x_24_8 = (v0.position.x * 256.0).round() as i32;
-                let iv0_y_24_8 = (v0.position.y * 256.0).round() as i32;
-                let iv1_x_24_8 = (v1.position.x * 256.0).round() as i32;
-                let iv1_y_24_8 = (v1.position.y * 256.0).round() as i32;
-                let iv2_x_24_8 = (v2.position.x * 256.0).round() as i32;
-                let iv2_y_24_8 = (v2.position.y * 256.0).round() as i32;
-                let iv01_x_24_8 = iv1_x_24_8 - iv0_x_24_8;
-                let iv01_y_24_8 = iv1_y_24_8 - iv0_y_24_8;
-                let iv12_x_24_8 = iv2_x_24_8 - iv1_x_24_8;
-                let iv12_y_24_8 = iv2_y_24_8 - iv1_y_24_8;
-                let iv20_x_24_8 = iv0_x_24_8 - iv2_x_24_8;
-                let iv20_y_24_8 = iv0_y_24_8 - iv2_y_24_8;
-                let is_tile_fully_outside = |tile_bounds: TileBinningBounds| {
-                    let iv1_xmin_24_8 = tile_bounds.xmin_24_8 - iv1_x_24_8;
-                    let iv1_ymin_24_8 = tile_bounds.ymin_24_8 - iv1_y_24_8;
-                    let iv1_xmax_24_8 = tile_bounds.xmax_24_8 - iv1_x_24_8;
-                    let iv1_ymax_24_8 = tile_bounds.ymax_24_8 - iv1_y_24_8;
-                    let iv2_xmin_24_8 = tile_bounds.xmin_24_8 - iv2_x_24_8;
-                    let iv2_ymin_24_8 = tile_bounds.ymin_24_8 - iv2_y_24_8;
-                    let iv2_xmax_24_8 = tile_bounds.xmax_24_8 - iv2_x_24_8;
-                    let iv2_ymax_24_8 = tile_bounds.ymax_24_8 - iv2_y_24_8;
-                    let iv0_xmin_24_8 = tile_bounds.xmin_24_8 - iv0_x_24_8;
-                    let iv0_ymin_24_8 = tile_bounds.ymin_24_8 - iv0_y_24_8;
-                    let iv0_xmax_24_8 = tile_bounds.xmax_24_8 - iv0_x_24_8;
-                    let iv0_ymax_24_8 = tile_bounds.ymax_24_8 - iv0_y_24_8;
-                    let e0_lb = iv12_x_24_8 as i64 * iv1_ymin_24_8 as i64 - iv12_y_24_8 as i64 * iv1_xmin_24_8 as i64;
-                    let e0_rb = iv12_x_24_8 as i64 * iv1_ymin_24_8 as i64 - iv12_y_24_8 as i64 * iv1_xmax_24_8 as i64;
-                    let e0_lt = iv12_x_24_8 as i64 * iv1_ymax_24_8 as i64 - iv12_y_24_8 as i64 * iv1_xmin_24_8 as i64;
-                    let e0_rt = iv12_x_24_8 as i64 * iv1_ymax_24_8 as i64 - iv12_y_24_8 as i64 * iv1_xmax_24_8 as i64;
-                    let e1_lb = iv20_x_24_8 as i64 * iv2_ymin_24_8 as i64 - iv20_y_24_8 as i64 * iv2_xmin_24_8 as i64;
-                    let e1_rb = iv20_x_24_8 as i64 * iv2_ymin_24_8 as i64 - iv20_y_24_8 as i64 * iv2_xmax_24_8 as i64;
-                    let e1_lt = iv20_x_24_8 as i64 * iv2_ymax_24_8 as i64 - iv20_y_24_8 as i64 * iv2_xmin_24_8 as i64;
-                    let e1_rt = iv20_x_24_8 as i64 * iv2_ymax_24_8 as i64 - iv20_y_24_8 as i64 * iv2_xmax_24_8 as i64;
-                    let e2_lb = iv01_x_24_8 as i64 * iv0_ymin_24_8 as i64 - iv01_y_24_8 as i64 * iv0_xmin_24_8 as i64;
-                    let e2_rb = iv01_x_24_8 as i64 * iv0_ymin_24_8 as i64 - iv01_y_24_8 as i64 * iv0_xmax_24_8 as i64;
-                    let e2_lt = iv01_x_24_8 as i64 * iv0_ymax_24_8 as i64 - iv01_y_24_8 as i64 * iv0_xmin_24_8 as i64;
-                    let e2_rt = iv01_x_24_8 as i64 * iv0_ymax_24_8 as i64 - iv01_y_24_8 as i64 * iv0_xmax_24_8 as i64;
-                    (e0_lb < 0 && e0_rb < 0 && e0_lt < 0 && e0_rt < 0)
-                        || (e1_lb < 0 && e1_rb < 0 && e1_lt < 0 && e1_rt < 0)
-                        || (e2_lb < 0 && e2_rb < 0 && e2_lt < 0 && e2_rt < 0)
-                };
 
-                for ind_y in ind_ymin..=ind_ymax {
-                    for ind_x in ind_xmin..=ind_xmax {
-                        let tile = &mut self.tiles[ind_y as usize * self.tiles_x as usize + ind_x as usize];
-                        if is_tile_fully_outside(tile.binning_bounds) {
-                            continue;
-                        }
-                        tile.triangles
-                            .push(ScheduledTriangle { cmd: scheduled_command_index, tri_start: vert_idx as u16 });
-                        self.stats.binned_triangles += 1;
+                let p: Vec2 = Vec2::new(origin_x + x as f32 + 0.5, origin_y + y as f32 + 0.5);
+                let (color, coverage): (Vec4, f32) = fill.shade(p, corner_radius);
+                if coverage > 0.0 {
+                    let pixel: &mut u32 = unsafe { &mut *row_ptr.add(x as usize) };
+                    Self::blend_covered_pixel(pixel, color, coverage, fill.alpha_blending, hdr);
+                    if cfg!(debug_assertions) {
+                        statistics.fragments_drawn += 1;
                     }
                 }
+                x += 1;
+            }
+            if cfg!(debug_assertions) && span_xmin <= span_xmax {
+                statistics.fragments_drawn += (span_xmax - span_xmin + 1) as usize;
             }
         }
+        statistics
     }
 
-    pub fn draw(&mut self, framebuffer: &mut Framebuffer) {
-        if self.vertices.is_empty() {
-            return;
-        }
-
-        if self.tiles_x > 1 || self.tiles_y > 1 {
-            // Draw tiles in parallel using rayon
-            let mut jobs = Vec::<TiledJob>::new();
-            for y in 0..self.tiles_y {
-                for x in 0..self.tiles_x {
-                    let idx = (y * self.tiles_x + x) as usize;
-                    if !self.tiles[idx].triangles.is_empty() {
-                        let render_tile: *const Tile = &mut self.tiles[idx];
-                        let framebuffer_tile = framebuffer.tile(x, y);
-                        jobs.push(TiledJob { framebuffer_tile, render_tile, statistics: PerTileStatistics::default() });
-                    }
-                }
-            }
-            // Order the tiles with the most triangles first
-            jobs.sort_by(|job1, job2| {
-                let tile1_triangles_len = unsafe { job1.render_tile.as_ref().unwrap_unchecked() }.triangles.len();
-                let tile2_triangles_len = unsafe { job2.render_tile.as_ref().unwrap_unchecked() }.triangles.len();
-                tile2_triangles_len.cmp(&tile1_triangles_len) // NB! This is the reverse order, because we want the most triangles first
-            });
-            use rayon::prelude::*;
-            jobs.par_iter_mut().for_each(|job| {
-                self.draw_tile(job);
-            });
-            for job in jobs {
-                self.stats.fragments_drawn += job.statistics.fragments_drawn;
+    // Blends a non-premultiplied color into the pixel of a screen-space fill partially covered by it.
+    // With PremultipliedNormal the color is taken as already premultiplied by alpha.
+    #[inline(always)]
+    fn blend_covered_pixel(pixel: &mut u32, color: Vec4, coverage: f32, alpha_blending: AlphaBlendingMode, hdr: bool) {
+        // Without blending, the coverage acts as the opacity of the color
+        let src: Vec4 = match alpha_blending {
+            AlphaBlendingMode::None => Vec4::new(color.x * coverage, color.y * coverage, color.z * coverage, coverage),
+            AlphaBlendingMode::PremultipliedNormal => color * coverage,
+            _ => {
+                let a: f32 = color.w * coverage;
+                Vec4::new(color.x * a, color.y * a, color.z * a, a)
             }
+        };
+        let mode: AlphaBlendingMode = match alpha_blending {
+            AlphaBlendingMode::None => AlphaBlendingMode::Normal,
+            mode => mode,
+        };
+        if hdr {
+            let blended: Vec3 = mode.blend(src, decode_rgb9e5(*pixel));
+            *pixel = encode_rgb9e5(blended.x, blended.y, blended.z);
         } else {
-            // Draw the single tile directly, don't bother with multithreading
-            let render_tile: *const Tile = &mut self.tiles[0];
-            let framebuffer_tile = framebuffer.tile(0, 0);
-            let mut job = TiledJob { framebuffer_tile, render_tile, statistics: PerTileStatistics::default() };
-            self.draw_tile(&mut job);
-            self.stats.fragments_drawn += job.statistics.fragments_drawn;
+            let dest: RGBA = RGBA::from_u32(*pixel);
+            let dest: Vec3 = Vec3::new(dest.r as f32, dest.g as f32, dest.b as f32) * (1.0 / 255.0);
+            let blended: Vec3 = mode.blend(src, dest);
+            let quantize = |c: f32| -> u8 { (c * 255.0 + 0.5).clamp(0.0, 255.0) as u8 };
+            *pixel = RGBA::new(quantize(blended.x), quantize(blended.y), quantize(blended.z), 255).to_u32();
         }
+    }
 
-        if self.draw_wireframe {
-            self.draw_wireframe(framebuffer);
+    // Draws the part of a screen-space convex polygon covered by the tile.
+    // For each row, the spans of the pixels touched by the polygon and of the pixels fully covered by it are found
+    // analytically from the edges. With an opaque color the fully covered span is filled 4 pixels at a time, the rest
+    // are blended with the framebuffer by their coverage, which is the distance to the nearest edge.
+    fn draw_polygon(
+        &self,
+        framebuffer: &mut FramebufferTile,
+        local_viewport: Viewport,
+        polygon: &ScheduledPolygon,
+        alpha_blending: AlphaBlendingMode,
+    ) -> PerTileStatistics {
+        let mut statistics = PerTileStatistics::default();
+        if framebuffer.color_buffer.is_none() {
+            return statistics;
         }
-    }
 
-    fn draw_tile(&self, job: &mut TiledJob) {
-        let render_tile = unsafe { &*job.render_tile };
-        if render_tile.triangles.is_empty() {
-            return;
+        // The polygon's bounds in the tile's pixels, expanded by a pixel for the anti-aliased edges
+        let origin_x: f32 = framebuffer.origin_x() as f32 - self.viewport.xmin as f32;
+        let origin_y: f32 = framebuffer.origin_y() as f32 - self.viewport.ymin as f32;
+        let rt_xmin = (max(local_viewport.xmin, framebuffer.origin_x()) - framebuffer.origin_x()) as i32;
+        let rt_xmax = (min(local_viewport.xmax, framebuffer.origin_x() + framebuffer.width())
+            - framebuffer.origin_x()
+            - 1) as i32;
+        let rt_ymin = (max(local_viewport.ymin, framebuffer.origin_y()) - framebuffer.origin_y()) as i32;
+        let rt_ymax = (min(local_viewport.ymax, framebuffer.origin_y() + framebuffer.height())
+            - framebuffer.origin_y()
+            - 1) as i32;
+        let xmin: i32 = rt_xmin.max((polygon.min.x - origin_x).floor() as i32 - 1);
+        let xmax: i32 = rt_xmax.min((polygon.max.x - origin_x).ceil() as i32);
+        let ymin: i32 = rt_ymin.max((polygon.min.y - origin_y).floor() as i32 - 1);
+        let ymax: i32 = rt_ymax.min((polygon.max.y - origin_y).ceil() as i32);
+        if xmin > xmax || ymin > ymax {
+            return statistics;
         }
 
-        let viewport = render_tile.local_viewport;
-        let vertices = &self.vertices;
+        let edges: &[Vec3] = &self.polygon_edges
+            [polygon.edges_start as usize..polygon.edges_start as usize + polygon.edges_count as usize];
+        let color: Vec4 = polygon.color;
+        let hdr: bool = framebuffer.color_format == ColorBufferFormat::Rgb9e5;
+        let opaque_color: Option<u32> = if alpha_blending != AlphaBlendingMode::None {
+            None
+        } else if hdr {
+            Some(encode_rgb9e5(color.x, color.y, color.z))
+        } else {
+            Some(
+                RGBA::new(
+                    (color.x * 255.0 + 0.5).clamp(0.0, 255.0) as u8,
+                    (color.y * 255.0 + 0.5).clamp(0.0, 255.0) as u8,
+                    (color.z * 255.0 + 0.5).clamp(0.0, 255.0) as u8,
+                    255,
+                )
+                .to_u32(),
+            )
+        };
 
-        let mut tile_verts = ArrayVec::<Vertex, 384>::new(); // up to 128 triangles
-        let mut cmd_idx = render_tile.triangles.first().unwrap().cmd;
+        // The distance to the edges at which the pixel centers are touched and fully covered by the polygon.
+        // Without anti-aliasing, a pixel is either fully covered when its center is inside or not covered at all.
+        let (outer_distance, inner_distance): (f32, f32) = if polygon.antialiased { (-0.5, 0.5) } else { (0.0, 0.0) };
+
+        // Finds the span of pixels in the row whose centers are at least at the distance from every edge
+        let span = |py: f32, distance: f32| -> (i32, i32) {
+            let mut lo: f32 = xmin as f32;
+            let mut hi: f32 = xmax as f32;
+            for edge in edges {
+                // edge.x * (origin_x + x + 0.5) + edge.y * py + edge.z >= distance
+                let rest: f32 = distance - edge.y * py - edge.z;
+                if edge.x > 0.00001 {
+                    lo = lo.max(rest / edge.x - origin_x - 0.5);
+                } else if edge.x < -0.00001 {
+                    hi = hi.min(rest / edge.x - origin_x - 0.5);
+                } else if rest > 0.0 {
+                    return (xmax + 1, xmax);
+                }
+            }
+            if lo > hi {
+                return (xmax + 1, xmax);
+            }
+            (lo.ceil() as i32, hi.floor() as i32)
+        };
 
-        for tri in &render_tile.triangles {
-            if tile_verts.is_full() || tri.cmd != cmd_idx {
-                let call_stats = self.draw_triangles_dispatch(
-                    &mut job.framebuffer_tile,
-                    viewport,
-                    &tile_verts,
-                    &self.commands[cmd_idx as usize],
-                );
-                job.statistics = job.statistics + call_stats;
-                tile_verts.clear();
-                cmd_idx = tri.cmd;
+        let color_buffer_ptr: *mut u32 = unsafe { framebuffer.color_buffer.as_mut().unwrap_unchecked().ptr };
+        for y in ymin..=ymax {
+            let row_ptr: *mut u32 = unsafe { color_buffer_ptr.add((y * Framebuffer::TILE_WITH as i32) as usize) };
+            let py: f32 = origin_y + y as f32 + 0.5;
+            let (outer_xmin, outer_xmax): (i32, i32) = span(py, outer_distance);
+            let (span_xmin, span_xmax): (i32, i32) = if opaque_color.is_some() {
+                span(py, inner_distance)
+            } else {
+                (xmax + 1, xmax)
+            };
+            let mut x: i32 = outer_xmin;
+            while x <= outer_xmax {
+                if x == span_xmin && span_xmin <= span_xmax {
+                    // Fill the fully covered span of an opaque color
+                    let color: u32 = opaque_color.unwrap();
+                    let color_x4: U32x4 = U32x4::splat(color);
+                    while x + 3 <= span_xmax {
+                        unsafe { color_x4.store_to_ptr(row_ptr.add(x as usize)) };
+                        x += 4;
+                    }
+                    while x <= span_xmax {
+                        unsafe { *row_ptr.add(x as usize) = color };
+                        x += 1;
+                    }
+                    continue;
+                }
+
+                let coverage: f32 = if polygon.antialiased {
+                    let p: Vec2 = Vec2::new(origin_x + x as f32 + 0.5, py);
+                    (polygon_distance(edges, p) + 0.5).clamp(0.0, 1.0)
+                } else {
+                    1.0
+                };
+                if coverage > 0.0 {
+                    let pixel: &mut u32 = unsafe { &mut *row_ptr.add(x as usize) };
+                    Self::blend_covered_pixel(pixel, color, coverage, alpha_blending, hdr);
+                    if cfg!(debug_assertions) {
+                        statistics.fragments_drawn += 1;
+                    }
+                }
+                x += 1;
             }
+            if cfg!(debug_assertions) && span_xmin <= span_xmax {
+                statistics.fragments_drawn += (span_xmax - span_xmin + 1) as usize;
+            }
+        }
+        statistics
+    }
+
+    // Draws the part of a screen-space stroke covered by the tile.
+    // The stroke covers the union of its convex shapes, so each pixel takes the coverage of the shape covering it the
+    // most. Only the shapes overlapping the tile are considered.
+    fn draw_stroke(
+        &self,
+        framebuffer: &mut FramebufferTile,
+        local_viewport: Viewport,
+        stroke: &ScheduledStroke,
+        alpha_blending: AlphaBlendingMode,
+    ) -> PerTileStatistics {
+        let mut statistics = PerTileStatistics::default();
+        if framebuffer.color_buffer.is_none() {
+            return statistics;
+        }
 
-            tile_verts.push(vertices[tri.tri_start as usize + 0]);
-            tile_verts.push(vertices[tri.tri_start as usize + 1]);
-            tile_verts.push(vertices[tri.tri_start as usize + 2]);
+        // The stroke's bounds in the tile's pixels, expanded by a pixel for the anti-aliased edges
+        let origin_x: f32 = framebuffer.origin_x() as f32 - self.viewport.xmin as f32;
+        let origin_y: f32 = framebuffer.origin_y() as f32 - self.viewport.ymin as f32;
+        let rt_xmin = (max(local_viewport.xmin, framebuffer.origin_x()) - framebuffer.origin_x()) as i32;
+        let rt_xmax = (min(local_viewport.xmax, framebuffer.origin_x() + framebuffer.width())
+            - framebuffer.origin_x()
+            - 1) as i32;
+        let rt_ymin = (max(local_viewport.ymin, framebuffer.origin_y()) - framebuffer.origin_y()) as i32;
+        let rt_ymax = (min(local_viewport.ymax, framebuffer.origin_y() + framebuffer.height())
+            - framebuffer.origin_y()
+            - 1) as i32;
+        let xmin: i32 = rt_xmin.max((stroke.min.x - origin_x).floor() as i32 - 1);
+        let xmax: i32 = rt_xmax.min((stroke.max.x - origin_x).ceil() as i32);
+        let ymin: i32 = rt_ymin.max((stroke.min.y - origin_y).floor() as i32 - 1);
+        let ymax: i32 = rt_ymax.min((stroke.max.y - origin_y).ceil() as i32);
+        if xmin > xmax || ymin > ymax {
+            return statistics;
         }
 
-        if !tile_verts.is_empty() {
-            let call_stats = self.draw_triangles_dispatch(
-                &mut job.framebuffer_tile,
-                viewport,
-                &tile_verts,
-                &self.commands[cmd_idx as usize],
-            );
-            job.statistics = job.statistics + call_stats;
+        let tile_min: Vec2 = Vec2::new(origin_x + xmin as f32 - 1.0, origin_y + ymin as f32 - 1.0);
+        let tile_max: Vec2 = Vec2::new(origin_x + xmax as f32 + 2.0, origin_y + ymax as f32 + 2.0);
+        let shapes: Vec<StrokeShape> = self.stroke_shapes
+            [stroke.shapes_start as usize..stroke.shapes_start as usize + stroke.shapes_count as usize]
+            .iter()
+            .filter(|shape| {
+                let (min, max): (Vec2, Vec2) = shape.bounds();
+                min.x <= tile_max.x && min.y <= tile_max.y && max.x >= tile_min.x && max.y >= tile_min.y
+            })
+            .copied()
+            .collect();
+        if shapes.is_empty() {
+            return statistics;
         }
-    }
-
-    // fn idx_to_color_hash(mut x: u32) -> u32 {
-    //     // Mix the bits using a few bitwise operations and multiplications
-    //     x ^= x >> 16;
-    //     x = x.wrapping_mul(0x85ebca6b);
-    //     x ^= x >> 13;
-    //     x = x.wrapping_mul(0xc2b2ae35);
-    //     x ^= x >> 16;
-    //
-    //     x | 0xFF
-    // }
 
-    fn encode_normal_as_u32(nx: f32, ny: f32, nz: f32) -> u32 {
-        unsafe {
-            let x8: u8 = (nx * 127.5 + 127.5).to_int_unchecked();
-            let y8: u8 = (ny * 127.5 + 127.5).to_int_unchecked();
-            let z8: u8 = (nz * 127.5 + 127.5).to_int_unchecked();
-            (x8 as u32) | ((y8 as u32) << 8) | ((z8 as u32) << 16)
+        // The distance at which a pixel is fully covered, no need to look any further
+        let full_coverage_distance: f32 = if stroke.antialiased { 0.5 } else { 0.0 };
+        let hdr: bool = framebuffer.color_format == ColorBufferFormat::Rgb9e5;
+        let edges: &[Vec3] = &self.polygon_edges;
+        let color_buffer_ptr: *mut u32 = unsafe { framebuffer.color_buffer.as_mut().unwrap_unchecked().ptr };
+        for y in ymin..=ymax {
+            let row_ptr: *mut u32 = unsafe { color_buffer_ptr.add((y * Framebuffer::TILE_WITH as i32) as usize) };
+            for x in xmin..=xmax {
+                let p: Vec2 = Vec2::new(origin_x + x as f32 + 0.5, origin_y + y as f32 + 0.5);
+                let mut distance: f32 = f32::MIN;
+                for shape in &shapes {
+                    distance = distance.max(shape.distance(edges, p));
+                    if distance >= full_coverage_distance {
+                        break;
+                    }
+                }
+                let coverage: f32 = if stroke.antialiased {
+                    (distance + 0.5).clamp(0.0, 1.0)
+                } else if distance >= 0.0 {
+                    1.0
+                } else {
+                    0.0
+                };
+                if coverage > 0.0 {
+                    let pixel: &mut u32 = unsafe { &mut *row_ptr.add(x as usize) };
+                    Self::blend_covered_pixel(pixel, stroke.color, coverage, alpha_blending, hdr);
+                    if cfg!(debug_assertions) {
+                        statistics.fragments_drawn += 1;
+                    }
+                }
+            }
         }
+        statistics
     }
 
-    fn is_top_left_24_8(edge_x: i32, edge_y: i32) -> bool {
-        (edge_y < 0) || // left edge
-            (edge_y == 0 && edge_x > 0) // top edge
-        // NB!
-        // This says "an edge that is exactly horizontal", but perhaps some epsilon is still needed...
-        // https://learn.microsoft.com/en-us/windows/win32/direct3d11/d3d10-graphics-programming-guide-rasterizer-stage-rules
-    }
-
-    fn draw_triangles_dispatch(
+    // A specialized version of draw_triangles() for opaque, untextured, non-depth-tested triangles drawn into the color
+    // buffer only. The covered span of each row is found directly from the edge functions and is filled 4 pixels at a
+    // time. Per-vertex colors are interpolated in fixed-point, which requires an affine mapping - triangles with
+    // varying w fall back to the generic path.
+    fn draw_opaque_fills<const COLOR_INTERPOLATION_MODE: u8>(
         &self,
         framebuffer: &mut FramebufferTile,
         local_viewport: Viewport,
         vertices: &[Vertex],
         command: &ScheduledCommand,
     ) -> PerTileStatistics {
-        let has_color: bool = framebuffer.color_buffer.is_some();
-        let has_depth: bool = framebuffer.depth_buffer.is_some();
-        let has_normal_buffer: bool = framebuffer.normal_buffer.is_some();
-        let has_texture: bool = command.texture.is_some();
-        let has_normal_map: bool = command.normal_map.is_some();
-        let alpha_blending_mode: u8 = command.alpha_blending as u8;
-        let normal_processing_mode: u8 = if has_normal_buffer {
-            if has_normal_map && has_texture {
-                NormalsProcessingMode::NormalMapping as u8
+        assert!(local_viewport.xmin >= framebuffer.origin_x());
+        assert!(local_viewport.xmax >= framebuffer.origin_x());
+        assert!(local_viewport.ymin >= framebuffer.origin_y());
+        assert!(local_viewport.ymax >= framebuffer.origin_y());
+        debug_assert!(framebuffer.color_buffer.is_some());
+        debug_assert!(framebuffer.depth_buffer.is_none());
+        debug_assert!(framebuffer.normal_buffer.is_none());
+        let mut statistics = PerTileStatistics::default();
+        let triangles_num = vertices.len() / 3;
+        if triangles_num == 0 {
+            return statistics;
+        }
+
+        let tile_origin = Vec2::new(framebuffer.origin_x() as f32, framebuffer.origin_y() as f32);
+        let tile_origin_x_24_8: i32 = framebuffer.origin_x() as i32 * 256;
+        let tile_origin_y_24_8: i32 = framebuffer.origin_y() as i32 * 256;
+
+        let rt_xmin = (max(local_viewport.xmin, framebuffer.origin_x()) - framebuffer.origin_x()) as i32;
+        let rt_xmax = (min(local_viewport.xmax, framebuffer.origin_x() + framebuffer.width())
+            - framebuffer.origin_x()
+            - 1) as i32;
+        let rt_ymin = (max(local_viewport.ymin, framebuffer.origin_y()) - framebuffer.origin_y()) as i32;
+        let rt_ymax = (min(local_viewport.ymax, framebuffer.origin_y() + framebuffer.height())
+            - framebuffer.origin_y()
+            - 1) as i32;
+
+        let color_buffer_ptr: *mut u32 = unsafe { framebuffer.color_buffer.as_mut().unwrap_unchecked().ptr };
+        let channel_max: U32x4 = U32x4::splat(256);
+        let channel_min: U32x4 = U32x4::splat(0);
+        let opaque_alpha: U32x4 = U32x4::splat(0xFF000000);
+
+        for i in 0..triangles_num {
+            let triangle: &[Vertex] = &vertices[i * 3..i * 3 + 3];
+            let v0 = &triangle[0];
+            let v1 = &triangle[1];
+            let v2 = &triangle[2];
+
+            // Fixed-point colors are interpolated affinely, which is exact only if w is the same across the triangle,
+            // e.g. under an orthographic projection. The fast math mode relaxes this the same way as the generic path.
+            if COLOR_INTERPOLATION_MODE == VerticesColorInterpolationMode::PerVertex as u8 {
+                let inv_w_lo: f32 = v0.position.w.min(v1.position.w).min(v2.position.w);
+                let inv_w_hi: f32 = v0.position.w.max(v1.position.w).max(v2.position.w);
+                let affine: bool = inv_w_lo == inv_w_hi
+                    || (command.fast_math && inv_w_hi <= inv_w_lo * (1.0 + self.fast_math_w_threshold));
+                if !affine {
+                    statistics = statistics
+                        + Self::draw_triangles::<
+                            true,
+                            false,
+                            { DepthProcessingMode::Default as u8 },
+                            { NormalsProcessingMode::None as u8 },
+                            false,
+                            { AlphaBlendingMode::None as u8 },
+                            false,
+                            COLOR_INTERPOLATION_MODE,
+                        >(self, framebuffer, local_viewport, triangle, command);
+                    continue;
+                }
+            }
+
+            // Calculate the triangle's vertice positions relative to the tile origin, as 24.8
+            let v0_x_24_8: i32 = (v0.position.x * 256.0).round() as i32 - tile_origin_x_24_8;
+            let v0_y_24_8: i32 = (v0.position.y * 256.0).round() as i32 - tile_origin_y_24_8;
+            let v1_x_24_8: i32 = (v1.position.x * 256.0).round() as i32 - tile_origin_x_24_8;
+            let v1_y_24_8: i32 = (v1.position.y * 256.0).round() as i32 - tile_origin_y_24_8;
+            let v2_x_24_8: i32 = (v2.position.x * 256.0).round() as i32 - tile_origin_x_24_8;
+            let v2_y_24_8: i32 = (v2.position.y * 256.0).round() as i32 - tile_origin_y_24_8;
+            let v01_x_24_8: i32 = v1_x_24_8 - v0_x_24_8;
+            let v01_y_24_8: i32 = v1_y_24_8 - v0_y_24_8;
+            let v12_x_24_8: i32 = v2_x_24_8 - v1_x_24_8;
+            let v12_y_24_8: i32 = v2_y_24_8 - v1_y_24_8;
+            let v20_x_24_8: i32 = v0_x_24_8 - v2_x_24_8;
+            let v20_y_24_8: i32 = v0_y_24_8 - v2_y_24_8;
+
+            // Calculate the doubled triangle's area, the same way as the generic path does to cull the same triangles
+            let v0_xy = v0.position.xy() - tile_origin;
+            let v1_xy = v1.position.xy() - tile_origin;
+            let v2_xy = v2.position.xy() - tile_origin;
+            let v01 = v1_xy - v0_xy;
+            let v02 = v2_xy - v0_xy;
+            let area_x_2: f32 = v01.x * v02.y - v01.y * v02.x;
+            if area_x_2 < 1.0 {
+                continue;
+            }
+
+            // Set up the edge function biases to follow the top-left fill rule
+            let v01_bias_x24_8: i32 = if Self::is_top_left_24_8(v01_x_24_8, v01_y_24_8) {
+                0
             } else {
-                NormalsProcessingMode::Vertex as u8
+                -1
+            };
+            let v12_bias_x24_8: i32 = if Self::is_top_left_24_8(v12_x_24_8, v12_y_24_8) {
+                0
+            } else {
+                -1
+            };
+            let v20_bias_x24_8: i32 = if Self::is_top_left_24_8(v20_x_24_8, v20_y_24_8) {
+                0
+            } else {
+                -1
+            };
+
+            let xmin = rt_xmin.max(v0_xy.x.min(v1_xy.x).min(v2_xy.x) as i32);
+            let xmax = rt_xmax.min(v0_xy.x.max(v1_xy.x).max(v2_xy.x) as i32);
+            let ymin = rt_ymin.max(v0_xy.y.min(v1_xy.y).min(v2_xy.y) as i32);
+            let ymax = rt_ymax.min(v0_xy.y.max(v1_xy.y).max(v2_xy.y) as i32);
+            if xmin > xmax || ymin > ymax {
+                continue;
             }
-        } else {
-            NormalsProcessingMode::None as u8
-        };
-        let alpha_test_enabled: bool = command.alpha_test > 0u8;
-        let color_interpolation_mode: u8 = command.color_interpolation as u8;
 
-        let mut idx = 0;
-        idx += has_color as usize;
-        idx *= 2; // two options for depth
-        idx += has_depth as usize;
-        idx *= 3; // three options for normals processing
-        idx += normal_processing_mode as usize;
-        idx *= 2; // two options for texture
-        idx += has_texture as usize;
-        idx *= 3; // three options for alpha blending
-        idx += alpha_blending_mode as usize;
-        idx *= 2; // two options for alpha test
-        idx += alpha_test_enabled as usize;
-        idx *= 3; // three options for color interpolation
-        idx += color_interpolation_mode as usize;
-        DRAW_TRIANGLE_FUNCTIONS[idx](self, framebuffer, local_viewport, vertices, command)
+            // Precompute edge functions start values and increments as 24.8
+            let p_min_x_24_8: i32 = xmin * 256 + 128;
+            let p_min_y_24_8: i32 = ymin * 256 + 128;
+            let edge_min = |ex: i32, ey: i32, vx: i32, vy: i32, bias: i32| -> i32 {
+                ((ex as i64 * (p_min_y_24_8 - vy) as i64 - ey as i64 * (p_min_x_24_8 - vx) as i64) / 256) as i32 + bias
+            };
+            let edges_min: [i32; 3] = [
+                edge_min(v12_x_24_8, v12_y_24_8, v1_x_24_8, v1_y_24_8, v12_bias_x24_8),
+                edge_min(v20_x_24_8, v20_y_24_8, v2_x_24_8, v2_y_24_8, v20_bias_x24_8),
+                edge_min(v01_x_24_8, v01_y_24_8, v0_x_24_8, v0_y_24_8, v01_bias_x24_8),
+            ];
+            let edges_dx: [i32; 3] = [-v12_y_24_8, -v20_y_24_8, -v01_y_24_8];
+            let edges_dy: [i32; 3] = [v12_x_24_8, v20_x_24_8, v01_x_24_8];
+
+            // Precompute the color: either a constant one or fixed-point 8.16 per-vertex colors with 256 being 1.0.
+            // NB! Same as the generic path, the color is multiplied by 256 and then by 255 to use binary shifts.
+            let constant_color: u32 = if COLOR_INTERPOLATION_MODE == VerticesColorInterpolationMode::Fixed as u8 {
+                let to_u8 = |v: f32| ((((v * 256.0) as u32) * 255) >> 8) as u8;
+                RGBA::new(to_u8(v0.color.x), to_u8(v0.color.y), to_u8(v0.color.z), 255).to_u32()
+            } else {
+                RGBA::new(255, 255, 255, 255).to_u32()
+            };
+            let constant_color_x4: U32x4 = U32x4::splat(constant_color);
+            let inv_area_x_2: f32 = 1.0 / area_x_2;
+            let p_min = Vec2::new(xmin as f32 + 0.5, ymin as f32 + 0.5);
+            let edge_min_v3 = Vec3::new(
+                (v2_xy.x - v1_xy.x) * (p_min.y - v1_xy.y) - (v2_xy.y - v1_xy.y) * (p_min.x - v1_xy.x),
+                (v0_xy.x - v2_xy.x) * (p_min.y - v2_xy.y) - (v0_xy.y - v2_xy.y) * (p_min.x - v2_xy.x),
+                (v1_xy.x - v0_xy.x) * (p_min.y - v0_xy.y) - (v1_xy.y - v0_xy.y) * (p_min.x - v0_xy.x),
+            );
+            let edge_dx_v3 = Vec3::new(v1_xy.y - v2_xy.y, v2_xy.y - v0_xy.y, v0_xy.y - v1_xy.y);
+            let edge_dy_v3 = Vec3::new(v2_xy.x - v1_xy.x, v0_xy.x - v2_xy.x, v1_xy.x - v0_xy.x);
+            let to_fixed_point = |v: f32| (v * inv_area_x_2 * 16777216.0) as i32;
+            let fixed_point_channel = |c: Vec3| -> (i32, i32, i32) {
+                (
+                    to_fixed_point(dot(edge_min_v3, c)),
+                    to_fixed_point(dot(edge_dx_v3, c)),
+                    to_fixed_point(dot(edge_dy_v3, c)),
+                )
+            };
+            let (r_fx_min, r_fx_dx, r_fx_dy) = fixed_point_channel(Vec3::new(v0.color.x, v1.color.x, v2.color.x));
+            let (g_fx_min, g_fx_dx, g_fx_dy) = fixed_point_channel(Vec3::new(v0.color.y, v1.color.y, v2.color.y));
+            let (b_fx_min, b_fx_dx, b_fx_dy) = fixed_point_channel(Vec3::new(v0.color.z, v1.color.z, v2.color.z));
+
+            // The fixed-point colors of a group of 4 consequent pixels and their increments between the groups
+            let lanes = |start: i32, dx: i32| -> U32x4 {
+                U32x4::load([start, start + dx, start + dx * 2, start + dx * 3].map(|v| v as u32))
+            };
+            let r_fx_step: U32x4 = U32x4::splat((r_fx_dx * 4) as u32);
+            let g_fx_step: U32x4 = U32x4::splat((g_fx_dx * 4) as u32);
+            let b_fx_step: U32x4 = U32x4::splat((b_fx_dx * 4) as u32);
+
+            let mut edges_row: [i32; 3] = edges_min;
+            let mut r_fx_row: i32 = r_fx_min;
+            let mut g_fx_row: i32 = g_fx_min;
+            let mut b_fx_row: i32 = b_fx_min;
+            let row_steps: i32 = xmax - xmin + 1;
+            for y in ymin..=ymax {
+                // Find the span [first, last) of the pixels where all 3 edge functions are non-negative
+                let mut first: i32 = 0;
+                let mut last: i32 = row_steps;
+                for e in 0..3 {
+                    let value: i32 = edges_row[e];
+                    let dx: i32 = edges_dx[e];
+                    if dx > 0 {
+                        if value < 0 {
+                            first = first.max((-value + dx - 1) / dx);
+                        }
+                    } else if dx < 0 {
+                        last = last.min(if value < 0 { 0 } else { value / -dx + 1 });
+                    } else if value < 0 {
+                        last = 0;
+                    }
+                }
+
+                if first < last {
+                    let mut color_ptr: *mut u32 =
+                        unsafe { color_buffer_ptr.add((y * Framebuffer::TILE_WITH as i32 + xmin + first) as usize) };
+                    let mut left: i32 = last - first;
+                    if COLOR_INTERPOLATION_MODE == VerticesColorInterpolationMode::PerVertex as u8 {
+                        let mut r_fx: U32x4 = lanes(r_fx_row + r_fx_dx * first, r_fx_dx);
+                        let mut g_fx: U32x4 = lanes(g_fx_row + g_fx_dx * first, g_fx_dx);
+                        let mut b_fx: U32x4 = lanes(b_fx_row + b_fx_dx * first, b_fx_dx);
+                        while left > 0 {
+                            // Convert the fixed-point colors into 0..=255: (clamp(c >> 16, 0, 256) * 255) >> 8
+                            let to_u8 = |c: U32x4| -> U32x4 {
+                                let c: U32x4 = c.shr_signed::<16>().clamp_signed(channel_min, channel_max);
+                                (c.shl::<8>() - c).shr_signed::<8>()
+                            };
+                            let colors: U32x4 =
+                                to_u8(r_fx) | to_u8(g_fx).shl::<8>() | to_u8(b_fx).shl::<16>() | opaque_alpha;
+                            if left >= 4 {
+                                unsafe { colors.store_to_ptr(color_ptr) };
+                            } else {
+                                let colors: [u32; 4] = colors.store();
+                                for (j, color) in colors.iter().take(left as usize).enumerate() {
+                                    unsafe { *color_ptr.add(j) = *color };
+                                }
+                            }
+                            r_fx += r_fx_step;
+                            g_fx += g_fx_step;
+                            b_fx += b_fx_step;
+                            color_ptr = unsafe { color_ptr.add(4) };
+                            left -= 4;
+                        }
+                    } else {
+                        while left >= 8 {
+                            unsafe {
+                                constant_color_x4.store_to_ptr(color_ptr);
+                                constant_color_x4.store_to_ptr(color_ptr.add(4));
+                                color_ptr = color_ptr.add(8);
+                            }
+                            left -= 8;
+                        }
+                        while left > 0 {
+                            unsafe {
+                                *color_ptr = constant_color;
+                                color_ptr = color_ptr.add(1);
+                            }
+                            left -= 1;
+                        }
+                    }
+                    if cfg!(debug_assertions) {
+                        statistics.fragments_drawn += (last - first) as usize;
+                    }
+                }
+
+                for e in 0..3 {
+                    edges_row[e] += edges_dy[e];
+                }
+                r_fx_row += r_fx_dy;
+                g_fx_row += g_fx_dy;
+                b_fx_row += b_fx_dy;
+            }
+        }
+        statistics
     }
 
     fn draw_triangles<
         const HAS_COLOR_BUFFER: bool,
         const HAS_DEPTH_BUFFER: bool,
+        const DEPTH_MODE: u8,
         const NORMALS_PROCESSING: u8,
         const HAS_TEXTURE: bool,
         const ALPHA_BLENDING: u8,
@@ -766,6 +4433,9 @@ impl Rasterizer {
             return statistics;
         }
 
+        // HDR color buffers keep the colors in floating point, unclamped
+        let hdr: bool = framebuffer.color_format == ColorBufferFormat::Rgb9e5;
+
         let tile_origin = Vec2::new(framebuffer.origin_x() as f32, framebuffer.origin_y() as f32);
         let tile_origin_x_24_8: i32 = framebuffer.origin_x() as i32 * 256;
         let tile_origin_y_24_8: i32 = framebuffer.origin_y() as i32 * 256;
@@ -780,6 +4450,39 @@ impl Rasterizer {
             - 1) as i32;
 
         let alpha_test_threshold: u8 = command.alpha_test;
+        let ignores_texture_alpha: bool = HAS_TEXTURE && command.alpha_source.ignores_texture_alpha();
+        let depth_clamp: bool = command.depth_clamp;
+        let depth_biased: bool = !command.depth_bias.is_zero();
+        let depth_test: DepthTest = command.depth_test;
+        let depth_write: bool = command.depth_write;
+        let color_write_mask: ColorWriteMask = command.color_write_mask;
+        let color_write_bits: u32 = color_write_mask.rgba8_bits();
+        let color_masked: bool = color_write_mask != ColorWriteMask::ALL;
+        let tint: ColorTint = command.tint;
+        let tinted: bool = !tint.is_identity();
+        // With alpha blending the fragments' colors are premultiplied
+        let tint_premultiplied: bool = command.alpha_blending != AlphaBlendingMode::None;
+        let fog: Option<Fog> = command.fog;
+        let reflection: Option<(&Reflection, EnvironmentSampler)> = command
+            .reflection
+            .as_ref()
+            .map(|reflection| (reflection, EnvironmentSampler::new(&reflection.environment)));
+        // The reflections need the normals and the world positions of the fragments
+        let reflective: bool = reflection.is_some();
+        // The motion of the fragments is the difference between their positions and the previous ones, which are found
+        // by projecting their world positions with the command's motion matrix
+        let writes_motion: bool = framebuffer.motion_buffer.is_some();
+        let motion: Option<Mat44> = if writes_motion { command.motion } else { None };
+        let interpolates_positions: bool = reflective || motion.is_some();
+        let viewport_scale: ViewportScale = self.viewport_scale;
+        let interpolates_normals: bool = NORMALS_PROCESSING >= NormalsProcessingMode::Vertex as u8 || reflective;
+        let pattern: Option<ProceduralPattern> = if HAS_TEXTURE { command.pattern } else { None };
+        let fragment_hook: Option<FragmentHook> = if HAS_TEXTURE { command.fragment_hook } else { None };
+        let has_albedo_texture: bool = HAS_TEXTURE && pattern.is_none() && command.texture.is_some();
+        let tile_origin_x: i32 = framebuffer.origin_x() as i32;
+        let tile_origin_y: i32 = framebuffer.origin_y() as i32;
+        let sdf: Option<SdfSampling> = if has_albedo_texture { command.sdf } else { None };
+        let lightmap: Option<&std::sync::Arc<Texture>> = if HAS_TEXTURE { command.lightmap.as_ref() } else { None };
         for i in 0..triangles_num {
             let v0 = &vertices[i * 3 + 0];
             let v1 = &vertices[i * 3 + 1];
@@ -814,38 +4517,106 @@ impl Rasterizer {
                 continue; // TODO: treat degenerate triangles separately
             }
 
+            // The anisotropic filter needs the derivatives of the texture coordinates, which are constant per triangle.
+            // It's not supported for texture regions, those fall back to the trilinear filter.
+            let anisotropic: bool =
+                command.sampling_filter == SamplerFilter::Anisotropic && command.texture_region.is_none();
+            let (uv_dx, uv_dy): (Vec2, Vec2) = if anisotropic {
+                Self::texture_derivatives(v0, v1, v2, v01, v02, area_x_2)
+            } else {
+                (Vec2::new(0.0, 0.0), Vec2::new(0.0, 0.0))
+            };
+
             // Set up the albedo texture sampler
-            let albedo_sampler: Sampler = if HAS_TEXTURE {
+            let albedo_sampler: Sampler = if has_albedo_texture && anisotropic {
                 let texture = command.texture.as_ref().unwrap();
-                let t01: Vec2 = v1.tex_coord - v0.tex_coord;
-                let t02: Vec2 = v2.tex_coord - v0.tex_coord;
-                let texel_area_x_2: f32 = (t01.x * t02.y - t02.x * t01.y).abs()
-                    * texture.mips[0].width as f32
-                    * texture.mips[0].height as f32;
-                let rho2: f32 = texel_area_x_2 / area_x_2;
-                let lod: f32 = 0.5 * rho2.log2();
-                Sampler::new(texture, command.sampling_filter, lod)
+                Sampler::new_anisotropic(texture, uv_dx, uv_dy, command.address_mode_u, command.address_mode_v)
+            } else if has_albedo_texture {
+                let texture = command.texture.as_ref().unwrap();
+                let lod: f32 = Self::texture_lod(texture, command.texture_region.as_ref(), v0, v1, v2, area_x_2);
+                match command.texture_region.as_ref() {
+                    Some(region) => Sampler::new_in_region(texture, command.sampling_filter, lod, region),
+                    None => Sampler::new_with_address_modes(
+                        texture,
+                        command.sampling_filter,
+                        lod,
+                        command.address_mode_u,
+                        command.address_mode_v,
+                    ),
+                }
             } else {
                 Sampler::default()
             };
-            let albedo_sampler_uv_scale: SamplerUVScale = albedo_sampler.uv_scale();
 
-            // Set up the normal map sampler
-            let normal_map_sampler: Sampler = if NORMALS_PROCESSING == NormalsProcessingMode::NormalMapping as u8 {
-                // TODO: check that the size of normal map [0] is the same as texture [0]?
-                // TODO: don't repeat the calculation and share the LOD somehow?
-                let texture = command.normal_map.as_ref().unwrap();
-                let t01: Vec2 = v1.tex_coord - v0.tex_coord;
-                let t02: Vec2 = v2.tex_coord - v0.tex_coord;
-                let texel_area_x_2: f32 = (t01.x * t02.y - t02.x * t01.y).abs()
-                    * texture.mips[0].width as f32
-                    * texture.mips[0].height as f32;
-                let rho2: f32 = texel_area_x_2 / area_x_2;
-                let lod: f32 = 0.5 * rho2.log2();
-                Sampler::new(texture, command.sampling_filter, lod)
+            // The distance field's smoothstep width follows the texels-per-pixel ratio of the triangle
+            let sdf_smoothing: f32 = match sdf.as_ref() {
+                Some(sdf) => {
+                    let texture = command.texture.as_ref().unwrap();
+                    sdf.smoothing_at_lod(Self::texture_lod(
+                        texture,
+                        command.texture_region.as_ref(),
+                        v0,
+                        v1,
+                        v2,
+                        area_x_2,
+                    ))
+                }
+                _ => 0.0,
+            };
+
+            // When sampling a texture region, the texture coordinates are interpolated as-is and are mapped onto the
+            // region per-fragment, otherwise they are prescaled for the sampler upfront. The pattern uses them as-is.
+            let has_texture_region: bool = command.texture_region.is_some() && has_albedo_texture;
+            let albedo_sampler_uv_scale: SamplerUVScale = if has_texture_region || pattern.is_some() {
+                SamplerUVScale::default()
             } else {
-                Sampler::default()
+                albedo_sampler.uv_scale()
+            };
+
+            // Set up the normal map sampler
+            let normal_map_sampler: Sampler =
+                if NORMALS_PROCESSING == NormalsProcessingMode::NormalMapping as u8 && anisotropic {
+                    let texture = command.normal_map.as_ref().unwrap();
+                    Sampler::new_anisotropic(texture, uv_dx, uv_dy, command.address_mode_u, command.address_mode_v)
+                } else if NORMALS_PROCESSING == NormalsProcessingMode::NormalMapping as u8 {
+                    // TODO: check that the size of normal map [0] is the same as texture [0]?
+                    // TODO: don't repeat the calculation and share the LOD somehow?
+                    let texture = command.normal_map.as_ref().unwrap();
+                    let lod: f32 = Self::texture_lod(texture, command.texture_region.as_ref(), v0, v1, v2, area_x_2);
+                    match command.texture_region.as_ref() {
+                        Some(region) => Sampler::new_in_region(texture, command.sampling_filter, lod, region),
+                        None => Sampler::new_with_address_modes(
+                            texture,
+                            command.sampling_filter,
+                            lod,
+                            command.address_mode_u,
+                            command.address_mode_v,
+                        ),
+                    }
+                } else {
+                    Sampler::default()
+                };
+
+            // Set up the lightmap sampler, its mip level follows the secondary texture coordinates
+            let lightmap_sampler: Sampler = match lightmap {
+                Some(texture) => {
+                    let filter: SamplerFilter = if command.sampling_filter == SamplerFilter::Anisotropic {
+                        SamplerFilter::Trilinear
+                    } else {
+                        command.sampling_filter
+                    };
+                    let lod: f32 = Self::lightmap_lod(texture, v0, v1, v2, area_x_2);
+                    Sampler::new_with_address_modes(
+                        texture,
+                        filter,
+                        lod,
+                        SamplerAddressMode::ClampToEdge,
+                        SamplerAddressMode::ClampToEdge,
+                    )
+                }
+                None => Sampler::default(),
             };
+            let lightmap_sampler_uv_scale: SamplerUVScale = lightmap_sampler.uv_scale();
 
             // Set up the edge function biases to follow the top-left fill rule
             let is_v01_top_left: bool = Self::is_top_left_24_8(v01_x_24_8, v01_y_24_8);
@@ -913,13 +4684,47 @@ impl Rasterizer {
             let z0 = (v0.position.z * 0.5 + 0.5) * 65535.0;
             let z1 = (v1.position.z * 0.5 + 0.5) * 65535.0;
             let z2 = (v2.position.z * 0.5 + 0.5) * 65535.0;
-            let z_f32_min = z0 * edge0_min / area_x_2 + z1 * edge1_min / area_x_2 + z2 * edge2_min / area_x_2;
             let z_f32_dx = (z0 * edge0_dx + z1 * edge1_dx + z2 * edge2_dx) / area_x_2;
             let z_f32_dy = (z0 * edge0_dy + z1 * edge1_dy + z2 * edge2_dy) / area_x_2;
+            let z_f32_bias: f32 = if depth_biased {
+                command.depth_bias.offset(z_f32_dx, z_f32_dy, 1.0)
+            } else {
+                0.0
+            };
+            let z_f32_min =
+                z0 * edge0_min / area_x_2 + z1 * edge1_min / area_x_2 + z2 * edge2_min / area_x_2 + z_f32_bias;
             let z_24_8_min = (z_f32_min * 256.0) as i32 as u32;
             let z_24x8_dx = (z_f32_dx * 256.0) as i32;
             let z_24x8_dy = (z_f32_dy * 256.0) as i32;
 
+            // The reverse-Z depth for the floating-point depth buffer is interpolated separately in floating-point
+            let (rz_min, rz_dx, rz_dy): (f32, f32, f32) = if DEPTH_MODE == DepthProcessingMode::Float as u8 {
+                let (rz0, rz1, rz2): (f32, f32, f32) = match command.reversed_depth {
+                    Some(reversed) => {
+                        (reversed.at(v0.position.w), reversed.at(v1.position.w), reversed.at(v2.position.w))
+                    }
+                    None => (
+                        encode_depth_f32(v0.position.z),
+                        encode_depth_f32(v1.position.z),
+                        encode_depth_f32(v2.position.z),
+                    ),
+                };
+                let rz_dx: f32 = (rz0 * edge0_dx + rz1 * edge1_dx + rz2 * edge2_dx) / area_x_2;
+                let rz_dy: f32 = (rz0 * edge0_dy + rz1 * edge1_dy + rz2 * edge2_dy) / area_x_2;
+                // The resolvable difference is the precision at the largest depth's exponent, the offset pushes away
+                // from the camera, i.e. towards zero
+                let rz_bias: f32 = if depth_biased {
+                    let rz_max: f32 = rz0.abs().max(rz1.abs()).max(rz2.abs());
+                    let r: f32 = f32::from_bits(rz_max.to_bits() & 0x7F80_0000) * f32::EPSILON;
+                    command.depth_bias.offset(rz_dx, rz_dy, r)
+                } else {
+                    0.0
+                };
+                ((rz0 * edge0_min + rz1 * edge1_min + rz2 * edge2_min) / area_x_2 - rz_bias, rz_dx, rz_dy)
+            } else {
+                (0.0, 0.0, 0.0)
+            };
+
             // Lane 0: depth iteration, 24.8 fixed-point
             // Lane 1: edge function v12, 24.8 fixed-point
             // Lane 2: edge function v20, 24.8 fixed-point
@@ -946,40 +4751,65 @@ impl Rasterizer {
             let edge_simd_non_negative_mask: U32x4 =
                 U32x4::load([0x00000000u32, 0x80000000u32, 0x80000000u32, 0x80000000u32]);
 
+            // In the fast math mode, check whether w is uniform enough across the triangle to interpolate affinely.
+            // If so, the 1/w weights are replaced with ones, which turns 1/w into a constant (the doubled area) and
+            // allows skipping the per-fragment division.
+            let affine: bool = command.fast_math && {
+                let inv_w_lo: f32 = v0.position.w.min(v1.position.w).min(v2.position.w);
+                let inv_w_hi: f32 = v0.position.w.max(v1.position.w).max(v2.position.w);
+                inv_w_hi <= inv_w_lo * (1.0 + self.fast_math_w_threshold)
+            };
+            let affine_inv_inv_w: f32 = 1.0 / area_x_2;
+            // The view-space depth of the affinely interpolated triangles is nearly uniform, it's taken at a vertex
+            let affine_view_depth: f32 = 1.0 / v0.position.w;
+            let w0: f32 = if affine { 1.0 } else { v0.position.w };
+            let w1: f32 = if affine { 1.0 } else { v1.position.w };
+            let w2: f32 = if affine { 1.0 } else { v2.position.w };
+
+            // With per-span perspective correction, w is recovered exactly only at the ends of each span and is
+            // linearly interpolated in between. The lowest valid 1/w bounds the span ends that fall outside of the
+            // triangle, where the interpolated 1/w can approach zero or even become negative.
+            let perspective_span: u32 = if affine { 1 } else { self.perspective_span };
+            let use_fast_reciprocal: bool = self.fast_reciprocal;
+            let span_inv_w_lo: f32 = area_x_2 * v0.position.w.min(v1.position.w).min(v2.position.w);
+
             // Express per-vertex edgefunctions, 1/w, colors/w and N/w as Vectors-3 to simplify the setup math
             let edge_min_v3 = Vec3::new(edge0_min, edge1_min, edge2_min);
             let edge_dx_v3 = Vec3::new(edge0_dx, edge1_dx, edge2_dx);
             let edge_dy_v3 = Vec3::new(edge0_dy, edge1_dy, edge2_dy);
-            let inv_w_v3 = Vec3::new(v0.position.w, v1.position.w, v2.position.w);
-            let r_over_w_v3 =
-                Vec3::new(v0.color.x * v0.position.w, v1.color.x * v1.position.w, v2.color.x * v2.position.w);
-            let g_over_w_v3 =
-                Vec3::new(v0.color.y * v0.position.w, v1.color.y * v1.position.w, v2.color.y * v2.position.w);
-            let b_over_w_v3 =
-                Vec3::new(v0.color.z * v0.position.w, v1.color.z * v1.position.w, v2.color.z * v2.position.w);
-            let a_over_w_v3 =
-                Vec3::new(v0.color.w * v0.position.w, v1.color.w * v1.position.w, v2.color.w * v2.position.w);
-            let nx_over_w_v3 =
-                Vec3::new(v0.normal.x * v0.position.w, v1.normal.x * v1.position.w, v2.normal.x * v2.position.w);
-            let ny_over_w_v3 =
-                Vec3::new(v0.normal.y * v0.position.w, v1.normal.y * v1.position.w, v2.normal.y * v2.position.w);
-            let nz_over_w_v3 =
-                Vec3::new(v0.normal.z * v0.position.w, v1.normal.z * v1.position.w, v2.normal.z * v2.position.w);
-            let tx_over_w_v3 =
-                Vec3::new(v0.tangent.x * v0.position.w, v1.tangent.x * v1.position.w, v2.tangent.x * v2.position.w);
-            let ty_over_w_v3 =
-                Vec3::new(v0.tangent.y * v0.position.w, v1.tangent.y * v1.position.w, v2.tangent.y * v2.position.w);
-            let tz_over_w_v3 =
-                Vec3::new(v0.tangent.z * v0.position.w, v1.tangent.z * v1.position.w, v2.tangent.z * v2.position.w);
+            let inv_w_v3 = Vec3::new(w0, w1, w2);
+            let r_over_w_v3 = Vec3::new(v0.color.x * w0, v1.color.x * w1, v2.color.x * w2);
+            let g_over_w_v3 = Vec3::new(v0.color.y * w0, v1.color.y * w1, v2.color.y * w2);
+            let b_over_w_v3 = Vec3::new(v0.color.z * w0, v1.color.z * w1, v2.color.z * w2);
+            let a_over_w_v3 = Vec3::new(v0.color.w * w0, v1.color.w * w1, v2.color.w * w2);
+            let nx_over_w_v3 = Vec3::new(v0.normal.x * w0, v1.normal.x * w1, v2.normal.x * w2);
+            let ny_over_w_v3 = Vec3::new(v0.normal.y * w0, v1.normal.y * w1, v2.normal.y * w2);
+            let nz_over_w_v3 = Vec3::new(v0.normal.z * w0, v1.normal.z * w1, v2.normal.z * w2);
+            let tx_over_w_v3 = Vec3::new(v0.tangent.x * w0, v1.tangent.x * w1, v2.tangent.x * w2);
+            let ty_over_w_v3 = Vec3::new(v0.tangent.y * w0, v1.tangent.y * w1, v2.tangent.y * w2);
+            let tz_over_w_v3 = Vec3::new(v0.tangent.z * w0, v1.tangent.z * w1, v2.tangent.z * w2);
+            let px_over_w_v3 = Vec3::new(v0.world_position.x * w0, v1.world_position.x * w1, v2.world_position.x * w2);
+            let py_over_w_v3 = Vec3::new(v0.world_position.y * w0, v1.world_position.y * w1, v2.world_position.y * w2);
+            let pz_over_w_v3 = Vec3::new(v0.world_position.z * w0, v1.world_position.z * w1, v2.world_position.z * w2);
             let u_over_w_v3 = Vec3::new(
-                (v0.tex_coord.x + albedo_sampler_uv_scale.bias) * albedo_sampler_uv_scale.scale * v0.position.w,
-                (v1.tex_coord.x + albedo_sampler_uv_scale.bias) * albedo_sampler_uv_scale.scale * v1.position.w,
-                (v2.tex_coord.x + albedo_sampler_uv_scale.bias) * albedo_sampler_uv_scale.scale * v2.position.w,
+                (v0.tex_coord.x + albedo_sampler_uv_scale.bias) * albedo_sampler_uv_scale.scale * w0,
+                (v1.tex_coord.x + albedo_sampler_uv_scale.bias) * albedo_sampler_uv_scale.scale * w1,
+                (v2.tex_coord.x + albedo_sampler_uv_scale.bias) * albedo_sampler_uv_scale.scale * w2,
             );
             let v_over_w_v3 = Vec3::new(
-                (v0.tex_coord.y + albedo_sampler_uv_scale.bias) * albedo_sampler_uv_scale.scale * v0.position.w,
-                (v1.tex_coord.y + albedo_sampler_uv_scale.bias) * albedo_sampler_uv_scale.scale * v1.position.w,
-                (v2.tex_coord.y + albedo_sampler_uv_scale.bias) * albedo_sampler_uv_scale.scale * v2.position.w,
+                (v0.tex_coord.y + albedo_sampler_uv_scale.bias) * albedo_sampler_uv_scale.scale * w0,
+                (v1.tex_coord.y + albedo_sampler_uv_scale.bias) * albedo_sampler_uv_scale.scale * w1,
+                (v2.tex_coord.y + albedo_sampler_uv_scale.bias) * albedo_sampler_uv_scale.scale * w2,
+            );
+            let u2_over_w_v3 = Vec3::new(
+                (v0.tex_coord2.x + lightmap_sampler_uv_scale.bias) * lightmap_sampler_uv_scale.scale * w0,
+                (v1.tex_coord2.x + lightmap_sampler_uv_scale.bias) * lightmap_sampler_uv_scale.scale * w1,
+                (v2.tex_coord2.x + lightmap_sampler_uv_scale.bias) * lightmap_sampler_uv_scale.scale * w2,
+            );
+            let v2_over_w_v3 = Vec3::new(
+                (v0.tex_coord2.y + lightmap_sampler_uv_scale.bias) * lightmap_sampler_uv_scale.scale * w0,
+                (v1.tex_coord2.y + lightmap_sampler_uv_scale.bias) * lightmap_sampler_uv_scale.scale * w1,
+                (v2.tex_coord2.y + lightmap_sampler_uv_scale.bias) * lightmap_sampler_uv_scale.scale * w2,
             );
 
             // Precompute color/w start values and interpolation increments
@@ -1018,6 +4848,17 @@ impl Rasterizer {
             let tz_over_w_dx: f32 = dot(edge_dx_v3, tz_over_w_v3);
             let tz_over_w_dy: f32 = dot(edge_dy_v3, tz_over_w_v3);
 
+            // Precompute world position/w start values and interpolation increments
+            let px_over_w_min: f32 = dot(edge_min_v3, px_over_w_v3);
+            let px_over_w_dx: f32 = dot(edge_dx_v3, px_over_w_v3);
+            let px_over_w_dy: f32 = dot(edge_dy_v3, px_over_w_v3);
+            let py_over_w_min: f32 = dot(edge_min_v3, py_over_w_v3);
+            let py_over_w_dx: f32 = dot(edge_dx_v3, py_over_w_v3);
+            let py_over_w_dy: f32 = dot(edge_dy_v3, py_over_w_v3);
+            let pz_over_w_min: f32 = dot(edge_min_v3, pz_over_w_v3);
+            let pz_over_w_dx: f32 = dot(edge_dx_v3, pz_over_w_v3);
+            let pz_over_w_dy: f32 = dot(edge_dy_v3, pz_over_w_v3);
+
             // Precompute texture coordinates start values and interpolation increments
             let u_over_w_min: f32 = dot(edge_min_v3, u_over_w_v3);
             let u_over_w_dx: f32 = dot(edge_dx_v3, u_over_w_v3);
@@ -1026,11 +4867,36 @@ impl Rasterizer {
             let v_over_w_dx: f32 = dot(edge_dx_v3, v_over_w_v3);
             let v_over_w_dy: f32 = dot(edge_dy_v3, v_over_w_v3);
 
+            // Precompute secondary texture coordinates start values and interpolation increments
+            let u2_over_w_min: f32 = dot(edge_min_v3, u2_over_w_v3);
+            let u2_over_w_dx: f32 = dot(edge_dx_v3, u2_over_w_v3);
+            let u2_over_w_dy: f32 = dot(edge_dy_v3, u2_over_w_v3);
+            let v2_over_w_min: f32 = dot(edge_min_v3, v2_over_w_v3);
+            let v2_over_w_dx: f32 = dot(edge_dx_v3, v2_over_w_v3);
+            let v2_over_w_dy: f32 = dot(edge_dy_v3, v2_over_w_v3);
+
             // Precompute 1/w start value and interpolation increments
             let inv_w_min: f32 = dot(edge_min_v3, inv_w_v3);
             let inv_w_dx: f32 = dot(edge_dx_v3, inv_w_v3);
             let inv_w_dy: f32 = dot(edge_dy_v3, inv_w_v3);
 
+            // If the triangle is interpolated affinely - prepare fixed-point per-vertex colors, 8.16 with 256 being 1.0.
+            let fixed_point_colors: bool =
+                affine && COLOR_INTERPOLATION_MODE == VerticesColorInterpolationMode::PerVertex as u8;
+            let to_fixed_point = |v: f32| (v * affine_inv_inv_w * 16777216.0) as i32;
+            let r_fx_min: i32 = to_fixed_point(r_over_w_min);
+            let r_fx_dx: i32 = to_fixed_point(r_over_w_dx);
+            let r_fx_dy: i32 = to_fixed_point(r_over_w_dy);
+            let g_fx_min: i32 = to_fixed_point(g_over_w_min);
+            let g_fx_dx: i32 = to_fixed_point(g_over_w_dx);
+            let g_fx_dy: i32 = to_fixed_point(g_over_w_dy);
+            let b_fx_min: i32 = to_fixed_point(b_over_w_min);
+            let b_fx_dx: i32 = to_fixed_point(b_over_w_dx);
+            let b_fx_dy: i32 = to_fixed_point(b_over_w_dy);
+            let a_fx_min: i32 = to_fixed_point(a_over_w_min);
+            let a_fx_dx: i32 = to_fixed_point(a_over_w_dx);
+            let a_fx_dy: i32 = to_fixed_point(a_over_w_dy);
+
             // If fixed per-triangle color is used - prepare integer values.
             // NB! The color is multiplied by 256 instead of 255 to use binary shift later.
             let v0_color_r: u32 = (v0.color.x * 256.0) as u32;
@@ -1063,6 +4929,18 @@ impl Rasterizer {
             } else {
                 ptr::null_mut()
             };
+            let mut depth_f32_row_ptr: *mut f32 = if DEPTH_MODE == DepthProcessingMode::Float as u8 {
+                unsafe {
+                    framebuffer
+                        .depth_buffer_f32
+                        .as_mut()
+                        .unwrap_unchecked()
+                        .ptr
+                        .add((ymin * Framebuffer::TILE_WITH as i32 + xmin) as usize)
+                }
+            } else {
+                ptr::null_mut()
+            };
             let mut normal_row_ptr: *mut u32 = if NORMALS_PROCESSING >= NormalsProcessingMode::Vertex as u8 {
                 unsafe {
                     framebuffer
@@ -1075,6 +4953,10 @@ impl Rasterizer {
             } else {
                 ptr::null_mut()
             };
+            let mut motion_row_ptr: *mut u32 = match framebuffer.motion_buffer.as_mut() {
+                Some(buffer) => unsafe { buffer.ptr.add((ymin * Framebuffer::TILE_WITH as i32 + xmin) as usize) },
+                None => ptr::null_mut(),
+            };
 
             // Set up the initial values at each consequent row
             let mut depth_edges_24_8_row: U32x4 = depth_edges_24_8_min; // starting z, v12, v20, v01 values
@@ -1082,33 +4964,53 @@ impl Rasterizer {
             let mut g_over_w_row: f32 = g_over_w_min; // starting g/w
             let mut b_over_w_row: f32 = b_over_w_min; // starting b/w
             let mut a_over_w_row: f32 = a_over_w_min; // starting a/w
+            let mut r_fx_row: i32 = r_fx_min; // starting fixed-point r
+            let mut g_fx_row: i32 = g_fx_min; // starting fixed-point g
+            let mut b_fx_row: i32 = b_fx_min; // starting fixed-point b
+            let mut a_fx_row: i32 = a_fx_min; // starting fixed-point a
             let mut nx_over_w_row: f32 = nx_over_w_min; // starting nx/w
             let mut ny_over_w_row: f32 = ny_over_w_min; // starting ny/w
             let mut nz_over_w_row: f32 = nz_over_w_min; // starting nz/w
             let mut tx_over_w_row: f32 = tx_over_w_min; // starting tx/w
             let mut ty_over_w_row: f32 = ty_over_w_min; // starting ty/w
             let mut tz_over_w_row: f32 = tz_over_w_min; // starting tz/w
+            let mut px_over_w_row: f32 = px_over_w_min; // starting px/w
+            let mut py_over_w_row: f32 = py_over_w_min; // starting py/w
+            let mut pz_over_w_row: f32 = pz_over_w_min; // starting pz/w
             let mut u_over_w_row: f32 = u_over_w_min; // starting u/w
             let mut v_over_w_row: f32 = v_over_w_min; // starting v/w
+            let mut u2_over_w_row: f32 = u2_over_w_min; // starting u2/w
+            let mut v2_over_w_row: f32 = v2_over_w_min; // starting v2/w
             let mut inv_w_row: f32 = inv_w_min; // starting 1/w
+            let mut rz_row: f32 = rz_min; // starting reverse-Z depth
 
             // The maximum horizontal span of the triangle
             let row_steps: u32 = (xmax - xmin + 1) as u32;
-            for _y in ymin..=ymax {
+            for y in ymin..=ymax {
                 let mut depth_edges_24_8: U32x4 = depth_edges_24_8_row;
                 let mut inv_w: f32 = inv_w_row;
+                let mut rz: f32 = rz_row;
                 let mut r_over_w: f32 = r_over_w_row;
                 let mut g_over_w: f32 = g_over_w_row;
                 let mut b_over_w: f32 = b_over_w_row;
                 let mut a_over_w: f32 = a_over_w_row;
+                let mut r_fx: i32 = r_fx_row;
+                let mut g_fx: i32 = g_fx_row;
+                let mut b_fx: i32 = b_fx_row;
+                let mut a_fx: i32 = a_fx_row;
                 let mut nx_over_w: f32 = nx_over_w_row;
                 let mut ny_over_w: f32 = ny_over_w_row;
                 let mut nz_over_w: f32 = nz_over_w_row;
                 let mut tx_over_w: f32 = tx_over_w_row;
                 let mut ty_over_w: f32 = ty_over_w_row;
                 let mut tz_over_w: f32 = tz_over_w_row;
+                let mut px_over_w: f32 = px_over_w_row;
+                let mut py_over_w: f32 = py_over_w_row;
+                let mut pz_over_w: f32 = pz_over_w_row;
                 let mut u_over_w: f32 = u_over_w_row;
                 let mut v_over_w: f32 = v_over_w_row;
+                let mut u2_over_w: f32 = u2_over_w_row;
+                let mut v2_over_w: f32 = v2_over_w_row;
                 let mut color_ptr: *mut u32 = if HAS_COLOR_BUFFER {
                     color_row_ptr
                 } else {
@@ -1119,16 +5021,18 @@ impl Rasterizer {
                 } else {
                     ptr::null_mut()
                 };
+                let mut depth_f32_ptr: *mut f32 = depth_f32_row_ptr;
                 let mut normal_ptr: *mut u32 = if NORMALS_PROCESSING >= NormalsProcessingMode::Vertex as u8 {
                     normal_row_ptr
                 } else {
                     ptr::null_mut()
                 };
+                let mut motion_ptr: *mut u32 = motion_row_ptr;
 
                 // Step in a tight loop until we're inside a triangle
                 let mut steps: u32 = row_steps;
-                while depth_edges_24_8.bitand(edge_simd_non_negative_mask).any_nonzero() && steps != 0 {
-                    depth_edges_24_8 = depth_edges_24_8.add(depth_edges_24_8_dx);
+                while (depth_edges_24_8 & edge_simd_non_negative_mask).any_nonzero() && steps != 0 {
+                    depth_edges_24_8 += depth_edges_24_8_dx;
                     steps -= 1;
                 }
 
@@ -1136,26 +5040,42 @@ impl Rasterizer {
                 if steps != row_steps && steps > 0 {
                     let skipped: u32 = row_steps - steps;
                     let skipped_f: f32 = skipped as f32;
-                    inv_w = inv_w_dx.mul_add(skipped_f, inv_w);
+                    inv_w = mul_add(inv_w_dx, skipped_f, inv_w);
                     if COLOR_INTERPOLATION_MODE == VerticesColorInterpolationMode::PerVertex as u8 {
-                        r_over_w = r_over_w_dx.mul_add(skipped_f, r_over_w);
-                        g_over_w = g_over_w_dx.mul_add(skipped_f, g_over_w);
-                        b_over_w = b_over_w_dx.mul_add(skipped_f, b_over_w);
-                        a_over_w = a_over_w_dx.mul_add(skipped_f, a_over_w);
+                        if fixed_point_colors {
+                            r_fx += r_fx_dx * skipped as i32;
+                            g_fx += g_fx_dx * skipped as i32;
+                            b_fx += b_fx_dx * skipped as i32;
+                            a_fx += a_fx_dx * skipped as i32;
+                        } else {
+                            r_over_w = mul_add(r_over_w_dx, skipped_f, r_over_w);
+                            g_over_w = mul_add(g_over_w_dx, skipped_f, g_over_w);
+                            b_over_w = mul_add(b_over_w_dx, skipped_f, b_over_w);
+                            a_over_w = mul_add(a_over_w_dx, skipped_f, a_over_w);
+                        }
                     }
-                    if NORMALS_PROCESSING >= NormalsProcessingMode::Vertex as u8 {
-                        nx_over_w = nx_over_w_dx.mul_add(skipped_f, nx_over_w);
-                        ny_over_w = ny_over_w_dx.mul_add(skipped_f, ny_over_w);
-                        nz_over_w = nz_over_w_dx.mul_add(skipped_f, nz_over_w);
+                    if interpolates_normals {
+                        nx_over_w = mul_add(nx_over_w_dx, skipped_f, nx_over_w);
+                        ny_over_w = mul_add(ny_over_w_dx, skipped_f, ny_over_w);
+                        nz_over_w = mul_add(nz_over_w_dx, skipped_f, nz_over_w);
+                    }
+                    if interpolates_positions {
+                        px_over_w = mul_add(px_over_w_dx, skipped_f, px_over_w);
+                        py_over_w = mul_add(py_over_w_dx, skipped_f, py_over_w);
+                        pz_over_w = mul_add(pz_over_w_dx, skipped_f, pz_over_w);
                     }
                     if NORMALS_PROCESSING == NormalsProcessingMode::NormalMapping as u8 {
-                        tx_over_w = tx_over_w_dx.mul_add(skipped_f, tx_over_w);
-                        ty_over_w = ty_over_w_dx.mul_add(skipped_f, ty_over_w);
-                        tz_over_w = tz_over_w_dx.mul_add(skipped_f, tz_over_w);
+                        tx_over_w = mul_add(tx_over_w_dx, skipped_f, tx_over_w);
+                        ty_over_w = mul_add(ty_over_w_dx, skipped_f, ty_over_w);
+                        tz_over_w = mul_add(tz_over_w_dx, skipped_f, tz_over_w);
                     }
                     if HAS_TEXTURE {
-                        u_over_w = u_over_w_dx.mul_add(skipped_f, u_over_w);
-                        v_over_w = v_over_w_dx.mul_add(skipped_f, v_over_w);
+                        u_over_w = mul_add(u_over_w_dx, skipped_f, u_over_w);
+                        v_over_w = mul_add(v_over_w_dx, skipped_f, v_over_w);
+                    }
+                    if lightmap.is_some() {
+                        u2_over_w = mul_add(u2_over_w_dx, skipped_f, u2_over_w);
+                        v2_over_w = mul_add(v2_over_w_dx, skipped_f, v2_over_w);
                     }
                     if HAS_COLOR_BUFFER {
                         unsafe {
@@ -1167,24 +5087,61 @@ impl Rasterizer {
                             depth_ptr = depth_ptr.add(skipped as usize);
                         }
                     }
+                    if DEPTH_MODE == DepthProcessingMode::Float as u8 {
+                        rz = mul_add(rz_dx, skipped_f, rz);
+                        unsafe {
+                            depth_f32_ptr = depth_f32_ptr.add(skipped as usize);
+                        }
+                    }
                     if NORMALS_PROCESSING >= NormalsProcessingMode::Vertex as u8 {
                         unsafe {
                             normal_ptr = normal_ptr.add(skipped as usize);
                         }
                     }
+                    if writes_motion {
+                        unsafe {
+                            motion_ptr = motion_ptr.add(skipped as usize);
+                        }
+                    }
                 }
 
+                // Per-span perspective correction state: w at the current pixel, its increment and the pixels left
+                let mut span_w: f32 = 0.0;
+                let mut span_w_dx: f32 = 0.0;
+                let mut span_left: u32 = 0;
+
                 // Iterate over the triangle
                 'triangle_body: while steps != 0 {
+                    if perspective_span > 1 && span_left == 0 {
+                        let span: u32 = perspective_span.min(steps);
+                        let span_w_end: f32 = 1.0 / (inv_w + inv_w_dx * span as f32).max(span_inv_w_lo);
+                        span_w = 1.0 / inv_w;
+                        span_w_dx = (span_w_end - span_w) / span as f32;
+                        span_left = span;
+                    }
                     'fragment: {
-                        if depth_edges_24_8.bitand(edge_simd_non_negative_mask).any_nonzero() {
+                        if (depth_edges_24_8 & edge_simd_non_negative_mask).any_nonzero() {
                             break 'triangle_body; // stop the entire row - out of the triangle bounds, no need to iterate further
                         }
 
                         let z_u16: u16 = if HAS_DEPTH_BUFFER {
-                            let z_u16: u16 = (depth_edges_24_8.extract_lane0() >> 8) as u16;
+                            let z_24_8: u32 = depth_edges_24_8.extract_lane0();
+                            let z_u16: u16 = if depth_clamp {
+                                // Beyond the far plane the depth exceeds u16, the rounding can take it slightly below 0
+                                (z_24_8.cast_signed() >> 8).clamp(DEPTH_NEAR as i32, DEPTH_FAR as i32 - 1) as u16
+                            } else if depth_biased {
+                                // The bias can take the depth outside of the u16 range near the planes
+                                (z_24_8.cast_signed() >> 8).clamp(DEPTH_NEAR as i32, DEPTH_FAR as i32) as u16
+                            } else {
+                                (z_24_8 >> 8) as u16
+                            };
                             unsafe {
-                                if z_u16 >= *depth_ptr {
+                                let passed: bool = if DEPTH_MODE == DepthProcessingMode::Configurable as u8 {
+                                    depth_test.passes(z_u16, *depth_ptr)
+                                } else {
+                                    z_u16 < *depth_ptr
+                                };
+                                if !passed {
                                     break 'fragment; // discard - failed the depth test
                                 }
                             }
@@ -1192,15 +5149,104 @@ impl Rasterizer {
                         } else {
                             0u16 // fake value just to keep the compiler happy, never actually materialized
                         };
+                        let z_f32: f32 = if DEPTH_MODE == DepthProcessingMode::Float as u8 {
+                            // Beyond the far plane the reversed depth goes below zero, keep it right above the far one
+                            let z_f32: f32 = if depth_clamp {
+                                rz.clamp(f32::MIN_POSITIVE, DEPTH_F32_NEAR)
+                            } else if depth_biased {
+                                rz.clamp(DEPTH_F32_FAR, DEPTH_F32_NEAR)
+                            } else {
+                                rz
+                            };
+                            if !depth_test.passes_reversed(z_f32, unsafe { *depth_f32_ptr }) {
+                                break 'fragment; // discard - failed the depth test
+                            }
+                            z_f32
+                        } else {
+                            0.0
+                        };
 
-                        let inv_inv_w: f32 = 1.0 / inv_w;
+                        let inv_inv_w: f32 = if affine {
+                            affine_inv_inv_w
+                        } else if perspective_span > 1 {
+                            span_w
+                        } else if use_fast_reciprocal {
+                            fast_reciprocal(inv_w)
+                        } else {
+                            1.0 / inv_w
+                        };
 
                         if HAS_COLOR_BUFFER {
                             // Fetch a corresponding texel color
                             let tex_fragment = if HAS_TEXTURE {
                                 let u: f32 = u_over_w * inv_inv_w;
                                 let v: f32 = v_over_w * inv_inv_w;
-                                albedo_sampler.sample_prescaled(u, v)
+                                let texel: RGBA = if let Some(pattern) = pattern.as_ref() {
+                                    // The derivatives of u = (u/w) / (1/w) along the screen's axes
+                                    let pattern_uv_dx: Vec2 = Vec2::new(
+                                        (u_over_w_dx - u * inv_w_dx) * inv_inv_w,
+                                        (v_over_w_dx - v * inv_w_dx) * inv_inv_w,
+                                    );
+                                    let pattern_uv_dy: Vec2 = Vec2::new(
+                                        (u_over_w_dy - u * inv_w_dy) * inv_inv_w,
+                                        (v_over_w_dy - v * inv_w_dy) * inv_inv_w,
+                                    );
+                                    pattern.evaluate(u, v, pattern_uv_dx, pattern_uv_dy)
+                                } else if !has_albedo_texture {
+                                    RGBA::new(255, 255, 255, 255)
+                                } else if has_texture_region {
+                                    albedo_sampler.sample_in_region(u, v)
+                                } else if anisotropic {
+                                    albedo_sampler.sample_anisotropic_prescaled(u, v)
+                                } else {
+                                    albedo_sampler.sample_prescaled(u, v)
+                                };
+                                let texel: RGBA = if let Some(sdf) = sdf.as_ref() {
+                                    let coverage: f32 = sdf.coverage(texel.r as f32 / 255.0, sdf_smoothing);
+                                    let c: u8 = (coverage * 255.0 + 0.5) as u8;
+                                    RGBA::new(c, c, c, c)
+                                } else {
+                                    texel
+                                };
+                                let texel: RGBA = if let Some(fragment_hook) = fragment_hook {
+                                    let fragment = HookFragment {
+                                        x: (tile_origin_x + xmin + (row_steps - steps) as i32) as u16,
+                                        y: (tile_origin_y + y) as u16,
+                                        tex_coord: Vec2::new(
+                                            u / albedo_sampler_uv_scale.scale - albedo_sampler_uv_scale.bias,
+                                            v / albedo_sampler_uv_scale.scale - albedo_sampler_uv_scale.bias,
+                                        ),
+                                        texel: Vec4::new(
+                                            texel.r as f32 / 255.0,
+                                            texel.g as f32 / 255.0,
+                                            texel.b as f32 / 255.0,
+                                            texel.a as f32 / 255.0,
+                                        ),
+                                    };
+                                    let color: Vec4 = fragment_hook(&fragment, &command.uniforms);
+                                    RGBA::new(
+                                        (color.x.clamp(0.0, 1.0) * 255.0 + 0.5) as u8,
+                                        (color.y.clamp(0.0, 1.0) * 255.0 + 0.5) as u8,
+                                        (color.z.clamp(0.0, 1.0) * 255.0 + 0.5) as u8,
+                                        (color.w.clamp(0.0, 1.0) * 255.0 + 0.5) as u8,
+                                    )
+                                } else {
+                                    texel
+                                };
+                                // The baked lighting modulates the texel's color, the alpha is kept
+                                if lightmap.is_some() {
+                                    let light: RGBA =
+                                        lightmap_sampler.sample_prescaled(u2_over_w * inv_inv_w, v2_over_w * inv_inv_w);
+                                    let modulate = |c: u8, l: u8| -> u8 { ((c as u32 * l as u32 + 127) / 255) as u8 };
+                                    RGBA::new(
+                                        modulate(texel.r, light.r),
+                                        modulate(texel.g, light.g),
+                                        modulate(texel.b, light.b),
+                                        texel.a,
+                                    )
+                                } else {
+                                    texel
+                                }
                             } else {
                                 RGBA::new(255, 255, 255, 255)
                             };
@@ -1208,78 +5254,213 @@ impl Rasterizer {
                             if ALPHA_TEST_ENABLED && tex_fragment.a < alpha_test_threshold {
                                 break 'fragment;
                             }
-
-                            // Color component of this fragment.
-                            // Either a mix of sampled and triangle colors or a sampled color as-is.
-                            let r: u8;
-                            let g: u8;
-                            let b: u8;
-                            let a: u8;
-
-                            if COLOR_INTERPOLATION_MODE == VerticesColorInterpolationMode::PerVertex as u8 {
-                                // If the triangle has different per-vertex colors - need to interpolate them.
-                                // Recover interpolated per-fragment color
-                                let interpolated_r: f32 = r_over_w * inv_inv_w;
-                                let interpolated_g: f32 = g_over_w * inv_inv_w;
-                                let interpolated_b: f32 = b_over_w * inv_inv_w;
-                                let interpolated_a: f32 = a_over_w * inv_inv_w;
-                                // Multiply the interpolated and texel colors
-                                r = (interpolated_r * tex_fragment.r as f32).clamp(0.0, 255.0) as u8;
-                                g = (interpolated_g * tex_fragment.g as f32).clamp(0.0, 255.0) as u8;
-                                b = (interpolated_b * tex_fragment.b as f32).clamp(0.0, 255.0) as u8;
-                                a = (interpolated_a * tex_fragment.a as f32).clamp(0.0, 255.0) as u8;
-                            } else if COLOR_INTERPOLATION_MODE == VerticesColorInterpolationMode::Fixed as u8 {
-                                // If the triangle has a fixed per-fragment color - multiply the sampled color by it.
-                                // Be stingy and do the multiplication in integers.
-                                r = ((v0_color_r * tex_fragment.r as u32) >> 8) as u8;
-                                g = ((v0_color_g * tex_fragment.g as u32) >> 8) as u8;
-                                b = ((v0_color_b * tex_fragment.b as u32) >> 8) as u8;
-                                a = ((v0_color_a * tex_fragment.a as u32) >> 8) as u8;
-                            } else {
-                                // Triangle has no color information - use the sampled color as-is
-                                r = tex_fragment.r;
-                                g = tex_fragment.g;
-                                b = tex_fragment.b;
-                                a = tex_fragment.a;
-                            }
-
-                            // Build the dest color
-                            let color: u32 = if ALPHA_BLENDING == AlphaBlendingMode::Normal as u8 {
-                                let dest: RGBA = RGBA::from_u32(unsafe { *color_ptr });
-                                let inv_a: u32 = (255 - a) as u32;
-                                RGBA::new(
-                                    r + ((dest.r as u32 * inv_a) / 255) as u8,
-                                    g + ((dest.g as u32 * inv_a) / 255) as u8,
-                                    b + ((dest.b as u32 * inv_a) / 255) as u8,
-                                    255,
-                                )
-                                .to_u32()
-                            } else if ALPHA_BLENDING == AlphaBlendingMode::Additive as u8 {
-                                let dest: RGBA = RGBA::from_u32(unsafe { *color_ptr });
-                                RGBA::new(
-                                    (r as u32 + dest.r as u32).min(255) as u8,
-                                    (g as u32 + dest.g as u32).min(255) as u8,
-                                    (b as u32 + dest.b as u32).min(255) as u8,
-                                    255,
-                                )
-                                .to_u32()
+                            let tex_fragment: RGBA = if ignores_texture_alpha {
+                                opaque_texel(tex_fragment)
                             } else {
-                                RGBA::new(r, g, b, 255).to_u32()
+                                tex_fragment
                             };
 
-                            // Write the fragment color into the framebuffer
-                            unsafe {
-                                *color_ptr = color;
+                            // The environment reflected towards the camera by the fragment
+                            let reflected: Option<(&Reflection, Vec3)> =
+                                reflection.as_ref().map(|(reflection, sampler)| {
+                                    let position: Vec3 =
+                                        Vec3::new(px_over_w * inv_inv_w, py_over_w * inv_inv_w, pz_over_w * inv_inv_w);
+                                    let normal: Vec3 =
+                                        Vec3::new(nx_over_w * inv_inv_w, ny_over_w * inv_inv_w, nz_over_w * inv_inv_w)
+                                            .normalized();
+                                    (*reflection, sampler.sample(reflection.reflect(position, normal)))
+                                });
+
+                            if hdr {
+                                // Same as below, but in floating point and without clamping the colors to 1.0
+                                let color: Vec4 = if COLOR_INTERPOLATION_MODE
+                                    == VerticesColorInterpolationMode::PerVertex as u8
+                                    && fixed_point_colors
+                                {
+                                    Vec4::new(
+                                        r_fx as f32 / 16777216.0,
+                                        g_fx as f32 / 16777216.0,
+                                        b_fx as f32 / 16777216.0,
+                                        a_fx as f32 / 16777216.0,
+                                    )
+                                } else if COLOR_INTERPOLATION_MODE == VerticesColorInterpolationMode::PerVertex as u8 {
+                                    Vec4::new(
+                                        r_over_w * inv_inv_w,
+                                        g_over_w * inv_inv_w,
+                                        b_over_w * inv_inv_w,
+                                        a_over_w * inv_inv_w,
+                                    )
+                                } else if COLOR_INTERPOLATION_MODE == VerticesColorInterpolationMode::Fixed as u8 {
+                                    v0.color
+                                } else {
+                                    Vec4::new(1.0, 1.0, 1.0, 1.0)
+                                };
+                                let src: Vec4 = if HAS_TEXTURE {
+                                    color
+                                        * Vec4::new(
+                                            tex_fragment.r as f32,
+                                            tex_fragment.g as f32,
+                                            tex_fragment.b as f32,
+                                            tex_fragment.a as f32,
+                                        )
+                                        * (1.0 / 255.0)
+                                } else {
+                                    color
+                                };
+                                let src: Vec4 = match reflected {
+                                    Some((reflection, environment)) => {
+                                        reflection.apply(src, environment, tint_premultiplied)
+                                    }
+                                    None => src,
+                                };
+                                let src: Vec4 = if tinted {
+                                    tint.apply(src, tint_premultiplied)
+                                } else {
+                                    src
+                                };
+                                let src: Vec4 = match fog.as_ref() {
+                                    Some(fog) => {
+                                        let view_depth: f32 = if affine {
+                                            affine_view_depth
+                                        } else {
+                                            inv_inv_w * area_x_2
+                                        };
+                                        fog.apply(src, view_depth, tint_premultiplied)
+                                    }
+                                    None => src,
+                                };
+                                unsafe {
+                                    let blended: Vec3 = if ALPHA_BLENDING == AlphaBlendingProcessingMode::None as u8 {
+                                        src.xyz()
+                                    } else if ALPHA_BLENDING == AlphaBlendingProcessingMode::Normal as u8 {
+                                        AlphaBlendingMode::Normal.blend(src, decode_rgb9e5(*color_ptr))
+                                    } else if ALPHA_BLENDING == AlphaBlendingProcessingMode::Additive as u8 {
+                                        AlphaBlendingMode::Additive.blend(src, decode_rgb9e5(*color_ptr))
+                                    } else {
+                                        command.alpha_blending.blend(src, decode_rgb9e5(*color_ptr))
+                                    };
+                                    let blended: Vec3 = if color_masked {
+                                        color_write_mask.select(blended, decode_rgb9e5(*color_ptr))
+                                    } else {
+                                        blended
+                                    };
+                                    *color_ptr = encode_rgb9e5(blended.x, blended.y, blended.z);
+                                }
+                            } else {
+                                // Color component of this fragment.
+                                // Either a mix of sampled and triangle colors or a sampled color as-is.
+                                let r: u8;
+                                let g: u8;
+                                let b: u8;
+                                let a: u8;
+
+                                if COLOR_INTERPOLATION_MODE == VerticesColorInterpolationMode::PerVertex as u8
+                                    && fixed_point_colors
+                                {
+                                    // Affinely interpolated per-vertex colors are already in fixed-point.
+                                    // Multiply them by the texel color in integers, the same way as the fixed color.
+                                    r = (((r_fx >> 16).clamp(0, 256) as u32 * tex_fragment.r as u32) >> 8) as u8;
+                                    g = (((g_fx >> 16).clamp(0, 256) as u32 * tex_fragment.g as u32) >> 8) as u8;
+                                    b = (((b_fx >> 16).clamp(0, 256) as u32 * tex_fragment.b as u32) >> 8) as u8;
+                                    a = (((a_fx >> 16).clamp(0, 256) as u32 * tex_fragment.a as u32) >> 8) as u8;
+                                } else if COLOR_INTERPOLATION_MODE == VerticesColorInterpolationMode::PerVertex as u8 {
+                                    // If the triangle has different per-vertex colors - need to interpolate them.
+                                    // Recover interpolated per-fragment color
+                                    let interpolated_r: f32 = r_over_w * inv_inv_w;
+                                    let interpolated_g: f32 = g_over_w * inv_inv_w;
+                                    let interpolated_b: f32 = b_over_w * inv_inv_w;
+                                    let interpolated_a: f32 = a_over_w * inv_inv_w;
+                                    // Multiply the interpolated and texel colors
+                                    r = (interpolated_r * tex_fragment.r as f32).clamp(0.0, 255.0) as u8;
+                                    g = (interpolated_g * tex_fragment.g as f32).clamp(0.0, 255.0) as u8;
+                                    b = (interpolated_b * tex_fragment.b as f32).clamp(0.0, 255.0) as u8;
+                                    a = (interpolated_a * tex_fragment.a as f32).clamp(0.0, 255.0) as u8;
+                                } else if COLOR_INTERPOLATION_MODE == VerticesColorInterpolationMode::Fixed as u8 {
+                                    // If the triangle has a fixed per-fragment color - multiply the sampled color by it.
+                                    // Be stingy and do the multiplication in integers.
+                                    r = ((v0_color_r * tex_fragment.r as u32) >> 8) as u8;
+                                    g = ((v0_color_g * tex_fragment.g as u32) >> 8) as u8;
+                                    b = ((v0_color_b * tex_fragment.b as u32) >> 8) as u8;
+                                    a = ((v0_color_a * tex_fragment.a as u32) >> 8) as u8;
+                                } else {
+                                    // Triangle has no color information - use the sampled color as-is
+                                    r = tex_fragment.r;
+                                    g = tex_fragment.g;
+                                    b = tex_fragment.b;
+                                    a = tex_fragment.a;
+                                }
+                                let (r, g, b, a): (u8, u8, u8, u8) = match reflected {
+                                    Some((reflection, environment)) => {
+                                        reflection.apply_rgba8(r, g, b, a, environment, tint_premultiplied)
+                                    }
+                                    None => (r, g, b, a),
+                                };
+                                let (r, g, b, a): (u8, u8, u8, u8) = if tinted {
+                                    tint.apply_rgba8(r, g, b, a, tint_premultiplied)
+                                } else {
+                                    (r, g, b, a)
+                                };
+                                let (r, g, b, a): (u8, u8, u8, u8) = match fog.as_ref() {
+                                    Some(fog) => {
+                                        let view_depth: f32 = if affine {
+                                            affine_view_depth
+                                        } else {
+                                            inv_inv_w * area_x_2
+                                        };
+                                        fog.apply_rgba8(r, g, b, a, view_depth, tint_premultiplied)
+                                    }
+                                    None => (r, g, b, a),
+                                };
+
+                                // Build the dest color
+                                let color: u32 = if ALPHA_BLENDING == AlphaBlendingProcessingMode::Normal as u8 {
+                                    let dest: RGBA = RGBA::from_u32(unsafe { *color_ptr });
+                                    let inv_a: u32 = (255 - a) as u32;
+                                    RGBA::new(
+                                        r + ((dest.r as u32 * inv_a) / 255) as u8,
+                                        g + ((dest.g as u32 * inv_a) / 255) as u8,
+                                        b + ((dest.b as u32 * inv_a) / 255) as u8,
+                                        255,
+                                    )
+                                    .to_u32()
+                                } else if ALPHA_BLENDING == AlphaBlendingProcessingMode::Additive as u8 {
+                                    let dest: RGBA = RGBA::from_u32(unsafe { *color_ptr });
+                                    RGBA::new(
+                                        (r as u32 + dest.r as u32).min(255) as u8,
+                                        (g as u32 + dest.g as u32).min(255) as u8,
+                                        (b as u32 + dest.b as u32).min(255) as u8,
+                                        255,
+                                    )
+                                    .to_u32()
+                                } else if ALPHA_BLENDING == AlphaBlendingProcessingMode::Generic as u8 {
+                                    blend_rgba8(command.alpha_blending, RGBA::new(r, g, b, a), unsafe { *color_ptr })
+                                } else {
+                                    RGBA::new(r, g, b, 255).to_u32()
+                                };
+
+                                // Write the fragment color into the framebuffer, keeping the masked-out channels
+                                unsafe {
+                                    *color_ptr = if color_masked {
+                                        (color & color_write_bits) | (*color_ptr & !color_write_bits)
+                                    } else {
+                                        color
+                                    };
+                                }
                             }
                         }
 
                         // Write into the depth buffer AFTER the color buffer because the alpha-test can discard the fragment.
                         // Writing the depth of a fragment which is discarded is incorrect, hence it's delayed.
-                        if HAS_DEPTH_BUFFER {
+                        if HAS_DEPTH_BUFFER && (DEPTH_MODE == DepthProcessingMode::Default as u8 || depth_write) {
                             unsafe {
                                 *depth_ptr = z_u16;
                             }
                         }
+                        if DEPTH_MODE == DepthProcessingMode::Float as u8 && depth_write {
+                            unsafe {
+                                *depth_f32_ptr = z_f32;
+                            }
+                        }
 
                         if NORMALS_PROCESSING == NormalsProcessingMode::Vertex as u8 {
                             unsafe {
@@ -1307,8 +5488,14 @@ impl Rasterizer {
                                 bitangent.z,
                                 normal.z,
                             ]);
-                            let sampled_normal_rgba: RGBA =
-                                normal_map_sampler.sample_prescaled(u_over_w * inv_inv_w, v_over_w * inv_inv_w);
+                            let sampled_normal_rgba: RGBA = if has_texture_region {
+                                normal_map_sampler.sample_in_region(u_over_w * inv_inv_w, v_over_w * inv_inv_w)
+                            } else if anisotropic {
+                                normal_map_sampler
+                                    .sample_anisotropic_prescaled(u_over_w * inv_inv_w, v_over_w * inv_inv_w)
+                            } else {
+                                normal_map_sampler.sample_prescaled(u_over_w * inv_inv_w, v_over_w * inv_inv_w)
+                            };
                             let sampled_normal: Vec3 = Vec3::new(
                                 (sampled_normal_rgba.r as f32 - 127.0) / 128.0,
                                 (sampled_normal_rgba.g as f32 - 127.0) / 128.0,
@@ -1316,8 +5503,28 @@ impl Rasterizer {
                             );
                             let final_normal = (tbn * sampled_normal).normalized();
                             unsafe {
-                                *normal_ptr =
-                                    Self::encode_normal_as_u32(final_normal.x, final_normal.y, final_normal.z);
+                                *normal_ptr =
+                                    Self::encode_normal_as_u32(final_normal.x, final_normal.y, final_normal.z);
+                            }
+                        }
+
+                        if writes_motion {
+                            let encoded: u32 = match motion.as_ref() {
+                                Some(motion) => {
+                                    let position: Vec3 =
+                                        Vec3::new(px_over_w * inv_inv_w, py_over_w * inv_inv_w, pz_over_w * inv_inv_w);
+                                    let previous: Vec4 = *motion * position.as_point4();
+                                    let previous: Vec4 = viewport_scale.apply(previous * (1.0 / previous.w));
+                                    let current: Vec2 = Vec2::new(
+                                        (tile_origin_x + xmin + (row_steps - steps) as i32) as f32 + 0.5,
+                                        (tile_origin_y + y) as f32 + 0.5,
+                                    );
+                                    encode_motion(current - previous.xy())
+                                }
+                                None => 0,
+                            };
+                            unsafe {
+                                *motion_ptr = encoded;
                             }
                         }
 
@@ -1326,19 +5533,35 @@ impl Rasterizer {
                         }
                     }
                     steps -= 1;
-                    depth_edges_24_8 = depth_edges_24_8.add(depth_edges_24_8_dx);
+                    depth_edges_24_8 += depth_edges_24_8_dx;
                     inv_w += inv_w_dx;
+                    if perspective_span > 1 {
+                        span_w += span_w_dx;
+                        span_left -= 1;
+                    }
                     if COLOR_INTERPOLATION_MODE == VerticesColorInterpolationMode::PerVertex as u8 {
-                        r_over_w += r_over_w_dx;
-                        g_over_w += g_over_w_dx;
-                        b_over_w += b_over_w_dx;
-                        a_over_w += a_over_w_dx;
+                        if fixed_point_colors {
+                            r_fx += r_fx_dx;
+                            g_fx += g_fx_dx;
+                            b_fx += b_fx_dx;
+                            a_fx += a_fx_dx;
+                        } else {
+                            r_over_w += r_over_w_dx;
+                            g_over_w += g_over_w_dx;
+                            b_over_w += b_over_w_dx;
+                            a_over_w += a_over_w_dx;
+                        }
                     }
-                    if NORMALS_PROCESSING >= NormalsProcessingMode::Vertex as u8 {
+                    if interpolates_normals {
                         nx_over_w += nx_over_w_dx;
                         ny_over_w += ny_over_w_dx;
                         nz_over_w += nz_over_w_dx;
                     }
+                    if interpolates_positions {
+                        px_over_w += px_over_w_dx;
+                        py_over_w += py_over_w_dx;
+                        pz_over_w += pz_over_w_dx;
+                    }
                     if NORMALS_PROCESSING == NormalsProcessingMode::NormalMapping as u8 {
                         tx_over_w += tx_over_w_dx;
                         ty_over_w += ty_over_w_dx;
@@ -1348,6 +5571,10 @@ impl Rasterizer {
                         u_over_w += u_over_w_dx;
                         v_over_w += v_over_w_dx;
                     }
+                    if lightmap.is_some() {
+                        u2_over_w += u2_over_w_dx;
+                        v2_over_w += v2_over_w_dx;
+                    }
                     if HAS_COLOR_BUFFER {
                         unsafe {
                             color_ptr = color_ptr.add(1);
@@ -1358,25 +5585,48 @@ impl Rasterizer {
                             depth_ptr = depth_ptr.add(1);
                         }
                     }
+                    if DEPTH_MODE == DepthProcessingMode::Float as u8 {
+                        rz += rz_dx;
+                        unsafe {
+                            depth_f32_ptr = depth_f32_ptr.add(1);
+                        }
+                    }
                     if NORMALS_PROCESSING >= NormalsProcessingMode::Vertex as u8 {
                         unsafe {
                             normal_ptr = normal_ptr.add(1);
                         }
                     }
+                    if writes_motion {
+                        unsafe {
+                            motion_ptr = motion_ptr.add(1);
+                        }
+                    }
                 }
-                depth_edges_24_8_row = depth_edges_24_8_row.add(depth_edges_24_8_dy);
+                depth_edges_24_8_row += depth_edges_24_8_dy;
                 inv_w_row += inv_w_dy;
                 if COLOR_INTERPOLATION_MODE == VerticesColorInterpolationMode::PerVertex as u8 {
-                    r_over_w_row += r_over_w_dy;
-                    g_over_w_row += g_over_w_dy;
-                    b_over_w_row += b_over_w_dy;
-                    a_over_w_row += a_over_w_dy;
+                    if fixed_point_colors {
+                        r_fx_row += r_fx_dy;
+                        g_fx_row += g_fx_dy;
+                        b_fx_row += b_fx_dy;
+                        a_fx_row += a_fx_dy;
+                    } else {
+                        r_over_w_row += r_over_w_dy;
+                        g_over_w_row += g_over_w_dy;
+                        b_over_w_row += b_over_w_dy;
+                        a_over_w_row += a_over_w_dy;
+                    }
                 }
-                if NORMALS_PROCESSING >= NormalsProcessingMode::Vertex as u8 {
+                if interpolates_normals {
                     nx_over_w_row += nx_over_w_dy;
                     ny_over_w_row += ny_over_w_dy;
                     nz_over_w_row += nz_over_w_dy;
                 }
+                if interpolates_positions {
+                    px_over_w_row += px_over_w_dy;
+                    py_over_w_row += py_over_w_dy;
+                    pz_over_w_row += pz_over_w_dy;
+                }
                 if NORMALS_PROCESSING == NormalsProcessingMode::NormalMapping as u8 {
                     tx_over_w_row += tx_over_w_dy;
                     ty_over_w_row += ty_over_w_dy;
@@ -1386,6 +5636,10 @@ impl Rasterizer {
                     u_over_w_row += u_over_w_dy;
                     v_over_w_row += v_over_w_dy;
                 }
+                if lightmap.is_some() {
+                    u2_over_w_row += u2_over_w_dy;
+                    v2_over_w_row += v2_over_w_dy;
+                }
                 if HAS_COLOR_BUFFER {
                     unsafe {
                         color_row_ptr = color_row_ptr.add(Framebuffer::TILE_WITH as usize);
@@ -1396,130 +5650,798 @@ impl Rasterizer {
                         depth_row_ptr = depth_row_ptr.add(Framebuffer::TILE_WITH as usize);
                     }
                 }
+                if DEPTH_MODE == DepthProcessingMode::Float as u8 {
+                    rz_row += rz_dy;
+                    unsafe {
+                        depth_f32_row_ptr = depth_f32_row_ptr.add(Framebuffer::TILE_WITH as usize);
+                    }
+                }
                 if NORMALS_PROCESSING >= NormalsProcessingMode::Vertex as u8 {
                     unsafe {
                         normal_row_ptr = normal_row_ptr.add(Framebuffer::TILE_WITH as usize);
                     }
                 }
-            } // end of the vertical loop
+                if writes_motion {
+                    unsafe {
+                        motion_row_ptr = motion_row_ptr.add(Framebuffer::TILE_WITH as usize);
+                    }
+                }
+            } // end of the vertical loop
+        }
+        statistics
+    }
+
+    pub fn statistics(&self) -> RasterizerStatistics {
+        self.stats
+    }
+
+    // The viewport the rasterizer was set up with.
+    pub fn viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    pub fn set_debug_coloring(&mut self, debug_coloring: bool) {
+        self.debug_coloring = debug_coloring;
+    }
+
+    pub fn set_draw_wireframe(&mut self, draw_wireframe: bool) {
+        self.draw_wireframe = draw_wireframe;
+    }
+
+    // Forces the fast math mode for all subsequently committed commands, regardless of RasterizationCommand::fast_math.
+    pub fn set_fast_math(&mut self, fast_math: bool) {
+        self.fast_math = fast_math;
+    }
+
+    // Sets the maximum relative variation of w across a triangle, max(w) / min(w) - 1, below which the fast math mode
+    // skips the perspective correction.
+    // Default: DEFAULT_FAST_MATH_W_THRESHOLD.
+    pub fn set_fast_math_w_threshold(&mut self, threshold: f32) {
+        assert!(threshold >= 0.0);
+        self.fast_math_w_threshold = threshold;
+    }
+
+    // Sets how often the perspective correction is performed along a row of pixels.
+    // With span > 1, w is calculated exactly only every span pixels and is linearly interpolated in between, which
+    // trades some accuracy for fewer per-fragment divisions.
+    // Default: 1, i.e. the perspective correction is performed for every pixel.
+    pub fn set_perspective_span(&mut self, span: u32) {
+        assert!(span >= 1);
+        self.perspective_span = span;
+    }
+
+    // Sets whether the per-fragment w is calculated via a hardware reciprocal estimate refined by a Newton-Raphson step
+    // instead of a full-precision division. The result is within ~2^-22 of the exact value, which may occasionally
+    // flip the rounding of the interpolated attributes.
+    // Default: false.
+    pub fn set_fast_reciprocal(&mut self, fast_reciprocal: bool) {
+        self.fast_reciprocal = fast_reciprocal;
+    }
+
+    // Sets whether opaque untextured triangles drawn without the depth and normal buffers, e.g. UI panels, take a
+    // specialized path which fills whole spans with SIMD stores instead of shading each fragment separately.
+    // Default: true.
+    pub fn set_opaque_fills_fast_path(&mut self, enabled: bool) {
+        self.opaque_fills_fast_path = enabled;
+    }
+
+    // Sets whether triangles entirely behind the depth buffer's contents should be skipped per tile before rasterization.
+    // The test uses the farthest depth of each 8x8 block of pixels, it's only performed when there's a depth buffer.
+    // The blocks are refreshed after each batch of a command's triangles, so a triangle can be rejected by the ones
+    // drawn before it in the previous batches, commands or draws, but not by the ones in its own batch.
+    // The rejected triangles are counted in RasterizerStatistics::hiz_rejected_triangles.
+    // Default: true.
+    pub fn set_hierarchical_z(&mut self, enabled: bool) {
+        self.hierarchical_z = enabled;
+    }
+
+    // Sets the number of binned triangles above which a tile is split into SUB_TILE_WIDTH x SUB_TILE_HEIGHT sub-tiles,
+    // each drawn as a separate job with only the triangles overlapping it, so that a single tile with lots of small
+    // triangles doesn't become the critical path of draw(). The sub-tiles don't overlap, the output is the same.
+    // The split tiles are counted in RasterizerStatistics::split_tiles.
+    // Default: DEFAULT_TILE_SPLITTING_THRESHOLD, usize::MAX disables the splitting.
+    pub fn set_tile_splitting_threshold(&mut self, threshold: usize) {
+        self.tile_splitting_threshold = threshold;
+    }
+
+    // Sets whether the triangles crossing the sides of the viewport should be rasterized as they are as long as they
+    // are within the guard band around it, instead of being clipped. Only the triangles exceeding the guard band or
+    // crossing the near or the far plane go through the clipper then, see RasterizerStatistics::clipped_triangles.
+    // The guard band is as wide as the fixed-point rasterization allows for the viewport's size, it shrinks down to the
+    // viewport for the viewports of about 4 megapixels. The textured triangles and the ones drawn with the affine fast
+    // math or the perspective spans are still clipped against the viewport's sides, as their mip levels and
+    // interpolation are set up for the whole triangle, see RasterizerStatistics::side_clipped_triangles.
+    // Default: true.
+    pub fn set_guard_band_clipping(&mut self, enabled: bool) {
+        self.guard_band_clipping = enabled;
+    }
+
+    // Sets the area in pixels below which the projected triangles are discarded by commit() instead of being binned and
+    // rasterized. The triangles smaller than half a pixel are never drawn, so lower values are raised to that. Unlike
+    // the triangles that can't cover any pixel center, which are always discarded, the larger triangles may still
+    // cover some pixels, so raising the threshold trades small holes in dense meshes for the time saved on their setup.
+    // The discarded triangles are counted in RasterizerStatistics::micro_triangles.
+    // Default: 0.5.
+    pub fn set_micro_triangle_area_threshold(&mut self, area: f32) {
+        self.micro_triangle_area_threshold = area.max(Self::MIN_TRIANGLE_AREA);
+    }
+
+    // Enables reordering the triangles of each tile by depth according to their commands' opacity hints, see
+    // RasterizationCommand::opacity. Drawing opaque triangles front-to-back lets the depth test and the hierarchical Z
+    // test discard more of the hidden ones, drawing translucent triangles back-to-front blends them correctly.
+    // Default: false, i.e. the submission order is always preserved.
+    pub fn set_depth_sorting(&mut self, enabled: bool) {
+        self.depth_sorting = enabled;
+    }
+
+    // Sets whether draw() should process the tiles in parallel and commit() should assemble the triangles of large
+    // commands in parallel. When disabled, the tiles are drawn one by one on the calling thread in the row-major order,
+    // which is slower but makes a reference to compare the parallel output against, see draw_checking_determinism().
+    // The committed triangles are scheduled and binned in the same order either way.
+    // Default: true.
+    pub fn set_multithreading(&mut self, enabled: bool) {
+        self.multithreading = enabled;
+    }
+
+    // Sets whether the work should be scheduled in a stable order, so that the statistics are reproducible, e.g. for
+    // benchmarks on CI and for the tests comparing the statistics against the expected values. The tiles and their
+    // sub-tiles are drawn in the row-major order, in parallel if the multithreading is enabled, and their statistics are
+    // reduced in that order. The large commands are committed in the same chunks regardless of the multithreading, so
+    // the statistics match between the single-threaded and the parallel runs too. Costs the load balancing of drawing
+    // the busiest tiles first.
+    // The fragments_drawn counter is gathered only in Debug builds, so the Release builds reproduce it as zero.
+    // Default: false.
+    pub fn set_deterministic_scheduling(&mut self, enabled: bool) {
+        self.deterministic_scheduling = enabled;
+    }
+
+    // Sets whether the committed commands should be recorded, so that the frame can be serialized and replayed later,
+    // see RecordedFrame. The recording starts over with each setup(), the frame is taken by take_recorded_frame().
+    // Costs copying the commands' vertices on commit.
+    // Default: false.
+    pub fn set_recording(&mut self, enabled: bool) {
+        self.recording = if enabled {
+            Some(RecordedFrame::new(self.viewport))
+        } else {
+            None
+        };
+    }
+
+    // Takes the commands recorded since the last setup(), the recording goes on with an empty frame.
+    // None if the recording is disabled.
+    pub fn take_recorded_frame(&mut self) -> Option<RecordedFrame> {
+        let viewport: Viewport = self.viewport;
+        self.recording
+            .as_mut()
+            .map(|recording| std::mem::replace(recording, RecordedFrame::new(viewport)))
+    }
+
+    // Sets whether the committed geometry should be additionally kept in world space, so that it can be drawn into
+    // multiple views with draw_views(). Costs the memory and the time of copying the processed vertices on commit.
+    // Should be set before committing, the geometry committed while it was disabled is not retained.
+    // Default: false.
+    pub fn set_retain_geometry(&mut self, enabled: bool) {
+        self.retain_geometry = enabled;
+    }
+
+    // The guard band to schedule the command's triangles with, if enabled.
+    // The textures' mip levels and the perspective approximations, i.e. the affine fast math and the perspective spans,
+    // are set up per triangle from its projected extents, which differ from the ones of its part within the viewport.
+    // Such triangles are still clipped against the viewport's sides, they're counted in
+    // RasterizerStatistics::side_clipped_triangles if they were within the band.
+    fn guard_band(&self, command: &ScheduledCommand) -> Option<GuardBand> {
+        if !self.guard_band_clipping {
+            return None;
+        }
+        Some(GuardBand {
+            extents: self.guard_band_extents,
+            clip_sides: command.has_per_triangle_setup() || self.perspective_span > 1,
+        })
+    }
+
+    // Calculates the extents in NDC of the widest guard band around the viewport whose area is within
+    // MAX_TRIANGLE_BOUNDS_AREA, i.e. the margin g solving (width + 2g) * (height + 2g) = MAX_TRIANGLE_BOUNDS_AREA.
+    fn guard_band_extents(viewport: Viewport) -> Vec2 {
+        let width: f32 = (viewport.xmax - viewport.xmin).max(1) as f32;
+        let height: f32 = (viewport.ymax - viewport.ymin).max(1) as f32;
+        let b: f32 = width + height;
+        let c: f32 = width * height - Self::MAX_TRIANGLE_BOUNDS_AREA;
+        let margin: f32 = ((-b + (b * b - 4.0 * c).max(0.0).sqrt()) / 4.0).max(0.0);
+        Vec2::new(1.0 + 2.0 * margin / width, 1.0 + 2.0 * margin / height)
+    }
+
+    fn draw_wireframe(&mut self, framebuffer: &mut Framebuffer) {
+        let mut lines = Vec::<Vec2>::new();
+        for i in 0..self.geometry.triangles_num() {
+            let [v0, v1, v2] = self.geometry.triangle(i);
+            lines.push(Vec2::new(v0.position.x, v0.position.y));
+            lines.push(Vec2::new(v1.position.x, v1.position.y));
+            lines.push(Vec2::new(v1.position.x, v1.position.y));
+            lines.push(Vec2::new(v2.position.x, v2.position.y));
+            lines.push(Vec2::new(v2.position.x, v2.position.y));
+            lines.push(Vec2::new(v0.position.x, v0.position.y));
+        }
+        draw_screen_lines_unclipped(framebuffer, &lines, Vec4::new(1.0, 1.0, 1.0, 1.0));
+    }
+}
+
+type DrawTrianglesFn =
+    fn(&Rasterizer, &mut FramebufferTile, Viewport, &[Vertex], &ScheduledCommand) -> PerTileStatistics;
+
+fn panicking_draw_triangles(
+    _: &Rasterizer,
+    _: &mut FramebufferTile,
+    _: Viewport,
+    _: &[Vertex],
+    _: &ScheduledCommand,
+) -> PerTileStatistics {
+    panic!("Dummy, should never be called");
+}
+
+const DRAW_TRIANGLE_FUNCTIONS_NUM: usize = 1152;
+const DRAW_TRIANGLE_FUNCTIONS: [DrawTrianglesFn; DRAW_TRIANGLE_FUNCTIONS_NUM] = {
+    let mut functions: [DrawTrianglesFn; DRAW_TRIANGLE_FUNCTIONS_NUM] =
+        [panicking_draw_triangles; DRAW_TRIANGLE_FUNCTIONS_NUM];
+    macro_rules! draw_triangles_instantiate_function {
+            ($t:expr, $i:expr, $a:expr, $b:expr, $c:expr, $d:expr, $e:expr, $f:expr, $g:expr, $h:expr) => {
+                $t[$i] = Rasterizer::draw_triangles::<$a, $b, $c, $d, $e, $f, $g, $h>;
+                $i += 1;
+            };
+        }
+    macro_rules! draw_triangles_per_color_interpolation_mode {
+        ($t:expr, $i:expr, $a:expr, $b:expr, $c:expr, $d:expr, $e:expr, $f:expr, $g:expr) => {
+            draw_triangles_instantiate_function!($t, $i, $a, $b, $c, $d, $e, $f, $g, 0u8);
+            draw_triangles_instantiate_function!($t, $i, $a, $b, $c, $d, $e, $f, $g, 1u8);
+            draw_triangles_instantiate_function!($t, $i, $a, $b, $c, $d, $e, $f, $g, 2u8);
+        };
+    }
+    macro_rules! draw_triangles_per_alpha_test_enabled {
+        ($t:expr, $i:expr, $a:expr, $b:expr, $c:expr, $d:expr, $e:expr, $f:expr) => {
+            draw_triangles_per_color_interpolation_mode!($t, $i, $a, $b, $c, $d, $e, $f, false);
+            draw_triangles_per_color_interpolation_mode!($t, $i, $a, $b, $c, $d, $e, $f, true);
+        };
+    }
+    macro_rules! draw_triangles_per_alpha_blending {
+        ($t:expr, $i:expr, $a:expr, $b:expr, $c:expr, $d:expr, $e:expr) => {
+            draw_triangles_per_alpha_test_enabled!($t, $i, $a, $b, $c, $d, $e, 0u8);
+            draw_triangles_per_alpha_test_enabled!($t, $i, $a, $b, $c, $d, $e, 1u8);
+            draw_triangles_per_alpha_test_enabled!($t, $i, $a, $b, $c, $d, $e, 2u8);
+            draw_triangles_per_alpha_test_enabled!($t, $i, $a, $b, $c, $d, $e, 3u8);
+        };
+    }
+    macro_rules! draw_triangles_per_has_texture {
+        ($t:expr, $i:expr, $a:expr, $b:expr, $c:expr, $d:expr) => {
+            draw_triangles_per_alpha_blending!($t, $i, $a, $b, $c, $d, false);
+            draw_triangles_per_alpha_blending!($t, $i, $a, $b, $c, $d, true);
+        };
+    }
+    macro_rules! draw_triangles_per_normal_processing {
+        ($t:expr, $i:expr, $a:expr, $b:expr, $c:expr) => {
+            draw_triangles_per_has_texture!($t, $i, $a, $b, $c, 0u8);
+            draw_triangles_per_has_texture!($t, $i, $a, $b, $c, 1u8);
+            draw_triangles_per_has_texture!($t, $i, $a, $b, $c, 2u8);
+        };
+    }
+    // No depth buffer, the default Less test with the depth write, any other test or no depth write, floating-point depth
+    macro_rules! draw_triangles_per_has_depth {
+        ($t:expr, $i:expr, $a:expr) => {
+            draw_triangles_per_normal_processing!($t, $i, $a, false, { DepthProcessingMode::Default as u8 });
+            draw_triangles_per_normal_processing!($t, $i, $a, true, { DepthProcessingMode::Default as u8 });
+            draw_triangles_per_normal_processing!($t, $i, $a, true, { DepthProcessingMode::Configurable as u8 });
+            draw_triangles_per_normal_processing!($t, $i, $a, false, { DepthProcessingMode::Float as u8 });
+        };
+    }
+    macro_rules! draw_triangles_per_has_color {
+        ($t:expr, $i:expr) => {
+            draw_triangles_per_has_depth!($t, $i, false);
+            draw_triangles_per_has_depth!($t, $i, true);
+        };
+    }
+
+    let mut index: usize = 0;
+    draw_triangles_per_has_color!(functions, index);
+    let _ = index;
+    functions
+};
+
+// Un-premultiplies the texel's color and makes it opaque, for the alpha sources ignoring the texture's alpha.
+#[inline(always)]
+fn opaque_texel(texel: RGBA) -> RGBA {
+    if texel.a == 255 {
+        return texel;
+    }
+    if texel.a == 0 {
+        return RGBA::new(0, 0, 0, 255);
+    }
+    let unpremultiply = |c: u8| -> u8 { ((c as u32 * 255 + texel.a as u32 / 2) / texel.a as u32).min(255) as u8 };
+    RGBA::new(unpremultiply(texel.r), unpremultiply(texel.g), unpremultiply(texel.b), 255)
+}
+
+// Blends the 8-bit source color premultiplied by alpha into the 8-bit destination one, in floating point.
+#[inline(always)]
+fn blend_rgba8(alpha_blending: AlphaBlendingMode, src: RGBA, dest: u32) -> u32 {
+    let src: Vec4 = Vec4::new(src.r as f32, src.g as f32, src.b as f32, src.a as f32) * (1.0 / 255.0);
+    let dest: RGBA = RGBA::from_u32(dest);
+    let dest: Vec3 = Vec3::new(dest.r as f32, dest.g as f32, dest.b as f32) * (1.0 / 255.0);
+    let blended: Vec3 = alpha_blending.blend(src, dest);
+    let quantize = |c: f32| -> u8 { (c * 255.0 + 0.5).clamp(0.0, 255.0) as u8 };
+    RGBA::new(quantize(blended.x), quantize(blended.y), quantize(blended.z), 255).to_u32()
+}
+
+// The memory allocated by the vector, in bytes.
+fn vec_bytes<T>(vec: &Vec<T>) -> usize {
+    vec.capacity() * std::mem::size_of::<T>()
+}
+
+fn debug_color(idx: u32) -> Vec4 {
+    fn hash(mut x: u32) -> u32 {
+        x = (x ^ 61) ^ (x >> 16);
+        x = x.wrapping_add(x << 3);
+        x ^= x >> 4;
+        x = x.wrapping_mul(0x27d4eb2d);
+        x ^ (x >> 15)
+    }
+    let h = hash(idx);
+    let r = (h & 0xff) as u8;
+    let g = ((h >> 8) & 0xff) as u8;
+    let b = ((h >> 16) & 0xff) as u8;
+    Vec4::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0)
+}
+
+// The per-batch state of the binning shared by the threads binning the batch's triangles.
+struct TriangleBinning<'a> {
+    geometry: &'a TriangleList,
+    // The index of the scheduled command the triangles are drawn with
+    command: u16,
+    scissor: Option<Viewport>,
+    first_tile_x: u32,
+    first_tile_y: u32,
+    tiles_x: u32,
+    tiles_y: u32,
+}
+
+// The per-command state of commit() shared by the threads assembling the command's triangles.
+// The guard band the triangles are scheduled with, see Rasterizer::guard_band().
+#[derive(Debug, Clone, Copy)]
+struct GuardBand {
+    // The extents of the band in NDC, (1, 1) is the viewport itself
+    extents: Vec2,
+    // Whether the triangles crossing the viewport's sides are clipped against them anyway
+    clip_sides: bool,
+}
+
+struct TriangleAssembly<'a, 'c> {
+    command: &'a RasterizationCommand<'c>,
+    // The command's transformed vertices, indexed the same way as its world positions
+    transformed: &'a [TransformedVertex],
+    command_color: Vec4,
+    is_command_color_defined: bool,
+    viewport_scale: ViewportScale,
+    scissored_out: bool,
+    retain_geometry: bool,
+    micro_triangle_area_threshold: f32,
+    guard_band: Option<GuardBand>,
+    // The camera's position in world space, only needed for the vertex lighting
+    eye: Vec3,
+}
+
+// What the colors of a range of assembled triangles require from the color interpolation, by the triangles' indices.
+// The interpolation is pessimized up to Fixed by the first triangle with a color other than white, and up to PerVertex
+// by a triangle with different per-vertex colors which comes after it.
+#[derive(Debug, Clone, Copy, Default)]
+struct AssembledColors {
+    first_colored: Option<usize>,
+    last_varying: Option<usize>,
+}
+
+// What happened to the triangles in Rasterizer::schedule_triangle(): the numbers of the ones passed through the clipper
+// and of the projected ones discarded before binning.
+#[derive(Debug, Clone, Copy, Default)]
+struct SchedulingCounters {
+    clipped: usize,
+    side_clipped: usize,
+    zero_area: usize,
+    micro: usize,
+}
+
+impl SchedulingCounters {
+    fn merge(self, other: SchedulingCounters) -> SchedulingCounters {
+        SchedulingCounters {
+            clipped: self.clipped + other.clipped,
+            side_clipped: self.side_clipped + other.side_clipped,
+            zero_area: self.zero_area + other.zero_area,
+            micro: self.micro + other.micro,
+        }
+    }
+
+    fn add_to(&self, stats: &mut RasterizerStatistics) {
+        stats.clipped_triangles += self.clipped;
+        stats.side_clipped_triangles += self.side_clipped;
+        stats.zero_area_triangles += self.zero_area;
+        stats.micro_triangles += self.micro;
+    }
+}
+
+impl AssembledColors {
+    fn merge(self, other: AssembledColors) -> AssembledColors {
+        AssembledColors {
+            first_colored: match (self.first_colored, other.first_colored) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+            last_varying: self.last_varying.max(other.last_varying),
+        }
+    }
+
+    fn color_interpolation_mode(&self) -> VerticesColorInterpolationMode {
+        match (self.first_colored, self.last_varying) {
+            (None, _) => VerticesColorInterpolationMode::None,
+            (Some(first_colored), Some(last_varying)) if last_varying >= first_colored => {
+                VerticesColorInterpolationMode::PerVertex
+            }
+            _ => VerticesColorInterpolationMode::Fixed,
+        }
+    }
+}
+
+impl TriangleBinning<'_> {
+    // Passes each triangle of the range to push() along with the index of every tile it may overlap, in the order of
+    // the triangles. Returns the number of the binned triangles, counted per tile.
+    fn bin(&self, triangles: std::ops::Range<usize>, mut push: impl FnMut(usize, ScheduledTriangle)) -> usize {
+        let mut binned: usize = 0;
+        for tri_idx in triangles {
+            let [v0, v1, v2] = self.geometry.triangle(tri_idx);
+            let mut v_xmin = v0.position.x.min(v1.position.x).min(v2.position.x) as u32;
+            let mut v_xmax = v0.position.x.max(v1.position.x).max(v2.position.x) as u32;
+            let mut v_ymin = v0.position.y.min(v1.position.y).min(v2.position.y) as u32;
+            let mut v_ymax = v0.position.y.max(v1.position.y).max(v2.position.y) as u32;
+            if let Some(scissor) = self.scissor {
+                // Bin only into the tiles overlapping the scissored part of the triangle's bounds
+                v_xmin = v_xmin.max(scissor.xmin as u32);
+                v_ymin = v_ymin.max(scissor.ymin as u32);
+                v_xmax = v_xmax.min(scissor.xmax as u32 - 1);
+                v_ymax = v_ymax.min(scissor.ymax as u32 - 1);
+                if v_xmin > v_xmax || v_ymin > v_ymax {
+                    continue;
+                }
+            }
+            // TODO: add less crude discarding by running simple edge functions
+            // TODO: check if this min() is required
+            let ind_xmin = (v_xmin / Rasterizer::TILE_WIDTH as u32)
+                .saturating_sub(self.first_tile_x)
+                .min(self.tiles_x - 1);
+            let ind_ymin = (v_ymin / Rasterizer::TILE_HEIGHT as u32)
+                .saturating_sub(self.first_tile_y)
+                .min(self.tiles_y - 1);
+            let ind_xmax = (v_xmax / Rasterizer::TILE_WIDTH as u32)
+                .saturating_sub(self.first_tile_x)
+                .min(self.tiles_x - 1);
+            let ind_ymax = (v_ymax / Rasterizer::TILE_HEIGHT as u32)
+                .saturating_sub(self.first_tile_y)
+                .min(self.tiles_y - 1);
+            if ind_xmin == ind_xmax || ind_ymin == ind_ymax {
+                // The triangle is fully contained in a single tile or it a horizontal or vertical line, bin it in the appropriate tiles.
+                // No additional overlap checks are required.
+                for ind_y in ind_ymin..=ind_ymax {
+                    for ind_x in ind_xmin..=ind_xmax {
+                        push(
+                            ind_y as usize * self.tiles_x as usize + ind_x as usize,
+                            ScheduledTriangle { cmd: self.command, tri: tri_idx as u16 },
+                        );
+                        binned += 1;
+                    }
+                }
+            } else {
+                // The triangle spans 2x2 or more tiles, bin in the appropriate tiles, but only after running simple edge functions check
+                let iv0_x_24_8 = (v0.position.x * 256.0).round() as i32;
+                let iv0_y_24_8 = (v0.position.y * 256.0).round() as i32;
+                let iv1_x_24_8 = (v1.position.x * 256.0).round() as i32;
+                let iv1_y_24_8 = (v1.position.y * 256.0).round() as i32;
+                let iv2_x_24_8 = (v2.position.x * 256.0).round() as i32;
+                let iv2_y_24_8 = (v2.position.y * 256.0).round() as i32;
+                let iv01_x_24_8 = iv1_x_24_8 - iv0_x_24_8;
+                let iv01_y_24_8 = iv1_y_24_8 - iv0_y_24_8;
+                let iv12_x_24_8 = iv2_x_24_8 - iv1_x_24_8;
+                let iv12_y_24_8 = iv2_y_24_8 - iv1_y_24_8;
+                let iv20_x_24_8 = iv0_x_24_8 - iv2_x_24_8;
+                let iv20_y_24_8 = iv0_y_24_8 - iv2_y_24_8;
+                let is_tile_fully_outside = |tile_bounds: TileBinningBounds| {
+                    let iv1_xmin_24_8 = tile_bounds.xmin_24_8 - iv1_x_24_8;
+                    let iv1_ymin_24_8 = tile_bounds.ymin_24_8 - iv1_y_24_8;
+                    let iv1_xmax_24_8 = tile_bounds.xmax_24_8 - iv1_x_24_8;
+                    let iv1_ymax_24_8 = tile_bounds.ymax_24_8 - iv1_y_24_8;
+                    let iv2_xmin_24_8 = tile_bounds.xmin_24_8 - iv2_x_24_8;
+                    let iv2_ymin_24_8 = tile_bounds.ymin_24_8 - iv2_y_24_8;
+                    let iv2_xmax_24_8 = tile_bounds.xmax_24_8 - iv2_x_24_8;
+                    let iv2_ymax_24_8 = tile_bounds.ymax_24_8 - iv2_y_24_8;
+                    let iv0_xmin_24_8 = tile_bounds.xmin_24_8 - iv0_x_24_8;
+                    let iv0_ymin_24_8 = tile_bounds.ymin_24_8 - iv0_y_24_8;
+                    let iv0_xmax_24_8 = tile_bounds.xmax_24_8 - iv0_x_24_8;
+                    let iv0_ymax_24_8 = tile_bounds.ymax_24_8 - iv0_y_24_8;
+                    let e0_lb = iv12_x_24_8 as i64 * iv1_ymin_24_8 as i64 - iv12_y_24_8 as i64 * iv1_xmin_24_8 as i64;
+                    let e0_rb = iv12_x_24_8 as i64 * iv1_ymin_24_8 as i64 - iv12_y_24_8 as i64 * iv1_xmax_24_8 as i64;
+                    let e0_lt = iv12_x_24_8 as i64 * iv1_ymax_24_8 as i64 - iv12_y_24_8 as i64 * iv1_xmin_24_8 as i64;
+                    let e0_rt = iv12_x_24_8 as i64 * iv1_ymax_24_8 as i64 - iv12_y_24_8 as i64 * iv1_xmax_24_8 as i64;
+                    let e1_lb = iv20_x_24_8 as i64 * iv2_ymin_24_8 as i64 - iv20_y_24_8 as i64 * iv2_xmin_24_8 as i64;
+                    let e1_rb = iv20_x_24_8 as i64 * iv2_ymin_24_8 as i64 - iv20_y_24_8 as i64 * iv2_xmax_24_8 as i64;
+                    let e1_lt = iv20_x_24_8 as i64 * iv2_ymax_24_8 as i64 - iv20_y_24_8 as i64 * iv2_xmin_24_8 as i64;
+                    let e1_rt = iv20_x_24_8 as i64 * iv2_ymax_24_8 as i64 - iv20_y_24_8 as i64 * iv2_xmax_24_8 as i64;
+                    let e2_lb = iv01_x_24_8 as i64 * iv0_ymin_24_8 as i64 - iv01_y_24_8 as i64 * iv0_xmin_24_8 as i64;
+                    let e2_rb = iv01_x_24_8 as i64 * iv0_ymin_24_8 as i64 - iv01_y_24_8 as i64 * iv0_xmax_24_8 as i64;
+                    let e2_lt = iv01_x_24_8 as i64 * iv0_ymax_24_8 as i64 - iv01_y_24_8 as i64 * iv0_xmin_24_8 as i64;
+                    let e2_rt = iv01_x_24_8 as i64 * iv0_ymax_24_8 as i64 - iv01_y_24_8 as i64 * iv0_xmax_24_8 as i64;
+                    (e0_lb < 0 && e0_rb < 0 && e0_lt < 0 && e0_rt < 0)
+                        || (e1_lb < 0 && e1_rb < 0 && e1_lt < 0 && e1_rt < 0)
+                        || (e2_lb < 0 && e2_rb < 0 && e2_lt < 0 && e2_rt < 0)
+                };
+
+                for ind_y in ind_ymin..=ind_ymax {
+                    for ind_x in ind_xmin..=ind_xmax {
+                        let tile_bounds =
+                            TileBinningBounds::of_tile(self.first_tile_x + ind_x, self.first_tile_y + ind_y);
+                        if is_tile_fully_outside(tile_bounds) {
+                            continue;
+                        }
+                        push(
+                            ind_y as usize * self.tiles_x as usize + ind_x as usize,
+                            ScheduledTriangle { cmd: self.command, tri: tri_idx as u16 },
+                        );
+                        binned += 1;
+                    }
+                }
+            }
         }
-        statistics
+        binned
     }
+}
 
-    pub fn statistics(&self) -> RasterizerStatistics {
-        self.stats
-    }
+impl TriangleAssembly<'_, '_> {
+    // Assembles the range of the command's triangles, appends the scheduled ones to the triangle list and, if the
+    // geometry is retained, all of them in world space to the retained vertices. Returns the requirements of their
+    // colors and the counters of their scheduling.
+    fn assemble(
+        &self,
+        triangles: std::ops::Range<usize>,
+        geometry: &mut TriangleList,
+        retained_vertices: &mut Vec<Vertex>,
+    ) -> (AssembledColors, SchedulingCounters) {
+        let mut colors = AssembledColors::default();
+        let mut counters = SchedulingCounters::default();
+        for i in triangles {
+            let index = |n: usize| {
+                if self.command.indices.is_empty() {
+                    i * 3 + n
+                } else {
+                    self.command.indices[i * 3 + n] as usize
+                }
+            };
+            let i0: usize = index(0);
+            let i1: usize = index(1);
+            let i2: usize = index(2);
+            let transformed: [TransformedVertex; 3] =
+                [self.transformed[i0], self.transformed[i1], self.transformed[i2]];
 
-    pub fn set_debug_coloring(&mut self, debug_coloring: bool) {
-        self.debug_coloring = debug_coloring;
-    }
+            // Fill world positions of the triangle vertices.
+            let world_positions: [Vec3; 3] =
+                [transformed[0].world_position, transformed[1].world_position, transformed[2].world_position];
 
-    pub fn set_draw_wireframe(&mut self, draw_wireframe: bool) {
-        self.draw_wireframe = draw_wireframe;
-    }
+            let mut input_vertices: [Vertex; 3] = [Vertex::default(); 3];
+            input_vertices[0].world_position = world_positions[0];
+            input_vertices[1].world_position = world_positions[1];
+            input_vertices[2].world_position = world_positions[2];
 
-    fn draw_wireframe(&mut self, framebuffer: &mut Framebuffer) {
-        let mut lines = Vec::<Vec2>::new();
-        for i in (0..self.vertices.len()).step_by(3) {
-            lines.push(Vec2::new(self.vertices[i + 0].position.x, self.vertices[i + 0].position.y));
-            lines.push(Vec2::new(self.vertices[i + 1].position.x, self.vertices[i + 1].position.y));
-            lines.push(Vec2::new(self.vertices[i + 1].position.x, self.vertices[i + 1].position.y));
-            lines.push(Vec2::new(self.vertices[i + 2].position.x, self.vertices[i + 2].position.y));
-            lines.push(Vec2::new(self.vertices[i + 2].position.x, self.vertices[i + 2].position.y));
-            lines.push(Vec2::new(self.vertices[i + 0].position.x, self.vertices[i + 0].position.y));
+            // Fill projected positions in NDC space [-1, 1].
+            input_vertices[0].position = transformed[0].position;
+            input_vertices[1].position = transformed[1].position;
+            input_vertices[2].position = transformed[2].position;
+
+            // Fill per-vertex texture coordinates.
+            if self.command.tex_coords.is_empty() {
+                input_vertices[0].tex_coord = Vec2::new(0.0, 0.0);
+                input_vertices[1].tex_coord = Vec2::new(0.0, 0.0);
+                input_vertices[2].tex_coord = Vec2::new(0.0, 0.0);
+            } else {
+                input_vertices[0].tex_coord = self.command.tex_coords[i0];
+                input_vertices[1].tex_coord = self.command.tex_coords[i1];
+                input_vertices[2].tex_coord = self.command.tex_coords[i2];
+            }
+            if !self.command.tex_coords2.is_empty() {
+                input_vertices[0].tex_coord2 = self.command.tex_coords2[i0];
+                input_vertices[1].tex_coord2 = self.command.tex_coords2[i1];
+                input_vertices[2].tex_coord2 = self.command.tex_coords2[i2];
+            }
+
+            // Fill normals, either with rotated input normals or derived from the triangle face.
+            if self.command.normals.is_empty() {
+                // Derive a uniform non-smooth normal vector from the triangle's vertices.
+                let edge1 = world_positions[1] - world_positions[0];
+                let edge2 = world_positions[2] - world_positions[0];
+                let face_normal = cross(edge1, edge2).normalized();
+                input_vertices[0].normal = face_normal;
+                input_vertices[1].normal = face_normal;
+                input_vertices[2].normal = face_normal;
+            } else {
+                input_vertices[0].normal = transformed[0].normal;
+                input_vertices[1].normal = transformed[1].normal;
+                input_vertices[2].normal = transformed[2].normal;
+            }
+
+            // TODO: support pre-defined smooth per-vertex tangents
+            {
+                // Derive a uniform non-smooth tangent vector from the triangle's vertices.
+                let uv1: Vec2 = input_vertices[1].tex_coord - input_vertices[0].tex_coord;
+                let uv2: Vec2 = input_vertices[2].tex_coord - input_vertices[0].tex_coord;
+                let e1: Vec3 = world_positions[1] - world_positions[0];
+                let e2: Vec3 = world_positions[2] - world_positions[0];
+                let denom: f32 = uv1.x * uv2.y - uv1.y * uv2.x;
+                let tangent: Vec3 = if denom.abs() > 0.000001 {
+                    let r: f32 = 1.0 / denom;
+                    (e1 * uv2.y - e2 * uv1.y) * r
+                } else {
+                    Vec3::new(1.0, 0.0, 0.0)
+                };
+                let n0 = input_vertices[0].normal;
+                let n1 = input_vertices[1].normal;
+                let n2 = input_vertices[2].normal;
+                input_vertices[0].tangent = (tangent - n0 * n0.dot(tangent)).normalized();
+                input_vertices[1].tangent = (tangent - n1 * n1.dot(tangent)).normalized();
+                input_vertices[2].tangent = (tangent - n2 * n2.dot(tangent)).normalized();
+            }
+
+            // Fill per-vertex colors.
+            if self.command.colors.is_empty() {
+                input_vertices[0].color = self.command_color;
+                input_vertices[1].color = self.command_color;
+                input_vertices[2].color = self.command_color;
+            } else {
+                input_vertices[0].color = self.command.colors[i0];
+                input_vertices[1].color = self.command.colors[i1];
+                input_vertices[2].color = self.command.colors[i2];
+                if self.is_command_color_defined {
+                    input_vertices[0].color *= self.command_color;
+                    input_vertices[1].color *= self.command_color;
+                    input_vertices[2].color *= self.command_color;
+                }
+                if let Some(alpha) = self.command.alpha_source.vertex_alpha() {
+                    // The vertices' alpha is ignored, the command's color is already premultiplied by the replacement
+                    input_vertices[0].color.w = alpha;
+                    input_vertices[1].color.w = alpha;
+                    input_vertices[2].color.w = alpha;
+                } else if self.command.alpha_blending.premultiplies_colors() {
+                    input_vertices[0].color.x *= input_vertices[0].color.w;
+                    input_vertices[0].color.y *= input_vertices[0].color.w;
+                    input_vertices[0].color.z *= input_vertices[0].color.w;
+                    input_vertices[1].color.x *= input_vertices[1].color.w;
+                    input_vertices[1].color.y *= input_vertices[1].color.w;
+                    input_vertices[1].color.z *= input_vertices[1].color.w;
+                    input_vertices[2].color.x *= input_vertices[2].color.w;
+                    input_vertices[2].color.y *= input_vertices[2].color.w;
+                    input_vertices[2].color.z *= input_vertices[2].color.w;
+                }
+            }
+
+            // Light the vertices with their world-space normals, either given or derived from the face.
+            if let Some(lighting) = self.command.lighting.as_ref() {
+                let premultiplied: bool = self.command.alpha_blending.premultiplies_colors();
+                for (vertex, &world_position) in input_vertices.iter_mut().zip(world_positions.iter()) {
+                    vertex.color = lighting.apply(vertex.color, world_position, vertex.normal, self.eye, premultiplied);
+                }
+            }
+
+            if (input_vertices[0].color - Vec4::new(1.0, 1.0, 1.0, 1.0)).length_squared() > 0.01
+                || (input_vertices[1].color - Vec4::new(1.0, 1.0, 1.0, 1.0)).length_squared() > 0.01
+                || (input_vertices[2].color - Vec4::new(1.0, 1.0, 1.0, 1.0)).length_squared() > 0.01
+            {
+                colors.first_colored = colors.first_colored.or(Some(i));
+            }
+            if (input_vertices[0].color - input_vertices[1].color).length_squared() > 0.01
+                || (input_vertices[0].color - input_vertices[2].color).length_squared() > 0.01
+            {
+                colors.last_varying = Some(i);
+            }
+
+            if self.retain_geometry {
+                for (vertex, world_position) in input_vertices.iter().zip(world_positions.iter()) {
+                    retained_vertices.push(Vertex { position: world_position.as_point4(), ..*vertex });
+                }
+            }
+            if !self.scissored_out {
+                counters = counters.merge(Rasterizer::schedule_triangle(
+                    &input_vertices,
+                    self.viewport_scale,
+                    self.command.culling,
+                    self.micro_triangle_area_threshold,
+                    self.guard_band,
+                    self.command.depth_clamp,
+                    geometry,
+                ));
+            }
         }
-        draw_screen_lines_unclipped(framebuffer, &lines, Vec4::new(1.0, 1.0, 1.0, 1.0));
+        (colors, counters)
     }
 }
 
-type DrawTrianglesFn =
-    fn(&Rasterizer, &mut FramebufferTile, Viewport, &[Vertex], &ScheduledCommand) -> PerTileStatistics;
-
-fn panicking_draw_triangles(
-    _: &Rasterizer,
-    _: &mut FramebufferTile,
-    _: Viewport,
-    _: &[Vertex],
-    _: &ScheduledCommand,
-) -> PerTileStatistics {
-    panic!("Dummy, should never be called");
+// Checks whether the box is entirely outside one of the planes of the frustum extracted from the matrix, see Frustum.
+// With the depth clamp the far plane is ignored.
+pub(crate) fn is_outside_frustum(aabb: &AABB, model_view_projection: &Mat44, depth_clamp: bool) -> bool {
+    Frustum::from_matrix(model_view_projection).is_aabb_outside(aabb, depth_clamp)
 }
 
-const DRAW_TRIANGLE_FUNCTIONS_NUM: usize = 432;
-const DRAW_TRIANGLE_FUNCTIONS: [DrawTrianglesFn; DRAW_TRIANGLE_FUNCTIONS_NUM] = {
-    let mut functions: [DrawTrianglesFn; DRAW_TRIANGLE_FUNCTIONS_NUM] =
-        [panicking_draw_triangles; DRAW_TRIANGLE_FUNCTIONS_NUM];
-    macro_rules! draw_triangles_instantiate_function {
-            ($t:expr, $i:expr, $a:expr, $b:expr, $c:expr, $d:expr, $e:expr, $f:expr, $g:expr) => {
-                $t[$i] = Rasterizer::draw_triangles::<$a, $b, $c, $d, $e, $f, $g>;
-                $i += 1;
-            };
-        }
-    macro_rules! draw_triangles_per_color_interpolation_mode {
-        ($t:expr, $i:expr, $a:expr, $b:expr, $c:expr, $d:expr, $e:expr, $f:expr) => {
-            draw_triangles_instantiate_function!($t, $i, $a, $b, $c, $d, $e, $f, 0u8);
-            draw_triangles_instantiate_function!($t, $i, $a, $b, $c, $d, $e, $f, 1u8);
-            draw_triangles_instantiate_function!($t, $i, $a, $b, $c, $d, $e, $f, 2u8);
-        };
-    }
-    macro_rules! draw_triangles_per_alpha_test_enabled {
-        ($t:expr, $i:expr, $a:expr, $b:expr, $c:expr, $d:expr, $e:expr) => {
-            draw_triangles_per_color_interpolation_mode!($t, $i, $a, $b, $c, $d, $e, false);
-            draw_triangles_per_color_interpolation_mode!($t, $i, $a, $b, $c, $d, $e, true);
-        };
-    }
-    macro_rules! draw_triangles_per_alpha_blending {
-        ($t:expr, $i:expr, $a:expr, $b:expr, $c:expr, $d:expr) => {
-            draw_triangles_per_alpha_test_enabled!($t, $i, $a, $b, $c, $d, 0u8);
-            draw_triangles_per_alpha_test_enabled!($t, $i, $a, $b, $c, $d, 1u8);
-            draw_triangles_per_alpha_test_enabled!($t, $i, $a, $b, $c, $d, 2u8);
-        };
-    }
-    macro_rules! draw_triangles_per_has_texture {
-        ($t:expr, $i:expr, $a:expr, $b:expr, $c:expr) => {
-            draw_triangles_per_alpha_blending!($t, $i, $a, $b, $c, false);
-            draw_triangles_per_alpha_blending!($t, $i, $a, $b, $c, true);
-        };
-    }
-    macro_rules! draw_triangles_per_normal_processing {
-        ($t:expr, $i:expr, $a:expr, $b:expr) => {
-            draw_triangles_per_has_texture!($t, $i, $a, $b, 0u8);
-            draw_triangles_per_has_texture!($t, $i, $a, $b, 1u8);
-            draw_triangles_per_has_texture!($t, $i, $a, $b, 2u8);
-        };
-    }
-    macro_rules! draw_triangles_per_has_depth {
-        ($t:expr, $i:expr, $a:expr) => {
-            draw_triangles_per_normal_processing!($t, $i, $a, false);
-            draw_triangles_per_normal_processing!($t, $i, $a, true);
-        };
-    }
-    macro_rules! draw_triangles_per_has_color {
-        ($t:expr, $i:expr) => {
-            draw_triangles_per_has_depth!($t, $i, false);
-            draw_triangles_per_has_depth!($t, $i, true);
+// Transforms the count vertices picked by index() into the world and the clip spaces, and rotates their normals by the
+// normal matrix, appending the results to the output in the same order.
+// Processes 4 vertices at a time in SIMD registers. The operations are performed in the same order as the scalar
+// products of Mat34, Mat44 and Mat33 do, so the results are bit-exact with them.
+#[allow(clippy::too_many_arguments)]
+fn transform_vertices(
+    model: &Mat34,
+    view_projection: &Mat44,
+    normal_matrix: &Mat33,
+    positions: &[Vec3],
+    normals: &[Vec3],
+    count: usize,
+    index: impl Fn(usize) -> usize,
+    output: &mut Vec<TransformedVertex>,
+) {
+    let m = |i: usize| F32x4::splat(model.0[i]);
+    let vp = |i: usize| F32x4::splat(view_projection.0[i]);
+    let nm = |i: usize| F32x4::splat(normal_matrix.0[i]);
+    output.reserve(count);
+
+    let batches_num: usize = count / 4;
+    for batch in 0..batches_num {
+        let indices: [usize; 4] = std::array::from_fn(|lane| index(batch * 4 + lane));
+        let x = F32x4::load(indices.map(|i| positions[i].x));
+        let y = F32x4::load(indices.map(|i| positions[i].y));
+        let z = F32x4::load(indices.map(|i| positions[i].z));
+
+        let wx: F32x4 = m(0) * x + m(1) * y + m(2) * z + m(3);
+        let wy: F32x4 = m(4) * x + m(5) * y + m(6) * z + m(7);
+        let wz: F32x4 = m(8) * x + m(9) * y + m(10) * z + m(11);
+
+        // The w component of the world position is 1.0
+        let cx: F32x4 = vp(0) * wx + vp(1) * wy + vp(2) * wz + vp(3);
+        let cy: F32x4 = vp(4) * wx + vp(5) * wy + vp(6) * wz + vp(7);
+        let cz: F32x4 = vp(8) * wx + vp(9) * wy + vp(10) * wz + vp(11);
+        let cw: F32x4 = vp(12) * wx + vp(13) * wy + vp(14) * wz + vp(15);
+
+        let (nx, ny, nz): ([f32; 4], [f32; 4], [f32; 4]) = if normals.is_empty() {
+            ([0.0; 4], [0.0; 4], [0.0; 4])
+        } else {
+            let x = F32x4::load(indices.map(|i| normals[i].x));
+            let y = F32x4::load(indices.map(|i| normals[i].y));
+            let z = F32x4::load(indices.map(|i| normals[i].z));
+            let nx: F32x4 = nm(0) * x + nm(1) * y + nm(2) * z;
+            let ny: F32x4 = nm(3) * x + nm(4) * y + nm(5) * z;
+            let nz: F32x4 = nm(6) * x + nm(7) * y + nm(8) * z;
+            let rec: F32x4 = F32x4::splat(1.0) / (nx * nx + ny * ny + nz * nz).sqrt();
+            ((nx * rec).store(), (ny * rec).store(), (nz * rec).store())
         };
-    }
 
-    let mut index: usize = 0;
-    draw_triangles_per_has_color!(functions, index);
-    let _ = index;
-    functions
-};
+        let (wx, wy, wz) = (wx.store(), wy.store(), wz.store());
+        let (cx, cy, cz, cw) = (cx.store(), cy.store(), cz.store(), cw.store());
+        for lane in 0..4 {
+            output.push(TransformedVertex {
+                world_position: Vec3::new(wx[lane], wy[lane], wz[lane]),
+                position: Vec4::new(cx[lane], cy[lane], cz[lane], cw[lane]),
+                normal: Vec3::new(nx[lane], ny[lane], nz[lane]),
+            });
+        }
+    }
 
-fn debug_color(idx: u32) -> Vec4 {
-    fn hash(mut x: u32) -> u32 {
-        x = (x ^ 61) ^ (x >> 16);
-        x = x.wrapping_add(x << 3);
-        x ^= x >> 4;
-        x = x.wrapping_mul(0x27d4eb2d);
-        x ^ (x >> 15)
+    // The remaining vertices one by one
+    for n in batches_num * 4..count {
+        let i: usize = index(n);
+        let world_position: Vec3 = *model * positions[i];
+        output.push(TransformedVertex {
+            world_position,
+            position: *view_projection * world_position.as_point4(),
+            normal: if normals.is_empty() {
+                Vec3::default()
+            } else {
+                (*normal_matrix * normals[i]).normalized()
+            },
+        });
     }
-    let h = hash(idx);
-    let r = (h & 0xff) as u8;
-    let g = ((h >> 8) & 0xff) as u8;
-    let b = ((h >> 16) & 0xff) as u8;
-    Vec4::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0)
 }
 
 fn perspective_divide(v: Vec4) -> Vec4 {
@@ -1564,6 +6486,7 @@ impl Default for RasterizationCommand<'_> {
             normals: &[],
             tex_coords: &[],
             colors: &[],
+            tex_coords2: &[],
             indices: &[],
             model: Mat34::identity(),
             view: Mat44::identity(),
@@ -1572,9 +6495,40 @@ impl Default for RasterizationCommand<'_> {
             color: Vec4::new(1.0, 1.0, 1.0, 1.0),
             texture: None,
             normal_map: None,
+            lightmap: None,
+            texture_region: None,
             sampling_filter: SamplerFilter::Nearest,
+            address_mode_u: SamplerAddressMode::Repeat,
+            address_mode_v: SamplerAddressMode::Repeat,
             alpha_blending: AlphaBlendingMode::None,
             alpha_test: 0u8,
+            alpha_source: AlphaSource::Modulate,
+            fast_math: false,
+            scissor: None,
+            sdf: None,
+            pattern: None,
+            uniforms: Uniforms::default(),
+            vertex_animation: None,
+            morph_targets: None,
+            skinning: None,
+            displacement: None,
+            vertex_hook: None,
+            fragment_hook: None,
+            opacity: OpacityHint::Ordered,
+            aabb: None,
+            instance_models: &[],
+            instance_colors: &[],
+            depth_clamp: false,
+            depth_test: DepthTest::Less,
+            depth_write: true,
+            depth_bias: DepthBias::default(),
+            color_write_mask: ColorWriteMask::ALL,
+            tint: ColorTint::IDENTITY,
+            fog: None,
+            lighting: None,
+            reflection: None,
+            previous_model: None,
+            previous_view_projection: None,
         }
     }
 }
@@ -1584,10 +6538,63 @@ impl Default for ScheduledCommand {
         ScheduledCommand {
             texture: None,
             normal_map: None,
+            lightmap: None,
+            texture_region: None,
             sampling_filter: SamplerFilter::Nearest,
+            address_mode_u: SamplerAddressMode::Repeat,
+            address_mode_v: SamplerAddressMode::Repeat,
             alpha_blending: AlphaBlendingMode::None,
             alpha_test: 0u8,
+            alpha_source: AlphaSource::Modulate,
             color_interpolation: VerticesColorInterpolationMode::None,
+            fast_math: false,
+            scissor: None,
+            sdf: None,
+            pattern: None,
+            uniforms: Uniforms::default(),
+            fragment_hook: None,
+            opacity: OpacityHint::Ordered,
+            depth_clamp: false,
+            depth_test: DepthTest::Less,
+            depth_write: true,
+            depth_bias: DepthBias::default(),
+            reversed_depth: None,
+            color_write_mask: ColorWriteMask::ALL,
+            tint: ColorTint::IDENTITY,
+            fog: None,
+            reflection: None,
+            motion: None,
+            fullscreen_color: None,
+            fill: None,
+            polygon: None,
+            stroke: None,
+            decal: None,
+        }
+    }
+}
+
+impl Default for FullscreenCommand {
+    fn default() -> Self {
+        Self {
+            color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            texture: None,
+            sampling_filter: SamplerFilter::Nearest,
+            alpha_blending: AlphaBlendingMode::None,
+        }
+    }
+}
+
+impl Default for DecalCommand {
+    fn default() -> Self {
+        Self {
+            view: Mat44::identity(),
+            projection: Mat44::identity(),
+            projector: Mat44::identity(),
+            color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            texture: None,
+            sampling_filter: SamplerFilter::Bilinear,
+            min_facing: 0.0,
+            alpha_blending: AlphaBlendingMode::Normal,
         }
     }
 }
@@ -1597,15 +6604,78 @@ impl PartialEq for ScheduledCommand {
         if self.sampling_filter != other.sampling_filter {
             return false;
         }
+        if self.address_mode_u != other.address_mode_u || self.address_mode_v != other.address_mode_v {
+            return false;
+        }
+        if self.texture_region != other.texture_region {
+            return false;
+        }
         if self.alpha_blending != other.alpha_blending {
             return false;
         }
-        if self.alpha_test != other.alpha_test {
+        if self.alpha_test != other.alpha_test || self.alpha_source != other.alpha_source {
             return false;
         }
         if self.color_interpolation != other.color_interpolation {
             return false;
         }
+        if self.fast_math != other.fast_math {
+            return false;
+        }
+        if self.scissor != other.scissor {
+            return false;
+        }
+        if self.sdf != other.sdf {
+            return false;
+        }
+        if self.pattern != other.pattern || self.uniforms != other.uniforms {
+            return false;
+        }
+        let same_fragment_hook: bool = match (self.fragment_hook, other.fragment_hook) {
+            (Some(a), Some(b)) => std::ptr::fn_addr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        if !same_fragment_hook {
+            return false;
+        }
+        if self.opacity != other.opacity {
+            return false;
+        }
+        if self.depth_clamp != other.depth_clamp {
+            return false;
+        }
+        if self.depth_test != other.depth_test
+            || self.depth_write != other.depth_write
+            || self.depth_bias != other.depth_bias
+            || self.reversed_depth != other.reversed_depth
+        {
+            return false;
+        }
+        if self.color_write_mask != other.color_write_mask || self.tint != other.tint || self.fog != other.fog {
+            return false;
+        }
+        if self.reflection != other.reflection {
+            return false;
+        }
+        if self.motion != other.motion {
+            return false;
+        }
+        if self.fullscreen_color != other.fullscreen_color {
+            return false;
+        }
+        if self.fill != other.fill {
+            return false;
+        }
+        if self.polygon != other.polygon {
+            return false;
+        }
+        if self.stroke != other.stroke {
+            return false;
+        }
+        if self.decal != other.decal {
+            return false;
+        }
 
         if self.texture.is_some() != other.texture.is_some() {
             return false;
@@ -1627,6 +6697,16 @@ impl PartialEq for ScheduledCommand {
             return false;
         }
 
+        if self.lightmap.is_some() != other.lightmap.is_some() {
+            return false;
+        }
+        if self.lightmap.is_some()
+            && other.lightmap.is_some()
+            && !std::sync::Arc::ptr_eq(self.lightmap.as_ref().unwrap(), &other.lightmap.as_ref().unwrap())
+        {
+            return false;
+        }
+
         true
     }
 }
@@ -1635,20 +6715,41 @@ impl Eq for ScheduledCommand {}
 
 impl Default for PerTileStatistics {
     fn default() -> Self {
-        Self { fragments_drawn: 0 }
+        Self { fragments_drawn: 0, hiz_rejected_triangles: 0 }
     }
 }
 
 impl Add for PerTileStatistics {
     type Output = Self;
     fn add(self, other: Self) -> Self {
-        Self { fragments_drawn: self.fragments_drawn + other.fragments_drawn }
+        Self {
+            fragments_drawn: self.fragments_drawn + other.fragments_drawn,
+            hiz_rejected_triangles: self.hiz_rejected_triangles + other.hiz_rejected_triangles,
+        }
     }
 }
 
 impl RasterizerStatistics {
     pub fn new() -> Self {
-        Self { committed_triangles: 0, scheduled_triangles: 0, binned_triangles: 0, fragments_drawn: 0 }
+        Self {
+            committed_triangles: 0,
+            scheduled_triangles: 0,
+            scheduled_vertices: 0,
+            transformed_vertices: 0,
+            vertex_cache_hits: 0,
+            culled_commands: 0,
+            clipped_triangles: 0,
+            side_clipped_triangles: 0,
+            zero_area_triangles: 0,
+            micro_triangles: 0,
+            binned_triangles: 0,
+            fragments_drawn: 0,
+            hiz_rejected_triangles: 0,
+            split_tiles: 0,
+            allocations: 0,
+            allocated_bytes: 0,
+            reserved_bytes: 0,
+        }
     }
 
     pub fn smoothed(&self, alpha: usize, prev_smooth: RasterizerStatistics) -> Self {
@@ -1658,8 +6759,32 @@ impl RasterizerStatistics {
         RasterizerStatistics {
             committed_triangles: smooth(self.committed_triangles, prev_smooth.committed_triangles),
             scheduled_triangles: smooth(self.scheduled_triangles, prev_smooth.scheduled_triangles),
+            scheduled_vertices: smooth(self.scheduled_vertices, prev_smooth.scheduled_vertices),
+            transformed_vertices: smooth(self.transformed_vertices, prev_smooth.transformed_vertices),
+            vertex_cache_hits: smooth(self.vertex_cache_hits, prev_smooth.vertex_cache_hits),
+            culled_commands: smooth(self.culled_commands, prev_smooth.culled_commands),
+            clipped_triangles: smooth(self.clipped_triangles, prev_smooth.clipped_triangles),
+            side_clipped_triangles: smooth(self.side_clipped_triangles, prev_smooth.side_clipped_triangles),
+            zero_area_triangles: smooth(self.zero_area_triangles, prev_smooth.zero_area_triangles),
+            micro_triangles: smooth(self.micro_triangles, prev_smooth.micro_triangles),
             binned_triangles: smooth(self.binned_triangles, prev_smooth.binned_triangles),
             fragments_drawn: smooth(self.fragments_drawn, prev_smooth.fragments_drawn),
+            hiz_rejected_triangles: smooth(self.hiz_rejected_triangles, prev_smooth.hiz_rejected_triangles),
+            split_tiles: smooth(self.split_tiles, prev_smooth.split_tiles),
+            allocations: smooth(self.allocations, prev_smooth.allocations),
+            allocated_bytes: smooth(self.allocated_bytes, prev_smooth.allocated_bytes),
+            reserved_bytes: smooth(self.reserved_bytes, prev_smooth.reserved_bytes),
+        }
+    }
+
+    // The fraction of the committed triangles' vertices that were taken from the vertex cache instead of being
+    // transformed, [0, 1].
+    pub fn vertex_cache_hit_rate(&self) -> f32 {
+        let total: usize = self.transformed_vertices + self.vertex_cache_hits;
+        if total == 0 {
+            0.0
+        } else {
+            self.vertex_cache_hits as f32 / total as f32
         }
     }
 }
@@ -1715,6 +6840,43 @@ mod tests_binning {
             assert_eq!(mask, tc.mask);
         }
     }
+
+    #[test]
+    fn chunked_binning_matches_serial() {
+        // A fan of thin triangles crossing each other and many tiles, several chunks of them
+        let positions: Vec<Vec3> = (0..3 * Rasterizer::COMMIT_CHUNK_TRIANGLES)
+            .flat_map(|i| {
+                let angle: f32 = i as f32 * 0.01;
+                [
+                    Vec3::new(angle.cos() * 0.1, angle.sin() * 0.1, 0.0),
+                    Vec3::new(angle.cos() * 1.2, angle.sin() * 1.2, 0.0),
+                    Vec3::new((angle + 0.005).cos() * 1.2, (angle + 0.005).sin() * 1.2, 0.0),
+                ]
+            })
+            .collect();
+        let bins = |multithreading: bool| {
+            let mut rasterizer = Rasterizer::new();
+            rasterizer.set_multithreading(multithreading);
+            rasterizer.setup(Viewport::new(0, 0, 300, 200));
+            rasterizer.commit(&RasterizationCommand { world_positions: &positions, ..Default::default() });
+            let bins: Vec<Vec<(u16, u16)>> = rasterizer
+                .tiles
+                .iter()
+                .map(|tile| {
+                    tile.triangles
+                        .iter()
+                        .map(|triangle| (triangle.cmd, triangle.tri))
+                        .collect()
+                })
+                .collect();
+            (bins, rasterizer.statistics().binned_triangles)
+        };
+        let (serial, serial_binned) = bins(false);
+        let (chunked, chunked_binned) = bins(true);
+        assert!(serial.iter().all(|bin| !bin.is_empty()));
+        assert_eq!(serial_binned, chunked_binned);
+        assert_eq!(serial, chunked);
+    }
 }
 
 #[cfg(test)]
@@ -1807,9 +6969,10 @@ mod tests_normal_mapping {
                 tex_coords: &[tc.tc0, tc.tc1, tc.tc2],
                 ..Default::default()
             });
-            assert!((rasterizer.vertices[0].tangent - tc.exp_t0).length() < 0.0001);
-            assert!((rasterizer.vertices[1].tangent - tc.exp_t1).length() < 0.0001);
-            assert!((rasterizer.vertices[2].tangent - tc.exp_t2).length() < 0.0001);
+            let vertices: [Vertex; 3] = rasterizer.geometry.triangle(0);
+            assert!((vertices[0].tangent - tc.exp_t0).length() < 0.0001);
+            assert!((vertices[1].tangent - tc.exp_t1).length() < 0.0001);
+            assert!((vertices[2].tangent - tc.exp_t2).length() < 0.0001);
         }
     }
 
@@ -1840,12 +7003,14 @@ mod tests_normal_mapping {
                 width: 1,
                 height: 1,
                 format: TextureFormat::RGB,
+                ..Default::default()
             });
             let normal_map = Texture::new(&TextureSource {
                 texels: &tc.normal_map,
                 width: 1,
                 height: 1,
                 format: TextureFormat::RGB,
+                ..Default::default()
             });
             rasterizer.commit(&RasterizationCommand {
                 world_positions: &[Vec3::new(0.0, 1.0, 0.0), Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0)],
@@ -1913,12 +7078,14 @@ mod tests_normal_mapping {
                 width: 1,
                 height: 1,
                 format: TextureFormat::RGB,
+                ..Default::default()
             });
             let normal_map = Texture::new(&TextureSource {
                 texels: &tc.normal_map,
                 width: 1,
                 height: 1,
                 format: TextureFormat::RGB,
+                ..Default::default()
             });
             rasterizer.commit(&RasterizationCommand {
                 world_positions: &[Vec3::new(0.0, 1.0, 0.0), Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0)],
@@ -1937,3 +7104,35 @@ mod tests_normal_mapping {
         }
     }
 }
+
+#[cfg(test)]
+mod tests_vertex_transform {
+    use super::*;
+
+    #[test]
+    fn batches_match_scalar_products() {
+        let model: Mat34 =
+            Mat34::translate(Vec3::new(0.5, -1.0, 2.0)) * Mat34::rotate_yz(0.3) * Mat34::scale_uniform(1.7);
+        let view_projection: Mat44 =
+            Mat44::perspective(0.1, 100.0, 1.2, 1.5) * Mat44::translate(Vec3::new(0.0, 0.0, -5.0));
+        let normal_matrix: Mat33 = model.as_mat33().inverse().transpose();
+        let positions: Vec<Vec3> = (0..11)
+            .map(|i| Vec3::new(i as f32 * 0.37 - 2.0, (i * i) as f32 * 0.05, 1.0 / (i + 1) as f32))
+            .collect();
+        let normals: Vec<Vec3> = positions.iter().map(|p| Vec3::new(p.z, 1.0, -p.x)).collect();
+        // Reversed order, 2 batches of 4 and 3 remaining vertices
+        let mut output: Vec<TransformedVertex> = Vec::new();
+        transform_vertices(&model, &view_projection, &normal_matrix, &positions, &normals, 11, |n| 10 - n, &mut output);
+        assert_eq!(output.len(), 11);
+        for (n, vertex) in output.iter().enumerate() {
+            let world_position: Vec3 = model * positions[10 - n];
+            assert_eq!(vertex.world_position, world_position);
+            assert_eq!(vertex.position, view_projection * world_position.as_point4());
+            assert_eq!(vertex.normal, (normal_matrix * normals[10 - n]).normalized());
+        }
+
+        output.clear();
+        transform_vertices(&model, &view_projection, &normal_matrix, &positions, &[], 8, |n| n, &mut output);
+        assert!(output.iter().all(|vertex| vertex.normal == Vec3::default()));
+    }
+}
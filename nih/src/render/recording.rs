@@ -0,0 +1,941 @@
+use super::*;
+use crate::math::*;
+use std::sync::Arc;
+
+// Recording of the commands committed to the rasterizer, e.g. to capture a frame from a user's report and to re-render
+// it headlessly afterwards. The frame is serialized into a compact binary format, the textures are referred to by
+// their handles, i.e. the indices in the order the frame uses them, and must be supplied again for the replay.
+//
+// The commands are recorded after the vertex animation, the morph targets, the skinning, the displacement, the vertex
+// hook and the instancing were applied, i.e. with the final vertices. The reflection and the fragment hook can't be
+// recorded, the commands using them are recorded without and counted in RecordedFrame::incomplete_commands.
+// The polygons, the strokes, the decals and the baked meshes are not recorded, they are counted in
+// RecordedFrame::skipped_commands.
+
+// The reason why bytes can't be loaded as a RecordedFrame or why the frame can't be replayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingError {
+    // The bytes don't start with the recording's signature.
+    InvalidSignature,
+
+    // The bytes were written by an incompatible version.
+    UnsupportedVersion(u32),
+
+    // The bytes end before the data they declare.
+    Truncated,
+
+    // The byte at the offset is not a valid value, e.g. an unknown blending mode.
+    InvalidValue(usize),
+
+    // The texture with the handle wasn't supplied for the replay.
+    MissingTexture(u32),
+
+    // The texture supplied for the handle has a different size or format than the recorded one.
+    TextureMismatch(u32),
+}
+
+impl std::fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            RecordingError::InvalidSignature => write!(f, "the data is not a recorded frame"),
+            RecordingError::UnsupportedVersion(version) => write!(f, "unsupported recording version {}", version),
+            RecordingError::Truncated => write!(f, "the recorded frame is truncated"),
+            RecordingError::InvalidValue(offset) => {
+                write!(f, "invalid value at offset {} of the recorded frame", offset)
+            }
+            RecordingError::MissingTexture(handle) => write!(f, "texture {} is not supplied", handle),
+            RecordingError::TextureMismatch(handle) => {
+                write!(f, "texture {} doesn't match the recorded size or format", handle)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+// The commands committed between Rasterizer::setup() and Rasterizer::take_recorded_frame(), see
+// Rasterizer::set_recording().
+#[derive(Debug, Clone)]
+pub struct RecordedFrame {
+    // The viewport the rasterizer was set up with.
+    pub viewport: Viewport,
+
+    // The number of commands which were not recorded as their kind is not supported, e.g. strokes.
+    pub skipped_commands: usize,
+
+    // The number of commands which were recorded without some of their features, e.g. the fragment hook.
+    pub incomplete_commands: usize,
+
+    commands: Vec<RecordedCommand>,
+    textures: Vec<RecordedTexture>,
+}
+
+#[derive(Debug, Clone)]
+enum RecordedCommand {
+    Rasterization(Box<RecordedRasterization>),
+    Fullscreen {
+        color: Vec4,
+        texture: Option<u32>,
+        sampling_filter: SamplerFilter,
+        alpha_blending: AlphaBlendingMode,
+    },
+    Fill(FillCommand),
+}
+
+// The owned copy of a RasterizationCommand: the state is kept as a command without the vertices, the textures and the
+// lighting, those are stored separately.
+#[derive(Debug, Clone)]
+struct RecordedRasterization {
+    state: RasterizationCommand<'static>,
+    world_positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    tex_coords: Vec<Vec2>,
+    colors: Vec<Vec4>,
+    tex_coords2: Vec<Vec2>,
+    indices: Vec<u32>,
+    texture: Option<u32>,
+    normal_map: Option<u32>,
+    lightmap: Option<u32>,
+    lights: Vec<Light>,
+    lighting_material: Option<LightingMaterial>,
+}
+
+// A texture used by the frame: its size and format are serialized to validate the textures supplied for the replay,
+// the texture itself is only known to the frame which was recorded in this process.
+#[derive(Debug, Clone)]
+struct RecordedTexture {
+    width: u16,
+    height: u16,
+    format: TextureFormat,
+    texture: Option<Arc<Texture>>,
+}
+
+impl RecordedFrame {
+    const SIGNATURE: [u8; 4] = *b"NIHR";
+    const VERSION: u32 = 1;
+
+    pub fn new(viewport: Viewport) -> Self {
+        Self { viewport, skipped_commands: 0, incomplete_commands: 0, commands: Vec::new(), textures: Vec::new() }
+    }
+
+    // The number of the recorded commands.
+    pub fn commands_num(&self) -> usize {
+        self.commands.len()
+    }
+
+    // The textures in the order of their handles, to replay the frame with. None if the frame was loaded from bytes,
+    // the textures must be supplied by the caller then.
+    pub fn textures(&self) -> Option<Vec<Arc<Texture>>> {
+        self.textures.iter().map(|texture| texture.texture.clone()).collect()
+    }
+
+    // Sets up the rasterizer with the recorded viewport and commits the recorded commands, the textures are looked up
+    // by their handles. Doesn't draw, so that the caller can adjust the rasterizer's settings or commit more.
+    pub fn commit_to(&self, rasterizer: &mut Rasterizer, textures: &[Arc<Texture>]) -> Result<(), RecordingError> {
+        for (handle, recorded) in self.textures.iter().enumerate() {
+            let Some(texture) = textures.get(handle) else {
+                return Err(RecordingError::MissingTexture(handle as u32));
+            };
+            if (texture.mips[0].width, texture.mips[0].height, texture.format)
+                != (recorded.width, recorded.height, recorded.format)
+            {
+                return Err(RecordingError::TextureMismatch(handle as u32));
+            }
+        }
+        let texture = |handle: Option<u32>| handle.map(|handle| textures[handle as usize].clone());
+        rasterizer.setup(self.viewport);
+        for command in &self.commands {
+            match command {
+                RecordedCommand::Rasterization(recorded) => rasterizer.commit(&RasterizationCommand {
+                    world_positions: &recorded.world_positions,
+                    normals: &recorded.normals,
+                    tex_coords: &recorded.tex_coords,
+                    colors: &recorded.colors,
+                    tex_coords2: &recorded.tex_coords2,
+                    indices: &recorded.indices,
+                    texture: texture(recorded.texture),
+                    normal_map: texture(recorded.normal_map),
+                    lightmap: texture(recorded.lightmap),
+                    lighting: recorded
+                        .lighting_material
+                        .map(|material| VertexLighting { lights: &recorded.lights, material }),
+                    ..recorded.state.clone()
+                }),
+                RecordedCommand::Fullscreen { color, texture: handle, sampling_filter, alpha_blending } => rasterizer
+                    .commit_fullscreen(&FullscreenCommand {
+                        color: *color,
+                        texture: texture(*handle),
+                        sampling_filter: *sampling_filter,
+                        alpha_blending: *alpha_blending,
+                    }),
+                RecordedCommand::Fill(fill) => rasterizer.commit_fill(fill),
+            }
+        }
+        Ok(())
+    }
+
+    // Re-renders the frame into the framebuffer, see commit_to().
+    pub fn replay(
+        &self,
+        rasterizer: &mut Rasterizer,
+        framebuffer: &mut Framebuffer,
+        textures: &[Arc<Texture>],
+    ) -> Result<(), RecordingError> {
+        self.commit_to(rasterizer, textures)?;
+        rasterizer.draw(framebuffer);
+        Ok(())
+    }
+
+    pub(crate) fn record(&mut self, command: &RasterizationCommand) {
+        if command.reflection.is_some() || command.fragment_hook.is_some() {
+            self.incomplete_commands += 1;
+        }
+        let recorded = RecordedRasterization {
+            state: RasterizationCommand {
+                model: command.model,
+                view: command.view,
+                projection: command.projection,
+                culling: command.culling,
+                color: command.color,
+                texture_region: command.texture_region,
+                sampling_filter: command.sampling_filter,
+                address_mode_u: command.address_mode_u,
+                address_mode_v: command.address_mode_v,
+                alpha_blending: command.alpha_blending,
+                alpha_test: command.alpha_test,
+                alpha_source: command.alpha_source,
+                fast_math: command.fast_math,
+                scissor: command.scissor,
+                sdf: command.sdf,
+                pattern: command.pattern,
+                uniforms: command.uniforms,
+                opacity: command.opacity,
+                aabb: command.aabb,
+                depth_clamp: command.depth_clamp,
+                depth_test: command.depth_test,
+                depth_write: command.depth_write,
+                depth_bias: command.depth_bias,
+                color_write_mask: command.color_write_mask,
+                tint: command.tint,
+                fog: command.fog,
+                previous_model: command.previous_model,
+                previous_view_projection: command.previous_view_projection,
+                ..Default::default()
+            },
+            world_positions: command.world_positions.to_vec(),
+            normals: command.normals.to_vec(),
+            tex_coords: command.tex_coords.to_vec(),
+            colors: command.colors.to_vec(),
+            tex_coords2: command.tex_coords2.to_vec(),
+            indices: command.indices.to_vec(),
+            texture: self.texture_handle(command.texture.as_ref()),
+            normal_map: self.texture_handle(command.normal_map.as_ref()),
+            lightmap: self.texture_handle(command.lightmap.as_ref()),
+            lights: command
+                .lighting
+                .as_ref()
+                .map(|lighting| lighting.lights.to_vec())
+                .unwrap_or_default(),
+            lighting_material: command.lighting.as_ref().map(|lighting| lighting.material),
+        };
+        self.commands.push(RecordedCommand::Rasterization(Box::new(recorded)));
+    }
+
+    pub(crate) fn record_fullscreen(&mut self, command: &FullscreenCommand) {
+        let texture: Option<u32> = self.texture_handle(command.texture.as_ref());
+        self.commands.push(RecordedCommand::Fullscreen {
+            color: command.color,
+            texture,
+            sampling_filter: command.sampling_filter,
+            alpha_blending: command.alpha_blending,
+        });
+    }
+
+    pub(crate) fn record_fill(&mut self, command: &FillCommand) {
+        self.commands.push(RecordedCommand::Fill(*command));
+    }
+
+    // The handle of the texture, the texture gets the next one if the frame didn't use it yet.
+    fn texture_handle(&mut self, texture: Option<&Arc<Texture>>) -> Option<u32> {
+        let texture: &Arc<Texture> = texture?;
+        let used = |recorded: &RecordedTexture| recorded.texture.as_ref().is_some_and(|t| Arc::ptr_eq(t, texture));
+        if let Some(handle) = self.textures.iter().position(used) {
+            return Some(handle as u32);
+        }
+        self.textures.push(RecordedTexture {
+            width: texture.mips[0].width,
+            height: texture.mips[0].height,
+            format: texture.format,
+            texture: Some(texture.clone()),
+        });
+        Some(self.textures.len() as u32 - 1)
+    }
+
+    // Writes the frame as little-endian: the signature, the version, the viewport, the counters of the commands which
+    // weren't recorded entirely, the textures' sizes and formats, then the commands, each as its kind and its fields.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer { bytes: Vec::new() };
+        w.bytes.extend_from_slice(&Self::SIGNATURE);
+        w.u32(Self::VERSION);
+        w.viewport(self.viewport);
+        w.u32(self.skipped_commands as u32);
+        w.u32(self.incomplete_commands as u32);
+        w.u32(self.textures.len() as u32);
+        for texture in &self.textures {
+            w.u16(texture.width);
+            w.u16(texture.height);
+            w.u8(texture.format as u8);
+        }
+        w.u32(self.commands.len() as u32);
+        for command in &self.commands {
+            match command {
+                RecordedCommand::Rasterization(recorded) => {
+                    w.u8(0);
+                    w.rasterization(recorded);
+                }
+                RecordedCommand::Fullscreen { color, texture, sampling_filter, alpha_blending } => {
+                    w.u8(1);
+                    w.vec4(*color);
+                    w.option(*texture, Writer::u32);
+                    w.u8(*sampling_filter as u8);
+                    w.u8(*alpha_blending as u8);
+                }
+                RecordedCommand::Fill(fill) => {
+                    w.u8(2);
+                    w.fill(fill);
+                }
+            }
+        }
+        w.bytes
+    }
+
+    // Loads the frame written by to_bytes(), validating the values and the texture handles.
+    pub fn from_bytes(bytes: &[u8]) -> Result<RecordedFrame, RecordingError> {
+        let mut r = Reader { bytes, offset: 0 };
+        if r.take(4)? != Self::SIGNATURE {
+            return Err(RecordingError::InvalidSignature);
+        }
+        let version: u32 = r.u32()?;
+        if version != Self::VERSION {
+            return Err(RecordingError::UnsupportedVersion(version));
+        }
+        let mut frame = RecordedFrame::new(r.viewport()?);
+        frame.skipped_commands = r.u32()? as usize;
+        frame.incomplete_commands = r.u32()? as usize;
+        for _ in 0..r.count(5)? {
+            let (width, height) = (r.u16()?, r.u16()?);
+            let format: TextureFormat = r.value(|value| match value {
+                0 => Some(TextureFormat::Grayscale),
+                1 => Some(TextureFormat::RGB),
+                2 => Some(TextureFormat::RGBA),
+                3 => Some(TextureFormat::BC3),
+                _ => None,
+            })?;
+            frame
+                .textures
+                .push(RecordedTexture { width, height, format, texture: None });
+        }
+        let textures_num: u32 = frame.textures.len() as u32;
+        for _ in 0..r.count(1)? {
+            let command: RecordedCommand = match r.u8()? {
+                0 => RecordedCommand::Rasterization(Box::new(r.rasterization(textures_num)?)),
+                1 => RecordedCommand::Fullscreen {
+                    color: r.vec4()?,
+                    texture: r.texture(textures_num)?,
+                    sampling_filter: r.sampling_filter()?,
+                    alpha_blending: r.alpha_blending()?,
+                },
+                2 => RecordedCommand::Fill(r.fill()?),
+                _ => return Err(RecordingError::InvalidValue(r.offset - 1)),
+            };
+            frame.commands.push(command);
+        }
+        if r.offset != bytes.len() {
+            return Err(RecordingError::InvalidValue(r.offset));
+        }
+        Ok(frame)
+    }
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn floats(&mut self, values: &[f32]) {
+        values.iter().for_each(|&value| self.f32(value));
+    }
+
+    fn vec2(&mut self, v: Vec2) {
+        self.floats(&[v.x, v.y]);
+    }
+
+    fn vec3(&mut self, v: Vec3) {
+        self.floats(&[v.x, v.y, v.z]);
+    }
+
+    fn vec4(&mut self, v: Vec4) {
+        self.floats(&[v.x, v.y, v.z, v.w]);
+    }
+
+    fn viewport(&mut self, viewport: Viewport) {
+        [viewport.xmin, viewport.ymin, viewport.xmax, viewport.ymax]
+            .into_iter()
+            .for_each(|value| self.u16(value));
+    }
+
+    fn option<T>(&mut self, value: Option<T>, write: impl FnOnce(&mut Self, T)) {
+        self.bool(value.is_some());
+        if let Some(value) = value {
+            write(self, value);
+        }
+    }
+
+    fn slice<T: Copy>(&mut self, values: &[T], write: impl Fn(&mut Self, T)) {
+        self.u32(values.len() as u32);
+        values.iter().for_each(|&value| write(self, value));
+    }
+
+    fn rasterization(&mut self, recorded: &RecordedRasterization) {
+        self.slice(&recorded.world_positions, Self::vec3);
+        self.slice(&recorded.normals, Self::vec3);
+        self.slice(&recorded.tex_coords, Self::vec2);
+        self.slice(&recorded.colors, Self::vec4);
+        self.slice(&recorded.tex_coords2, Self::vec2);
+        self.slice(&recorded.indices, Self::u32);
+        for texture in [recorded.texture, recorded.normal_map, recorded.lightmap] {
+            self.option(texture, Self::u32);
+        }
+        self.slice(&recorded.lights, |w, light| match light {
+            Light::Directional { direction, color } => {
+                w.u8(0);
+                w.vec3(direction);
+                w.vec3(color);
+            }
+            Light::Point { position, color, range } => {
+                w.u8(1);
+                w.vec3(position);
+                w.vec3(color);
+                w.f32(range);
+            }
+        });
+        self.option(recorded.lighting_material, |w, material| {
+            w.vec3(material.ambient);
+            w.vec3(material.diffuse);
+            w.vec3(material.specular);
+            w.f32(material.shininess);
+        });
+
+        let state: &RasterizationCommand = &recorded.state;
+        self.floats(&state.model.0);
+        self.floats(&state.view.0);
+        self.floats(&state.projection.0);
+        self.u8(state.culling as u8);
+        self.vec4(state.color);
+        self.option(state.texture_region, |w, region| {
+            w.floats(&[region.u0, region.v0, region.u1, region.v1]);
+            w.bool(region.clamp);
+        });
+        self.u8(state.sampling_filter as u8);
+        self.u8(state.address_mode_u as u8);
+        self.u8(state.address_mode_v as u8);
+        self.u8(state.alpha_blending as u8);
+        self.u8(state.alpha_test);
+        match state.alpha_source {
+            AlphaSource::Modulate => self.u8(0),
+            AlphaSource::Texture => self.u8(1),
+            AlphaSource::Vertex => self.u8(2),
+            AlphaSource::Constant(alpha) => {
+                self.u8(3);
+                self.u8(alpha);
+            }
+        }
+        self.bool(state.fast_math);
+        self.option(state.scissor, Self::viewport);
+        self.option(state.sdf, |w, sdf| w.floats(&[sdf.threshold, sdf.range, sdf.smoothing]));
+        self.option(state.pattern, |w, pattern| match pattern {
+            ProceduralPattern::Checker { even, odd } => {
+                w.u8(0);
+                w.vec4(even);
+                w.vec4(odd);
+            }
+            ProceduralPattern::Grid { line, background, width } => {
+                w.u8(1);
+                w.vec4(line);
+                w.vec4(background);
+                w.f32(width);
+            }
+        });
+        self.f32(state.uniforms.time);
+        self.u32(state.uniforms.seed);
+        state.uniforms.user.iter().for_each(|&user| self.vec4(user));
+        self.u8(state.opacity as u8);
+        self.option(state.aabb, |w, aabb| {
+            w.vec3(aabb.min);
+            w.vec3(aabb.max);
+        });
+        self.bool(state.depth_clamp);
+        self.u8(state.depth_test as u8);
+        self.bool(state.depth_write);
+        self.floats(&[state.depth_bias.constant, state.depth_bias.slope]);
+        let mask: ColorWriteMask = state.color_write_mask;
+        self.u8(mask.r as u8 | (mask.g as u8) << 1 | (mask.b as u8) << 2 | (mask.a as u8) << 3);
+        self.vec4(state.tint.multiply);
+        self.vec4(state.tint.add);
+        self.option(state.fog, |w, fog| {
+            match fog.mode {
+                FogMode::Linear { start, end } => {
+                    w.u8(0);
+                    w.floats(&[start, end]);
+                }
+                FogMode::Exp { density } => {
+                    w.u8(1);
+                    w.f32(density);
+                }
+                FogMode::Exp2 { density } => {
+                    w.u8(2);
+                    w.f32(density);
+                }
+            }
+            w.vec3(fog.color);
+        });
+        self.option(state.previous_model, |w, model| w.floats(&model.0));
+        self.option(state.previous_view_projection, |w, view_projection| w.floats(&view_projection.0));
+    }
+
+    fn fill(&mut self, fill: &FillCommand) {
+        self.vec2(fill.min);
+        self.vec2(fill.max);
+        self.f32(fill.corner_radius);
+        self.f32(fill.border_width);
+        self.vec4(fill.border_color);
+        match fill.paint {
+            FillPaint::Solid(color) => {
+                self.u8(0);
+                self.vec4(color);
+            }
+            FillPaint::LinearGradient { from, to, color_from, color_to } => {
+                self.u8(1);
+                self.vec2(from);
+                self.vec2(to);
+                self.vec4(color_from);
+                self.vec4(color_to);
+            }
+            FillPaint::RadialGradient { center, radius, color_inner, color_outer } => {
+                self.u8(2);
+                self.vec2(center);
+                self.f32(radius);
+                self.vec4(color_inner);
+                self.vec4(color_outer);
+            }
+        }
+        self.u8(fill.alpha_blending as u8);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], RecordingError> {
+        let end: usize = self.offset.checked_add(len).ok_or(RecordingError::Truncated)?;
+        let bytes: &[u8] = self.bytes.get(self.offset..end).ok_or(RecordingError::Truncated)?;
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, RecordingError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, RecordingError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, RecordingError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, RecordingError> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    fn floats<const N: usize>(&mut self) -> Result<[f32; N], RecordingError> {
+        let mut values: [f32; N] = [0.0; N];
+        for value in &mut values {
+            *value = self.f32()?;
+        }
+        Ok(values)
+    }
+
+    // Reads a byte and maps it to a value, failing at the byte's offset if it doesn't map to any.
+    fn value<T>(&mut self, map: impl FnOnce(u8) -> Option<T>) -> Result<T, RecordingError> {
+        let offset: usize = self.offset;
+        map(self.u8()?).ok_or(RecordingError::InvalidValue(offset))
+    }
+
+    fn bool(&mut self) -> Result<bool, RecordingError> {
+        self.value(|value| match value {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        })
+    }
+
+    // Reads the number of the elements of at least the given size each, which must fit into the rest of the bytes.
+    fn count(&mut self, min_element_size: usize) -> Result<usize, RecordingError> {
+        let count: usize = self.u32()? as usize;
+        if count.saturating_mul(min_element_size) > self.bytes.len() - self.offset {
+            return Err(RecordingError::Truncated);
+        }
+        Ok(count)
+    }
+
+    fn vec2(&mut self) -> Result<Vec2, RecordingError> {
+        let [x, y] = self.floats()?;
+        Ok(Vec2::new(x, y))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, RecordingError> {
+        let [x, y, z] = self.floats()?;
+        Ok(Vec3::new(x, y, z))
+    }
+
+    fn vec4(&mut self) -> Result<Vec4, RecordingError> {
+        let [x, y, z, w] = self.floats()?;
+        Ok(Vec4::new(x, y, z, w))
+    }
+
+    fn viewport(&mut self) -> Result<Viewport, RecordingError> {
+        Ok(Viewport::new(self.u16()?, self.u16()?, self.u16()?, self.u16()?))
+    }
+
+    fn option<T>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<T, RecordingError>,
+    ) -> Result<Option<T>, RecordingError> {
+        if self.bool()? { Ok(Some(read(self)?)) } else { Ok(None) }
+    }
+
+    fn vec<T>(
+        &mut self,
+        element_size: usize,
+        read: impl Fn(&mut Self) -> Result<T, RecordingError>,
+    ) -> Result<Vec<T>, RecordingError> {
+        (0..self.count(element_size)?).map(|_| read(self)).collect()
+    }
+
+    fn texture(&mut self, textures_num: u32) -> Result<Option<u32>, RecordingError> {
+        let offset: usize = self.offset;
+        match self.option(Self::u32)? {
+            Some(handle) if handle >= textures_num => Err(RecordingError::InvalidValue(offset)),
+            handle => Ok(handle),
+        }
+    }
+
+    fn sampling_filter(&mut self) -> Result<SamplerFilter, RecordingError> {
+        self.value(|value| match value {
+            0 => Some(SamplerFilter::Nearest),
+            1 => Some(SamplerFilter::Bilinear),
+            2 => Some(SamplerFilter::DebugMip),
+            3 => Some(SamplerFilter::Trilinear),
+            4 => Some(SamplerFilter::Anisotropic),
+            _ => None,
+        })
+    }
+
+    fn address_mode(&mut self) -> Result<SamplerAddressMode, RecordingError> {
+        self.value(|value| match value {
+            0 => Some(SamplerAddressMode::Repeat),
+            1 => Some(SamplerAddressMode::ClampToEdge),
+            2 => Some(SamplerAddressMode::MirroredRepeat),
+            _ => None,
+        })
+    }
+
+    fn alpha_blending(&mut self) -> Result<AlphaBlendingMode, RecordingError> {
+        self.value(|value| match value {
+            0 => Some(AlphaBlendingMode::None),
+            1 => Some(AlphaBlendingMode::Normal),
+            2 => Some(AlphaBlendingMode::Additive),
+            3 => Some(AlphaBlendingMode::Multiply),
+            4 => Some(AlphaBlendingMode::Screen),
+            5 => Some(AlphaBlendingMode::Subtract),
+            6 => Some(AlphaBlendingMode::Min),
+            7 => Some(AlphaBlendingMode::Max),
+            8 => Some(AlphaBlendingMode::PremultipliedNormal),
+            _ => None,
+        })
+    }
+
+    fn rasterization(&mut self, textures_num: u32) -> Result<RecordedRasterization, RecordingError> {
+        let world_positions: Vec<Vec3> = self.vec(12, Self::vec3)?;
+        let normals: Vec<Vec3> = self.vec(12, Self::vec3)?;
+        let tex_coords: Vec<Vec2> = self.vec(8, Self::vec2)?;
+        let colors: Vec<Vec4> = self.vec(16, Self::vec4)?;
+        let tex_coords2: Vec<Vec2> = self.vec(8, Self::vec2)?;
+        let indices: Vec<u32> = self.vec(4, Self::u32)?;
+        let texture: Option<u32> = self.texture(textures_num)?;
+        let normal_map: Option<u32> = self.texture(textures_num)?;
+        let lightmap: Option<u32> = self.texture(textures_num)?;
+        let lights: Vec<Light> = self.vec(25, |r| match r.u8()? {
+            0 => Ok(Light::Directional { direction: r.vec3()?, color: r.vec3()? }),
+            1 => Ok(Light::Point { position: r.vec3()?, color: r.vec3()?, range: r.f32()? }),
+            _ => Err(RecordingError::InvalidValue(r.offset - 1)),
+        })?;
+        let lighting_material: Option<LightingMaterial> = self.option(|r| {
+            Ok(LightingMaterial { ambient: r.vec3()?, diffuse: r.vec3()?, specular: r.vec3()?, shininess: r.f32()? })
+        })?;
+
+        let model = Mat34(self.floats()?);
+        let view = Mat44(self.floats()?);
+        let projection = Mat44(self.floats()?);
+        let culling: CullMode = self.value(|value| match value {
+            0 => Some(CullMode::None),
+            1 => Some(CullMode::CW),
+            2 => Some(CullMode::CCW),
+            _ => None,
+        })?;
+        let color: Vec4 = self.vec4()?;
+        let texture_region: Option<TextureRegion> = self.option(|r| {
+            let [u0, v0, u1, v1] = r.floats()?;
+            Ok(TextureRegion { u0, v0, u1, v1, clamp: r.bool()? })
+        })?;
+        let sampling_filter: SamplerFilter = self.sampling_filter()?;
+        let address_mode_u: SamplerAddressMode = self.address_mode()?;
+        let address_mode_v: SamplerAddressMode = self.address_mode()?;
+        let alpha_blending: AlphaBlendingMode = self.alpha_blending()?;
+        let alpha_test: u8 = self.u8()?;
+        let alpha_source: AlphaSource = match self.u8()? {
+            0 => AlphaSource::Modulate,
+            1 => AlphaSource::Texture,
+            2 => AlphaSource::Vertex,
+            3 => AlphaSource::Constant(self.u8()?),
+            _ => return Err(RecordingError::InvalidValue(self.offset - 1)),
+        };
+        let fast_math: bool = self.bool()?;
+        let scissor: Option<Viewport> = self.option(Self::viewport)?;
+        let sdf: Option<SdfSampling> = self.option(|r| {
+            let [threshold, range, smoothing] = r.floats()?;
+            Ok(SdfSampling { threshold, range, smoothing })
+        })?;
+        let pattern: Option<ProceduralPattern> = self.option(|r| match r.u8()? {
+            0 => Ok(ProceduralPattern::Checker { even: r.vec4()?, odd: r.vec4()? }),
+            1 => Ok(ProceduralPattern::Grid { line: r.vec4()?, background: r.vec4()?, width: r.f32()? }),
+            _ => Err(RecordingError::InvalidValue(r.offset - 1)),
+        })?;
+        let uniforms = Uniforms {
+            time: self.f32()?,
+            seed: self.u32()?,
+            user: [self.vec4()?, self.vec4()?, self.vec4()?, self.vec4()?],
+        };
+        let opacity: OpacityHint = self.value(|value| match value {
+            0 => Some(OpacityHint::Ordered),
+            1 => Some(OpacityHint::Opaque),
+            2 => Some(OpacityHint::Translucent),
+            _ => None,
+        })?;
+        let aabb: Option<AABB> = self.option(|r| Ok(AABB::new(r.vec3()?, r.vec3()?)))?;
+        let depth_clamp: bool = self.bool()?;
+        let depth_test: DepthTest = self.value(|value| match value {
+            0 => Some(DepthTest::Never),
+            1 => Some(DepthTest::Less),
+            2 => Some(DepthTest::LEqual),
+            3 => Some(DepthTest::Greater),
+            4 => Some(DepthTest::GEqual),
+            5 => Some(DepthTest::Equal),
+            6 => Some(DepthTest::Always),
+            _ => None,
+        })?;
+        let depth_write: bool = self.bool()?;
+        let [constant, slope] = self.floats()?;
+        let color_write_mask: ColorWriteMask = self.value(|mask| {
+            (mask < 16).then_some(ColorWriteMask {
+                r: mask & 1 != 0,
+                g: mask & 2 != 0,
+                b: mask & 4 != 0,
+                a: mask & 8 != 0,
+            })
+        })?;
+        let tint = ColorTint { multiply: self.vec4()?, add: self.vec4()? };
+        let fog: Option<Fog> = self.option(|r| {
+            let mode: FogMode = match r.u8()? {
+                0 => {
+                    let [start, end] = r.floats()?;
+                    FogMode::Linear { start, end }
+                }
+                1 => FogMode::Exp { density: r.f32()? },
+                2 => FogMode::Exp2 { density: r.f32()? },
+                _ => return Err(RecordingError::InvalidValue(r.offset - 1)),
+            };
+            Ok(Fog { mode, color: r.vec3()? })
+        })?;
+        let previous_model: Option<Mat34> = self.option(|r| Ok(Mat34(r.floats()?)))?;
+        let previous_view_projection: Option<Mat44> = self.option(|r| Ok(Mat44(r.floats()?)))?;
+
+        Ok(RecordedRasterization {
+            state: RasterizationCommand {
+                model,
+                view,
+                projection,
+                culling,
+                color,
+                texture_region,
+                sampling_filter,
+                address_mode_u,
+                address_mode_v,
+                alpha_blending,
+                alpha_test,
+                alpha_source,
+                fast_math,
+                scissor,
+                sdf,
+                pattern,
+                uniforms,
+                opacity,
+                aabb,
+                depth_clamp,
+                depth_test,
+                depth_write,
+                depth_bias: DepthBias { constant, slope },
+                color_write_mask,
+                tint,
+                fog,
+                previous_model,
+                previous_view_projection,
+                ..Default::default()
+            },
+            world_positions,
+            normals,
+            tex_coords,
+            colors,
+            tex_coords2,
+            indices,
+            texture,
+            normal_map,
+            lightmap,
+            lights,
+            lighting_material,
+        })
+    }
+
+    fn fill(&mut self) -> Result<FillCommand, RecordingError> {
+        let (min, max) = (self.vec2()?, self.vec2()?);
+        let (corner_radius, border_width) = (self.f32()?, self.f32()?);
+        let border_color: Vec4 = self.vec4()?;
+        let paint: FillPaint = match self.u8()? {
+            0 => FillPaint::Solid(self.vec4()?),
+            1 => FillPaint::LinearGradient {
+                from: self.vec2()?,
+                to: self.vec2()?,
+                color_from: self.vec4()?,
+                color_to: self.vec4()?,
+            },
+            2 => FillPaint::RadialGradient {
+                center: self.vec2()?,
+                radius: self.f32()?,
+                color_inner: self.vec4()?,
+                color_outer: self.vec4()?,
+            },
+            _ => return Err(RecordingError::InvalidValue(self.offset - 1)),
+        };
+        Ok(FillCommand {
+            min,
+            max,
+            corner_radius,
+            border_width,
+            border_color,
+            paint,
+            alpha_blending: self.alpha_blending()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded_quad() -> RecordedFrame {
+        let mut frame = RecordedFrame::new(Viewport::new(0, 0, 16, 16));
+        let positions: [Vec3; 4] = [
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(-1.0, 1.0, 0.0),
+        ];
+        let lights: [Light; 1] =
+            [Light::Point { position: Vec3::new(0.0, 0.0, 1.0), color: Vec3::new(1.0, 1.0, 1.0), range: 5.0 }];
+        frame.record(&RasterizationCommand {
+            world_positions: &positions,
+            indices: &[0, 1, 2, 0, 2, 3],
+            alpha_source: AlphaSource::Constant(128),
+            fog: Some(Fog { mode: FogMode::Exp2 { density: 0.5 }, color: Vec3::new(0.1, 0.2, 0.3) }),
+            lighting: Some(VertexLighting { lights: &lights, material: LightingMaterial::default() }),
+            previous_model: Some(Mat34::translate(Vec3::new(0.5, 0.0, 0.0))),
+            ..Default::default()
+        });
+        frame.record_fill(&FillCommand {
+            paint: FillPaint::RadialGradient {
+                center: Vec2::new(8.0, 8.0),
+                radius: 4.0,
+                color_inner: Vec4::new(1.0, 0.0, 0.0, 1.0),
+                color_outer: Vec4::new(0.0, 0.0, 1.0, 1.0),
+            },
+            ..Default::default()
+        });
+        frame
+    }
+
+    #[test]
+    fn frame_round_trips_through_bytes() {
+        let frame: RecordedFrame = recorded_quad();
+        let bytes: Vec<u8> = frame.to_bytes();
+        let loaded: RecordedFrame = RecordedFrame::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.viewport, frame.viewport);
+        assert_eq!(loaded.commands_num(), 2);
+        assert_eq!(loaded.to_bytes(), bytes);
+        let RecordedCommand::Rasterization(quad) = &loaded.commands[0] else {
+            panic!("{:?}", loaded.commands[0]);
+        };
+        assert_eq!(quad.indices, [0, 1, 2, 0, 2, 3]);
+        assert_eq!(quad.state.alpha_source, AlphaSource::Constant(128));
+        assert_eq!(quad.state.fog.map(|fog| fog.mode), Some(FogMode::Exp2 { density: 0.5 }));
+        assert_eq!(quad.lights.len(), 1);
+    }
+
+    #[test]
+    fn invalid_bytes_are_rejected() {
+        let bytes: Vec<u8> = recorded_quad().to_bytes();
+        assert_eq!(RecordedFrame::from_bytes(b"BVH!").unwrap_err(), RecordingError::InvalidSignature);
+        assert_eq!(RecordedFrame::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(), RecordingError::Truncated);
+        let mut version: Vec<u8> = bytes.clone();
+        version[4] = 7;
+        assert_eq!(RecordedFrame::from_bytes(&version).unwrap_err(), RecordingError::UnsupportedVersion(7));
+        // The fill's blending mode is the last byte
+        let mut blending: Vec<u8> = bytes.clone();
+        *blending.last_mut().unwrap() = 42;
+        assert_eq!(RecordedFrame::from_bytes(&blending).unwrap_err(), RecordingError::InvalidValue(bytes.len() - 1));
+    }
+}
@@ -31,8 +31,30 @@ impl RGBA {
     }
 }
 
+// The encoded zero vector, i.e. "no normal". The normal buffer can be cleared with it to tell the pixels without any
+// geometry apart, as no unit normal is encoded into it.
+pub const NORMAL_NONE: RGBA = RGBA { r: 127, g: 127, b: 127, a: 0 };
+
+// The encoded up vector (0, 1, 0).
+pub const NORMAL_UP: RGBA = RGBA { r: 127, g: 255, b: 127, a: 0 };
+
+// Encodes the normal the same way the rasterizer writes it into the normal buffer: each component is mapped from
+// [-1, 1] to [0, 255], the alpha is zero.
+pub fn encode_normal_as_color(normal: Vec3) -> RGBA {
+    let encode = |v: f32| (v.clamp(-1.0, 1.0) * 127.5 + 127.5) as u8;
+    RGBA::new(encode(normal.x), encode(normal.y), encode(normal.z), 0)
+}
+
+// Decodes the normal written by the rasterizer, the result is approximately unit length.
+// The pixels without a normal, i.e. NORMAL_NONE, must be skipped by the caller, in debug builds decoding one panics.
 pub fn decode_normal_from_color(color: RGBA) -> Vec3 {
+    debug_assert!(!is_normal_none(color), "decoding a pixel without a normal, the normal buffer wasn't written there");
     let normal: Vec3 =
         (Vec3::new(color.r as f32, color.g as f32, color.b as f32) - Vec3::new(127.0, 127.0, 127.0)) / 128.0;
     normal
 }
+
+// Checks whether the encoded value is NORMAL_NONE, ignoring the alpha.
+pub fn is_normal_none(color: RGBA) -> bool {
+    color.r == NORMAL_NONE.r && color.g == NORMAL_NONE.g && color.b == NORMAL_NONE.b
+}
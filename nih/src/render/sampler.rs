@@ -1,12 +1,35 @@
+use super::super::math::*;
 use super::*;
 
 #[repr(u8)]
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SamplerFilter {
     Nearest = 0,
     Bilinear = 1,
     DebugMip = 2,
     Trilinear = 3,
+
+    // Trilinear taps spread along the major axis of the pixel's footprint in the texture.
+    // Requires the UV derivatives, see Sampler::new_anisotropic(), otherwise falls back to Trilinear.
+    Anisotropic = 4,
+}
+
+// Maximum number of taps the anisotropic filter takes along the major axis of the footprint.
+pub const MAX_ANISOTROPY: u32 = 8;
+
+// Specifies how texture coordinates outside of [0, 1] are mapped onto the texture, independently for U and V.
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SamplerAddressMode {
+    // The texture is tiled, i.e. only the fractional part of the coordinate is used.
+    Repeat = 0,
+
+    // The coordinate is clamped to the texture's edge, the border texels are stretched.
+    ClampToEdge = 1,
+
+    // The texture is tiled with every other tile flipped, so the adjacent tiles share their edges.
+    MirroredRepeat = 2,
 }
 
 type SampleFunction = fn(*const u8, f32, f32) -> RGBA;
@@ -20,14 +43,61 @@ pub struct SamplerUVScale {
     pub scale: f32,
 }
 
+// Mapping of region-relative texture coordinates onto the prescaled coordinates of the entire texture.
+#[derive(Clone, Copy, Debug)]
+struct SamplerRegion {
+    // Region-relative coordinates are clamped to [lo, hi] to avoid filtering with the texels outside of the region...
+    u_lo: f32,
+    u_hi: f32,
+    v_lo: f32,
+    v_hi: f32,
+
+    // ... and then mapped as x' = x * scale + bias.
+    u_scale: f32,
+    u_bias: f32,
+    v_scale: f32,
+    v_bias: f32,
+
+    // Whether the coordinates are clamped or repeated before the mapping.
+    clamp: bool,
+}
+
 pub struct Sampler {
     texels0: *const u8,
     sample_function: SampleFunction,
     uv_scale: SamplerUVScale,
+    mip_width: u16,
+    region: SamplerRegion,
+
+    // Anisotropic filtering: number of taps and the prescaled distance between them.
+    taps: u32,
+    tap_du: f32,
+    tap_dv: f32,
 }
 
 impl Sampler {
     pub fn new(texture: &std::sync::Arc<Texture>, filtering: SamplerFilter, lod: f32) -> Self {
+        Self::new_with_address_modes(texture, filtering, lod, SamplerAddressMode::Repeat, SamplerAddressMode::Repeat)
+    }
+
+    // Creates a sampler which maps the coordinates outside of [0, 1] according to the specified address modes.
+    // The modes are baked into the sampling function, so the choice has no per-sample cost.
+    pub fn new_with_address_modes(
+        texture: &std::sync::Arc<Texture>,
+        filtering: SamplerFilter,
+        lod: f32,
+        address_mode_u: SamplerAddressMode,
+        address_mode_v: SamplerAddressMode,
+    ) -> Self {
+        // There are no trilinear samplers for RGBA and BC3 textures, those are tapped bilinearly from the nearest mip.
+        let filtering: SamplerFilter = if (filtering == SamplerFilter::Trilinear
+            || filtering == SamplerFilter::Anisotropic)
+            && (texture.format == TextureFormat::RGBA || texture.format == TextureFormat::BC3)
+        {
+            SamplerFilter::Bilinear
+        } else {
+            filtering
+        };
         let mips: u32 = texture.count;
         let lod_rounded: f32 = if lod > 0.0 { lod.round() } else { 0.0 };
         let lod_floored: f32 = if lod > 0.0 { lod.floor() } else { 0.0 };
@@ -39,32 +109,157 @@ impl Sampler {
             SamplerFilter::Nearest | SamplerFilter::Bilinear | SamplerFilter::DebugMip => {
                 (lod_rounded as i32).clamp(0, mips as i32 - 1)
             }
-            SamplerFilter::Trilinear => (lod_floored as i32).clamp(0, mips as i32 - 1),
+            SamplerFilter::Trilinear | SamplerFilter::Anisotropic => (lod_floored as i32).clamp(0, mips as i32 - 1),
         };
         let mip0 = &texture.mips[mip0_index as usize];
         let texels0 = unsafe { texture.texels.as_ptr().add(mip0.offset as usize) };
         let log2_size = mip0.width.trailing_zeros() as usize;
+        let (mode_u, mode_v): (usize, usize) = (address_mode_u as usize, address_mode_v as usize);
+
+        // A texture with a truncated mip chain has no next level to blend with at its smallest level
+        let filtering: SamplerFilter = if (filtering == SamplerFilter::Trilinear
+            || filtering == SamplerFilter::Anisotropic)
+            && mip0_index as u32 == mips - 1
+            && log2_size > 0
+        {
+            SamplerFilter::Bilinear
+        } else {
+            filtering
+        };
         let entry = match filtering {
-            SamplerFilter::Nearest => &NEAREST_SAMPLER_TABLE[texture.format as usize][log2_size],
-            SamplerFilter::Bilinear => &BILINEAR_SAMPLER_TABLE[texture.format as usize][log2_size],
+            SamplerFilter::Nearest => &NEAREST_SAMPLER_TABLE[mode_u][mode_v][texture.format as usize][log2_size],
+            SamplerFilter::Bilinear => &BILINEAR_SAMPLER_TABLE[mode_u][mode_v][texture.format as usize][log2_size],
             SamplerFilter::DebugMip => &DEBUG_SAMPLER_TABLE[texture.format as usize][log2_size],
-            SamplerFilter::Trilinear => &TRILINEAR_SAMPLER_TABLE[texture.format as usize][log2_size][lod_fract_level],
+            SamplerFilter::Trilinear | SamplerFilter::Anisotropic => {
+                &TRILINEAR_SAMPLER_TABLE[mode_u][mode_v][texture.format as usize][log2_size][lod_fract_level]
+            }
         };
         let sample_function = entry.f;
         let uv_scale = SamplerUVScale { bias: entry.b, scale: entry.s };
-        Sampler { texels0, sample_function, uv_scale }
+        Sampler {
+            texels0,
+            sample_function,
+            uv_scale,
+            mip_width: mip0.width,
+            region: SamplerRegion::default(),
+            taps: 1,
+            tap_du: 0.0,
+            tap_dv: 0.0,
+        }
+    }
+
+    // Creates an anisotropic sampler from the derivatives of the texture coordinates along the screen axes.
+    // Up to MAX_ANISOTROPY trilinear taps are spread along the major axis of the footprint, while the level of detail is
+    // selected by the footprint's length divided by the number of taps, which keeps the surfaces at grazing angles sharp.
+    // Should be sampled via sample_anisotropic_prescaled().
+    pub fn new_anisotropic(
+        texture: &std::sync::Arc<Texture>,
+        uv_dx: Vec2,
+        uv_dy: Vec2,
+        address_mode_u: SamplerAddressMode,
+        address_mode_v: SamplerAddressMode,
+    ) -> Self {
+        let width: f32 = texture.mips[0].width as f32;
+        let height: f32 = texture.mips[0].height as f32;
+        let texels_dx: f32 = Vec2::new(uv_dx.x * width, uv_dx.y * height).length();
+        let texels_dy: f32 = Vec2::new(uv_dy.x * width, uv_dy.y * height).length();
+        let (major, major_length, minor_length): (Vec2, f32, f32) = if texels_dx >= texels_dy {
+            (uv_dx, texels_dx, texels_dy)
+        } else {
+            (uv_dy, texels_dy, texels_dx)
+        };
+        let ratio: f32 = major_length / minor_length.max(f32::MIN_POSITIVE);
+        let taps: u32 = (ratio.ceil().min(MAX_ANISOTROPY as f32) as u32)
+            .max(1)
+            .next_power_of_two();
+        let lod: f32 = (major_length / taps as f32).max(f32::MIN_POSITIVE).log2();
+
+        let mut sampler =
+            Self::new_with_address_modes(texture, SamplerFilter::Trilinear, lod, address_mode_u, address_mode_v);
+        let step: Vec2 = major * (sampler.uv_scale.scale / taps as f32);
+        sampler.taps = taps;
+        sampler.tap_du = step.x;
+        sampler.tap_dv = step.y;
+        sampler
+    }
+
+    // Creates a sampler restricted to a region of the texture, which should be sampled via sample_in_region().
+    // The level of detail is limited by the mips where the region still covers at least a single texel.
+    pub fn new_in_region(
+        texture: &std::sync::Arc<Texture>,
+        filtering: SamplerFilter,
+        lod: f32,
+        region: &TextureRegion,
+    ) -> Self {
+        let mut sampler = Self::new(texture, filtering, lod.min(region.max_lod(texture)));
+
+        // Keep the filter footprint inside the region: inset the coordinates by half a texel of the coarsest mip used.
+        let (width, height) = region.size_in_texels(texture);
+        let mut level_scale: f32 = sampler.mip_width as f32 / texture.mips[0].width as f32;
+        if filtering == SamplerFilter::Trilinear || filtering == SamplerFilter::Anisotropic {
+            level_scale *= 0.5;
+        }
+        let u_inset: f32 = (0.5 / (width * level_scale).max(1.0)).min(0.5);
+        let v_inset: f32 = (0.5 / (height * level_scale).max(1.0)).min(0.5);
+
+        let uv_scale = sampler.uv_scale;
+        sampler.region = SamplerRegion {
+            u_lo: u_inset,
+            u_hi: 1.0 - u_inset,
+            v_lo: v_inset,
+            v_hi: 1.0 - v_inset,
+            u_scale: (region.u1 - region.u0) * uv_scale.scale,
+            u_bias: (region.u0 + uv_scale.bias) * uv_scale.scale,
+            v_scale: (region.v1 - region.v0) * uv_scale.scale,
+            v_bias: (region.v0 + uv_scale.bias) * uv_scale.scale,
+            clamp: region.clamp,
+        };
+        sampler
     }
 
     pub fn sample_prescaled(&self, u: f32, v: f32) -> RGBA {
         (self.sample_function)(self.texels0, u, v)
     }
 
+    // Averages the taps of an anisotropic sampler centered at the prescaled coordinates.
+    pub fn sample_anisotropic_prescaled(&self, u: f32, v: f32) -> RGBA {
+        let taps: u32 = self.taps;
+        let first: f32 = -0.5 * (taps - 1) as f32;
+        let mut r: u32 = 0;
+        let mut g: u32 = 0;
+        let mut b: u32 = 0;
+        let mut a: u32 = 0;
+        for i in 0..taps {
+            let t: f32 = first + i as f32;
+            let tap: RGBA = (self.sample_function)(self.texels0, u + t * self.tap_du, v + t * self.tap_dv);
+            r += tap.r as u32;
+            g += tap.g as u32;
+            b += tap.b as u32;
+            a += tap.a as u32;
+        }
+        let shift: u32 = taps.trailing_zeros();
+        RGBA::new((r >> shift) as u8, (g >> shift) as u8, (b >> shift) as u8, (a >> shift) as u8)
+    }
+
     pub fn sample(&self, u: f32, v: f32) -> RGBA {
         let tu = (u + self.uv_scale.bias) * self.uv_scale.scale;
         let tv = (v + self.uv_scale.bias) * self.uv_scale.scale;
         (self.sample_function)(self.texels0, tu, tv)
     }
 
+    // Samples the region the sampler was created with, [0, 1] coordinates cover the entire region.
+    pub fn sample_in_region(&self, u: f32, v: f32) -> RGBA {
+        let region: &SamplerRegion = &self.region;
+        let (u, v) = if region.clamp {
+            (u, v)
+        } else {
+            (u - u.floor(), v - v.floor())
+        };
+        let tu: f32 = u.clamp(region.u_lo, region.u_hi) * region.u_scale + region.u_bias;
+        let tv: f32 = v.clamp(region.v_lo, region.v_hi) * region.v_scale + region.v_bias;
+        (self.sample_function)(self.texels0, tu, tv)
+    }
+
     pub fn uv_scale(&self) -> SamplerUVScale {
         self.uv_scale
     }
@@ -72,7 +267,32 @@ impl Sampler {
 
 impl Default for Sampler {
     fn default() -> Self {
-        Sampler { texels0: std::ptr::null(), sample_function: noop_sample, uv_scale: SamplerUVScale::default() }
+        Sampler {
+            texels0: std::ptr::null(),
+            sample_function: noop_sample,
+            uv_scale: SamplerUVScale::default(),
+            mip_width: 0,
+            region: SamplerRegion::default(),
+            taps: 1,
+            tap_du: 0.0,
+            tap_dv: 0.0,
+        }
+    }
+}
+
+impl Default for SamplerRegion {
+    fn default() -> Self {
+        SamplerRegion {
+            u_lo: 0.0,
+            u_hi: 1.0,
+            v_lo: 0.0,
+            v_hi: 1.0,
+            u_scale: 1.0,
+            u_bias: 0.0,
+            v_scale: 1.0,
+            v_bias: 0.0,
+            clamp: false,
+        }
     }
 }
 
@@ -86,14 +306,37 @@ fn noop_sample(_texels: *const u8, _u: f32, _v: f32) -> RGBA {
     RGBA::new(0, 0, 0, 255)
 }
 
-fn sample_nearest<const SIZE: u16, const FORMAT: u8>(texels: *const u8, u: f32, v: f32) -> RGBA {
-    debug_assert!(u >= 0.0 && v >= 0.0);
+// Maps a texel coordinate, biased by 10 texture sizes into positive territory, onto [0, size).
+// The address mode is a compile-time constant, so only one of the branches survives.
+#[inline(always)]
+fn address_texel<const MODE: u8>(x: i32, size: u32) -> u32 {
+    if MODE == SamplerAddressMode::ClampToEdge as u8 {
+        return (x - 10 * size as i32).clamp(0, size as i32 - 1) as u32;
+    }
+    if MODE == SamplerAddressMode::MirroredRepeat as u8 {
+        let period: u32 = 2 * size;
+        let m: u32 = (x as u32) & (period - 1);
+        return m.min(period - 1 - m);
+    }
+    (x as u32) & (size - 1)
+}
+
+const fn is_clamped(mode: u8) -> bool {
+    mode == SamplerAddressMode::ClampToEdge as u8
+}
+
+fn sample_nearest<const SIZE: u16, const FORMAT: u8, const ADDRESS_U: u8, const ADDRESS_V: u8>(
+    texels: *const u8,
+    u: f32,
+    v: f32,
+) -> RGBA {
+    debug_assert!((u >= 0.0 || is_clamped(ADDRESS_U)) && (v >= 0.0 || is_clamped(ADDRESS_V)));
     let bpp: usize = bytes_per_pixel_u8(FORMAT);
     let stride: usize = SIZE as usize * bpp;
     let itx: i32 = unsafe { u.to_int_unchecked() };
     let ity: i32 = unsafe { v.to_int_unchecked() };
-    let x: usize = (itx as usize) & (SIZE as usize - 1);
-    let y: usize = (ity as usize) & (SIZE as usize - 1);
+    let x: usize = address_texel::<ADDRESS_U>(itx, SIZE as u32) as usize;
+    let y: usize = address_texel::<ADDRESS_V>(ity, SIZE as u32) as usize;
     let offset: usize = y * stride + x * bpp;
     let texel: *const u8 = unsafe { texels.add(offset) };
     if FORMAT == TextureFormat::Grayscale as u8 {
@@ -109,8 +352,12 @@ fn sample_nearest<const SIZE: u16, const FORMAT: u8>(texels: *const u8, u: f32,
     RGBA::new(0, 0, 0, 255)
 }
 
-fn sample_bilinear<const SIZE: u16, const FORMAT: u8>(texels: *const u8, u: f32, v: f32) -> RGBA {
-    debug_assert!(u >= 0.0 && v >= 0.0);
+fn sample_bilinear<const SIZE: u16, const FORMAT: u8, const ADDRESS_U: u8, const ADDRESS_V: u8>(
+    texels: *const u8,
+    u: f32,
+    v: f32,
+) -> RGBA {
+    debug_assert!((u >= 0.0 || is_clamped(ADDRESS_U)) && (v >= 0.0 || is_clamped(ADDRESS_V)));
     let bpp: usize = bytes_per_pixel_u8(FORMAT);
     let stride: usize = SIZE as usize * bpp;
     let itx: i32 = unsafe { u.to_int_unchecked() };
@@ -125,14 +372,14 @@ fn sample_bilinear<const SIZE: u16, const FORMAT: u8>(texels: *const u8, u: f32,
     let wb: u32 = wx1 * wy;
     let wc: u32 = wx * wy1;
     let wd: u32 = wx1 * wy1;
-    let x0: u32 = tx >> 8;
-    let x1: u32 = x0 + 1;
-    let y0: u32 = ty >> 8;
-    let y1: u32 = y0 + 1;
-    let tx0: u32 = x0 & (SIZE as u32 - 1);
-    let tx1: u32 = x1 & (SIZE as u32 - 1);
-    let ty0: u32 = y0 & (SIZE as u32 - 1);
-    let ty1: u32 = y1 & (SIZE as u32 - 1);
+    let x0: i32 = itx >> 8;
+    let x1: i32 = x0 + 1;
+    let y0: i32 = ity >> 8;
+    let y1: i32 = y0 + 1;
+    let tx0: u32 = address_texel::<ADDRESS_U>(x0, SIZE as u32);
+    let tx1: u32 = address_texel::<ADDRESS_U>(x1, SIZE as u32);
+    let ty0: u32 = address_texel::<ADDRESS_V>(y0, SIZE as u32);
+    let ty1: u32 = address_texel::<ADDRESS_V>(y1, SIZE as u32);
     let offset_a: usize = ty0 as usize * stride + tx0 as usize * bpp;
     let offset_b: usize = ty0 as usize * stride + tx1 as usize * bpp;
     let offset_c: usize = ty1 as usize * stride + tx0 as usize * bpp;
@@ -178,6 +425,61 @@ fn sample_bilinear<const SIZE: u16, const FORMAT: u8>(texels: *const u8, u: f32,
     RGBA::new(0, 0, 0, 255)
 }
 
+// Fetches a single texel of a BC3-compressed mip level, decoding it on the fly.
+#[inline(always)]
+fn fetch_bc3<const SIZE: u16>(texels: *const u8, x: u32, y: u32) -> RGBA {
+    let blocks_per_row: usize = (SIZE as usize).div_ceil(4);
+    let block_offset: usize = ((y >> 2) as usize * blocks_per_row + (x >> 2) as usize) * BC3_BLOCK_SIZE;
+    let block: &[u8; BC3_BLOCK_SIZE] = unsafe { &*(texels.add(block_offset) as *const [u8; BC3_BLOCK_SIZE]) };
+    decode_bc3_texel(block, (y & 3) * 4 + (x & 3))
+}
+
+fn sample_nearest_bc3<const SIZE: u16, const ADDRESS_U: u8, const ADDRESS_V: u8>(
+    texels: *const u8,
+    u: f32,
+    v: f32,
+) -> RGBA {
+    debug_assert!((u >= 0.0 || is_clamped(ADDRESS_U)) && (v >= 0.0 || is_clamped(ADDRESS_V)));
+    let itx: i32 = unsafe { u.to_int_unchecked() };
+    let ity: i32 = unsafe { v.to_int_unchecked() };
+    let x: u32 = address_texel::<ADDRESS_U>(itx, SIZE as u32);
+    let y: u32 = address_texel::<ADDRESS_V>(ity, SIZE as u32);
+    fetch_bc3::<SIZE>(texels, x, y)
+}
+
+fn sample_bilinear_bc3<const SIZE: u16, const ADDRESS_U: u8, const ADDRESS_V: u8>(
+    texels: *const u8,
+    u: f32,
+    v: f32,
+) -> RGBA {
+    debug_assert!((u >= 0.0 || is_clamped(ADDRESS_U)) && (v >= 0.0 || is_clamped(ADDRESS_V)));
+    let itx: i32 = unsafe { u.to_int_unchecked() };
+    let ity: i32 = unsafe { v.to_int_unchecked() };
+    let wx1: u32 = itx as u32 & 255;
+    let wx: u32 = 256 - wx1;
+    let wy1: u32 = ity as u32 & 255;
+    let wy: u32 = 256 - wy1;
+    let wa: u32 = wx * wy;
+    let wb: u32 = wx1 * wy;
+    let wc: u32 = wx * wy1;
+    let wd: u32 = wx1 * wy1;
+    let x0: i32 = itx >> 8;
+    let y0: i32 = ity >> 8;
+    let tx0: u32 = address_texel::<ADDRESS_U>(x0, SIZE as u32);
+    let tx1: u32 = address_texel::<ADDRESS_U>(x0 + 1, SIZE as u32);
+    let ty0: u32 = address_texel::<ADDRESS_V>(y0, SIZE as u32);
+    let ty1: u32 = address_texel::<ADDRESS_V>(y0 + 1, SIZE as u32);
+    let a: RGBA = fetch_bc3::<SIZE>(texels, tx0, ty0);
+    let b: RGBA = fetch_bc3::<SIZE>(texels, tx1, ty0);
+    let c: RGBA = fetch_bc3::<SIZE>(texels, tx0, ty1);
+    let d: RGBA = fetch_bc3::<SIZE>(texels, tx1, ty1);
+    let r: u32 = a.r as u32 * wa + b.r as u32 * wb + c.r as u32 * wc + d.r as u32 * wd;
+    let g: u32 = a.g as u32 * wa + b.g as u32 * wb + c.g as u32 * wc + d.g as u32 * wd;
+    let bl: u32 = a.b as u32 * wa + b.b as u32 * wb + c.b as u32 * wc + d.b as u32 * wd;
+    let al: u32 = a.a as u32 * wa + b.a as u32 * wb + c.a as u32 * wc + d.a as u32 * wd;
+    RGBA::new((r >> 16) as u8, (g >> 16) as u8, (bl >> 16) as u8, (al >> 16) as u8)
+}
+
 fn mip_size_sample<const SIZE: u16>(_texels: *const u8, _u: f32, _v: f32) -> RGBA {
     match SIZE {
         1 => RGBA::new(255, 0, 0, 255),       // red
@@ -199,12 +501,18 @@ fn mip_size_sample<const SIZE: u16>(_texels: *const u8, _u: f32, _v: f32) -> RGB
 
 const TRILINEAR_FRACT_LEVELS: u32 = 16;
 const TRILINEAR_FRACT_LEVELS_LOG2: u32 = TRILINEAR_FRACT_LEVELS.ilog2();
-fn sample_trilinear<const MIP0_SIZE: u16, const FORMAT: u8, const FRACT: u32>(
+fn sample_trilinear<
+    const MIP0_SIZE: u16,
+    const FORMAT: u8,
+    const FRACT: u32,
+    const ADDRESS_U: u8,
+    const ADDRESS_V: u8,
+>(
     mip0_texels: *const u8,
     u: f32,
     v: f32,
 ) -> RGBA {
-    debug_assert!(u >= 0.0 && v >= 0.0);
+    debug_assert!((u >= 0.0 || is_clamped(ADDRESS_U)) && (v >= 0.0 || is_clamped(ADDRESS_V)));
     debug_assert!(MIP0_SIZE >= 2);
 
     // These are all compile-time constants, but the compiler doesn't allow to declare them as const
@@ -230,22 +538,24 @@ fn sample_trilinear<const MIP0_SIZE: u16, const FORMAT: u8, const FRACT: u32>(
     let mip0_wb: u32 = mip0_wx1 * mip0_wy;
     let mip0_wc: u32 = mip0_wx * mip0_wy1;
     let mip0_wd: u32 = mip0_wx1 * mip0_wy1;
-    let mip0_x0: u32 = mip0_tx >> 8;
-    let mip0_x1: u32 = mip0_x0 + 1;
-    let mip0_y0: u32 = mip0_ty >> 8;
-    let mip0_y1: u32 = mip0_y0 + 1;
-    let mip0_tx0: u32 = mip0_x0 & (mip0_size as u32 - 1);
-    let mip0_tx1: u32 = mip0_x1 & (mip0_size as u32 - 1);
-    let mip0_ty0: u32 = mip0_y0 & (mip0_size as u32 - 1);
-    let mip0_ty1: u32 = mip0_y1 & (mip0_size as u32 - 1);
+    let mip0_x0: i32 = itx >> 8;
+    let mip0_x1: i32 = mip0_x0 + 1;
+    let mip0_y0: i32 = ity >> 8;
+    let mip0_y1: i32 = mip0_y0 + 1;
+    let mip0_tx0: u32 = address_texel::<ADDRESS_U>(mip0_x0, mip0_size as u32);
+    let mip0_tx1: u32 = address_texel::<ADDRESS_U>(mip0_x1, mip0_size as u32);
+    let mip0_ty0: u32 = address_texel::<ADDRESS_V>(mip0_y0, mip0_size as u32);
+    let mip0_ty1: u32 = address_texel::<ADDRESS_V>(mip0_y1, mip0_size as u32);
     let mip0_offset_a: usize = mip0_ty0 as usize * mip0_stride + mip0_tx0 as usize * bpp;
     let mip0_offset_b: usize = mip0_ty0 as usize * mip0_stride + mip0_tx1 as usize * bpp;
     let mip0_offset_c: usize = mip0_ty1 as usize * mip0_stride + mip0_tx0 as usize * bpp;
     let mip0_offset_d: usize = mip0_ty1 as usize * mip0_stride + mip0_tx1 as usize * bpp;
 
     // Extract coordinates, offsets and bilinears weights for the mip1 texels
-    let mip1_tx: u32 = (itx as u32 - 127) >> 1;
-    let mip1_ty: u32 = (ity as u32 - 127) >> 1;
+    let mip1_itx: i32 = (itx - 127) >> 1;
+    let mip1_ity: i32 = (ity - 127) >> 1;
+    let mip1_tx: u32 = mip1_itx as u32;
+    let mip1_ty: u32 = mip1_ity as u32;
     let mip1_wx1: u32 = mip1_tx & 255;
     let mip1_wx: u32 = 256 - mip1_wx1;
     let mip1_wy1: u32 = mip1_ty & 255;
@@ -254,14 +564,14 @@ fn sample_trilinear<const MIP0_SIZE: u16, const FORMAT: u8, const FRACT: u32>(
     let mip1_wb: u32 = mip1_wx1 * mip1_wy;
     let mip1_wc: u32 = mip1_wx * mip1_wy1;
     let mip1_wd: u32 = mip1_wx1 * mip1_wy1;
-    let mip1_x0: u32 = mip1_tx >> 8;
-    let mip1_x1: u32 = mip1_x0 + 1;
-    let mip1_y0: u32 = mip1_ty >> 8;
-    let mip1_y1: u32 = mip1_y0 + 1;
-    let mip1_tx0: u32 = mip1_x0 & (mip1_size as u32 - 1);
-    let mip1_tx1: u32 = mip1_x1 & (mip1_size as u32 - 1);
-    let mip1_ty0: u32 = mip1_y0 & (mip1_size as u32 - 1);
-    let mip1_ty1: u32 = mip1_y1 & (mip1_size as u32 - 1);
+    let mip1_x0: i32 = mip1_itx >> 8;
+    let mip1_x1: i32 = mip1_x0 + 1;
+    let mip1_y0: i32 = mip1_ity >> 8;
+    let mip1_y1: i32 = mip1_y0 + 1;
+    let mip1_tx0: u32 = address_texel::<ADDRESS_U>(mip1_x0, mip1_size as u32);
+    let mip1_tx1: u32 = address_texel::<ADDRESS_U>(mip1_x1, mip1_size as u32);
+    let mip1_ty0: u32 = address_texel::<ADDRESS_V>(mip1_y0, mip1_size as u32);
+    let mip1_ty1: u32 = address_texel::<ADDRESS_V>(mip1_y1, mip1_size as u32);
     let mip1_offset_a: usize = mip1_ty0 as usize * mip1_stride + mip1_tx0 as usize * bpp;
     let mip1_offset_b: usize = mip1_ty0 as usize * mip1_stride + mip1_tx1 as usize * bpp;
     let mip1_offset_c: usize = mip1_ty1 as usize * mip1_stride + mip1_tx0 as usize * bpp;
@@ -351,7 +661,80 @@ const fn bytes_per_pixel_u8(fmt: u8) -> usize {
 }
 
 const MAX_LOG2_SIZE: usize = 10; // up to 1024
-const FORMATS: usize = 3; // Grayscale, RGB, RGBA
+const FORMATS: usize = 4; // Grayscale, RGB, RGBA, BC3
+const ADDRESS_MODES: usize = 3; // Repeat, ClampToEdge, MirroredRepeat
+
+// The sampler tables are generated from the lists below, a format, a size or an address mode added to the lists gets
+// its entries in every table without listing them one by one.
+// The lists are checked against the tables' dimensions at compile time.
+
+// Expands to an array of the entries produced by the generic function for every texture format, indexed by the format.
+// The function's generic parameters are the size, the format and then the ones passed to the macro.
+macro_rules! entries_per_format {
+    ($entry:ident) => {
+        entries_per_format!(@ $entry, [])
+    };
+    ($entry:ident::<$($args:ident),*>) => {
+        entries_per_format!(@ $entry, [$($args),*])
+    };
+    (@ $entry:ident, $args:tt) => {
+        [
+            entries_per_size!($entry, { TextureFormat::Grayscale as u8 }, $args),
+            entries_per_size!($entry, { TextureFormat::RGB as u8 }, $args),
+            entries_per_size!($entry, { TextureFormat::RGBA as u8 }, $args),
+            entries_per_size!($entry, { TextureFormat::BC3 as u8 }, $args),
+        ]
+    };
+}
+
+// Expands to an array of the entries produced by the generic function for every texture size, indexed by log2 of the
+// size.
+macro_rules! entries_per_size {
+    ($entry:ident, $format:tt, $args:tt) => {
+        entries_per_size!(@ $entry, $format, $args, [1, 2, 4, 8, 16, 32, 64, 128, 256, 512, 1024])
+    };
+    (@ $entry:ident, $format:tt, $args:tt, [$($size:literal),*]) => {
+        [$(entries_per_size!(@@ $entry, $size, $format, $args)),*]
+    };
+    (@@ $entry:ident, $size:literal, $format:tt, [$($args:ident),*]) => {
+        $entry::<$size, $format, $($args),*>()
+    };
+}
+
+// Expands to an array of the entries produced by the generic function for every trilinear fraction level, which is
+// passed as the function's last generic parameter.
+macro_rules! entries_per_fract {
+    ($entry:ident::<$($args:ident),*>) => {
+        entries_per_fract!(@ $entry, [$($args),*], [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15])
+    };
+    (@ $entry:ident, $args:tt, [$($fract:literal),*]) => {
+        [$(entries_per_fract!(@@ $entry, $fract, $args)),*]
+    };
+    (@@ $entry:ident, $fract:literal, [$($args:ident),*]) => {
+        $entry::<$($args,)* $fract>()
+    };
+}
+
+// Instantiates a table for every combination of the U and V address modes, indexed by [u][v].
+macro_rules! entries_per_address_modes {
+    ($table:ident) => {
+        entries_per_address_modes!(@ $table, [0, 1, 2], [0, 1, 2])
+    };
+    (@ $table:ident, [$($u:literal),*], $vs:tt) => {
+        [$(entries_per_address_modes!(@@ $table, $u, $vs)),*]
+    };
+    (@@ $table:ident, $u:literal, [$($v:literal),*]) => {
+        [$($table::<$u, $v>()),*]
+    };
+}
+
+const _: () = assert!(TextureFormat::Grayscale as usize == 0);
+const _: () = assert!(TextureFormat::RGB as usize == 1);
+const _: () = assert!(TextureFormat::RGBA as usize == 2);
+const _: () = assert!(TextureFormat::BC3 as usize == 3);
+const _: () = assert!(SamplerAddressMode::Repeat as usize == 0);
+const _: () = assert!(SamplerAddressMode::ClampToEdge as usize == 1);
+const _: () = assert!(SamplerAddressMode::MirroredRepeat as usize == 2);
 
 #[derive(Debug, Copy, Clone)]
 struct SamplerEntry {
@@ -365,192 +748,83 @@ struct SamplerEntry {
     s: f32,
 }
 
-static NEAREST_SAMPLER_TABLE: [[SamplerEntry; MAX_LOG2_SIZE + 1]; FORMATS] = {
-    let mut table = [[SamplerEntry { f: noop_sample, b: 0.0, s: 1.0 }; MAX_LOG2_SIZE + 1]; FORMATS];
-    const TF_GRS: u8 = TextureFormat::Grayscale as u8;
-    const TF_RGB: u8 = TextureFormat::RGB as u8;
-    const TF_RGBA: u8 = TextureFormat::RGBA as u8;
-    type SA = SamplerEntry;
-    let grs = &mut table[TextureFormat::Grayscale as usize];
-    grs[0] = SA { f: sample_nearest::<1, TF_GRS>, b: 10.0, s: 1.0 };
-    grs[1] = SA { f: sample_nearest::<2, TF_GRS>, b: 10.0, s: 2.0 };
-    grs[2] = SA { f: sample_nearest::<4, TF_GRS>, b: 10.0, s: 4.0 };
-    grs[3] = SA { f: sample_nearest::<8, TF_GRS>, b: 10.0, s: 8.0 };
-    grs[4] = SA { f: sample_nearest::<16, TF_GRS>, b: 10.0, s: 16.0 };
-    grs[5] = SA { f: sample_nearest::<32, TF_GRS>, b: 10.0, s: 32.0 };
-    grs[6] = SA { f: sample_nearest::<64, TF_GRS>, b: 10.0, s: 64.0 };
-    grs[7] = SA { f: sample_nearest::<128, TF_GRS>, b: 10.0, s: 128.0 };
-    grs[8] = SA { f: sample_nearest::<256, TF_GRS>, b: 10.0, s: 256.0 };
-    grs[9] = SA { f: sample_nearest::<512, TF_GRS>, b: 10.0, s: 512.0 };
-    grs[10] = SA { f: sample_nearest::<1024, TF_GRS>, b: 10.0, s: 1024.0 };
-    let rgb = &mut table[TextureFormat::RGB as usize];
-    rgb[0] = SA { f: sample_nearest::<1, TF_RGB>, b: 10.0, s: 1.0 };
-    rgb[1] = SA { f: sample_nearest::<2, TF_RGB>, b: 10.0, s: 2.0 };
-    rgb[2] = SA { f: sample_nearest::<4, TF_RGB>, b: 10.0, s: 4.0 };
-    rgb[3] = SA { f: sample_nearest::<8, TF_RGB>, b: 10.0, s: 8.0 };
-    rgb[4] = SA { f: sample_nearest::<16, TF_RGB>, b: 10.0, s: 16.0 };
-    rgb[5] = SA { f: sample_nearest::<32, TF_RGB>, b: 10.0, s: 32.0 };
-    rgb[6] = SA { f: sample_nearest::<64, TF_RGB>, b: 10.0, s: 64.0 };
-    rgb[7] = SA { f: sample_nearest::<128, TF_RGB>, b: 10.0, s: 128.0 };
-    rgb[8] = SA { f: sample_nearest::<256, TF_RGB>, b: 10.0, s: 256.0 };
-    rgb[9] = SA { f: sample_nearest::<512, TF_RGB>, b: 10.0, s: 512.0 };
-    rgb[10] = SA { f: sample_nearest::<1024, TF_RGB>, b: 10.0, s: 1024.0 };
-    let rgba = &mut table[TextureFormat::RGBA as usize];
-    rgba[0] = SA { f: sample_nearest::<1, TF_RGBA>, b: 10.0, s: 1.0 };
-    rgba[1] = SA { f: sample_nearest::<2, TF_RGBA>, b: 10.0, s: 2.0 };
-    rgba[2] = SA { f: sample_nearest::<4, TF_RGBA>, b: 10.0, s: 4.0 };
-    rgba[3] = SA { f: sample_nearest::<8, TF_RGBA>, b: 10.0, s: 8.0 };
-    rgba[4] = SA { f: sample_nearest::<16, TF_RGBA>, b: 10.0, s: 16.0 };
-    rgba[5] = SA { f: sample_nearest::<32, TF_RGBA>, b: 10.0, s: 32.0 };
-    rgba[6] = SA { f: sample_nearest::<64, TF_RGBA>, b: 10.0, s: 64.0 };
-    rgba[7] = SA { f: sample_nearest::<128, TF_RGBA>, b: 10.0, s: 128.0 };
-    rgba[8] = SA { f: sample_nearest::<256, TF_RGBA>, b: 10.0, s: 256.0 };
-    rgba[9] = SA { f: sample_nearest::<512, TF_RGBA>, b: 10.0, s: 512.0 };
-    rgba[10] = SA { f: sample_nearest::<1024, TF_RGBA>, b: 10.0, s: 1024.0 };
-    table
-};
-
-static BILINEAR_SAMPLER_TABLE: [[SamplerEntry; MAX_LOG2_SIZE + 1]; FORMATS] = {
-    let mut table = [[SamplerEntry { f: noop_sample, b: 0.0, s: 1.0 }; MAX_LOG2_SIZE + 1]; FORMATS];
-    const TF_GRS: u8 = TextureFormat::Grayscale as u8;
-    const TF_RGB: u8 = TextureFormat::RGB as u8;
-    const TF_RGBA: u8 = TextureFormat::RGBA as u8;
-    type SA = SamplerEntry;
-    let grs = &mut table[TextureFormat::Grayscale as usize];
-    grs[0] = SA { f: sample_bilinear::<1, TF_GRS>, b: 10.0 - 127.0 / (1.0 * 256.0), s: 1.0 * 256.0 };
-    grs[1] = SA { f: sample_bilinear::<2, TF_GRS>, b: 10.0 - 127.0 / (2.0 * 256.0), s: 2.0 * 256.0 };
-    grs[2] = SA { f: sample_bilinear::<4, TF_GRS>, b: 10.0 - 127.0 / (4.0 * 256.0), s: 4.0 * 256.0 };
-    grs[3] = SA { f: sample_bilinear::<8, TF_GRS>, b: 10.0 - 127.0 / (8.0 * 256.0), s: 8.0 * 256.0 };
-    grs[4] = SA { f: sample_bilinear::<16, TF_GRS>, b: 10.0 - 127.0 / (16.0 * 256.0), s: 16.0 * 256.0 };
-    grs[5] = SA { f: sample_bilinear::<32, TF_GRS>, b: 10.0 - 127.0 / (32.0 * 256.0), s: 32.0 * 256.0 };
-    grs[6] = SA { f: sample_bilinear::<64, TF_GRS>, b: 10.0 - 127.0 / (64.0 * 256.0), s: 64.0 * 256.0 };
-    grs[7] = SA { f: sample_bilinear::<128, TF_GRS>, b: 10.0 - 127.0 / (128.0 * 256.0), s: 128.0 * 256.0 };
-    grs[8] = SA { f: sample_bilinear::<256, TF_GRS>, b: 10.0 - 127.0 / (256.0 * 256.0), s: 256.0 * 256.0 };
-    grs[9] = SA { f: sample_bilinear::<512, TF_GRS>, b: 10.0 - 127.0 / (512.0 * 256.0), s: 512.0 * 256.0 };
-    grs[10] = SA { f: sample_bilinear::<1024, TF_GRS>, b: 10.0 - 127.0 / (1024.0 * 256.0), s: 1024.0 * 256.0 };
-    let rgb = &mut table[TextureFormat::RGB as usize];
-    rgb[0] = SA { f: sample_bilinear::<1, TF_RGB>, b: 10.0 - 127.0 / (1.0 * 256.0), s: 1.0 * 256.0 };
-    rgb[1] = SA { f: sample_bilinear::<2, TF_RGB>, b: 10.0 - 127.0 / (2.0 * 256.0), s: 2.0 * 256.0 };
-    rgb[2] = SA { f: sample_bilinear::<4, TF_RGB>, b: 10.0 - 127.0 / (4.0 * 256.0), s: 4.0 * 256.0 };
-    rgb[3] = SA { f: sample_bilinear::<8, TF_RGB>, b: 10.0 - 127.0 / (8.0 * 256.0), s: 8.0 * 256.0 };
-    rgb[4] = SA { f: sample_bilinear::<16, TF_RGB>, b: 10.0 - 127.0 / (16.0 * 256.0), s: 16.0 * 256.0 };
-    rgb[5] = SA { f: sample_bilinear::<32, TF_RGB>, b: 10.0 - 127.0 / (32.0 * 256.0), s: 32.0 * 256.0 };
-    rgb[6] = SA { f: sample_bilinear::<64, TF_RGB>, b: 10.0 - 127.0 / (64.0 * 256.0), s: 64.0 * 256.0 };
-    rgb[7] = SA { f: sample_bilinear::<128, TF_RGB>, b: 10.0 - 127.0 / (128.0 * 256.0), s: 128.0 * 256.0 };
-    rgb[8] = SA { f: sample_bilinear::<256, TF_RGB>, b: 10.0 - 127.0 / (256.0 * 256.0), s: 256.0 * 256.0 };
-    rgb[9] = SA { f: sample_bilinear::<512, TF_RGB>, b: 10.0 - 127.0 / (512.0 * 256.0), s: 512.0 * 256.0 };
-    rgb[10] = SA { f: sample_bilinear::<1024, TF_RGB>, b: 10.0 - 127.0 / (1024.0 * 256.0), s: 1024.0 * 256.0 };
-    let rgba = &mut table[TextureFormat::RGBA as usize];
-    rgba[0] = SA { f: sample_bilinear::<1, TF_RGBA>, b: 10.0 - 127.0 / (1.0 * 256.0), s: 1.0 * 256.0 };
-    rgba[1] = SA { f: sample_bilinear::<2, TF_RGBA>, b: 10.0 - 127.0 / (2.0 * 256.0), s: 2.0 * 256.0 };
-    rgba[2] = SA { f: sample_bilinear::<4, TF_RGBA>, b: 10.0 - 127.0 / (4.0 * 256.0), s: 4.0 * 256.0 };
-    rgba[3] = SA { f: sample_bilinear::<8, TF_RGBA>, b: 10.0 - 127.0 / (8.0 * 256.0), s: 8.0 * 256.0 };
-    rgba[4] = SA { f: sample_bilinear::<16, TF_RGBA>, b: 10.0 - 127.0 / (16.0 * 256.0), s: 16.0 * 256.0 };
-    rgba[5] = SA { f: sample_bilinear::<32, TF_RGBA>, b: 10.0 - 127.0 / (32.0 * 256.0), s: 32.0 * 256.0 };
-    rgba[6] = SA { f: sample_bilinear::<64, TF_RGBA>, b: 10.0 - 127.0 / (64.0 * 256.0), s: 64.0 * 256.0 };
-    rgba[7] = SA { f: sample_bilinear::<128, TF_RGBA>, b: 10.0 - 127.0 / (128.0 * 256.0), s: 128.0 * 256.0 };
-    rgba[8] = SA { f: sample_bilinear::<256, TF_RGBA>, b: 10.0 - 127.0 / (256.0 * 256.0), s: 256.0 * 256.0 };
-    rgba[9] = SA { f: sample_bilinear::<512, TF_RGBA>, b: 10.0 - 127.0 / (512.0 * 256.0), s: 512.0 * 256.0 };
-    rgba[10] = SA { f: sample_bilinear::<1024, TF_RGBA>, b: 10.0 - 127.0 / (1024.0 * 256.0), s: 1024.0 * 256.0 };
-    table
-};
-
-static DEBUG_SAMPLER_TABLE: [[SamplerEntry; MAX_LOG2_SIZE + 1]; FORMATS] = {
-    let mut table = [[SamplerEntry { f: noop_sample, b: 0.0, s: 1.0 }; MAX_LOG2_SIZE + 1]; FORMATS];
-    type SA = SamplerEntry;
-    let grs = &mut table[TextureFormat::Grayscale as usize];
-    grs[0] = SA { f: mip_size_sample::<1>, b: 0.0, s: 1.0 };
-    grs[1] = SA { f: mip_size_sample::<2>, b: 0.0, s: 1.0 };
-    grs[2] = SA { f: mip_size_sample::<4>, b: 0.0, s: 1.0 };
-    grs[3] = SA { f: mip_size_sample::<8>, b: 0.0, s: 1.0 };
-    grs[4] = SA { f: mip_size_sample::<16>, b: 0.0, s: 1.0 };
-    grs[5] = SA { f: mip_size_sample::<32>, b: 0.0, s: 1.0 };
-    grs[6] = SA { f: mip_size_sample::<64>, b: 0.0, s: 1.0 };
-    grs[7] = SA { f: mip_size_sample::<128>, b: 0.0, s: 1.0 };
-    grs[8] = SA { f: mip_size_sample::<256>, b: 0.0, s: 1.0 };
-    grs[9] = SA { f: mip_size_sample::<512>, b: 0.0, s: 1.0 };
-    grs[10] = SA { f: mip_size_sample::<1024>, b: 0.0, s: 1.0 };
-    table[TextureFormat::RGB as usize] = table[TextureFormat::Grayscale as usize];
-    table
-};
-
-// Helper to repeat over fract levels
-macro_rules! for_each_fract {
-    ($mac:ident, $arr:ident[$idx:expr], $size:expr, $format:expr) => {
-        $mac!($arr[$idx], $size, $format, 0);
-        $mac!($arr[$idx], $size, $format, 1);
-        $mac!($arr[$idx], $size, $format, 2);
-        $mac!($arr[$idx], $size, $format, 3);
-        $mac!($arr[$idx], $size, $format, 4);
-        $mac!($arr[$idx], $size, $format, 5);
-        $mac!($arr[$idx], $size, $format, 6);
-        $mac!($arr[$idx], $size, $format, 7);
-        $mac!($arr[$idx], $size, $format, 8);
-        $mac!($arr[$idx], $size, $format, 9);
-        $mac!($arr[$idx], $size, $format, 10);
-        $mac!($arr[$idx], $size, $format, 11);
-        $mac!($arr[$idx], $size, $format, 12);
-        $mac!($arr[$idx], $size, $format, 13);
-        $mac!($arr[$idx], $size, $format, 14);
-        $mac!($arr[$idx], $size, $format, 15);
+type SamplerTable = [[SamplerEntry; MAX_LOG2_SIZE + 1]; FORMATS];
+
+type TrilinearSamplerTable = [[[SamplerEntry; TRILINEAR_FRACT_LEVELS as usize]; MAX_LOG2_SIZE + 1]; FORMATS];
+
+const fn nearest_entry<const SIZE: u16, const FORMAT: u8, const ADDRESS_U: u8, const ADDRESS_V: u8>() -> SamplerEntry {
+    let f: SampleFunction = if FORMAT == TextureFormat::BC3 as u8 {
+        sample_nearest_bc3::<SIZE, ADDRESS_U, ADDRESS_V>
+    } else {
+        sample_nearest::<SIZE, FORMAT, ADDRESS_U, ADDRESS_V>
     };
+    SamplerEntry { f, b: 10.0, s: SIZE as f32 }
 }
 
-// Specialization of fill_trilinear_entries for each FRACT value
-macro_rules! fill_trilinear_entry {
-    ($arr:ident[$idx:expr], $size:expr, $format:expr, $fract:expr) => {
-        $arr[$idx][$fract] = SA {
-            f: sample_trilinear::<$size, $format, $fract>,
-            b: 10.0 - 127.0 / ($size as f32 * 256.0),
-            s: $size as f32 * 256.0,
-        };
+const fn bilinear_entry<const SIZE: u16, const FORMAT: u8, const ADDRESS_U: u8, const ADDRESS_V: u8>() -> SamplerEntry {
+    let f: SampleFunction = if FORMAT == TextureFormat::BC3 as u8 {
+        sample_bilinear_bc3::<SIZE, ADDRESS_U, ADDRESS_V>
+    } else {
+        sample_bilinear::<SIZE, FORMAT, ADDRESS_U, ADDRESS_V>
     };
+    SamplerEntry { f, b: 10.0 - 127.0 / (SIZE as f32 * 256.0), s: SIZE as f32 * 256.0 }
+}
+
+const fn trilinear_entry<
+    const SIZE: u16,
+    const FORMAT: u8,
+    const ADDRESS_U: u8,
+    const ADDRESS_V: u8,
+    const FRACT: u32,
+>() -> SamplerEntry {
+    if SIZE == 1 {
+        // There's no next mip level to blend with
+        nearest_entry::<SIZE, FORMAT, ADDRESS_U, ADDRESS_V>()
+    } else {
+        SamplerEntry {
+            f: sample_trilinear::<SIZE, FORMAT, FRACT, ADDRESS_U, ADDRESS_V>,
+            b: 10.0 - 127.0 / (SIZE as f32 * 256.0),
+            s: SIZE as f32 * 256.0,
+        }
+    }
+}
+
+// There are no trilinear samplers for RGBA and BC3 textures, the sampler falls back to the bilinear ones for those.
+const fn trilinear_entries<const SIZE: u16, const FORMAT: u8, const ADDRESS_U: u8, const ADDRESS_V: u8>()
+-> [SamplerEntry; TRILINEAR_FRACT_LEVELS as usize] {
+    if FORMAT == TextureFormat::Grayscale as u8 || FORMAT == TextureFormat::RGB as u8 {
+        entries_per_fract!(trilinear_entry::<SIZE, FORMAT, ADDRESS_U, ADDRESS_V>)
+    } else {
+        [SamplerEntry { f: noop_sample, b: 0.0, s: 1.0 }; TRILINEAR_FRACT_LEVELS as usize]
+    }
 }
 
-static TRILINEAR_SAMPLER_TABLE: [[[SamplerEntry; TRILINEAR_FRACT_LEVELS as usize]; MAX_LOG2_SIZE + 1]; FORMATS] = {
-    let mut table = [[[SamplerEntry { f: noop_sample, b: 0.0, s: 1.0 }; TRILINEAR_FRACT_LEVELS as usize];
-        MAX_LOG2_SIZE + 1]; FORMATS];
-    const GRAYSCALE: u8 = TextureFormat::Grayscale as u8;
-    const RGB: u8 = TextureFormat::RGB as u8;
-    type SA = SamplerEntry;
-    let grs = &mut table[GRAYSCALE as usize];
-
-    // Sometimes Rust is really obnoxious: "cannot use `for` loop on `std::ops::Range<usize>` in statics"
-    let mut i: usize = 0;
-    while i < 16 {
-        grs[0][i] = SA { f: sample_nearest::<1, GRAYSCALE>, b: 10.0, s: 1.0 };
-        i += 1
-    }
-    for_each_fract!(fill_trilinear_entry, grs[1], 2, GRAYSCALE);
-    for_each_fract!(fill_trilinear_entry, grs[2], 4, GRAYSCALE);
-    for_each_fract!(fill_trilinear_entry, grs[3], 8, GRAYSCALE);
-    for_each_fract!(fill_trilinear_entry, grs[4], 16, GRAYSCALE);
-    for_each_fract!(fill_trilinear_entry, grs[5], 32, GRAYSCALE);
-    for_each_fract!(fill_trilinear_entry, grs[6], 64, GRAYSCALE);
-    for_each_fract!(fill_trilinear_entry, grs[7], 128, GRAYSCALE);
-    for_each_fract!(fill_trilinear_entry, grs[8], 256, GRAYSCALE);
-    for_each_fract!(fill_trilinear_entry, grs[9], 512, GRAYSCALE);
-    for_each_fract!(fill_trilinear_entry, grs[10], 1024, GRAYSCALE);
-
-    let rgb = &mut table[RGB as usize];
-    i = 0;
-    while i < 16 {
-        rgb[0][i] = SA { f: sample_nearest::<1, RGB>, b: 10.0, s: 1.0 };
-        i += 1
-    }
-    for_each_fract!(fill_trilinear_entry, rgb[1], 2, RGB);
-    for_each_fract!(fill_trilinear_entry, rgb[2], 4, RGB);
-    for_each_fract!(fill_trilinear_entry, rgb[3], 8, RGB);
-    for_each_fract!(fill_trilinear_entry, rgb[4], 16, RGB);
-    for_each_fract!(fill_trilinear_entry, rgb[5], 32, RGB);
-    for_each_fract!(fill_trilinear_entry, rgb[6], 64, RGB);
-    for_each_fract!(fill_trilinear_entry, rgb[7], 128, RGB);
-    for_each_fract!(fill_trilinear_entry, rgb[8], 256, RGB);
-    for_each_fract!(fill_trilinear_entry, rgb[9], 512, RGB);
-    for_each_fract!(fill_trilinear_entry, rgb[10], 1024, RGB);
-
-    table
-};
+const fn debug_entry<const SIZE: u16, const FORMAT: u8>() -> SamplerEntry {
+    SamplerEntry { f: mip_size_sample::<SIZE>, b: 0.0, s: 1.0 }
+}
+
+const fn nearest_sampler_table<const ADDRESS_U: u8, const ADDRESS_V: u8>() -> SamplerTable {
+    entries_per_format!(nearest_entry::<ADDRESS_U, ADDRESS_V>)
+}
+
+const fn bilinear_sampler_table<const ADDRESS_U: u8, const ADDRESS_V: u8>() -> SamplerTable {
+    entries_per_format!(bilinear_entry::<ADDRESS_U, ADDRESS_V>)
+}
+
+const fn trilinear_sampler_table<const ADDRESS_U: u8, const ADDRESS_V: u8>() -> TrilinearSamplerTable {
+    entries_per_format!(trilinear_entries::<ADDRESS_U, ADDRESS_V>)
+}
+
+static NEAREST_SAMPLER_TABLE: [[SamplerTable; ADDRESS_MODES]; ADDRESS_MODES] =
+    entries_per_address_modes!(nearest_sampler_table);
+
+static BILINEAR_SAMPLER_TABLE: [[SamplerTable; ADDRESS_MODES]; ADDRESS_MODES] =
+    entries_per_address_modes!(bilinear_sampler_table);
+
+static TRILINEAR_SAMPLER_TABLE: [[TrilinearSamplerTable; ADDRESS_MODES]; ADDRESS_MODES] =
+    entries_per_address_modes!(trilinear_sampler_table);
+
+static DEBUG_SAMPLER_TABLE: SamplerTable = entries_per_format!(debug_entry);
 
 #[cfg(test)]
 mod tests {
@@ -575,10 +849,47 @@ mod tests {
         }};
     }
 
+    #[test]
+    fn test_sampler_tables_are_complete() {
+        let is_noop = |entry: &SamplerEntry| std::ptr::fn_addr_eq(entry.f, noop_sample as SampleFunction);
+        for u in 0..ADDRESS_MODES {
+            for v in 0..ADDRESS_MODES {
+                for format in 0..FORMATS {
+                    for log2_size in 0..=MAX_LOG2_SIZE {
+                        let size: f32 = (1 << log2_size) as f32;
+                        let nearest: &SamplerEntry = &NEAREST_SAMPLER_TABLE[u][v][format][log2_size];
+                        let bilinear: &SamplerEntry = &BILINEAR_SAMPLER_TABLE[u][v][format][log2_size];
+                        assert!(!is_noop(nearest) && nearest.s == size);
+                        assert!(!is_noop(bilinear) && bilinear.s == size * 256.0);
+                        assert!(!is_noop(&DEBUG_SAMPLER_TABLE[format][log2_size]));
+                        let trilinear_supported: bool =
+                            format == TextureFormat::Grayscale as usize || format == TextureFormat::RGB as usize;
+                        // The same function can have different addresses in optimized builds, so the fallback
+                        // entries are told apart by their unit scale
+                        let trilinear_scale: f32 = match (trilinear_supported, log2_size) {
+                            (false, _) => 1.0,
+                            (true, 0) => size,
+                            (true, _) => size * 256.0,
+                        };
+                        for entry in &TRILINEAR_SAMPLER_TABLE[u][v][format][log2_size] {
+                            assert_eq!(entry.s, trilinear_scale);
+                            assert!(!trilinear_supported || !is_noop(entry));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_sample_nearest_from_1x1_grayscale_texture() {
-        let texture =
-            Texture::new(&TextureSource { texels: &[42u8], width: 1, height: 1, format: TextureFormat::Grayscale });
+        let texture = Texture::new(&TextureSource {
+            texels: &[42u8],
+            width: 1,
+            height: 1,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        });
         let sampler = Sampler::new(&texture, SamplerFilter::Nearest, 0.0);
         assert_eq!(sampler.sample(0.0, 0.0), RGBA::new(42, 42, 42, 255));
         assert_eq!(sampler.sample(1.0, 0.0), RGBA::new(42, 42, 42, 255));
@@ -595,6 +906,7 @@ mod tests {
             width: 2,
             height: 2,
             format: TextureFormat::Grayscale,
+            ..Default::default()
         });
         {
             let sampler = Sampler::new(&texture, SamplerFilter::Nearest, 0.0);
@@ -645,7 +957,13 @@ mod tests {
             0, 0, 255, // (0,1) blue
             255, 255, 255, // (1,1) white
         ];
-        let texture = Texture::new(&TextureSource { texels: &texels, width: 2, height: 2, format: TextureFormat::RGB });
+        let texture = Texture::new(&TextureSource {
+            texels: &texels,
+            width: 2,
+            height: 2,
+            format: TextureFormat::RGB,
+            ..Default::default()
+        });
         let sampler = Sampler::new(&texture, SamplerFilter::Nearest, 0.0);
         // Top-left (should be red)
         assert_eq!(sampler.sample(0.1, 0.1), RGBA::new(255, 0, 0, 255));
@@ -674,8 +992,13 @@ mod tests {
 
     #[test]
     fn test_sample_bilinear_from_1x1_grayscale_texture() {
-        let texture =
-            Texture::new(&TextureSource { texels: &[250u8], width: 1, height: 1, format: TextureFormat::Grayscale });
+        let texture = Texture::new(&TextureSource {
+            texels: &[250u8],
+            width: 1,
+            height: 1,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        });
         let sampler = Sampler::new(&texture, SamplerFilter::Bilinear, 0.0);
         assert_rgba_eq!(sampler.sample(0.0, 0.0), RGBA::new(250, 250, 250, 255), 1);
         assert_rgba_eq!(sampler.sample(1.0, 0.0), RGBA::new(250, 250, 250, 255), 1);
@@ -692,6 +1015,7 @@ mod tests {
             width: 2,
             height: 2,
             format: TextureFormat::Grayscale,
+            ..Default::default()
         });
         {
             let sampler = Sampler::new(&texture, SamplerFilter::Bilinear, 0.0);
@@ -729,6 +1053,7 @@ mod tests {
             width: 2,
             height: 2,
             format: TextureFormat::Grayscale,
+            ..Default::default()
         });
         {
             let sampler = Sampler::new(&texture, SamplerFilter::Bilinear, 0.0);
@@ -766,6 +1091,7 @@ mod tests {
             width: 1,
             height: 1,
             format: TextureFormat::RGB,
+            ..Default::default()
         });
         let sampler = Sampler::new(&texture, SamplerFilter::Bilinear, 0.0);
         assert_rgba_eq!(sampler.sample(0.0, 0.0), RGBA::new(250, 150, 50, 255), 1);
@@ -788,7 +1114,13 @@ mod tests {
             0, 0, 255, // (0,1) blue
             255, 255, 255, // (1,1) white
         ];
-        let texture = Texture::new(&TextureSource { texels: &texels, width: 2, height: 2, format: TextureFormat::RGB });
+        let texture = Texture::new(&TextureSource {
+            texels: &texels,
+            width: 2,
+            height: 2,
+            format: TextureFormat::RGB,
+            ..Default::default()
+        });
         let sampler = Sampler::new(&texture, SamplerFilter::Bilinear, 0.0);
         assert_rgba_eq!(sampler.sample(0.00, 0.00), RGBA::new(127, 127, 127, 255), 2);
         assert_rgba_eq!(sampler.sample(0.25, 0.00), RGBA::new(127, 0, 127, 255), 2);
@@ -931,4 +1263,324 @@ mod tests {
             assert_rgba_eq!(sampler.sample(1.000, 0.750), RGBA::new(126, 126, 126, 255), e);
         }
     }
+
+    fn atlas_2x2_cells_rgb_texture() -> Arc<Texture> {
+        // 4x4 texture with 2x2 cells of 2x2 texels each:
+        // [ red,  green ]
+        // [ blue, white ]
+        let colors: [[u8; 3]; 4] = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+        let mut texels: Vec<u8> = Vec::new();
+        for y in 0..4 {
+            for x in 0..4 {
+                texels.extend_from_slice(&colors[(y / 2) * 2 + x / 2]);
+            }
+        }
+        Texture::new(&TextureSource {
+            texels: &texels,
+            width: 4,
+            height: 4,
+            format: TextureFormat::RGB,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_sample_in_region_stays_within_the_cell() {
+        let texture = atlas_2x2_cells_rgb_texture();
+        let expected: [RGBA; 4] = [
+            RGBA::new(255, 0, 0, 255),
+            RGBA::new(0, 255, 0, 255),
+            RGBA::new(0, 0, 255, 255),
+            RGBA::new(255, 255, 255, 255),
+        ];
+        let coords: [f32; 9] = [-1.3, -0.5, 0.0, 0.01, 0.5, 0.99, 1.0, 1.5, 7.25];
+        for filter in [SamplerFilter::Nearest, SamplerFilter::Bilinear] {
+            for clamp in [false, true] {
+                for cell in 0..4 {
+                    let region = TextureRegion { clamp, ..TextureRegion::from_grid(2, 2, cell % 2, cell / 2) };
+                    let sampler = Sampler::new_in_region(&texture, filter, 0.0, &region);
+                    for u in coords {
+                        for v in coords {
+                            assert_rgba_eq!(sampler.sample_in_region(u, v), expected[cell as usize], 2);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sample_in_region_restricts_mip_selection() {
+        let texture = atlas_2x2_cells_rgb_texture();
+        let region = TextureRegion::from_grid(2, 2, 1, 0);
+        // Mip 2 is a single texel which mixes all cells, the region can be sampled only from mips 0 and 1.
+        let sampler = Sampler::new_in_region(&texture, SamplerFilter::Nearest, 2.0, &region);
+        assert_rgba_eq!(sampler.sample_in_region(0.5, 0.5), RGBA::new(0, 255, 0, 255), 2);
+        let sampler = Sampler::new_in_region(&texture, SamplerFilter::Trilinear, 5.0, &region);
+        assert_rgba_eq!(sampler.sample_in_region(0.5, 0.5), RGBA::new(0, 255, 0, 255), 2);
+    }
+
+    #[test]
+    fn test_sample_in_region_clamp_vs_repeat() {
+        // 4x4 texture, the region is the top-left 2x2 cell with a darker left column and a brighter right column.
+        let mut texels: Vec<u8> = vec![0u8; 16];
+        texels[0] = 10;
+        texels[1] = 20;
+        texels[4] = 10;
+        texels[5] = 20;
+        let texture = Texture::new(&TextureSource {
+            texels: &texels,
+            width: 4,
+            height: 4,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        });
+        let region = TextureRegion::from_grid(2, 2, 0, 0);
+        let repeat = Sampler::new_in_region(&texture, SamplerFilter::Nearest, 0.0, &region);
+        let clamp =
+            Sampler::new_in_region(&texture, SamplerFilter::Nearest, 0.0, &TextureRegion { clamp: true, ..region });
+        assert_eq!(repeat.sample_in_region(1.25, 0.5), RGBA::new(10, 10, 10, 255));
+        assert_eq!(clamp.sample_in_region(1.25, 0.5), RGBA::new(20, 20, 20, 255));
+        assert_eq!(repeat.sample_in_region(-0.25, 0.5), RGBA::new(20, 20, 20, 255));
+        assert_eq!(clamp.sample_in_region(-0.25, 0.5), RGBA::new(10, 10, 10, 255));
+    }
+
+    // 4x4 grayscale texture where texel(x, y) = 10 * (x + 1) + y
+    fn gradient_4x4_grayscale_texture() -> Arc<Texture> {
+        let mut texels: Vec<u8> = Vec::new();
+        for y in 0..4u8 {
+            for x in 0..4u8 {
+                texels.push(10 * (x + 1) + y);
+            }
+        }
+        Texture::new(&TextureSource {
+            texels: &texels,
+            width: 4,
+            height: 4,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        })
+    }
+
+    fn gray(c: u8) -> RGBA {
+        RGBA::new(c, c, c, 255)
+    }
+
+    #[test]
+    fn test_sample_nearest_with_address_modes() {
+        let texture = gradient_4x4_grayscale_texture();
+        let clamp_repeat = Sampler::new_with_address_modes(
+            &texture,
+            SamplerFilter::Nearest,
+            0.0,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::Repeat,
+        );
+        assert_eq!(clamp_repeat.sample(0.3, 0.1), gray(20));
+        assert_eq!(clamp_repeat.sample(-0.5, 0.1), gray(10));
+        assert_eq!(clamp_repeat.sample(-25.0, 0.1), gray(10));
+        assert_eq!(clamp_repeat.sample(1.7, 0.1), gray(40));
+        assert_eq!(clamp_repeat.sample(25.0, 0.1), gray(40));
+        assert_eq!(clamp_repeat.sample(-0.5, 1.3), gray(11));
+        assert_eq!(clamp_repeat.sample(1.7, -0.1), gray(43));
+
+        let mirror_clamp = Sampler::new_with_address_modes(
+            &texture,
+            SamplerFilter::Nearest,
+            0.0,
+            SamplerAddressMode::MirroredRepeat,
+            SamplerAddressMode::ClampToEdge,
+        );
+        assert_eq!(mirror_clamp.sample(0.1, 0.1), gray(10));
+        assert_eq!(mirror_clamp.sample(0.9, 0.1), gray(40));
+        assert_eq!(mirror_clamp.sample(1.1, 0.1), gray(40));
+        assert_eq!(mirror_clamp.sample(1.9, 0.1), gray(10));
+        assert_eq!(mirror_clamp.sample(2.1, 0.1), gray(10));
+        assert_eq!(mirror_clamp.sample(-0.1, 0.1), gray(10));
+        assert_eq!(mirror_clamp.sample(-0.3, 0.1), gray(20));
+        assert_eq!(mirror_clamp.sample(0.1, 1.5), gray(13));
+        assert_eq!(mirror_clamp.sample(0.1, -1.5), gray(10));
+    }
+
+    #[test]
+    fn test_sample_bilinear_with_address_modes() {
+        let texture = gradient_4x4_grayscale_texture();
+        let sampler = |u: SamplerAddressMode, v: SamplerAddressMode| {
+            Sampler::new_with_address_modes(&texture, SamplerFilter::Bilinear, 0.0, u, v)
+        };
+        let repeat = sampler(SamplerAddressMode::Repeat, SamplerAddressMode::Repeat);
+        let clamp = sampler(SamplerAddressMode::ClampToEdge, SamplerAddressMode::ClampToEdge);
+        let mirror = sampler(SamplerAddressMode::MirroredRepeat, SamplerAddressMode::MirroredRepeat);
+
+        // On the texture's left and right edges the filter footprint wraps, stays or reflects
+        assert_rgba_eq!(repeat.sample(0.0, 0.125), gray(25), 2);
+        assert_rgba_eq!(clamp.sample(0.0, 0.125), gray(10), 2);
+        assert_rgba_eq!(mirror.sample(0.0, 0.125), gray(10), 2);
+        assert_rgba_eq!(repeat.sample(1.0, 0.125), gray(25), 2);
+        assert_rgba_eq!(clamp.sample(1.0, 0.125), gray(40), 2);
+        assert_rgba_eq!(mirror.sample(1.0, 0.125), gray(40), 2);
+
+        // Inside the texture the modes are indistinguishable
+        assert_rgba_eq!(clamp.sample(0.5, 0.5), repeat.sample(0.5, 0.5), 0);
+        assert_rgba_eq!(mirror.sample(0.5, 0.5), repeat.sample(0.5, 0.5), 0);
+
+        // Far outside of the texture
+        assert_rgba_eq!(clamp.sample(-7.0, -7.0), gray(10), 2);
+        assert_rgba_eq!(clamp.sample(7.0, 7.0), gray(43), 2);
+        assert_rgba_eq!(mirror.sample(1.375, 0.125), gray(30), 2);
+    }
+
+    #[test]
+    fn test_sample_trilinear_with_address_modes() {
+        let texture = gradient_4x4_grayscale_texture();
+        let repeat = Sampler::new(&texture, SamplerFilter::Trilinear, 0.0);
+        let clamp = Sampler::new_with_address_modes(
+            &texture,
+            SamplerFilter::Trilinear,
+            0.0,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+        );
+        assert_rgba_eq!(repeat.sample(0.0, 0.125), gray(25), 3);
+        assert_rgba_eq!(clamp.sample(0.0, 0.125), gray(10), 3);
+        assert_rgba_eq!(clamp.sample(1.0, 0.125), gray(40), 3);
+        assert_rgba_eq!(clamp.sample(-3.0, 3.0), gray(13), 3);
+
+        // The coarser mip is addressed in the same way
+        let clamp_coarse = Sampler::new_with_address_modes(
+            &texture,
+            SamplerFilter::Trilinear,
+            0.5,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+        );
+        let inside: RGBA = clamp_coarse.sample(0.01, 0.01);
+        assert_rgba_eq!(clamp_coarse.sample(-3.0, -3.0), inside, 3);
+    }
+
+    // 16x16 grayscale texture with horizontal stripes of a single texel: white, black, white, ...
+    fn horizontal_stripes_16x16_grayscale_texture() -> Arc<Texture> {
+        let mut texels: Vec<u8> = Vec::new();
+        for y in 0..16 {
+            texels.extend_from_slice(&[if y % 2 == 0 { 255u8 } else { 0u8 }; 16]);
+        }
+        Texture::new(&TextureSource {
+            texels: &texels,
+            width: 16,
+            height: 16,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_anisotropic_taps_follow_the_footprint() {
+        let texture = horizontal_stripes_16x16_grayscale_texture();
+        let texel: f32 = 1.0 / 16.0;
+        let sampler = |uv_dx: Vec2, uv_dy: Vec2| {
+            Sampler::new_anisotropic(&texture, uv_dx, uv_dy, SamplerAddressMode::Repeat, SamplerAddressMode::Repeat)
+        };
+        assert_eq!(sampler(Vec2::new(texel, 0.0), Vec2::new(0.0, texel)).taps, 1);
+        assert_eq!(sampler(Vec2::new(2.0 * texel, 0.0), Vec2::new(0.0, texel)).taps, 2);
+        assert_eq!(sampler(Vec2::new(3.0 * texel, 0.0), Vec2::new(0.0, texel)).taps, 4);
+        assert_eq!(sampler(Vec2::new(0.0, 8.0 * texel), Vec2::new(texel, 0.0)).taps, 8);
+        assert_eq!(sampler(Vec2::new(64.0 * texel, 0.0), Vec2::new(0.0, texel)).taps, MAX_ANISOTROPY);
+    }
+
+    #[test]
+    fn test_anisotropic_keeps_the_minor_axis_sharp() {
+        let texture = horizontal_stripes_16x16_grayscale_texture();
+        let texel: f32 = 1.0 / 16.0;
+
+        // The footprint is 8 texels long along the stripes and a single texel across them.
+        let anisotropic = Sampler::new_anisotropic(
+            &texture,
+            Vec2::new(8.0 * texel, 0.0),
+            Vec2::new(0.0, texel),
+            SamplerAddressMode::Repeat,
+            SamplerAddressMode::Repeat,
+        );
+        let trilinear = Sampler::new(&texture, SamplerFilter::Trilinear, 1.5);
+        let sample = |sampler: &Sampler, u: f32, v: f32| {
+            let scale: SamplerUVScale = sampler.uv_scale();
+            sampler.sample_anisotropic_prescaled((u + scale.bias) * scale.scale, (v + scale.bias) * scale.scale)
+        };
+        for row in 0..16 {
+            let v: f32 = (row as f32 + 0.5) * texel;
+            let expected: RGBA = if row % 2 == 0 { gray(255) } else { gray(0) };
+            assert_rgba_eq!(sample(&anisotropic, 0.5, v), expected, 3);
+            assert_rgba_eq!(sample(&trilinear, 0.5, v), gray(127), 32);
+        }
+    }
+
+    #[test]
+    fn test_sample_compressed_texture() {
+        // 8x8 RGBA texture of 2x2 solid quadrants: red, green, blue, transparent
+        let colors: [[u8; 4]; 4] = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [0, 0, 0, 0]];
+        let mut texels: Vec<u8> = Vec::new();
+        for y in 0..8 {
+            for x in 0..8 {
+                texels.extend_from_slice(&colors[(y / 4) * 2 + x / 4]);
+            }
+        }
+        let source =
+            TextureSource { texels: &texels, width: 8, height: 8, format: TextureFormat::RGBA, ..Default::default() };
+        let plain = Texture::new(&source);
+        let compressed = Texture::new_with_options(&source, &TextureOptions { compress: true, ..Default::default() });
+        assert_eq!(compressed.format, TextureFormat::BC3);
+        for filter in [SamplerFilter::Nearest, SamplerFilter::Bilinear] {
+            // NB! At lod 1 the whole 4x4 mip is a single block with 4 distinct colors, which can't be represented exactly
+            for lod in [0.0, 3.0] {
+                let expected = Sampler::new(&plain, filter, lod);
+                let actual = Sampler::new(&compressed, filter, lod);
+                for i in 0..32 {
+                    let u: f32 = i as f32 / 32.0;
+                    let v: f32 = 1.0 - u * 0.7;
+                    assert_rgba_eq!(actual.sample(u, v), expected.sample(u, v), 4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sample_truncated_mip_chain() {
+        // 4x4 grayscale with only the base level, the trilinear sampler has to fall back to the bilinear one
+        let texels: Vec<u8> = vec![100u8; 16];
+        let source = TextureSource {
+            texels: &texels,
+            width: 4,
+            height: 4,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        };
+        let texture = Texture::new_with_options(
+            &source,
+            &TextureOptions { mip_generation: MipGeneration::None, ..Default::default() },
+        );
+        assert_eq!(texture.count, 1);
+        for filter in [SamplerFilter::Nearest, SamplerFilter::Bilinear, SamplerFilter::Trilinear] {
+            for lod in [0.0, 0.5, 1.5, 5.0] {
+                let sampler = Sampler::new(&texture, filter, lod);
+                assert_eq!(sampler.sample(0.3, 0.6), RGBA::new(100, 100, 100, 255));
+            }
+        }
+    }
+
+    #[test]
+    fn test_sample_trilinear_from_rgba_texture() {
+        // There are no trilinear samplers for RGBA, the sampler has to fall back to the bilinear one
+        let texels: Vec<u8> = [200u8, 100, 50, 255].repeat(16);
+        let texture = Texture::new(&TextureSource {
+            texels: &texels,
+            width: 4,
+            height: 4,
+            format: TextureFormat::RGBA,
+            ..Default::default()
+        });
+        assert_eq!(texture.count, 3);
+        for lod in [0.0, 0.5, 1.5, 5.0] {
+            let sampler = Sampler::new(&texture, SamplerFilter::Trilinear, lod);
+            assert_rgba_eq!(sampler.sample(0.3, 0.6), RGBA::new(200, 100, 50, 255), 1);
+        }
+    }
 }
@@ -0,0 +1,314 @@
+use super::super::math::*;
+use super::world::Slots;
+use super::*;
+use std::sync::Arc;
+
+// A hierarchy of nodes, each placed relative to its parent, so that moving a node moves all of its descendants, e.g. the
+// wheels of a car or the items held by a character. The nodes can carry a mesh with its materials, the others serve as
+// groups and pivots. render() propagates the transforms down the hierarchy, culls the meshes outside the camera's
+// frustum and commits the rest in the depth-first order, the children after their parents in the order of adding.
+// Unlike RenderWorld, the blended sections aren't sorted by distance and no lighting is applied.
+// The nodes are referred to by handles, which stay valid until the node is removed and can be reused afterwards.
+pub struct SceneGraph {
+    nodes: Slots<Slot>,
+    roots: Vec<NodeId>,
+    statistics: SceneGraphStatistics,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(u32);
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    // Optional name to look the node up by, see SceneGraph::find().
+    pub name: String,
+
+    // The transform relative to the parent, or to the world if the node has no parent.
+    // Default: identity.
+    pub local: Mat34,
+
+    // Default: None, i.e. the node isn't drawn itself.
+    pub mesh: Option<Arc<MeshData>>,
+
+    // The materials of the mesh's sections, indexed by MeshDataSection::material_index, the default material is used for
+    // the missing ones.
+    // Default: empty.
+    pub materials: Vec<Material>,
+
+    // Hides the node along with all of its descendants.
+    // Default: true.
+    pub visible: bool,
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self { name: String::new(), local: Mat34::identity(), mesh: None, materials: Vec::new(), visible: true }
+    }
+}
+
+// Counters of the last render() call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SceneGraphStatistics {
+    // The nodes with meshes committed into the rasterizer.
+    pub visible_nodes: usize,
+
+    // The nodes with meshes skipped as being outside the camera's frustum.
+    pub culled_nodes: usize,
+
+    // The commands committed into the rasterizer, one per mesh section.
+    pub committed_commands: usize,
+}
+
+struct Slot {
+    node: Node,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+
+    // The node-to-world transform as of the last update_transforms()
+    world: Mat34,
+}
+
+impl Default for SceneGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SceneGraph {
+    pub fn new() -> Self {
+        Self { nodes: Slots::new(), roots: Vec::new(), statistics: SceneGraphStatistics::default() }
+    }
+
+    // Adds the node as the last child of the parent, or as a root if there's no parent.
+    // Panics if the parent doesn't exist.
+    pub fn add_node(&mut self, parent: Option<NodeId>, node: Node) -> NodeId {
+        let world: Mat34 = match parent {
+            Some(parent) => self.slot(parent).world * node.local,
+            None => node.local,
+        };
+        let id = NodeId(self.nodes.insert(Slot { node, parent, children: Vec::new(), world }));
+        self.siblings_mut(parent).push(id);
+        id
+    }
+
+    // Removes the node along with all of its descendants and returns it.
+    pub fn remove_node(&mut self, id: NodeId) -> Option<Node> {
+        let parent: Option<NodeId> = self.nodes.get(id.0)?.parent;
+        self.siblings_mut(parent).retain(|&sibling| sibling != id);
+        let mut removed: Option<Node> = None;
+        let mut stack: Vec<NodeId> = vec![id];
+        while let Some(next) = stack.pop() {
+            let slot: Slot = self.nodes.remove(next.0).unwrap();
+            stack.extend_from_slice(&slot.children);
+            if next == id {
+                removed = Some(slot.node);
+            }
+        }
+        removed
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.get(id.0).map(|slot| &slot.node)
+    }
+
+    // Changes to the local transforms take effect in the world transforms after update_transforms() or render().
+    pub fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.nodes.get_mut(id.0).map(|slot| &mut slot.node)
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes.get(id.0)?.parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        self.nodes.get(id.0).map_or(&[], |slot| &slot.children)
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    // The first node with the given name in the depth-first order, if any.
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.depth_first().find(|&id| self.slot(id).node.name == name)
+    }
+
+    // Moves the node with its descendants under another parent, or makes it a root. The local transform is kept, so
+    // the node follows the new parent.
+    // Returns false and keeps the hierarchy if either node doesn't exist or the parent is the node's descendant.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> bool {
+        if self.nodes.get(id.0).is_none() {
+            return false;
+        }
+        if let Some(parent) = parent {
+            // Walking up from the new parent mustn't reach the node
+            let mut ancestor: Option<NodeId> = Some(parent);
+            while let Some(next) = ancestor {
+                let Some(slot) = self.nodes.get(next.0) else {
+                    return false;
+                };
+                if next == id {
+                    return false;
+                }
+                ancestor = slot.parent;
+            }
+        }
+        let old_parent: Option<NodeId> = self.slot(id).parent;
+        self.siblings_mut(old_parent).retain(|&sibling| sibling != id);
+        self.siblings_mut(parent).push(id);
+        self.slot_mut(id).parent = parent;
+        true
+    }
+
+    // The node-to-world transform as of the last update_transforms() or render().
+    pub fn world_transform(&self, id: NodeId) -> Option<Mat34> {
+        self.nodes.get(id.0).map(|slot| slot.world)
+    }
+
+    // Recalculates the world transforms of all the nodes from their local transforms.
+    pub fn update_transforms(&mut self) {
+        let mut stack: Vec<(NodeId, Mat34)> = self.roots.iter().rev().map(|&id| (id, Mat34::identity())).collect();
+        while let Some((id, parent_world)) = stack.pop() {
+            let slot: &mut Slot = self.slot_mut(id);
+            slot.world = parent_world * slot.node.local;
+            let world: Mat34 = slot.world;
+            stack.extend(slot.children.iter().rev().map(|&child| (child, world)));
+        }
+    }
+
+    // Updates the transforms and commits the visible meshes inside the camera's frustum into the rasterizer.
+    // The rasterizer has to be set up beforehand and drawn afterwards, so that the graph can be drawn along with other
+    // geometry.
+    pub fn render(&mut self, rasterizer: &mut Rasterizer, camera: &Camera) {
+        self.update_transforms();
+        let mut statistics = SceneGraphStatistics::default();
+        let (view, projection): (Mat44, Mat44) = (camera.view(), camera.projection_matrix());
+        let view_projection: Mat44 = projection * view;
+        let default_material: Material = Material::default();
+        let mut stack: Vec<NodeId> = self.roots.iter().rev().copied().collect();
+        while let Some(id) = stack.pop() {
+            let slot: &Slot = self.slot(id);
+            if !slot.node.visible {
+                continue;
+            }
+            stack.extend(slot.children.iter().rev());
+            let Some(mesh) = slot.node.mesh.as_ref() else {
+                continue;
+            };
+            let has_bounds: bool = mesh.aabb.min != mesh.aabb.max;
+            if has_bounds && is_outside_frustum(&mesh.aabb, &(view_projection * slot.world.as_mat44()), false) {
+                statistics.culled_nodes += 1;
+                continue;
+            }
+            statistics.visible_nodes += 1;
+            let mut commit = |command: RasterizationCommand, material_index: usize| {
+                let material: &Material = slot.node.materials.get(material_index).unwrap_or(&default_material);
+                rasterizer.commit(&material.apply(RasterizationCommand {
+                    model: slot.world,
+                    view,
+                    projection,
+                    ..command
+                }));
+                statistics.committed_commands += 1;
+            };
+            if mesh.sections.is_empty() {
+                commit(mesh.command(), 0);
+            } else {
+                for section in &mesh.sections {
+                    commit(mesh.section_command(section), section.material_index);
+                }
+            }
+        }
+        self.statistics = statistics;
+    }
+
+    pub fn statistics(&self) -> SceneGraphStatistics {
+        self.statistics
+    }
+
+    fn depth_first(&self) -> impl Iterator<Item = NodeId> + '_ {
+        let mut stack: Vec<NodeId> = self.roots.iter().rev().copied().collect();
+        std::iter::from_fn(move || {
+            let id: NodeId = stack.pop()?;
+            stack.extend(self.slot(id).children.iter().rev());
+            Some(id)
+        })
+    }
+
+    fn slot(&self, id: NodeId) -> &Slot {
+        self.nodes.get(id.0).expect("the node doesn't exist")
+    }
+
+    fn slot_mut(&mut self, id: NodeId) -> &mut Slot {
+        self.nodes.get_mut(id.0).expect("the node doesn't exist")
+    }
+
+    // The children of the parent, or the roots.
+    fn siblings_mut(&mut self, parent: Option<NodeId>) -> &mut Vec<NodeId> {
+        match parent {
+            Some(parent) => &mut self.slot_mut(parent).children,
+            None => &mut self.roots,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translation(transform: Mat34) -> Vec3 {
+        transform * Vec3::new(0.0, 0.0, 0.0)
+    }
+
+    fn moved(x: f32, y: f32, z: f32) -> Node {
+        Node { local: Mat34::translate(Vec3::new(x, y, z)), ..Default::default() }
+    }
+
+    #[test]
+    fn transforms_are_propagated() {
+        let mut graph = SceneGraph::new();
+        let root: NodeId = graph.add_node(None, moved(1.0, 0.0, 0.0));
+        let child: NodeId = graph.add_node(Some(root), moved(0.0, 2.0, 0.0));
+        let grandchild: NodeId = graph.add_node(Some(child), moved(0.0, 0.0, 3.0));
+        assert_eq!(translation(graph.world_transform(grandchild).unwrap()), Vec3::new(1.0, 2.0, 3.0));
+
+        // Rotating the root by 90° around Z turns the child's offset along Y into -X
+        graph.node_mut(root).unwrap().local = Mat34::rotate_xy(std::f32::consts::FRAC_PI_2);
+        graph.update_transforms();
+        let position: Vec3 = translation(graph.world_transform(grandchild).unwrap());
+        assert!((position - Vec3::new(-2.0, 0.0, 3.0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn reparenting_rejects_cycles() {
+        let mut graph = SceneGraph::new();
+        let a: NodeId = graph.add_node(None, moved(1.0, 0.0, 0.0));
+        let b: NodeId = graph.add_node(Some(a), moved(1.0, 0.0, 0.0));
+        let c: NodeId = graph.add_node(None, moved(0.0, 5.0, 0.0));
+        assert!(!graph.set_parent(a, Some(b)));
+        assert!(!graph.set_parent(a, Some(a)));
+        assert!(graph.set_parent(b, Some(c)));
+        assert_eq!(graph.parent(b), Some(c));
+        assert!(graph.children(a).is_empty());
+        assert_eq!(graph.children(c), &[b]);
+        graph.update_transforms();
+        assert_eq!(translation(graph.world_transform(b).unwrap()), Vec3::new(1.0, 5.0, 0.0));
+        assert!(graph.set_parent(c, None));
+        assert_eq!(graph.roots(), &[a, c]);
+    }
+
+    #[test]
+    fn removing_node_removes_subtree() {
+        let mut graph = SceneGraph::new();
+        let root: NodeId = graph.add_node(None, Node { name: "root".to_string(), ..Default::default() });
+        let child: NodeId = graph.add_node(Some(root), Node { name: "child".to_string(), ..Default::default() });
+        let leaf: NodeId = graph.add_node(Some(child), Node { name: "leaf".to_string(), ..Default::default() });
+        let sibling: NodeId = graph.add_node(Some(root), Node { name: "leaf".to_string(), ..Default::default() });
+        assert_eq!(graph.find("leaf"), Some(leaf));
+        assert_eq!(graph.remove_node(child).unwrap().name, "child");
+        assert!(graph.node(leaf).is_none());
+        assert_eq!(graph.children(root), &[sibling]);
+        assert_eq!(graph.find("leaf"), Some(sibling));
+        assert!(graph.remove_node(child).is_none());
+    }
+}
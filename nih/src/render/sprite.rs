@@ -0,0 +1,265 @@
+use super::super::math::*;
+use super::*;
+use std::sync::Arc;
+
+// A textured, tinted and rotated 2D quad in pixels relative to the viewport's top-left corner, see SpriteBatch.
+#[derive(Debug, Clone)]
+pub struct Sprite {
+    // Where the sprite's pivot is placed.
+    pub position: Vec2,
+
+    // The width and the height of the quad before the rotation.
+    pub size: Vec2,
+
+    // The point the sprite is placed by and rotated around, relative to the quad: (0, 0) is the top-left corner, (1, 1)
+    // is the bottom-right one.
+    // Default: (0.5, 0.5), i.e. the center.
+    pub pivot: Vec2,
+
+    // The rotation around the pivot in radians, clockwise on the screen.
+    // Default: 0.
+    pub rotation: f32,
+
+    // Without a texture the quad is filled with the color.
+    // Default: None.
+    pub texture: Option<Arc<Texture>>,
+
+    // The part of the texture shown on the quad, e.g. a frame of a sprite sheet. Swapping u0 and u1 or v0 and v1 flips
+    // the image. Unlike RasterizationCommand::texture_region, it's not clamped, so the neighbouring cells of an atlas
+    // may bleed in with the bilinear filtering.
+    // Default: None, i.e. the entire texture.
+    pub region: Option<TextureRegion>,
+
+    // The color multiplied by the texels, not premultiplied by alpha.
+    // Default: (1, 1, 1, 1).
+    pub color: Vec4,
+}
+
+impl Default for Sprite {
+    fn default() -> Self {
+        Self {
+            position: Vec2::new(0.0, 0.0),
+            size: Vec2::new(0.0, 0.0),
+            pivot: Vec2::new(0.5, 0.5),
+            rotation: 0.0,
+            texture: None,
+            region: None,
+            color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Sprite {
+    // The number of vertices appended by append_geometry(): two triangles.
+    pub const VERTICES_NUM: usize = 6;
+
+    // Appends the two triangles of the quad, with positions in pixels and texture coordinates over the region.
+    pub fn append_geometry(&self, positions: &mut Vec<Vec3>, tex_coords: &mut Vec<Vec2>) {
+        let (sin, cos): (f32, f32) = self.rotation.sin_cos();
+        let corner = |x: f32, y: f32| -> Vec3 {
+            let local = Vec2::new((x - self.pivot.x) * self.size.x, (y - self.pivot.y) * self.size.y);
+            Vec3::new(
+                self.position.x + local.x * cos - local.y * sin,
+                self.position.y + local.x * sin + local.y * cos,
+                0.0,
+            )
+        };
+        let (u0, v0, u1, v1): (f32, f32, f32, f32) = match self.region.as_ref() {
+            Some(region) => (region.u0, region.v0, region.u1, region.v1),
+            None => (0.0, 0.0, 1.0, 1.0),
+        };
+        let (p00, p10, p11, p01) = (corner(0.0, 0.0), corner(1.0, 0.0), corner(1.0, 1.0), corner(0.0, 1.0));
+        let (t00, t10, t11, t01) = (Vec2::new(u0, v0), Vec2::new(u1, v0), Vec2::new(u1, v1), Vec2::new(u0, v1));
+        positions.extend_from_slice(&[p00, p10, p11, p00, p11, p01]);
+        tex_coords.extend_from_slice(&[t00, t10, t11, t00, t11, t01]);
+    }
+}
+
+// The sprites of a SpriteBatch sharing the same texture.
+#[derive(Debug, Clone, Default)]
+struct SpriteBatchEntry {
+    texture: Option<Arc<Texture>>,
+    positions: Vec<Vec3>,
+    tex_coords: Vec<Vec2>,
+    colors: Vec<Vec4>,
+}
+
+// Accumulates sprites, e.g. of a HUD or of a 2D game, and commits them as a single RasterizationCommand per texture.
+// The sprites sharing a texture are drawn in the order they were added, while the textures are drawn in the order of
+// their first sprites, so overlapping sprites with different textures should be put into separate batches if their
+// order matters. The batch keeps its memory between the frames.
+#[derive(Debug, Clone)]
+pub struct SpriteBatch {
+    entries: Vec<SpriteBatchEntry>,
+    used_entries: usize,
+
+    // Set the filter to be used when sampling the textures.
+    // Default: nearest.
+    pub sampling_filter: SamplerFilter,
+
+    // Default: Normal.
+    pub alpha_blending: AlphaBlendingMode,
+
+    // See RasterizationCommand::alpha_test.
+    // Default: 0, i.e. disabled.
+    pub alpha_test: u8,
+}
+
+impl Default for SpriteBatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpriteBatch {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            used_entries: 0,
+            sampling_filter: SamplerFilter::Nearest,
+            alpha_blending: AlphaBlendingMode::Normal,
+            alpha_test: 0,
+        }
+    }
+
+    pub fn add(&mut self, sprite: &Sprite) {
+        let same_texture = |entry: &SpriteBatchEntry| match (&entry.texture, &sprite.texture) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        let index: usize = match self.entries[..self.used_entries].iter().position(same_texture) {
+            Some(index) => index,
+            None => {
+                if self.used_entries == self.entries.len() {
+                    self.entries.push(SpriteBatchEntry::default());
+                }
+                self.entries[self.used_entries].texture = sprite.texture.clone();
+                self.used_entries += 1;
+                self.used_entries - 1
+            }
+        };
+        let entry: &mut SpriteBatchEntry = &mut self.entries[index];
+        sprite.append_geometry(&mut entry.positions, &mut entry.tex_coords);
+        entry.colors.extend_from_slice(&[sprite.color; Sprite::VERTICES_NUM]);
+    }
+
+    // The number of the sprites added since the last commit or clear.
+    pub fn len(&self) -> usize {
+        self.entries[..self.used_entries]
+            .iter()
+            .map(|entry| entry.positions.len())
+            .sum::<usize>()
+            / Sprite::VERTICES_NUM
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.used_entries == 0
+    }
+
+    pub fn clear(&mut self) {
+        for entry in &mut self.entries[..self.used_entries] {
+            entry.texture = None;
+            entry.positions.clear();
+            entry.tex_coords.clear();
+            entry.colors.clear();
+        }
+        self.used_entries = 0;
+    }
+
+    // Commits the sprites in the pixels of the rasterizer's viewport and clears the batch.
+    pub fn commit(&mut self, rasterizer: &mut Rasterizer) {
+        let viewport: Viewport = rasterizer.viewport();
+        let width: f32 = (viewport.xmax - viewport.xmin) as f32;
+        let height: f32 = (viewport.ymax - viewport.ymin) as f32;
+        let projection: Mat44 = Mat44::orthographic(0.0, width, height, 0.0, -1.0, 1.0);
+        for entry in &self.entries[..self.used_entries] {
+            rasterizer.commit(&RasterizationCommand {
+                world_positions: &entry.positions,
+                tex_coords: &entry.tex_coords,
+                colors: &entry.colors,
+                projection,
+                culling: CullMode::None,
+                texture: entry.texture.clone(),
+                sampling_filter: self.sampling_filter,
+                address_mode_u: SamplerAddressMode::ClampToEdge,
+                address_mode_v: SamplerAddressMode::ClampToEdge,
+                alpha_blending: self.alpha_blending,
+                alpha_test: self.alpha_test,
+                ..Default::default()
+            });
+        }
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texture() -> Arc<Texture> {
+        Texture::new(&TextureSource {
+            texels: &[255u8; 4 * 4],
+            width: 4,
+            height: 4,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn rotated_around_pivot() {
+        let sprite = Sprite {
+            position: Vec2::new(10.0, 20.0),
+            size: Vec2::new(4.0, 2.0),
+            pivot: Vec2::new(0.0, 0.0),
+            rotation: std::f32::consts::FRAC_PI_2,
+            ..Default::default()
+        };
+        let mut positions: Vec<Vec3> = Vec::new();
+        let mut tex_coords: Vec<Vec2> = Vec::new();
+        sprite.append_geometry(&mut positions, &mut tex_coords);
+        assert_eq!(positions.len(), Sprite::VERTICES_NUM);
+        // The top edge points down the screen after a quarter turn clockwise
+        assert!((positions[0] - Vec3::new(10.0, 20.0, 0.0)).length() < 1e-5);
+        assert!((positions[1] - Vec3::new(10.0, 24.0, 0.0)).length() < 1e-5);
+        assert!((positions[2] - Vec3::new(8.0, 24.0, 0.0)).length() < 1e-5);
+        assert_eq!(tex_coords[2], Vec2::new(1.0, 1.0));
+    }
+
+    #[test]
+    fn region_maps_onto_corners() {
+        let sprite = Sprite {
+            size: Vec2::new(2.0, 2.0),
+            region: Some(TextureRegion { u0: 0.5, v0: 0.25, u1: 0.25, v1: 0.5, clamp: false }),
+            ..Default::default()
+        };
+        let mut positions: Vec<Vec3> = Vec::new();
+        let mut tex_coords: Vec<Vec2> = Vec::new();
+        sprite.append_geometry(&mut positions, &mut tex_coords);
+        assert_eq!(positions[0], Vec3::new(-1.0, -1.0, 0.0));
+        assert_eq!(tex_coords[0], Vec2::new(0.5, 0.25));
+        assert_eq!(tex_coords[2], Vec2::new(0.25, 0.5));
+    }
+
+    #[test]
+    fn sprites_are_grouped_by_texture() {
+        let (a, b) = (texture(), texture());
+        let mut batch = SpriteBatch::new();
+        batch.add(&Sprite { texture: Some(a.clone()), ..Default::default() });
+        batch.add(&Sprite { texture: Some(b.clone()), ..Default::default() });
+        batch.add(&Sprite { texture: Some(a.clone()), ..Default::default() });
+        batch.add(&Sprite::default());
+        assert_eq!(batch.len(), 4);
+        assert_eq!(batch.used_entries, 3);
+        assert!(Arc::ptr_eq(batch.entries[0].texture.as_ref().unwrap(), &a));
+        assert_eq!(batch.entries[0].positions.len(), 2 * Sprite::VERTICES_NUM);
+        assert_eq!(batch.entries[0].colors.len(), 2 * Sprite::VERTICES_NUM);
+
+        batch.clear();
+        assert!(batch.is_empty());
+        assert_eq!(batch.len(), 0);
+        batch.add(&Sprite { texture: Some(b), ..Default::default() });
+        assert_eq!((batch.len(), batch.used_entries), (1, 1));
+    }
+}
@@ -0,0 +1,422 @@
+use super::super::math::*;
+use super::*;
+
+// A 2D path made of polylines, with the curves flattened into line segments as they're added.
+// All positions are in pixels relative to the viewport's top-left corner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Path {
+    // The maximum distance between a curve and its flattened approximation, in pixels.
+    // Default: 0.25.
+    pub tolerance: f32,
+
+    subpaths: Vec<Subpath>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Subpath {
+    points: Vec<Vec2>,
+    closed: bool,
+}
+
+impl Default for Path {
+    fn default() -> Self {
+        Self { tolerance: 0.25, subpaths: Vec::new() }
+    }
+}
+
+impl Path {
+    // The upper limit of line segments a single curve is flattened into.
+    pub const MAX_CURVE_SEGMENTS: usize = 64;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A path of a single polyline going through the points.
+    pub fn from_polyline(points: &[Vec2], closed: bool) -> Self {
+        let mut path = Self::new();
+        if let Some((first, rest)) = points.split_first() {
+            path.move_to(*first);
+            for p in rest {
+                path.line_to(*p);
+            }
+            if closed {
+                path.close();
+            }
+        }
+        path
+    }
+
+    // Starts a new subpath at the point.
+    pub fn move_to(&mut self, p: Vec2) {
+        self.subpaths.push(Subpath { points: vec![p], closed: false });
+    }
+
+    pub fn line_to(&mut self, p: Vec2) {
+        self.current().points.push(p);
+    }
+
+    // A quadratic Bezier curve from the current point to `p` with the control point `c`.
+    pub fn quad_to(&mut self, c: Vec2, p: Vec2) {
+        let p0: Vec2 = self.current_point();
+        let deviation: f32 = (p0 - c * 2.0 + p).length();
+        let segments: usize = self.curve_segments(deviation * 0.25);
+        for i in 1..=segments {
+            let t: f32 = i as f32 / segments as f32;
+            let it: f32 = 1.0 - t;
+            self.line_to(p0 * (it * it) + c * (2.0 * it * t) + p * (t * t));
+        }
+    }
+
+    // A cubic Bezier curve from the current point to `p` with the control points `c0` and `c1`.
+    pub fn cubic_to(&mut self, c0: Vec2, c1: Vec2, p: Vec2) {
+        let p0: Vec2 = self.current_point();
+        let deviation: f32 = (p0 - c0 * 2.0 + c1).length().max((c0 - c1 * 2.0 + p).length());
+        let segments: usize = self.curve_segments(deviation * 0.75);
+        for i in 1..=segments {
+            let t: f32 = i as f32 / segments as f32;
+            let it: f32 = 1.0 - t;
+            self.line_to(p0 * (it * it * it) + c0 * (3.0 * it * it * t) + c1 * (3.0 * it * t * t) + p * (t * t * t));
+        }
+    }
+
+    // Closes the current subpath with a segment back to its first point.
+    pub fn close(&mut self) {
+        self.current().closed = true;
+    }
+
+    // The flattened polylines and whether each of them is closed.
+    pub fn polylines(&self) -> impl Iterator<Item = (&[Vec2], bool)> {
+        self.subpaths.iter().map(|s| (s.points.as_slice(), s.closed))
+    }
+
+    fn current(&mut self) -> &mut Subpath {
+        if self.subpaths.is_empty() {
+            self.move_to(Vec2::new(0.0, 0.0));
+        }
+        self.subpaths.last_mut().unwrap()
+    }
+
+    fn current_point(&mut self) -> Vec2 {
+        *self.current().points.last().unwrap()
+    }
+
+    // The number of segments keeping a curve with the second derivative bounded by `deviation` within the tolerance.
+    fn curve_segments(&self, deviation: f32) -> usize {
+        let segments: f32 = (deviation / self.tolerance.max(0.001)).sqrt().ceil();
+        (segments as usize).clamp(1, Self::MAX_CURVE_SEGMENTS)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineJoin {
+    // The outer corner is rounded with the radius of half the stroke width.
+    Round,
+
+    // The outer edges are extended until they meet, unless the miter is longer than `limit` stroke widths,
+    // in which case the corner is beveled.
+    Miter { limit: f32 },
+
+    // The outer corner is cut off straight.
+    Bevel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineCap {
+    // The stroke ends exactly at the end points.
+    Butt,
+
+    // The stroke ends with a half-circle around the end points.
+    Round,
+
+    // The stroke is extended beyond the end points by half the width.
+    Square,
+}
+
+// A screen-space stroke of a path, e.g. a plot, an annotation or a vector HUD element.
+// Overlapping parts of the stroke are covered once, so translucent strokes don't get darker at the joins.
+// Bypasses the geometry processing, the depth and normal buffers are not affected.
+#[derive(Debug, Clone, Copy)]
+pub struct StrokeCommand<'a> {
+    pub path: &'a Path,
+
+    // The stroke width in pixels.
+    // Default: 1.0.
+    pub width: f32,
+
+    // Default: Round.
+    pub join: LineJoin,
+
+    // Default: Butt.
+    pub cap: LineCap,
+
+    // The stroke color, not premultiplied by alpha.
+    // Default: (1, 1, 1, 1).
+    pub color: Vec4,
+
+    // Sets whether the edges should be anti-aliased, the same way as PolygonFillCommand does.
+    // Default: true.
+    pub antialiased: bool,
+
+    // Sets whether the stroke should be alpha-blended with the framebuffer, the same way as RasterizationCommand does.
+    // Default: None.
+    pub alpha_blending: AlphaBlendingMode,
+}
+
+impl Default for StrokeCommand<'_> {
+    fn default() -> Self {
+        static EMPTY_PATH: Path = Path { tolerance: 0.25, subpaths: Vec::new() };
+        Self {
+            path: &EMPTY_PATH,
+            width: 1.0,
+            join: LineJoin::Round,
+            cap: LineCap::Butt,
+            color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            antialiased: true,
+            alpha_blending: AlphaBlendingMode::None,
+        }
+    }
+}
+
+// A convex piece of a tessellated stroke, the stroke covers the union of its shapes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StrokeShape {
+    // A convex polygon defined by a range of edges, as produced by PolygonFillCommand::append_edges().
+    Polygon {
+        edges_start: u32,
+        edges_count: u32,
+        min: Vec2,
+        max: Vec2,
+    },
+
+    // A disc, used for the round joins and caps.
+    Disc {
+        center: Vec2,
+        radius: f32,
+    },
+}
+
+impl StrokeShape {
+    // Signed distance from the point to the shape: positive inside, negative outside.
+    #[inline(always)]
+    pub fn distance(&self, edges: &[Vec3], p: Vec2) -> f32 {
+        match *self {
+            StrokeShape::Polygon { edges_start, edges_count, .. } => {
+                polygon_distance(&edges[edges_start as usize..(edges_start + edges_count) as usize], p)
+            }
+            StrokeShape::Disc { center, radius } => radius - (p - center).length(),
+        }
+    }
+
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        match *self {
+            StrokeShape::Polygon { min, max, .. } => (min, max),
+            StrokeShape::Disc { center, radius } => {
+                (center - Vec2::new(radius, radius), center + Vec2::new(radius, radius))
+            }
+        }
+    }
+}
+
+impl StrokeCommand<'_> {
+    // Tessellates the stroke into convex shapes: a quad per segment plus the joins and the caps.
+    // The polygons' edges are appended to `edges`. Returns the number of shapes appended.
+    pub fn append_shapes(&self, shapes: &mut Vec<StrokeShape>, edges: &mut Vec<Vec3>) -> usize {
+        if self.width <= 0.0 {
+            return 0;
+        }
+        let shapes_start: usize = shapes.len();
+        let half_width: f32 = self.width * 0.5;
+        let mut push_polygon = |shapes: &mut Vec<StrokeShape>, points: &[Vec2]| {
+            let edges_start: usize = edges.len();
+            let polygon = PolygonFillCommand { points, ..Default::default() };
+            let edges_count: usize = polygon.append_edges(edges);
+            if edges_count > 0 {
+                let (min, max): (Vec2, Vec2) = polygon.bounds();
+                shapes.push(StrokeShape::Polygon {
+                    edges_start: edges_start as u32,
+                    edges_count: edges_count as u32,
+                    min,
+                    max,
+                });
+            }
+        };
+
+        let mut points: Vec<Vec2> = Vec::new();
+        for (polyline, closed) in self.path.polylines() {
+            // Coincident points would have no direction, drop them
+            points.clear();
+            for p in polyline {
+                if points.last().is_none_or(|last| (*p - *last).length() > 0.0001) {
+                    points.push(*p);
+                }
+            }
+            if closed && points.len() > 2 && (points[0] - *points.last().unwrap()).length() <= 0.0001 {
+                points.pop();
+            }
+            if points.len() < 2 {
+                continue;
+            }
+            let closed: bool = closed && points.len() > 2;
+            let segments_num: usize = if closed { points.len() } else { points.len() - 1 };
+
+            for i in 0..segments_num {
+                let mut p0: Vec2 = points[i];
+                let mut p1: Vec2 = points[(i + 1) % points.len()];
+                let d: Vec2 = (p1 - p0).normalized();
+                if !closed && self.cap == LineCap::Square {
+                    if i == 0 {
+                        p0 -= d * half_width;
+                    }
+                    if i == segments_num - 1 {
+                        p1 += d * half_width;
+                    }
+                }
+                let n: Vec2 = Vec2::new(-d.y, d.x) * half_width;
+                push_polygon(shapes, &[p0 + n, p1 + n, p1 - n, p0 - n]);
+            }
+
+            let joins = if closed { 0..points.len() } else { 1..points.len() - 1 };
+            for i in joins {
+                let v: Vec2 = points[i];
+                let d0: Vec2 = (v - points[(i + points.len() - 1) % points.len()]).normalized();
+                let d1: Vec2 = (points[(i + 1) % points.len()] - v).normalized();
+                let cross: f32 = d0.x * d1.y - d0.y * d1.x;
+                if cross.abs() < 0.0001 && dot(d0, d1) > 0.0 {
+                    continue; // a straight continuation, the segments' quads already meet
+                }
+                if self.join == LineJoin::Round {
+                    shapes.push(StrokeShape::Disc { center: v, radius: half_width });
+                    continue;
+                }
+                // The outer side of the corner is the one opposite to the turn
+                let side: f32 = if cross > 0.0 { -half_width } else { half_width };
+                let o0: Vec2 = v + Vec2::new(-d0.y, d0.x) * side;
+                let o1: Vec2 = v + Vec2::new(-d1.y, d1.x) * side;
+                let miter: Option<Vec2> = match self.join {
+                    LineJoin::Miter { limit } => {
+                        // The miter length relative to the stroke width is 1 / sin(angle / 2) of the angle between
+                        // the segments, i.e. 1 / cos(half the angle between their normals)
+                        let bisector: Vec2 = (o0 - v) + (o1 - v);
+                        let bisector_length: f32 = bisector.length();
+                        if bisector_length < 0.0001 {
+                            None
+                        } else {
+                            let bisector: Vec2 = bisector / bisector_length;
+                            let cos_half: f32 = dot(bisector, o0 - v) / half_width;
+                            if cos_half * limit >= 1.0 {
+                                Some(v + bisector * (half_width / cos_half))
+                            } else {
+                                None
+                            }
+                        }
+                    }
+                    _ => None,
+                };
+                match miter {
+                    Some(tip) => push_polygon(shapes, &[v, o0, tip, o1]),
+                    None => push_polygon(shapes, &[v, o0, o1]),
+                }
+            }
+
+            if !closed && self.cap == LineCap::Round {
+                shapes.push(StrokeShape::Disc { center: points[0], radius: half_width });
+                shapes.push(StrokeShape::Disc { center: *points.last().unwrap(), radius: half_width });
+            }
+        }
+        shapes.len() - shapes_start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coverage(shapes: &[StrokeShape], edges: &[Vec3], p: Vec2) -> f32 {
+        shapes.iter().map(|s| s.distance(edges, p)).fold(f32::MIN, f32::max)
+    }
+
+    #[test]
+    fn flattening_stays_within_tolerance() {
+        let mut path = Path::new();
+        path.move_to(Vec2::new(0.0, 0.0));
+        path.quad_to(Vec2::new(50.0, 100.0), Vec2::new(100.0, 0.0));
+        let (points, closed) = path.polylines().next().unwrap();
+        assert!(!closed);
+        assert!(points.len() > 2 && points.len() <= Path::MAX_CURVE_SEGMENTS + 1);
+        assert_eq!(*points.last().unwrap(), Vec2::new(100.0, 0.0));
+        // The segments are uniform in t, each of them should stay close to its piece of the curve
+        let segments: usize = points.len() - 1;
+        for i in 0..=1000 {
+            let t: f32 = i as f32 / 1000.0;
+            let curve: Vec2 = Vec2::new(0.0, 0.0) * ((1.0 - t) * (1.0 - t))
+                + Vec2::new(50.0, 100.0) * (2.0 * (1.0 - t) * t)
+                + Vec2::new(100.0, 0.0) * (t * t);
+            let segment: usize = ((t * segments as f32) as usize).min(segments - 1);
+            let deviation: f32 = distance(points[segment], points[segment + 1], curve);
+            assert!(deviation <= path.tolerance, "{} at t={}", deviation, t);
+        }
+
+        let mut path = Path::new();
+        path.move_to(Vec2::new(0.0, 0.0));
+        path.cubic_to(Vec2::new(0.0, 10.0), Vec2::new(10.0, 10.0), Vec2::new(10.0, 0.0));
+        let (points, _) = path.polylines().next().unwrap();
+        assert!(points.len() > 2);
+        assert_eq!(*points.last().unwrap(), Vec2::new(10.0, 0.0));
+    }
+
+    #[test]
+    fn straight_lines_are_not_subdivided() {
+        let mut path = Path::new();
+        path.move_to(Vec2::new(0.0, 0.0));
+        path.quad_to(Vec2::new(5.0, 5.0), Vec2::new(10.0, 10.0));
+        assert_eq!(path.polylines().next().unwrap().0.len(), 2);
+    }
+
+    #[test]
+    fn joins() {
+        // A right-angle corner at (10, 0) turning down
+        let path = Path::from_polyline(&[Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0), Vec2::new(10.0, 10.0)], false);
+        let outer_corner = Vec2::new(11.9, -1.9);
+        for (join, inside) in [
+            (LineJoin::Miter { limit: 4.0 }, true),
+            (LineJoin::Miter { limit: 1.2 }, false),
+            (LineJoin::Bevel, false),
+            (LineJoin::Round, false),
+        ] {
+            let stroke = StrokeCommand { path: &path, width: 4.0, join, ..Default::default() };
+            let mut shapes: Vec<StrokeShape> = Vec::new();
+            let mut edges: Vec<Vec3> = Vec::new();
+            assert_eq!(stroke.append_shapes(&mut shapes, &mut edges), 3);
+            assert_eq!(coverage(&shapes, &edges, outer_corner) > 0.0, inside, "{:?}", join);
+            assert!(coverage(&shapes, &edges, Vec2::new(10.5, -1.0)) > 0.0, "{:?}", join);
+        }
+    }
+
+    #[test]
+    fn caps() {
+        let path = Path::from_polyline(&[Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0)], false);
+        for (cap, shapes_num, covered) in
+            [(LineCap::Butt, 1, false), (LineCap::Square, 1, true), (LineCap::Round, 3, true)]
+        {
+            let stroke = StrokeCommand { path: &path, width: 4.0, cap, ..Default::default() };
+            let mut shapes: Vec<StrokeShape> = Vec::new();
+            let mut edges: Vec<Vec3> = Vec::new();
+            assert_eq!(stroke.append_shapes(&mut shapes, &mut edges), shapes_num);
+            assert_eq!(coverage(&shapes, &edges, Vec2::new(11.0, 0.0)) > 0.0, covered, "{:?}", cap);
+            assert!(coverage(&shapes, &edges, Vec2::new(13.0, 0.0)) < 0.0, "{:?}", cap);
+        }
+    }
+
+    #[test]
+    fn closed_paths_have_no_caps() {
+        let square = [Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0), Vec2::new(10.0, 10.0), Vec2::new(0.0, 10.0)];
+        let path = Path::from_polyline(&square, true);
+        let stroke = StrokeCommand { path: &path, width: 2.0, cap: LineCap::Round, ..Default::default() };
+        let mut shapes: Vec<StrokeShape> = Vec::new();
+        let mut edges: Vec<Vec3> = Vec::new();
+        // 4 segments and 4 round joins
+        assert_eq!(stroke.append_shapes(&mut shapes, &mut edges), 8);
+        assert!(coverage(&shapes, &edges, Vec2::new(5.0, 5.0)) < 0.0);
+    }
+}
@@ -0,0 +1,237 @@
+use super::*;
+
+// The 16-bit and the paletted output surfaces, e.g. for small displays and retro-styled output. The frame is rendered
+// into the 8-bit RGBA color buffer as usual, which the blending needs, and is converted when blitted into the surface.
+// The surfaces take a half and a quarter of the memory, and of the bandwidth when presented.
+
+// Packs the color into 16-bit RGB565: 5 bits of red in the high bits, 6 bits of green and 5 bits of blue. The alpha is
+// dropped.
+pub fn encode_rgb565(color: RGBA) -> u16 {
+    let quantize = |c: u8, max: u32| -> u16 { ((c as u32 * max + 127) / 255) as u16 };
+    (quantize(color.r, 31) << 11) | (quantize(color.g, 63) << 5) | quantize(color.b, 31)
+}
+
+// Unpacks the RGB565 color into an opaque RGBA8 one, the channels are expanded to the full [0, 255] range.
+pub fn decode_rgb565(color: u16) -> RGBA {
+    let r: u8 = (color >> 11) as u8 & 0x1F;
+    let g: u8 = (color >> 5) as u8 & 0x3F;
+    let b: u8 = color as u8 & 0x1F;
+    RGBA::new((r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2), 255)
+}
+
+// Up to 256 colors the pixels of a paletted surface index into.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    colors: Vec<RGBA>,
+
+    // The index of the nearest color for each of the colors reduced to 5 bits per channel
+    lookup: Vec<u8>,
+}
+
+impl Palette {
+    // The number of entries in the lookup of the nearest colors.
+    const LOOKUP_SIZE: usize = 32 * 32 * 32;
+
+    // Builds the palette of the given colors, their alpha is ignored. The nearest colors are looked up in a table built
+    // here, which takes about a millisecond.
+    pub fn new(colors: &[RGBA]) -> Self {
+        assert!(
+            !colors.is_empty() && colors.len() <= 256,
+            "a palette has from 1 to 256 colors, {} given",
+            colors.len()
+        );
+        let expand = |c: usize| -> i32 { ((c * 255 + 15) / 31) as i32 };
+        let lookup: Vec<u8> = (0..Self::LOOKUP_SIZE)
+            .map(|index| {
+                let (r, g, b): (i32, i32, i32) =
+                    (expand(index >> 10), expand((index >> 5) & 0x1F), expand(index & 0x1F));
+                let distance = |color: &RGBA| -> i32 {
+                    let (dr, dg, db) = (color.r as i32 - r, color.g as i32 - g, color.b as i32 - b);
+                    dr * dr + dg * dg + db * db
+                };
+                let (nearest, _) = colors
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, color)| distance(color))
+                    .unwrap();
+                nearest as u8
+            })
+            .collect();
+        Self { colors: colors.to_vec(), lookup }
+    }
+
+    pub fn colors(&self) -> &[RGBA] {
+        &self.colors
+    }
+
+    // The opaque color of the palette's entry, the indices past the last color wrap around.
+    pub fn color(&self, index: u8) -> RGBA {
+        RGBA { a: 255, ..self.colors[index as usize % self.colors.len()] }
+    }
+
+    // The index of the palette's color nearest to the given one, up to the precision of 5 bits per channel.
+    pub fn nearest(&self, color: RGBA) -> u8 {
+        let reduce = |c: u8| -> usize { (c as usize * 31 + 127) / 255 };
+        self.lookup[(reduce(color.r) << 10) | (reduce(color.g) << 5) | reduce(color.b)]
+    }
+
+    // The step between the neighbouring levels of a channel if the palette's colors were spread evenly over the RGB cube,
+    // the amplitude of the dithering noise.
+    fn dithering_spread(&self) -> f32 {
+        let levels: f32 = (self.colors.len() as f32).cbrt().max(2.0);
+        255.0 / (levels - 1.0)
+    }
+
+    // Converts the paletted surface into an RGBA8 one, e.g. to present it on a display without the palette support.
+    pub fn expand(&self, surface: &Buffer<u8>) -> Buffer<u32> {
+        map_surface(surface, |_, _, index| self.color(index).to_u32())
+    }
+}
+
+// Converts the RGBA8 color buffer into a 16-bit RGB565 surface of the same size. The dithering breaks the banding of
+// the 5 and 6 bits per channel. The HDR color buffers need to be tone mapped first, see tone_map().
+pub fn blit_rgb565(color: &TiledBuffer<u32, 64, 64>, dithering: Dithering) -> Buffer<u16> {
+    let mut surface = Buffer::<u16>::new(color.width(), color.height());
+    fill_surface(&mut surface, |x, y| {
+        let pixel: RGBA = RGBA::from_u32(color.at(x, y));
+        let offset: f32 = dithering.offset(x, y);
+        let quantize = |c: u8, max: f32| -> u16 { (c as f32 * (max / 255.0) + 0.5 + offset).clamp(0.0, max) as u16 };
+        (quantize(pixel.r, 31.0) << 11) | (quantize(pixel.g, 63.0) << 5) | quantize(pixel.b, 31.0)
+    });
+    surface
+}
+
+// Converts the RGB565 surface into an RGBA8 one, e.g. to present it on a display without the 16-bit support.
+pub fn expand_rgb565(surface: &Buffer<u16>) -> Buffer<u32> {
+    map_surface(surface, |_, _, color| decode_rgb565(color).to_u32())
+}
+
+// Converts the RGBA8 color buffer into a surface of the indices of the palette's nearest colors. The dithering mixes
+// the neighbouring colors of the palette in place of the ones it lacks, its noise is as large as the step between the
+// palette's levels would be if its colors were spread evenly. The HDR color buffers need to be tone mapped first.
+pub fn blit_paletted(color: &TiledBuffer<u32, 64, 64>, palette: &Palette, dithering: Dithering) -> Buffer<u8> {
+    let mut surface = Buffer::<u8>::new(color.width(), color.height());
+    let spread: f32 = palette.dithering_spread();
+    fill_surface(&mut surface, |x, y| {
+        let pixel: RGBA = RGBA::from_u32(color.at(x, y));
+        if dithering == Dithering::None {
+            return palette.nearest(pixel);
+        }
+        let offset: f32 = dithering.offset(x, y) * spread;
+        let dither = |c: u8| -> u8 { (c as f32 + offset + 0.5).clamp(0.0, 255.0) as u8 };
+        palette.nearest(RGBA::new(dither(pixel.r), dither(pixel.g), dither(pixel.b), pixel.a))
+    });
+    surface
+}
+
+// Fills the surface with the values of its pixels, the rows are processed in parallel.
+fn fill_surface<T, F>(surface: &mut Buffer<T>, f: F)
+where
+    T: Send,
+    F: Fn(u16, u16) -> T + Send + Sync,
+{
+    let (width, stride): (usize, usize) = (surface.width as usize, surface.stride as usize);
+    if width == 0 {
+        return;
+    }
+    use rayon::prelude::*;
+    surface.elems.par_chunks_mut(stride).enumerate().for_each(|(y, row)| {
+        for (x, value) in row[..width].iter_mut().enumerate() {
+            *value = f(x as u16, y as u16);
+        }
+    });
+}
+
+// A new surface of the same size with each pixel mapped by the function.
+fn map_surface<T, U, F>(surface: &Buffer<T>, f: F) -> Buffer<U>
+where
+    T: Copy + bytemuck::Zeroable + bytemuck::Pod + Send + Sync,
+    U: Copy + bytemuck::Zeroable + bytemuck::Pod + Send,
+    F: Fn(u16, u16, T) -> U + Send + Sync,
+{
+    let mut result = Buffer::<U>::new(surface.width, surface.height);
+    fill_surface(&mut result, |x, y| f(x, y, surface.at(x, y)));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgb565_roundtrip() {
+        assert_eq!(encode_rgb565(RGBA::new(0, 0, 0, 0)), 0x0000);
+        assert_eq!(encode_rgb565(RGBA::new(255, 255, 255, 0)), 0xFFFF);
+        assert_eq!(encode_rgb565(RGBA::new(255, 0, 0, 255)), 0xF800);
+        assert_eq!(encode_rgb565(RGBA::new(0, 255, 0, 255)), 0x07E0);
+        assert_eq!(decode_rgb565(0xFFFF), RGBA::new(255, 255, 255, 255));
+        assert_eq!(decode_rgb565(0x001F), RGBA::new(0, 0, 255, 255));
+        for color in [RGBA::new(10, 100, 200, 255), RGBA::new(128, 64, 32, 255), RGBA::new(77, 177, 7, 255)] {
+            let decoded: RGBA = decode_rgb565(encode_rgb565(color));
+            assert!(decoded.r.abs_diff(color.r) <= 4 && decoded.b.abs_diff(color.b) <= 4, "{:?}", decoded);
+            assert!(decoded.g.abs_diff(color.g) <= 2, "{:?}", decoded);
+            assert_eq!(encode_rgb565(decoded), encode_rgb565(color));
+        }
+    }
+
+    #[test]
+    fn rgb565_surface() {
+        let mut color = TiledBuffer::<u32, 64, 64>::new(100, 70);
+        color.fill(RGBA::new(255, 0, 0, 255).to_u32());
+        *color.at_mut(99, 69) = RGBA::new(0, 0, 255, 255).to_u32();
+        let surface: Buffer<u16> = blit_rgb565(&color, Dithering::None);
+        assert_eq!((surface.width, surface.height), (100, 70));
+        assert_eq!(surface.elems.len() * std::mem::size_of::<u16>(), 100 * 70 * 2);
+        assert_eq!(surface.at(0, 0), 0xF800);
+        assert_eq!(surface.at(99, 69), 0x001F);
+        let expanded: Buffer<u32> = expand_rgb565(&surface);
+        assert_eq!(RGBA::from_u32(expanded.at(50, 30)), RGBA::new(255, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(expanded.at(99, 69)), RGBA::new(0, 0, 255, 255));
+
+        // A gray between two levels of red is dithered into a mix of both, averaging to the exact value
+        color.fill(RGBA::new(100, 100, 100, 255).to_u32());
+        let surface: Buffer<u16> = blit_rgb565(&color, Dithering::BlueNoise);
+        let mut sum: f32 = 0.0;
+        for y in 0..64 {
+            for x in 0..64 {
+                sum += (surface.at(x, y) >> 11) as f32;
+            }
+        }
+        let average: f32 = sum / (64.0 * 64.0);
+        assert!((average - 100.0 * 31.0 / 255.0).abs() < 0.01, "{}", average);
+    }
+
+    #[test]
+    fn paletted_surface() {
+        let palette = Palette::new(&[
+            RGBA::new(0, 0, 0, 255),
+            RGBA::new(255, 255, 255, 255),
+            RGBA::new(255, 0, 0, 255),
+            RGBA::new(0, 0, 255, 128),
+        ]);
+        assert_eq!(palette.nearest(RGBA::new(20, 10, 30, 255)), 0);
+        assert_eq!(palette.nearest(RGBA::new(230, 240, 220, 255)), 1);
+        assert_eq!(palette.nearest(RGBA::new(200, 40, 30, 255)), 2);
+        assert_eq!(palette.nearest(RGBA::new(30, 20, 180, 255)), 3);
+        assert_eq!(palette.color(3), RGBA::new(0, 0, 255, 255));
+
+        let mut color = TiledBuffer::<u32, 64, 64>::new(80, 20);
+        color.fill(RGBA::new(250, 10, 10, 255).to_u32());
+        *color.at_mut(79, 19) = RGBA::new(0, 0, 0, 255).to_u32();
+        let surface: Buffer<u8> = blit_paletted(&color, &palette, Dithering::None);
+        assert_eq!(surface.elems.len(), 80 * 20);
+        assert_eq!(surface.at(10, 10), 2);
+        assert_eq!(surface.at(79, 19), 0);
+        assert_eq!(RGBA::from_u32(palette.expand(&surface).at(10, 10)), RGBA::new(255, 0, 0, 255));
+
+        // A mid gray is dithered between black and white
+        color.fill(RGBA::new(128, 128, 128, 255).to_u32());
+        let surface: Buffer<u8> = blit_paletted(&color, &palette, Dithering::Bayer4x4);
+        let whites: usize = (0..4)
+            .flat_map(|y| (0..4).map(move |x| (x, y)))
+            .filter(|&(x, y)| surface.at(x, y) == 1)
+            .count();
+        assert_eq!(whites, 8);
+        assert!((0..4).all(|y| (0..4).all(|x| surface.at(x, y) <= 1)));
+    }
+}
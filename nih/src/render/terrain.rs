@@ -0,0 +1,347 @@
+use super::super::math::*;
+use super::*;
+
+// Describes how a heightmap is turned into a Terrain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TerrainDescription {
+    // The size of the terrain on the XZ plane in world units, centered at the origin. The first row of the heightmap is
+    // at -Z, the first column at -X.
+    // Default: (256, 256).
+    pub size: Vec2,
+
+    // The height of the samples equal to 0.
+    // Default: 0.
+    pub base: f32,
+
+    // The height difference between the samples equal to 0 and to u16::MAX.
+    // Default: 32.
+    pub height: f32,
+
+    // The number of cells along a side of a chunk at the finest level of detail. Each coarser level halves it, so it
+    // should be a power of two.
+    // Default: 32.
+    pub chunk_cells: u32,
+
+    // The number of the levels of detail, limited by the chunk_cells.
+    // Default: 4.
+    pub lods: u32,
+
+    // How far below the chunks' edges their skirts go, hiding the cracks between the neighbouring chunks of different
+    // levels of detail. Should be about the largest height difference within the coarsest cells. 0 disables the skirts.
+    // Default: 1.
+    pub skirt_depth: f32,
+}
+
+impl Default for TerrainDescription {
+    fn default() -> Self {
+        Self { size: Vec2::new(256.0, 256.0), base: 0.0, height: 32.0, chunk_cells: 32, lods: 4, skirt_depth: 1.0 }
+    }
+}
+
+// Counters of the last Terrain::commit() call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TerrainStatistics {
+    // The chunks committed into the rasterizer.
+    pub visible_chunks: usize,
+
+    // The chunks skipped as being outside the camera's frustum.
+    pub culled_chunks: usize,
+
+    // The triangles of the visible chunks, including the skirts.
+    pub committed_triangles: usize,
+}
+
+struct TerrainChunk {
+    // The meshes from the finest to the coarsest level of detail
+    lods: Vec<MeshData>,
+
+    // The bounds of the chunk's surface, without the skirts
+    aabb: AABB,
+}
+
+// A heightmap split into square chunks, each one pre-built at several levels of detail. The chunks outside the frustum
+// are skipped and the others are drawn at the level of detail chosen by their distance to the camera, so the far
+// parts of large terrains don't flood the tiles with tiny triangles.
+pub struct Terrain {
+    chunks: Vec<TerrainChunk>,
+    statistics: TerrainStatistics,
+
+    // The distance from the camera to a chunk from which its second level of detail is used, each next level is
+    // used from twice the distance of the previous one.
+    // Default: a quarter of the larger side of the terrain.
+    pub lod_distance: f32,
+}
+
+impl Terrain {
+    // Builds the chunks from the heightmap, at least 2x2 samples, which are placed at the corners of the cells.
+    pub fn from_heightmap(heightmap: &Buffer<u16>, description: &TerrainDescription) -> Self {
+        assert!(heightmap.width >= 2 && heightmap.height >= 2);
+        assert!(description.chunk_cells > 0 && description.lods > 0);
+        let samples = Samples { heightmap, description };
+        let (cells_x, cells_z) = (heightmap.width as u32 - 1, heightmap.height as u32 - 1);
+        let lods: u32 = description.lods.min(description.chunk_cells.ilog2() + 1);
+        let mut chunks: Vec<TerrainChunk> = Vec::new();
+        for z0 in (0..cells_z).step_by(description.chunk_cells as usize) {
+            for x0 in (0..cells_x).step_by(description.chunk_cells as usize) {
+                let x1: u32 = (x0 + description.chunk_cells).min(cells_x);
+                let z1: u32 = (z0 + description.chunk_cells).min(cells_z);
+                let lods: Vec<MeshData> = (0..lods)
+                    .map(|lod| samples.chunk_mesh(x0, z0, x1, z1, 1 << lod))
+                    .collect();
+                let aabb: AABB = samples.chunk_aabb(x0, z0, x1, z1);
+                chunks.push(TerrainChunk { lods, aabb });
+            }
+        }
+        Self {
+            chunks,
+            statistics: TerrainStatistics::default(),
+            lod_distance: description.size.x.max(description.size.y) / 4.0,
+        }
+    }
+
+    // Same as from_heightmap(), with the 8-bit samples, e.g. the luma of an image, row by row.
+    pub fn from_grayscale(texels: &[u8], width: u16, height: u16, description: &TerrainDescription) -> Self {
+        assert_eq!(texels.len(), width as usize * height as usize);
+        let mut heightmap: Buffer<u16> = Buffer::new(width, height);
+        for (sample, &texel) in heightmap.as_mut_slice().iter_mut().zip(texels) {
+            *sample = texel as u16 * 257;
+        }
+        Self::from_heightmap(&heightmap, description)
+    }
+
+    pub fn chunks_num(&self) -> usize {
+        self.chunks.len()
+    }
+
+    // The bounds of the chunk's surface in the terrain's space.
+    pub fn chunk_aabb(&self, chunk: usize) -> AABB {
+        self.chunks[chunk].aabb
+    }
+
+    // The mesh of the chunk at the level of detail, 0 being the finest one.
+    pub fn chunk_mesh(&self, chunk: usize, lod: usize) -> &MeshData {
+        let lods: &[MeshData] = &self.chunks[chunk].lods;
+        &lods[lod.min(lods.len() - 1)]
+    }
+
+    // The level of detail used for a chunk at the distance from the camera.
+    pub fn lod_at(&self, distance: f32) -> usize {
+        let lods: usize = self.chunks.first().map_or(1, |chunk| chunk.lods.len());
+        if distance < self.lod_distance || self.lod_distance <= 0.0 {
+            return 0;
+        }
+        ((distance / self.lod_distance).log2().floor() as usize + 1).min(lods - 1)
+    }
+
+    // Commits the visible chunks with the material, the model transform places the terrain in the world.
+    pub fn commit(&mut self, rasterizer: &mut Rasterizer, material: &Material, transforms: &Transforms) {
+        self.statistics = TerrainStatistics::default();
+        let model_view_projection: Mat44 = transforms.projection * transforms.view * transforms.model.as_mat44();
+        let inverse_view: Mat44 = transforms.view.inverse();
+        let eye = Vec3::new(inverse_view.0[3], inverse_view.0[7], inverse_view.0[11]);
+        for chunk in &self.chunks {
+            if is_outside_frustum(&chunk.aabb, &model_view_projection, false) {
+                self.statistics.culled_chunks += 1;
+                continue;
+            }
+            let center: Vec3 = transforms.model * ((chunk.aabb.min + chunk.aabb.max) * 0.5);
+            let mesh: &MeshData = &chunk.lods[self.lod_at((center - eye).length()).min(chunk.lods.len() - 1)];
+            rasterizer.commit(&material.apply(RasterizationCommand {
+                model: transforms.model,
+                view: transforms.view,
+                projection: transforms.projection,
+                ..mesh.command()
+            }));
+            self.statistics.visible_chunks += 1;
+            self.statistics.committed_triangles += mesh.num_triangles();
+        }
+    }
+
+    pub fn statistics(&self) -> TerrainStatistics {
+        self.statistics
+    }
+}
+
+struct Samples<'a> {
+    heightmap: &'a Buffer<u16>,
+    description: &'a TerrainDescription,
+}
+
+impl Samples<'_> {
+    fn height(&self, x: u32, z: u32) -> f32 {
+        let x: u16 = (x as u16).min(self.heightmap.width - 1);
+        let z: u16 = (z as u16).min(self.heightmap.height - 1);
+        self.description.base + self.heightmap.at(x, z) as f32 / u16::MAX as f32 * self.description.height
+    }
+
+    fn position(&self, x: u32, z: u32) -> Vec3 {
+        let uv: Vec2 = self.tex_coord(x, z);
+        Vec3::new((uv.x - 0.5) * self.description.size.x, self.height(x, z), (uv.y - 0.5) * self.description.size.y)
+    }
+
+    fn tex_coord(&self, x: u32, z: u32) -> Vec2 {
+        Vec2::new(x as f32 / (self.heightmap.width - 1) as f32, z as f32 / (self.heightmap.height - 1) as f32)
+    }
+
+    // From the central differences of the full-resolution heights, so that the lighting doesn't change with the LOD.
+    fn normal(&self, x: u32, z: u32) -> Vec3 {
+        let (x0, x1) = (x.saturating_sub(1), (x + 1).min(self.heightmap.width as u32 - 1));
+        let (z0, z1) = (z.saturating_sub(1), (z + 1).min(self.heightmap.height as u32 - 1));
+        let dx: f32 = (x1 - x0) as f32 * self.description.size.x / (self.heightmap.width - 1) as f32;
+        let dz: f32 = (z1 - z0) as f32 * self.description.size.y / (self.heightmap.height - 1) as f32;
+        let slope_x: f32 = (self.height(x1, z) - self.height(x0, z)) / dx;
+        let slope_z: f32 = (self.height(x, z1) - self.height(x, z0)) / dz;
+        Vec3::new(-slope_x, 1.0, -slope_z).normalized()
+    }
+
+    fn chunk_aabb(&self, x0: u32, z0: u32, x1: u32, z1: u32) -> AABB {
+        let mut positions: Vec<Vec3> = Vec::new();
+        for z in z0..=z1 {
+            for x in x0..=x1 {
+                positions.push(self.position(x, z));
+            }
+        }
+        AABB::from_points(&positions)
+    }
+
+    // The cells [x0, x1) x [z0, z1) with every step-th sample, the last row and column are always kept so that the
+    // chunk's edges stay in place.
+    fn chunk_mesh(&self, x0: u32, z0: u32, x1: u32, z1: u32, step: u32) -> MeshData {
+        let columns: u32 = (x1 - x0).div_ceil(step);
+        let rows: u32 = (z1 - z0).div_ceil(step);
+        let sample = |i: u32, j: u32| -> (u32, u32) { ((x0 + i * step).min(x1), (z0 + j * step).min(z1)) };
+        let mut mesh = MeshData::default();
+        let push = |mesh: &mut MeshData, x: u32, z: u32, drop: f32| {
+            mesh.positions.push(self.position(x, z) - Vec3::new(0.0, drop, 0.0));
+            mesh.normals.push(self.normal(x, z));
+            mesh.tex_coords.push(self.tex_coord(x, z));
+        };
+        for j in 0..=rows {
+            for i in 0..=columns {
+                let (x, z) = sample(i, j);
+                push(&mut mesh, x, z, 0.0);
+            }
+        }
+        // Counter-clockwise when seen from above, same as MeshData::grid()
+        let vertex = |i: u32, j: u32| -> u32 { j * (columns + 1) + i };
+        for j in 0..rows {
+            for i in 0..columns {
+                let (a, b, c, d) = (vertex(i, j), vertex(i, j + 1), vertex(i + 1, j), vertex(i + 1, j + 1));
+                mesh.indices.extend_from_slice(&[a, b, c, c, b, d]);
+            }
+        }
+
+        if self.description.skirt_depth > 0.0 {
+            // The edge vertices around the chunk, clockwise when seen from above, so that the skirts face outwards
+            let mut edge: Vec<(u32, u32)> = Vec::new();
+            edge.extend((1..=columns).rev().map(|i| (i, 0)));
+            edge.extend((0..rows).map(|j| (0, j)));
+            edge.extend((0..columns).map(|i| (i, rows)));
+            edge.extend((1..=rows).rev().map(|j| (columns, j)));
+            let first_skirt_vertex: u32 = mesh.positions.len() as u32;
+            for &(i, j) in &edge {
+                let (x, z) = sample(i, j);
+                push(&mut mesh, x, z, self.description.skirt_depth);
+            }
+            for (k, &(i, j)) in edge.iter().enumerate() {
+                let (next_i, next_j) = edge[(k + 1) % edge.len()];
+                let (top, next_top) = (vertex(i, j), vertex(next_i, next_j));
+                let bottom: u32 = first_skirt_vertex + k as u32;
+                let next_bottom: u32 = first_skirt_vertex + ((k + 1) % edge.len()) as u32;
+                mesh.indices
+                    .extend_from_slice(&[top, bottom, next_top, next_top, bottom, next_bottom]);
+            }
+        }
+        mesh.sections
+            .push(MeshDataSection { num_triangles: mesh.indices.len() / 3, ..Default::default() });
+        mesh.update_aabb();
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A ramp rising along +X from 0 to the full height
+    fn ramp(width: u16, height: u16) -> Buffer<u16> {
+        let mut heightmap: Buffer<u16> = Buffer::new(width, height);
+        for z in 0..height {
+            for x in 0..width {
+                *heightmap.at_mut(x, z) = (x as u32 * u16::MAX as u32 / (width - 1) as u32) as u16;
+            }
+        }
+        heightmap
+    }
+
+    fn face_normal(mesh: &MeshData, triangle: usize) -> Vec3 {
+        let [a, b, c] = [0, 1, 2].map(|k| mesh.positions[mesh.indices[triangle * 3 + k] as usize]);
+        cross(b - a, c - a).normalized()
+    }
+
+    #[test]
+    fn chunks_and_lods() {
+        let description = TerrainDescription {
+            size: Vec2::new(8.0, 8.0),
+            chunk_cells: 4,
+            lods: 8,
+            skirt_depth: 0.0,
+            ..Default::default()
+        };
+        let terrain = Terrain::from_heightmap(&ramp(9, 9), &description);
+        assert_eq!(terrain.chunks_num(), 4);
+        // The LODs stop at a single cell per chunk
+        assert_eq!(terrain.chunks[0].lods.len(), 3);
+        assert_eq!(terrain.chunk_mesh(0, 0).num_triangles(), 4 * 4 * 2);
+        assert_eq!(terrain.chunk_mesh(0, 1).num_triangles(), 2 * 2 * 2);
+        assert_eq!(terrain.chunk_mesh(0, 9).num_triangles(), 2);
+
+        let aabb: AABB = terrain.chunk_aabb(3);
+        assert!((aabb.min - Vec3::new(0.0, 16.0, 0.0)).length() < 1e-2);
+        assert_eq!(aabb.max, Vec3::new(4.0, 32.0, 4.0));
+        // The surface faces up and the normals follow the slope
+        let mesh: &MeshData = terrain.chunk_mesh(0, 0);
+        assert!(face_normal(mesh, 0).y > 0.0);
+        assert!((mesh.normals[0] - Vec3::new(-4.0, 1.0, 0.0).normalized()).length() < 1e-3);
+    }
+
+    #[test]
+    fn partial_chunks_keep_edges() {
+        let description = TerrainDescription { chunk_cells: 4, lods: 2, skirt_depth: 0.0, ..Default::default() };
+        let terrain = Terrain::from_heightmap(&ramp(7, 3), &description);
+        assert_eq!(terrain.chunks_num(), 2);
+        let mesh: &MeshData = terrain.chunk_mesh(1, 1);
+        assert_eq!(mesh.aabb.max.x, 128.0);
+        assert_eq!(mesh.aabb.max.z, 128.0);
+        assert_eq!(mesh.num_triangles(), 2);
+    }
+
+    #[test]
+    fn skirts_face_outwards() {
+        let description =
+            TerrainDescription { size: Vec2::new(4.0, 4.0), chunk_cells: 4, lods: 1, ..Default::default() };
+        let terrain = Terrain::from_heightmap(&Buffer::new(5, 5), &description);
+        let mesh: &MeshData = terrain.chunk_mesh(0, 0);
+        assert_eq!(mesh.num_triangles(), 4 * 4 * 2 + 16 * 2);
+        assert_eq!(mesh.aabb.min.y, -1.0);
+        for triangle in 32..mesh.num_triangles() {
+            let normal: Vec3 = face_normal(mesh, triangle);
+            let center: Vec3 = mesh.positions[mesh.indices[triangle * 3] as usize];
+            assert!(dot(normal, Vec3::new(center.x, 0.0, center.z)) > 0.0, "triangle {}", triangle);
+        }
+        // The chunk's bounds don't include the skirts
+        assert_eq!(terrain.chunk_aabb(0).min.y, 0.0);
+    }
+
+    #[test]
+    fn lod_by_distance() {
+        let mut terrain =
+            Terrain::from_heightmap(&ramp(9, 9), &TerrainDescription { chunk_cells: 8, ..Default::default() });
+        terrain.lod_distance = 10.0;
+        assert_eq!(terrain.lod_at(5.0), 0);
+        assert_eq!(terrain.lod_at(10.0), 1);
+        assert_eq!(terrain.lod_at(25.0), 2);
+        assert_eq!(terrain.lod_at(45.0), 3);
+        assert_eq!(terrain.lod_at(1000.0), 3);
+    }
+}
@@ -0,0 +1,429 @@
+use super::super::math::*;
+use super::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// A character's image in a BitmapFont and its placement relative to the pen, in texels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glyph {
+    // The rectangle of the image in the font's texture, from its top-left corner.
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+
+    // The offset of the image's top-left corner from the pen, which is at the top of the line.
+    pub offset: Vec2,
+
+    // How far the pen moves to the right after the character.
+    pub advance: f32,
+}
+
+// A font with the characters' images packed into a single texture, either as a grid of equal cells or as described by
+// an AngelCode BMFont descriptor. The texture's alpha channel is the glyphs' coverage, i.e. it should be RGBA.
+#[derive(Debug, Clone)]
+pub struct BitmapFont {
+    pub texture: Arc<Texture>,
+    pub glyphs: HashMap<char, Glyph>,
+
+    // The adjustment of the advance between pairs of characters, in texels.
+    pub kerning: HashMap<(char, char), f32>,
+
+    // The distance between the tops of the consecutive lines, in texels.
+    pub line_height: f32,
+
+    // The character drawn instead of the ones missing in the font, e.g. '?'. The missing ones are skipped without it.
+    pub fallback: Option<char>,
+}
+
+// The reason why a BMFont descriptor can't be turned into a BitmapFont.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FontError {
+    // The line of the descriptor, counting from 1, is malformed.
+    Syntax { line: usize, message: String },
+
+    // Only the fonts fitting into a single texture page are supported.
+    UnsupportedPages(u32),
+
+    // The glyph's rectangle goes beyond the texture.
+    GlyphOutOfTexture(char),
+}
+
+impl std::fmt::Display for FontError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FontError::Syntax { line, message } => write!(f, "invalid font descriptor at line {}: {}", line, message),
+            FontError::UnsupportedPages(pages) => write!(f, "the font has {} pages, only 1 is supported", pages),
+            FontError::GlyphOutOfTexture(c) => write!(f, "glyph {:?} is out of the font's texture", c),
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+impl BitmapFont {
+    // A monospaced font from a texture split into cells of cell_width x cell_height texels, containing the consecutive
+    // characters starting from the first one, left to right and top to bottom, e.g. the printable ASCII from ' '.
+    pub fn from_grid(texture: Arc<Texture>, cell_width: u32, cell_height: u32, first: char, count: u32) -> BitmapFont {
+        assert!(cell_width > 0 && cell_height > 0);
+        let columns: u32 = texture.mips[0].width as u32 / cell_width;
+        let rows: u32 = texture.mips[0].height as u32 / cell_height;
+        assert!(count <= columns * rows, "the texture can't hold {} cells", count);
+        let mut glyphs: HashMap<char, Glyph> = HashMap::with_capacity(count as usize);
+        for index in 0..count {
+            let Some(c) = char::from_u32(first as u32 + index) else {
+                continue;
+            };
+            let glyph = Glyph {
+                x: index % columns * cell_width,
+                y: index / columns * cell_height,
+                width: cell_width,
+                height: cell_height,
+                offset: Vec2::new(0.0, 0.0),
+                advance: cell_width as f32,
+            };
+            glyphs.insert(c, glyph);
+        }
+        let fallback: Option<char> = glyphs.contains_key(&'?').then_some('?');
+        BitmapFont { texture, glyphs, kerning: HashMap::new(), line_height: cell_height as f32, fallback }
+    }
+
+    // A font from the text variant of the BMFont descriptor (.fnt), with its single page already loaded as the texture.
+    // Only the "common", "char" and "kerning" lines are used, the others are ignored.
+    pub fn from_bmfont(texture: Arc<Texture>, descriptor: &str) -> Result<BitmapFont, FontError> {
+        let texture_width: u32 = texture.mips[0].width as u32;
+        let texture_height: u32 = texture.mips[0].height as u32;
+        let mut font =
+            BitmapFont { texture, glyphs: HashMap::new(), kerning: HashMap::new(), line_height: 0.0, fallback: None };
+        for (index, line) in descriptor.lines().enumerate() {
+            let syntax = |message: String| FontError::Syntax { line: index + 1, message };
+            let mut tokens = BMFontTokens { rest: line.trim() };
+            let Some(tag) = tokens.next() else {
+                continue;
+            };
+            let mut attributes: HashMap<&str, &str> = HashMap::new();
+            for token in tokens {
+                let (key, value) = token
+                    .split_once('=')
+                    .ok_or_else(|| syntax(format!("expected key=value, got {}", token)))?;
+                attributes.insert(key, value.trim_matches('"'));
+            }
+            let number = |key: &str| -> Result<i32, FontError> {
+                let value: &str = attributes
+                    .get(key)
+                    .ok_or_else(|| syntax(format!("{} has no {}", tag, key)))?;
+                value
+                    .parse::<i32>()
+                    .map_err(|_| syntax(format!("{} is not a number: {}", key, value)))
+            };
+            let character = |key: &str| -> Result<char, FontError> {
+                let id: i32 = number(key)?;
+                char::from_u32(id as u32).ok_or_else(|| syntax(format!("{} is not a character: {}", key, id)))
+            };
+            match tag {
+                "common" => {
+                    font.line_height = number("lineHeight")? as f32;
+                    if let Ok(pages) = number("pages")
+                        && pages != 1
+                    {
+                        return Err(FontError::UnsupportedPages(pages.max(0) as u32));
+                    }
+                }
+                "char" => {
+                    let c: char = character("id")?;
+                    let glyph = Glyph {
+                        x: number("x")?.max(0) as u32,
+                        y: number("y")?.max(0) as u32,
+                        width: number("width")?.max(0) as u32,
+                        height: number("height")?.max(0) as u32,
+                        offset: Vec2::new(number("xoffset")? as f32, number("yoffset")? as f32),
+                        advance: number("xadvance")? as f32,
+                    };
+                    if glyph.x + glyph.width > texture_width || glyph.y + glyph.height > texture_height {
+                        return Err(FontError::GlyphOutOfTexture(c));
+                    }
+                    font.glyphs.insert(c, glyph);
+                }
+                "kerning" => {
+                    font.kerning
+                        .insert((character("first")?, character("second")?), number("amount")? as f32);
+                }
+                _ => {}
+            }
+        }
+        font.fallback = font.glyphs.contains_key(&'?').then_some('?');
+        Ok(font)
+    }
+
+    fn glyph(&self, c: char) -> Option<(char, &Glyph)> {
+        match self.glyphs.get(&c) {
+            Some(glyph) => Some((c, glyph)),
+            None => self.fallback.and_then(|f| self.glyphs.get(&f).map(|glyph| (f, glyph))),
+        }
+    }
+
+    // The size of the text's bounding box in texels: the widest line's advance by the lines' height.
+    pub fn measure(&self, text: &str) -> Vec2 {
+        let mut width: f32 = 0.0;
+        let mut lines: usize = 0;
+        for line in text.split('\n') {
+            let mut pen: f32 = 0.0;
+            let mut previous: Option<char> = None;
+            for c in line.chars() {
+                let Some((c, glyph)) = self.glyph(c) else {
+                    continue;
+                };
+                pen += self.kerning_between(previous, c) + glyph.advance;
+                previous = Some(c);
+            }
+            width = width.max(pen);
+            lines += 1;
+        }
+        Vec2::new(width, lines as f32 * self.line_height)
+    }
+
+    fn kerning_between(&self, previous: Option<char>, c: char) -> f32 {
+        match previous {
+            Some(previous) => self.kerning.get(&(previous, c)).copied().unwrap_or(0.0),
+            None => 0.0,
+        }
+    }
+
+    // Appends two triangles per visible glyph of the text, with positions in pixels starting from the top-left corner
+    // at the origin and scaled by the scale, and texture coordinates in [0, 1] over the font's texture.
+    // '\n' starts a new line below the previous one.
+    pub fn append_geometry(
+        &self,
+        origin: Vec2,
+        text: &str,
+        scale: f32,
+        positions: &mut Vec<Vec2>,
+        tex_coords: &mut Vec<Vec2>,
+    ) {
+        let texture_width: f32 = self.texture.mips[0].width as f32;
+        let texture_height: f32 = self.texture.mips[0].height as f32;
+        let mut pen: Vec2 = origin;
+        let mut previous: Option<char> = None;
+        for c in text.chars() {
+            if c == '\n' {
+                pen = Vec2::new(origin.x, pen.y + self.line_height * scale);
+                previous = None;
+                continue;
+            }
+            let Some((c, glyph)) = self.glyph(c) else {
+                continue;
+            };
+            pen.x += self.kerning_between(previous, c) * scale;
+            previous = Some(c);
+            if glyph.width > 0 && glyph.height > 0 {
+                let min: Vec2 = pen + glyph.offset * scale;
+                let max: Vec2 = min + Vec2::new(glyph.width as f32, glyph.height as f32) * scale;
+                let t0 = Vec2::new(glyph.x as f32 / texture_width, glyph.y as f32 / texture_height);
+                let t1 = Vec2::new(
+                    (glyph.x + glyph.width) as f32 / texture_width,
+                    (glyph.y + glyph.height) as f32 / texture_height,
+                );
+                let p00 = min;
+                let p10 = Vec2::new(max.x, min.y);
+                let p11 = max;
+                let p01 = Vec2::new(min.x, max.y);
+                let t00 = t0;
+                let t10 = Vec2::new(t1.x, t0.y);
+                let t11 = t1;
+                let t01 = Vec2::new(t0.x, t1.y);
+                positions.extend_from_slice(&[p00, p10, p11, p00, p11, p01]);
+                tex_coords.extend_from_slice(&[t00, t10, t11, t00, t11, t01]);
+            }
+            pen.x += glyph.advance * scale;
+        }
+    }
+}
+
+// Splits a BMFont descriptor's line into the whitespace-separated tokens, keeping the quoted values as a whole, e.g.
+// face="Open Sans".
+struct BMFontTokens<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for BMFontTokens<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let rest: &str = self.rest.trim_start();
+        if rest.is_empty() {
+            return None;
+        }
+        let mut in_quotes: bool = false;
+        let end: usize = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                if c == '"' {
+                    in_quotes = !in_quotes;
+                }
+                c.is_whitespace() && !in_quotes
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        self.rest = &rest[end..];
+        Some(&rest[..end])
+    }
+}
+
+// The way a text is drawn by draw_text().
+#[derive(Debug, Clone)]
+pub struct TextStyle {
+    pub font: Arc<BitmapFont>,
+
+    // The color multiplied by the glyphs' texels.
+    // Default: (1, 1, 1, 1).
+    pub color: Vec4,
+
+    // The size of a font's texel on the screen, in pixels.
+    // Default: 1.0.
+    pub scale: f32,
+
+    // The glyphs' texels with lower alpha are discarded, see RasterizationCommand::alpha_test.
+    // Default: 128.
+    pub alpha_test: u8,
+
+    // Default: Nearest.
+    pub sampling_filter: SamplerFilter,
+
+    // Sets whether the glyphs should be alpha-blended with the framebuffer, e.g. for a semi-transparent color or for
+    // the smooth edges with the alpha test disabled.
+    // Default: None.
+    pub alpha_blending: AlphaBlendingMode,
+}
+
+impl TextStyle {
+    pub fn new(font: Arc<BitmapFont>) -> Self {
+        Self {
+            font,
+            color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            scale: 1.0,
+            alpha_test: 128,
+            sampling_filter: SamplerFilter::Nearest,
+            alpha_blending: AlphaBlendingMode::None,
+        }
+    }
+
+    // The size of the text's bounding box in pixels.
+    pub fn measure(&self, text: &str) -> Vec2 {
+        self.font.measure(text) * self.scale
+    }
+}
+
+// Commits the text with its top-left corner at the position in pixels relative to the viewport's top-left corner.
+// All the glyphs are batched into a single RasterizationCommand, so it's ordered and blended the same way as the other
+// geometry, e.g. draw it last to overlay the frame's statistics.
+pub fn draw_text(rasterizer: &mut Rasterizer, position: Vec2, text: &str, style: &TextStyle) {
+    let mut positions: Vec<Vec2> = Vec::with_capacity(text.len() * 6);
+    let mut tex_coords: Vec<Vec2> = Vec::with_capacity(text.len() * 6);
+    style
+        .font
+        .append_geometry(position, text, style.scale, &mut positions, &mut tex_coords);
+    if positions.is_empty() {
+        return;
+    }
+    let world_positions: Vec<Vec3> = positions.iter().map(|p| Vec3::new(p.x, p.y, 0.0)).collect();
+    let viewport: Viewport = rasterizer.viewport();
+    let width: f32 = (viewport.xmax - viewport.xmin) as f32;
+    let height: f32 = (viewport.ymax - viewport.ymin) as f32;
+    rasterizer.commit(&RasterizationCommand {
+        world_positions: &world_positions,
+        tex_coords: &tex_coords,
+        projection: Mat44::orthographic(0.0, width, height, 0.0, -1.0, 1.0),
+        color: style.color,
+        texture: Some(style.font.texture.clone()),
+        sampling_filter: style.sampling_filter,
+        address_mode_u: SamplerAddressMode::ClampToEdge,
+        address_mode_v: SamplerAddressMode::ClampToEdge,
+        alpha_blending: style.alpha_blending,
+        alpha_test: style.alpha_test,
+        ..Default::default()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texture(size: u32) -> Arc<Texture> {
+        Texture::new(&TextureSource {
+            texels: &vec![255u8; (size * size * 4) as usize],
+            width: size,
+            height: size,
+            format: TextureFormat::RGBA,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn grid_cells_go_left_to_right_then_down() {
+        let font = BitmapFont::from_grid(texture(64), 16, 32, ' ', 8);
+        assert_eq!(font.glyphs.len(), 8);
+        assert_eq!((font.glyphs[&' '].x, font.glyphs[&' '].y), (0, 0));
+        assert_eq!((font.glyphs[&'#'].x, font.glyphs[&'#'].y), (48, 0));
+        assert_eq!((font.glyphs[&'$'].x, font.glyphs[&'$'].y), (0, 32));
+        assert_eq!(font.line_height, 32.0);
+        assert_eq!(font.fallback, None);
+        assert_eq!(font.measure("!!\n\"\"\""), Vec2::new(48.0, 64.0));
+    }
+
+    #[test]
+    fn bmfont_descriptor() {
+        let descriptor = r#"info face="Open Sans" size=16 bold=0
+common lineHeight=20 base=16 scaleW=64 scaleH=64 pages=1
+page id=0 file="open_sans.png"
+chars count=2
+char id=65   x=0  y=0  width=10 height=12 xoffset=1 yoffset=4 xadvance=11 page=0
+char id=86   x=10 y=0  width=10 height=12 xoffset=0 yoffset=4 xadvance=10 page=0
+kernings count=1
+kerning first=65 second=86 amount=-2
+"#;
+        let font = BitmapFont::from_bmfont(texture(64), descriptor).unwrap();
+        assert_eq!(font.line_height, 20.0);
+        assert_eq!(
+            font.glyphs[&'V'],
+            Glyph { x: 10, y: 0, width: 10, height: 12, offset: Vec2::new(0.0, 4.0), advance: 10.0 }
+        );
+        assert_eq!(font.kerning[&('A', 'V')], -2.0);
+        assert_eq!(font.measure("AV"), Vec2::new(19.0, 20.0));
+        assert_eq!(font.measure("VA"), Vec2::new(21.0, 20.0));
+
+        let mut positions: Vec<Vec2> = Vec::new();
+        let mut tex_coords: Vec<Vec2> = Vec::new();
+        font.append_geometry(Vec2::new(100.0, 50.0), "AV", 2.0, &mut positions, &mut tex_coords);
+        assert_eq!(positions.len(), 12);
+        assert_eq!(positions[0], Vec2::new(102.0, 58.0));
+        assert_eq!(positions[2], Vec2::new(122.0, 82.0));
+        assert_eq!(positions[6], Vec2::new(118.0, 58.0));
+        assert_eq!(tex_coords[6], Vec2::new(10.0 / 64.0, 0.0));
+        assert_eq!(tex_coords[8], Vec2::new(20.0 / 64.0, 12.0 / 64.0));
+    }
+
+    #[test]
+    fn bmfont_errors() {
+        let error =
+            BitmapFont::from_bmfont(texture(16), "common lineHeight=20\nchar id=65 x=0 y=0 width=10").unwrap_err();
+        assert_eq!(error, FontError::Syntax { line: 2, message: "char has no height".to_string() });
+        let error = BitmapFont::from_bmfont(texture(16), "common lineHeight=abc").unwrap_err();
+        assert!(matches!(error, FontError::Syntax { line: 1, .. }));
+        let error = BitmapFont::from_bmfont(texture(16), "common lineHeight=20 pages=2").unwrap_err();
+        assert_eq!(error, FontError::UnsupportedPages(2));
+        let error = BitmapFont::from_bmfont(
+            texture(16),
+            "char id=66 x=8 y=0 width=10 height=10 xoffset=0 yoffset=0 xadvance=10",
+        )
+        .unwrap_err();
+        assert_eq!(error, FontError::GlyphOutOfTexture('B'));
+    }
+
+    #[test]
+    fn missing_characters_use_fallback() {
+        let mut font = BitmapFont::from_grid(texture(64), 8, 8, '0', 16);
+        assert_eq!(font.fallback, Some('?'));
+        assert_eq!(font.measure("1x2"), Vec2::new(24.0, 8.0));
+        font.fallback = None;
+        assert_eq!(font.measure("1x2"), Vec2::new(16.0, 8.0));
+    }
+}
@@ -1,3 +1,4 @@
+use super::*;
 use std::sync::Arc;
 
 #[repr(u8)]
@@ -6,6 +7,10 @@ pub enum TextureFormat {
     Grayscale = 0,
     RGB = 1,
     RGBA = 2,
+
+    // Premultiplied RGBA compressed into 4x4 blocks of 16 bytes, see texture_compression.rs.
+    // Can't be used as a source format, produced by Texture::new_with_options() with TextureOptions::compress.
+    BC3 = 3,
 }
 
 pub struct TextureSource<'a> {
@@ -13,6 +18,150 @@ pub struct TextureSource<'a> {
     pub width: u32,
     pub height: u32,
     pub format: TextureFormat,
+
+    // Optional texels of the mip levels, starting from level 1, each one half the size of the previous one.
+    // The levels which are not provided are generated from the last provided one, see TextureOptions::mip_generation.
+    pub mips: &'a [&'a [u8]],
+
+    // Optional color to be made transparent, e.g. the magenta background of classic sprites, so that they can be drawn
+    // with the alpha test. Color-keyed textures are always created as RGBA.
+    // Default: None.
+    pub color_key: Option<ColorKey>,
+}
+
+impl Default for TextureSource<'_> {
+    fn default() -> Self {
+        Self { texels: &[], width: 0, height: 0, format: TextureFormat::Grayscale, mips: &[], color_key: None }
+    }
+}
+
+// The texels of this color get alpha 0 when a texture is created, see TextureSource::color_key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorKey {
+    // Only the RGB part is compared, the alpha is ignored.
+    pub color: RGBA,
+
+    // Fill the keyed texels next to the visible ones with the color of those neighbours, keeping their alpha at 0.
+    // Without it the bilinear filtering darkens the alpha-tested edges towards black, but the filled texels are no
+    // longer premultiplied, so the blended drawing gets a faint halo instead.
+    // Default: false.
+    pub bleed: bool,
+}
+
+impl ColorKey {
+    pub fn new(color: RGBA) -> Self {
+        Self { color, bleed: false }
+    }
+
+    fn matches(&self, r: u8, g: u8, b: u8) -> bool {
+        self.color.r == r && self.color.g == g && self.color.b == b
+    }
+}
+
+// The reason why a TextureSource can't be turned into a Texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureError {
+    // The format can't be used as a source, e.g. BC3.
+    UnsupportedSourceFormat(TextureFormat),
+
+    // Textures must be square and their sides must be non-zero powers of two.
+    UnsupportedSize {
+        width: u32,
+        height: u32,
+    },
+
+    // The texels of the mip level don't match its size and format.
+    TexelsSizeMismatch {
+        level: usize,
+        expected: usize,
+        actual: usize,
+    },
+
+    // More mip levels are provided than the texture can have.
+    TooManyMips {
+        provided: usize,
+        max: usize,
+    },
+
+    // The rendered buffer doesn't match the texture it updates, which must be an RGBA texture of the same size.
+    UpdateMismatch {
+        width: u32,
+        height: u32,
+    },
+}
+
+impl std::fmt::Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            TextureError::UnsupportedSourceFormat(format) => {
+                write!(f, "{:?} textures can't be used as a source", format)
+            }
+            TextureError::UnsupportedSize { width, height } => {
+                write!(f, "unsupported texture size {}x{}, must be square with power-of-two sides", width, height)
+            }
+            TextureError::TexelsSizeMismatch { level, expected, actual } => {
+                write!(f, "mip level {} must have {} bytes of texels, got {}", level, expected, actual)
+            }
+            TextureError::TooManyMips { provided, max } => {
+                write!(f, "{} mip levels provided, at most {} are possible", provided, max)
+            }
+            TextureError::UpdateMismatch { width, height } => {
+                write!(f, "a {}x{} buffer can't update the texture, it must be RGBA of the same size", width, height)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+// Defines how the mip levels not provided by the TextureSource are produced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MipGeneration {
+    // Don't generate the mips, the texture only has level 0 and the levels provided explicitly.
+    // The samplers fall back to the smallest available level.
+    // Suits the textures which are only magnified, e.g. UI and fonts: saves a quarter of the memory and the generation
+    // time. The mips of an existing texture can be dropped with Texture::drop_mips().
+    None,
+
+    // Average each 2x2 block of texels. Fast, but somewhat blurry.
+    Box,
+
+    // Downsample with a Kaiser-windowed sinc filter, which keeps the smaller levels sharper at a higher cost.
+    Kaiser,
+}
+
+// A rectangular sub-region of a texture in normalized texture coordinates, e.g. a single cell of a texture atlas.
+// Texture coordinates [0, 1] are mapped onto [u0, u1] x [v0, v1], coordinates outside of that range are either repeated
+// or clamped within the region, so that sampling never bleeds into the neighbouring cells.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureRegion {
+    pub u0: f32,
+    pub v0: f32,
+    pub u1: f32,
+    pub v1: f32,
+
+    // Clamp the texture coordinates to the edges of the region instead of repeating it.
+    pub clamp: bool,
+}
+
+// Optional processing of the source texels performed by Texture::new_with_options().
+#[derive(Debug, Clone, Copy)]
+pub struct TextureOptions {
+    // Compress RGB and RGBA textures into the BC3 format, which takes 1 byte per texel instead of 3 or 4.
+    // Trades some color precision for the lower memory bandwidth when sampling large textures.
+    // Grayscale textures are kept as-is.
+    // Default: false.
+    pub compress: bool,
+
+    // The way the mip levels missing in the source are generated.
+    // Default: Box.
+    pub mip_generation: MipGeneration,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self { compress: false, mip_generation: MipGeneration::Box }
+    }
 }
 
 pub const MAX_MIP_LEVELS: usize = 16;
@@ -40,111 +189,573 @@ pub struct Texture {
 
 impl Texture {
     pub fn new(source: &TextureSource) -> Arc<Self> {
+        Self::new_with_options(source, &TextureOptions::default())
+    }
+
+    // Panics if the source is not valid, see try_new_with_options().
+    pub fn new_with_options(source: &TextureSource, options: &TextureOptions) -> Arc<Self> {
+        match Self::try_new_with_options(source, options) {
+            Ok(texture) => texture,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    pub fn try_new(source: &TextureSource) -> Result<Arc<Self>, TextureError> {
+        Self::try_new_with_options(source, &TextureOptions::default())
+    }
+
+    // Validates the source before creating the texture, returns an error instead of panicking if it can't be used.
+    pub fn try_new_with_options(source: &TextureSource, options: &TextureOptions) -> Result<Arc<Self>, TextureError> {
+        source.validate()?;
+        let texture = match source.color_key {
+            Some(key) => Self::new_color_keyed(source, key, options.mip_generation),
+            None => Self::new_uncompressed(source, options.mip_generation),
+        };
+        if options.compress && source.format != TextureFormat::Grayscale {
+            Ok(Arc::new(texture.compressed()))
+        } else {
+            Ok(Arc::new(texture))
+        }
+    }
+
+    // Wraps the rendered Rgba8 color buffer as an RGBA texture, e.g. the view of a mirror or a CCTV camera rendered by
+    // the first pass to be sampled by the second one. The tiles are copied straight into the texture's texels, without
+    // a flat intermediate buffer, and the mip levels are generated from them as specified.
+    // The colors are taken as they are, i.e. as already premultiplied by alpha, which is how the rasterizer blends them.
+    // The buffer must be square with power-of-two sides, the same as any texture.
+    pub fn from_color_buffer<const W: usize, const H: usize>(
+        buffer: &TiledBuffer<u32, W, H>,
+        mip_generation: MipGeneration,
+    ) -> Result<Arc<Self>, TextureError> {
+        validate_rendered_size(buffer.width(), buffer.height())?;
+        Ok(Arc::new(Self::new_rendered(buffer.width() as u32, mip_generation, |texels| {
+            copy_tiled_colors(buffer, texels)
+        })))
+    }
+
+    // Same as from_color_buffer(), but for a flat buffer, e.g. a previously resolved or loaded image.
+    pub fn from_flat_color_buffer(
+        buffer: &Buffer<u32>,
+        mip_generation: MipGeneration,
+    ) -> Result<Arc<Self>, TextureError> {
+        validate_rendered_size(buffer.width, buffer.height)?;
+        Ok(Arc::new(Self::new_rendered(buffer.width as u32, mip_generation, |texels| copy_flat_colors(buffer, texels))))
+    }
+
+    // Replaces the texels of an RGBA texture with the next rendered frame of the same size and regenerates its mip
+    // levels, reusing the texture's memory instead of allocating a new one every frame. The texture is usually shared,
+    // so it can be updated via Arc::get_mut() once the commands sampling the previous frame are drawn and dropped.
+    // The texture keeps its number of mip levels, they're regenerated with the Box filter for MipGeneration::None.
+    pub fn update_from_color_buffer<const W: usize, const H: usize>(
+        &mut self,
+        buffer: &TiledBuffer<u32, W, H>,
+        mip_generation: MipGeneration,
+    ) -> Result<(), TextureError> {
+        self.validate_update(buffer.width(), buffer.height())?;
+        self.update_rendered(mip_generation, |texels| copy_tiled_colors(buffer, texels));
+        Ok(())
+    }
+
+    // Same as update_from_color_buffer(), but for a flat buffer.
+    pub fn update_from_flat_color_buffer(
+        &mut self,
+        buffer: &Buffer<u32>,
+        mip_generation: MipGeneration,
+    ) -> Result<(), TextureError> {
+        self.validate_update(buffer.width, buffer.height)?;
+        self.update_rendered(mip_generation, |texels| copy_flat_colors(buffer, texels));
+        Ok(())
+    }
+
+    // Drops all the mip levels but level 0 and releases their memory, same as if the texture was created with
+    // MipGeneration::None, e.g. once it turns out to be only magnified. The samplers fall back to level 0 then.
+    // The texture is usually shared, so it can be modified via Arc::get_mut() once no commands sample it.
+    pub fn drop_mips(&mut self) {
+        if self.count <= 1 {
+            return;
+        }
+        self.texels.truncate(self.mips[1].offset as usize);
+        self.texels.shrink_to_fit();
+        self.mips[1..].fill(Mip::default());
+        self.count = 1;
+    }
+
+    fn new_rendered(size: u32, mip_generation: MipGeneration, copy: impl FnOnce(&mut [u8])) -> Self {
+        let count: usize = if mip_generation == MipGeneration::None {
+            1
+        } else {
+            full_mip_count(size)
+        };
+        let (mips, total_size) = mip_layout(size, 4, count);
+        let mut texture =
+            Texture { mips, count: count as u32, format: TextureFormat::RGBA, texels: vec![0u8; total_size] };
+        texture.update_rendered(mip_generation, copy);
+        texture
+    }
+
+    fn validate_update(&self, width: u16, height: u16) -> Result<(), TextureError> {
+        let level0: Mip = self.mips[0];
+        if self.format != TextureFormat::RGBA || level0.width != width || level0.height != height {
+            return Err(TextureError::UpdateMismatch { width: width as u32, height: height as u32 });
+        }
+        Ok(())
+    }
+
+    // Copies the colors into level 0 and regenerates the rest of the levels the texture has.
+    fn update_rendered(&mut self, mip_generation: MipGeneration, copy: impl FnOnce(&mut [u8])) {
+        let level0: Mip = self.mips[0];
+        let len: usize = level0.width as usize * level0.height as usize * 4;
+        copy(&mut self.texels[level0.offset as usize..level0.offset as usize + len]);
+        let mip_generation = if mip_generation == MipGeneration::None {
+            MipGeneration::Box
+        } else {
+            mip_generation
+        };
+        generate_mips::<4>(&mut self.texels, &self.mips, 1..self.count as usize, mip_generation);
+    }
+
+    fn new_uncompressed(source: &TextureSource, mip_generation: MipGeneration) -> Self {
         let bpp = bytes_per_pixel(source.format);
         match bpp {
-            1 => Self::new_impl::<1>(source),
-            2 => Self::new_impl::<2>(source),
-            3 => Self::new_impl::<3>(source),
-            4 => Self::new_impl::<4>(source),
+            1 => Self::new_impl::<1>(source, mip_generation),
+            2 => Self::new_impl::<2>(source, mip_generation),
+            3 => Self::new_impl::<3>(source, mip_generation),
+            4 => Self::new_impl::<4>(source, mip_generation),
             _ => unreachable!(),
         }
     }
 
-    fn new_impl<const BPP: usize>(source: &TextureSource) -> Arc<Self> {
-        assert!(source.height > 0);
-        assert!(source.width > 0);
-        assert!(source.height.is_power_of_two());
-        assert!(source.width.is_power_of_two());
-        assert_eq!(source.height, source.width);
-        assert_eq!(source.texels.len(), source.height as usize * source.width as usize * BPP);
+    // Converts the source levels into RGBA with the keyed texels made transparent and creates the texture from them.
+    fn new_color_keyed(source: &TextureSource, key: ColorKey, mip_generation: MipGeneration) -> Self {
+        let bpp = bytes_per_pixel(source.format);
+        let levels: Vec<Vec<u8>> = std::iter::once(source.texels)
+            .chain(source.mips.iter().copied())
+            .map(|texels| {
+                let mut rgba: Vec<u8> = Vec::with_capacity(texels.len() / bpp * 4);
+                for texel in texels.chunks_exact(bpp) {
+                    let (r, g, b, a) = match bpp {
+                        1 => (texel[0], texel[0], texel[0], 255),
+                        3 => (texel[0], texel[1], texel[2], 255),
+                        _ => (texel[0], texel[1], texel[2], texel[3]),
+                    };
+                    rgba.extend_from_slice(&if key.matches(r, g, b) {
+                        [0, 0, 0, 0]
+                    } else {
+                        [r, g, b, a]
+                    });
+                }
+                rgba
+            })
+            .collect();
+        let mips: Vec<&[u8]> = levels[1..].iter().map(|level| level.as_slice()).collect();
+        let mut texture = Self::new_uncompressed(
+            &TextureSource {
+                texels: &levels[0],
+                width: source.width,
+                height: source.height,
+                format: TextureFormat::RGBA,
+                mips: &mips,
+                color_key: None,
+            },
+            mip_generation,
+        );
+        if key.bleed {
+            for level in 0..texture.count as usize {
+                texture.bleed_into_transparent(level);
+            }
+        }
+        texture
+    }
 
-        // Compute mip count
-        let mut dim = source.width;
-        let mut mip_count = 1;
-        while dim > 1 && mip_count < MAX_MIP_LEVELS {
-            dim >>= 1;
-            mip_count += 1;
+    // Sets the color of the fully transparent RGBA texels to the average un-premultiplied color of their visible
+    // neighbours, if there are any.
+    fn bleed_into_transparent(&mut self, level: usize) {
+        debug_assert!(self.format == TextureFormat::RGBA);
+        let mip: Mip = self.mips[level];
+        let (width, height) = (mip.width as usize, mip.height as usize);
+        let offset = mip.offset as usize;
+        let source: Vec<u8> = self.texels[offset..offset + width * height * 4].to_vec();
+        for y in 0..height {
+            for x in 0..width {
+                if source[(y * width + x) * 4 + 3] != 0 {
+                    continue;
+                }
+                let mut sum: [u32; 3] = [0; 3];
+                let mut count: u32 = 0;
+                for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                        let neighbour: &[u8] = &source[(ny * width + nx) * 4..(ny * width + nx) * 4 + 4];
+                        let a = neighbour[3] as u32;
+                        if a == 0 {
+                            continue;
+                        }
+                        for (sum, &c) in sum.iter_mut().zip(&neighbour[..3]) {
+                            *sum += (c as u32 * 255 + a / 2) / a;
+                        }
+                        count += 1;
+                    }
+                }
+                if count == 0 {
+                    continue;
+                }
+                let texel: &mut [u8] = &mut self.texels[offset + (y * width + x) * 4..];
+                for (c, sum) in texel[..3].iter_mut().zip(sum) {
+                    *c = ((sum + count / 2) / count).min(255) as u8;
+                }
+            }
         }
+    }
+
+    fn new_impl<const BPP: usize>(source: &TextureSource, mip_generation: MipGeneration) -> Self {
+        debug_assert!(source.validate().is_ok());
+
+        // Compute mip count
+        let mip_count = if mip_generation == MipGeneration::None {
+            1 + source.mips.len()
+        } else {
+            full_mip_count(source.width)
+        };
 
         // Compute total memory required and mip infos
-        let mut total_size = 0 as usize;
-        let mut mips: [Mip; MAX_MIP_LEVELS] = Default::default();
-        dim = source.width;
-        for level in 0..mip_count {
-            let mip_size = ((dim * dim) as usize * BPP + 3) & !3;
-            mips[level] = Mip { width: dim as u16, height: dim as u16, offset: total_size as u32 };
-            total_size += mip_size;
-            dim >>= 1;
-        }
+        let (mips, total_size) = mip_layout(source.width, BPP, mip_count);
 
         // Allocate texels
         let mut texel_data = vec![0u8; total_size];
 
-        // Copy base level
-        texel_data[..source.texels.len()].copy_from_slice(&source.texels);
-
-        // Premultiply alpha
-        if source.format == TextureFormat::RGBA {
-            for i in 0..source.height as usize * source.width as usize {
-                let a = texel_data[i * 4 + 3] as u32;
-                texel_data[i * 4 + 0] = (texel_data[i * 4 + 0] as u32 * a / 255) as u8;
-                texel_data[i * 4 + 1] = (texel_data[i * 4 + 1] as u32 * a / 255) as u8;
-                texel_data[i * 4 + 2] = (texel_data[i * 4 + 2] as u32 * a / 255) as u8;
-            }
-        }
-
-        // Generate mip levels
-        for level in 1..mip_count {
-            let src_mip: Mip = mips[level - 1];
-            let dst_mip: Mip = mips[level];
-
-            // Split the entire buffer into two parts to keep the borrow checker happy
-            let (texel_data_before, texel_data_after): (&mut [u8], &mut [u8]) = texel_data.split_at_mut(dst_mip.offset as usize);
-
-            // Texels to copy from
-            let src: &[u8] = &texel_data_before[src_mip.offset as usize
-                ..src_mip.offset as usize + src_mip.width as usize * src_mip.height as usize * BPP];
-
-            // Texels to write to
-            let dst: &mut [u8] = &mut texel_data_after[0..dst_mip.width as usize * dst_mip.height as usize * BPP];
-
-            let src_stride = src_mip.width as usize * BPP;
-            for y in 0..dst_mip.height as usize {
-                let src_row1: *const u8 = unsafe { src.as_ptr().add(src_stride * y * 2) };
-                let src_row2: *const u8 = unsafe { src.as_ptr().add(src_stride * (y * 2 + 1)) };
-                let dst_row: *mut u8 = unsafe { dst.as_mut_ptr().add(dst_mip.width as usize * BPP * y) };
-                for idx in 0..dst_mip.width as usize {
-                    for i in 0..BPP {
-                        let sum: u32 = 2u32 +
-                            unsafe { *src_row1.add(idx * 2 * BPP + i) } as u32 +
-                            unsafe { *src_row1.add(((idx * 2) + 1) * BPP + i) } as u32 +
-                            unsafe { *src_row2.add(idx * 2 * BPP + i) } as u32 +
-                            unsafe { *src_row2.add(((idx * 2) + 1) * BPP + i) } as u32;
-                        unsafe { *dst_row.add(idx * BPP + i) = (sum / 4) as u8 };
+        // Copy base level and the explicitly provided ones
+        let provided_count = 1 + source.mips.len();
+        for (level, texels) in std::iter::once(source.texels)
+            .chain(source.mips.iter().copied())
+            .enumerate()
+        {
+            let offset = mips[level].offset as usize;
+            let level_texels: &mut [u8] = &mut texel_data[offset..offset + texels.len()];
+            level_texels.copy_from_slice(texels);
+
+            // Premultiply alpha
+            if source.format == TextureFormat::RGBA {
+                let size: usize = mips[level].width as usize;
+                for_each_row(level_texels, size * 4, |_, row| {
+                    for texel in row.chunks_exact_mut(4) {
+                        let a = texel[3] as u32;
+                        texel[0] = (texel[0] as u32 * a / 255) as u8;
+                        texel[1] = (texel[1] as u32 * a / 255) as u8;
+                        texel[2] = (texel[2] as u32 * a / 255) as u8;
                     }
+                });
+            }
+        }
+
+        // Generate the rest of mip levels
+        generate_mips::<BPP>(&mut texel_data, &mips, provided_count..mip_count, mip_generation);
+
+        Texture { mips, count: mip_count as u32, format: source.format, texels: texel_data }
+    }
+
+    // Compresses every mip level of an RGB or RGBA texture into BC3 blocks.
+    fn compressed(&self) -> Self {
+        let bpp = bytes_per_pixel(self.format);
+        let mut mips: [Mip; MAX_MIP_LEVELS] = Default::default();
+        let mut texels: Vec<u8> = Vec::new();
+        for (level, mip) in self.mips[..self.count as usize].iter().enumerate() {
+            let (width, height) = (mip.width as usize, mip.height as usize);
+            mips[level] = Mip { width: mip.width, height: mip.height, offset: texels.len() as u32 };
+            for block_y in (0..height).step_by(4) {
+                for block_x in (0..width).step_by(4) {
+                    // Levels smaller than a block are padded by repeating their texels
+                    let block_texels: [[u8; 4]; 16] = std::array::from_fn(|i| {
+                        let x = block_x + (i % 4) % width;
+                        let y = block_y + (i / 4) % height;
+                        let offset = mip.offset as usize + (y * width + x) * bpp;
+                        let texel = &self.texels[offset..offset + bpp];
+                        if bpp == 4 {
+                            [texel[0], texel[1], texel[2], texel[3]]
+                        } else {
+                            [texel[0], texel[1], texel[2], 255]
+                        }
+                    });
+                    texels.extend_from_slice(&compress_bc3_block(&block_texels));
                 }
             }
+            debug_assert_eq!(texels.len() - mips[level].offset as usize, bc3_mip_size(width, height));
+        }
+        Texture { mips, count: self.count, format: TextureFormat::BC3, texels }
+    }
+}
+
+impl TextureRegion {
+    pub fn new(u0: f32, v0: f32, u1: f32, v1: f32) -> Self {
+        Self { u0, v0, u1, v1, clamp: false }
+    }
+
+    // Returns the region of a cell in an atlas uniformly divided into columns x rows cells.
+    pub fn from_grid(columns: u32, rows: u32, column: u32, row: u32) -> Self {
+        assert!(column < columns && row < rows);
+        let du = 1.0 / columns as f32;
+        let dv = 1.0 / rows as f32;
+        Self::new(column as f32 * du, row as f32 * dv, (column + 1) as f32 * du, (row + 1) as f32 * dv)
+    }
+
+    // The size of the region in texels at the base mip level.
+    pub fn size_in_texels(&self, texture: &Texture) -> (f32, f32) {
+        (
+            (self.u1 - self.u0).abs() * texture.mips[0].width as f32,
+            (self.v1 - self.v0).abs() * texture.mips[0].height as f32,
+        )
+    }
+
+    // The highest level of detail at which the region still covers at least a single texel.
+    // Mips beyond this level mix the region with its neighbours and must not be sampled.
+    pub fn max_lod(&self, texture: &Texture) -> f32 {
+        let (width, height) = self.size_in_texels(texture);
+        width.min(height).max(1.0).log2().floor()
+    }
+}
+
+impl TextureSource<'_> {
+    // Checks that the texture can be created from this source.
+    pub fn validate(&self) -> Result<(), TextureError> {
+        if self.format == TextureFormat::BC3 {
+            return Err(TextureError::UnsupportedSourceFormat(self.format));
+        }
+        if self.width == 0 || self.width != self.height || !self.width.is_power_of_two() {
+            return Err(TextureError::UnsupportedSize { width: self.width, height: self.height });
+        }
+        let max_mips = full_mip_count(self.width) - 1;
+        if self.mips.len() > max_mips {
+            return Err(TextureError::TooManyMips { provided: self.mips.len(), max: max_mips });
+        }
+        let bpp = bytes_per_pixel(self.format);
+        for (level, texels) in std::iter::once(self.texels)
+            .chain(self.mips.iter().copied())
+            .enumerate()
+        {
+            let dim = (self.width >> level) as usize;
+            if texels.len() != dim * dim * bpp {
+                return Err(TextureError::TexelsSizeMismatch {
+                    level,
+                    expected: dim * dim * bpp,
+                    actual: texels.len(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// The number of mip levels down to 1x1 for a texture of the given power-of-two size.
+fn full_mip_count(size: u32) -> usize {
+    (size.trailing_zeros() as usize + 1).min(MAX_MIP_LEVELS)
+}
+
+// The rendered buffers become textures as they are, so they must have the textures' sizes.
+fn validate_rendered_size(width: u16, height: u16) -> Result<(), TextureError> {
+    if width == 0 || width != height || !width.is_power_of_two() {
+        return Err(TextureError::UnsupportedSize { width: width as u32, height: height as u32 });
+    }
+    Ok(())
+}
+
+// Detiles the colors into the rows of RGBA texels.
+fn copy_tiled_colors<const W: usize, const H: usize>(buffer: &TiledBuffer<u32, W, H>, texels: &mut [u8]) {
+    let width: usize = buffer.width() as usize;
+    let values: &[u32] = buffer.values();
+    for_each_row(texels, width * 4, |y, row| {
+        let (tile_y, row_in_tile) = (y / H, y % H);
+        for (tile_x, dst) in row.chunks_mut(W * 4).enumerate() {
+            let start: usize = (tile_y * buffer.tiles_x() as usize + tile_x) * W * H + row_in_tile * W;
+            dst.copy_from_slice(bytemuck::cast_slice(&values[start..start + dst.len() / 4]));
+        }
+    });
+}
+
+fn copy_flat_colors(buffer: &Buffer<u32>, texels: &mut [u8]) {
+    let (width, stride) = (buffer.width as usize, buffer.stride as usize);
+    for_each_row(texels, width * 4, |y, row| {
+        row.copy_from_slice(bytemuck::cast_slice(&buffer.elems[y * stride..y * stride + width]));
+    });
+}
+
+// The placement of the square mip levels of the given size in the texels and the total number of bytes they occupy.
+// Each level starts at a 4-byte boundary.
+fn mip_layout(size: u32, bpp: usize, count: usize) -> ([Mip; MAX_MIP_LEVELS], usize) {
+    let mut total_size = 0 as usize;
+    let mut mips: [Mip; MAX_MIP_LEVELS] = Default::default();
+    let mut dim = size;
+    for mip in mips.iter_mut().take(count) {
+        let mip_size = ((dim * dim) as usize * bpp + 3) & !3;
+        *mip = Mip { width: dim as u16, height: dim as u16, offset: total_size as u32 };
+        total_size += mip_size;
+        dim >>= 1;
+    }
+    (mips, total_size)
+}
+
+// Generates the mip levels in the range, each one from the previous, the rows of the large ones are filtered in
+// parallel.
+fn generate_mips<const BPP: usize>(
+    texels: &mut [u8],
+    mips: &[Mip; MAX_MIP_LEVELS],
+    levels: std::ops::Range<usize>,
+    mip_generation: MipGeneration,
+) {
+    for level in levels {
+        let src_mip: Mip = mips[level - 1];
+        let dst_mip: Mip = mips[level];
+
+        // Split the entire buffer into two parts to keep the borrow checker happy
+        let (texels_before, texels_after): (&mut [u8], &mut [u8]) = texels.split_at_mut(dst_mip.offset as usize);
+
+        // Texels to copy from
+        let src: &[u8] = &texels_before
+            [src_mip.offset as usize..src_mip.offset as usize + src_mip.width as usize * src_mip.height as usize * BPP];
+
+        // Texels to write to
+        let dst: &mut [u8] = &mut texels_after[0..dst_mip.width as usize * dst_mip.height as usize * BPP];
+
+        match mip_generation {
+            MipGeneration::Kaiser => downsample_kaiser::<BPP>(src, src_mip.width as usize, dst),
+            _ => downsample_box::<BPP>(src, src_mip.width as usize, dst),
         }
+    }
+}
 
-        Arc::new(Texture { mips, count: mip_count as u32, format: source.format, texels: texel_data })
+// The mip levels with at least this many rows are processed by the rayon pool, one task per row, the smaller ones on
+// the calling thread, where they are faster than the cost of spreading the work.
+const PARALLEL_MIP_MIN_ROWS: usize = 128;
+
+// Runs the function over the rows of the texels, in parallel when there are enough of them.
+// The function receives the row's index and its texels.
+fn for_each_row<T: Send>(texels: &mut [T], row_len: usize, f: impl Fn(usize, &mut [T]) + Send + Sync) {
+    if texels.len() / row_len >= PARALLEL_MIP_MIN_ROWS {
+        use rayon::prelude::*;
+        texels
+            .par_chunks_mut(row_len)
+            .enumerate()
+            .for_each(|(y, row)| f(y, row));
+    } else {
+        texels.chunks_mut(row_len).enumerate().for_each(|(y, row)| f(y, row));
     }
 }
 
+// Generates the next mip level by averaging each 2x2 block of texels.
+fn downsample_box<const BPP: usize>(src: &[u8], src_size: usize, dst: &mut [u8]) {
+    let dst_size = src_size / 2;
+    let src_stride = src_size * BPP;
+    for_each_row(dst, dst_size * BPP, |y, dst_row| {
+        let src_row1: &[u8] = &src[src_stride * y * 2..src_stride * (y * 2 + 1)];
+        let src_row2: &[u8] = &src[src_stride * (y * 2 + 1)..src_stride * (y * 2 + 2)];
+        for idx in 0..dst_size {
+            for i in 0..BPP {
+                let sum: u32 = 2u32
+                    + src_row1[idx * 2 * BPP + i] as u32
+                    + src_row1[(idx * 2 + 1) * BPP + i] as u32
+                    + src_row2[idx * 2 * BPP + i] as u32
+                    + src_row2[(idx * 2 + 1) * BPP + i] as u32;
+                dst_row[idx * BPP + i] = (sum / 4) as u8;
+            }
+        }
+    });
+}
+
+// Weights of the 8 source texels contributing to a destination texel when downsampling by 2x.
+// It's a sinc windowed by the Kaiser window with the radius of 2 destination texels, normalized to sum up to 1.
+fn kaiser_weights() -> [f32; 8] {
+    // Modified Bessel function of the first kind, order zero
+    fn bessel_i0(x: f32) -> f32 {
+        let mut sum: f32 = 1.0;
+        let mut term: f32 = 1.0;
+        for k in 1..16 {
+            let t: f32 = x / (2.0 * k as f32);
+            term *= t * t;
+            sum += term;
+        }
+        sum
+    }
+    const BETA: f32 = 4.0;
+    const RADIUS: f32 = 2.0;
+    let mut weights: [f32; 8] = [0.0; 8];
+    for (k, weight) in weights.iter_mut().enumerate() {
+        // Distance from the center of the destination texel, in destination texels
+        let t: f32 = (k as f32 - 3.5) / 2.0;
+        let sinc: f32 = (std::f32::consts::PI * t).sin() / (std::f32::consts::PI * t);
+        let r: f32 = t / RADIUS;
+        *weight = sinc * bessel_i0(BETA * (1.0 - r * r).sqrt()) / bessel_i0(BETA);
+    }
+    let sum: f32 = weights.iter().sum();
+    weights.map(|w| w / sum)
+}
+
+// Generates the next mip level with a separable Kaiser-windowed sinc filter, the texels are wrapped around the edges.
+fn downsample_kaiser<const BPP: usize>(src: &[u8], src_size: usize, dst: &mut [u8]) {
+    let weights: [f32; 8] = kaiser_weights();
+    let dst_size = src_size / 2;
+    let mask = src_size - 1;
+
+    // Filter horizontally: src_size rows of dst_size texels
+    let mut horizontal: Vec<f32> = vec![0.0; dst_size * src_size * BPP];
+    for_each_row(&mut horizontal, dst_size * BPP, |y, row| {
+        for x in 0..dst_size {
+            for i in 0..BPP {
+                let mut sum: f32 = 0.0;
+                for (k, weight) in weights.iter().enumerate() {
+                    let sx = (x * 2 + k).wrapping_sub(3) & mask;
+                    sum += src[(y * src_size + sx) * BPP + i] as f32 * weight;
+                }
+                row[x * BPP + i] = sum;
+            }
+        }
+    });
+
+    // Filter vertically into the destination
+    for_each_row(dst, dst_size * BPP, |y, row| {
+        for x in 0..dst_size {
+            for i in 0..BPP {
+                let mut sum: f32 = 0.0;
+                for (k, weight) in weights.iter().enumerate() {
+                    let sy = (y * 2 + k).wrapping_sub(3) & mask;
+                    sum += horizontal[(sy * dst_size + x) * BPP + i] * weight;
+                }
+                row[x * BPP + i] = (sum + 0.5).clamp(0.0, 255.0) as u8;
+            }
+            // The ringing must not break the premultiplied alpha
+            if BPP == 4 {
+                let texel: &mut [u8] = &mut row[x * 4..x * 4 + 4];
+                texel[0] = texel[0].min(texel[3]);
+                texel[1] = texel[1].min(texel[3]);
+                texel[2] = texel[2].min(texel[3]);
+            }
+        }
+    });
+}
+
 fn bytes_per_pixel(fmt: TextureFormat) -> usize {
     match fmt {
         TextureFormat::RGBA => 4,
         TextureFormat::RGB => 3,
         TextureFormat::Grayscale => 1,
+        TextureFormat::BC3 => unreachable!(),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    fn source(width: u32, height: u32, format: TextureFormat, texels: &[u8]) -> TextureSource<'_> {
+        TextureSource { texels, width, height, format, ..Default::default() }
+    }
+
     #[test]
     fn bake_grayscale_1x1() {
         let texel = [42u8];
-        let source = TextureSource { texels: &texel, width: 1, height: 1, format: TextureFormat::Grayscale };
+        let source = TextureSource {
+            texels: &texel,
+            width: 1,
+            height: 1,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        };
         let texture = Texture::new(&source);
         assert_eq!(texture.count, 1);
         assert_eq!(texture.mips[0].width, 1);
@@ -156,7 +767,8 @@ mod tests {
     #[test]
     fn bake_rgb_1x1() {
         let texel = [10u8, 20u8, 30u8];
-        let source = TextureSource { texels: &texel, width: 1, height: 1, format: TextureFormat::RGB };
+        let source =
+            TextureSource { texels: &texel, width: 1, height: 1, format: TextureFormat::RGB, ..Default::default() };
         let texture = Texture::new(&source);
         assert_eq!(texture.count, 1);
         assert_eq!(texture.mips[0].width, 1);
@@ -168,7 +780,13 @@ mod tests {
     #[test]
     fn bake_grayscale_2x2() {
         let texels = [10u8, 20u8, 30u8, 40u8];
-        let source = TextureSource { texels: &texels, width: 2, height: 2, format: TextureFormat::Grayscale };
+        let source = TextureSource {
+            texels: &texels,
+            width: 2,
+            height: 2,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        };
         let texture = Texture::new(&source);
         assert_eq!(texture.count, 2);
         assert_eq!(texture.mips[0].width, 2);
@@ -183,7 +801,8 @@ mod tests {
     #[test]
     fn bake_rgb_2x2() {
         let texels = [10u8, 20u8, 30u8, 40u8, 50u8, 60u8, 70u8, 80u8, 90u8, 100u8, 110u8, 120u8];
-        let source = TextureSource { texels: &texels, width: 2, height: 2, format: TextureFormat::RGB };
+        let source =
+            TextureSource { texels: &texels, width: 2, height: 2, format: TextureFormat::RGB, ..Default::default() };
         let texture = Texture::new(&source);
         assert_eq!(texture.count, 2);
         assert_eq!(texture.mips[0].width, 2);
@@ -204,7 +823,8 @@ mod tests {
     #[test]
     fn bake_rgb_4x4() {
         let texels: Vec<u8> = (0u8..48u8).collect();
-        let source = TextureSource { texels: &texels, width: 4, height: 4, format: TextureFormat::RGB };
+        let source =
+            TextureSource { texels: &texels, width: 4, height: 4, format: TextureFormat::RGB, ..Default::default() };
         let texture = Texture::new(&source);
         assert_eq!(texture.count, 3);
 
@@ -225,4 +845,349 @@ mod tests {
     }
 
     // TODO: tests for RGBA baking
+
+    #[test]
+    fn bake_compressed_rgba_8x8() {
+        let texels: Vec<u8> = (0..8 * 8)
+            .flat_map(|i| [i as u8 * 4, 255 - i as u8 * 4, 0u8, 255u8])
+            .collect();
+        let source =
+            TextureSource { texels: &texels, width: 8, height: 8, format: TextureFormat::RGBA, ..Default::default() };
+        let texture = Texture::new_with_options(&source, &TextureOptions { compress: true, ..Default::default() });
+        assert_eq!(texture.format, TextureFormat::BC3);
+        assert_eq!(texture.count, 4);
+        assert_eq!(texture.mips[0].width, 8);
+        assert_eq!(texture.mips[0].offset, 0);
+        assert_eq!(texture.mips[1].width, 4);
+        assert_eq!(texture.mips[1].offset, 64);
+        assert_eq!(texture.mips[2].width, 2);
+        assert_eq!(texture.mips[2].offset, 80);
+        assert_eq!(texture.mips[3].width, 1);
+        assert_eq!(texture.mips[3].offset, 96);
+        assert_eq!(texture.texels.len(), 112);
+    }
+
+    #[test]
+    fn grayscale_is_not_compressed() {
+        let texels = [1u8, 2u8, 3u8, 4u8];
+        let source = TextureSource {
+            texels: &texels,
+            width: 2,
+            height: 2,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        };
+        let texture = Texture::new_with_options(&source, &TextureOptions { compress: true, ..Default::default() });
+        assert_eq!(texture.format, TextureFormat::Grayscale);
+    }
+
+    #[test]
+    fn region_from_grid() {
+        assert_eq!(TextureRegion::from_grid(1, 1, 0, 0), TextureRegion::new(0.0, 0.0, 1.0, 1.0));
+        assert_eq!(TextureRegion::from_grid(2, 2, 1, 0), TextureRegion::new(0.5, 0.0, 1.0, 0.5));
+        assert_eq!(TextureRegion::from_grid(4, 2, 3, 1), TextureRegion::new(0.75, 0.5, 1.0, 1.0));
+    }
+
+    #[test]
+    fn region_max_lod() {
+        let texels: Vec<u8> = vec![0u8; 64 * 64];
+        let texture = Texture::new(&TextureSource {
+            texels: &texels,
+            width: 64,
+            height: 64,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        });
+        assert_eq!(TextureRegion::from_grid(1, 1, 0, 0).max_lod(&texture), 6.0);
+        assert_eq!(TextureRegion::from_grid(2, 2, 0, 0).max_lod(&texture), 5.0);
+        assert_eq!(TextureRegion::from_grid(8, 4, 0, 0).max_lod(&texture), 3.0);
+        assert_eq!(TextureRegion::new(0.0, 0.0, 3.0 / 64.0, 1.0).max_lod(&texture), 1.0);
+        assert_eq!(TextureRegion::from_grid(64, 64, 0, 0).max_lod(&texture), 0.0);
+    }
+
+    #[test]
+    fn explicit_mips() {
+        let texels: Vec<u8> = vec![10u8; 16];
+        let mip1: Vec<u8> = vec![20u8; 4];
+        let mip2: Vec<u8> = vec![30u8; 1];
+        let source = TextureSource {
+            texels: &texels,
+            width: 4,
+            height: 4,
+            format: TextureFormat::Grayscale,
+            mips: &[&mip1, &mip2],
+            ..Default::default()
+        };
+        let texture = Texture::new(&source);
+        assert_eq!(texture.count, 3);
+        assert_eq!(texture.texels[16..20], [20u8, 20u8, 20u8, 20u8]);
+        assert_eq!(texture.texels[20], 30u8);
+    }
+
+    #[test]
+    fn explicit_mips_are_completed_by_generation() {
+        let texels: Vec<u8> = vec![10u8; 16];
+        let mip1: Vec<u8> = vec![20u8, 40u8, 60u8, 80u8];
+        let source = TextureSource { mips: &[&mip1], ..source(4, 4, TextureFormat::Grayscale, &texels) };
+        let texture = Texture::new(&source);
+        assert_eq!(texture.count, 3);
+        assert_eq!(texture.texels[16..20], [20u8, 40u8, 60u8, 80u8]);
+        assert_eq!(texture.texels[20], 50u8);
+
+        let texture = Texture::new_with_options(
+            &source,
+            &TextureOptions { mip_generation: MipGeneration::None, ..Default::default() },
+        );
+        assert_eq!(texture.count, 2);
+        assert_eq!(texture.texels.len(), 20);
+    }
+
+    #[test]
+    fn no_mip_generation() {
+        let texels: Vec<u8> = vec![0u8; 64 * 64 * 3];
+        let source =
+            TextureSource { texels: &texels, width: 64, height: 64, format: TextureFormat::RGB, ..Default::default() };
+        let texture = Texture::new_with_options(
+            &source,
+            &TextureOptions { mip_generation: MipGeneration::None, ..Default::default() },
+        );
+        assert_eq!(texture.count, 1);
+        assert_eq!(texture.texels.len(), 64 * 64 * 3);
+    }
+
+    #[test]
+    fn dropped_mips() {
+        let texels: Vec<u8> = (0..64 * 64 * 3).map(|i| (i % 251) as u8).collect();
+        let source =
+            TextureSource { texels: &texels, width: 64, height: 64, format: TextureFormat::RGB, ..Default::default() };
+        let mut texture: Arc<Texture> = Texture::new(&source);
+        assert_eq!(texture.count, 7);
+        let texel = |texture: &Arc<Texture>, lod: f32| {
+            Sampler::new(texture, SamplerFilter::Trilinear, lod).sample(0.3, 0.6)
+        };
+        let level0: RGBA = texel(&texture, 0.0);
+        assert_ne!(texel(&texture, 3.0), level0);
+
+        // Same as not generating the mips in the first place, the memory of the levels below is released
+        Arc::get_mut(&mut texture).unwrap().drop_mips();
+        assert_eq!(texture.count, 1);
+        assert_eq!(texture.texels, texels);
+        assert_eq!(texture.texels.capacity(), texels.len());
+        assert_eq!(texel(&texture, 3.0), level0);
+        Arc::get_mut(&mut texture).unwrap().drop_mips();
+        assert_eq!(texture.count, 1);
+
+        // The compressed level 0 is kept as it is
+        let mut compressed =
+            Texture::new_with_options(&source, &TextureOptions { compress: true, ..Default::default() });
+        let level0: Vec<u8> = compressed.texels[..bc3_mip_size(64, 64)].to_vec();
+        Arc::get_mut(&mut compressed).unwrap().drop_mips();
+        assert_eq!((compressed.count, compressed.format), (1, TextureFormat::BC3));
+        assert_eq!(compressed.texels, level0);
+    }
+
+    #[test]
+    fn large_mips_generated_in_parallel() {
+        // Big enough for the top levels to be filtered by the rayon pool, the levels must match a plain 2x2 average
+        const SIZE: usize = 512;
+        let texels: Vec<u8> = (0..SIZE * SIZE * 3).map(|i| ((i * 7 + i / 1536) % 251) as u8).collect();
+        let source = TextureSource {
+            texels: &texels,
+            width: SIZE as u32,
+            height: SIZE as u32,
+            format: TextureFormat::RGB,
+            ..Default::default()
+        };
+        let texture = Texture::new(&source);
+        assert_eq!(texture.count, 10);
+        let mut expected: Vec<u8> = texels;
+        for level in 1..texture.count as usize {
+            let src_size: usize = SIZE >> (level - 1);
+            let dst_size: usize = src_size / 2;
+            let at = |x: usize, y: usize, i: usize| expected[(y * src_size + x) * 3 + i] as u32;
+            let mut next: Vec<u8> = vec![0u8; dst_size * dst_size * 3];
+            for y in 0..dst_size {
+                for x in 0..dst_size {
+                    for i in 0..3 {
+                        let sum: u32 = 2
+                            + at(x * 2, y * 2, i)
+                            + at(x * 2 + 1, y * 2, i)
+                            + at(x * 2, y * 2 + 1, i)
+                            + at(x * 2 + 1, y * 2 + 1, i);
+                        next[(y * dst_size + x) * 3 + i] = (sum / 4) as u8;
+                    }
+                }
+            }
+            let offset: usize = texture.mips[level].offset as usize;
+            assert_eq!(&texture.texels[offset..offset + next.len()], &next[..], "level {}", level);
+            expected = next;
+        }
+    }
+
+    #[test]
+    fn kaiser_mip_generation() {
+        // A uniform texture stays uniform
+        let texels: Vec<u8> = vec![77u8; 8 * 8];
+        let source = TextureSource {
+            texels: &texels,
+            width: 8,
+            height: 8,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        };
+        let texture = Texture::new_with_options(
+            &source,
+            &TextureOptions { mip_generation: MipGeneration::Kaiser, ..Default::default() },
+        );
+        assert_eq!(texture.count, 4);
+        assert!(texture.texels[64..64 + 16].iter().all(|&t| t == 77));
+        assert_eq!(texture.texels[texture.mips[3].offset as usize], 77);
+
+        // A checkerboard of 2x2 blocks keeps more contrast than the box filter gives
+        let texels: Vec<u8> = (0..8 * 8)
+            .map(|i| if ((i % 8) / 2 + (i / 16)) % 2 == 0 { 255u8 } else { 0u8 })
+            .collect();
+        let source = TextureSource {
+            texels: &texels,
+            width: 8,
+            height: 8,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        };
+        let kaiser = Texture::new_with_options(
+            &source,
+            &TextureOptions { mip_generation: MipGeneration::Kaiser, ..Default::default() },
+        );
+        let boxed = Texture::new(&source);
+        let contrast = |t: &Texture| {
+            let mip1 = &t.texels[64..64 + 16];
+            *mip1.iter().max().unwrap() as i32 - *mip1.iter().min().unwrap() as i32
+        };
+        assert!(contrast(&kaiser) > 0);
+        assert!(contrast(&kaiser) <= 255);
+        assert_eq!(contrast(&boxed), 255);
+    }
+
+    #[test]
+    fn validation_errors() {
+        let texels: Vec<u8> = vec![0u8; 64];
+        let texels: &[u8] = &texels;
+        assert_eq!(
+            Texture::try_new(&source(4, 4, TextureFormat::BC3, texels)).unwrap_err(),
+            TextureError::UnsupportedSourceFormat(TextureFormat::BC3)
+        );
+        assert_eq!(
+            Texture::try_new(&source(0, 0, TextureFormat::Grayscale, texels)).unwrap_err(),
+            TextureError::UnsupportedSize { width: 0, height: 0 }
+        );
+        assert_eq!(
+            Texture::try_new(&source(8, 4, TextureFormat::Grayscale, texels)).unwrap_err(),
+            TextureError::UnsupportedSize { width: 8, height: 4 }
+        );
+        assert_eq!(
+            Texture::try_new(&source(6, 6, TextureFormat::Grayscale, texels)).unwrap_err(),
+            TextureError::UnsupportedSize { width: 6, height: 6 }
+        );
+        assert_eq!(
+            Texture::try_new(&source(4, 4, TextureFormat::RGB, texels)).unwrap_err(),
+            TextureError::TexelsSizeMismatch { level: 0, expected: 48, actual: 64 }
+        );
+        let mip1: Vec<u8> = vec![0u8; 8];
+        assert_eq!(
+            Texture::try_new(&TextureSource { mips: &[&mip1], ..source(8, 8, TextureFormat::Grayscale, texels) })
+                .unwrap_err(),
+            TextureError::TexelsSizeMismatch { level: 1, expected: 16, actual: 8 }
+        );
+        let mip: Vec<u8> = vec![0u8; 1];
+        assert_eq!(
+            Texture::try_new(&TextureSource {
+                mips: &[&mip, &mip],
+                ..source(2, 2, TextureFormat::Grayscale, &texels[..4])
+            })
+            .unwrap_err(),
+            TextureError::TooManyMips { provided: 2, max: 1 }
+        );
+        assert!(Texture::try_new(&source(8, 8, TextureFormat::Grayscale, texels)).is_ok());
+    }
+
+    #[test]
+    fn color_key_makes_texels_transparent() {
+        // A red texel in the top-left corner over the magenta background
+        let mut texels: Vec<u8> = [255u8, 0, 255].repeat(4);
+        texels[..3].copy_from_slice(&[255, 0, 0]);
+        let key = ColorKey::new(RGBA::new(255, 0, 255, 255));
+        let texture =
+            Texture::new(&TextureSource { color_key: Some(key), ..source(2, 2, TextureFormat::RGB, &texels) });
+        assert_eq!(texture.format, TextureFormat::RGBA);
+        assert_eq!(texture.count, 2);
+        assert_eq!(texture.texels[..8], [255, 0, 0, 255, 0, 0, 0, 0]);
+        // The keyed texels don't tint the mips
+        assert_eq!(texture.texels[16..20], [64, 0, 0, 64]);
+
+        let texture = Texture::new(&TextureSource {
+            color_key: Some(ColorKey { bleed: true, ..key }),
+            ..source(2, 2, TextureFormat::RGB, &texels)
+        });
+        assert_eq!(texture.texels[..8], [255, 0, 0, 255, 255, 0, 0, 0]);
+        assert_eq!(texture.texels[16..20], [64, 0, 0, 64]);
+    }
+
+    #[test]
+    fn color_key_ignores_alpha_and_other_colors() {
+        let texels: [u8; 16] = [255, 0, 255, 128, 255, 0, 254, 255, 10, 10, 10, 10, 255, 0, 255, 255];
+        let texture = Texture::new_with_options(
+            &TextureSource {
+                color_key: Some(ColorKey::new(RGBA::new(255, 0, 255, 0))),
+                ..source(2, 2, TextureFormat::RGBA, &texels)
+            },
+            &TextureOptions { mip_generation: MipGeneration::None, ..Default::default() },
+        );
+        assert_eq!(texture.texels, [0, 0, 0, 0, 255, 0, 254, 255, 0, 0, 0, 10, 0, 0, 0, 0]);
+
+        // Grayscale texels are keyed by gray colors
+        let texture = Texture::new(&TextureSource {
+            color_key: Some(ColorKey::new(RGBA::new(0, 0, 0, 255))),
+            ..source(1, 1, TextureFormat::Grayscale, &[0u8])
+        });
+        assert_eq!((texture.format, &texture.texels[..4]), (TextureFormat::RGBA, &[0u8, 0, 0, 0][..]));
+    }
+
+    #[test]
+    fn rendered_color_buffer_as_texture() {
+        // A 16x16 buffer of 8x8 tiles, each pixel's red and green are its coordinates
+        let mut tiled = TiledBuffer::<u32, 8, 8>::new(16, 16);
+        for y in 0..16 {
+            for x in 0..16 {
+                *tiled.at_mut(x, y) = RGBA::new(x as u8 * 16, y as u8 * 16, 255, 255).to_u32();
+            }
+        }
+        let texture = Texture::from_color_buffer(&tiled, MipGeneration::Box).unwrap();
+        assert_eq!((texture.format, texture.count), (TextureFormat::RGBA, 5));
+        assert_eq!(&texture.texels[(3 * 16 + 9) * 4..(3 * 16 + 10) * 4], &[144, 48, 255, 255]);
+        // The first 2x2 block is averaged into the first texel of level 1
+        let mip1: usize = texture.mips[1].offset as usize;
+        assert_eq!(&texture.texels[mip1..mip1 + 4], &[8, 8, 255, 255]);
+
+        // The flat buffer gives the same texture
+        let flat: Buffer<u32> = tiled.as_flat_buffer();
+        let from_flat = Texture::from_flat_color_buffer(&flat, MipGeneration::Box).unwrap();
+        assert_eq!(from_flat.texels, texture.texels);
+
+        // The next frame replaces the texels in place
+        let mut texture: Texture = Arc::into_inner(texture).unwrap();
+        let pointer: *const u8 = texture.texels.as_ptr();
+        tiled.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        texture.update_from_color_buffer(&tiled, MipGeneration::Box).unwrap();
+        assert_eq!(texture.texels.as_ptr(), pointer);
+        assert!(texture.texels.chunks(4).all(|texel| texel == [0, 0, 0, 255]));
+
+        assert_eq!(
+            texture.update_from_flat_color_buffer(&Buffer::new(8, 8), MipGeneration::Box).unwrap_err(),
+            TextureError::UpdateMismatch { width: 8, height: 8 }
+        );
+        assert_eq!(
+            Texture::from_color_buffer(&TiledBuffer::<u32, 8, 8>::new(16, 8), MipGeneration::Box).unwrap_err(),
+            TextureError::UnsupportedSize { width: 16, height: 8 }
+        );
+    }
 }
@@ -0,0 +1,235 @@
+use super::*;
+
+// Block compression of RGBA texels, the layout follows BC3 (aka DXT5):
+// Each 4x4 block takes 16 bytes, i.e. 1 byte per texel:
+// [0]: alpha0, [1]: alpha1, [2..8]: 16 3-bit alpha indices,
+// [8..10]: color0 (RGB565), [10..12]: color1 (RGB565), [12..16]: 16 2-bit color indices.
+// Texels are enumerated row-major within the block, the first texel takes the least significant bits.
+pub const BC3_BLOCK_SIZE: usize = 16;
+
+// Number of bytes required to store a w x h mip level, levels smaller than 4x4 still occupy a full block.
+pub fn bc3_mip_size(width: usize, height: usize) -> usize {
+    width.div_ceil(4) * height.div_ceil(4) * BC3_BLOCK_SIZE
+}
+
+// Compresses a 4x4 block of RGBA texels.
+pub fn compress_bc3_block(texels: &[[u8; 4]; 16]) -> [u8; BC3_BLOCK_SIZE] {
+    let mut block = [0u8; BC3_BLOCK_SIZE];
+
+    // Alpha: use the full range of the block, with alpha0 > alpha1 to select the 8-values mode.
+    let alpha_max: u8 = texels.iter().map(|t| t[3]).max().unwrap();
+    let alpha_min: u8 = texels.iter().map(|t| t[3]).min().unwrap();
+    block[0] = alpha_max;
+    block[1] = alpha_min;
+    if alpha_max > alpha_min {
+        let alphas: [u8; 8] = bc3_alphas(alpha_max, alpha_min);
+        let mut bits: u64 = 0;
+        for (i, texel) in texels.iter().enumerate() {
+            let index: usize = closest(&alphas, |a| (*a as i32 - texel[3] as i32).abs());
+            bits |= (index as u64) << (3 * i);
+        }
+        block[2..8].copy_from_slice(&bits.to_le_bytes()[0..6]);
+    }
+
+    // Color: pick the endpoints as the extreme texels along the principal axis of the block's colors.
+    let axis: [f32; 3] = principal_axis(texels);
+    let projection = |t: &[u8; 4]| t[0] as f32 * axis[0] + t[1] as f32 * axis[1] + t[2] as f32 * axis[2];
+    let mut lo: &[u8; 4] = &texels[0];
+    let mut hi: &[u8; 4] = &texels[0];
+    for texel in texels.iter() {
+        if projection(texel) < projection(lo) {
+            lo = texel;
+        }
+        if projection(texel) > projection(hi) {
+            hi = texel;
+        }
+    }
+    let color0: u16 = to_rgb565(hi);
+    let color1: u16 = to_rgb565(lo);
+    block[8..10].copy_from_slice(&color0.to_le_bytes());
+    block[10..12].copy_from_slice(&color1.to_le_bytes());
+    let colors: [[u8; 3]; 4] = bc3_colors(color0, color1);
+    let mut bits: u32 = 0;
+    for (i, texel) in texels.iter().enumerate() {
+        let index: usize = closest(&colors, |c| {
+            let dr: i32 = c[0] as i32 - texel[0] as i32;
+            let dg: i32 = c[1] as i32 - texel[1] as i32;
+            let db: i32 = c[2] as i32 - texel[2] as i32;
+            dr * dr + dg * dg + db * db
+        });
+        bits |= (index as u32) << (2 * i);
+    }
+    block[12..16].copy_from_slice(&bits.to_le_bytes());
+    block
+}
+
+// Decodes a single texel of a compressed block, index is y * 4 + x.
+#[inline(always)]
+pub fn decode_bc3_texel(block: &[u8; BC3_BLOCK_SIZE], index: u32) -> RGBA {
+    let alpha0: u32 = block[0] as u32;
+    let alpha1: u32 = block[1] as u32;
+    let alpha_bits: u64 = u64::from_le_bytes([block[2], block[3], block[4], block[5], block[6], block[7], 0, 0]);
+    let alpha_index: u32 = ((alpha_bits >> (3 * index)) & 7) as u32;
+    let alpha: u32 = match alpha_index {
+        0 => alpha0,
+        1 => alpha1,
+        i if alpha0 > alpha1 => ((8 - i) * alpha0 + (i - 1) * alpha1) / 7,
+        6 => 0,
+        7 => 255,
+        i => ((6 - i) * alpha0 + (i - 1) * alpha1) / 5,
+    };
+
+    let color0: u32 = u16::from_le_bytes([block[8], block[9]]) as u32;
+    let color1: u32 = u16::from_le_bytes([block[10], block[11]]) as u32;
+    let color_bits: u32 = u32::from_le_bytes([block[12], block[13], block[14], block[15]]);
+    let color_index: u32 = (color_bits >> (2 * index)) & 3;
+    let (weight0, weight1): (u32, u32) = match color_index {
+        0 => (3, 0),
+        1 => (0, 3),
+        2 => (2, 1),
+        _ => (1, 2),
+    };
+    let r: u32 = (expand5(color0 >> 11) * weight0 + expand5(color1 >> 11) * weight1) / 3;
+    let g: u32 = (expand6((color0 >> 5) & 63) * weight0 + expand6((color1 >> 5) & 63) * weight1) / 3;
+    let b: u32 = (expand5(color0 & 31) * weight0 + expand5(color1 & 31) * weight1) / 3;
+    RGBA::new(r as u8, g as u8, b as u8, alpha as u8)
+}
+
+fn bc3_alphas(alpha0: u8, alpha1: u8) -> [u8; 8] {
+    let mut alphas = [0u8; 8];
+    for (i, alpha) in alphas.iter_mut().enumerate() {
+        let block: [u8; BC3_BLOCK_SIZE] = [alpha0, alpha1, (i as u8) & 7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        *alpha = decode_bc3_texel(&block, 0).a;
+    }
+    alphas
+}
+
+fn bc3_colors(color0: u16, color1: u16) -> [[u8; 3]; 4] {
+    let mut colors = [[0u8; 3]; 4];
+    for (i, color) in colors.iter_mut().enumerate() {
+        let mut block = [0u8; BC3_BLOCK_SIZE];
+        block[8..10].copy_from_slice(&color0.to_le_bytes());
+        block[10..12].copy_from_slice(&color1.to_le_bytes());
+        block[12] = i as u8;
+        let texel: RGBA = decode_bc3_texel(&block, 0);
+        *color = [texel.r, texel.g, texel.b];
+    }
+    colors
+}
+
+fn closest<T>(candidates: &[T], distance: impl Fn(&T) -> i32) -> usize {
+    let mut best: usize = 0;
+    for i in 1..candidates.len() {
+        if distance(&candidates[i]) < distance(&candidates[best]) {
+            best = i;
+        }
+    }
+    best
+}
+
+// Approximates the principal axis of the colors via a few power iterations over their covariance matrix.
+fn principal_axis(texels: &[[u8; 4]; 16]) -> [f32; 3] {
+    let mut mean = [0.0f32; 3];
+    for texel in texels.iter() {
+        for c in 0..3 {
+            mean[c] += texel[c] as f32 / 16.0;
+        }
+    }
+    let mut covariance = [[0.0f32; 3]; 3];
+    for texel in texels.iter() {
+        let d: [f32; 3] = [texel[0] as f32 - mean[0], texel[1] as f32 - mean[1], texel[2] as f32 - mean[2]];
+        for i in 0..3 {
+            for j in 0..3 {
+                covariance[i][j] += d[i] * d[j];
+            }
+        }
+    }
+    let mut axis = [1.0f32, 1.0, 1.0];
+    for _ in 0..4 {
+        let mut next = [0.0f32; 3];
+        for i in 0..3 {
+            next[i] = covariance[i][0] * axis[0] + covariance[i][1] * axis[1] + covariance[i][2] * axis[2];
+        }
+        let length: f32 = (next[0] * next[0] + next[1] * next[1] + next[2] * next[2]).sqrt();
+        if length < 1.0e-6 {
+            break; // all colors are the same
+        }
+        axis = [next[0] / length, next[1] / length, next[2] / length];
+    }
+    axis
+}
+
+fn to_rgb565(texel: &[u8; 4]) -> u16 {
+    let r: u16 = (texel[0] as u16 * 31 + 127) / 255;
+    let g: u16 = (texel[1] as u16 * 63 + 127) / 255;
+    let b: u16 = (texel[2] as u16 * 31 + 127) / 255;
+    (r << 11) | (g << 5) | b
+}
+
+#[inline(always)]
+fn expand5(v: u32) -> u32 {
+    (v << 3) | (v >> 2)
+}
+
+#[inline(always)]
+fn expand6(v: u32) -> u32 {
+    (v << 2) | (v >> 4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_block(block: &[u8; BC3_BLOCK_SIZE]) -> [RGBA; 16] {
+        std::array::from_fn(|i| decode_bc3_texel(block, i as u32))
+    }
+
+    #[test]
+    fn solid_block_is_lossless_in_rgb565() {
+        let texels: [[u8; 4]; 16] = [[255, 0, 255, 255]; 16];
+        let decoded = decode_block(&compress_bc3_block(&texels));
+        for texel in decoded {
+            assert_eq!(texel, RGBA::new(255, 0, 255, 255));
+        }
+    }
+
+    #[test]
+    fn gradient_block_stays_close() {
+        let texels: [[u8; 4]; 16] = std::array::from_fn(|i| {
+            // A smooth ramp of colors with the alpha spanning the full range
+            let v: u8 = (100 + i * 3) as u8;
+            [v, 255 - v, v / 2, (i * 17) as u8]
+        });
+        let decoded = decode_block(&compress_bc3_block(&texels));
+        for i in 0..16 {
+            assert!((decoded[i].r as i32 - texels[i][0] as i32).abs() <= 12, "{:?} vs {:?}", decoded[i], texels[i]);
+            assert!((decoded[i].g as i32 - texels[i][1] as i32).abs() <= 12, "{:?} vs {:?}", decoded[i], texels[i]);
+            assert!((decoded[i].b as i32 - texels[i][2] as i32).abs() <= 12, "{:?} vs {:?}", decoded[i], texels[i]);
+            assert!((decoded[i].a as i32 - texels[i][3] as i32).abs() <= 20, "{:?} vs {:?}", decoded[i], texels[i]);
+        }
+    }
+
+    #[test]
+    fn two_colors_block_is_exact() {
+        let texels: [[u8; 4]; 16] = std::array::from_fn(|i| {
+            if (i + i / 4) % 2 == 0 {
+                [0, 0, 0, 0]
+            } else {
+                [255, 255, 255, 255]
+            }
+        });
+        let decoded = decode_block(&compress_bc3_block(&texels));
+        for i in 0..16 {
+            let expected = texels[i];
+            assert_eq!(decoded[i], RGBA::new(expected[0], expected[1], expected[2], expected[3]));
+        }
+    }
+
+    #[test]
+    fn mip_sizes() {
+        assert_eq!(bc3_mip_size(1, 1), 16);
+        assert_eq!(bc3_mip_size(4, 4), 16);
+        assert_eq!(bc3_mip_size(8, 8), 64);
+        assert_eq!(bc3_mip_size(1024, 1024), 1024 * 1024);
+    }
+}
@@ -167,6 +167,74 @@ where
 //     _marker: std::marker::PhantomData<&'a T>,
 // }
 
+/// Memory provided by the caller to place the values of a `TiledBuffer` into, e.g. shared memory, a memory-mapped file
+/// or a GPU staging buffer, so that the rendered values can be consumed without copying.
+/// The buffer takes the ownership of the handle, which keeps the memory alive and exclusively borrowed for as long as
+/// the buffer exists, and gives it back via `TiledBuffer::into_external_memory()`.
+///
+/// The handles are owned rather than borrowed on purpose: `TiledBuffer` has no lifetime parameter, the buffers are kept
+/// across frames, e.g. by `FrameGraph`, and are sent to other threads, e.g. by `PipelinedRasterizer`, which a borrow of
+/// the caller's memory couldn't outlive. A region with a limited lifetime is placed by a handle owning whatever keeps it
+/// valid, e.g. the memory map itself instead of a slice of it, or the frame is rendered into an owned buffer and copied.
+///
+/// # Safety
+/// The implementations must return the same memory region from every call to `bytes()` and `bytes_mut()`, which must
+/// stay valid until the handle is dropped. The buffer keeps pointers into it while its tiles are being drawn.
+pub unsafe trait ExternalMemory: Send + Sync {
+    fn bytes(&self) -> &[u8];
+    fn bytes_mut(&mut self) -> &mut [u8];
+}
+
+unsafe impl ExternalMemory for Vec<u8> {
+    fn bytes(&self) -> &[u8] {
+        self
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+unsafe impl ExternalMemory for &'static mut [u8] {
+    fn bytes(&self) -> &[u8] {
+        self
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+/// The reason why a `TiledBuffer` can't be placed into the external memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TiledBufferError {
+    /// The memory is smaller than `TiledBuffer::required_bytes()`.
+    InsufficientSize { required: usize, provided: usize },
+
+    /// The memory's start is not aligned to the alignment of the values.
+    Misaligned { alignment: usize },
+}
+
+impl std::fmt::Display for TiledBufferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            TiledBufferError::InsufficientSize { required, provided } => {
+                write!(f, "the external memory must have at least {} bytes, got {}", required, provided)
+            }
+            TiledBufferError::Misaligned { alignment } => {
+                write!(f, "the external memory must be aligned to {} bytes", alignment)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TiledBufferError {}
+
+enum TiledBufferStorage<T> {
+    Owned(Vec<T>),
+    External(Box<dyn ExternalMemory>),
+}
+
 pub struct TiledBuffer<T, const W: usize, const H: usize> {
     /// Logical width of the buffer.
     width: u16,
@@ -180,8 +248,26 @@ pub struct TiledBuffer<T, const W: usize, const H: usize> {
     /// Number of W*H titles along Y
     tiles_y: u16,
 
-    /// The data itself. Row-major order of tiles inside.
-    values: Vec<T>,
+    /// The data itself. Row-major order of tiles inside, each tile is W*H values in the row-major order.
+    storage: TiledBufferStorage<T>,
+
+    /// The first value of the storage, cached so that the per-element access doesn't go through the storage.
+    /// Stays valid when the buffer is moved: the owned values are on the heap and the external memory doesn't move.
+    values: *mut T,
+}
+
+// The values are only accessed through the buffer, the same way as the ones of a Vec.
+unsafe impl<T: Send, const W: usize, const H: usize> Send for TiledBuffer<T, W, H> {}
+unsafe impl<T: Sync, const W: usize, const H: usize> Sync for TiledBuffer<T, W, H> {}
+
+impl<T, const W: usize, const H: usize> TiledBuffer<T, W, H> {
+    fn with_storage(width: u16, height: u16, mut storage: TiledBufferStorage<T>) -> Self {
+        let values: *mut T = match &mut storage {
+            TiledBufferStorage::Owned(values) => values.as_mut_ptr(),
+            TiledBufferStorage::External(memory) => memory.bytes_mut().as_mut_ptr() as *mut T,
+        };
+        Self { width, height, tiles_x: width.div_ceil(W as u16), tiles_y: height.div_ceil(H as u16), storage, values }
+    }
 }
 
 impl<T: Copy + Zeroable + Pod + Default, const W: usize, const H: usize> TiledBuffer<T, W, H> {
@@ -206,47 +292,110 @@ impl<T: Copy + Zeroable + Pod + Default, const W: usize, const H: usize> TiledBu
 
     pub fn new(width: u16, height: u16) -> Self {
         assert!(width > 0 && height > 0);
-        let mut t = Self::default();
         let tiles_x = (width + W as u16 - 1) / W as u16;
         let tiles_y = (height + H as u16 - 1) / H as u16;
         let physical_width = tiles_x * W as u16;
         let physical_height = tiles_y * H as u16;
-        t.width = width;
-        t.height = height;
-        t.tiles_x = tiles_x;
-        t.tiles_y = tiles_y;
-        t.values
-            .resize_with(physical_width as usize * physical_height as usize, Default::default);
-        t
+        let values: Vec<T> = vec![T::default(); physical_width as usize * physical_height as usize];
+        Self::with_storage(width, height, TiledBufferStorage::Owned(values))
+    }
+
+    /// The number of bytes of external memory a buffer of the given size occupies, including the padding of the
+    /// partially covered tiles along the right and the bottom edges.
+    pub fn required_bytes(width: u16, height: u16) -> usize {
+        let tiles_x = (width as usize).div_ceil(W);
+        let tiles_y = (height as usize).div_ceil(H);
+        tiles_x * tiles_y * W * H * size_of::<T>()
+    }
+
+    /// Places the buffer into the memory provided by the caller instead of allocating it.
+    /// The values are laid out the same way as in an owned buffer: tiles in the row-major order, each tile being W*H
+    /// values in the row-major order. The memory's existing contents are kept as they are, i.e. the buffer is not
+    /// cleared. The memory must be at least `required_bytes()` long and aligned to the values' alignment.
+    pub fn from_external_memory(
+        width: u16,
+        height: u16,
+        memory: Box<dyn ExternalMemory>,
+    ) -> Result<Self, TiledBufferError> {
+        assert!(width > 0 && height > 0);
+        let required: usize = Self::required_bytes(width, height);
+        let provided: usize = memory.bytes().len();
+        if provided < required {
+            return Err(TiledBufferError::InsufficientSize { required, provided });
+        }
+        if !(memory.bytes().as_ptr() as usize).is_multiple_of(align_of::<T>()) {
+            return Err(TiledBufferError::Misaligned { alignment: align_of::<T>() });
+        }
+        Ok(Self::with_storage(width, height, TiledBufferStorage::External(memory)))
+    }
+
+    /// Returns the external memory the buffer was placed into, or None if the buffer owns its values.
+    pub fn into_external_memory(self) -> Option<Box<dyn ExternalMemory>> {
+        match self.storage {
+            TiledBufferStorage::Owned(_) => None,
+            TiledBufferStorage::External(memory) => Some(memory),
+        }
+    }
+
+    pub fn is_external(&self) -> bool {
+        matches!(self.storage, TiledBufferStorage::External(_))
+    }
+
+    /// All the values, tile by tile, including the padding of the partially covered tiles.
+    pub fn values(&self) -> &[T] {
+        let len: usize = self.tiles_x as usize * self.tiles_y as usize * W * H;
+        unsafe { std::slice::from_raw_parts(self.values, len) }
+    }
+
+    pub fn values_mut(&mut self) -> &mut [T] {
+        let len: usize = self.tiles_x as usize * self.tiles_y as usize * W * H;
+        unsafe { std::slice::from_raw_parts_mut(self.values, len) }
     }
 
     pub fn fill(&mut self, value: T) {
-        for v in self.values.iter_mut() {
+        for v in self.values_mut().iter_mut() {
             *v = value;
         }
     }
 
     pub fn at(&self, x: u16, y: u16) -> T {
-        debug_assert!(x < self.width, "x out of bounds: {} >= {}", x, self.width);
-        debug_assert!(y < self.height, "y out of bounds: {} >= {}", y, self.height);
-        // self.elems[(y as usize) * (self.stride as usize) + (x as usize)]
-        let tile_x = x / W as u16;
-        let tile_y = y / H as u16;
-        let tile = self.tile(tile_x, tile_y); // TODO: this is super-inefficient
-        tile.get_unchecked(x as usize % W, y as usize % H)
+        assert!(x < self.width, "x out of bounds: {} >= {}", x, self.width);
+        assert!(y < self.height, "y out of bounds: {} >= {}", y, self.height);
+        // safe because bounds were checked
+        unsafe { self.at_unchecked(x, y) }
     }
 
     pub fn at_mut(&mut self, x: u16, y: u16) -> &mut T {
-        debug_assert!(x < self.width, "x out of bounds: {} >= {}", x, self.width);
-        debug_assert!(y < self.height, "y out of bounds: {} >= {}", y, self.height);
-        // &mut self.elems[(y as usize) * (self.stride as usize) + (x as usize)]
+        assert!(x < self.width, "x out of bounds: {} >= {}", x, self.width);
+        assert!(y < self.height, "y out of bounds: {} >= {}", y, self.height);
+        // safe because bounds were checked
+        unsafe { self.at_unchecked_mut(x, y) }
+    }
+
+    /// Returns the value at (x, y) without bounds checking.
+    ///
+    /// # Safety
+    /// Caller must ensure that x < self.width and y < self.height.
+    pub unsafe fn at_unchecked(&self, x: u16, y: u16) -> T {
+        debug_assert!(x < self.width && y < self.height);
+        unsafe { *self.values.add(Self::index_of(self.tiles_x, x, y)) }
+    }
+
+    /// Returns a mutable reference to the value at (x, y) without bounds checking.
+    ///
+    /// # Safety
+    /// Caller must ensure that x < self.width and y < self.height.
+    pub unsafe fn at_unchecked_mut(&mut self, x: u16, y: u16) -> &mut T {
+        debug_assert!(x < self.width && y < self.height);
+        unsafe { &mut *self.values.add(Self::index_of(self.tiles_x, x, y)) }
+    }
+
+    // The index of the value at (x, y) among the values stored tile by tile.
+    fn index_of(tiles_x: u16, x: u16, y: u16) -> usize {
         let tile_x = x / W as u16;
         let tile_y = y / H as u16;
-        let start_index = (tile_y as usize * self.tiles_x as usize + tile_x as usize) * (W * H);
-        let offset = (y as usize % H) * W + (x as usize % W);
-        &mut self.values[start_index + offset]
-        // let tile = self.tile_mut(tile_x, tile_y); // TODO: this is super-inefficient
-        // tile.get_unchecked(x as usize % W, y as usize % H)
+        let start_index = (tile_y as usize * tiles_x as usize + tile_x as usize) * (W * H);
+        start_index + (y as usize % H) * W + (x as usize % W)
     }
 
     pub fn tile(&self, tile_x: u16, tile_y: u16) -> TiledBufferTile<T, W, H> {
@@ -258,7 +407,7 @@ impl<T: Copy + Zeroable + Pod + Default, const W: usize, const H: usize> TiledBu
                 origin_y: tile_y * H as u16,
                 width: (self.width - tile_x * W as u16).min(W as u16),
                 height: (self.height - tile_y * H as u16).min(H as u16),
-                ptr: self.values.as_ptr().add(start_index),
+                ptr: self.values.add(start_index),
                 // _marker: std::marker::PhantomData,
             }
         }
@@ -273,18 +422,33 @@ impl<T: Copy + Zeroable + Pod + Default, const W: usize, const H: usize> TiledBu
                 origin_y: tile_y * H as u16,
                 width: (self.width - tile_x * W as u16).min(W as u16),
                 height: (self.height - tile_y * H as u16).min(H as u16),
-                ptr: self.values.as_mut_ptr().add(start_index),
+                ptr: self.values.add(start_index),
                 // _marker: std::marker::PhantomData,
             }
         }
     }
 
+    /// The tiled copy of a flat buffer, the opposite of `as_flat_buffer()`.
+    pub fn from_flat_buffer(buffer: &Buffer<T>) -> Self {
+        let mut tiled = Self::new(buffer.width, buffer.height);
+        let tiles_x: usize = tiled.tiles_x as usize;
+        let values: &mut [T] = tiled.values_mut();
+        for (y, row) in buffer.rows().enumerate() {
+            for (tile_x, src) in row.chunks(W).enumerate() {
+                let start: usize = ((y / H) * tiles_x + tile_x) * (W * H) + (y % H) * W;
+                values[start..start + src.len()].copy_from_slice(src);
+            }
+        }
+        tiled
+    }
+
     pub fn as_flat_buffer(&self) -> Buffer<T> {
         let mut buffer = Buffer::<T>::new(self.width, self.height);
 
         // Fast path: write row chunks directly into the flat buffer.
         // Assumes Buffer<T> exposes a contiguous mutable slice.
         let dst = buffer.as_mut_slice();
+        let values: &[T] = self.values();
 
         let width = self.width as usize;
         let height = self.height as usize;
@@ -312,7 +476,7 @@ impl<T: Copy + Zeroable + Pod + Default, const W: usize, const H: usize> TiledBu
                     // Start of the `row` within that tile
                     let src_row_start = tile_base + row * W;
 
-                    let src = &self.values[src_row_start..src_row_start + cols_in_tile];
+                    let src = &values[src_row_start..src_row_start + cols_in_tile];
                     let dst_start = dst_row_start + dst_col;
                     let dst_end = dst_start + cols_in_tile;
 
@@ -336,7 +500,14 @@ impl<T: Copy + Zeroable + Pod + Default, const W: usize, const H: usize> TiledBu
 
 impl<T, const W: usize, const H: usize> Default for TiledBuffer<T, W, H> {
     fn default() -> Self {
-        Self { width: 0, height: 0, tiles_x: 0, tiles_y: 0, values: Vec::new() }
+        Self::with_storage(0, 0, TiledBufferStorage::Owned(Vec::new()))
+    }
+}
+
+/// Cloning a buffer placed into external memory makes an owned copy of its values.
+impl<T: Copy + Zeroable + Pod + Default, const W: usize, const H: usize> Clone for TiledBuffer<T, W, H> {
+    fn clone(&self) -> Self {
+        Self::with_storage(self.width, self.height, TiledBufferStorage::Owned(self.values().to_vec()))
     }
 }
 
@@ -349,7 +520,7 @@ mod tests {
         // Create a 6x6 buffer with 4x4 tiles
         let mut buf = TiledBuffer::<u32, 4, 4>::new(6, 6);
         // Fill with sequential values
-        for (i, v) in buf.values.iter_mut().enumerate() {
+        for (i, v) in buf.values_mut().iter_mut().enumerate() {
             *v = i as u32;
         }
 
@@ -398,4 +569,50 @@ mod tests {
         assert_eq!(tile.width, 1);
         assert_eq!(tile.height, 1);
     }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn test_at_bounds() {
+        // Buffer 5x5, the values past its width are within the allocated tiles
+        let buf = TiledBuffer::<u32, 4, 4>::new(5, 5);
+        buf.at(6, 0);
+    }
+
+    #[test]
+    fn external_memory() {
+        // 5x3 values with 4x4 tiles occupy 2 tiles
+        assert_eq!(TiledBuffer::<u16, 4, 4>::required_bytes(5, 3), 64);
+        let too_small = TiledBuffer::<u16, 4, 4>::from_external_memory(5, 3, Box::new(vec![0u8; 63]));
+        assert_eq!(too_small.err(), Some(TiledBufferError::InsufficientSize { required: 64, provided: 63 }));
+
+        let mut buf = TiledBuffer::<u16, 4, 4>::from_external_memory(5, 3, Box::new(vec![0xFFu8; 65])).unwrap();
+        assert!(buf.is_external());
+        assert_eq!(buf.at(4, 2), 0xFFFF);
+        buf.fill(0);
+        *buf.at_mut(4, 2) = 0x0102;
+        assert_eq!(buf.tile(1, 0).get(0, 2), 0x0102);
+        assert!(!buf.clone().is_external());
+
+        // The values are written in place, tile by tile, the trailing byte is intact
+        let memory = buf.into_external_memory().unwrap();
+        let bytes: &[u8] = memory.bytes();
+        let offset: usize = (16 + 2 * 4) * 2;
+        assert_eq!(u16::from_ne_bytes([bytes[offset], bytes[offset + 1]]), 0x0102);
+        assert!(
+            bytes[..64]
+                .iter()
+                .enumerate()
+                .all(|(i, &b)| b == 0 || i / 2 == offset / 2)
+        );
+        assert_eq!(bytes[64], 0xFF);
+    }
+
+    #[test]
+    fn external_memory_must_be_aligned() {
+        let words: &'static mut [u32] = Box::leak(vec![0u32; 17].into_boxed_slice());
+        let bytes: &'static mut [u8] = bytemuck::cast_slice_mut(words);
+        let misaligned: &'static mut [u8] = &mut bytes[1..];
+        let result = TiledBuffer::<u32, 4, 4>::from_external_memory(4, 4, Box::new(misaligned));
+        assert_eq!(result.err(), Some(TiledBufferError::Misaligned { alignment: 4 }));
+    }
 }
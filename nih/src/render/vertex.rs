@@ -1,12 +1,14 @@
 use crate::math::*;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Vertex {
     pub position: Vec4,
     pub normal: Vec3,
     pub tangent: Vec3,
     pub color: Vec4,
     pub tex_coord: Vec2,
+    pub tex_coord2: Vec2,
+    pub world_position: Vec3,
 }
 
 impl Default for Vertex {
@@ -17,6 +19,8 @@ impl Default for Vertex {
             tangent: Vec3::new(0.0, 0.0, 0.0),
             color: Vec4::new(0.0, 0.0, 0.0, 0.0),
             tex_coord: Vec2::new(0.0, 0.0),
+            tex_coord2: Vec2::new(0.0, 0.0),
+            world_position: Vec3::new(0.0, 0.0, 0.0),
         }
     }
 }
@@ -0,0 +1,203 @@
+use super::super::math::*;
+use std::sync::Arc;
+
+// A vertex animation baked into a texture, e.g. a cloth or a destruction simulation exported from a DCC tool: a column
+// per vertex and a row per frame, each texel holding the vertex's position in object space and optionally its normal.
+// Replaying it only costs a lookup per vertex, see RasterizationCommand::vertex_animation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VertexAnimationTexture {
+    vertices: u32,
+    frames: u32,
+
+    // The positions of all the vertices of the first frame, then of the second one and so on
+    positions: Vec<Vec3>,
+
+    // Either empty or laid out the same way as the positions
+    normals: Vec<Vec3>,
+}
+
+impl VertexAnimationTexture {
+    // Panics if there are no vertices or frames, or if the positions or the non-empty normals are not vertices * frames.
+    pub fn new(vertices: u32, frames: u32, positions: &[Vec3], normals: &[Vec3]) -> Arc<Self> {
+        assert!(vertices > 0 && frames > 0, "the animation must have vertices and frames");
+        let size = vertices as usize * frames as usize;
+        assert_eq!(positions.len(), size, "expected {} positions", size);
+        assert!(normals.is_empty() || normals.len() == size, "expected {} normals", size);
+        Arc::new(Self { vertices, frames, positions: positions.to_vec(), normals: normals.to_vec() })
+    }
+
+    // Decodes the common 8-bit export, where the RGB channels of the position texels are normalized into the bounds of
+    // the whole animation and the ones of the normal texels map [-1, 1] to [0, 255]. The texels have 3 or 4 bytes each,
+    // the alpha is ignored. The normals can be empty.
+    pub fn from_rgb8(
+        vertices: u32,
+        frames: u32,
+        bytes_per_texel: usize,
+        positions: &[u8],
+        bounds: AABB,
+        normals: &[u8],
+    ) -> Arc<Self> {
+        assert!(bytes_per_texel == 3 || bytes_per_texel == 4, "the texels must be RGB or RGBA");
+        let decode = |texels: &[u8], min: Vec3, extent: Vec3| -> Vec<Vec3> {
+            texels
+                .chunks_exact(bytes_per_texel)
+                .map(|texel| {
+                    let unit = Vec3::new(texel[0] as f32, texel[1] as f32, texel[2] as f32) / 255.0;
+                    min + Vec3::new(unit.x * extent.x, unit.y * extent.y, unit.z * extent.z)
+                })
+                .collect()
+        };
+        let positions: Vec<Vec3> = decode(positions, bounds.min, bounds.max - bounds.min);
+        let normals: Vec<Vec3> = decode(normals, Vec3::new(-1.0, -1.0, -1.0), Vec3::new(2.0, 2.0, 2.0))
+            .into_iter()
+            .map(|normal| normal.normalized())
+            .collect();
+        Self::new(vertices, frames, &positions, &normals)
+    }
+
+    pub fn vertices(&self) -> u32 {
+        self.vertices
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    pub fn has_normals(&self) -> bool {
+        !self.normals.is_empty()
+    }
+
+    pub fn position(&self, vertex: u32, frame: u32) -> Vec3 {
+        self.positions[self.texel(vertex, frame)]
+    }
+
+    // The baked normal, or (0, 0, 0) if there are no normals.
+    pub fn normal(&self, vertex: u32, frame: u32) -> Vec3 {
+        self.normals
+            .get(self.texel(vertex, frame))
+            .copied()
+            .unwrap_or(Vec3::new(0.0, 0.0, 0.0))
+    }
+
+    // The bounding box of the positions over all the frames, which suits RasterizationCommand::aabb.
+    pub fn aabb(&self) -> AABB {
+        AABB::from_points(&self.positions)
+    }
+
+    fn texel(&self, vertex: u32, frame: u32) -> usize {
+        assert!(vertex < self.vertices && frame < self.frames);
+        frame as usize * self.vertices as usize + vertex as usize
+    }
+}
+
+// The playback of a vertex animation texture by a command. Each vertex of the command samples its column of the texture
+// at the current frame, its position and its normal, if the texture has normals, are replaced by the sampled ones
+// before the vertex hook runs and before the vertex is transformed.
+#[derive(Debug, Clone)]
+pub struct VertexAnimation<'a> {
+    pub texture: Arc<VertexAnimationTexture>,
+
+    // The frame to sample, i.e. the row of the texture. The fractional frames blend the two nearest rows.
+    // Default: 0.
+    pub frame: f32,
+
+    // Whether the frames past the last one wrap around to the first one, which is also blended with the last one.
+    // Otherwise the frames are clamped.
+    // Default: true.
+    pub looping: bool,
+
+    // The texture's column of each of the command's vertices, i.e. the per-vertex index baked alongside the texture.
+    // Empty if the columns are the indices of the vertices in world_positions.
+    // The vertices whose columns are outside the texture keep their positions and normals.
+    // Default: empty.
+    pub columns: &'a [u32],
+}
+
+impl VertexAnimation<'_> {
+    pub fn new(texture: Arc<VertexAnimationTexture>) -> Self {
+        Self { texture, frame: 0.0, looping: true, columns: &[] }
+    }
+
+    // The frame playing after the given time in seconds.
+    pub fn at_time(self, seconds: f32, frames_per_second: f32) -> Self {
+        Self { frame: seconds * frames_per_second, ..self }
+    }
+
+    // The two rows to blend and the weight of the second one.
+    pub(crate) fn rows(&self) -> (u32, u32, f32) {
+        let frames: u32 = self.texture.frames;
+        let frame: f32 = if self.looping {
+            self.frame.rem_euclid(frames as f32)
+        } else {
+            self.frame.clamp(0.0, (frames - 1) as f32)
+        };
+        let first: u32 = (frame.floor() as u32).min(frames - 1);
+        let second: u32 = if self.looping {
+            (first + 1) % frames
+        } else {
+            (first + 1).min(frames - 1)
+        };
+        (first, second, frame - first as f32)
+    }
+
+    // The animated position and normal of the command's vertex, None if its column is outside the texture.
+    pub(crate) fn sample(&self, vertex: usize, rows: (u32, u32, f32)) -> Option<(Vec3, Option<Vec3>)> {
+        let column: u32 = match self.columns.is_empty() {
+            true => vertex as u32,
+            false => *self.columns.get(vertex)?,
+        };
+        if column >= self.texture.vertices {
+            return None;
+        }
+        let (first, second, t) = rows;
+        let texture: &VertexAnimationTexture = &self.texture;
+        let position: Vec3 = lerp(texture.position(column, first), texture.position(column, second), t);
+        let normal: Option<Vec3> = texture
+            .has_normals()
+            .then(|| lerp(texture.normal(column, first), texture.normal(column, second), t).normalized());
+        Some((position, normal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_blended_and_wrapped() {
+        let positions =
+            [Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), Vec3::new(1.0, 2.0, 0.0)];
+        let texture = VertexAnimationTexture::new(2, 2, &positions, &[]);
+        assert_eq!(texture.aabb(), AABB { min: Vec3::new(0.0, 0.0, 0.0), max: Vec3::new(1.0, 2.0, 0.0) });
+        let animation = VertexAnimation { frame: 0.25, ..VertexAnimation::new(texture) };
+        assert_eq!(animation.sample(1, animation.rows()), Some((Vec3::new(1.0, 0.5, 0.0), None)));
+        assert_eq!(animation.sample(2, animation.rows()), None);
+
+        // Looping blends the last frame back into the first one, clamping holds the last one
+        let looped = VertexAnimation { frame: 3.5, ..animation.clone() };
+        assert_eq!(looped.rows(), (1, 0, 0.5));
+        let clamped = VertexAnimation { looping: false, ..looped.clone() };
+        assert_eq!(clamped.rows(), (1, 1, 0.0));
+        assert_eq!(VertexAnimation { frame: -0.5, ..animation.clone() }.rows(), (1, 0, 0.5));
+        assert_eq!(animation.clone().at_time(0.5, 2.0).frame, 1.0);
+
+        // The explicit columns remap the vertices
+        let columns = [1, 0, 7];
+        let remapped = VertexAnimation { frame: 1.0, columns: &columns, ..animation };
+        assert_eq!(remapped.sample(0, remapped.rows()), Some((Vec3::new(1.0, 2.0, 0.0), None)));
+        assert_eq!(remapped.sample(2, remapped.rows()), None);
+        assert_eq!(remapped.sample(3, remapped.rows()), None);
+    }
+
+    #[test]
+    fn rgb8_is_decoded_into_bounds() {
+        let bounds = AABB { min: Vec3::new(-1.0, 0.0, 10.0), max: Vec3::new(1.0, 4.0, 20.0) };
+        let positions: [u8; 8] = [0, 0, 0, 0, 255, 255, 255, 255];
+        let normals: [u8; 8] = [128, 128, 255, 0, 128, 0, 128, 0];
+        let texture = VertexAnimationTexture::from_rgb8(1, 2, 4, &positions, bounds, &normals);
+        assert_eq!(texture.position(0, 0), Vec3::new(-1.0, 0.0, 10.0));
+        assert_eq!(texture.position(0, 1), Vec3::new(1.0, 4.0, 20.0));
+        assert!((texture.normal(0, 0) - Vec3::new(0.0, 0.0, 1.0)).length() < 0.01);
+        assert!((texture.normal(0, 1) - Vec3::new(0.0, -1.0, 0.0)).length() < 0.01);
+    }
+}
@@ -10,4 +10,19 @@ impl Viewport {
     pub fn new(xmin: u16, ymin: u16, xmax: u16, ymax: u16) -> Viewport {
         Viewport { xmin, ymin, xmax, ymax }
     }
+
+    // The overlapping part of the two viewports, None if they don't overlap.
+    pub fn intersection(&self, other: &Viewport) -> Option<Viewport> {
+        let intersection = Viewport {
+            xmin: self.xmin.max(other.xmin),
+            ymin: self.ymin.max(other.ymin),
+            xmax: self.xmax.min(other.xmax),
+            ymax: self.ymax.min(other.ymax),
+        };
+        if intersection.xmin < intersection.xmax && intersection.ymin < intersection.ymax {
+            Some(intersection)
+        } else {
+            None
+        }
+    }
 }
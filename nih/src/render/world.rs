@@ -0,0 +1,920 @@
+use super::super::math::*;
+use super::*;
+use std::sync::Arc;
+
+#[cfg(feature = "serde")]
+mod scene;
+#[cfg(feature = "serde")]
+pub use scene::*;
+
+// A higher-level facade over the rasterizer for the applications that want "a renderer": it keeps the meshes, the
+// materials, the instances of the meshes placed in the world, the lights and the camera, and turns them into a frame
+// with a single render() call:
+//   1. the instances outside the camera's frustum are culled by their bounding boxes;
+//   2. the opaque sections are committed front-to-back, then the blended ones back-to-front;
+//   3. the frame is drawn;
+//   4. if there are any lights or an environment probe, the color buffer is lit per pixel from the normal and depth
+//      buffers.
+// The objects are referred to by handles, which stay valid until the object is removed and can be reused afterwards.
+pub struct RenderWorld {
+    meshes: Slots<WorldMesh>,
+    materials: Slots<Material>,
+    instances: Slots<Instance>,
+    lights: Slots<Light>,
+    view: Mat44,
+    projection: Mat44,
+    ambient: Vec3,
+    light_units: LightUnits,
+    probe: Option<Arc<EnvironmentProbe>>,
+    clear_values: Option<ClearValues>,
+    statistics: RenderWorldStatistics,
+
+    // The draw list, kept between the frames to avoid reallocations
+    draws: Vec<Draw>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MeshId(u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaterialId(u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InstanceId(u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LightId(u32);
+
+// The surface's appearance, shared by the instances and mapped onto the fields of RasterizationCommand.
+#[derive(Debug, Clone)]
+pub struct Material {
+    // The color multiplied by the texture and the vertex colors, not premultiplied by alpha.
+    // Default: (1, 1, 1, 1).
+    pub color: Vec4,
+
+    // Default: None.
+    pub texture: Option<Arc<Texture>>,
+
+    // Default: None.
+    pub normal_map: Option<Arc<Texture>>,
+
+    // Default: nearest.
+    pub sampling_filter: SamplerFilter,
+
+    // Default: CW, i.e. the back faces of counter-clockwise meshes are culled.
+    pub culling: CullMode,
+
+    // The materials with alpha blending are drawn after the opaque ones, back-to-front.
+    // Default: None.
+    pub alpha_blending: AlphaBlendingMode,
+
+    // Default: 0, i.e. no alpha test.
+    pub alpha_test: u8,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            texture: None,
+            normal_map: None,
+            sampling_filter: SamplerFilter::Nearest,
+            culling: CullMode::CW,
+            alpha_blending: AlphaBlendingMode::None,
+            alpha_test: 0,
+        }
+    }
+}
+
+impl Material {
+    // Sets the command's fields the material maps onto, keeping the rest.
+    pub fn apply<'a>(&self, command: RasterizationCommand<'a>) -> RasterizationCommand<'a> {
+        RasterizationCommand {
+            culling: self.culling,
+            color: self.color,
+            texture: self.texture.clone(),
+            normal_map: self.normal_map.clone(),
+            sampling_filter: self.sampling_filter,
+            alpha_blending: self.alpha_blending,
+            alpha_test: self.alpha_test,
+            ..command
+        }
+    }
+}
+
+// A light source in world space. The colors are linear and can exceed 1.0, their units are set by
+// RenderWorld::set_light_units().
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Light {
+    // Light coming from infinitely far away, e.g. the sun. The direction is the one the light travels in.
+    Directional { direction: Vec3, color: Vec3 },
+
+    // Light emitted from the position in all directions, smoothly fading out to zero at the range.
+    Point { position: Vec3, color: Vec3, range: f32 },
+}
+
+// The units of the lights' colors and the ambient light in RenderWorld.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LightUnits {
+    // The lights' colors multiply the surfaces' colors, the point lights fade out smoothly towards their range.
+    Relative,
+
+    // The directional lights' colors are the illuminance in lux, the point lights' colors are the luminous intensity in
+    // candela falling off with the square of the distance within their range, and the ambient is the luminance of a
+    // white surface in cd/m². The surfaces are Lambertian and the resulting luminance is exposed by the camera, so the
+    // tone mapping of the rendered frames should keep the exposure of 1.0. The environment probes are taken as already
+    // exposed.
+    Photometric(CameraExposure),
+}
+
+// Counters of the last render() call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RenderWorldStatistics {
+    // The instances committed into the rasterizer.
+    pub visible_instances: usize,
+
+    // The instances skipped as being outside the camera's frustum.
+    pub culled_instances: usize,
+
+    // The commands committed into the rasterizer, one per mesh section.
+    pub committed_commands: usize,
+}
+
+struct WorldMesh {
+    data: Arc<MeshData>,
+
+    // The mesh's bounding box, calculated from the positions if the mesh doesn't have one
+    aabb: AABB,
+}
+
+struct Instance {
+    mesh: MeshId,
+
+    // The materials of the mesh's sections, indexed by MeshDataSection::material_index
+    materials: Vec<MaterialId>,
+    transform: Mat34,
+    visible: bool,
+}
+
+// A section of an instance to be committed
+struct Draw {
+    instance: u32,
+
+    // The range of the mesh's indices, empty for non-indexed meshes
+    indices: std::ops::Range<usize>,
+    material: Option<MaterialId>,
+    blended: bool,
+
+    // The distance from the camera to the center of the instance's bounding box, along the view direction
+    distance: f32,
+}
+
+// A vector of reusable slots, the handles are the indices of the slots.
+pub(crate) struct Slots<T> {
+    items: Vec<Option<T>>,
+    free: Vec<u32>,
+}
+
+impl<T> Slots<T> {
+    pub(crate) fn new() -> Self {
+        Self { items: Vec::new(), free: Vec::new() }
+    }
+
+    pub(crate) fn insert(&mut self, item: T) -> u32 {
+        if let Some(index) = self.free.pop() {
+            self.items[index as usize] = Some(item);
+            index
+        } else {
+            self.items.push(Some(item));
+            (self.items.len() - 1) as u32
+        }
+    }
+
+    pub(crate) fn remove(&mut self, index: u32) -> Option<T> {
+        let item: Option<T> = self.items.get_mut(index as usize)?.take();
+        if item.is_some() {
+            self.free.push(index);
+        }
+        item
+    }
+
+    pub(crate) fn get(&self, index: u32) -> Option<&T> {
+        self.items.get(index as usize)?.as_ref()
+    }
+
+    pub(crate) fn get_mut(&mut self, index: u32) -> Option<&mut T> {
+        self.items.get_mut(index as usize)?.as_mut()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (u32, &T)> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| item.as_ref().map(|item| (index as u32, item)))
+    }
+}
+
+impl Default for RenderWorld {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RenderWorld {
+    pub fn new() -> Self {
+        Self {
+            meshes: Slots::new(),
+            materials: Slots::new(),
+            instances: Slots::new(),
+            lights: Slots::new(),
+            view: Mat44::identity(),
+            projection: Mat44::identity(),
+            ambient: Vec3::new(0.2, 0.2, 0.2),
+            light_units: LightUnits::Relative,
+            probe: None,
+            clear_values: Some(ClearValues::default()),
+            statistics: RenderWorldStatistics::default(),
+            draws: Vec::new(),
+        }
+    }
+
+    pub fn add_mesh(&mut self, mesh: Arc<MeshData>) -> MeshId {
+        let aabb: AABB = if mesh.aabb.min == mesh.aabb.max {
+            AABB::from_points(&mesh.positions)
+        } else {
+            mesh.aabb
+        };
+        MeshId(self.meshes.insert(WorldMesh { data: mesh, aabb }))
+    }
+
+    // The instances of a removed mesh are kept, but are not drawn.
+    pub fn remove_mesh(&mut self, id: MeshId) -> Option<Arc<MeshData>> {
+        self.meshes.remove(id.0).map(|mesh| mesh.data)
+    }
+
+    pub fn add_material(&mut self, material: Material) -> MaterialId {
+        MaterialId(self.materials.insert(material))
+    }
+
+    pub fn material_mut(&mut self, id: MaterialId) -> Option<&mut Material> {
+        self.materials.get_mut(id.0)
+    }
+
+    // The sections referring to a removed material are drawn with the default one.
+    pub fn remove_material(&mut self, id: MaterialId) -> Option<Material> {
+        self.materials.remove(id.0)
+    }
+
+    // Places the mesh into the world with the model transform.
+    // The materials are picked by the sections' material_index, the default material is used for the missing ones.
+    pub fn add_instance(&mut self, mesh: MeshId, materials: &[MaterialId], transform: Mat34) -> InstanceId {
+        InstanceId(
+            self.instances
+                .insert(Instance { mesh, materials: materials.to_vec(), transform, visible: true }),
+        )
+    }
+
+    pub fn update_transform(&mut self, id: InstanceId, transform: Mat34) {
+        if let Some(instance) = self.instances.get_mut(id.0) {
+            instance.transform = transform;
+        }
+    }
+
+    // Hides the instance without removing it.
+    pub fn set_instance_visible(&mut self, id: InstanceId, visible: bool) {
+        if let Some(instance) = self.instances.get_mut(id.0) {
+            instance.visible = visible;
+        }
+    }
+
+    pub fn remove_instance(&mut self, id: InstanceId) {
+        self.instances.remove(id.0);
+    }
+
+    pub fn add_light(&mut self, light: Light) -> LightId {
+        LightId(self.lights.insert(light))
+    }
+
+    pub fn update_light(&mut self, id: LightId, light: Light) {
+        if let Some(existing) = self.lights.get_mut(id.0) {
+            *existing = light;
+        }
+    }
+
+    pub fn remove_light(&mut self, id: LightId) {
+        self.lights.remove(id.0);
+    }
+
+    // Sets the world-to-camera transform and the projection, the same as in RasterizationCommand.
+    pub fn set_camera(&mut self, view: Mat44, projection: Mat44) {
+        self.view = view;
+        self.projection = projection;
+    }
+
+    // Sets the light reaching every surface regardless of the lights, only applied when there's at least one light.
+    // Default: (0.2, 0.2, 0.2).
+    pub fn set_ambient(&mut self, ambient: Vec3) {
+        self.ambient = ambient;
+    }
+
+    // Sets the units the lights are given in, which allows composing the daylight with the artificial lights.
+    // Default: LightUnits::Relative.
+    pub fn set_light_units(&mut self, units: LightUnits) {
+        self.light_units = units;
+    }
+
+    // Sets the probe the ambient light comes from instead of the constant ambient: the roughest level of the probe
+    // sampled in the direction of each pixel's normal. See bake_probe().
+    // Default: None.
+    pub fn set_environment_probe(&mut self, probe: Option<Arc<EnvironmentProbe>>) {
+        self.probe = probe;
+    }
+
+    // Sets the values the framebuffer is cleared with before drawing, None keeps its contents.
+    // Default: ClearValues::default().
+    pub fn set_clear_values(&mut self, clear_values: Option<ClearValues>) {
+        self.clear_values = clear_values;
+    }
+
+    pub fn statistics(&self) -> RenderWorldStatistics {
+        self.statistics
+    }
+
+    // Captures the world as seen from the position into the six faces of a cube map and prefilters them for the rough
+    // surfaces, see EnvironmentProbe. The faces are rendered as usual, lit and cleared with the clear values.
+    // The camera and the statistics are kept.
+    pub fn bake_probe(
+        &mut self,
+        rasterizer: &mut Rasterizer,
+        position: Vec3,
+        parameters: &ProbeBaking,
+    ) -> EnvironmentProbe {
+        assert!(parameters.size.is_power_of_two(), "the size of a probe must be a power of two");
+        let (view, projection, statistics): (Mat44, Mat44, RenderWorldStatistics) =
+            (self.view, self.projection, self.statistics);
+        let size: u16 = parameters.size;
+        let mut color = TiledBuffer::<u32, 64, 64>::new(size, size);
+        let mut depth = TiledBuffer::<u16, 64, 64>::new(size, size);
+        let mut normals = TiledBuffer::<u32, 64, 64>::new(size, size);
+        let face_projection: Mat44 =
+            Mat44::perspective(parameters.near, parameters.far, std::f32::consts::FRAC_PI_2, 1.0);
+        let faces: [Arc<Texture>; 6] = std::array::from_fn(|index| {
+            self.set_camera(CubeFace::ALL[index].view(position), face_projection);
+            self.render(
+                rasterizer,
+                &mut Framebuffer {
+                    color_buffer: Some(&mut color),
+                    depth_buffer: Some(&mut depth),
+                    normal_buffer: Some(&mut normals),
+                    ..Default::default()
+                },
+            );
+            // The rows are flipped, as the faces' v axis points up
+            let mut texels: Vec<u8> = Vec::with_capacity(size as usize * size as usize * 4);
+            for y in (0..size).rev() {
+                for x in 0..size {
+                    let pixel: RGBA = RGBA::from_u32(color.at(x, y));
+                    texels.extend_from_slice(&[pixel.r, pixel.g, pixel.b, 255]);
+                }
+            }
+            Texture::new(&TextureSource {
+                texels: &texels,
+                width: size as u32,
+                height: size as u32,
+                format: TextureFormat::RGBA,
+                ..Default::default()
+            })
+        });
+        self.view = view;
+        self.projection = projection;
+        self.statistics = statistics;
+        EnvironmentProbe::prefilter(position, faces, parameters)
+    }
+
+    // Renders the world into the entire framebuffer, setting the rasterizer up for its size.
+    // Lighting requires the color, either of the depth and the normal buffers, otherwise the colors are left unlit.
+    // As the lighting is deferred, the blended surfaces are lit with the normals stored beneath them.
+    pub fn render(&mut self, rasterizer: &mut Rasterizer, framebuffer: &mut Framebuffer) {
+        let (width, height): (u16, u16) = (framebuffer.width(), framebuffer.height());
+        rasterizer.setup(Viewport::new(0, 0, width, height));
+        if let Some(clear_values) = self.clear_values.as_ref() {
+            framebuffer.clear_all(clear_values);
+        }
+
+        self.build_draws();
+        for draw in &self.draws {
+            let instance: &Instance = self.instances.get(draw.instance).unwrap();
+            let mesh: &MeshData = &self.meshes.get(instance.mesh.0).unwrap().data;
+            let default_material: Material = Material::default();
+            let material: &Material = draw
+                .material
+                .and_then(|id| self.materials.get(id.0))
+                .unwrap_or(&default_material);
+            rasterizer.commit(&material.apply(RasterizationCommand {
+                world_positions: &mesh.positions,
+                normals: &mesh.normals,
+                tex_coords: &mesh.tex_coords,
+                colors: &mesh.colors,
+                indices: &mesh.indices[draw.indices.clone()],
+                model: instance.transform,
+                view: self.view,
+                projection: self.projection,
+                opacity: if draw.blended {
+                    OpacityHint::Translucent
+                } else {
+                    OpacityHint::Opaque
+                },
+                ..Default::default()
+            }));
+        }
+        self.statistics.committed_commands = self.draws.len();
+        rasterizer.draw(framebuffer);
+
+        if self.lights.iter().next().is_some() || self.probe.is_some() {
+            self.apply_lighting(framebuffer);
+        }
+    }
+
+    // Culls the instances and fills the draw list in the order of committing.
+    fn build_draws(&mut self) {
+        self.draws.clear();
+        self.statistics = RenderWorldStatistics::default();
+        let view_projection: Mat44 = self.projection * self.view;
+        for (index, instance) in self.instances.iter() {
+            let Some(mesh) = self.meshes.get(instance.mesh.0) else {
+                continue;
+            };
+            if !instance.visible {
+                continue;
+            }
+            if is_outside_frustum(&mesh.aabb, &(view_projection * instance.transform.as_mat44()), false) {
+                self.statistics.culled_instances += 1;
+                continue;
+            }
+            self.statistics.visible_instances += 1;
+
+            let center: Vec3 = instance.transform * ((mesh.aabb.min + mesh.aabb.max) * 0.5);
+            let distance: f32 = -(self.view * center.as_point4()).z;
+            let mut push = |indices: std::ops::Range<usize>, material_index: usize| {
+                let material: Option<MaterialId> = instance.materials.get(material_index).copied();
+                let blended: bool = material
+                    .and_then(|id| self.materials.get(id.0))
+                    .is_some_and(|material| material.alpha_blending != AlphaBlendingMode::None);
+                self.draws
+                    .push(Draw { instance: index, indices, material, blended, distance });
+            };
+            if mesh.data.sections.is_empty() {
+                push(0..mesh.data.indices.len(), 0);
+            } else {
+                for section in &mesh.data.sections {
+                    push(section.index_range(), section.material_index);
+                }
+            }
+        }
+
+        // Opaque front-to-back to make the most of the depth test, blended back-to-front to compose correctly
+        self.draws.sort_by(|a, b| {
+            a.blended.cmp(&b.blended).then_with(|| {
+                if a.blended {
+                    b.distance.total_cmp(&a.distance)
+                } else {
+                    a.distance.total_cmp(&b.distance)
+                }
+            })
+        });
+    }
+
+    fn apply_lighting(&self, framebuffer: &mut Framebuffer) {
+        if framebuffer.color_buffer.is_none()
+            || (framebuffer.depth_buffer.is_none() && framebuffer.depth_buffer_f32.is_none())
+            || framebuffer.normal_buffer.is_none()
+        {
+            return;
+        }
+        let lights: Vec<Light> = self.lights.iter().map(|(_, light)| *light).collect();
+        // The multipliers of the lights' illuminance and of the ambient light
+        let (light_scale, ambient_scale): (f32, f32) = match self.light_units {
+            LightUnits::Relative => (1.0, 1.0),
+            LightUnits::Photometric(camera) => (camera.scale() / std::f32::consts::PI, camera.scale()),
+        };
+        let photometric: bool = matches!(self.light_units, LightUnits::Photometric(_));
+        let ambient: Vec3 = self.ambient * ambient_scale;
+        let probe: Option<Arc<CubeMap>> = self.probe.as_ref().and_then(|probe| probe.levels.last().cloned());
+        let inverse_view_projection: Mat44 = (self.projection * self.view).inverse();
+        let (width, height): (f32, f32) = (framebuffer.width() as f32, framebuffer.height() as f32);
+        framebuffer.for_each_tile_mut_parallel(move |tile| {
+            let environment: Option<EnvironmentSampler> = probe
+                .as_ref()
+                .map(|cube_map| EnvironmentSampler::new(&EnvironmentMap::Cube(cube_map.clone())));
+            let color_format: ColorBufferFormat = tile.color_format;
+            let (origin_x, origin_y): (u16, u16) = (tile.origin_x(), tile.origin_y());
+            let depth_tile = tile.depth_buffer.as_ref();
+            let depth_f32_tile = tile.depth_buffer_f32.as_ref();
+            // The depth of the pixel in NDC, None if nothing was drawn there
+            let ndc_z = |x: usize, y: usize| -> Option<f32> {
+                match (depth_tile, depth_f32_tile) {
+                    (Some(depth_tile), _) => {
+                        let depth: u16 = depth_tile.at_unchecked(x, y);
+                        (depth != DEPTH_FAR).then(|| decode_depth(depth))
+                    }
+                    (None, Some(depth_tile)) => {
+                        let depth: f32 = depth_tile.at_unchecked(x, y);
+                        (depth != DEPTH_F32_FAR).then(|| decode_depth_f32(depth))
+                    }
+                    (None, None) => None,
+                }
+            };
+            let normal_tile = tile.normal_buffer.as_ref().unwrap();
+            let color_tile = tile.color_buffer.as_mut().unwrap();
+            for y in 0..normal_tile.height as usize {
+                for x in 0..normal_tile.width as usize {
+                    let encoded_normal: RGBA = RGBA::from_u32(normal_tile.at_unchecked(x, y));
+                    let Some(depth) = ndc_z(x, y) else {
+                        continue;
+                    };
+                    if is_normal_none(encoded_normal) {
+                        continue;
+                    }
+                    let normal: Vec3 = decode_normal_from_color(encoded_normal).normalized();
+                    let ndc: Vec4 = Vec4::new(
+                        (origin_x as usize + x) as f32 + 0.5,
+                        (origin_y as usize + y) as f32 + 0.5,
+                        depth,
+                        1.0,
+                    );
+                    let ndc: Vec4 = Vec4::new(ndc.x / width * 2.0 - 1.0, 1.0 - ndc.y / height * 2.0, ndc.z, 1.0);
+                    let world: Vec4 = inverse_view_projection * ndc;
+                    let position: Vec3 = world.xyz() / world.w;
+                    let mut light: Vec3 = match &environment {
+                        Some(environment) => environment.sample(normal),
+                        None => ambient,
+                    };
+                    for source in &lights {
+                        light += illuminance(source, position, normal, photometric) * light_scale;
+                    }
+
+                    let pixel: &mut u32 = color_tile.get_unchecked(x, y);
+                    *pixel = match color_format {
+                        ColorBufferFormat::Rgba8 => {
+                            let color: RGBA = RGBA::from_u32(*pixel);
+                            let lit: Vec3 =
+                                (Vec3::new(color.r as f32, color.g as f32, color.b as f32) * light).clamped(0.0, 255.0);
+                            RGBA::new(lit.x as u8, lit.y as u8, lit.z as u8, color.a).to_u32()
+                        }
+                        ColorBufferFormat::Rgb9e5 => {
+                            let lit: Vec3 = decode_rgb9e5(*pixel) * light;
+                            encode_rgb9e5(lit.x, lit.y, lit.z)
+                        }
+                    };
+                }
+            }
+        });
+    }
+}
+
+impl Light {
+    // The direction from the position towards the light and the light's color reaching the position, if any.
+    pub fn incident(&self, position: Vec3) -> Option<(Vec3, Vec3)> {
+        match *self {
+            Light::Directional { direction, color } => Some((-direction.normalized(), color)),
+            Light::Point { position: light_position, color, range } => {
+                let to_light: Vec3 = light_position - position;
+                let distance: f32 = to_light.length();
+                if distance >= range || distance <= f32::EPSILON {
+                    return None;
+                }
+                let falloff: f32 = 1.0 - (distance / range) * (distance / range);
+                Some((to_light / distance, color * (falloff * falloff)))
+            }
+        }
+    }
+}
+
+// The light received from the source by a surface at the position facing the normal. The photometric point lights also
+// fall off with the square of the distance, which is clamped to 1cm.
+fn illuminance(light: &Light, position: Vec3, normal: Vec3, photometric: bool) -> Vec3 {
+    let Some((direction, color)) = light.incident(position) else {
+        return Vec3::new(0.0, 0.0, 0.0);
+    };
+    let received: Vec3 = color * dot(normal, direction).max(0.0);
+    match light {
+        Light::Point { position: light_position, .. } if photometric => {
+            let to_light: Vec3 = *light_position - position;
+            received / dot(to_light, to_light).max(0.0001)
+        }
+        _ => received,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A unit quad in the XY plane facing +Z, with two sections: the left and the right triangles
+    fn quad() -> Arc<MeshData> {
+        let mut mesh = MeshData {
+            positions: vec![
+                Vec3::new(-0.5, -0.5, 0.0),
+                Vec3::new(0.5, -0.5, 0.0),
+                Vec3::new(0.5, 0.5, 0.0),
+                Vec3::new(-0.5, 0.5, 0.0),
+            ],
+            normals: vec![Vec3::new(0.0, 0.0, 1.0); 4],
+            indices: vec![0, 2, 3, 0, 1, 2],
+            ..Default::default()
+        };
+        mesh.sections.push(MeshDataSection {
+            start_index: 0,
+            num_triangles: 1,
+            material_index: 0,
+            ..Default::default()
+        });
+        mesh.sections.push(MeshDataSection {
+            start_index: 3,
+            num_triangles: 1,
+            material_index: 1,
+            ..Default::default()
+        });
+        Arc::new(mesh)
+    }
+
+    // A camera at (0, 0, 2) looking along -Z
+    fn world() -> RenderWorld {
+        let mut world = RenderWorld::new();
+        world.set_camera(
+            Mat34::translate(Vec3::new(0.0, 0.0, -2.0)).as_mat44(),
+            Mat44::perspective(0.1, 10.0, std::f32::consts::PI / 2.0, 1.0),
+        );
+        world
+    }
+
+    struct Buffers {
+        color: TiledBuffer<u32, 64, 64>,
+        depth: TiledBuffer<u16, 64, 64>,
+        normals: TiledBuffer<u32, 64, 64>,
+    }
+
+    fn render(world: &mut RenderWorld) -> Buffers {
+        let mut buffers = Buffers {
+            color: TiledBuffer::new(80, 80),
+            depth: TiledBuffer::new(80, 80),
+            normals: TiledBuffer::new(80, 80),
+        };
+        let mut rasterizer = Rasterizer::new();
+        world.render(
+            &mut rasterizer,
+            &mut Framebuffer {
+                color_buffer: Some(&mut buffers.color),
+                depth_buffer: Some(&mut buffers.depth),
+                normal_buffer: Some(&mut buffers.normals),
+                ..Default::default()
+            },
+        );
+        buffers
+    }
+
+    #[test]
+    fn sections_use_their_materials() {
+        let mut world = world();
+        let mesh: MeshId = world.add_mesh(quad());
+        let red: MaterialId =
+            world.add_material(Material { color: Vec4::new(1.0, 0.0, 0.0, 1.0), ..Default::default() });
+        let blue: MaterialId =
+            world.add_material(Material { color: Vec4::new(0.0, 0.0, 1.0, 1.0), ..Default::default() });
+        world.add_instance(mesh, &[red, blue], Mat34::identity());
+        let buffers = render(&mut world);
+        // The quad covers the middle quarter of the viewport, the top-left triangle is red, the bottom-right one is blue
+        assert_eq!(RGBA::from_u32(buffers.color.at(33, 36)), RGBA::new(255, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(buffers.color.at(46, 44)), RGBA::new(0, 0, 255, 255));
+        assert_eq!(RGBA::from_u32(buffers.color.at(5, 5)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(world.statistics().committed_commands, 2);
+    }
+
+    #[test]
+    fn instances_outside_the_view_are_culled() {
+        let mut world = world();
+        let mesh: MeshId = world.add_mesh(quad());
+        let instance: InstanceId = world.add_instance(mesh, &[], Mat34::translate(Vec3::new(100.0, 0.0, 0.0)));
+        world.add_instance(mesh, &[], Mat34::identity());
+        let buffers = render(&mut world);
+        assert_eq!(world.statistics().visible_instances, 1);
+        assert_eq!(world.statistics().culled_instances, 1);
+        assert_eq!(RGBA::from_u32(buffers.color.at(40, 40)), RGBA::new(255, 255, 255, 255));
+
+        // Moved into the view, in front of the other one
+        world.update_transform(instance, Mat34::translate(Vec3::new(0.0, 0.0, 1.0)));
+        render(&mut world);
+        assert_eq!(world.statistics().visible_instances, 2);
+        assert_eq!(world.statistics().culled_instances, 0);
+
+        world.set_instance_visible(instance, false);
+        render(&mut world);
+        assert_eq!(world.statistics().visible_instances, 1);
+        assert_eq!(world.statistics().culled_instances, 0);
+    }
+
+    #[test]
+    fn blended_sections_are_drawn_after_opaque_ones() {
+        let mut world = world();
+        let mesh: MeshId = world.add_mesh(quad());
+        let glass: MaterialId = world.add_material(Material {
+            color: Vec4::new(0.0, 0.0, 1.0, 0.5),
+            alpha_blending: AlphaBlendingMode::Normal,
+            ..Default::default()
+        });
+        let green: MaterialId =
+            world.add_material(Material { color: Vec4::new(0.0, 1.0, 0.0, 1.0), ..Default::default() });
+        // The glass is in front, but is added first
+        world.add_instance(mesh, &[glass, glass], Mat34::translate(Vec3::new(0.0, 0.0, 0.5)));
+        world.add_instance(mesh, &[green, green], Mat34::identity());
+        let buffers = render(&mut world);
+        let color: RGBA = RGBA::from_u32(buffers.color.at(40, 40));
+        assert!(color.g.abs_diff(128) <= 2 && color.b.abs_diff(128) <= 2, "{:?}", color);
+    }
+
+    #[test]
+    fn lights_shade_the_surfaces() {
+        let mut world = world();
+        let mesh: MeshId = world.add_mesh(quad());
+        world.add_instance(mesh, &[], Mat34::identity());
+        world.set_ambient(Vec3::new(0.25, 0.25, 0.25));
+        let sun: LightId = world
+            .add_light(Light::Directional { direction: Vec3::new(0.0, 0.0, -1.0), color: Vec3::new(0.5, 0.25, 0.0) });
+        let buffers = render(&mut world);
+        let color: RGBA = RGBA::from_u32(buffers.color.at(40, 40));
+        assert!(color.r.abs_diff(191) <= 2 && color.g.abs_diff(127) <= 2 && color.b.abs_diff(63) <= 2, "{:?}", color);
+        // The background is not lit
+        assert_eq!(RGBA::from_u32(buffers.color.at(5, 5)), RGBA::new(0, 0, 0, 255));
+
+        // A point light barely reaching the quad adds almost nothing
+        world.remove_light(sun);
+        world.add_light(Light::Point {
+            position: Vec3::new(0.0, 0.0, 1.0),
+            color: Vec3::new(1.0, 1.0, 1.0),
+            range: 1.01,
+        });
+        let buffers = render(&mut world);
+        let color: RGBA = RGBA::from_u32(buffers.color.at(40, 40));
+        assert!(color.r.abs_diff(63) <= 2, "{:?}", color);
+    }
+
+    #[test]
+    fn lights_shade_the_surfaces_with_f32_depth() {
+        let mut world = world();
+        let mesh: MeshId = world.add_mesh(quad());
+        world.add_instance(mesh, &[], Mat34::rotate_yz(0.5));
+        world.add_light(Light::Point {
+            position: Vec3::new(0.3, -0.2, 0.6),
+            color: Vec3::new(1.0, 1.0, 1.0),
+            range: 1.5,
+        });
+        let expected = render(&mut world);
+
+        let mut color: TiledBuffer<u32, 64, 64> = TiledBuffer::new(80, 80);
+        let mut depth: TiledBuffer<f32, 64, 64> = TiledBuffer::new(80, 80);
+        let mut normals: TiledBuffer<u32, 64, 64> = TiledBuffer::new(80, 80);
+        world.render(
+            &mut Rasterizer::new(),
+            &mut Framebuffer {
+                color_buffer: Some(&mut color),
+                depth_buffer_f32: Some(&mut depth),
+                normal_buffer: Some(&mut normals),
+                ..Default::default()
+            },
+        );
+        // The point light's falloff depends on the positions reconstructed from the depth, they match the 16-bit ones
+        let lit: RGBA = RGBA::from_u32(color.at(40, 40));
+        assert!(lit.r > 100 && lit.r < 250, "{:?}", lit);
+        for (x, y) in [(30, 34), (40, 40), (48, 44), (36, 47)] {
+            let a: RGBA = RGBA::from_u32(color.at(x, y));
+            let b: RGBA = RGBA::from_u32(expected.color.at(x, y));
+            assert!(a.r.abs_diff(b.r) <= 2 && a.g.abs_diff(b.g) <= 2, "({}, {}): {:?} != {:?}", x, y, a, b);
+        }
+        // The background is not lit
+        assert_eq!(RGBA::from_u32(color.at(5, 5)), RGBA::new(0, 0, 0, 255));
+    }
+
+    #[test]
+    fn handles_are_reused() {
+        let mut world = world();
+        let mesh: MeshId = world.add_mesh(quad());
+        let first: InstanceId = world.add_instance(mesh, &[], Mat34::identity());
+        world.remove_instance(first);
+        let second: InstanceId = world.add_instance(mesh, &[], Mat34::identity());
+        assert_eq!(first, second);
+
+        // The instances of a removed mesh are not drawn
+        assert!(world.remove_mesh(mesh).is_some());
+        render(&mut world);
+        assert_eq!(world.statistics().visible_instances, 0);
+        assert!(world.remove_mesh(mesh).is_none());
+    }
+
+    #[test]
+    fn baked_probe_captures_surroundings() {
+        let mut world = world();
+        let mesh: MeshId = world.add_mesh(quad());
+        let material = |color: Vec4| Material { color, culling: CullMode::None, ..Default::default() };
+        let red: MaterialId = world.add_material(material(Vec4::new(1.0, 0.0, 0.0, 1.0)));
+        let green: MaterialId = world.add_material(material(Vec4::new(0.0, 1.0, 0.0, 1.0)));
+        // A red wall to the right and a small green patch on the ceiling, towards +Z
+        world.add_instance(
+            mesh,
+            &[red, red],
+            Mat34::translate(Vec3::new(2.0, 0.0, 0.0))
+                * Mat34::rotate_zx(std::f32::consts::FRAC_PI_2)
+                * Mat34::scale_uniform(4.0),
+        );
+        world.add_instance(
+            mesh,
+            &[green, green],
+            Mat34::translate(Vec3::new(0.0, 2.0, 1.0)) * Mat34::rotate_yz(std::f32::consts::FRAC_PI_2),
+        );
+        let probe: EnvironmentProbe = world.bake_probe(
+            &mut Rasterizer::new(),
+            Vec3::new(0.0, 0.0, 0.0),
+            &ProbeBaking { size: 16, levels: 3, samples: 32, ..Default::default() },
+        );
+        assert_eq!(probe.levels.len(), 3);
+        let near = |a: Vec3, b: Vec3| (a - b).length() < 0.05;
+        assert!(near(probe.sample(Vec3::new(1.0, 0.0, 0.0), 0.0), Vec3::new(1.0, 0.0, 0.0)));
+        assert!(near(probe.sample(Vec3::new(1.0, 0.3, -0.2), 0.0), Vec3::new(1.0, 0.0, 0.0)));
+        assert!(near(probe.sample(Vec3::new(-1.0, 0.0, 0.0), 0.0), Vec3::new(0.0, 0.0, 0.0)));
+        assert!(near(probe.sample(Vec3::new(0.0, 2.0, 1.0), 0.0), Vec3::new(0.0, 1.0, 0.0)));
+        assert!(near(probe.sample(Vec3::new(0.0, 2.0, -1.0), 0.0), Vec3::new(0.0, 0.0, 0.0)));
+        // The rough levels spread the wall's color around
+        let rough: Vec3 = probe.sample(Vec3::new(0.0, 0.0, 1.0), 1.0);
+        assert!(rough.x > 0.05 && rough.x < 0.9, "{:?}", rough);
+
+        // The camera and the statistics are kept
+        assert_eq!(world.view, Mat34::translate(Vec3::new(0.0, 0.0, -2.0)).as_mat44());
+        assert_eq!(world.statistics(), RenderWorldStatistics::default());
+    }
+
+    #[test]
+    fn probe_provides_ambient_light() {
+        let mut world = world();
+        let mesh: MeshId = world.add_mesh(quad());
+        world.add_instance(mesh, &[], Mat34::identity());
+        let texels: Vec<u8> = [128u8, 64, 255, 255].repeat(16);
+        let face = || {
+            Texture::new(&TextureSource {
+                texels: &texels,
+                width: 4,
+                height: 4,
+                format: TextureFormat::RGBA,
+                ..Default::default()
+            })
+        };
+        let probe = EnvironmentProbe::prefilter(
+            Vec3::new(0.0, 0.0, 0.0),
+            std::array::from_fn(|_| face()),
+            &ProbeBaking::default(),
+        );
+        world.set_environment_probe(Some(Arc::new(probe)));
+        let buffers = render(&mut world);
+        let color: RGBA = RGBA::from_u32(buffers.color.at(40, 40));
+        assert!(color.r.abs_diff(128) <= 2 && color.g.abs_diff(64) <= 2 && color.b.abs_diff(255) <= 2, "{:?}", color);
+        assert_eq!(RGBA::from_u32(buffers.color.at(5, 5)), RGBA::new(0, 0, 0, 255));
+    }
+
+    #[test]
+    fn photometric_lights_are_exposed() {
+        let mut world = world();
+        let mesh: MeshId = world.add_mesh(quad());
+        world.add_instance(mesh, &[], Mat34::identity());
+        world.set_ambient(Vec3::new(0.0, 0.0, 0.0));
+        world.set_light_units(LightUnits::Photometric(CameraExposure::from_ev100(15.0)));
+        let sun: LightId = world.add_light(Light::Directional {
+            direction: Vec3::new(0.0, 0.0, -1.0),
+            color: Vec3::new(100000.0, 100000.0, 100000.0),
+        });
+        // 100000 / pi cd/m², exposed by 1 / (1.2 * 2^15)
+        let color: RGBA = RGBA::from_u32(render(&mut world).color.at(40, 40));
+        assert!(color.r.abs_diff(206) <= 2, "{:?}", color);
+
+        // The point lights fall off with the square of the distance
+        world.remove_light(sun);
+        world.set_light_units(LightUnits::Photometric(CameraExposure::from_ev100(0.0)));
+        let intensity: f32 = 0.8 * std::f32::consts::PI * 1.2;
+        let lamp: LightId = world.add_light(Light::Point {
+            position: Vec3::new(0.0, 0.0, 1.0),
+            color: Vec3::new(intensity, intensity, intensity),
+            range: 1000.0,
+        });
+        let color: RGBA = RGBA::from_u32(render(&mut world).color.at(40, 40));
+        assert!(color.r.abs_diff(204) <= 2, "{:?}", color);
+        world.update_light(
+            lamp,
+            Light::Point {
+                position: Vec3::new(0.0, 0.0, 2.0),
+                color: Vec3::new(intensity, intensity, intensity),
+                range: 1000.0,
+            },
+        );
+        let color: RGBA = RGBA::from_u32(render(&mut world).color.at(40, 40));
+        assert!(color.r.abs_diff(51) <= 2, "{:?}", color);
+    }
+}
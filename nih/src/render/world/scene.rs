@@ -0,0 +1,515 @@
+use super::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// A serializable snapshot of a RenderWorld: the material parameters, the instances, the lights and the camera.
+// The meshes and the textures are not stored, they're referred to by their paths and resolved via SceneAssets, which
+// keeps the scene files small enough to be checked into a repository next to the assets.
+// The handles are not preserved, the objects are renumbered densely when saving.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneDescription {
+    // The paths of the meshes, the instances refer to them by index.
+    pub meshes: Vec<String>,
+
+    #[serde(default)]
+    pub materials: Vec<MaterialDescription>,
+
+    #[serde(default)]
+    pub instances: Vec<InstanceDescription>,
+
+    #[serde(default)]
+    pub lights: Vec<Light>,
+
+    pub view: Mat44,
+    pub projection: Mat44,
+    pub ambient: Vec3,
+
+    // Default: LightUnits::Relative.
+    #[serde(default = "default_light_units")]
+    pub light_units: LightUnits,
+}
+
+// Material with the textures referred to by their paths. The omitted fields take the values of Material::default().
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialDescription {
+    pub color: Vec4,
+    pub texture: Option<String>,
+    pub normal_map: Option<String>,
+    pub sampling_filter: SamplerFilter,
+    pub culling: CullMode,
+    pub alpha_blending: AlphaBlendingMode,
+    pub alpha_test: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstanceDescription {
+    // The index in SceneDescription::meshes.
+    pub mesh: usize,
+
+    // The indices in SceneDescription::materials, per the mesh's material_index.
+    #[serde(default)]
+    pub materials: Vec<usize>,
+
+    pub transform: Mat34,
+
+    #[serde(default = "default_visible")]
+    pub visible: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SceneFormat {
+    Ron,
+    Json,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    // Saving: the mesh is not in SceneAssets, so it has no path.
+    UnregisteredMesh(MeshId),
+
+    // Saving: a texture of the material is not in SceneAssets, so it has no path.
+    UnregisteredTexture(MaterialId),
+
+    // Loading: there's no mesh with this path in SceneAssets.
+    MissingMesh(String),
+
+    // Loading: there's no texture with this path in SceneAssets.
+    MissingTexture(String),
+
+    // Loading: an instance refers to a mesh or to a material out of the description's range.
+    InvalidIndex { instance: usize, index: usize },
+
+    // The text is not a valid scene in the format.
+    Syntax(String),
+
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::UnregisteredMesh(id) => write!(f, "mesh {:?} has no path in the scene assets", id),
+            SceneError::UnregisteredTexture(id) => {
+                write!(f, "a texture of material {:?} has no path in the scene assets", id)
+            }
+            SceneError::MissingMesh(path) => write!(f, "mesh \"{}\" is not in the scene assets", path),
+            SceneError::MissingTexture(path) => write!(f, "texture \"{}\" is not in the scene assets", path),
+            SceneError::InvalidIndex { instance, index } => {
+                write!(f, "instance {} refers to a non-existent object {}", instance, index)
+            }
+            SceneError::Syntax(message) => write!(f, "invalid scene: {}", message),
+            SceneError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<std::io::Error> for SceneError {
+    fn from(error: std::io::Error) -> Self {
+        SceneError::Io(error)
+    }
+}
+
+// The meshes and the textures of the scenes, known by their paths. The paths are opaque to the scene, these are just
+// the keys the application loads its assets by.
+#[derive(Default, Clone)]
+pub struct SceneAssets {
+    meshes: Vec<(String, Arc<MeshData>)>,
+    textures: Vec<(String, Arc<Texture>)>,
+}
+
+impl SceneAssets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Registers the mesh, replacing the one previously registered with the same path.
+    pub fn add_mesh(&mut self, path: &str, mesh: Arc<MeshData>) {
+        self.meshes.retain(|(existing, _)| existing != path);
+        self.meshes.push((path.to_string(), mesh));
+    }
+
+    // Registers the texture, replacing the one previously registered with the same path.
+    pub fn add_texture(&mut self, path: &str, texture: Arc<Texture>) {
+        self.textures.retain(|(existing, _)| existing != path);
+        self.textures.push((path.to_string(), texture));
+    }
+
+    pub fn mesh(&self, path: &str) -> Option<&Arc<MeshData>> {
+        self.meshes
+            .iter()
+            .find(|(existing, _)| existing == path)
+            .map(|(_, mesh)| mesh)
+    }
+
+    pub fn texture(&self, path: &str) -> Option<&Arc<Texture>> {
+        self.textures
+            .iter()
+            .find(|(existing, _)| existing == path)
+            .map(|(_, texture)| texture)
+    }
+
+    // The assets are matched by identity, not by contents.
+    fn mesh_path(&self, mesh: &Arc<MeshData>) -> Option<&str> {
+        self.meshes
+            .iter()
+            .find(|(_, existing)| Arc::ptr_eq(existing, mesh))
+            .map(|(path, _)| path.as_str())
+    }
+
+    fn texture_path(&self, texture: &Arc<Texture>) -> Option<&str> {
+        self.textures
+            .iter()
+            .find(|(_, existing)| Arc::ptr_eq(existing, texture))
+            .map(|(path, _)| path.as_str())
+    }
+}
+
+impl Default for MaterialDescription {
+    fn default() -> Self {
+        let material: Material = Material::default();
+        Self {
+            color: material.color,
+            texture: None,
+            normal_map: None,
+            sampling_filter: material.sampling_filter,
+            culling: material.culling,
+            alpha_blending: material.alpha_blending,
+            alpha_test: material.alpha_test,
+        }
+    }
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+fn default_light_units() -> LightUnits {
+    LightUnits::Relative
+}
+
+impl SceneDescription {
+    pub fn parse(text: &str, format: SceneFormat) -> Result<Self, SceneError> {
+        match format {
+            SceneFormat::Ron => ron::from_str(text).map_err(|error| SceneError::Syntax(error.to_string())),
+            SceneFormat::Json => serde_json::from_str(text).map_err(|error| SceneError::Syntax(error.to_string())),
+        }
+    }
+
+    // Pretty-printed to keep the diffs of the checked-in scenes readable.
+    pub fn serialize(&self, format: SceneFormat) -> Result<String, SceneError> {
+        match format {
+            SceneFormat::Ron => ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+                .map_err(|error| SceneError::Syntax(error.to_string())),
+            SceneFormat::Json => {
+                serde_json::to_string_pretty(self).map_err(|error| SceneError::Syntax(error.to_string()))
+            }
+        }
+    }
+
+    // The paths of the textures referred to by the materials, each listed once, to be loaded before instantiating.
+    pub fn texture_paths(&self) -> Vec<&str> {
+        let mut paths: Vec<&str> = Vec::new();
+        for material in &self.materials {
+            for path in material.texture.iter().chain(material.normal_map.iter()) {
+                if !paths.contains(&path.as_str()) {
+                    paths.push(path);
+                }
+            }
+        }
+        paths
+    }
+}
+
+impl SceneFormat {
+    // JSON for the ".json" extension, RON otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => SceneFormat::Json,
+            _ => SceneFormat::Ron,
+        }
+    }
+}
+
+impl RenderWorld {
+    // Describes the world with every mesh and texture it uses looked up in the assets.
+    // The instances of the removed meshes are skipped, the removed materials are saved as default ones.
+    pub fn to_scene(&self, assets: &SceneAssets) -> Result<SceneDescription, SceneError> {
+        let mut scene = SceneDescription {
+            meshes: Vec::new(),
+            materials: Vec::new(),
+            instances: Vec::new(),
+            lights: self.lights.iter().map(|(_, light)| *light).collect(),
+            view: self.view,
+            projection: self.projection,
+            ambient: self.ambient,
+            light_units: self.light_units,
+        };
+
+        // Slot index -> index in the description
+        let mut mesh_indices: Vec<Option<usize>> = vec![None; self.meshes.items.len()];
+        for (index, mesh) in self.meshes.iter() {
+            let path: &str = assets
+                .mesh_path(&mesh.data)
+                .ok_or(SceneError::UnregisteredMesh(MeshId(index)))?;
+            mesh_indices[index as usize] = Some(scene.meshes.len());
+            scene.meshes.push(path.to_string());
+        }
+
+        let mut material_indices: Vec<Option<usize>> = vec![None; self.materials.items.len()];
+        for (index, material) in self.materials.iter() {
+            let texture_path = |texture: &Option<Arc<Texture>>| -> Result<Option<String>, SceneError> {
+                match texture {
+                    Some(texture) => assets
+                        .texture_path(texture)
+                        .map(|path| Some(path.to_string()))
+                        .ok_or(SceneError::UnregisteredTexture(MaterialId(index))),
+                    None => Ok(None),
+                }
+            };
+            material_indices[index as usize] = Some(scene.materials.len());
+            scene.materials.push(MaterialDescription {
+                color: material.color,
+                texture: texture_path(&material.texture)?,
+                normal_map: texture_path(&material.normal_map)?,
+                sampling_filter: material.sampling_filter,
+                culling: material.culling,
+                alpha_blending: material.alpha_blending,
+                alpha_test: material.alpha_test,
+            });
+        }
+
+        let mut default_material: Option<usize> = None;
+        for (_, instance) in self.instances.iter() {
+            let Some(mesh) = mesh_indices.get(instance.mesh.0 as usize).copied().flatten() else {
+                continue;
+            };
+            let mut materials: Vec<usize> = Vec::with_capacity(instance.materials.len());
+            for id in &instance.materials {
+                let index: usize = match material_indices.get(id.0 as usize).copied().flatten() {
+                    Some(index) => index,
+                    None => *default_material.get_or_insert_with(|| {
+                        scene.materials.push(MaterialDescription::default());
+                        scene.materials.len() - 1
+                    }),
+                };
+                materials.push(index);
+            }
+            scene.instances.push(InstanceDescription {
+                mesh,
+                materials,
+                transform: instance.transform,
+                visible: instance.visible,
+            });
+        }
+        Ok(scene)
+    }
+
+    // Creates a world from the description, every mesh and texture it refers to must be in the assets.
+    pub fn from_scene(scene: &SceneDescription, assets: &SceneAssets) -> Result<RenderWorld, SceneError> {
+        let mut world = RenderWorld::new();
+        world.set_camera(scene.view, scene.projection);
+        world.set_ambient(scene.ambient);
+        world.set_light_units(scene.light_units);
+
+        let mut meshes: Vec<MeshId> = Vec::with_capacity(scene.meshes.len());
+        for path in &scene.meshes {
+            let mesh: &Arc<MeshData> = assets.mesh(path).ok_or_else(|| SceneError::MissingMesh(path.clone()))?;
+            meshes.push(world.add_mesh(mesh.clone()));
+        }
+
+        let texture = |path: &Option<String>| -> Result<Option<Arc<Texture>>, SceneError> {
+            match path {
+                Some(path) => assets
+                    .texture(path)
+                    .map(|texture| Some(texture.clone()))
+                    .ok_or_else(|| SceneError::MissingTexture(path.clone())),
+                None => Ok(None),
+            }
+        };
+        let mut materials: Vec<MaterialId> = Vec::with_capacity(scene.materials.len());
+        for material in &scene.materials {
+            materials.push(world.add_material(Material {
+                color: material.color,
+                texture: texture(&material.texture)?,
+                normal_map: texture(&material.normal_map)?,
+                sampling_filter: material.sampling_filter,
+                culling: material.culling,
+                alpha_blending: material.alpha_blending,
+                alpha_test: material.alpha_test,
+            }));
+        }
+
+        for (index, instance) in scene.instances.iter().enumerate() {
+            let invalid = |object: usize| SceneError::InvalidIndex { instance: index, index: object };
+            let mesh: MeshId = *meshes.get(instance.mesh).ok_or_else(|| invalid(instance.mesh))?;
+            let instance_materials: Vec<MaterialId> = instance
+                .materials
+                .iter()
+                .map(|&material| materials.get(material).copied().ok_or_else(|| invalid(material)))
+                .collect::<Result<_, _>>()?;
+            let id: InstanceId = world.add_instance(mesh, &instance_materials, instance.transform);
+            world.set_instance_visible(id, instance.visible);
+        }
+
+        for light in &scene.lights {
+            world.add_light(*light);
+        }
+        Ok(world)
+    }
+
+    // Saves the world into the file, as JSON if the extension is ".json" and as RON otherwise.
+    pub fn save_scene(&self, path: &Path, assets: &SceneAssets) -> Result<(), SceneError> {
+        let text: String = self.to_scene(assets)?.serialize(SceneFormat::from_path(path))?;
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    // Loads the world from the file, as JSON if the extension is ".json" and as RON otherwise.
+    pub fn load_scene(path: &Path, assets: &SceneAssets) -> Result<RenderWorld, SceneError> {
+        let text: String = std::fs::read_to_string(path)?;
+        RenderWorld::from_scene(&SceneDescription::parse(&text, SceneFormat::from_path(path))?, assets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle() -> Arc<MeshData> {
+        Arc::new(MeshData {
+            positions: vec![Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)],
+            indices: vec![0, 1, 2],
+            ..Default::default()
+        })
+    }
+
+    fn checker() -> Arc<Texture> {
+        let texels: [u8; 4] = [0, 255, 255, 0];
+        Texture::new(&TextureSource {
+            texels: &texels,
+            width: 2,
+            height: 2,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        })
+    }
+
+    fn sample_world(assets: &mut SceneAssets) -> RenderWorld {
+        let mesh: Arc<MeshData> = triangle();
+        let texture: Arc<Texture> = checker();
+        assets.add_mesh("meshes/triangle.obj", mesh.clone());
+        assets.add_texture("textures/checker.png", texture.clone());
+
+        let mut world = RenderWorld::new();
+        world.set_camera(Mat34::translate(Vec3::new(0.0, 0.0, -2.0)).as_mat44(), Mat44::identity());
+        world.set_ambient(Vec3::new(0.1, 0.2, 0.3));
+        let mesh: MeshId = world.add_mesh(mesh);
+        let textured: MaterialId = world.add_material(Material {
+            texture: Some(texture),
+            sampling_filter: SamplerFilter::Bilinear,
+            ..Default::default()
+        });
+        let glass: MaterialId = world.add_material(Material {
+            color: Vec4::new(0.0, 0.5, 1.0, 0.5),
+            alpha_blending: AlphaBlendingMode::Normal,
+            culling: CullMode::None,
+            ..Default::default()
+        });
+        world.add_instance(mesh, &[textured], Mat34::identity());
+        let hidden: InstanceId = world.add_instance(mesh, &[glass], Mat34::translate(Vec3::new(1.0, 2.0, 3.0)));
+        world.set_instance_visible(hidden, false);
+        world.add_light(Light::Point {
+            position: Vec3::new(0.0, 1.0, 0.0),
+            color: Vec3::new(1.0, 1.0, 1.0),
+            range: 5.0,
+        });
+        world
+    }
+
+    #[test]
+    fn round_trip_through_both_formats() {
+        let mut assets = SceneAssets::new();
+        let mut world: RenderWorld = sample_world(&mut assets);
+        let units = LightUnits::Photometric(CameraExposure::from_ev100(12.0));
+        world.set_light_units(units);
+        let scene: SceneDescription = world.to_scene(&assets).unwrap();
+        assert_eq!(scene.meshes, vec!["meshes/triangle.obj".to_string()]);
+        assert_eq!(scene.light_units, units);
+        assert_eq!(scene.texture_paths(), vec!["textures/checker.png"]);
+        assert_eq!(scene.instances.len(), 2);
+        assert!(!scene.instances[1].visible);
+
+        for format in [SceneFormat::Ron, SceneFormat::Json] {
+            let text: String = scene.serialize(format).unwrap();
+            let parsed: SceneDescription = SceneDescription::parse(&text, format).unwrap();
+            assert_eq!(parsed, scene);
+
+            let loaded: RenderWorld = RenderWorld::from_scene(&parsed, &assets).unwrap();
+            assert_eq!(loaded.to_scene(&assets).unwrap(), scene);
+        }
+    }
+
+    #[test]
+    fn omitted_fields_take_defaults() {
+        let text = r#"(
+            meshes: ["triangle"],
+            materials: [(color: (x: 1.0, y: 0.0, z: 0.0, w: 1.0))],
+            instances: [(mesh: 0, materials: [0], transform: ((1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0)))],
+            view: ((1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0)),
+            projection: ((1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0)),
+            ambient: (x: 0.2, y: 0.2, z: 0.2),
+        )"#;
+        let scene: SceneDescription = SceneDescription::parse(text, SceneFormat::Ron).unwrap();
+        assert_eq!(scene.materials[0].culling, CullMode::CW);
+        assert_eq!(scene.materials[0].alpha_blending, AlphaBlendingMode::None);
+        assert!(scene.instances[0].visible);
+        assert!(scene.lights.is_empty());
+        assert_eq!(scene.light_units, LightUnits::Relative);
+    }
+
+    #[test]
+    fn unknown_assets_are_reported() {
+        let mut assets = SceneAssets::new();
+        let world: RenderWorld = sample_world(&mut assets);
+        let scene: SceneDescription = world.to_scene(&assets).unwrap();
+
+        assert!(matches!(
+            RenderWorld::from_scene(&scene, &SceneAssets::new()),
+            Err(SceneError::MissingMesh(path)) if path == "meshes/triangle.obj"
+        ));
+        let mut meshes_only = SceneAssets::new();
+        meshes_only.add_mesh("meshes/triangle.obj", triangle());
+        assert!(matches!(
+            RenderWorld::from_scene(&scene, &meshes_only),
+            Err(SceneError::MissingTexture(path)) if path == "textures/checker.png"
+        ));
+        assert!(matches!(world.to_scene(&meshes_only), Err(SceneError::UnregisteredMesh(_))));
+
+        let mut invalid: SceneDescription = scene.clone();
+        invalid.instances[0].mesh = 5;
+        assert!(matches!(
+            RenderWorld::from_scene(&invalid, &assets),
+            Err(SceneError::InvalidIndex { instance: 0, index: 5 })
+        ));
+        assert!(matches!(SceneDescription::parse("{", SceneFormat::Json), Err(SceneError::Syntax(_))));
+    }
+
+    #[test]
+    fn removed_objects_are_not_saved() {
+        let mut assets = SceneAssets::new();
+        let mut world: RenderWorld = sample_world(&mut assets);
+        world.remove_material(MaterialId(0));
+        let orphan: MeshId = world.add_mesh(triangle());
+        world.add_instance(orphan, &[], Mat34::identity());
+        world.remove_mesh(orphan);
+
+        let scene: SceneDescription = world.to_scene(&assets).unwrap();
+        assert_eq!(scene.instances.len(), 2);
+        // The removed textured material is replaced by the default one
+        assert_eq!(scene.materials.len(), 2);
+        assert_eq!(scene.materials[scene.instances[0].materials[0]], MaterialDescription::default());
+    }
+}
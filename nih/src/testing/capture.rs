@@ -0,0 +1,144 @@
+//! Headless rendering of frames into flat buffers, e.g. to compare them against the reference images, see `golden`.
+
+use crate::render::*;
+
+/// Which buffers a frame is rendered into and what they're cleared with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CaptureOptions {
+    pub width: u16,
+    pub height: u16,
+
+    /// Default: `ClearValues::default()`, i.e. opaque black, the far depth and the up normal.
+    pub clear: ClearValues,
+
+    /// Default: true.
+    pub color: bool,
+
+    /// The depth buffer makes the commands depth-tested as usual, so it changes the colors too.
+    /// Default: false.
+    pub depth: bool,
+
+    /// Default: false.
+    pub normals: bool,
+}
+
+impl CaptureOptions {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self { width, height, clear: ClearValues::default(), color: true, depth: false, normals: false }
+    }
+}
+
+/// The flattened buffers of a rendered frame, only the ones requested by `CaptureOptions` are present.
+#[derive(Default)]
+pub struct CapturedFrame {
+    pub color: Option<Buffer<u32>>,
+    pub depth: Option<Buffer<u16>>,
+    pub normals: Option<Buffer<u32>>,
+}
+
+/// Renders a frame with a fresh rasterizer set up for the whole frame: the closure commits the commands, then they're
+/// drawn into the cleared buffers.
+pub fn capture_frame(options: &CaptureOptions, commit: impl FnOnce(&mut Rasterizer)) -> CapturedFrame {
+    capture_frame_with(&mut Rasterizer::new(), options, commit)
+}
+
+/// Same as `capture_frame`, but with the given rasterizer, e.g. the one configured by the test or reused across the
+/// frames. It's set up for the whole frame anew and keeps the statistics of the captured frame afterwards.
+pub fn capture_frame_with(
+    rasterizer: &mut Rasterizer,
+    options: &CaptureOptions,
+    commit: impl FnOnce(&mut Rasterizer),
+) -> CapturedFrame {
+    let (width, height) = (options.width, options.height);
+    let mut color = options.color.then(|| TiledBuffer::<u32, 64, 64>::new(width, height));
+    let mut depth = options.depth.then(|| TiledBuffer::<u16, 64, 64>::new(width, height));
+    let mut normals = options.normals.then(|| TiledBuffer::<u32, 64, 64>::new(width, height));
+    rasterizer.setup(Viewport::new(0, 0, width, height));
+    commit(rasterizer);
+    let mut framebuffer = Framebuffer {
+        color_buffer: color.as_mut(),
+        depth_buffer: depth.as_mut(),
+        normal_buffer: normals.as_mut(),
+        ..Default::default()
+    };
+    framebuffer.clear_all(&options.clear);
+    rasterizer.draw(&mut framebuffer);
+    CapturedFrame {
+        color: color.map(|buffer| buffer.as_flat_buffer()),
+        depth: depth.map(|buffer| buffer.as_flat_buffer()),
+        normals: normals.map(|buffer| buffer.as_flat_buffer()),
+    }
+}
+
+/// The colors of the single command rendered over opaque black.
+pub fn render_to_buffer(width: u16, height: u16, command: &RasterizationCommand) -> Buffer<u32> {
+    let frame = capture_frame(&CaptureOptions::new(width, height), |rasterizer| rasterizer.commit(command));
+    frame.color.unwrap()
+}
+
+/// The depth of the single command rendered without a color buffer, starting from the far depth.
+pub fn render_depth_to_buffer(width: u16, height: u16, command: &RasterizationCommand) -> Buffer<u16> {
+    let options = CaptureOptions { color: false, depth: true, ..CaptureOptions::new(width, height) };
+    capture_frame(&options, |rasterizer| rasterizer.commit(command))
+        .depth
+        .unwrap()
+}
+
+/// The normals of the single command rendered without a depth buffer, the pixels it doesn't cover are `NORMAL_NONE`.
+pub fn render_normals_to_buffer(width: u16, height: u16, command: &RasterizationCommand) -> Buffer<u32> {
+    let options = CaptureOptions {
+        clear: ClearValues { normal: NORMAL_NONE, ..Default::default() },
+        normals: true,
+        ..CaptureOptions::new(width, height)
+    };
+    capture_frame(&options, |rasterizer| rasterizer.commit(command))
+        .normals
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::*;
+
+    #[test]
+    fn captures_requested_buffers() {
+        let command = RasterizationCommand {
+            world_positions: &[Vec3::new(0.0, 1.0, 0.0), Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0)],
+            normals: &[Vec3::new(0.0, 0.0, 1.0); 3],
+            color: Vec4::new(1.0, 0.0, 0.0, 1.0),
+            ..Default::default()
+        };
+        let color: Buffer<u32> = render_to_buffer(16, 8, &command);
+        assert_eq!((color.width, color.height), (16, 8));
+        assert_eq!(RGBA::from_u32(color.at(8, 6)), RGBA::new(255, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(color.at(0, 0)), RGBA::new(0, 0, 0, 255));
+
+        let depth: Buffer<u16> = render_depth_to_buffer(16, 8, &command);
+        assert!(depth.at(8, 6) < DEPTH_FAR);
+        assert_eq!(depth.at(0, 0), DEPTH_FAR);
+
+        let normals: Buffer<u32> = render_normals_to_buffer(16, 8, &command);
+        assert_eq!(RGBA::from_u32(normals.at(8, 6)), encode_normal_as_color(Vec3::new(0.0, 0.0, 1.0)));
+        assert_eq!(RGBA::from_u32(normals.at(0, 0)), NORMAL_NONE);
+
+        let frame = capture_frame(&CaptureOptions { color: false, ..CaptureOptions::new(4, 4) }, |_| {});
+        assert!(frame.color.is_none() && frame.depth.is_none() && frame.normals.is_none());
+    }
+
+    #[test]
+    fn captures_with_given_rasterizer() {
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.set_multithreading(false);
+        let commit = |rasterizer: &mut Rasterizer| {
+            rasterizer.commit(&RasterizationCommand {
+                world_positions: &[Vec3::new(0.0, 1.0, 0.0), Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0)],
+                ..Default::default()
+            })
+        };
+        let options = CaptureOptions::new(16, 8);
+        let frame = capture_frame_with(&mut rasterizer, &options, commit);
+        assert_eq!(rasterizer.statistics().committed_triangles, 1);
+        assert_eq!(frame.color.unwrap().elems, capture_frame(&options, commit).color.unwrap().elems);
+    }
+}
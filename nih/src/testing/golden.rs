@@ -0,0 +1,289 @@
+//! Golden-image tests: the rendered buffers are compared against the reference images stored next to the tests, with
+//! a tolerance for the small differences, e.g. of the rounding. A buffer which doesn't match is saved next to its
+//! reference with `.actual` inserted before the extension, so the two can be inspected side by side and the new result
+//! accepted by renaming it. With the `NIH_UPDATE_REFERENCES` environment variable set, the results are written over
+//! the references instead of being compared.
+
+use crate::render::*;
+use std::path::{Path, PathBuf};
+
+/// The environment variable which makes the checks write the results as the new references.
+pub const UPDATE_REFERENCES_VARIABLE: &str = "NIH_UPDATE_REFERENCES";
+
+/// How much a rendered buffer may differ from its reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tolerance {
+    /// The largest difference of a pixel's channel, or of a depth value, for the pixel to still match.
+    /// Default: 2, i.e. ~1% of an 8-bit channel.
+    pub per_channel: u16,
+
+    /// How many pixels may differ more than that, e.g. along the edges of many small triangles.
+    /// Default: 0.
+    pub mismatched_pixels: usize,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self { per_channel: 2, mismatched_pixels: 0 }
+    }
+}
+
+impl Tolerance {
+    pub fn exact() -> Self {
+        Self { per_channel: 0, mismatched_pixels: 0 }
+    }
+}
+
+/// How a rendered buffer differs from its reference of the same size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Difference {
+    /// The pixels with a channel differing more than the tolerance allows.
+    pub mismatched_pixels: usize,
+
+    /// The largest difference of a channel across all the pixels.
+    pub max_channel_difference: u16,
+}
+
+/// The reason why a rendered buffer doesn't pass the check against its reference.
+#[derive(Debug)]
+pub enum GoldenError {
+    /// There's no reference yet, the result is saved as the actual image.
+    MissingReference { reference: PathBuf, actual: PathBuf },
+
+    /// The result has a different size than the reference, it's saved as the actual image.
+    SizeMismatch {
+        reference: PathBuf,
+        actual: PathBuf,
+        expected: (u16, u16),
+        rendered: (u16, u16),
+    },
+
+    /// Too many pixels differ, the result is saved as the actual image.
+    Mismatch {
+        reference: PathBuf,
+        actual: PathBuf,
+        difference: Difference,
+    },
+
+    /// The reference exists but can't be loaded, or the result can't be saved.
+    Io(ImageIoError),
+}
+
+impl std::fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GoldenError::MissingReference { reference, actual } => {
+                write!(f, "no reference {:?}, the result is saved to {:?}", reference, actual)
+            }
+            GoldenError::SizeMismatch { reference, actual, expected, rendered } => write!(
+                f,
+                "the result is {}x{} while {:?} is {}x{}, the result is saved to {:?}",
+                rendered.0, rendered.1, reference, expected.0, expected.1, actual
+            ),
+            GoldenError::Mismatch { reference, actual, difference } => write!(
+                f,
+                "{} pixels differ from {:?} by up to {}, the result is saved to {:?}",
+                difference.mismatched_pixels, reference, difference.max_channel_difference, actual
+            ),
+            GoldenError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GoldenError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ImageIoError> for GoldenError {
+    fn from(error: ImageIoError) -> Self {
+        GoldenError::Io(error)
+    }
+}
+
+/// Compares the 8-bit RGBA colors channel by channel, None if the buffers have different sizes.
+pub fn compare_colors(actual: &Buffer<u32>, expected: &Buffer<u32>, per_channel: u16) -> Option<Difference> {
+    compare(actual, expected, per_channel, color_difference)
+}
+
+/// Compares the 16-bit depth values, None if the buffers have different sizes.
+pub fn compare_depths(actual: &Buffer<u16>, expected: &Buffer<u16>, per_value: u16) -> Option<Difference> {
+    compare(actual, expected, per_value, |a, b| a.abs_diff(b))
+}
+
+/// Checks the colors against the reference image, see `Buffer::save_color()`.
+pub fn check_colors_against_reference<P: AsRef<Path>>(
+    actual: &Buffer<u32>,
+    reference: P,
+    tolerance: &Tolerance,
+) -> Result<(), GoldenError> {
+    let files =
+        ReferenceFiles { load: |path| Buffer::<u32>::load_color(path), save: |buffer, path| buffer.save_color(path) };
+    check(actual, reference.as_ref(), tolerance, files, color_difference)
+}
+
+/// Checks the normals against the reference image, see `Buffer::save_normals()`.
+pub fn check_normals_against_reference<P: AsRef<Path>>(
+    actual: &Buffer<u32>,
+    reference: P,
+    tolerance: &Tolerance,
+) -> Result<(), GoldenError> {
+    let files = ReferenceFiles {
+        load: |path| Buffer::<u32>::load_normals(path),
+        save: |buffer, path| buffer.save_normals(path),
+    };
+    check(actual, reference.as_ref(), tolerance, files, color_difference)
+}
+
+/// Checks the depth values against the reference image, see `Buffer::save_depth()`.
+pub fn check_depth_against_reference<P: AsRef<Path>>(
+    actual: &Buffer<u16>,
+    reference: P,
+    tolerance: &Tolerance,
+) -> Result<(), GoldenError> {
+    let files =
+        ReferenceFiles { load: |path| Buffer::<u16>::load_depth(path), save: |buffer, path| buffer.save_depth(path) };
+    check(actual, reference.as_ref(), tolerance, files, |a, b| a.abs_diff(b))
+}
+
+/// Same as `check_colors_against_reference()`, but panics with the reason if the check fails.
+pub fn assert_colors_against_reference<P: AsRef<Path>>(actual: &Buffer<u32>, reference: P, tolerance: &Tolerance) {
+    if let Err(error) = check_colors_against_reference(actual, reference, tolerance) {
+        panic!("{}", error);
+    }
+}
+
+/// Same as `check_normals_against_reference()`, but panics with the reason if the check fails.
+pub fn assert_normals_against_reference<P: AsRef<Path>>(actual: &Buffer<u32>, reference: P, tolerance: &Tolerance) {
+    if let Err(error) = check_normals_against_reference(actual, reference, tolerance) {
+        panic!("{}", error);
+    }
+}
+
+/// Same as `check_depth_against_reference()`, but panics with the reason if the check fails.
+pub fn assert_depth_against_reference<P: AsRef<Path>>(actual: &Buffer<u16>, reference: P, tolerance: &Tolerance) {
+    if let Err(error) = check_depth_against_reference(actual, reference, tolerance) {
+        panic!("{}", error);
+    }
+}
+
+/// Where the result checked against the reference is saved, e.g. "foo.actual.png" for "foo.png".
+pub fn actual_path(reference: &Path) -> PathBuf {
+    match reference.extension() {
+        Some(extension) => reference.with_extension(format!("actual.{}", extension.to_string_lossy())),
+        None => reference.with_extension("actual"),
+    }
+}
+
+// The way a kind of buffers is stored as images.
+struct ReferenceFiles<T> {
+    load: fn(&Path) -> Result<Buffer<T>, ImageIoError>,
+    save: fn(&Buffer<T>, &Path) -> Result<(), ImageIoError>,
+}
+
+fn check<T: Copy + bytemuck::Zeroable + bytemuck::Pod>(
+    actual: &Buffer<T>,
+    reference: &Path,
+    tolerance: &Tolerance,
+    files: ReferenceFiles<T>,
+    difference: fn(T, T) -> u16,
+) -> Result<(), GoldenError> {
+    if std::env::var_os(UPDATE_REFERENCES_VARIABLE).is_some() {
+        (files.save)(actual, reference)?;
+        return Ok(());
+    }
+    let actual_path: PathBuf = actual_path(reference);
+    if !reference.exists() {
+        (files.save)(actual, &actual_path)?;
+        return Err(GoldenError::MissingReference { reference: reference.to_path_buf(), actual: actual_path });
+    }
+    let expected: Buffer<T> = (files.load)(reference)?;
+    let error: GoldenError = match compare(actual, &expected, tolerance.per_channel, difference) {
+        Some(difference) if difference.mismatched_pixels <= tolerance.mismatched_pixels => return Ok(()),
+        Some(difference) => {
+            GoldenError::Mismatch { reference: reference.to_path_buf(), actual: actual_path.clone(), difference }
+        }
+        None => GoldenError::SizeMismatch {
+            reference: reference.to_path_buf(),
+            actual: actual_path.clone(),
+            expected: (expected.width, expected.height),
+            rendered: (actual.width, actual.height),
+        },
+    };
+    (files.save)(actual, &actual_path)?;
+    Err(error)
+}
+
+fn compare<T: Copy + bytemuck::Zeroable + bytemuck::Pod>(
+    actual: &Buffer<T>,
+    expected: &Buffer<T>,
+    per_channel: u16,
+    difference: fn(T, T) -> u16,
+) -> Option<Difference> {
+    if (actual.width, actual.height) != (expected.width, expected.height) {
+        return None;
+    }
+    let mut result = Difference::default();
+    for (&a, &b) in actual.rows().flatten().zip(expected.rows().flatten()) {
+        let pixel: u16 = difference(a, b);
+        result.max_channel_difference = result.max_channel_difference.max(pixel);
+        result.mismatched_pixels += (pixel > per_channel) as usize;
+    }
+    Some(result)
+}
+
+fn color_difference(a: u32, b: u32) -> u16 {
+    let (a, b) = (a.to_le_bytes(), b.to_le_bytes());
+    (0..4).map(|i| a[i].abs_diff(b[i]) as u16).max().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mismatches_are_saved_next_to_reference() {
+        let directory: PathBuf = std::env::temp_dir().join(format!("nih_golden_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let reference: PathBuf = directory.join("quad.png");
+        let mut buffer = Buffer::<u32>::new(4, 4);
+        buffer.fill(RGBA::new(10, 20, 30, 255).to_u32());
+
+        // The first run has nothing to compare against
+        let error = check_colors_against_reference(&buffer, &reference, &Tolerance::default()).unwrap_err();
+        assert!(matches!(error, GoldenError::MissingReference { .. }));
+        std::fs::rename(directory.join("quad.actual.png"), &reference).unwrap();
+        assert!(check_colors_against_reference(&buffer, &reference, &Tolerance::default()).is_ok());
+
+        // Small differences are tolerated, the larger ones only in a few pixels
+        *buffer.at_mut(1, 1) = RGBA::new(12, 20, 30, 255).to_u32();
+        assert!(check_colors_against_reference(&buffer, &reference, &Tolerance::default()).is_ok());
+        *buffer.at_mut(2, 2) = RGBA::new(10, 20, 40, 255).to_u32();
+        let error = check_colors_against_reference(&buffer, &reference, &Tolerance::default()).unwrap_err();
+        let GoldenError::Mismatch { difference, actual, .. } = error else {
+            panic!("{:?}", error);
+        };
+        assert_eq!(difference, Difference { mismatched_pixels: 1, max_channel_difference: 10 });
+        assert_eq!(Buffer::<u32>::load_color(&actual).unwrap().elems, buffer.elems);
+        let lenient = Tolerance { mismatched_pixels: 1, ..Default::default() };
+        assert!(check_colors_against_reference(&buffer, &reference, &lenient).is_ok());
+
+        let error = check_colors_against_reference(&Buffer::new(2, 2), &reference, &lenient).unwrap_err();
+        assert!(matches!(error, GoldenError::SizeMismatch { expected: (4, 4), rendered: (2, 2), .. }));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn depths_are_compared_by_values() {
+        let mut a = Buffer::<u16>::new(2, 1);
+        let b = Buffer::<u16>::new(2, 1);
+        *a.at_mut(1, 0) = 300;
+        assert_eq!(compare_depths(&a, &b, 100), Some(Difference { mismatched_pixels: 1, max_channel_difference: 300 }));
+        assert_eq!(compare_depths(&a, &Buffer::new(1, 1), 100), None);
+        assert_eq!(actual_path(Path::new("dir/depth.exr")), Path::new("dir/depth.actual.exr"));
+    }
+}
@@ -0,0 +1,5 @@
+pub mod capture;
+pub mod golden;
+
+pub use capture::*;
+pub use golden::*;
@@ -0,0 +1,173 @@
+/// `FrameTimeHistory` keeps the most recent duration samples (in milliseconds) in a ring buffer,
+/// so that the worst cases can be reported alongside the average, see `FramePacing`.
+#[derive(Debug, Clone)]
+pub struct FrameTimeHistory {
+    samples: Vec<f64>,
+    capacity: usize,
+    next: usize,
+}
+
+/// The distribution of the samples in a `FrameTimeHistory`, all durations are in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FramePacing {
+    /// The number of samples the report is based on.
+    pub samples: usize,
+    pub average: f64,
+    pub median: f64,
+    pub max: f64,
+    /// 99% of the samples are not longer than this.
+    pub percentile_99: f64,
+    /// 99.9% of the samples are not longer than this.
+    pub percentile_99_9: f64,
+    /// The average of the slowest 1% of the samples, a.k.a. "1% low" when converted to FPS.
+    pub low_1: f64,
+    /// The average of the slowest 0.1% of the samples, a.k.a. "0.1% low" when converted to FPS.
+    pub low_0_1: f64,
+    /// The number of samples longer than `FrameTimeHistory::STUTTER_FACTOR` times the median.
+    pub stutters: usize,
+}
+
+impl FrameTimeHistory {
+    /// A sample is considered a stutter if it's this many times longer than the median.
+    pub const STUTTER_FACTOR: f64 = 2.0;
+
+    /// Create an empty history keeping up to `capacity` most recent samples.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0);
+        Self { samples: Vec::new(), capacity, next: 0 }
+    }
+
+    /// Add a sample, replacing the oldest one if the history is full.
+    pub fn push(&mut self, duration: f64) {
+        if self.samples.len() < self.capacity {
+            self.samples.push(duration);
+        } else {
+            self.samples[self.next] = duration;
+        }
+        self.next = (self.next + 1) % self.capacity;
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.next = 0;
+    }
+
+    /// Iterate over the samples from the oldest to the most recent one.
+    pub fn iter(&self) -> impl Iterator<Item = f64> + '_ {
+        let (recent, oldest) = self.samples.split_at(self.next % self.samples.len().max(1));
+        oldest.iter().chain(recent.iter()).copied()
+    }
+
+    /// Get the sample which `percentile` percents of the samples don't exceed, using the nearest rank.
+    /// Returns 0 if the history is empty.
+    pub fn percentile(&self, percentile: f64) -> f64 {
+        percentile_of_sorted(&self.sorted(), percentile)
+    }
+
+    /// Compute the distribution of the samples.
+    pub fn pacing(&self) -> FramePacing {
+        if self.samples.is_empty() {
+            return FramePacing::default();
+        }
+        let sorted: Vec<f64> = self.sorted();
+        let median: f64 = percentile_of_sorted(&sorted, 50.0);
+        FramePacing {
+            samples: sorted.len(),
+            average: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            median,
+            max: sorted[sorted.len() - 1],
+            percentile_99: percentile_of_sorted(&sorted, 99.0),
+            percentile_99_9: percentile_of_sorted(&sorted, 99.9),
+            low_1: slowest_average(&sorted, 0.01),
+            low_0_1: slowest_average(&sorted, 0.001),
+            stutters: sorted
+                .iter()
+                .filter(|&&sample| sample > median * Self::STUTTER_FACTOR)
+                .count(),
+        }
+    }
+
+    fn sorted(&self) -> Vec<f64> {
+        let mut sorted: Vec<f64> = self.samples.clone();
+        sorted.sort_by(f64::total_cmp);
+        sorted
+    }
+}
+
+impl FramePacing {
+    /// Convert a duration in milliseconds into frames per second, e.g. `pacing.low_1` into the "1% low" FPS.
+    pub fn fps(duration: f64) -> f64 {
+        if duration > 0.0 { 1000.0 / duration } else { 0.0 }
+    }
+}
+
+fn percentile_of_sorted(sorted: &[f64], percentile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank: usize = (percentile.clamp(0.0, 100.0) * sorted.len() as f64 / 100.0).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// The average of the slowest `fraction` of the sorted samples, at least one of them.
+fn slowest_average(sorted: &[f64], fraction: f64) -> f64 {
+    let count: usize = ((sorted.len() as f64 * fraction).ceil() as usize).clamp(1, sorted.len());
+    sorted[sorted.len() - count..].iter().sum::<f64>() / count as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_wraps_around() {
+        let mut history = FrameTimeHistory::new(3);
+        assert!(history.is_empty());
+        for sample in [1.0, 2.0, 3.0, 4.0, 5.0] {
+            history.push(sample);
+        }
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.iter().collect::<Vec<f64>>(), vec![3.0, 4.0, 5.0]);
+        history.clear();
+        assert!(history.is_empty());
+        assert_eq!(history.percentile(50.0), 0.0);
+        assert_eq!(history.pacing(), FramePacing::default());
+    }
+
+    #[test]
+    fn test_percentiles_and_lows() {
+        // 990 frames of 10ms, 9 of 30ms and a single 100ms hitch
+        let mut history = FrameTimeHistory::new(1000);
+        for i in 0..1000 {
+            history.push(match i {
+                500 => 100.0,
+                _ if i % 100 == 0 => 30.0,
+                _ => 10.0,
+            });
+        }
+        assert_eq!(history.percentile(50.0), 10.0);
+        assert_eq!(history.percentile(99.0), 10.0);
+        assert_eq!(history.percentile(99.1), 30.0);
+        assert_eq!(history.percentile(100.0), 100.0);
+
+        let pacing: FramePacing = history.pacing();
+        assert_eq!(pacing.samples, 1000);
+        assert!((pacing.average - 10.27).abs() < 1e-9);
+        assert_eq!(pacing.median, 10.0);
+        assert_eq!(pacing.max, 100.0);
+        assert_eq!(pacing.percentile_99_9, 30.0);
+        // The slowest 10 frames: the hitch and the 9 slow ones
+        assert!((pacing.low_1 - 37.0).abs() < 1e-9);
+        assert_eq!(pacing.low_0_1, 100.0);
+        assert_eq!(pacing.stutters, 10);
+        assert_eq!(FramePacing::fps(pacing.low_0_1), 10.0);
+    }
+}
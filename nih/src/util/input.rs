@@ -0,0 +1,267 @@
+use crate::math::*;
+
+/// `InputEvent` is a windowing-library-agnostic input event consumed by the camera controllers.
+/// Applications translate their native events into these, e.g. the SDL examples do it via `examples/support`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    /// Relative mouse motion in pixels, along with the buttons held during the motion.
+    MouseMotion { dx: f32, dy: f32, buttons: MouseButtons },
+
+    /// Mouse wheel scrolling in steps, positive when scrolling away from the user.
+    MouseWheel { delta: f32 },
+
+    /// A movement key was pressed.
+    KeyDown(MovementKey),
+
+    /// A movement key was released.
+    KeyUp(MovementKey),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MouseButtons {
+    pub left: bool,
+    pub middle: bool,
+    pub right: bool,
+}
+
+/// Directions of the continuous camera movement, relative to the camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovementKey {
+    Forward = 0,
+    Backward = 1,
+    Left = 2,
+    Right = 3,
+    Up = 4,
+    Down = 5,
+}
+
+/// `CameraController` turns input events into the camera's position and orientation.
+/// The camera looks along its local -Z axis, with +Y being up and +X being right.
+pub trait CameraController {
+    /// Reacts to a single input event.
+    fn handle_event(&mut self, event: &InputEvent);
+
+    /// Advances the continuous movement by `dt` seconds, should be called once per frame.
+    fn update(&mut self, dt: f32);
+
+    fn position(&self) -> Vec3;
+
+    fn orientation(&self) -> Quat;
+
+    /// The world-to-camera transform, to be used as `RasterizationCommand::view`.
+    fn view(&self) -> Mat44 {
+        view_matrix(self.orientation(), self.position()).as_mat44()
+    }
+}
+
+/// Builds the world-to-camera transform of a camera with the given orientation and position, i.e. the inverse of the
+/// camera-to-world rigid transform.
+pub fn view_matrix(orientation: Quat, position: Vec3) -> Mat34 {
+    let r: Mat33 = orientation.as_mat33();
+    let r_inv: Mat33 = r.transpose();
+    let t_inv: Vec3 = -(r_inv * position);
+    Mat34([
+        r_inv.0[0], r_inv.0[1], r_inv.0[2], t_inv.x, //
+        r_inv.0[3], r_inv.0[4], r_inv.0[5], t_inv.y, //
+        r_inv.0[6], r_inv.0[7], r_inv.0[8], t_inv.z, //
+    ])
+}
+
+/// `FirstPersonController` is a free-flying camera: dragging the mouse with the left button looks around, the movement
+/// keys move the camera relative to where it looks.
+#[derive(Debug, Clone, Copy)]
+pub struct FirstPersonController {
+    pub position: Vec3,
+    pub orientation: Quat,
+
+    /// Radians of rotation per pixel of mouse motion.
+    pub sensitivity: f32,
+
+    /// Movement speed in world units per second, zero disables the movement.
+    pub speed: f32,
+
+    held: [bool; 6],
+}
+
+impl FirstPersonController {
+    pub fn new(position: Vec3) -> Self {
+        Self { position, orientation: Quat::identity(), sensitivity: 0.002, speed: 5.0, held: [false; 6] }
+    }
+}
+
+impl CameraController for FirstPersonController {
+    fn handle_event(&mut self, event: &InputEvent) {
+        match *event {
+            InputEvent::MouseMotion { dx, dy, buttons } if buttons.left => {
+                // Yaw around the world's up axis, pitch around the camera's right axis
+                let yaw: Quat = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), -dx * self.sensitivity);
+                let pitch: Quat =
+                    Quat::from_axis_angle(self.orientation * Vec3::new(1.0, 0.0, 0.0), -dy * self.sensitivity);
+                self.orientation = (yaw * pitch * self.orientation).normalized();
+            }
+            InputEvent::KeyDown(key) => self.held[key as usize] = true,
+            InputEvent::KeyUp(key) => self.held[key as usize] = false,
+            _ => {}
+        }
+    }
+
+    fn update(&mut self, dt: f32) {
+        let axis = |positive: MovementKey, negative: MovementKey| -> f32 {
+            self.held[positive as usize] as i32 as f32 - self.held[negative as usize] as i32 as f32
+        };
+        let direction: Vec3 = Vec3::new(
+            axis(MovementKey::Right, MovementKey::Left),
+            axis(MovementKey::Up, MovementKey::Down),
+            axis(MovementKey::Backward, MovementKey::Forward),
+        );
+        if direction.length() > 0.0 {
+            self.position += (self.orientation * direction.normalized()) * (self.speed * dt);
+        }
+    }
+
+    fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    fn orientation(&self) -> Quat {
+        self.orientation
+    }
+}
+
+/// `OrbitController` keeps the camera looking at a target: dragging the mouse with the left button orbits around it,
+/// with the right or the middle button pans the target, the wheel zooms in and out.
+#[derive(Debug, Clone, Copy)]
+pub struct OrbitController {
+    pub target: Vec3,
+    pub distance: f32,
+
+    /// Rotation around the world's up axis, in radians.
+    pub yaw: f32,
+
+    /// Elevation above the target's horizon, in radians, kept within (-PI/2, PI/2).
+    pub pitch: f32,
+
+    /// Radians of rotation per pixel of mouse motion.
+    pub sensitivity: f32,
+
+    /// The relative change of the distance per wheel step.
+    pub zoom_speed: f32,
+
+    pub min_distance: f32,
+    pub max_distance: f32,
+}
+
+impl OrbitController {
+    const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+    pub fn new(target: Vec3, distance: f32) -> Self {
+        Self {
+            target,
+            distance,
+            yaw: 0.0,
+            pitch: 0.0,
+            sensitivity: 0.005,
+            zoom_speed: 0.1,
+            min_distance: 0.1,
+            max_distance: 1000.0,
+        }
+    }
+}
+
+impl CameraController for OrbitController {
+    fn handle_event(&mut self, event: &InputEvent) {
+        match *event {
+            InputEvent::MouseMotion { dx, dy, buttons } if buttons.left => {
+                self.yaw -= dx * self.sensitivity;
+                self.pitch = (self.pitch - dy * self.sensitivity).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+            }
+            InputEvent::MouseMotion { dx, dy, buttons } if buttons.right || buttons.middle => {
+                // Move the target along the camera's plane, proportionally to the distance to keep the pace on screen
+                let scale: f32 = self.distance * self.sensitivity * 0.2;
+                let right: Vec3 = self.orientation() * Vec3::new(1.0, 0.0, 0.0);
+                let up: Vec3 = self.orientation() * Vec3::new(0.0, 1.0, 0.0);
+                self.target += up * (dy * scale) - right * (dx * scale);
+            }
+            InputEvent::MouseWheel { delta } => {
+                let factor: f32 = (1.0 - self.zoom_speed).powf(delta);
+                self.distance = (self.distance * factor).clamp(self.min_distance, self.max_distance);
+            }
+            _ => {}
+        }
+    }
+
+    fn update(&mut self, _dt: f32) {}
+
+    fn position(&self) -> Vec3 {
+        self.target + self.orientation() * Vec3::new(0.0, 0.0, self.distance)
+    }
+
+    fn orientation(&self) -> Quat {
+        let yaw: Quat = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), self.yaw);
+        let pitch: Quat = Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), -self.pitch);
+        (yaw * pitch).normalized()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_vec3_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn view_matrix_inverts_camera_transform() {
+        let orientation: Quat = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 0.7);
+        let position: Vec3 = Vec3::new(1.0, 2.0, 3.0);
+        let view: Mat34 = view_matrix(orientation, position);
+        // The camera's position maps to the origin, a point in front of it maps onto -Z
+        assert_vec3_near(view * position, Vec3::new(0.0, 0.0, 0.0));
+        assert_vec3_near(view * (position + orientation * Vec3::new(0.0, 0.0, -2.0)), Vec3::new(0.0, 0.0, -2.0));
+    }
+
+    #[test]
+    fn first_person_looks_and_moves() {
+        let mut controller = FirstPersonController::new(Vec3::new(0.0, 0.0, 0.0));
+        // Dragging without buttons doesn't rotate
+        controller.handle_event(&InputEvent::MouseMotion { dx: 100.0, dy: 0.0, buttons: MouseButtons::default() });
+        assert_eq!(controller.orientation, Quat::identity());
+
+        // Dragging to the left turns left, i.e. -Z turns towards -X
+        let left = MouseButtons { left: true, ..Default::default() };
+        let quarter: f32 = std::f32::consts::FRAC_PI_2 / controller.sensitivity;
+        controller.handle_event(&InputEvent::MouseMotion { dx: -quarter, dy: 0.0, buttons: left });
+        assert_vec3_near(controller.orientation * Vec3::new(0.0, 0.0, -1.0), Vec3::new(-1.0, 0.0, 0.0));
+
+        // Moving forward follows the look direction
+        controller.handle_event(&InputEvent::KeyDown(MovementKey::Forward));
+        controller.update(0.5);
+        assert_vec3_near(controller.position, Vec3::new(-2.5, 0.0, 0.0));
+        controller.handle_event(&InputEvent::KeyUp(MovementKey::Forward));
+        controller.update(0.5);
+        assert_vec3_near(controller.position, Vec3::new(-2.5, 0.0, 0.0));
+    }
+
+    #[test]
+    fn orbit_keeps_target_in_front() {
+        let mut controller = OrbitController::new(Vec3::new(1.0, 0.0, 0.0), 10.0);
+        assert_vec3_near(controller.position(), Vec3::new(1.0, 0.0, 10.0));
+
+        let left = MouseButtons { left: true, ..Default::default() };
+        controller.handle_event(&InputEvent::MouseMotion { dx: 150.0, dy: -80.0, buttons: left });
+        let view: Mat34 = view_matrix(controller.orientation(), controller.position());
+        assert_vec3_near(view * controller.target, Vec3::new(0.0, 0.0, -10.0));
+        assert!(controller.position().y > 0.0);
+
+        // Pitch is clamped short of the pole
+        controller.handle_event(&InputEvent::MouseMotion { dx: 0.0, dy: -100000.0, buttons: left });
+        assert!(controller.pitch < std::f32::consts::FRAC_PI_2);
+
+        // Zooming in and out by the same number of steps is symmetric
+        controller.handle_event(&InputEvent::MouseWheel { delta: 3.0 });
+        assert!(controller.distance < 10.0);
+        controller.handle_event(&InputEvent::MouseWheel { delta: -3.0 });
+        assert!((controller.distance - 10.0).abs() < 1e-3);
+    }
+}
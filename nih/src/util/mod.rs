@@ -1 +1,3 @@
+pub mod frame_pacing;
+pub mod input;
 pub mod profiler;
@@ -1,23 +1,41 @@
+use super::frame_pacing::*;
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::time::Instant;
 
 /// `ProfileRecord` represents a single profiling entry with a label, timing statistics,
 /// and potential child records for nested profiling scopes.
-#[derive(Default)]
 pub struct ProfileRecord {
     label: String,
     average: f64,
     samples: u32,
     min: f64,
     max: f64,
+    history: FrameTimeHistory,
     children: Vec<Rc<RefCell<ProfileRecord>>>,
 }
 
+impl Default for ProfileRecord {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
 impl ProfileRecord {
+    /// The number of the most recent samples kept to report the worst cases, see `pacing()`.
+    pub const HISTORY_SIZE: usize = 1000;
+
     /// Create a new `ProfileRecord` with the specified label.
     pub fn new(label: &str) -> Self {
-        Self { label: label.to_string(), average: 0.0, samples: 0, min: f64::MAX, max: f64::MIN, children: vec![] }
+        Self {
+            label: label.to_string(),
+            average: 0.0,
+            samples: 0,
+            min: f64::MAX,
+            max: f64::MIN,
+            history: FrameTimeHistory::new(Self::HISTORY_SIZE),
+            children: vec![],
+        }
     }
 
     /// Get or create a child record with the given label. If a child with the label already exists,
@@ -39,6 +57,12 @@ impl ProfileRecord {
         self.samples += 1;
         self.min = self.min.min(duration);
         self.max = self.max.max(duration);
+        self.history.push(duration);
+    }
+
+    /// Get the distribution of the most recent samples: percentiles, lows and stutters.
+    pub fn pacing(&self) -> FramePacing {
+        self.history.pacing()
     }
 
     /// Get a reference to the child records.
@@ -83,7 +107,8 @@ impl Profiler {
         record.borrow_mut().commit(duration);
     }
 
-    /// Print the profiling report, showing average durations for all records in a tree format.
+    /// Print the profiling report, showing average durations for all records in a tree format,
+    /// along with the 1% lows and the number of stutters among the recent samples.
     pub fn print(&self) {
         fn print_records(records: &[Rc<RefCell<ProfileRecord>>], depth: usize) {
             for record in records {
@@ -93,7 +118,11 @@ impl Profiler {
                 } else {
                     r.label.clone()
                 };
-                println!("{:<40.40} {:>7.2}ms", header, r.average);
+                let pacing: FramePacing = r.pacing();
+                println!(
+                    "{:<40.40} {:>7.2}ms  1% low: {:>7.2}ms  stutters: {}",
+                    header, r.average, pacing.low_1, pacing.stutters
+                );
                 print_records(&r.children(), depth + 1);
             }
         }
@@ -201,6 +230,23 @@ mod tests {
         assert_eq!(rec.max, 20.0);
     }
 
+    #[test]
+    fn test_profile_record_pacing() {
+        let mut rec = ProfileRecord::new("pacing");
+        for _ in 0..ProfileRecord::HISTORY_SIZE {
+            rec.commit(40.0);
+        }
+        for _ in 0..99 {
+            rec.commit(10.0);
+        }
+        rec.commit(30.0);
+        let pacing: FramePacing = rec.pacing();
+        assert_eq!(pacing.samples, ProfileRecord::HISTORY_SIZE);
+        assert_eq!(pacing.median, 40.0);
+        assert_eq!(pacing.stutters, 0);
+        assert_eq!(rec.samples as usize, ProfileRecord::HISTORY_SIZE + 100);
+    }
+
     #[test]
     fn test_profiler_new_root() {
         let profiler = Profiler::new();
@@ -22,6 +22,7 @@ macro_rules! assert_rgba_eq {
 mod tests {
     use super::*;
     use image::{ImageBuffer, Rgba, RgbaImage};
+    use nih::testing::*;
     use rstest::rstest;
     use std::path::Path;
 
@@ -31,40 +32,15 @@ mod tests {
             .join(reference)
     }
 
-    fn save_albedo_next_to_reference<P: AsRef<Path>>(result: &Buffer<u32>, reference: P) {
-        let mut actual_path = reference_path(reference);
-        actual_path.set_extension("actual.png");
-
-        let raw_rgba: Vec<u8> = result
-            .elems
-            .iter()
-            .flat_map(|&pixel| {
-                let bytes = pixel.to_le_bytes();
-                [bytes[0], bytes[1], bytes[2], bytes[3]]
-            })
-            .collect();
-        let img1: ImageBuffer<Rgba<u8>, _> =
-            ImageBuffer::from_raw(result.width as u32, result.height as u32, raw_rgba).unwrap();
-        img1.save(actual_path).unwrap();
+    fn assert_albedo_against_reference<P: AsRef<Path>>(result: &Buffer<u32>, reference: P) {
+        assert_colors_against_reference(result, reference_path(reference), &Tolerance::default());
     }
 
-    fn save_normals_next_to_reference<P: AsRef<Path>>(result: &Buffer<u32>, reference: P) {
-        let mut actual_path = reference_path(reference);
-        actual_path.set_extension("actual.png");
-
-        let raw_rgba: Vec<u8> = result
-            .elems
-            .iter()
-            .flat_map(|&pixel| {
-                let bytes = pixel.to_le_bytes();
-                [bytes[0], bytes[1], bytes[2], 255]
-            })
-            .collect();
-        let img1: ImageBuffer<Rgba<u8>, _> =
-            ImageBuffer::from_raw(result.width as u32, result.height as u32, raw_rgba).unwrap();
-        img1.save(actual_path).unwrap();
+    fn assert_normals_against_reference<P: AsRef<Path>>(result: &Buffer<u32>, reference: P) {
+        nih::testing::assert_normals_against_reference(result, reference_path(reference), &Tolerance::default());
     }
 
+    // The depth references predate Buffer::save_depth(), they pack the depth's high and low bytes into red and green
     fn save_depth_next_to_reference<P: AsRef<Path>>(result: &Buffer<u16>, reference: P) {
         let mut actual_path = reference_path(reference);
         actual_path.set_extension("actual.png");
@@ -82,68 +58,6 @@ mod tests {
         img1.save(actual_path).unwrap();
     }
 
-    fn compare_albedo_against_reference<P: AsRef<Path>>(result: &Buffer<u32>, reference: P) -> bool {
-        const ERROR_TOLERANCE: u8 = 2; // acceptable difference per channel, 2 ~= 1%
-        let reference_path = reference_path(reference);
-
-        let raw_rgba: Vec<u8> = result
-            .elems
-            .iter()
-            .flat_map(|&pixel| {
-                let bytes = pixel.to_le_bytes();
-                [bytes[0], bytes[1], bytes[2], bytes[3]]
-            })
-            .collect();
-        let img1: ImageBuffer<Rgba<u8>, _> =
-            ImageBuffer::from_raw(result.width as u32, result.height as u32, raw_rgba).unwrap();
-
-        let img2: RgbaImage = image::open(reference_path).unwrap().into_rgba8();
-
-        if img1.dimensions() != img2.dimensions() {
-            return false;
-        }
-
-        img1.pixels().zip(img2.pixels()).all(|(p1, p2)| {
-            let diff_r = (p1[0] as i16 - p2[0] as i16).abs() as u8;
-            let diff_g = (p1[1] as i16 - p2[1] as i16).abs() as u8;
-            let diff_b = (p1[2] as i16 - p2[2] as i16).abs() as u8;
-            let diff_a = (p1[3] as i16 - p2[3] as i16).abs() as u8;
-            diff_r <= ERROR_TOLERANCE
-                && diff_g <= ERROR_TOLERANCE
-                && diff_b <= ERROR_TOLERANCE
-                && diff_a <= ERROR_TOLERANCE
-        })
-    }
-
-    fn compare_normals_against_reference<P: AsRef<Path>>(result: &Buffer<u32>, reference: P) -> bool {
-        const ERROR_TOLERANCE: u8 = 2; // acceptable difference per channel, 2 ~= 1%
-        let reference_path = reference_path(reference);
-
-        let raw_rgba: Vec<u8> = result
-            .elems
-            .iter()
-            .flat_map(|&pixel| {
-                let bytes = pixel.to_le_bytes();
-                [bytes[0], bytes[1], bytes[2], bytes[3]]
-            })
-            .collect();
-        let img1: ImageBuffer<Rgba<u8>, _> =
-            ImageBuffer::from_raw(result.width as u32, result.height as u32, raw_rgba).unwrap();
-
-        let img2: RgbaImage = image::open(reference_path).unwrap().into_rgba8();
-
-        if img1.dimensions() != img2.dimensions() {
-            return false;
-        }
-
-        img1.pixels().zip(img2.pixels()).all(|(p1, p2)| {
-            let diff_r = (p1[0] as i16 - p2[0] as i16).abs() as u8;
-            let diff_g = (p1[1] as i16 - p2[1] as i16).abs() as u8;
-            let diff_b = (p1[2] as i16 - p2[2] as i16).abs() as u8;
-            diff_r <= ERROR_TOLERANCE && diff_g <= ERROR_TOLERANCE && diff_b <= ERROR_TOLERANCE
-        })
-    }
-
     fn compare_depth_against_reference<P: AsRef<Path>>(result: &Buffer<u16>, reference: P) -> bool {
         let reference_path = reference_path(reference);
         let reference_image: RgbaImage = image::open(reference_path).unwrap().into_rgba8();
@@ -163,14 +77,6 @@ mod tests {
         true
     }
 
-    fn assert_albedo_against_reference<P: AsRef<Path>>(result: &Buffer<u32>, reference: P) {
-        let equal = compare_albedo_against_reference(result, &reference);
-        if !equal {
-            save_albedo_next_to_reference(result, &reference);
-        }
-        assert!(equal);
-    }
-
     fn assert_depth_against_reference<P: AsRef<Path>>(result: &Buffer<u16>, reference: P) {
         let equal = compare_depth_against_reference(result, &reference);
         if !equal {
@@ -179,61 +85,24 @@ mod tests {
         assert!(equal);
     }
 
-    fn assert_normals_against_reference<P: AsRef<Path>>(result: &Buffer<u32>, reference: P) {
-        let equal = compare_normals_against_reference(result, &reference);
-        if !equal {
-            save_normals_next_to_reference(result, &reference);
-        }
-        assert!(equal);
-    }
-
     fn render_to_64x64_albedo(command: &RasterizationCommand) -> Buffer<u32> {
-        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
-        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
-        let mut rasterizer = Rasterizer::new();
-        rasterizer.setup(Viewport::new(0, 0, 64, 64));
-        rasterizer.commit(&command);
-        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Framebuffer::default() });
-        color_buffer.as_flat_buffer()
+        render_to_buffer(64, 64, command)
     }
 
     fn render_to_64x64_albedo_wbg(command: &RasterizationCommand) -> Buffer<u32> {
-        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
-        color_buffer.fill(RGBA::new(255, 255, 255, 255).to_u32());
-        let mut rasterizer = Rasterizer::new();
-        rasterizer.setup(Viewport::new(0, 0, 64, 64));
-        rasterizer.commit(&command);
-        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Framebuffer::default() });
-        color_buffer.as_flat_buffer()
+        let options = CaptureOptions {
+            clear: ClearValues { color: RGBA::new(255, 255, 255, 255), ..Default::default() },
+            ..CaptureOptions::new(64, 64)
+        };
+        capture_frame(&options, |rasterizer| rasterizer.commit(command)).color.unwrap()
     }
 
     fn render_to_256x256_albedo(command: &RasterizationCommand) -> Buffer<u32> {
-        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(256, 256);
-        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
-        let mut rasterizer = Rasterizer::new();
-        rasterizer.setup(Viewport::new(0, 0, 256, 256));
-        rasterizer.commit(&command);
-        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Framebuffer::default() });
-        color_buffer.as_flat_buffer()
+        render_to_buffer(256, 256, command)
     }
 
-    // render_to_64x64_normals?
-
     fn render_to_64x64_depth(command: &RasterizationCommand) -> Buffer<u16> {
-        // let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
-        // color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
-        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(64, 64);
-        depth_buffer.fill(65535);
-        let mut framebuffer = Framebuffer::default();
-        // framebuffer.color_buffer = Some(&mut color_buffer);
-        framebuffer.depth_buffer = Some(&mut depth_buffer);
-
-        let mut rasterizer = Rasterizer::new();
-        rasterizer.setup(Viewport::new(0, 0, 64, 64));
-        rasterizer.commit(&command);
-        rasterizer.draw(&mut framebuffer);
-
-        depth_buffer.as_flat_buffer()
+        render_depth_to_buffer(64, 64, command)
     }
 
     #[rstest]
@@ -507,6 +376,45 @@ mod tests {
         assert_albedo_against_reference(&color_buffer.as_flat_buffer(), filename);
     }
 
+    #[rstest]
+    #[case(5.0, "rasterizer/depth_f32/intersection_near.png")]
+    #[case(20000.0, "rasterizer/depth_f32/intersection_far.png")]
+    fn depth_f32_intersection(#[case] distance: f32, #[case] filename: &str) {
+        // Two quads crossing each other in the middle of the frame, their depths differ by 1% of the distance
+        let (s, d): (f32, f32) = (distance, distance * 0.005);
+        let rising: [Vec3; 6] = [
+            Vec3::new(-s, -s, -s - d),
+            Vec3::new(s, -s, -s + d),
+            Vec3::new(s, s, -s + d),
+            Vec3::new(-s, -s, -s - d),
+            Vec3::new(s, s, -s + d),
+            Vec3::new(-s, s, -s - d),
+        ];
+        let falling: [Vec3; 6] = rising.map(|p| Vec3::new(p.x, p.y, -2.0 * s - p.z));
+        let projection = Mat44::perspective(0.01, 100000.0, std::f32::consts::FRAC_PI_2, 1.0);
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        let mut depth_buffer = TiledBuffer::<f32, 64, 64>::new(64, 64);
+        let mut framebuffer = Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer_f32: Some(&mut depth_buffer),
+            ..Default::default()
+        };
+        framebuffer.clear_all(&ClearValues::default());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        for (positions, color) in [(&rising, Vec4::new(1.0, 0.0, 0.0, 1.0)), (&falling, Vec4::new(0.0, 1.0, 0.0, 1.0))] {
+            rasterizer.commit(&RasterizationCommand {
+                world_positions: positions,
+                color,
+                projection,
+                culling: CullMode::None,
+                ..Default::default()
+            });
+        }
+        rasterizer.draw(&mut framebuffer);
+        assert_albedo_against_reference(&color_buffer.as_flat_buffer(), filename);
+    }
+
     #[rstest]
     #[case(256, 256, Vec2::new(0.0, 0.5), Vec2::new(-0.5, -0.5), Vec2::new(0.5, -0.5), "rasterizer/tiling/256x256_00.png")]
     #[case(256, 256, Vec2::new(-0.5, 0.75), Vec2::new(-0.75, -0.75), Vec2::new(-0.25, -0.75), "rasterizer/tiling/256x256_01.png")]
@@ -668,8 +576,13 @@ mod tests {
                 }
             }
         }
-        let source =
-            TextureSource { texels: &texels, width: width as u32, height: height as u32, format: TextureFormat::RGB };
+        let source = TextureSource {
+            texels: &texels,
+            width: width as u32,
+            height: height as u32,
+            format: TextureFormat::RGB,
+            ..Default::default()
+        };
         Texture::new(&source)
     }
 
@@ -778,6 +691,7 @@ mod tests {
             width: 64,
             height: 64,
             format: TextureFormat::Grayscale,
+            ..Default::default()
         });
         let command = RasterizationCommand {
             world_positions: &[
@@ -1272,6 +1186,7 @@ mod tests {
             width: 1,
             height: 1,
             format: TextureFormat::RGB,
+            ..Default::default()
         });
         let command = RasterizationCommand {
             world_positions: &[Vec3::new(0.0, 0.5, 0.0), Vec3::new(-0.5, -0.5, 0.0), Vec3::new(0.5, -0.5, 0.0)],
@@ -1441,6 +1356,7 @@ mod tests {
             width: 1,
             height: 1,
             format: TextureFormat::RGB,
+            ..Default::default()
         });
         let command = RasterizationCommand {
             world_positions: &[Vec3::new(0.0, 0.5, 0.0), Vec3::new(-0.5, -0.5, 0.0), Vec3::new(0.5, -0.5, 0.0)],
@@ -1610,6 +1526,7 @@ mod tests {
             width: 1,
             height: 1,
             format: TextureFormat::RGBA,
+            ..Default::default()
         });
         let command = RasterizationCommand {
             world_positions: &[Vec3::new(0.0, 0.5, 0.0), Vec3::new(-0.5, -0.5, 0.0), Vec3::new(0.5, -0.5, 0.0)],
@@ -1621,28 +1538,133 @@ mod tests {
         };
         assert_albedo_against_reference(&render_to_64x64_albedo_wbg(&command), filename);
     }
+
+    #[test]
+    fn ambient_occlusion_room_corner() {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(64, 64);
+        let mut normal_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        depth_buffer.fill(DEPTH_FAR);
+        normal_buffer.fill(NORMAL_NONE.to_u32());
+        let view: Mat44 = Mat44::rotate_yz(0.3) * Mat44::rotate_zx(-0.5) * Mat44::translate(Vec3::new(-0.5, -0.5, 0.0));
+        let projection: Mat44 = Mat44::perspective(0.1, 10.0, std::f32::consts::PI / 3.0, 1.0);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        // The floor, the back wall and the left wall of a room, with a box standing in the corner
+        let quads: [([Vec3; 4], Vec3); 6] = [
+            (
+                [
+                    Vec3::new(-2.0, -1.0, -1.0),
+                    Vec3::new(2.0, -1.0, -1.0),
+                    Vec3::new(2.0, -1.0, -4.0),
+                    Vec3::new(-2.0, -1.0, -4.0),
+                ],
+                Vec3::new(0.0, 1.0, 0.0),
+            ),
+            (
+                [
+                    Vec3::new(-2.0, -1.0, -4.0),
+                    Vec3::new(2.0, -1.0, -4.0),
+                    Vec3::new(2.0, 2.0, -4.0),
+                    Vec3::new(-2.0, 2.0, -4.0),
+                ],
+                Vec3::new(0.0, 0.0, 1.0),
+            ),
+            (
+                [
+                    Vec3::new(-2.0, -1.0, -1.0),
+                    Vec3::new(-2.0, -1.0, -4.0),
+                    Vec3::new(-2.0, 2.0, -4.0),
+                    Vec3::new(-2.0, 2.0, -1.0),
+                ],
+                Vec3::new(1.0, 0.0, 0.0),
+            ),
+            (
+                [
+                    Vec3::new(-2.0, -0.4, -3.4),
+                    Vec3::new(-1.4, -0.4, -3.4),
+                    Vec3::new(-1.4, -0.4, -4.0),
+                    Vec3::new(-2.0, -0.4, -4.0),
+                ],
+                Vec3::new(0.0, 1.0, 0.0),
+            ),
+            (
+                [
+                    Vec3::new(-2.0, -1.0, -3.4),
+                    Vec3::new(-1.4, -1.0, -3.4),
+                    Vec3::new(-1.4, -0.4, -3.4),
+                    Vec3::new(-2.0, -0.4, -3.4),
+                ],
+                Vec3::new(0.0, 0.0, 1.0),
+            ),
+            (
+                [
+                    Vec3::new(-1.4, -1.0, -3.4),
+                    Vec3::new(-1.4, -1.0, -4.0),
+                    Vec3::new(-1.4, -0.4, -4.0),
+                    Vec3::new(-1.4, -0.4, -3.4),
+                ],
+                Vec3::new(1.0, 0.0, 0.0),
+            ),
+        ];
+        for (positions, normal) in &quads {
+            rasterizer.commit(&RasterizationCommand {
+                world_positions: positions,
+                normals: &[*normal; 4],
+                indices: &[0, 1, 2, 0, 2, 3],
+                culling: CullMode::None,
+                view,
+                projection,
+                ..Default::default()
+            });
+        }
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: Some(&mut depth_buffer),
+            normal_buffer: Some(&mut normal_buffer),
+            ..Default::default()
+        });
+
+        let mut occlusion = TiledBuffer::<u8, 64, 64>::new(64, 64);
+        let parameters = AmbientOcclusion { view, projection, radius: 0.6, samples: 32, ..Default::default() };
+        ambient_occlusion(&depth_buffer, &normal_buffer, &parameters, &mut occlusion);
+        apply_ambient_occlusion(&mut color_buffer, ColorBufferFormat::Rgba8, &occlusion);
+        assert_albedo_against_reference(&color_buffer.as_flat_buffer(), "post/ssao/room_corner.png");
+    }
+
+    #[test]
+    fn fxaa_triangles() {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        // A shallow white triangle crossing the tile border and a steep orange one over it
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &[Vec3::new(-0.9, -0.8, 0.0), Vec3::new(0.95, -0.3, 0.0), Vec3::new(-0.7, 0.6, 0.0)],
+            color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            ..Default::default()
+        });
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &[Vec3::new(0.1, -0.9, 0.0), Vec3::new(0.5, 0.9, 0.0), Vec3::new(-0.2, 0.7, 0.0)],
+            color: Vec4::new(1.0, 0.5, 0.0, 1.0),
+            culling: CullMode::None,
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+
+        let mut chain = PostProcessChain::new();
+        chain.add(Fxaa::default());
+        chain.run(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        assert_albedo_against_reference(&color_buffer.as_flat_buffer(), "post/fxaa/triangles.png");
+    }
 }
 
 #[cfg(test)]
 mod tests_watertight {
     use super::*;
-    use image::{ImageBuffer, Rgba};
     use std::path::Path;
 
-    fn save_image<P: AsRef<Path>>(path: &P, image: &Buffer<u32>) {
-        let raw_rgba: Vec<u8> = image
-            .elems
-            .iter()
-            .flat_map(|&pixel| {
-                let bytes = pixel.to_le_bytes();
-                [bytes[0], bytes[1], bytes[2], bytes[3]]
-            })
-            .collect();
-        let img1: ImageBuffer<Rgba<u8>, _> =
-            ImageBuffer::from_raw(image.width as u32, image.height as u32, raw_rgba).unwrap();
-        img1.save(path).unwrap();
-    }
-
     #[test]
     fn fullscreen_quad() {
         // v0--v2v3|
@@ -1693,11 +1715,11 @@ mod tests_watertight {
                     c.r > 0 || c.g > 0 || c.b > 0
                 });
                 if !tight {
-                    save_image(
-                        &Path::new(env!("CARGO_MANIFEST_DIR"))
+                    flat.save_color(
+                        Path::new(env!("CARGO_MANIFEST_DIR"))
                             .join(format!("tests/fullscreen_quad_{0}x{0}.actual.png", dim)),
-                        &flat,
-                    );
+                    )
+                    .unwrap();
                 }
                 assert!(tight);
             }
@@ -1826,6 +1848,7 @@ mod tests_alpha_test {
                 width: 1,
                 height: 1,
                 format: TextureFormat::RGBA,
+                ..Default::default()
             });
             color_buffer.fill(0u32);
             depth_buffer.fill(u16::MAX);
@@ -1852,4 +1875,5849 @@ mod tests_alpha_test {
             assert_eq!(normal_discarded, tc.expected_discard);
         }
     }
+
+    #[test]
+    fn color_keyed_texels_are_discarded() {
+        // The left column is red, the right one is the magenta background
+        let texels: [u8; 12] = [255, 0, 0, 255, 0, 255, 255, 0, 0, 255, 0, 255];
+        let texture = Texture::new(&TextureSource {
+            texels: &texels,
+            width: 2,
+            height: 2,
+            format: TextureFormat::RGB,
+            color_key: Some(ColorKey::new(RGBA::new(255, 0, 255, 255))),
+            ..Default::default()
+        });
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(16, 16);
+        color_buffer.fill(RGBA::new(0, 0, 255, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 16, 16));
+        let pos = [
+            Vec3::new(-1.0, 1.0, 0.0),
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(-1.0, 1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+        ];
+        let tex_coords = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(0.0, 1.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(1.0, 0.0),
+        ];
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &pos,
+            tex_coords: &tex_coords,
+            texture: Some(texture),
+            sampling_filter: SamplerFilter::Nearest,
+            alpha_test: 128,
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        assert_eq!(RGBA::from_u32(color_buffer.at(2, 8)), RGBA::new(255, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(13, 8)), RGBA::new(0, 0, 255, 255));
+    }
+}
+
+#[cfg(test)]
+mod tests_alpha_source {
+    use super::*;
+    use rstest::rstest;
+
+    const TRIANGLE: [Vec3; 3] = [Vec3::new(0.0, 1.0, 0.0), Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0)];
+    const TEX_COORDS: [Vec2; 3] = [Vec2::new(0.5, 0.0), Vec2::new(0.0, 1.0), Vec2::new(1.0, 1.0)];
+
+    // A half-transparent texel blended over black
+    fn draw(command: &RasterizationCommand) -> RGBA {
+        let texture = Texture::new(&TextureSource {
+            texels: &[200u8, 100, 50, 128],
+            width: 1,
+            height: 1,
+            format: TextureFormat::RGBA,
+            ..Default::default()
+        });
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(1u16, 1u16);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 1u16, 1u16));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &TRIANGLE,
+            tex_coords: &TEX_COORDS,
+            texture: Some(texture),
+            alpha_blending: AlphaBlendingMode::Normal,
+            ..command.clone()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        RGBA::from_u32(color_buffer.at(0, 0))
+    }
+
+    #[rstest]
+    #[case(AlphaSource::Modulate, RGBA::new(25, 12, 6, 255))]
+    #[case(AlphaSource::Texture, RGBA::new(100, 50, 25, 255))]
+    #[case(AlphaSource::Vertex, RGBA::new(50, 25, 12, 255))]
+    #[case(AlphaSource::Constant(191), RGBA::new(150, 75, 37, 255))]
+    fn alpha_comes_from_source(#[case] alpha_source: AlphaSource, #[case] expected: RGBA) {
+        let command =
+            RasterizationCommand { color: Vec4::new(1.0, 1.0, 1.0, 0.25), alpha_source, ..Default::default() };
+        assert_rgba_eq!(draw(&command), expected, 2);
+
+        // The same with the alpha in the vertices' colors
+        let colors = [Vec4::new(1.0, 1.0, 1.0, 0.25); 3];
+        let command = RasterizationCommand { colors: &colors, alpha_source, ..Default::default() };
+        assert_rgba_eq!(draw(&command), expected, 2);
+    }
+
+    #[test]
+    fn alpha_test_still_uses_texels() {
+        let command = RasterizationCommand { alpha_source: AlphaSource::Vertex, alpha_test: 200, ..Default::default() };
+        assert_eq!(draw(&command), RGBA::new(0, 0, 0, 255));
+        let command = RasterizationCommand {
+            alpha_source: AlphaSource::Texture,
+            alpha_test: 100,
+            color: Vec4::new(1.0, 1.0, 1.0, 0.0),
+            ..Default::default()
+        };
+        assert_rgba_eq!(draw(&command), RGBA::new(100, 50, 25, 255), 2);
+    }
+}
+
+#[cfg(test)]
+mod tests_fast_math {
+    use super::*;
+
+    fn render(rasterizer: &mut Rasterizer, command: &RasterizationCommand) -> Buffer<u32> {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit(command);
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        color_buffer.as_flat_buffer()
+    }
+
+    fn max_channel_difference(a: &Buffer<u32>, b: &Buffer<u32>) -> u8 {
+        a.elems
+            .iter()
+            .zip(b.elems.iter())
+            .map(|(&x, &y)| {
+                let x = RGBA::from_u32(x);
+                let y = RGBA::from_u32(y);
+                x.r.abs_diff(y.r)
+                    .max(x.g.abs_diff(y.g))
+                    .max(x.b.abs_diff(y.b))
+                    .max(x.a.abs_diff(y.a))
+            })
+            .max()
+            .unwrap()
+    }
+
+    const COLORS: [Vec4; 3] =
+        [Vec4::new(1.0, 0.0, 0.0, 1.0), Vec4::new(0.0, 1.0, 0.0, 1.0), Vec4::new(0.0, 0.0, 1.0, 1.0)];
+
+    #[test]
+    fn flat_triangle_matches_precise() {
+        let positions = [Vec3::new(-0.75, -0.75, -1.5), Vec3::new(0.75, -0.75, -1.5), Vec3::new(0.0, 0.75, -1.5)];
+        let command = RasterizationCommand {
+            world_positions: &positions,
+            colors: &COLORS,
+            projection: Mat44::perspective(0.1, 10.0, std::f32::consts::PI / 3.0, 1.),
+            ..Default::default()
+        };
+        let mut rasterizer = Rasterizer::new();
+        let precise = render(&mut rasterizer, &command);
+        let fast = render(&mut rasterizer, &RasterizationCommand { fast_math: true, ..command.clone() });
+        assert!(max_channel_difference(&precise, &fast) <= 2);
+    }
+
+    #[test]
+    fn deep_triangle_keeps_perspective_correction() {
+        let positions = [Vec3::new(-3.75, -3.75, -7.5), Vec3::new(0.75, -0.75, -1.5), Vec3::new(0.0, 0.75, -1.5)];
+        let command = RasterizationCommand {
+            world_positions: &positions,
+            colors: &COLORS,
+            projection: Mat44::perspective(0.1, 10.0, std::f32::consts::PI / 3.0, 1.),
+            ..Default::default()
+        };
+        let mut rasterizer = Rasterizer::new();
+        let precise = render(&mut rasterizer, &command);
+        let fast = render(&mut rasterizer, &RasterizationCommand { fast_math: true, ..command.clone() });
+        assert_eq!(precise.elems, fast.elems);
+
+        // With a permissive threshold the same triangle is interpolated affinely, which is visibly different.
+        rasterizer.set_fast_math_w_threshold(100.0);
+        let affine = render(&mut rasterizer, &RasterizationCommand { fast_math: true, ..command.clone() });
+        assert!(max_channel_difference(&precise, &affine) > 16);
+    }
+
+    #[test]
+    fn global_switch() {
+        let positions = [Vec3::new(-3.75, -3.75, -7.5), Vec3::new(0.75, -0.75, -1.5), Vec3::new(0.0, 0.75, -1.5)];
+        let command = RasterizationCommand {
+            world_positions: &positions,
+            colors: &COLORS,
+            projection: Mat44::perspective(0.1, 10.0, std::f32::consts::PI / 3.0, 1.),
+            ..Default::default()
+        };
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.set_fast_math_w_threshold(100.0);
+        let precise = render(&mut rasterizer, &command);
+        rasterizer.set_fast_math(true);
+        let affine = render(&mut rasterizer, &command);
+        assert!(max_channel_difference(&precise, &affine) > 16);
+    }
+}
+
+#[cfg(test)]
+mod tests_texture_region {
+    use super::*;
+
+    // 64x64 atlas with 2x2 cells: red, green, blue, white.
+    fn atlas_texture() -> std::sync::Arc<Texture> {
+        let colors: [[u8; 3]; 4] = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+        let mut texels: Vec<u8> = Vec::new();
+        for y in 0..64 {
+            for x in 0..64 {
+                texels.extend_from_slice(&colors[(y / 32) * 2 + x / 32]);
+            }
+        }
+        Texture::new(&TextureSource {
+            texels: &texels,
+            width: 64,
+            height: 64,
+            format: TextureFormat::RGB,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn repeated_cell_does_not_bleed() {
+        let texture = atlas_texture();
+        let expected: [RGBA; 4] = [
+            RGBA::new(255, 0, 0, 255),
+            RGBA::new(0, 255, 0, 255),
+            RGBA::new(0, 0, 255, 255),
+            RGBA::new(255, 255, 255, 255),
+        ];
+        let positions = [
+            Vec3::new(-1.0, 1.0, 0.0),
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+        ];
+        // The cell is repeated 5 times along each axis, which requires minification as well.
+        let tex_coords = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(0.0, 5.0),
+            Vec2::new(5.0, 0.0),
+            Vec2::new(5.0, 0.0),
+            Vec2::new(0.0, 5.0),
+            Vec2::new(5.0, 5.0),
+        ];
+        for filter in [SamplerFilter::Nearest, SamplerFilter::Bilinear, SamplerFilter::Trilinear] {
+            for cell in 0..4u32 {
+                let command = RasterizationCommand {
+                    world_positions: &positions,
+                    tex_coords: &tex_coords,
+                    texture: Some(texture.clone()),
+                    texture_region: Some(TextureRegion::from_grid(2, 2, cell % 2, cell / 2)),
+                    sampling_filter: filter,
+                    ..Default::default()
+                };
+                let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+                let mut rasterizer = Rasterizer::new();
+                rasterizer.setup(Viewport::new(0, 0, 64, 64));
+                rasterizer.commit(&command);
+                rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+                for y in 0..64 {
+                    for x in 0..64 {
+                        assert_rgba_eq!(RGBA::from_u32(color_buffer.at(x, y)), expected[cell as usize], 2);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_perspective_span {
+    use super::*;
+
+    // Renders a wall receding into the distance horizontally, i.e. w changes along the rows.
+    // Span 0 stands for the affine interpolation without any perspective correction.
+    fn render(span: u32) -> Buffer<u32> {
+        let positions = [
+            Vec3::new(-0.5, -1.0, -1.0),
+            Vec3::new(-0.5, 1.0, -1.0),
+            Vec3::new(-0.5, -1.0, -8.0),
+            Vec3::new(-0.5, -1.0, -8.0),
+            Vec3::new(-0.5, 1.0, -1.0),
+            Vec3::new(-0.5, 1.0, -8.0),
+        ];
+        let colors = [
+            Vec4::new(1.0, 0.0, 0.0, 1.0),
+            Vec4::new(0.0, 1.0, 0.0, 1.0),
+            Vec4::new(0.0, 0.0, 1.0, 1.0),
+            Vec4::new(0.0, 0.0, 1.0, 1.0),
+            Vec4::new(0.0, 1.0, 0.0, 1.0),
+            Vec4::new(1.0, 1.0, 1.0, 1.0),
+        ];
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(128, 128);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        if span == 0 {
+            rasterizer.set_fast_math(true);
+            rasterizer.set_fast_math_w_threshold(f32::MAX);
+        } else {
+            rasterizer.set_perspective_span(span);
+        }
+        rasterizer.setup(Viewport::new(0, 0, 128, 128));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &positions,
+            colors: &colors,
+            projection: Mat44::perspective(0.1, 10.0, std::f32::consts::PI / 3.0, 1.),
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        color_buffer.as_flat_buffer()
+    }
+
+    fn mean_channel_difference(a: &Buffer<u32>, b: &Buffer<u32>) -> f32 {
+        let sum: u32 = a
+            .elems
+            .iter()
+            .zip(b.elems.iter())
+            .map(|(&x, &y)| {
+                let x = RGBA::from_u32(x);
+                let y = RGBA::from_u32(y);
+                x.r.abs_diff(y.r) as u32 + x.g.abs_diff(y.g) as u32 + x.b.abs_diff(y.b) as u32
+            })
+            .sum();
+        sum as f32 / (a.elems.len() * 3) as f32
+    }
+
+    #[test]
+    fn error_grows_with_span_length() {
+        let reference = render(1);
+        let affine_difference = mean_channel_difference(&reference, &render(0));
+        let mut previous_difference: f32 = 0.0;
+        for span in [2, 4, 8, 16, 128] {
+            let difference = mean_channel_difference(&reference, &render(span));
+            assert!(difference >= previous_difference);
+            assert!(difference < affine_difference);
+            previous_difference = difference;
+        }
+    }
+
+    #[test]
+    fn short_spans_are_close_to_per_pixel_correction() {
+        let reference = render(1);
+        assert!(mean_channel_difference(&reference, &render(2)) < 0.1);
+        assert!(mean_channel_difference(&reference, &render(4)) < 0.25);
+    }
+}
+
+#[cfg(test)]
+mod tests_address_modes {
+    use super::*;
+
+    // 64x64 texture with 2x2 cells: red, green, blue, white.
+    fn quadrants_texture() -> std::sync::Arc<Texture> {
+        let colors: [[u8; 3]; 4] = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+        let mut texels: Vec<u8> = Vec::new();
+        for y in 0..64 {
+            for x in 0..64 {
+                texels.extend_from_slice(&colors[(y / 32) * 2 + x / 32]);
+            }
+        }
+        Texture::new(&TextureSource {
+            texels: &texels,
+            width: 64,
+            height: 64,
+            format: TextureFormat::RGB,
+            ..Default::default()
+        })
+    }
+
+    // Renders a fullscreen quad with texture coordinates spanning [-1, 2] along both axes.
+    fn render(
+        filter: SamplerFilter,
+        address_mode_u: SamplerAddressMode,
+        address_mode_v: SamplerAddressMode,
+    ) -> TiledBuffer<u32, 64, 64> {
+        let positions = [
+            Vec3::new(-1.0, 1.0, 0.0),
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+        ];
+        let tex_coords = [
+            Vec2::new(-1.0, -1.0),
+            Vec2::new(-1.0, 2.0),
+            Vec2::new(2.0, -1.0),
+            Vec2::new(2.0, -1.0),
+            Vec2::new(-1.0, 2.0),
+            Vec2::new(2.0, 2.0),
+        ];
+        let command = RasterizationCommand {
+            world_positions: &positions,
+            tex_coords: &tex_coords,
+            texture: Some(quadrants_texture()),
+            sampling_filter: filter,
+            address_mode_u,
+            address_mode_v,
+            ..Default::default()
+        };
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit(&command);
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        color_buffer
+    }
+
+    #[test]
+    fn modes_are_applied_per_axis() {
+        let red = RGBA::new(255, 0, 0, 255);
+        let green = RGBA::new(0, 255, 0, 255);
+        let blue = RGBA::new(0, 0, 255, 255);
+        // Pixel 15 maps onto -0.27 and pixel 50 maps onto 1.37, pixel 27 maps onto 0.29.
+        let cases = [
+            (SamplerAddressMode::Repeat, [green, red], [blue, red]),
+            (SamplerAddressMode::ClampToEdge, [red, green], [red, blue]),
+            (SamplerAddressMode::MirroredRepeat, [red, green], [red, blue]),
+        ];
+        for filter in [SamplerFilter::Nearest, SamplerFilter::Bilinear] {
+            for (mode, along_u, along_v) in cases {
+                let buffer = render(filter, mode, SamplerAddressMode::Repeat);
+                assert_rgba_eq!(RGBA::from_u32(buffer.at(15, 27)), along_u[0], 2);
+                assert_rgba_eq!(RGBA::from_u32(buffer.at(50, 27)), along_u[1], 2);
+
+                let buffer = render(filter, SamplerAddressMode::Repeat, mode);
+                assert_rgba_eq!(RGBA::from_u32(buffer.at(27, 15)), along_v[0], 2);
+                assert_rgba_eq!(RGBA::from_u32(buffer.at(27, 50)), along_v[1], 2);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_anisotropic_filtering {
+    use super::*;
+
+    // Renders a fullscreen quad with 1-texel horizontal stripes, squeezed 8 times horizontally.
+    fn render(filter: SamplerFilter) -> TiledBuffer<u32, 64, 64> {
+        let mut texels: Vec<u8> = Vec::new();
+        for y in 0..64 {
+            texels.extend_from_slice(&[if y % 2 == 0 { 255u8 } else { 0u8 }; 64]);
+        }
+        let texture = Texture::new(&TextureSource {
+            texels: &texels,
+            width: 64,
+            height: 64,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        });
+        let positions = [
+            Vec3::new(-1.0, 1.0, 0.0),
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+        ];
+        let tex_coords = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(0.0, 1.0),
+            Vec2::new(8.0, 0.0),
+            Vec2::new(8.0, 0.0),
+            Vec2::new(0.0, 1.0),
+            Vec2::new(8.0, 1.0),
+        ];
+        let command = RasterizationCommand {
+            world_positions: &positions,
+            tex_coords: &tex_coords,
+            texture: Some(texture),
+            sampling_filter: filter,
+            ..Default::default()
+        };
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit(&command);
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        color_buffer
+    }
+
+    fn stripes_contrast(buffer: &TiledBuffer<u32, 64, 64>) -> i32 {
+        let mut min_contrast: i32 = 255;
+        for y in 1..63 {
+            for x in 1..63 {
+                let a: i32 = RGBA::from_u32(buffer.at(x, y)).r as i32;
+                let b: i32 = RGBA::from_u32(buffer.at(x, y + 1)).r as i32;
+                min_contrast = min_contrast.min((a - b).abs());
+            }
+        }
+        min_contrast
+    }
+
+    #[test]
+    fn stripes_stay_sharp_when_squeezed_across() {
+        assert!(stripes_contrast(&render(SamplerFilter::Anisotropic)) > 240);
+        assert!(stripes_contrast(&render(SamplerFilter::Trilinear)) < 128);
+    }
+}
+
+#[cfg(test)]
+mod tests_fast_reciprocal {
+    use super::*;
+
+    // Renders a textured triangle with per-vertex colors and strongly varying w.
+    fn render(fast_reciprocal: bool) -> TiledBuffer<u32, 64, 64> {
+        let mut texels: Vec<u8> = Vec::new();
+        for y in 0..16 {
+            for x in 0..16 {
+                texels.push(if (x + y) % 2 == 0 { 255 } else { 64 });
+            }
+        }
+        let texture = Texture::new(&TextureSource {
+            texels: &texels,
+            width: 16,
+            height: 16,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        });
+        let positions = [Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, -1.0, -1.0), Vec3::new(0.0, 1.0, -6.0)];
+        let tex_coords = [Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), Vec2::new(0.5, 1.0)];
+        let colors = [Vec4::new(1.0, 0.0, 0.0, 1.0), Vec4::new(0.0, 1.0, 0.0, 1.0), Vec4::new(0.0, 0.0, 1.0, 1.0)];
+        let command = RasterizationCommand {
+            world_positions: &positions,
+            tex_coords: &tex_coords,
+            colors: &colors,
+            texture: Some(texture),
+            projection: Mat44::perspective(0.5, 10.0, std::f32::consts::PI / 2.0, 1.0),
+            ..Default::default()
+        };
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.set_fast_reciprocal(fast_reciprocal);
+        rasterizer.commit(&command);
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        color_buffer
+    }
+
+    #[test]
+    fn matches_precise_division() {
+        let precise = render(false);
+        let fast = render(true);
+        let mut covered: usize = 0;
+        for y in 0..64 {
+            for x in 0..64 {
+                assert_rgba_eq!(RGBA::from_u32(fast.at(x, y)), RGBA::from_u32(precise.at(x, y)), 1);
+                covered += (precise.at(x, y) != 0) as usize;
+            }
+        }
+        assert!(covered > 500);
+    }
+}
+
+#[cfg(test)]
+mod tests_opaque_fills_fast_path {
+    use super::*;
+
+    // Renders a fan of triangles with per-vertex colors, a fan with a uniform color and a perspective-projected triangle
+    // with per-vertex colors, which doesn't qualify for the affine interpolation.
+    fn render(fast_path: bool) -> TiledBuffer<u32, 64, 64> {
+        let mut positions: Vec<Vec3> = Vec::new();
+        let mut colors: Vec<Vec4> = Vec::new();
+        let mut uniform_positions: Vec<Vec3> = Vec::new();
+        for i in 0..12 {
+            let a0: f32 = i as f32 * std::f32::consts::PI / 6.0;
+            let a1: f32 = (i + 1) as f32 * std::f32::consts::PI / 6.0;
+            let center = Vec3::new(-0.3, -0.2, 0.0);
+            let p0 = Vec3::new(center.x + a0.cos() * 0.67, center.y + a0.sin() * 0.71, 0.0);
+            let p1 = Vec3::new(center.x + a1.cos() * 0.67, center.y + a1.sin() * 0.71, 0.0);
+            positions.extend_from_slice(&[center, p0, p1]);
+            colors.extend_from_slice(&[
+                Vec4::new(1.0, 1.0, 1.0, 1.0),
+                Vec4::new(a0.cos() * 0.5 + 0.5, 0.2, 0.9, 1.0),
+                Vec4::new(0.1, a1.sin() * 0.5 + 0.5, 0.3, 1.0),
+            ]);
+            let center = Vec3::new(0.55, 0.6, 0.0);
+            uniform_positions.extend_from_slice(&[
+                center,
+                Vec3::new(center.x + a0.cos() * 0.33, center.y + a0.sin() * 0.29, 0.0),
+                Vec3::new(center.x + a1.cos() * 0.33, center.y + a1.sin() * 0.29, 0.0),
+            ]);
+        }
+        let perspective_positions = [Vec3::new(0.0, -1.0, -1.0), Vec3::new(1.0, -1.0, -1.0), Vec3::new(1.0, 0.0, -5.0)];
+        let perspective_colors =
+            [Vec4::new(1.0, 0.0, 0.0, 1.0), Vec4::new(0.0, 1.0, 0.0, 1.0), Vec4::new(0.0, 0.0, 1.0, 1.0)];
+
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.set_opaque_fills_fast_path(fast_path);
+        rasterizer.commit(&RasterizationCommand { world_positions: &positions, colors: &colors, ..Default::default() });
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &uniform_positions,
+            color: Vec4::new(0.2, 0.4, 0.8, 1.0),
+            ..Default::default()
+        });
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &perspective_positions,
+            colors: &perspective_colors,
+            projection: Mat44::perspective(0.5, 10.0, std::f32::consts::PI / 2.0, 1.0),
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        color_buffer
+    }
+
+    #[test]
+    fn matches_generic_path() {
+        let generic = render(false);
+        let fast = render(true);
+        let mut covered: usize = 0;
+        for y in 0..64 {
+            for x in 0..64 {
+                // The coverage must be exactly the same, while the colors may differ in rounding
+                assert_eq!(fast.at(x, y) == 0, generic.at(x, y) == 0, "at ({x}, {y})");
+                assert_rgba_eq!(RGBA::from_u32(fast.at(x, y)), RGBA::from_u32(generic.at(x, y)), 1);
+                covered += (generic.at(x, y) != 0) as usize;
+            }
+        }
+        assert!(covered > 1000);
+    }
+}
+
+#[cfg(test)]
+mod tests_fullscreen {
+    use super::*;
+
+    #[test]
+    fn fills_only_the_viewport() {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(128, 128);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 100, 72));
+        rasterizer.commit_fullscreen(&FullscreenCommand { color: Vec4::new(1.0, 0.0, 0.0, 1.0), ..Default::default() });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        for y in 0..128 {
+            for x in 0..128 {
+                let inside: bool = x < 100 && y < 72;
+                let expected: u32 = if inside { RGBA::new(255, 0, 0, 255).to_u32() } else { 0 };
+                assert_eq!(color_buffer.at(x, y), expected, "at ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn is_ordered_with_triangles() {
+        let triangle = [Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 0.0)];
+        let red = RGBA::new(255, 0, 0, 255).to_u32();
+        let blue = RGBA::new(0, 0, 255, 255).to_u32();
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &triangle,
+            color: Vec4::new(0.0, 1.0, 0.0, 1.0),
+            ..Default::default()
+        });
+        rasterizer.commit_fullscreen(&FullscreenCommand { color: Vec4::new(1.0, 0.0, 0.0, 1.0), ..Default::default() });
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &triangle,
+            color: Vec4::new(0.0, 0.0, 1.0, 1.0),
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        assert_eq!(color_buffer.at(4, 4), red); // only the fill
+        assert_eq!(color_buffer.at(60, 60), blue); // the fill under the second triangle
+    }
+
+    #[test]
+    fn fades_with_alpha_blending() {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        color_buffer.fill(RGBA::new(200, 100, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit_fullscreen(&FullscreenCommand {
+            color: Vec4::new(0.0, 0.0, 0.0, 0.5),
+            alpha_blending: AlphaBlendingMode::Normal,
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        assert_rgba_eq!(RGBA::from_u32(color_buffer.at(31, 17)), RGBA::new(100, 50, 0, 255), 1);
+    }
+
+    #[test]
+    fn stretches_the_texture_over_the_viewport() {
+        let texels: [u8; 16] = [
+            255, 0, 0, 255, //
+            0, 255, 0, 255, //
+            0, 0, 255, 255, //
+            255, 255, 255, 255, //
+        ];
+        let texture = Texture::new(&TextureSource {
+            texels: &texels,
+            width: 2,
+            height: 2,
+            format: TextureFormat::RGBA,
+            ..Default::default()
+        });
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(128, 128);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 128, 128));
+        rasterizer.commit_fullscreen(&FullscreenCommand { texture: Some(texture), ..Default::default() });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        assert_eq!(color_buffer.at(0, 0), RGBA::new(255, 0, 0, 255).to_u32());
+        assert_eq!(color_buffer.at(63, 63), RGBA::new(255, 0, 0, 255).to_u32());
+        assert_eq!(color_buffer.at(64, 0), RGBA::new(0, 255, 0, 255).to_u32());
+        assert_eq!(color_buffer.at(0, 64), RGBA::new(0, 0, 255, 255).to_u32());
+        assert_eq!(color_buffer.at(127, 127), RGBA::new(255, 255, 255, 255).to_u32());
+    }
+}
+
+#[cfg(test)]
+mod tests_fills {
+    use super::*;
+
+    fn render(fills: &[FillCommand]) -> TiledBuffer<u32, 64, 64> {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(128, 128);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 128, 128));
+        for fill in fills {
+            rasterizer.commit_fill(fill);
+        }
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        color_buffer
+    }
+
+    #[test]
+    fn rounded_rect_across_tiles() {
+        let buffer = render(&[FillCommand {
+            min: Vec2::new(20.0, 30.0),
+            max: Vec2::new(100.0, 90.0),
+            corner_radius: 10.0,
+            paint: FillPaint::Solid(Vec4::new(1.0, 0.0, 0.0, 1.0)),
+            ..Default::default()
+        }]);
+        let red = RGBA::new(255, 0, 0, 255).to_u32();
+        let black = RGBA::new(0, 0, 0, 255).to_u32();
+        assert_eq!(buffer.at(20, 60), red);
+        assert_eq!(buffer.at(99, 60), red);
+        assert_eq!(buffer.at(64, 64), red);
+        assert_eq!(buffer.at(60, 30), red);
+        assert_eq!(buffer.at(60, 89), red);
+        assert_eq!(buffer.at(19, 60), black);
+        assert_eq!(buffer.at(100, 60), black);
+        assert_eq!(buffer.at(60, 29), black);
+        assert_eq!(buffer.at(60, 90), black);
+        // The corners are cut off and their outline is anti-aliased
+        assert_eq!(buffer.at(20, 30), black);
+        assert_eq!(buffer.at(99, 89), black);
+        let outline = RGBA::from_u32(buffer.at(23, 32));
+        assert!(outline.r > 0 && outline.r < 255, "{:?}", outline);
+    }
+
+    #[test]
+    fn interior_fast_path_matches_shading() {
+        // A degenerate gradient of a single color goes through the per-pixel shading everywhere
+        let color = Vec4::new(0.2, 0.6, 0.9, 1.0);
+        let solid = FillCommand {
+            min: Vec2::new(3.3, 7.6),
+            max: Vec2::new(121.2, 77.7),
+            corner_radius: 6.5,
+            border_width: 1.5,
+            border_color: Vec4::new(1.0, 1.0, 0.0, 1.0),
+            paint: FillPaint::Solid(color),
+            ..Default::default()
+        };
+        let gradient = FillCommand {
+            paint: FillPaint::LinearGradient {
+                from: Vec2::new(0.0, 0.0),
+                to: Vec2::new(1.0, 0.0),
+                color_from: color,
+                color_to: color,
+            },
+            ..solid
+        };
+        let fast = render(&[solid]);
+        let shaded = render(&[gradient]);
+        for y in 0..128 {
+            for x in 0..128 {
+                assert_rgba_eq!(RGBA::from_u32(fast.at(x, y)), RGBA::from_u32(shaded.at(x, y)), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn linear_gradient() {
+        let buffer = render(&[FillCommand {
+            min: Vec2::new(0.0, 0.0),
+            max: Vec2::new(128.0, 128.0),
+            paint: FillPaint::LinearGradient {
+                from: Vec2::new(0.0, 0.0),
+                to: Vec2::new(128.0, 0.0),
+                color_from: Vec4::new(0.0, 0.0, 0.0, 1.0),
+                color_to: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            },
+            ..Default::default()
+        }]);
+        assert_rgba_eq!(RGBA::from_u32(buffer.at(0, 5)), RGBA::new(1, 1, 1, 255), 1);
+        assert_rgba_eq!(RGBA::from_u32(buffer.at(64, 70)), RGBA::new(129, 129, 129, 255), 1);
+        assert_rgba_eq!(RGBA::from_u32(buffer.at(127, 127)), RGBA::new(254, 254, 254, 255), 1);
+    }
+
+    #[test]
+    fn radial_gradient_with_alpha_blending() {
+        let buffer = render(&[FillCommand {
+            min: Vec2::new(0.0, 0.0),
+            max: Vec2::new(128.0, 128.0),
+            paint: FillPaint::RadialGradient {
+                center: Vec2::new(64.0, 64.0),
+                radius: 32.0,
+                color_inner: Vec4::new(1.0, 1.0, 1.0, 1.0),
+                color_outer: Vec4::new(1.0, 1.0, 1.0, 0.0),
+            },
+            alpha_blending: AlphaBlendingMode::Normal,
+            ..Default::default()
+        }]);
+        assert_rgba_eq!(RGBA::from_u32(buffer.at(64, 64)), RGBA::new(249, 249, 249, 255), 2);
+        assert_rgba_eq!(RGBA::from_u32(buffer.at(80, 64)), RGBA::new(123, 123, 123, 255), 2);
+        assert_eq!(buffer.at(5, 5), RGBA::new(0, 0, 0, 255).to_u32());
+    }
+
+    #[test]
+    fn border() {
+        let buffer = render(&[FillCommand {
+            min: Vec2::new(10.0, 10.0),
+            max: Vec2::new(50.0, 50.0),
+            border_width: 3.0,
+            border_color: Vec4::new(0.0, 1.0, 0.0, 1.0),
+            paint: FillPaint::Solid(Vec4::new(0.0, 0.0, 1.0, 1.0)),
+            ..Default::default()
+        }]);
+        assert_eq!(buffer.at(10, 30), RGBA::new(0, 255, 0, 255).to_u32());
+        assert_eq!(buffer.at(12, 30), RGBA::new(0, 255, 0, 255).to_u32());
+        assert_eq!(buffer.at(13, 30), RGBA::new(0, 0, 255, 255).to_u32());
+        assert_eq!(buffer.at(30, 47), RGBA::new(0, 255, 0, 255).to_u32());
+    }
+}
+
+#[cfg(test)]
+mod tests_hdr {
+    use super::*;
+
+    fn hdr_buffer() -> TiledBuffer<u32, 64, 64> {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        color_buffer.fill(encode_rgb9e5(0.0, 0.0, 0.0));
+        color_buffer
+    }
+
+    fn draw_hdr(rasterizer: &mut Rasterizer, color_buffer: &mut TiledBuffer<u32, 64, 64>) {
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(color_buffer),
+            color_format: ColorBufferFormat::Rgb9e5,
+            ..Default::default()
+        });
+    }
+
+    fn assert_hdr_eq(actual: u32, expected: Vec3) {
+        let actual: Vec3 = decode_rgb9e5(actual);
+        let tolerance: f32 = expected.x.max(expected.y).max(expected.z) / 100.0 + 0.001;
+        assert!(
+            (actual.x - expected.x).abs() <= tolerance
+                && (actual.y - expected.y).abs() <= tolerance
+                && (actual.z - expected.z).abs() <= tolerance,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    const QUAD: [Vec3; 6] = [
+        Vec3::new(-1.0, -1.0, 0.0),
+        Vec3::new(1.0, -1.0, 0.0),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec3::new(-1.0, -1.0, 0.0),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec3::new(-1.0, 1.0, 0.0),
+    ];
+
+    #[test]
+    fn colors_are_not_clamped() {
+        let mut color_buffer = hdr_buffer();
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &QUAD,
+            color: Vec4::new(8.0, 2.0, 0.5, 1.0),
+            ..Default::default()
+        });
+        draw_hdr(&mut rasterizer, &mut color_buffer);
+        assert_hdr_eq(color_buffer.at(10, 10), Vec3::new(8.0, 2.0, 0.5));
+        assert_hdr_eq(color_buffer.at(50, 50), Vec3::new(8.0, 2.0, 0.5));
+    }
+
+    #[test]
+    fn per_vertex_colors_are_not_clamped() {
+        let colors: [Vec4; 6] = [Vec4::new(16.0, 16.0, 16.0, 1.0); 6];
+        let mut color_buffer = hdr_buffer();
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit(&RasterizationCommand { world_positions: &QUAD, colors: &colors, ..Default::default() });
+        draw_hdr(&mut rasterizer, &mut color_buffer);
+        assert_hdr_eq(color_buffer.at(32, 32), Vec3::new(16.0, 16.0, 16.0));
+    }
+
+    #[test]
+    fn additive_blending_accumulates_radiance() {
+        let mut color_buffer = hdr_buffer();
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        for _ in 0..3 {
+            rasterizer.commit(&RasterizationCommand {
+                world_positions: &QUAD,
+                color: Vec4::new(1.5, 0.5, 0.0, 1.0),
+                alpha_blending: AlphaBlendingMode::Additive,
+                ..Default::default()
+            });
+        }
+        draw_hdr(&mut rasterizer, &mut color_buffer);
+        assert_hdr_eq(color_buffer.at(20, 40), Vec3::new(4.5, 1.5, 0.0));
+    }
+
+    #[test]
+    fn fullscreen_and_fills() {
+        let mut color_buffer = hdr_buffer();
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit_fullscreen(&FullscreenCommand { color: Vec4::new(3.0, 0.0, 0.0, 1.0), ..Default::default() });
+        rasterizer.commit_fill(&FillCommand {
+            min: Vec2::new(16.0, 16.0),
+            max: Vec2::new(48.0, 48.0),
+            paint: FillPaint::Solid(Vec4::new(0.0, 0.0, 10.0, 0.5)),
+            alpha_blending: AlphaBlendingMode::Normal,
+            ..Default::default()
+        });
+        draw_hdr(&mut rasterizer, &mut color_buffer);
+        assert_hdr_eq(color_buffer.at(4, 4), Vec3::new(3.0, 0.0, 0.0));
+        assert_hdr_eq(color_buffer.at(32, 32), Vec3::new(1.5, 0.0, 5.0));
+    }
+
+    #[test]
+    fn tone_map_resolves_into_display_buffer() {
+        let mut color_buffer = hdr_buffer();
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &QUAD[0..3],
+            color: Vec4::new(100.0, 100.0, 100.0, 1.0),
+            ..Default::default()
+        });
+        draw_hdr(&mut rasterizer, &mut color_buffer);
+        let mut display = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        tone_map(
+            &color_buffer,
+            &mut display,
+            &ToneMapping { operator: ToneMappingOperator::Aces, ..Default::default() },
+        );
+        assert_eq!(RGBA::from_u32(display.at(60, 60)), RGBA::new(255, 255, 255, 255));
+        assert_eq!(RGBA::from_u32(display.at(3, 0)), RGBA::new(0, 0, 0, 255));
+    }
+}
+
+#[cfg(test)]
+mod tests_polygons {
+    use super::*;
+
+    fn render(polygons: &[PolygonFillCommand]) -> TiledBuffer<u32, 64, 64> {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(128, 128);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 128, 128));
+        for polygon in polygons {
+            rasterizer.commit_polygon(polygon);
+        }
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        color_buffer
+    }
+
+    fn hexagon(center: Vec2, radius: f32) -> Vec<Vec2> {
+        (0..6)
+            .map(|i| {
+                let angle: f32 = i as f32 * std::f32::consts::PI / 3.0;
+                center + Vec2::new(angle.cos(), angle.sin()) * radius
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hexagon_across_tiles() {
+        let points = hexagon(Vec2::new(64.0, 64.0), 40.0);
+        let buffer = render(&[PolygonFillCommand {
+            points: &points,
+            color: Vec4::new(1.0, 0.0, 0.0, 1.0),
+            ..Default::default()
+        }]);
+        let red = RGBA::new(255, 0, 0, 255).to_u32();
+        let black = RGBA::new(0, 0, 0, 255).to_u32();
+        assert_eq!(buffer.at(64, 64), red);
+        assert_eq!(buffer.at(30, 64), red);
+        assert_eq!(buffer.at(97, 64), red);
+        assert_eq!(buffer.at(64, 31), red);
+        assert_eq!(buffer.at(64, 96), red);
+        assert_eq!(buffer.at(22, 64), black);
+        assert_eq!(buffer.at(105, 64), black);
+        assert_eq!(buffer.at(64, 27), black);
+        assert_eq!(buffer.at(64, 100), black);
+        // The corners of the bounding box are outside
+        assert_eq!(buffer.at(26, 31), black);
+        assert_eq!(buffer.at(101, 96), black);
+    }
+
+    #[test]
+    fn antialiased_edges() {
+        // A right triangle with the hypotenuse going through the pixels' centers diagonally
+        let points = [Vec2::new(10.0, 10.0), Vec2::new(50.0, 10.0), Vec2::new(10.0, 50.0)];
+        let color = Vec4::new(1.0, 1.0, 1.0, 1.0);
+        let smooth = render(&[PolygonFillCommand { points: &points, color, ..Default::default() }]);
+        let aliased =
+            render(&[PolygonFillCommand { points: &points, color, antialiased: false, ..Default::default() }]);
+        let mut partial_pixels = 0;
+        for y in 0..64 {
+            for x in 0..64 {
+                let smooth = RGBA::from_u32(smooth.at(x, y));
+                let aliased = RGBA::from_u32(aliased.at(x, y));
+                assert!(aliased.r == 0 || aliased.r == 255, "{:?}", aliased);
+                if smooth.r > 0 && smooth.r < 255 {
+                    partial_pixels += 1;
+                }
+            }
+        }
+        assert!(partial_pixels >= 40, "{}", partial_pixels);
+        // The axis-aligned edges are exactly at the pixels' borders
+        assert_eq!(RGBA::from_u32(smooth.at(9, 30)).r, 0);
+        assert_eq!(RGBA::from_u32(smooth.at(10, 30)).r, 255);
+        assert_eq!(RGBA::from_u32(smooth.at(30, 9)).r, 0);
+        assert_eq!(RGBA::from_u32(smooth.at(30, 10)).r, 255);
+    }
+
+    #[test]
+    fn winding_does_not_matter() {
+        let points = hexagon(Vec2::new(60.5, 70.3), 33.3);
+        let reversed: Vec<Vec2> = points.iter().rev().copied().collect();
+        let a = render(&[PolygonFillCommand { points: &points, ..Default::default() }]);
+        let b = render(&[PolygonFillCommand { points: &reversed, ..Default::default() }]);
+        for y in 0..128 {
+            for x in 0..128 {
+                assert_rgba_eq!(RGBA::from_u32(a.at(x, y)), RGBA::from_u32(b.at(x, y)), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn alpha_blending() {
+        let points = [Vec2::new(0.0, 0.0), Vec2::new(128.0, 0.0), Vec2::new(128.0, 128.0), Vec2::new(0.0, 128.0)];
+        let buffer = render(&[
+            PolygonFillCommand { points: &points, color: Vec4::new(0.0, 0.0, 1.0, 1.0), ..Default::default() },
+            PolygonFillCommand {
+                points: &points[..3],
+                color: Vec4::new(1.0, 0.0, 0.0, 0.5),
+                alpha_blending: AlphaBlendingMode::Normal,
+                ..Default::default()
+            },
+        ]);
+        assert_rgba_eq!(RGBA::from_u32(buffer.at(100, 20)), RGBA::new(128, 0, 127, 255), 1);
+        assert_eq!(RGBA::from_u32(buffer.at(20, 100)), RGBA::new(0, 0, 255, 255));
+    }
+
+    #[test]
+    fn degenerate_polygons_are_ignored() {
+        let line = [Vec2::new(10.0, 10.0), Vec2::new(50.0, 50.0), Vec2::new(90.0, 90.0)];
+        let buffer = render(&[
+            PolygonFillCommand { points: &line, ..Default::default() },
+            PolygonFillCommand { points: &line[..2], ..Default::default() },
+        ]);
+        for y in 0..128 {
+            for x in 0..128 {
+                assert_eq!(buffer.at(x, y), RGBA::new(0, 0, 0, 255).to_u32());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_strokes {
+    use super::*;
+
+    fn render(path: &Path, stroke: StrokeCommand) -> TiledBuffer<u32, 64, 64> {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(128, 128);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 128, 128));
+        rasterizer.commit_stroke(&StrokeCommand { path, ..stroke });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        color_buffer
+    }
+
+    #[test]
+    fn polyline_across_tiles() {
+        let path = Path::from_polyline(&[Vec2::new(10.0, 64.0), Vec2::new(118.0, 64.0), Vec2::new(118.0, 10.0)], false);
+        let buffer =
+            render(&path, StrokeCommand { width: 4.0, color: Vec4::new(0.0, 1.0, 0.0, 1.0), ..Default::default() });
+        let green = RGBA::new(0, 255, 0, 255).to_u32();
+        let black = RGBA::new(0, 0, 0, 255).to_u32();
+        assert_eq!(buffer.at(10, 62), green);
+        assert_eq!(buffer.at(63, 65), green);
+        assert_eq!(buffer.at(64, 62), green);
+        assert_eq!(buffer.at(117, 10), green);
+        assert_eq!(buffer.at(119, 40), green);
+        assert_eq!(buffer.at(9, 64), black);
+        assert_eq!(buffer.at(64, 60), black);
+        assert_eq!(buffer.at(64, 67), black);
+        assert_eq!(buffer.at(118, 9), black);
+        assert_eq!(buffer.at(64, 30), black);
+    }
+
+    #[test]
+    fn translucent_joins_are_covered_once() {
+        // A zig-zag with sharp joins, every pixel is either untouched or blended with the same color
+        let path = Path::from_polyline(
+            &[Vec2::new(10.0, 10.0), Vec2::new(60.0, 100.0), Vec2::new(70.0, 20.0), Vec2::new(120.0, 110.0)],
+            false,
+        );
+        for join in [LineJoin::Round, LineJoin::Miter { limit: 10.0 }, LineJoin::Bevel] {
+            let buffer = render(
+                &path,
+                StrokeCommand {
+                    width: 8.0,
+                    join,
+                    color: Vec4::new(1.0, 1.0, 1.0, 0.5),
+                    antialiased: false,
+                    alpha_blending: AlphaBlendingMode::Normal,
+                    ..Default::default()
+                },
+            );
+            let mut covered = 0;
+            for y in 0..128 {
+                for x in 0..128 {
+                    let pixel = RGBA::from_u32(buffer.at(x, y));
+                    if pixel.r != 0 {
+                        assert_rgba_eq!(pixel, RGBA::new(128, 128, 128, 255), 1);
+                        covered += 1;
+                    }
+                }
+            }
+            assert!(covered > 1000, "{}", covered);
+        }
+    }
+
+    #[test]
+    fn antialiased_bezier() {
+        let mut path = Path::new();
+        path.move_to(Vec2::new(10.0, 100.0));
+        path.cubic_to(Vec2::new(30.0, 0.0), Vec2::new(90.0, 0.0), Vec2::new(110.0, 100.0));
+        let buffer = render(&path, StrokeCommand { width: 3.0, ..Default::default() });
+        let mut full = 0;
+        let mut partial = 0;
+        for y in 0..128 {
+            for x in 0..128 {
+                match RGBA::from_u32(buffer.at(x, y)).r {
+                    0 => {}
+                    255 => full += 1,
+                    _ => partial += 1,
+                }
+            }
+        }
+        assert!(full > 200, "{}", full);
+        assert!(partial > 100, "{}", partial);
+        // The curve's apex is at (60, 25)
+        assert_eq!(RGBA::from_u32(buffer.at(60, 25)).r, 255);
+        assert_eq!(RGBA::from_u32(buffer.at(60, 50)).r, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests_scissor {
+    use super::*;
+
+    const QUAD: [Vec3; 6] = [
+        Vec3::new(-1.0, -1.0, 0.0),
+        Vec3::new(1.0, -1.0, 0.0),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec3::new(-1.0, -1.0, 0.0),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec3::new(-1.0, 1.0, 0.0),
+    ];
+
+    fn render(commands: &[(Vec4, Option<Viewport>)], depth: bool) -> TiledBuffer<u32, 64, 64> {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(128, 128);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(128, 128);
+        depth_buffer.fill(u16::MAX);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 128, 128));
+        for (color, scissor) in commands {
+            rasterizer.commit(&RasterizationCommand {
+                world_positions: &QUAD,
+                color: *color,
+                scissor: *scissor,
+                ..Default::default()
+            });
+        }
+        let mut framebuffer = Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() };
+        if depth {
+            framebuffer.depth_buffer = Some(&mut depth_buffer);
+        }
+        rasterizer.draw(&mut framebuffer);
+        color_buffer
+    }
+
+    #[test]
+    fn scissor_across_tiles() {
+        let red = RGBA::new(255, 0, 0, 255).to_u32();
+        let black = RGBA::new(0, 0, 0, 255).to_u32();
+        for depth in [false, true] {
+            let buffer = render(&[(Vec4::new(1.0, 0.0, 0.0, 1.0), Some(Viewport::new(30, 40, 100, 90)))], depth);
+            for y in 0..128 {
+                for x in 0..128 {
+                    let inside = (30..100).contains(&x) && (40..90).contains(&y);
+                    assert_eq!(buffer.at(x, y), if inside { red } else { black }, "{} {} {}", x, y, depth);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn split_screen() {
+        let buffer = render(
+            &[
+                (Vec4::new(1.0, 0.0, 0.0, 1.0), Some(Viewport::new(0, 0, 50, 128))),
+                (Vec4::new(0.0, 0.0, 1.0, 1.0), Some(Viewport::new(50, 0, 128, 128))),
+            ],
+            false,
+        );
+        assert_eq!(buffer.at(0, 0), RGBA::new(255, 0, 0, 255).to_u32());
+        assert_eq!(buffer.at(49, 127), RGBA::new(255, 0, 0, 255).to_u32());
+        assert_eq!(buffer.at(50, 0), RGBA::new(0, 0, 255, 255).to_u32());
+        assert_eq!(buffer.at(127, 127), RGBA::new(0, 0, 255, 255).to_u32());
+    }
+
+    #[test]
+    fn scissor_outside_viewport() {
+        let buffer = render(&[(Vec4::new(1.0, 0.0, 0.0, 1.0), Some(Viewport::new(200, 200, 300, 300)))], false);
+        for y in 0..128 {
+            for x in 0..128 {
+                assert_eq!(buffer.at(x, y), RGBA::new(0, 0, 0, 255).to_u32());
+            }
+        }
+        // A scissor partially outside the viewport is clamped to it
+        let buffer = render(&[(Vec4::new(1.0, 0.0, 0.0, 1.0), Some(Viewport::new(100, 100, 300, 300)))], false);
+        assert_eq!(buffer.at(127, 127), RGBA::new(255, 0, 0, 255).to_u32());
+        assert_eq!(buffer.at(99, 127), RGBA::new(0, 0, 0, 255).to_u32());
+    }
+}
+
+#[cfg(test)]
+mod tests_sdf {
+    use super::*;
+
+    // A 64x64 distance field of a circle with the radius of 20 texels, the field's range is 8 texels
+    fn circle_sdf() -> std::sync::Arc<Texture> {
+        let mut texels: Vec<u8> = Vec::new();
+        for y in 0..64 {
+            for x in 0..64 {
+                let distance: f32 = Vec2::new(x as f32 + 0.5 - 32.0, y as f32 + 0.5 - 32.0).length();
+                let value: f32 = (0.5 + (20.0 - distance) / 8.0).clamp(0.0, 1.0);
+                texels.push((value * 255.0 + 0.5) as u8);
+            }
+        }
+        Texture::new(&TextureSource {
+            texels: &texels,
+            width: 64,
+            height: 64,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        })
+    }
+
+    fn render(size: u16, sdf: SdfSampling, alpha_test: u8) -> TiledBuffer<u32, 64, 64> {
+        let positions = [
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(-1.0, 1.0, 0.0),
+        ];
+        let tex_coords = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(0.0, 1.0),
+        ];
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(size, size);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, size, size));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &positions,
+            tex_coords: &tex_coords,
+            texture: Some(circle_sdf()),
+            sampling_filter: SamplerFilter::Bilinear,
+            alpha_blending: AlphaBlendingMode::Normal,
+            alpha_test,
+            color: Vec4::new(1.0, 1.0, 0.0, 1.0),
+            sdf: Some(sdf),
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        color_buffer
+    }
+
+    // The number of partially covered pixels along the row through the circle's center
+    fn partial_pixels_in_middle_row(buffer: &TiledBuffer<u32, 64, 64>, size: u16) -> usize {
+        (0..size)
+            .filter(|x| {
+                let r = RGBA::from_u32(buffer.at(*x, size / 2)).r;
+                r > 0 && r < 255
+            })
+            .count()
+    }
+
+    #[test]
+    fn circle_at_different_scales() {
+        for size in [64u16, 128, 256] {
+            let buffer = render(size, SdfSampling::default(), 0);
+            let center = size / 2;
+            let radius = 20 * size / 64;
+            assert_eq!(RGBA::from_u32(buffer.at(center, center)), RGBA::new(255, 255, 0, 255));
+            assert_eq!(RGBA::from_u32(buffer.at(center - radius + 2, center)), RGBA::new(255, 255, 0, 255));
+            assert_eq!(RGBA::from_u32(buffer.at(center + radius + 2, center)), RGBA::new(0, 0, 0, 255));
+            assert_eq!(RGBA::from_u32(buffer.at(0, 0)), RGBA::new(0, 0, 0, 255));
+            // The edges stay about a pixel wide regardless of the magnification
+            let partial = partial_pixels_in_middle_row(&buffer, size);
+            assert!((1..=4).contains(&partial), "{} at {}", partial, size);
+        }
+    }
+
+    #[test]
+    fn explicit_smoothing() {
+        let buffer = render(256, SdfSampling { smoothing: 0.25, ..Default::default() }, 0);
+        assert!(partial_pixels_in_middle_row(&buffer, 256) > 10);
+    }
+
+    #[test]
+    fn alpha_test_gives_hard_edges() {
+        let buffer = render(256, SdfSampling::default(), 128);
+        for y in 0..256 {
+            for x in 0..256 {
+                let pixel = RGBA::from_u32(buffer.at(x, y));
+                assert!(pixel.r == 0 || pixel.r > 128, "{:?}", pixel);
+            }
+        }
+    }
+
+    #[test]
+    fn threshold_changes_the_weight() {
+        let regular = render(128, SdfSampling::default(), 0);
+        let bold = render(128, SdfSampling { threshold: 0.3, ..Default::default() }, 0);
+        let covered =
+            |buffer: &TiledBuffer<u32, 64, 64>| (0..128).filter(|x| RGBA::from_u32(buffer.at(*x, 64)).r > 128).count();
+        assert!(covered(&bold) > covered(&regular) + 4);
+    }
+}
+
+#[cfg(test)]
+mod tests_views {
+    use super::*;
+
+    // A colored pyramid in front of the camera, looking differently from the different views
+    const POSITIONS: [Vec3; 9] = [
+        Vec3::new(-0.5, -0.5, -3.0),
+        Vec3::new(0.5, -0.5, -3.0),
+        Vec3::new(0.0, 0.5, -3.5),
+        Vec3::new(0.5, -0.5, -3.0),
+        Vec3::new(0.5, -0.5, -4.0),
+        Vec3::new(0.0, 0.5, -3.5),
+        Vec3::new(-0.5, -0.5, -4.0),
+        Vec3::new(-0.5, -0.5, -3.0),
+        Vec3::new(0.0, 0.5, -3.5),
+    ];
+    const COLORS: [Vec4; 9] = [
+        Vec4::new(1.0, 0.0, 0.0, 1.0),
+        Vec4::new(0.0, 1.0, 0.0, 1.0),
+        Vec4::new(0.0, 0.0, 1.0, 1.0),
+        Vec4::new(1.0, 1.0, 0.0, 1.0),
+        Vec4::new(0.0, 1.0, 1.0, 1.0),
+        Vec4::new(1.0, 0.0, 1.0, 1.0),
+        Vec4::new(1.0, 1.0, 1.0, 1.0),
+        Vec4::new(0.5, 0.5, 0.5, 1.0),
+        Vec4::new(0.2, 0.4, 0.8, 1.0),
+    ];
+
+    fn views() -> [ViewTarget; 2] {
+        // A horizontal split which doesn't align with the tiles
+        let top = Viewport::new(0, 0, 150, 70);
+        let bottom = Viewport::new(0, 70, 150, 130);
+        [
+            ViewTarget::new(top, Mat44::identity(), Mat44::perspective(0.5, 10.0, 1.0, 150.0 / 70.0)),
+            ViewTarget::new(
+                bottom,
+                Mat44::rotate_zx(0.4) * Mat44::translate(Vec3::new(0.3, 0.0, 0.0)),
+                Mat44::perspective(0.5, 10.0, 1.0, 150.0 / 60.0),
+            ),
+        ]
+    }
+
+    fn commit_scene(rasterizer: &mut Rasterizer, view: Mat44, projection: Mat44) {
+        rasterizer.commit_fill(&FillCommand {
+            min: Vec2::new(5.0, 5.0),
+            max: Vec2::new(40.0, 20.0),
+            paint: FillPaint::Solid(Vec4::new(0.0, 0.5, 0.0, 1.0)),
+            ..Default::default()
+        });
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &POSITIONS,
+            colors: &COLORS,
+            model: Mat34::translate(Vec3::new(0.1, 0.0, 0.0)),
+            view,
+            projection,
+            ..Default::default()
+        });
+    }
+
+    fn new_buffers() -> (TiledBuffer<u32, 64, 64>, TiledBuffer<u16, 64, 64>) {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(150, 130);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(150, 130);
+        depth_buffer.fill(u16::MAX);
+        (color_buffer, depth_buffer)
+    }
+
+    fn assert_buffers_eq(a: &TiledBuffer<u32, 64, 64>, b: &TiledBuffer<u32, 64, 64>) {
+        for y in 0..a.height() {
+            for x in 0..a.width() {
+                assert_eq!(RGBA::from_u32(a.at(x, y)), RGBA::from_u32(b.at(x, y)), "{} {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn split_screen_matches_separate_commits() {
+        // Each view rendered separately
+        let (mut expected, mut expected_depth) = new_buffers();
+        for view in views() {
+            let mut rasterizer = Rasterizer::new();
+            rasterizer.setup(view.viewport);
+            commit_scene(&mut rasterizer, view.view, view.projection);
+            rasterizer.draw(&mut Framebuffer {
+                color_buffer: Some(&mut expected),
+                depth_buffer: Some(&mut expected_depth),
+                ..Default::default()
+            });
+        }
+
+        // Both views from a single commit
+        let (mut actual, mut actual_depth) = new_buffers();
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 150, 130));
+        rasterizer.set_retain_geometry(true);
+        commit_scene(&mut rasterizer, Mat44::identity(), Mat44::identity());
+        let mut views = views();
+        rasterizer.draw_views(
+            &mut Framebuffer {
+                color_buffer: Some(&mut actual),
+                depth_buffer: Some(&mut actual_depth),
+                ..Default::default()
+            },
+            &mut views,
+        );
+        assert_buffers_eq(&actual, &expected);
+
+        // Both views see the pyramid
+        assert!(views[0].statistics.scheduled_triangles > 0);
+        assert!(views[1].statistics.scheduled_triangles > 0);
+        assert_eq!(views[0].statistics.committed_triangles, 3);
+        // The fill is placed relative to each view's viewport
+        assert_eq!(RGBA::from_u32(actual.at(10, 10)), RGBA::new(0, 128, 0, 255));
+        assert_eq!(RGBA::from_u32(actual.at(10, 80)), RGBA::new(0, 128, 0, 255));
+    }
+
+    #[test]
+    fn draw_is_kept_intact() {
+        let (mut expected, _) = new_buffers();
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 150, 130));
+        commit_scene(&mut rasterizer, Mat44::identity(), Mat44::perspective(0.5, 10.0, 1.0, 150.0 / 130.0));
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut expected), ..Default::default() });
+
+        let (mut actual, _) = new_buffers();
+        let (mut views_buffer, _) = new_buffers();
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 150, 130));
+        rasterizer.set_retain_geometry(true);
+        commit_scene(&mut rasterizer, Mat44::identity(), Mat44::perspective(0.5, 10.0, 1.0, 150.0 / 130.0));
+        rasterizer
+            .draw_views(&mut Framebuffer { color_buffer: Some(&mut views_buffer), ..Default::default() }, &mut views());
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut actual), ..Default::default() });
+        assert_buffers_eq(&actual, &expected);
+    }
+
+    #[test]
+    fn scissor_is_clamped_to_each_view() {
+        let (mut color_buffer, _) = new_buffers();
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 150, 70));
+        rasterizer.set_retain_geometry(true);
+        // The scissor is outside the setup viewport, but inside the second view
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &[
+                Vec3::new(-1.0, -1.0, 0.0),
+                Vec3::new(1.0, -1.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(-1.0, -1.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(-1.0, 1.0, 0.0),
+            ],
+            scissor: Some(Viewport::new(0, 100, 150, 130)),
+            ..Default::default()
+        });
+        let mut views = [
+            ViewTarget::new(Viewport::new(0, 0, 150, 70), Mat44::identity(), Mat44::identity()),
+            ViewTarget::new(Viewport::new(0, 70, 150, 130), Mat44::identity(), Mat44::identity()),
+        ];
+        rasterizer
+            .draw_views(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() }, &mut views);
+        assert_eq!(views[0].statistics.scheduled_triangles, 0);
+        assert_eq!(RGBA::from_u32(color_buffer.at(75, 99)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(75, 100)), RGBA::new(255, 255, 255, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(149, 129)), RGBA::new(255, 255, 255, 255));
+    }
+}
+
+#[cfg(test)]
+mod tests_text {
+    use super::*;
+    use std::sync::Arc;
+
+    // A 16x16 grid font of 8x8 cells for "ABCD": 'A' is solid, 'B' is transparent, 'C' is faint with alpha 100 and 'D' is
+    // solid
+    fn font() -> Arc<BitmapFont> {
+        let mut texels: Vec<u8> = vec![255u8; 16 * 16 * 4];
+        for y in 0..16 {
+            for x in 0..16 {
+                let alpha: u8 = match (x / 8, y / 8) {
+                    (1, 0) => 0,
+                    (0, 1) => 100,
+                    _ => 255,
+                };
+                texels[(y * 16 + x) * 4 + 3] = alpha;
+            }
+        }
+        let texture = Texture::new(&TextureSource {
+            texels: &texels,
+            width: 16,
+            height: 16,
+            format: TextureFormat::RGBA,
+            ..Default::default()
+        });
+        Arc::new(BitmapFont::from_grid(texture, 8, 8, 'A', 4))
+    }
+
+    fn draw(text: &str, style: &TextStyle) -> TiledBuffer<u32, 64, 64> {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(100, 70);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 100, 70));
+        draw_text(&mut rasterizer, Vec2::new(10.0, 20.0), text, style);
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        color_buffer
+    }
+
+    #[test]
+    fn glyphs_are_placed_along_lines() {
+        let style = TextStyle { color: Vec4::new(1.0, 0.0, 0.0, 1.0), scale: 2.0, ..TextStyle::new(font()) };
+        let color_buffer = draw("AB\nBA", &style);
+        let red = RGBA::new(255, 0, 0, 255);
+        let black = RGBA::new(0, 0, 0, 255);
+        assert_eq!(RGBA::from_u32(color_buffer.at(9, 20)), black);
+        assert_eq!(RGBA::from_u32(color_buffer.at(10, 20)), red);
+        assert_eq!(RGBA::from_u32(color_buffer.at(25, 35)), red);
+        assert_eq!(RGBA::from_u32(color_buffer.at(26, 20)), black);
+        assert_eq!(RGBA::from_u32(color_buffer.at(20, 36)), black);
+        assert_eq!(RGBA::from_u32(color_buffer.at(26, 36)), red);
+        assert_eq!(RGBA::from_u32(color_buffer.at(41, 51)), red);
+        assert_eq!(RGBA::from_u32(color_buffer.at(42, 51)), black);
+        assert_eq!(style.measure("AB\nBA"), Vec2::new(32.0, 32.0));
+    }
+
+    #[test]
+    fn alpha_test_discards_faint_texels() {
+        let color_buffer = draw("CD", &TextStyle::new(font()));
+        assert_eq!(RGBA::from_u32(color_buffer.at(12, 22)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(20, 22)), RGBA::new(255, 255, 255, 255));
+
+        let style = TextStyle { alpha_test: 50, ..TextStyle::new(font()) };
+        let color_buffer = draw("CD", &style);
+        assert_ne!(RGBA::from_u32(color_buffer.at(12, 22)), RGBA::new(0, 0, 0, 255));
+    }
+}
+
+#[cfg(test)]
+mod tests_sprites {
+    use super::*;
+
+    fn draw(batch: &mut SpriteBatch) -> TiledBuffer<u32, 64, 64> {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(100, 70);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 100, 70));
+        batch.commit(&mut rasterizer);
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        color_buffer
+    }
+
+    #[test]
+    fn sprites_are_tinted_and_rotated() {
+        // A 2x2 texture with a white left column and a green right one
+        let texture = Texture::new(&TextureSource {
+            texels: &[255, 255, 255, 255, 0, 255, 0, 255, 255, 255, 255, 255, 0, 255, 0, 255],
+            width: 2,
+            height: 2,
+            format: TextureFormat::RGBA,
+            ..Default::default()
+        });
+        let mut batch = SpriteBatch::new();
+        batch.add(&Sprite {
+            position: Vec2::new(25.0, 35.0),
+            size: Vec2::new(20.0, 20.0),
+            texture: Some(texture.clone()),
+            color: Vec4::new(1.0, 0.0, 1.0, 1.0),
+            ..Default::default()
+        });
+        // Turned upside down, so the green column is on the left
+        batch.add(&Sprite {
+            position: Vec2::new(75.0, 35.0),
+            size: Vec2::new(20.0, 20.0),
+            rotation: std::f32::consts::PI,
+            texture: Some(texture),
+            ..Default::default()
+        });
+        // A translucent untextured quad over both
+        batch.add(&Sprite {
+            position: Vec2::new(50.0, 10.0),
+            size: Vec2::new(100.0, 4.0),
+            color: Vec4::new(0.0, 0.0, 1.0, 0.5),
+            ..Default::default()
+        });
+        assert_eq!(batch.len(), 3);
+        let color_buffer = draw(&mut batch);
+        assert!(batch.is_empty());
+        assert_eq!(RGBA::from_u32(color_buffer.at(18, 35)), RGBA::new(255, 0, 255, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(31, 35)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(68, 35)), RGBA::new(0, 255, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(81, 35)), RGBA::new(255, 255, 255, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(50, 35)), RGBA::new(0, 0, 0, 255));
+        assert_rgba_eq!(RGBA::from_u32(color_buffer.at(50, 10)), RGBA::new(0, 0, 128, 255), 1);
+    }
+}
+
+#[cfg(test)]
+mod tests_billboards {
+    use super::*;
+
+    fn draw(command: &BillboardCommand) -> TiledBuffer<u32, 64, 64> {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(64, 64);
+        depth_buffer.fill(u16::MAX);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit_billboards(command);
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: Some(&mut depth_buffer),
+            ..Default::default()
+        });
+        color_buffer
+    }
+
+    #[test]
+    fn billboards_face_rotated_camera() {
+        // The camera at (10, 0, 0) looking towards the origin, an edge-on quad would've been invisible
+        let view: Mat44 = Mat44::rotate_zx(-std::f32::consts::FRAC_PI_2) * Mat44::translate(Vec3::new(-10.0, 0.0, 0.0));
+        let command = BillboardCommand {
+            centers: &[Vec3::new(0.0, 0.0, 0.0)],
+            size: Vec2::new(2.0, 2.0),
+            colors: &[Vec4::new(1.0, 0.0, 0.0, 1.0)],
+            view,
+            projection: Mat44::perspective(1.0, 100.0, std::f32::consts::PI / 3.0, 1.0),
+            ..Default::default()
+        };
+        for mode in [BillboardMode::Spherical, BillboardMode::Cylindrical(Vec3::new(0.0, 1.0, 0.0))] {
+            let color_buffer = draw(&BillboardCommand { mode, ..command.clone() });
+            assert_eq!(RGBA::from_u32(color_buffer.at(32, 32)), RGBA::new(255, 0, 0, 255));
+            assert_eq!(RGBA::from_u32(color_buffer.at(2, 2)), RGBA::new(0, 0, 0, 255));
+        }
+    }
+
+    #[test]
+    fn billboards_without_depth_write_dont_occlude() {
+        // Two overlapping billboards, the nearer one is committed first
+        let centers = [Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.5, 0.0, -10.0)];
+        let colors = [Vec4::new(1.0, 0.0, 0.0, 1.0), Vec4::new(0.0, 0.0, 1.0, 1.0)];
+        let command = BillboardCommand {
+            centers: &centers,
+            size: Vec2::new(2.0, 2.0),
+            colors: &colors,
+            projection: Mat44::perspective(1.0, 100.0, std::f32::consts::PI / 3.0, 1.0),
+            ..Default::default()
+        };
+        let color_buffer = draw(&command);
+        assert_eq!(RGBA::from_u32(color_buffer.at(32, 32)), RGBA::new(255, 0, 0, 255));
+
+        let color_buffer = draw(&BillboardCommand { depth_write: false, ..command });
+        assert_eq!(RGBA::from_u32(color_buffer.at(32, 32)), RGBA::new(0, 0, 255, 255));
+    }
+}
+
+#[cfg(test)]
+mod tests_particles {
+    use super::*;
+
+    #[test]
+    fn particles_are_drawn_as_billboards() {
+        let emitter = ParticleEmitter {
+            position: Vec3::new(0.0, 0.0, -5.0),
+            spawn_rate: 0.0,
+            lifetime: 2.0..2.0,
+            velocity: Vec3::new(0.0, 0.0, 0.0)..Vec3::new(0.0, 0.0, 0.0),
+            color: ParticleCurve::linear(Vec4::new(1.0, 0.0, 0.0, 1.0), Vec4::new(0.0, 0.0, 1.0, 1.0)),
+            alpha_blending: AlphaBlendingMode::None,
+            ..Default::default()
+        };
+        let mut system = ParticleSystem::new(emitter, 42);
+        system.emit(1);
+        system.step(1.0);
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        let projection: Mat44 = Mat44::perspective(1.0, 100.0, std::f32::consts::PI / 3.0, 1.0);
+        system.commit(&mut rasterizer, Mat44::identity(), projection);
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        // Halfway through the life, halfway between the colors
+        assert_eq!(RGBA::from_u32(color_buffer.at(32, 32)), RGBA::new(127, 0, 127, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(2, 2)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(rasterizer.statistics().committed_triangles, 2);
+    }
+}
+
+#[cfg(test)]
+mod tests_nine_patch {
+    use super::*;
+
+    // A 16x16 image with a 4-texel red frame around a green center, placed into the given cell of a square atlas with
+    // the blue background
+    fn frame_atlas(cells: usize, cell_x: usize, cell_y: usize) -> std::sync::Arc<Texture> {
+        let size: usize = 16 * cells;
+        let mut texels: Vec<u8> = vec![0u8; size * size * 4];
+        for y in 0..size {
+            for x in 0..size {
+                let color: [u8; 4] = if x / 16 != cell_x || y / 16 != cell_y {
+                    [0, 0, 255, 255]
+                } else if (4..12).contains(&(x % 16)) && (4..12).contains(&(y % 16)) {
+                    [0, 255, 0, 255]
+                } else {
+                    [255, 0, 0, 255]
+                };
+                texels[(y * size + x) * 4..(y * size + x) * 4 + 4].copy_from_slice(&color);
+            }
+        }
+        Texture::new(&TextureSource {
+            texels: &texels,
+            width: size as u32,
+            height: size as u32,
+            format: TextureFormat::RGBA,
+            ..Default::default()
+        })
+    }
+
+    fn draw(command: &NinePatchCommand) -> TiledBuffer<u32, 64, 64> {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(100, 70);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 100, 70));
+        rasterizer.commit_nine_patch(command);
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        color_buffer
+    }
+
+    #[test]
+    fn borders_are_not_stretched() {
+        let color_buffer = draw(&NinePatchCommand {
+            min: Vec2::new(10.0, 10.0),
+            max: Vec2::new(90.0, 60.0),
+            texture: Some(frame_atlas(1, 0, 0)),
+            left: 4.0,
+            top: 4.0,
+            right: 4.0,
+            bottom: 4.0,
+            ..Default::default()
+        });
+        let red = RGBA::new(255, 0, 0, 255);
+        let green = RGBA::new(0, 255, 0, 255);
+        assert_eq!(RGBA::from_u32(color_buffer.at(9, 9)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(10, 10)), red);
+        assert_eq!(RGBA::from_u32(color_buffer.at(13, 13)), red);
+        assert_eq!(RGBA::from_u32(color_buffer.at(14, 14)), green);
+        assert_eq!(RGBA::from_u32(color_buffer.at(50, 12)), red);
+        assert_eq!(RGBA::from_u32(color_buffer.at(50, 35)), green);
+        assert_eq!(RGBA::from_u32(color_buffer.at(85, 55)), green);
+        assert_eq!(RGBA::from_u32(color_buffer.at(86, 56)), red);
+        assert_eq!(RGBA::from_u32(color_buffer.at(89, 59)), red);
+        assert_eq!(RGBA::from_u32(color_buffer.at(90, 60)), RGBA::new(0, 0, 0, 255));
+    }
+
+    #[test]
+    fn atlas_cell_does_not_bleed() {
+        let color_buffer = draw(&NinePatchCommand {
+            min: Vec2::new(10.0, 10.0),
+            max: Vec2::new(90.0, 60.0),
+            texture: Some(frame_atlas(4, 1, 2)),
+            region: Some(TextureRegion { u0: 0.25, v0: 0.5, u1: 0.5, v1: 0.75, clamp: false }),
+            left: 4.0,
+            top: 4.0,
+            right: 4.0,
+            bottom: 4.0,
+            scale: 2.0,
+            sampling_filter: SamplerFilter::Bilinear,
+            ..Default::default()
+        });
+        for y in 10..60 {
+            for x in 10..90 {
+                assert!(RGBA::from_u32(color_buffer.at(x, y)).b <= 2, "({}, {})", x, y);
+            }
+        }
+        assert_eq!(RGBA::from_u32(color_buffer.at(11, 11)), RGBA::new(255, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(50, 35)), RGBA::new(0, 255, 0, 255));
+    }
+
+    #[test]
+    fn untextured_panel_is_a_color_fill() {
+        let color_buffer = draw(&NinePatchCommand {
+            min: Vec2::new(20.0, 20.0),
+            max: Vec2::new(40.0, 30.0),
+            color: Vec4::new(1.0, 1.0, 1.0, 0.5),
+            left: 8.0,
+            right: 8.0,
+            alpha_blending: AlphaBlendingMode::Normal,
+            ..Default::default()
+        });
+        assert_eq!(RGBA::from_u32(color_buffer.at(19, 25)), RGBA::new(0, 0, 0, 255));
+        assert_rgba_eq!(RGBA::from_u32(color_buffer.at(20, 20)), RGBA::new(128, 128, 128, 255), 1);
+        assert_rgba_eq!(RGBA::from_u32(color_buffer.at(30, 25)), RGBA::new(128, 128, 128, 255), 1);
+        assert_rgba_eq!(RGBA::from_u32(color_buffer.at(39, 29)), RGBA::new(128, 128, 128, 255), 1);
+        assert_eq!(RGBA::from_u32(color_buffer.at(40, 30)), RGBA::new(0, 0, 0, 255));
+    }
+}
+
+#[cfg(test)]
+mod tests_hiz {
+    use super::*;
+
+    // A quad in normalized device coordinates, drawn with the identity matrices
+    fn quad(xmin: f32, ymin: f32, xmax: f32, ymax: f32, z: f32) -> [Vec3; 6] {
+        [
+            Vec3::new(xmin, ymin, z),
+            Vec3::new(xmax, ymin, z),
+            Vec3::new(xmax, ymax, z),
+            Vec3::new(xmin, ymin, z),
+            Vec3::new(xmax, ymax, z),
+            Vec3::new(xmin, ymax, z),
+        ]
+    }
+
+    fn commit_quad(rasterizer: &mut Rasterizer, positions: &[Vec3], color: Vec4, sampling_filter: SamplerFilter) {
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: positions,
+            color,
+            sampling_filter,
+            ..Default::default()
+        });
+    }
+
+    // Draws the quads front to back, returns the color buffer and the statistics
+    fn draw(quads: &[([Vec3; 6], Vec4)], hierarchical_z: bool) -> (TiledBuffer<u32, 64, 64>, RasterizerStatistics) {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(150, 130);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(150, 130);
+        depth_buffer.fill(u16::MAX);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.set_hierarchical_z(hierarchical_z);
+        rasterizer.setup(Viewport::new(0, 0, 150, 130));
+        // The HiZ is refreshed after each batch of a command's triangles, alternate the (unused) sampling filter to keep
+        // the quads in separate commands
+        for (i, (positions, color)) in quads.iter().enumerate() {
+            let sampling_filter = if i % 2 == 0 {
+                SamplerFilter::Nearest
+            } else {
+                SamplerFilter::Bilinear
+            };
+            commit_quad(&mut rasterizer, positions, *color, sampling_filter);
+        }
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: Some(&mut depth_buffer),
+            ..Default::default()
+        });
+        (color_buffer, rasterizer.statistics())
+    }
+
+    fn assert_buffers_eq(a: &TiledBuffer<u32, 64, 64>, b: &TiledBuffer<u32, 64, 64>) {
+        for y in 0..a.height() {
+            for x in 0..a.width() {
+                assert_eq!(RGBA::from_u32(a.at(x, y)), RGBA::from_u32(b.at(x, y)), "{} {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn occluded_triangles_are_rejected() {
+        let quads = [
+            (quad(-1.0, -1.0, 1.0, 1.0, -0.5), Vec4::new(1.0, 0.0, 0.0, 1.0)),
+            (quad(-0.8, -0.8, 0.8, 0.8, 0.5), Vec4::new(0.0, 1.0, 0.0, 1.0)),
+        ];
+        let (expected, expected_stats) = draw(&quads, false);
+        let (actual, actual_stats) = draw(&quads, true);
+        assert_buffers_eq(&actual, &expected);
+        assert_eq!(expected_stats.hiz_rejected_triangles, 0);
+        // Both triangles of the far quad are rejected in every tile they were binned into
+        let (_, far_stats) = draw(&quads[1..], true);
+        assert_eq!(actual_stats.hiz_rejected_triangles, far_stats.binned_triangles);
+        assert!(actual_stats.fragments_drawn <= expected_stats.fragments_drawn);
+        assert_eq!(RGBA::from_u32(actual.at(75, 65)), RGBA::new(255, 0, 0, 255));
+    }
+
+    #[test]
+    fn visible_triangles_are_kept() {
+        // Back to front, nothing can be rejected
+        let quads = [
+            (quad(-1.0, -1.0, 1.0, 1.0, 0.5), Vec4::new(1.0, 0.0, 0.0, 1.0)),
+            (quad(-0.8, -0.8, 0.8, 0.8, -0.5), Vec4::new(0.0, 1.0, 0.0, 1.0)),
+            // Same depth as the first quad, the depth test discards it, but the blocks are not strictly closer
+            (quad(-0.9, -0.9, 0.9, 0.9, 0.5), Vec4::new(0.0, 0.0, 1.0, 1.0)),
+        ];
+        let (expected, _) = draw(&quads, false);
+        let (actual, actual_stats) = draw(&quads, true);
+        assert_buffers_eq(&actual, &expected);
+        assert_eq!(actual_stats.hiz_rejected_triangles, 0);
+        assert_eq!(RGBA::from_u32(actual.at(75, 65)), RGBA::new(0, 255, 0, 255));
+    }
+
+    #[test]
+    fn partially_covered_blocks_keep_triangles() {
+        // The occluder covers the left half only, the far quad behind it is visible on the right
+        let quads = [
+            (quad(-1.0, -1.0, 0.0, 1.0, -0.5), Vec4::new(1.0, 0.0, 0.0, 1.0)),
+            (quad(-0.8, -0.8, 0.8, 0.8, 0.5), Vec4::new(0.0, 1.0, 0.0, 1.0)),
+            // Small far quads: entirely behind the occluder and straddling its edge
+            (quad(-0.7, -0.2, -0.5, 0.2, 0.9), Vec4::new(0.0, 0.0, 1.0, 1.0)),
+            (quad(-0.1, -0.2, 0.1, 0.2, 0.0), Vec4::new(1.0, 1.0, 0.0, 1.0)),
+        ];
+        let (expected, _) = draw(&quads, false);
+        let (actual, actual_stats) = draw(&quads, true);
+        assert_buffers_eq(&actual, &expected);
+        assert!(actual_stats.hiz_rejected_triangles >= 2);
+        assert_eq!(RGBA::from_u32(actual.at(110, 65)), RGBA::new(0, 255, 0, 255));
+        assert_eq!(RGBA::from_u32(actual.at(80, 65)), RGBA::new(255, 255, 0, 255));
+    }
+
+    #[test]
+    fn depth_from_previous_draws_is_used() {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(150, 130);
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(150, 130);
+        depth_buffer.fill(u16::MAX);
+        let occluder = quad(-1.0, -1.0, 1.0, 1.0, -0.5);
+        let occluded = quad(-0.5, -0.5, 0.5, 0.5, 0.0);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 150, 130));
+        commit_quad(&mut rasterizer, &occluder, Vec4::new(1.0, 0.0, 0.0, 1.0), SamplerFilter::Nearest);
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: Some(&mut depth_buffer),
+            ..Default::default()
+        });
+        assert_eq!(rasterizer.statistics().hiz_rejected_triangles, 0);
+
+        rasterizer.setup(Viewport::new(0, 0, 150, 130));
+        commit_quad(&mut rasterizer, &occluded, Vec4::new(0.0, 1.0, 0.0, 1.0), SamplerFilter::Nearest);
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: Some(&mut depth_buffer),
+            ..Default::default()
+        });
+        let statistics: RasterizerStatistics = rasterizer.statistics();
+        assert!(statistics.binned_triangles > 0);
+        assert_eq!(statistics.hiz_rejected_triangles, statistics.binned_triangles);
+        assert_eq!(RGBA::from_u32(color_buffer.at(75, 65)), RGBA::new(255, 0, 0, 255));
+    }
+}
+
+#[cfg(test)]
+mod tests_depth_sorting {
+    use super::*;
+
+    // A quad in normalized device coordinates, drawn with the identity matrices
+    fn quad(xmin: f32, ymin: f32, xmax: f32, ymax: f32, z: f32) -> [Vec3; 6] {
+        [
+            Vec3::new(xmin, ymin, z),
+            Vec3::new(xmax, ymin, z),
+            Vec3::new(xmax, ymax, z),
+            Vec3::new(xmin, ymin, z),
+            Vec3::new(xmax, ymax, z),
+            Vec3::new(xmin, ymax, z),
+        ]
+    }
+
+    // Draws the quads in the given order, each one as a separate command
+    fn draw(
+        quads: &[([Vec3; 6], Vec4)],
+        opacity: OpacityHint,
+        depth_sorting: bool,
+    ) -> (TiledBuffer<u32, 64, 64>, RasterizerStatistics) {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(150, 130);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(150, 130);
+        depth_buffer.fill(u16::MAX);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.set_depth_sorting(depth_sorting);
+        rasterizer.setup(Viewport::new(0, 0, 150, 130));
+        let alpha_blending = if opacity != OpacityHint::Opaque {
+            AlphaBlendingMode::Normal
+        } else {
+            AlphaBlendingMode::None
+        };
+        for (i, (positions, color)) in quads.iter().enumerate() {
+            let sampling_filter = if i % 2 == 0 {
+                SamplerFilter::Nearest
+            } else {
+                SamplerFilter::Bilinear
+            };
+            rasterizer.commit(&RasterizationCommand {
+                world_positions: positions,
+                color: *color,
+                sampling_filter,
+                alpha_blending,
+                opacity,
+                ..Default::default()
+            });
+        }
+        // Blended quads don't need a depth buffer
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: if opacity != OpacityHint::Opaque {
+                None
+            } else {
+                Some(&mut depth_buffer)
+            },
+            ..Default::default()
+        });
+        (color_buffer, rasterizer.statistics())
+    }
+
+    #[test]
+    fn opaque_triangles_are_drawn_front_to_back() {
+        // Back to front
+        let quads = [
+            (quad(-0.8, -0.8, 0.8, 0.8, 0.5), Vec4::new(1.0, 0.0, 0.0, 1.0)),
+            (quad(-1.0, -1.0, 1.0, 1.0, -0.5), Vec4::new(0.0, 1.0, 0.0, 1.0)),
+        ];
+        let (unsorted, unsorted_stats) = draw(&quads, OpacityHint::Opaque, false);
+        let (sorted, sorted_stats) = draw(&quads, OpacityHint::Opaque, true);
+        for y in 0..unsorted.height() {
+            for x in 0..unsorted.width() {
+                assert_eq!(RGBA::from_u32(sorted.at(x, y)), RGBA::from_u32(unsorted.at(x, y)), "{} {}", x, y);
+            }
+        }
+        assert_eq!(RGBA::from_u32(sorted.at(75, 65)), RGBA::new(0, 255, 0, 255));
+        // Drawn first, the near quad lets the hierarchical Z test reject the far one entirely
+        assert_eq!(unsorted_stats.hiz_rejected_triangles, 0);
+        let (_, far_stats) = draw(&quads[..1], OpacityHint::Opaque, true);
+        assert_eq!(sorted_stats.hiz_rejected_triangles, far_stats.binned_triangles);
+    }
+
+    #[test]
+    fn translucent_triangles_are_drawn_back_to_front() {
+        // Front to back
+        let quads = [
+            (quad(-0.5, -0.5, 0.5, 0.5, -0.5), Vec4::new(1.0, 0.0, 0.0, 0.5)),
+            (quad(-1.0, -1.0, 1.0, 1.0, 0.5), Vec4::new(0.0, 0.0, 1.0, 0.5)),
+        ];
+        // Submission order: the far blue quad is blended over the near red one
+        let (unsorted, _) = draw(&quads, OpacityHint::Translucent, false);
+        let color = RGBA::from_u32(unsorted.at(75, 65));
+        assert!(color.b > color.r, "{:?}", color);
+        // Sorted: the near red quad is blended last
+        let (sorted, _) = draw(&quads, OpacityHint::Translucent, true);
+        let color = RGBA::from_u32(sorted.at(75, 65));
+        assert!(color.r > color.b, "{:?}", color);
+        assert!(color.r.abs_diff(128) <= 2 && color.b.abs_diff(64) <= 2, "{:?}", color);
+        assert_eq!(sorted.at(5, 5), unsorted.at(5, 5));
+    }
+
+    #[test]
+    fn ordered_commands_keep_their_place() {
+        let quads = [
+            (quad(-0.5, -0.5, 0.5, 0.5, -0.5), Vec4::new(1.0, 0.0, 0.0, 0.5)),
+            (quad(-1.0, -1.0, 1.0, 1.0, 0.5), Vec4::new(0.0, 0.0, 1.0, 0.5)),
+        ];
+        let (unsorted, _) = draw(&quads, OpacityHint::Ordered, false);
+        let (sorted, _) = draw(&quads, OpacityHint::Ordered, true);
+        assert_eq!(sorted.at(75, 65), unsorted.at(75, 65));
+        let color = RGBA::from_u32(sorted.at(75, 65));
+        assert!(color.b > color.r, "{:?}", color);
+    }
+}
+
+#[cfg(test)]
+mod tests_vertex_cache {
+    use super::*;
+
+    // A unit cube with 8 shared vertices and 12 triangles
+    const CUBE_POSITIONS: [Vec3; 8] = [
+        Vec3::new(-0.5, -0.5, -0.5),
+        Vec3::new(0.5, -0.5, -0.5),
+        Vec3::new(0.5, 0.5, -0.5),
+        Vec3::new(-0.5, 0.5, -0.5),
+        Vec3::new(-0.5, -0.5, 0.5),
+        Vec3::new(0.5, -0.5, 0.5),
+        Vec3::new(0.5, 0.5, 0.5),
+        Vec3::new(-0.5, 0.5, 0.5),
+    ];
+    const CUBE_INDICES: [u32; 36] = [
+        0, 2, 1, 0, 3, 2, // -Z
+        4, 5, 6, 4, 6, 7, // +Z
+        0, 1, 5, 0, 5, 4, // -Y
+        3, 7, 6, 3, 6, 2, // +Y
+        0, 4, 7, 0, 7, 3, // -X
+        1, 2, 6, 1, 6, 5, // +X
+    ];
+
+    fn draw(commands: &[RasterizationCommand]) -> (Buffer<u32>, RasterizerStatistics) {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(100, 100);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(100, 100);
+        depth_buffer.fill(u16::MAX);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 100, 100));
+        for command in commands {
+            rasterizer.commit(command);
+        }
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: Some(&mut depth_buffer),
+            ..Default::default()
+        });
+        (color_buffer.as_flat_buffer(), rasterizer.statistics())
+    }
+
+    fn cube_command<'a>(positions: &'a [Vec3], indices: &'a [u32], colors: &'a [Vec4]) -> RasterizationCommand<'a> {
+        RasterizationCommand {
+            world_positions: positions,
+            indices,
+            colors,
+            model: Mat34::translate(Vec3::new(0.0, 0.0, -3.0)) * Mat34::rotate_zx(0.5) * Mat34::rotate_yz(0.4),
+            projection: Mat44::perspective(0.1, 10.0, std::f32::consts::PI / 3.0, 1.0),
+            culling: CullMode::CW,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn shared_vertices_are_transformed_once() {
+        let colors: Vec<Vec4> = CUBE_POSITIONS
+            .iter()
+            .map(|p| (*p + Vec3::new(0.5, 0.5, 0.5)).as_point4())
+            .collect();
+        let (indexed, indexed_stats) = draw(&[cube_command(&CUBE_POSITIONS, &CUBE_INDICES, &colors)]);
+        assert_eq!(indexed_stats.transformed_vertices, 8);
+        assert_eq!(indexed_stats.vertex_cache_hits, 28);
+        assert!((indexed_stats.vertex_cache_hit_rate() - 28.0 / 36.0).abs() < 1e-6);
+
+        // The same cube without indices, every vertex is transformed
+        let positions: Vec<Vec3> = CUBE_INDICES.iter().map(|&i| CUBE_POSITIONS[i as usize]).collect();
+        let flat_colors: Vec<Vec4> = CUBE_INDICES.iter().map(|&i| colors[i as usize]).collect();
+        let (flat, flat_stats) = draw(&[cube_command(&positions, &[], &flat_colors)]);
+        assert_eq!(flat_stats.transformed_vertices, 36);
+        assert_eq!(flat_stats.vertex_cache_hits, 0);
+        assert_eq!(flat_stats.vertex_cache_hit_rate(), 0.0);
+        assert_eq!(flat_stats.scheduled_triangles, indexed_stats.scheduled_triangles);
+        assert_eq!(indexed.elems, flat.elems);
+    }
+
+    #[test]
+    fn cache_is_per_command() {
+        let colors: Vec<Vec4> = vec![Vec4::new(1.0, 0.0, 0.0, 1.0); 8];
+        let first = cube_command(&CUBE_POSITIONS, &CUBE_INDICES, &colors);
+        // Moved by the model matrix, the same vertices must be transformed again
+        let second = RasterizationCommand { model: Mat34::translate(Vec3::new(0.5, 0.0, -4.0)), ..first.clone() };
+        let (_, stats) = draw(&[first, second]);
+        assert_eq!(stats.transformed_vertices, 16);
+        assert_eq!(stats.vertex_cache_hits, 56);
+    }
+}
+
+#[cfg(test)]
+mod tests_determinism {
+    use super::*;
+
+    // Pseudo-random values in [0, 1), a fixed sequence
+    struct Lcg(u32);
+    impl Lcg {
+        fn next(&mut self) -> f32 {
+            self.0 = self.0.wrapping_mul(1664525).wrapping_add(1013904223);
+            (self.0 >> 8) as f32 / (1 << 24) as f32
+        }
+    }
+
+    // Many overlapping triangles crossing the tiles' boundaries, depth-tested, blended and with per-vertex normals
+    fn commit_triangles(rasterizer: &mut Rasterizer, positions: &[Vec3], normals: &[Vec3], colors: &[Vec4]) {
+        for chunk in 0..positions.len() / 30 {
+            let range = chunk * 30..(chunk + 1) * 30;
+            rasterizer.commit(&RasterizationCommand {
+                world_positions: &positions[range.clone()],
+                normals: &normals[range.clone()],
+                colors: &colors[range],
+                alpha_blending: if chunk % 2 == 0 {
+                    AlphaBlendingMode::None
+                } else {
+                    AlphaBlendingMode::Normal
+                },
+                ..Default::default()
+            });
+        }
+    }
+
+    fn geometry() -> (Vec<Vec3>, Vec<Vec3>, Vec<Vec4>) {
+        let mut rng = Lcg(42);
+        let mut positions: Vec<Vec3> = Vec::new();
+        let mut normals: Vec<Vec3> = Vec::new();
+        let mut colors: Vec<Vec4> = Vec::new();
+        for _ in 0..600 {
+            let center = Vec2::new(rng.next() * 2.2 - 1.1, rng.next() * 2.2 - 1.1);
+            let z: f32 = rng.next() * 1.8 - 0.9;
+            for _ in 0..3 {
+                positions.push(Vec3::new(
+                    center.x + rng.next() - 0.5,
+                    center.y + rng.next() - 0.5,
+                    z + rng.next() * 0.1,
+                ));
+                normals.push(Vec3::new(rng.next() - 0.5, rng.next() - 0.5, 1.0).normalized());
+                colors.push(Vec4::new(rng.next(), rng.next(), rng.next(), rng.next()));
+            }
+        }
+        (positions, normals, colors)
+    }
+
+    #[test]
+    fn parallel_output_matches_single_threaded() {
+        let (positions, normals, colors) = geometry();
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(333, 257);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(333, 257);
+        depth_buffer.fill(u16::MAX);
+        let mut normal_buffer = TiledBuffer::<u32, 64, 64>::new(333, 257);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 333, 257));
+        commit_triangles(&mut rasterizer, &positions, &normals, &colors);
+        let mismatches = rasterizer.draw_checking_determinism(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: Some(&mut depth_buffer),
+            normal_buffer: Some(&mut normal_buffer),
+            ..Default::default()
+        });
+        assert!(mismatches.is_empty(), "{:?}", mismatches);
+        // The fragments are counted only in Debug builds
+        assert_eq!(rasterizer.statistics().fragments_drawn > 0, cfg!(debug_assertions));
+    }
+
+    #[test]
+    fn single_threaded_draw_matches_parallel() {
+        let (positions, normals, colors) = geometry();
+        let render = |multithreading: bool| {
+            let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(333, 257);
+            let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(333, 257);
+            depth_buffer.fill(u16::MAX);
+            let mut rasterizer = Rasterizer::new();
+            rasterizer.set_multithreading(multithreading);
+            rasterizer.setup(Viewport::new(0, 0, 333, 257));
+            commit_triangles(&mut rasterizer, &positions, &normals, &colors);
+            rasterizer.draw(&mut Framebuffer {
+                color_buffer: Some(&mut color_buffer),
+                depth_buffer: Some(&mut depth_buffer),
+                ..Default::default()
+            });
+            (color_buffer, rasterizer.statistics())
+        };
+        let (single_buffer, single_stats) = render(false);
+        let (parallel_buffer, parallel_stats) = render(true);
+        assert_eq!(single_stats.fragments_drawn, parallel_stats.fragments_drawn);
+        assert_eq!(single_stats.hiz_rejected_triangles, parallel_stats.hiz_rejected_triangles);
+        for y in 0..257 {
+            for x in 0..333 {
+                assert_eq!(single_buffer.at(x, y), parallel_buffer.at(x, y), "at ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn statistics_are_accumulated_once() {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(100, 100);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 100, 100));
+        let positions = [Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 0.0)];
+        rasterizer.commit(&RasterizationCommand { world_positions: &positions, ..Default::default() });
+        let mismatches = rasterizer.draw_checking_determinism(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            ..Default::default()
+        });
+        assert!(mismatches.is_empty());
+        let expected_fragments: usize = if cfg!(debug_assertions) { 5050 } else { 0 };
+        assert_eq!(rasterizer.statistics().fragments_drawn, expected_fragments);
+        assert_eq!(RGBA::from_u32(color_buffer.at(99, 0)), RGBA::new(255, 255, 255, 255));
+    }
+}
+
+#[cfg(test)]
+mod tests_external_memory {
+    use super::*;
+
+    #[test]
+    fn draw_into_external_memory() {
+        let color_memory = vec![0u8; TiledBuffer::<u32, 64, 64>::required_bytes(100, 70)];
+        let mut color_buffer =
+            TiledBuffer::<u32, 64, 64>::from_external_memory(100, 70, Box::new(color_memory)).unwrap();
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 100, 70));
+        let positions = [Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 0.0)];
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &positions,
+            color: Vec4::new(1.0, 0.0, 0.0, 1.0),
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        assert_eq!(RGBA::from_u32(color_buffer.at(99, 0)), RGBA::new(255, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(0, 69)), RGBA::new(0, 0, 0, 0));
+
+        // The drawn pixels are in the caller's memory, (99, 0) is in the second tile
+        let memory = color_buffer.into_external_memory().unwrap();
+        let offset: usize = (64 * 64 + 35) * 4;
+        let pixel = u32::from_ne_bytes(memory.bytes()[offset..offset + 4].try_into().unwrap());
+        assert_eq!(RGBA::from_u32(pixel), RGBA::new(255, 0, 0, 255));
+    }
+}
+
+#[cfg(test)]
+mod tests_parallel_commit {
+    use super::*;
+
+    // A grid of quads covering the viewport, white except for the quads of the last rows, which get a gradient
+    fn grid(cells: usize) -> (Vec<Vec3>, Vec<Vec4>, Vec<u32>) {
+        let mut positions: Vec<Vec3> = Vec::new();
+        let mut colors: Vec<Vec4> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        for y in 0..=cells {
+            for x in 0..=cells {
+                let (u, v) = (x as f32 / cells as f32, y as f32 / cells as f32);
+                positions.push(Vec3::new(u * 2.2 - 1.1, v * 2.2 - 1.1, (u - v) * 0.5));
+                colors.push(if y + 10 > cells {
+                    Vec4::new(u, v, 1.0 - u, 1.0)
+                } else {
+                    Vec4::new(1.0, 1.0, 1.0, 1.0)
+                });
+            }
+        }
+        let row: u32 = cells as u32 + 1;
+        for y in 0..cells as u32 {
+            for x in 0..cells as u32 {
+                let i: u32 = y * row + x;
+                indices.extend_from_slice(&[i, i + 1, i + row + 1, i, i + row + 1, i + row]);
+            }
+        }
+        (positions, colors, indices)
+    }
+
+    fn draw(multithreading: bool, indexed: bool) -> (TiledBuffer<u32, 64, 64>, RasterizerStatistics) {
+        let (positions, colors, indices) = grid(60);
+        let (positions, colors, indices): (Vec<Vec3>, Vec<Vec4>, Vec<u32>) = if indexed {
+            (positions, colors, indices)
+        } else {
+            let positions = indices.iter().map(|&i| positions[i as usize]).collect();
+            let colors = indices.iter().map(|&i| colors[i as usize]).collect();
+            (positions, colors, Vec::new())
+        };
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(200, 150);
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(200, 150);
+        depth_buffer.fill(u16::MAX);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.set_multithreading(multithreading);
+        rasterizer.setup(Viewport::new(0, 0, 200, 150));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &positions,
+            colors: &colors,
+            indices: &indices,
+            model: Mat34::rotate_xy(0.3),
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: Some(&mut depth_buffer),
+            ..Default::default()
+        });
+        (color_buffer, rasterizer.statistics())
+    }
+
+    #[test]
+    fn parallel_commit_matches_single_threaded() {
+        for indexed in [false, true] {
+            let (single_buffer, single_stats) = draw(false, indexed);
+            let (parallel_buffer, parallel_stats) = draw(true, indexed);
+            assert_eq!(single_stats.committed_triangles, 7200);
+            assert_eq!(single_stats.scheduled_triangles, parallel_stats.scheduled_triangles);
+            assert_eq!(single_stats.binned_triangles, parallel_stats.binned_triangles);
+            assert_eq!(single_stats.fragments_drawn, parallel_stats.fragments_drawn);
+            for y in 0..150 {
+                for x in 0..200 {
+                    assert_eq!(single_buffer.at(x, y), parallel_buffer.at(x, y), "at ({}, {})", x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn colors_of_later_chunks_are_interpolated() {
+        // Only the last rows are colored, they are assembled by the last chunks
+        let (color_buffer, _) = draw(true, true);
+        let top: RGBA = RGBA::from_u32(color_buffer.at(100, 2));
+        let bottom: RGBA = RGBA::from_u32(color_buffer.at(100, 147));
+        assert_ne!(top, RGBA::new(255, 255, 255, 255));
+        assert_eq!(bottom, RGBA::new(255, 255, 255, 255));
+    }
+}
+
+#[cfg(test)]
+mod tests_clear_values {
+    use super::*;
+
+    #[test]
+    fn clear_all_fills_present_buffers() {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(70, 10);
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(70, 10);
+        let mut framebuffer = Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: Some(&mut depth_buffer),
+            ..Default::default()
+        };
+        framebuffer.clear_all(&ClearValues { color: RGBA::new(10, 20, 30, 40), ..Default::default() });
+        assert_eq!(RGBA::from_u32(color_buffer.at(69, 9)), RGBA::new(10, 20, 30, 40));
+        assert_eq!(depth_buffer.at(69, 9), u16::MAX);
+    }
+
+    #[test]
+    fn default_normal_points_up() {
+        let mut normal_buffer = TiledBuffer::<u32, 64, 64>::new(10, 10);
+        Framebuffer { normal_buffer: Some(&mut normal_buffer), ..Default::default() }
+            .clear_all(&ClearValues::default());
+        let normal: Vec3 = decode_normal_from_color(RGBA::from_u32(normal_buffer.at(5, 5)));
+        assert!((normal - Vec3::new(0.0, 1.0, 0.0)).length() < 0.01, "{:?}", normal);
+        assert_eq!(RGBA::from_u32(normal_buffer.at(5, 5)), RGBA::new(127, 255, 127, 0));
+    }
+
+    #[test]
+    fn hdr_color_is_encoded() {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(10, 10);
+        Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            color_format: ColorBufferFormat::Rgb9e5,
+            ..Default::default()
+        }
+        .clear_all(&ClearValues { color: RGBA::new(255, 0, 51, 255), ..Default::default() });
+        let color: Vec3 = decode_rgb9e5(color_buffer.at(0, 0));
+        assert!((color - Vec3::new(1.0, 0.0, 0.2)).length() < 0.01, "{:?}", color);
+    }
+}
+
+#[cfg(test)]
+mod tests_baked_mesh {
+    use super::*;
+
+    const WHITE: RGBA = RGBA { r: 255, g: 255, b: 255, a: 255 };
+    const BLACK: RGBA = RGBA { r: 0, g: 0, b: 0, a: 255 };
+
+    // A quad spanning [-0.5, 0.5] in NDC plus a triangle behind the camera, which is clipped away
+    const POSITIONS: [Vec3; 9] = [
+        Vec3 { x: -0.5, y: -0.5, z: 0.0 },
+        Vec3 { x: 0.5, y: -0.5, z: 0.0 },
+        Vec3 { x: 0.5, y: 0.5, z: 0.0 },
+        Vec3 { x: -0.5, y: -0.5, z: 0.0 },
+        Vec3 { x: 0.5, y: 0.5, z: 0.0 },
+        Vec3 { x: -0.5, y: 0.5, z: 0.0 },
+        Vec3 { x: -0.5, y: -0.5, z: 2.0 },
+        Vec3 { x: 0.5, y: -0.5, z: 2.0 },
+        Vec3 { x: 0.5, y: 0.5, z: 2.0 },
+    ];
+
+    fn draw(rasterizer: &mut Rasterizer) -> TiledBuffer<u32, 64, 64> {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(200, 100);
+        color_buffer.fill(BLACK.to_u32());
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        color_buffer
+    }
+
+    #[test]
+    fn committing_baked_mesh_matches_committing_command() {
+        let command = RasterizationCommand { world_positions: &POSITIONS, ..Default::default() };
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 200, 100));
+        rasterizer.commit(&command);
+        let expected_stats: RasterizerStatistics = rasterizer.statistics();
+        let expected: TiledBuffer<u32, 64, 64> = draw(&mut rasterizer);
+
+        rasterizer.setup(Viewport::new(0, 0, 200, 100));
+        let baked: BakedMesh = rasterizer.bake(&command);
+        assert_eq!(baked.scheduled_triangles(), 2);
+        assert_eq!(rasterizer.statistics().committed_triangles, 0);
+        rasterizer.commit_baked(&baked, None);
+        let stats: RasterizerStatistics = rasterizer.statistics();
+        assert_eq!(stats.committed_triangles, expected_stats.committed_triangles);
+        assert_eq!(stats.scheduled_triangles, expected_stats.scheduled_triangles);
+        assert_eq!(stats.binned_triangles, expected_stats.binned_triangles);
+        let actual: TiledBuffer<u32, 64, 64> = draw(&mut rasterizer);
+        for y in 0..100 {
+            for x in 0..200 {
+                assert_eq!(actual.at(x, y), expected.at(x, y), "at ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn baking_keeps_committed_commands() {
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 200, 100));
+        let left = [Vec3::new(-1.0, -1.0, 0.0), Vec3::new(-0.9, -1.0, 0.0), Vec3::new(-0.9, 1.0, 0.0)];
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &left,
+            color: Vec4::new(1.0, 0.0, 0.0, 1.0),
+            ..Default::default()
+        });
+        let baked: BakedMesh =
+            rasterizer.bake(&RasterizationCommand { world_positions: &POSITIONS, ..Default::default() });
+        rasterizer.commit_baked(&baked, None);
+        rasterizer.commit_baked(&baked, None);
+        assert_eq!(rasterizer.statistics().committed_triangles, 7);
+        let color_buffer = draw(&mut rasterizer);
+        assert_eq!(RGBA::from_u32(color_buffer.at(9, 50)), RGBA::new(255, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(100, 50)), WHITE);
+    }
+
+    #[test]
+    fn transform_delta_moves_baked_vertices() {
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 200, 100));
+        let baked: BakedMesh =
+            rasterizer.bake(&RasterizationCommand { world_positions: &POSITIONS, ..Default::default() });
+        // The quad covers [50, 150) x [25, 75), move it right by 40 pixels
+        rasterizer.commit_baked(&baked, Some(Mat34::translate(Vec3::new(40.0, 0.0, 0.0))));
+        let color_buffer = draw(&mut rasterizer);
+        assert_eq!(RGBA::from_u32(color_buffer.at(85, 50)), BLACK);
+        assert_eq!(RGBA::from_u32(color_buffer.at(95, 50)), WHITE);
+        assert_eq!(RGBA::from_u32(color_buffer.at(185, 50)), WHITE);
+        assert_eq!(RGBA::from_u32(color_buffer.at(195, 50)), BLACK);
+
+        // Mirroring keeps the triangles visible
+        rasterizer.setup(Viewport::new(0, 0, 200, 100));
+        rasterizer.commit_baked(
+            &baked,
+            Some(Mat34::translate(Vec3::new(200.0, 0.0, 0.0)) * Mat34::scale_non_uniform(Vec3::new(-1.0, 1.0, 1.0))),
+        );
+        assert_eq!(RGBA::from_u32(draw(&mut rasterizer).at(100, 50)), WHITE);
+    }
+
+    #[test]
+    #[should_panic]
+    fn baked_mesh_requires_same_viewport() {
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 200, 100));
+        let baked: BakedMesh =
+            rasterizer.bake(&RasterizationCommand { world_positions: &POSITIONS, ..Default::default() });
+        rasterizer.setup(Viewport::new(0, 0, 100, 100));
+        rasterizer.commit_baked(&baked, None);
+    }
+}
+
+#[cfg(test)]
+mod tests_aabb_culling {
+    use super::*;
+
+    fn commit_cube(rasterizer: &mut Rasterizer, model: Mat34, aabb: Option<AABB>) {
+        let positions = [
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(-1.0, 1.0, 0.0),
+        ];
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &positions,
+            model,
+            view: Mat44::translate(Vec3::new(0.0, 0.0, -10.0)),
+            projection: Mat44::perspective(1.0, 100.0, std::f32::consts::PI / 3.0, 1.0),
+            aabb,
+            ..Default::default()
+        });
+    }
+
+    const BOX: AABB = AABB { min: Vec3 { x: -1.0, y: -1.0, z: 0.0 }, max: Vec3 { x: 1.0, y: 1.0, z: 0.0 } };
+
+    #[test]
+    fn commands_outside_frustum_are_culled() {
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 100, 100));
+        // To the left, above, behind the camera and beyond the far plane
+        for translation in [
+            Vec3::new(-20.0, 0.0, 0.0),
+            Vec3::new(0.0, 20.0, 0.0),
+            Vec3::new(0.0, 0.0, 20.0),
+            Vec3::new(0.0, 0.0, -200.0),
+        ] {
+            commit_cube(&mut rasterizer, Mat34::translate(translation), Some(BOX));
+        }
+        let stats: RasterizerStatistics = rasterizer.statistics();
+        assert_eq!(stats.culled_commands, 4);
+        assert_eq!(stats.committed_triangles, 8);
+        assert_eq!(stats.transformed_vertices, 0);
+        assert_eq!(stats.scheduled_triangles, 0);
+    }
+
+    #[test]
+    fn commands_inside_or_crossing_frustum_are_kept() {
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 100, 100));
+        commit_cube(&mut rasterizer, Mat34::identity(), Some(BOX));
+        // Crossing the left plane
+        commit_cube(&mut rasterizer, Mat34::translate(Vec3::new(-6.5, 0.0, 0.0)), Some(BOX));
+        // Outside, but without the box
+        commit_cube(&mut rasterizer, Mat34::translate(Vec3::new(-20.0, 0.0, 0.0)), None);
+        let stats: RasterizerStatistics = rasterizer.statistics();
+        assert_eq!(stats.culled_commands, 0);
+        assert_eq!(stats.transformed_vertices, 18);
+        // The crossing one is partially clipped
+        assert!(stats.scheduled_triangles > 2);
+    }
+
+    #[test]
+    fn retained_commands_are_not_culled() {
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.set_retain_geometry(true);
+        rasterizer.setup(Viewport::new(0, 0, 100, 100));
+        commit_cube(&mut rasterizer, Mat34::translate(Vec3::new(-20.0, 0.0, 0.0)), Some(BOX));
+        assert_eq!(rasterizer.statistics().culled_commands, 0);
+        assert_eq!(rasterizer.statistics().transformed_vertices, 6);
+    }
+}
+
+#[cfg(test)]
+mod tests_terrain {
+    use super::*;
+
+    #[test]
+    fn terrain_chunks_are_culled_and_simplified() {
+        // A flat 256x256 terrain of 8x8 chunks, the camera stands in its middle looking towards -Z
+        let description = TerrainDescription { chunk_cells: 8, ..Default::default() };
+        let mut terrain = Terrain::from_heightmap(&Buffer::new(65, 65), &description);
+        assert_eq!(terrain.chunks_num(), 64);
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        let material = Material { color: Vec4::new(0.0, 1.0, 0.0, 1.0), ..Default::default() };
+        let transforms = Transforms {
+            view: Mat44::translate(Vec3::new(0.0, -10.0, 0.0)),
+            projection: Mat44::perspective(1.0, 1000.0, std::f32::consts::PI / 3.0, 1.0),
+            ..Default::default()
+        };
+        terrain.commit(&mut rasterizer, &material, &transforms);
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+
+        let stats: TerrainStatistics = terrain.statistics();
+        assert_eq!(stats.visible_chunks + stats.culled_chunks, 64);
+        assert!(stats.culled_chunks >= 32);
+        // The distant chunks use fewer triangles than the finest level's 8 * 8 * 2 and the skirts
+        assert!(stats.committed_triangles < stats.visible_chunks * (8 * 8 * 2 + 32 * 2));
+        assert_eq!(RGBA::from_u32(color_buffer.at(32, 60)), RGBA::new(0, 255, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(32, 4)), RGBA::new(0, 0, 0, 255));
+    }
+}
+
+#[cfg(test)]
+mod tests_primitives {
+    use super::*;
+    use nih::geometry::*;
+
+    #[test]
+    fn primitives_show_their_outer_side() {
+        // Without a depth buffer and with the back faces culled, only the sides facing the camera are drawn
+        // The pixels to check: the middle of the screen or the torus' tube to the left of its hole
+        let primitives: [(MeshData, u16); 6] = [
+            (uv_sphere(1.0, 16, 8), 32),
+            (icosphere(1.0, 2), 32),
+            (cuboid(Vec3::new(1.5, 1.5, 1.5)), 32),
+            (cylinder(1.0, 1.5, 12), 32),
+            (cone(1.0, 1.5, 12), 32),
+            (torus(1.0, 0.5, 16, 8), 21),
+        ];
+        for (mesh, x) in &primitives {
+            let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+            color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+            let mut normal_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+            normal_buffer.fill(0);
+            let mut rasterizer = Rasterizer::new();
+            rasterizer.setup(Viewport::new(0, 0, 64, 64));
+            rasterizer.commit(&RasterizationCommand {
+                // Tilted towards the camera to see the caps and the torus' hole
+                model: Mat34::translate(Vec3::new(0.0, 0.0, -5.0)) * Mat34::rotate_yz(std::f32::consts::FRAC_PI_4),
+                projection: Mat44::perspective(1.0, 10.0, std::f32::consts::PI / 3.0, 1.0),
+                culling: CullMode::CW,
+                ..mesh.command()
+            });
+            rasterizer.draw(&mut Framebuffer {
+                color_buffer: Some(&mut color_buffer),
+                normal_buffer: Some(&mut normal_buffer),
+                ..Default::default()
+            });
+            assert_eq!(RGBA::from_u32(color_buffer.at(*x, 32)), RGBA::new(255, 255, 255, 255));
+            let normal: Vec3 = decode_normal_from_color(RGBA::from_u32(normal_buffer.at(*x, 32)));
+            assert!(normal.z > 0.5, "{:?}", normal);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_scene_graph {
+    use super::*;
+    use nih::geometry::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn children_follow_parents_and_outside_nodes_are_culled() {
+        let cube: Arc<MeshData> = cuboid(Vec3::new(1.0, 1.0, 1.0)).into_shared();
+        let red = Material { color: Vec4::new(1.0, 0.0, 0.0, 1.0), ..Default::default() };
+        let mut graph = SceneGraph::new();
+        // The parent is off to the left and the child is moved back to the middle of the screen
+        let parent: NodeId = graph.add_node(
+            None,
+            Node { local: Mat34::translate(Vec3::new(-10.0, 0.0, -5.0)), mesh: Some(cube.clone()), ..Default::default() },
+        );
+        graph.add_node(
+            Some(parent),
+            Node {
+                local: Mat34::translate(Vec3::new(10.0, 0.0, 0.0)),
+                mesh: Some(cube.clone()),
+                materials: vec![red],
+                ..Default::default()
+            },
+        );
+        // Neither the hidden node nor its child is drawn
+        let hidden: NodeId = graph.add_node(
+            None,
+            Node { local: Mat34::translate(Vec3::new(0.0, 0.0, -3.0)), visible: false, ..Default::default() },
+        );
+        graph.add_node(Some(hidden), Node { mesh: Some(cube), ..Default::default() });
+
+        let camera = Camera::perspective(std::f32::consts::PI / 3.0, 1.0, 1.0, 10.0);
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        graph.render(&mut rasterizer, &camera);
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        assert_eq!(RGBA::from_u32(color_buffer.at(32, 32)), RGBA::new(255, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(2, 32)), RGBA::new(0, 0, 0, 255));
+        let statistics: SceneGraphStatistics = graph.statistics();
+        assert_eq!(statistics.visible_nodes, 1);
+        assert_eq!(statistics.culled_nodes, 1);
+        assert_eq!(statistics.committed_commands, 1);
+
+        // Moving the parent moves the child out of the view
+        graph.node_mut(parent).unwrap().local = Mat34::translate(Vec3::new(-20.0, 0.0, -5.0));
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        graph.render(&mut rasterizer, &camera);
+        assert_eq!(graph.statistics().visible_nodes, 0);
+        assert_eq!(graph.statistics().culled_nodes, 2);
+    }
+}
+
+#[cfg(test)]
+mod tests_picking {
+    use super::*;
+    use nih::geometry::*;
+
+    #[test]
+    fn picked_mesh_is_the_one_drawn_at_the_pixel() {
+        // A red cube partially in front of a larger green one
+        let cube: MeshData = cuboid(Vec3::new(1.0, 1.0, 1.0));
+        let meshes: [(&MeshData, Mat34); 2] = [
+            (&cube, Mat34::translate(Vec3::new(0.5, 0.0, -4.0))),
+            (&cube, Mat34::translate(Vec3::new(-0.5, 0.0, -7.0)) * Mat34::scale_uniform(3.0)),
+        ];
+        let colors: [Vec4; 2] = [Vec4::new(1.0, 0.0, 0.0, 1.0), Vec4::new(0.0, 1.0, 0.0, 1.0)];
+        let expected: [RGBA; 2] = [RGBA::new(255, 0, 0, 255), RGBA::new(0, 255, 0, 255)];
+        let viewport = Viewport::new(0, 0, 64, 64);
+        let view: Mat44 = Mat44::identity();
+        let projection: Mat44 = Mat44::perspective(1.0, 20.0, std::f32::consts::PI / 3.0, 1.0);
+
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(64, 64);
+        depth_buffer.fill(u16::MAX);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(viewport);
+        for ((mesh, model), color) in meshes.iter().zip(colors) {
+            rasterizer.commit(&RasterizationCommand {
+                model: *model,
+                view,
+                projection,
+                color,
+                ..mesh.command()
+            });
+        }
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: Some(&mut depth_buffer),
+            ..Default::default()
+        });
+
+        let mut picked: [usize; 3] = [0; 3];
+        for y in (0..64).step_by(3) {
+            for x in (0..64).step_by(3) {
+                let ray: Ray = screen_ray(x as f32 + 0.5, y as f32 + 0.5, &viewport, &view, &projection);
+                let color = RGBA::from_u32(color_buffer.at(x, y));
+                match pick(&ray, &meshes) {
+                    Some((index, _)) => {
+                        assert_eq!(color, expected[index], "at ({}, {})", x, y);
+                        picked[index] += 1;
+                    }
+                    None => {
+                        assert_eq!(color, RGBA::new(0, 0, 0, 255), "at ({}, {})", x, y);
+                        picked[2] += 1;
+                    }
+                }
+            }
+        }
+        assert!(picked.iter().all(|&count| count > 0), "{:?}", picked);
+    }
+}
+
+#[cfg(test)]
+mod tests_instancing {
+    use super::*;
+
+    // A quad covering a quarter of NDC around the origin
+    const QUAD: [Vec3; 6] = [
+        Vec3::new(-0.25, -0.25, 0.0),
+        Vec3::new(0.25, -0.25, 0.0),
+        Vec3::new(0.25, 0.25, 0.0),
+        Vec3::new(-0.25, -0.25, 0.0),
+        Vec3::new(0.25, 0.25, 0.0),
+        Vec3::new(-0.25, 0.25, 0.0),
+    ];
+
+    fn draw(command: &RasterizationCommand) -> (TiledBuffer<u32, 64, 64>, RasterizerStatistics) {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit(command);
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        (color_buffer, rasterizer.statistics())
+    }
+
+    #[test]
+    fn instances_are_placed_and_tinted() {
+        let models = [Mat34::translate(Vec3::new(-0.5, -0.5, 0.0)), Mat34::translate(Vec3::new(0.5, 0.5, 0.0))];
+        let colors = [Vec4::new(1.0, 0.0, 0.0, 1.0), Vec4::new(0.0, 1.0, 1.0, 1.0)];
+        let command = RasterizationCommand {
+            world_positions: &QUAD,
+            color: Vec4::new(1.0, 1.0, 0.0, 1.0),
+            instance_models: &models,
+            instance_colors: &colors,
+            ..Default::default()
+        };
+        let (color_buffer, stats) = draw(&command);
+        assert_eq!(RGBA::from_u32(color_buffer.at(16, 48)), RGBA::new(255, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(48, 16)), RGBA::new(0, 255, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(32, 32)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(48, 48)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(stats.committed_triangles, 4);
+
+        // The command's model places all the instances
+        let (color_buffer, _) = draw(&RasterizationCommand {
+            model: Mat34::translate(Vec3::new(0.5, 0.0, 0.0)),
+            instance_colors: &[],
+            ..command
+        });
+        assert_eq!(RGBA::from_u32(color_buffer.at(16, 48)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(32, 48)), RGBA::new(255, 255, 0, 255));
+    }
+
+    #[test]
+    fn instances_are_culled_separately() {
+        let models = [
+            Mat34::translate(Vec3::new(0.0, 0.0, 0.0)),
+            Mat34::translate(Vec3::new(-20.0, 0.0, 0.0)),
+            Mat34::translate(Vec3::new(1.0, 0.0, 0.0)),
+        ];
+        let (_, stats) = draw(&RasterizationCommand {
+            world_positions: &QUAD,
+            view: Mat44::translate(Vec3::new(0.0, 0.0, -10.0)),
+            projection: Mat44::perspective(1.0, 100.0, std::f32::consts::PI / 3.0, 1.0),
+            aabb: Some(AABB::from_points(&QUAD)),
+            instance_models: &models,
+            ..Default::default()
+        });
+        assert_eq!(stats.culled_commands, 1);
+        assert_eq!(stats.committed_triangles, 6);
+        assert_eq!(stats.transformed_vertices, 12);
+    }
+
+    #[test]
+    fn vertex_hook_runs_before_instancing() {
+        // Moves the quad into the bottom-left corner, the instance then moves it to the right
+        fn shift(vertex: &mut HookVertex, _uniforms: &Uniforms) {
+            vertex.position += Vec3::new(-0.5, -0.5, 0.0);
+        }
+        let models = [Mat34::translate(Vec3::new(1.0, 0.0, 0.0))];
+        let (color_buffer, _) = draw(&RasterizationCommand {
+            world_positions: &QUAD,
+            vertex_hook: Some(shift),
+            instance_models: &models,
+            ..Default::default()
+        });
+        assert_eq!(RGBA::from_u32(color_buffer.at(16, 48)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(48, 48)), RGBA::new(255, 255, 255, 255));
+    }
+}
+
+#[cfg(test)]
+mod tests_framebuffer_constants {
+    use super::*;
+
+    #[test]
+    fn uncovered_pixels_keep_far_depth_and_no_normal() {
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(16, 16);
+        let mut normal_buffer = TiledBuffer::<u32, 64, 64>::new(16, 16);
+        let mut framebuffer = Framebuffer {
+            depth_buffer: Some(&mut depth_buffer),
+            normal_buffer: Some(&mut normal_buffer),
+            ..Default::default()
+        };
+        framebuffer.clear_all(&ClearValues { normal: NORMAL_NONE, ..Default::default() });
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 16, 16));
+        // A triangle covering the left-bottom half of the viewport, facing the camera
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &[Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), Vec3::new(-1.0, 1.0, 0.0)],
+            normals: &[Vec3::new(0.0, 0.0, 1.0); 3],
+            ..Default::default()
+        });
+        rasterizer.draw(&mut framebuffer);
+
+        assert_eq!(depth_buffer.at(15, 0), DEPTH_FAR);
+        assert!(is_normal_none(RGBA::from_u32(normal_buffer.at(15, 0))));
+        assert_eq!(depth_buffer.at(2, 13), encode_depth(0.0));
+        let normal: RGBA = RGBA::from_u32(normal_buffer.at(2, 13));
+        assert!(!is_normal_none(normal));
+        assert!((decode_normal_from_color(normal) - Vec3::new(0.0, 0.0, 1.0)).length() < 0.01);
+    }
+
+    #[test]
+    fn depth_encoding_round_trips() {
+        assert_eq!(encode_depth(1.0), DEPTH_FAR);
+        assert_eq!(encode_depth(-1.0), DEPTH_NEAR);
+        assert_eq!(decode_depth(DEPTH_FAR), 1.0);
+        assert_eq!(decode_depth(DEPTH_NEAR), -1.0);
+        for z in [-0.75f32, -0.1, 0.0, 0.3, 0.99] {
+            assert!((decode_depth(encode_depth(z)) - z).abs() < 1e-4, "{}", z);
+        }
+    }
+
+    #[test]
+    fn named_normals_match_encoding() {
+        assert_eq!(encode_normal_as_color(Vec3::new(0.0, 0.0, 0.0)), NORMAL_NONE);
+        assert_eq!(encode_normal_as_color(Vec3::new(0.0, 1.0, 0.0)), NORMAL_UP);
+        assert!(is_normal_none(RGBA { a: 255, ..NORMAL_NONE }));
+        assert!(!is_normal_none(NORMAL_UP));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn decoding_no_normal_panics_in_debug() {
+        decode_normal_from_color(NORMAL_NONE);
+    }
+}
+
+#[cfg(test)]
+mod tests_motion_vectors {
+    use super::*;
+
+    // A square of the given half-size facing the camera, at the origin
+    fn square(half: f32) -> [Vec3; 6] {
+        [
+            Vec3::new(-half, -half, 0.0),
+            Vec3::new(half, -half, 0.0),
+            Vec3::new(half, half, 0.0),
+            Vec3::new(-half, -half, 0.0),
+            Vec3::new(half, half, 0.0),
+            Vec3::new(-half, half, 0.0),
+        ]
+    }
+
+    // Draws the command into a 32x32 motion buffer filled with garbage, returns the decoded motions
+    fn draw(command: &RasterizationCommand) -> Vec<Vec2> {
+        let mut motion_buffer = TiledBuffer::<u32, 64, 64>::new(32, 32);
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(32, 32);
+        depth_buffer.fill(DEPTH_FAR);
+        motion_buffer.fill(encode_motion(Vec2::new(100.0, 100.0)));
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 32, 32));
+        rasterizer.commit(command);
+        rasterizer.draw(&mut Framebuffer {
+            depth_buffer: Some(&mut depth_buffer),
+            motion_buffer: Some(&mut motion_buffer),
+            ..Default::default()
+        });
+        let mut motions: Vec<Vec2> = Vec::new();
+        for y in 0..32 {
+            for x in 0..32 {
+                motions.push(decode_motion(motion_buffer.at(x, y)));
+            }
+        }
+        motions
+    }
+
+    fn assert_motion(actual: Vec2, expected: Vec2) {
+        assert!((actual - expected).length() <= 1.0 / MOTION_SUBPIXELS, "{:?} vs {:?}", actual, expected);
+    }
+
+    #[test]
+    fn motion_encoding_round_trips() {
+        assert_eq!(encode_motion(Vec2::new(0.0, 0.0)), 0);
+        for motion in [Vec2::new(1.5, -2.25), Vec2::new(-0.03125, 700.0), Vec2::new(-1000.0, 0.5)] {
+            assert_eq!(decode_motion(encode_motion(motion)), motion);
+        }
+        assert_eq!(decode_motion(encode_motion(Vec2::new(5000.0, -5000.0))), Vec2::new(1023.96875, -1024.0));
+    }
+
+    #[test]
+    fn moving_mesh_writes_its_motion() {
+        let positions: [Vec3; 6] = square(8.0);
+        let projection: Mat44 = Mat44::orthographic(0.0, 32.0, 32.0, 0.0, -1.0, 1.0);
+        // Moved by 3 pixels to the right, the camera moved 2 pixels down, i.e. the scene moved up
+        let motions: Vec<Vec2> = draw(&RasterizationCommand {
+            world_positions: &positions,
+            model: Mat34::translate(Vec3::new(16.0, 16.0, 0.0)),
+            projection,
+            previous_model: Some(Mat34::translate(Vec3::new(13.0, 16.0, 0.0))),
+            previous_view_projection: Some(projection * Mat44::translate(Vec3::new(0.0, 2.0, 0.0))),
+            ..Default::default()
+        });
+        assert_motion(motions[16 * 32 + 16], Vec2::new(3.0, -2.0));
+        assert_motion(motions[9 * 32 + 22], Vec2::new(3.0, -2.0));
+        // The uncovered pixels are left intact
+        assert_motion(motions[2 * 32 + 2], Vec2::new(100.0, 100.0));
+
+        // Without the previous transforms the mesh writes zero motion
+        let motions: Vec<Vec2> = draw(&RasterizationCommand {
+            world_positions: &positions,
+            model: Mat34::translate(Vec3::new(16.0, 16.0, 0.0)),
+            projection,
+            ..Default::default()
+        });
+        assert_eq!(motions[16 * 32 + 16], Vec2::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn motion_is_perspective_correct() {
+        let positions: [Vec3; 6] = square(2.8);
+        let projection: Mat44 = Mat44::perspective(0.1, 10.0, std::f32::consts::FRAC_PI_2, 1.0);
+
+        // A tilted mesh which didn't move has no motion anywhere
+        let model: Mat34 = Mat34::translate(Vec3::new(0.0, 0.0, -3.0)) * Mat34::rotate_zx(0.6);
+        let motions: Vec<Vec2> = draw(&RasterizationCommand {
+            world_positions: &positions,
+            model,
+            projection,
+            previous_model: Some(model),
+            ..Default::default()
+        });
+        assert_motion(motions[16 * 32 + 16], Vec2::new(0.0, 0.0));
+        assert_motion(motions[13 * 32 + 20], Vec2::new(0.0, 0.0));
+        assert_motion(motions[19 * 32 + 11], Vec2::new(0.0, 0.0));
+
+        // A mesh approaching the camera from 3.5 to 3 grows away from the center by 1/7 of the pixels' offsets
+        let motions: Vec<Vec2> = draw(&RasterizationCommand {
+            world_positions: &positions,
+            model: Mat34::translate(Vec3::new(0.0, 0.0, -3.0)),
+            projection,
+            previous_model: Some(Mat34::translate(Vec3::new(0.0, 0.0, -3.5))),
+            ..Default::default()
+        });
+        assert_motion(motions[16 * 32 + 28], Vec2::new(12.5 / 7.0, 0.5 / 7.0));
+        assert_motion(motions[3 * 32 + 6], Vec2::new(-9.5 / 7.0, -12.5 / 7.0));
+    }
+}
+
+#[cfg(test)]
+mod tests_micro_triangles {
+    use super::*;
+
+    // Draws the triangles given in pixels into a 32x32 black buffer, returns the buffer and the statistics
+    fn draw(positions: &[Vec3], area_threshold: f32) -> (TiledBuffer<u32, 64, 64>, RasterizerStatistics) {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(32, 32);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 32, 32));
+        rasterizer.set_micro_triangle_area_threshold(area_threshold);
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: positions,
+            projection: Mat44::orthographic(0.0, 32.0, 32.0, 0.0, -1.0, 1.0),
+            culling: CullMode::None,
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        (color_buffer, rasterizer.statistics())
+    }
+
+    fn lit_pixels(buffer: &TiledBuffer<u32, 64, 64>) -> usize {
+        let mut lit: usize = 0;
+        for y in 0..32 {
+            for x in 0..32 {
+                lit += (RGBA::from_u32(buffer.at(x, y)) != RGBA::new(0, 0, 0, 255)) as usize;
+            }
+        }
+        lit
+    }
+
+    #[test]
+    fn triangles_between_pixel_centers_are_discarded() {
+        // A sub-pixel one, and a sliver between two rows of centers
+        let positions = [
+            Vec3::new(10.6, 10.6, 0.0),
+            Vec3::new(11.4, 10.6, 0.0),
+            Vec3::new(10.6, 11.4, 0.0),
+            Vec3::new(2.0, 20.6, 0.0),
+            Vec3::new(30.0, 20.7, 0.0),
+            Vec3::new(2.0, 21.4, 0.0),
+        ];
+        let (buffer, stats) = draw(&positions, 0.0);
+        assert_eq!(stats.micro_triangles, 2);
+        assert_eq!(stats.scheduled_triangles, 0);
+        assert_eq!(stats.binned_triangles, 0);
+        assert_eq!(lit_pixels(&buffer), 0);
+    }
+
+    #[test]
+    fn small_triangles_covering_pixel_centers_are_kept() {
+        let positions = [Vec3::new(10.2, 10.2, 0.0), Vec3::new(11.4, 10.2, 0.0), Vec3::new(10.2, 11.4, 0.0)];
+        let (buffer, stats) = draw(&positions, 0.0);
+        assert_eq!(stats.micro_triangles, 0);
+        assert_eq!(stats.scheduled_triangles, 1);
+        assert_eq!(lit_pixels(&buffer), 1);
+        assert_eq!(RGBA::from_u32(buffer.at(10, 10)), RGBA::new(255, 255, 255, 255));
+
+        // Below the threshold of 1 pixel
+        let (buffer, stats) = draw(&positions, 1.0);
+        assert_eq!(stats.micro_triangles, 1);
+        assert_eq!(stats.scheduled_triangles, 0);
+        assert_eq!(lit_pixels(&buffer), 0);
+    }
+
+    #[test]
+    fn sub_pixel_triangles_are_never_binned() {
+        // A 4x4 pixel square made of 16x16 quads
+        let mut positions: Vec<Vec3> = Vec::new();
+        for y in 0..16 {
+            for x in 0..16 {
+                let (x0, y0) = (8.0 + x as f32 * 0.25, 8.0 + y as f32 * 0.25);
+                let (x1, y1) = (x0 + 0.25, y0 + 0.25);
+                positions.extend_from_slice(&[
+                    Vec3::new(x0, y0, 0.0),
+                    Vec3::new(x1, y0, 0.0),
+                    Vec3::new(x1, y1, 0.0),
+                    Vec3::new(x0, y0, 0.0),
+                    Vec3::new(x1, y1, 0.0),
+                    Vec3::new(x0, y1, 0.0),
+                ]);
+            }
+        }
+        let (_, stats) = draw(&positions, 0.0);
+        assert_eq!(stats.micro_triangles, 16 * 16 * 2);
+        assert_eq!(stats.binned_triangles, 0);
+    }
+
+    #[test]
+    fn discarding_keeps_the_coverage() {
+        // A 16x16 pixel square made of horizontal strips a quarter of a pixel high, half of them between the centers
+        let mut positions: Vec<Vec3> = Vec::new();
+        for y in 0..64 {
+            let (y0, y1) = (8.0 + y as f32 * 0.25, 8.25 + y as f32 * 0.25);
+            positions.extend_from_slice(&[
+                Vec3::new(8.0, y0, 0.0),
+                Vec3::new(24.0, y0, 0.0),
+                Vec3::new(24.0, y1, 0.0),
+                Vec3::new(8.0, y0, 0.0),
+                Vec3::new(24.0, y1, 0.0),
+                Vec3::new(8.0, y1, 0.0),
+            ]);
+        }
+        let (buffer, stats) = draw(&positions, 0.0);
+        assert_eq!(stats.micro_triangles, 64);
+        assert_eq!(stats.scheduled_triangles, 64);
+        assert_eq!(lit_pixels(&buffer), 16 * 16);
+    }
+
+    #[test]
+    fn zero_area_triangles_are_counted() {
+        let positions = [
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(20.0, 20.0, 0.0),
+            Vec3::new(10.0, 10.0, 0.0),
+            Vec3::new(5.0, 5.0, 0.0),
+            Vec3::new(5.0, 5.0, 0.0),
+            Vec3::new(5.0, 5.0, 0.0),
+        ];
+        let (buffer, stats) = draw(&positions, 0.0);
+        assert_eq!(stats.zero_area_triangles, 2);
+        assert_eq!(stats.micro_triangles, 0);
+        assert_eq!(stats.scheduled_triangles, 0);
+        assert_eq!(lit_pixels(&buffer), 0);
+    }
+}
+
+#[cfg(test)]
+mod tests_guard_band {
+    use super::*;
+
+    // Draws the triangles given in NDC into the left half of a 128x64 buffer
+    fn draw(positions: &[Vec3], guard_band: bool) -> (TiledBuffer<u32, 64, 64>, RasterizerStatistics) {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(128, 64);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.set_guard_band_clipping(guard_band);
+        let colors: Vec<Vec4> = (0..positions.len())
+            .map(|i| Vec4::new((i % 3) as f32 / 2.0, (i % 5) as f32 / 4.0, (i % 7) as f32 / 6.0, 1.0))
+            .collect();
+        rasterizer.commit(&RasterizationCommand { world_positions: positions, colors: &colors, ..Default::default() });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        (color_buffer, rasterizer.statistics())
+    }
+
+    #[test]
+    fn triangles_within_the_band_are_not_clipped() {
+        let positions = [
+            // On the screen
+            Vec3::new(-0.5, -0.5, 0.0),
+            Vec3::new(0.5, -0.5, 0.0),
+            Vec3::new(0.5, 0.5, 0.0),
+            // Crossing the right and the top sides
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(3.0, 0.0, 0.0),
+            Vec3::new(0.0, 3.0, 0.0),
+            // Entirely outside
+            Vec3::new(2.0, 2.0, 0.0),
+            Vec3::new(3.0, 2.0, 0.0),
+            Vec3::new(3.0, 3.0, 0.0),
+        ];
+        let (buffer, stats) = draw(&positions, true);
+        assert_eq!(stats.clipped_triangles, 0);
+        assert_eq!(stats.scheduled_triangles, 2);
+        // Nothing is drawn outside the viewport
+        assert_eq!(RGBA::from_u32(buffer.at(64, 0)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(buffer.at(100, 30)), RGBA::new(0, 0, 0, 255));
+
+        let (_, stats) = draw(&positions, false);
+        assert_eq!(stats.clipped_triangles, 3);
+        assert_eq!(stats.scheduled_triangles, 3);
+    }
+
+    #[test]
+    fn triangles_exceeding_the_band_or_depth_range_are_clipped() {
+        let positions = [
+            // Far beyond the right side
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1000.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.5, 0.0),
+            // Crossing the far plane
+            Vec3::new(-0.5, -0.5, 0.0),
+            Vec3::new(0.5, -0.5, 2.0),
+            Vec3::new(-0.5, 0.5, 0.0),
+        ];
+        let (_, stats) = draw(&positions, true);
+        assert_eq!(stats.clipped_triangles, 2);
+        assert_eq!(stats.side_clipped_triangles, 0);
+    }
+
+    #[test]
+    fn output_matches_full_clipping() {
+        let positions = [
+            Vec3::new(-1.7, -0.3, 0.0),
+            Vec3::new(0.4, -1.9, 0.5),
+            Vec3::new(0.9, 1.6, -0.5),
+            Vec3::new(-0.2, 0.1, 0.0),
+            Vec3::new(2.5, 0.3, 0.0),
+            Vec3::new(0.1, 2.2, 0.0),
+        ];
+        let (with_band, stats) = draw(&positions, true);
+        assert_eq!(stats.clipped_triangles, 0);
+        let (without_band, _) = draw(&positions, false);
+        let mut mismatches: usize = 0;
+        for y in 0..64 {
+            for x in 0..128 {
+                let a: RGBA = RGBA::from_u32(with_band.at(x, y));
+                let b: RGBA = RGBA::from_u32(without_band.at(x, y));
+                let diff: u8 = a.r.abs_diff(b.r).max(a.g.abs_diff(b.g)).max(a.b.abs_diff(b.b));
+                assert!(diff <= 2, "({}, {}): {:?} != {:?}", x, y, a, b);
+                mismatches += (diff > 0) as usize;
+            }
+        }
+        assert!(mismatches < 64, "{}", mismatches);
+    }
+
+    // Draws a textured floor receding from the camera, wider than the viewport, with the mip levels sampled trilinearly
+    fn draw_textured_floor(
+        guard_band: bool,
+        texture: Option<std::sync::Arc<Texture>>,
+    ) -> (TiledBuffer<u32, 64, 64>, RasterizerStatistics) {
+        let positions = [
+            Vec3::new(-6.0, -1.0, -2.0),
+            Vec3::new(6.0, -1.0, -2.0),
+            Vec3::new(6.0, -1.0, -40.0),
+            Vec3::new(-6.0, -1.0, -2.0),
+            Vec3::new(6.0, -1.0, -40.0),
+            Vec3::new(-6.0, -1.0, -40.0),
+        ];
+        let tex_coords = positions.map(|p| Vec2::new(p.x + 6.0, -p.z) * 0.5);
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.set_guard_band_clipping(guard_band);
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &positions,
+            tex_coords: &tex_coords,
+            texture,
+            sampling_filter: SamplerFilter::Trilinear,
+            projection: Mat44::perspective(0.1, 50.0, std::f32::consts::FRAC_PI_2, 1.0),
+            culling: CullMode::None,
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        (color_buffer, rasterizer.statistics())
+    }
+
+    #[test]
+    fn textured_perspective_output_matches_full_clipping() {
+        // Noisy texels, so that each mip level looks different
+        let texels: Vec<u8> = (0..32u32 * 32 * 3).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let texture = Texture::new(&TextureSource {
+            texels: &texels,
+            width: 32,
+            height: 32,
+            format: TextureFormat::RGB,
+            ..Default::default()
+        });
+        let (with_band, stats) = draw_textured_floor(true, Some(texture.clone()));
+        // The floor crosses the viewport's sides, which is within the band, but its mip levels follow its whole area
+        assert_eq!(stats.clipped_triangles, 2);
+        assert_eq!(stats.side_clipped_triangles, 2);
+        let (without_band, stats) = draw_textured_floor(false, Some(texture));
+        assert_eq!(stats.clipped_triangles, 2);
+        assert_eq!(stats.side_clipped_triangles, 0);
+        for y in 0..64 {
+            for x in 0..64 {
+                let a: RGBA = RGBA::from_u32(with_band.at(x, y));
+                let b: RGBA = RGBA::from_u32(without_band.at(x, y));
+                assert_eq!(a, b, "({}, {})", x, y);
+            }
+        }
+
+        // The same floor without the texture is rasterized within the band as it is
+        let (_, stats) = draw_textured_floor(true, None);
+        assert_eq!(stats.clipped_triangles, 0);
+        assert_eq!(stats.side_clipped_triangles, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests_frustum_lines {
+    use super::*;
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn perspective_frustum_corners() {
+        // A 90 degrees square frustum from 1 to 10, looking along -Z from (0, 0, 5)
+        let view: Mat44 = Mat44::translate(Vec3::new(0.0, 0.0, -5.0));
+        let projection: Mat44 = Mat44::perspective(1.0, 10.0, std::f32::consts::PI / 2.0, 1.0);
+        let corners: [Vec3; 8] = frustum_corners(&(projection * view));
+        assert_near(corners[0], Vec3::new(-1.0, -1.0, 4.0));
+        assert_near(corners[2], Vec3::new(1.0, 1.0, 4.0));
+        assert_near(corners[4], Vec3::new(-10.0, -10.0, -5.0));
+        assert_near(corners[6], Vec3::new(10.0, 10.0, -5.0));
+
+        let slice: [Vec3; 8] = frustum_slice_corners(&view, &projection, 2.0, 4.0);
+        assert_near(slice[1], Vec3::new(2.0, -2.0, 3.0));
+        assert_near(slice[7], Vec3::new(-4.0, 4.0, 1.0));
+    }
+
+    #[test]
+    fn orthographic_cascade_box() {
+        let cascade: Mat44 = Mat44::orthographic(-2.0, 2.0, -1.0, 1.0, 0.0, 8.0);
+        let lines: [Vec3; 24] = frustum_lines(&cascade);
+        // Every edge is axis-aligned and as long as the box's side along that axis
+        for pair in lines.chunks(2) {
+            let length: f32 = (pair[1] - pair[0]).length();
+            assert!([4.0, 2.0, 8.0].iter().any(|side| (length - side).abs() < 1e-4), "{}", length);
+        }
+
+        let mut lines: Vec<Vec3> = Vec::new();
+        cascade_lines(&[cascade, cascade], &mut lines);
+        assert_eq!(lines.len(), 48);
+    }
+
+    #[test]
+    fn splits_add_rectangles() {
+        let projection: Mat44 = Mat44::perspective(1.0, 10.0, std::f32::consts::PI / 2.0, 1.0);
+        let mut lines: Vec<Vec3> = Vec::new();
+        frustum_split_lines(&Mat44::identity(), &projection, &[3.0, 6.0], &mut lines);
+        assert_eq!(lines.len(), 24 + 2 * 8);
+        // The rectangles lie on the split planes
+        assert!(lines[24..32].iter().all(|p| (p.z + 3.0).abs() < 1e-3));
+        assert!(lines[32..40].iter().all(|p| (p.z + 6.0).abs() < 1e-3));
+        assert_near(lines[24], Vec3::new(-3.0, -3.0, -3.0));
+    }
+}
+
+#[cfg(test)]
+mod tests_depth_clamp {
+    use super::*;
+
+    // Draws the triangles given in NDC with identity matrices into a 64x64 buffer, returns the colors and the depths
+    fn draw(
+        positions: &[Vec3],
+        depth_clamp: bool,
+        aabb: Option<AABB>,
+    ) -> (TiledBuffer<u32, 64, 64>, TiledBuffer<u16, 64, 64>, RasterizerStatistics) {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(64, 64);
+        depth_buffer.fill(DEPTH_FAR);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: positions,
+            color: Vec4::new(1.0, 0.0, 0.0, 1.0),
+            depth_clamp,
+            aabb,
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: Some(&mut depth_buffer),
+            ..Default::default()
+        });
+        (color_buffer, depth_buffer, rasterizer.statistics())
+    }
+
+    const RED: RGBA = RGBA { r: 255, g: 0, b: 0, a: 255 };
+
+    #[test]
+    fn triangles_beyond_the_far_plane_are_drawn_at_far_depth() {
+        let positions = [Vec3::new(-1.0, -1.0, 1.5), Vec3::new(1.0, -1.0, 1.5), Vec3::new(-1.0, 1.0, 3.0)];
+        let (color, _, stats) = draw(&positions, false, None);
+        assert_eq!(RGBA::from_u32(color.at(10, 50)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(stats.scheduled_triangles, 0);
+
+        let (color, depth, stats) = draw(&positions, true, None);
+        assert_eq!(RGBA::from_u32(color.at(10, 50)), RED);
+        assert_eq!(depth.at(10, 50), DEPTH_FAR - 1);
+        assert_eq!(stats.clipped_triangles, 0);
+    }
+
+    #[test]
+    fn depth_within_the_range_is_kept() {
+        // From the middle of the depth range at the bottom to beyond the far plane at the top
+        let positions = [Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), Vec3::new(-1.0, 3.0, 4.0)];
+        let (color, depth, _) = draw(&positions, true, None);
+        assert_eq!(RGBA::from_u32(color.at(1, 1)), RED);
+        assert_eq!(RGBA::from_u32(color.at(1, 62)), RED);
+        assert_eq!(depth.at(1, 1), DEPTH_FAR - 1);
+        // The pixel's center is at NDC y = -0.953, where z = 0.047
+        assert!(depth.at(1, 62).abs_diff(encode_depth(0.047)) < 64, "{}", depth.at(1, 62));
+
+        // Without the clamp the triangle is cut off by the far plane in the middle of the viewport
+        let (color, _, _) = draw(&positions, false, None);
+        assert_eq!(RGBA::from_u32(color.at(1, 1)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(color.at(1, 62)), RED);
+    }
+
+    #[test]
+    fn triangles_are_still_clipped_by_the_near_plane() {
+        let positions = [Vec3::new(-1.0, -1.0, -1.5), Vec3::new(1.0, -1.0, -1.5), Vec3::new(-1.0, 1.0, -3.0)];
+        let (color, _, stats) = draw(&positions, true, None);
+        assert_eq!(RGBA::from_u32(color.at(10, 50)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(stats.scheduled_triangles, 0);
+    }
+
+    #[test]
+    fn commands_beyond_the_far_plane_are_not_culled() {
+        let positions = [Vec3::new(-1.0, -1.0, 2.0), Vec3::new(1.0, -1.0, 2.0), Vec3::new(-1.0, 1.0, 2.0)];
+        let aabb = Some(AABB::from_points(&positions));
+        let (_, _, stats) = draw(&positions, false, aabb);
+        assert_eq!(stats.culled_commands, 1);
+        let (color, _, stats) = draw(&positions, true, aabb);
+        assert_eq!(stats.culled_commands, 0);
+        assert_eq!(RGBA::from_u32(color.at(10, 50)), RED);
+    }
+}
+
+#[cfg(test)]
+mod tests_depth_test {
+    use super::*;
+
+    // Draws a fullscreen quad at NDC z=0 with the given depth test and write mask into a 64x64 buffer, which depth is
+    // pre-filled with `stored`
+    fn draw(stored: u16, depth_test: DepthTest, depth_write: bool) -> (RGBA, u16, RasterizerStatistics) {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(64, 64);
+        depth_buffer.fill(stored);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &[
+                Vec3::new(-1.0, -1.0, 0.0),
+                Vec3::new(1.0, -1.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(-1.0, -1.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(-1.0, 1.0, 0.0),
+            ],
+            color: Vec4::new(1.0, 0.0, 0.0, 1.0),
+            depth_test,
+            depth_write,
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: Some(&mut depth_buffer),
+            ..Default::default()
+        });
+        (RGBA::from_u32(color_buffer.at(20, 30)), depth_buffer.at(20, 30), rasterizer.statistics())
+    }
+
+    const RED: RGBA = RGBA { r: 255, g: 0, b: 0, a: 255 };
+    const BLACK: RGBA = RGBA { r: 0, g: 0, b: 0, a: 255 };
+
+    #[test]
+    fn depth_functions() {
+        let fragment: u16 = encode_depth(0.0);
+        let nearer: u16 = fragment - 1000;
+        let farther: u16 = fragment + 1000;
+        let passes = |stored: u16, depth_test: DepthTest| draw(stored, depth_test, true).0 == RED;
+
+        assert!(!passes(farther, DepthTest::Never));
+        assert!(!passes(nearer, DepthTest::Never));
+
+        assert!(passes(farther, DepthTest::Less));
+        assert!(!passes(fragment, DepthTest::Less));
+        assert!(!passes(nearer, DepthTest::Less));
+
+        assert!(passes(farther, DepthTest::LEqual));
+        assert!(passes(fragment, DepthTest::LEqual));
+        assert!(!passes(nearer, DepthTest::LEqual));
+
+        assert!(!passes(farther, DepthTest::Greater));
+        assert!(!passes(fragment, DepthTest::Greater));
+        assert!(passes(nearer, DepthTest::Greater));
+
+        assert!(!passes(farther, DepthTest::GEqual));
+        assert!(passes(fragment, DepthTest::GEqual));
+        assert!(passes(nearer, DepthTest::GEqual));
+
+        assert!(!passes(farther, DepthTest::Equal));
+        assert!(passes(fragment, DepthTest::Equal));
+        assert!(!passes(nearer, DepthTest::Equal));
+
+        assert!(passes(farther, DepthTest::Always));
+        assert!(passes(nearer, DepthTest::Always));
+    }
+
+    #[test]
+    fn passed_fragments_write_depth() {
+        let fragment: u16 = encode_depth(0.0);
+        let (_, depth, _) = draw(DEPTH_FAR, DepthTest::LEqual, true);
+        assert_eq!(depth, fragment);
+        let (_, depth, _) = draw(DEPTH_NEAR, DepthTest::Greater, true);
+        assert_eq!(depth, fragment);
+        let (color, depth, _) = draw(DEPTH_NEAR, DepthTest::Less, true);
+        assert_eq!(color, BLACK);
+        assert_eq!(depth, DEPTH_NEAR);
+    }
+
+    #[test]
+    fn disabled_depth_write_keeps_depth() {
+        let (color, depth, _) = draw(DEPTH_FAR, DepthTest::Less, false);
+        assert_eq!(color, RED);
+        assert_eq!(depth, DEPTH_FAR);
+        let (color, depth, _) = draw(DEPTH_NEAR, DepthTest::Always, false);
+        assert_eq!(color, RED);
+        assert_eq!(depth, DEPTH_NEAR);
+    }
+
+    #[test]
+    fn hierarchical_z_rejects_only_with_nearer_tests() {
+        let (color, _, stats) = draw(DEPTH_NEAR, DepthTest::Less, true);
+        assert_eq!(color, BLACK);
+        assert!(stats.hiz_rejected_triangles > 0);
+        for depth_test in [DepthTest::Greater, DepthTest::GEqual, DepthTest::Always] {
+            let (color, _, stats) = draw(DEPTH_NEAR, depth_test, true);
+            assert_eq!(color, RED, "{:?}", depth_test);
+            assert_eq!(stats.hiz_rejected_triangles, 0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_procedural_pattern {
+    use super::*;
+
+    // Draws a viewport-covering quad with the texture coordinates from (0, 0) at the top-left corner to (scale, scale)
+    // at the bottom-right one into a 64x64 buffer
+    fn draw(pattern: ProceduralPattern, scale: f32) -> TiledBuffer<u32, 64, 64> {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &[
+                Vec3::new(-1.0, 1.0, 0.0),
+                Vec3::new(-1.0, -1.0, 0.0),
+                Vec3::new(1.0, -1.0, 0.0),
+                Vec3::new(-1.0, 1.0, 0.0),
+                Vec3::new(1.0, -1.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+            ],
+            tex_coords: &[
+                Vec2::new(0.0, 0.0),
+                Vec2::new(0.0, scale),
+                Vec2::new(scale, scale),
+                Vec2::new(0.0, 0.0),
+                Vec2::new(scale, scale),
+                Vec2::new(scale, 0.0),
+            ],
+            culling: CullMode::None,
+            pattern: Some(pattern),
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        color_buffer
+    }
+
+    const WHITE: Vec4 = Vec4 { x: 1.0, y: 1.0, z: 1.0, w: 1.0 };
+    const BLACK: Vec4 = Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 1.0 };
+
+    fn assert_gray(color: u32, expected: u8, tolerance: u8) {
+        let color: RGBA = RGBA::from_u32(color);
+        assert!(color.r.abs_diff(expected) <= tolerance, "{:?} != {}", color, expected);
+        assert_eq!(color.r, color.g);
+        assert_eq!(color.r, color.b);
+    }
+
+    #[test]
+    fn checker_alternates_cells() {
+        // 4x4 cells of 16x16 pixels
+        let buffer = draw(ProceduralPattern::Checker { even: WHITE, odd: BLACK }, 4.0);
+        assert_gray(buffer.at(8, 8), 255, 0);
+        assert_gray(buffer.at(24, 8), 0, 0);
+        assert_gray(buffer.at(8, 24), 0, 0);
+        assert_gray(buffer.at(24, 24), 255, 0);
+        // The edges aligned with the pixels' borders don't bleed into the neighbouring cells
+        assert_gray(buffer.at(15, 8), 255, 0);
+        assert_gray(buffer.at(16, 8), 0, 0);
+
+        // While the pixels straddling an edge are only partially covered by either cell
+        let buffer = draw(ProceduralPattern::Checker { even: WHITE, odd: BLACK }, 3.0);
+        let edge: RGBA = RGBA::from_u32(buffer.at(21, 8));
+        assert!(edge.r > 0 && edge.r < 255, "{:?}", edge);
+    }
+
+    #[test]
+    fn grid_draws_lines_along_cell_borders() {
+        let line: Vec4 = Vec4::new(1.0, 0.0, 0.0, 1.0);
+        let buffer = draw(ProceduralPattern::Grid { line, background: BLACK, width: 1.0 }, 4.0);
+        assert_eq!(RGBA::from_u32(buffer.at(8, 8)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(buffer.at(30, 8)), RGBA::new(0, 0, 0, 255));
+        // A border at u=1 goes between the pixels 15 and 16, each of them is half-covered by a line of 1 pixel
+        let border: RGBA = RGBA::from_u32(buffer.at(16, 8));
+        assert!(border.r > 96 && border.r < 160, "{:?}", border);
+        assert_eq!(RGBA::from_u32(buffer.at(15, 8)), border);
+    }
+
+    #[test]
+    fn cells_smaller_than_a_pixel_fade_into_average() {
+        let buffer = draw(ProceduralPattern::Checker { even: WHITE, odd: BLACK }, 500.0);
+        for (x, y) in [(3, 5), (20, 40), (63, 0), (47, 47)] {
+            assert_gray(buffer.at(x, y), 128, 8);
+        }
+        let grid = ProceduralPattern::Grid { line: WHITE, background: BLACK, width: 1.0 };
+        let buffer = draw(grid, 500.0);
+        for (x, y) in [(3, 5), (20, 40), (63, 0), (47, 47)] {
+            assert_gray(buffer.at(x, y), 255, 8);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_hooks {
+    use super::*;
+
+    // Draws the command into a 64x64 buffer cleared to black
+    fn draw(command: &RasterizationCommand) -> TiledBuffer<u32, 64, 64> {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit(command);
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        color_buffer
+    }
+
+    // The bottom-left half of the viewport
+    const POSITIONS: [Vec3; 3] =
+        [Vec3 { x: -1.0, y: -1.0, z: 0.0 }, Vec3 { x: 1.0, y: -1.0, z: 0.0 }, Vec3 { x: -1.0, y: 1.0, z: 0.0 }];
+
+    #[test]
+    fn vertex_hook_moves_vertices_by_uniforms() {
+        fn shift(vertex: &mut HookVertex, uniforms: &Uniforms) {
+            vertex.position += uniforms.user[0].xyz() * uniforms.time;
+            vertex.color = Vec4::new(0.0, 1.0, 0.0, 1.0);
+        }
+        let mut uniforms = Uniforms::default();
+        uniforms.user[0] = Vec4::new(1.0, 1.0, 0.0, 0.0);
+        let command =
+            RasterizationCommand { world_positions: &POSITIONS, vertex_hook: Some(shift), ..Default::default() };
+        let buffer = draw(&command);
+        assert_eq!(RGBA::from_u32(buffer.at(8, 56)), RGBA::new(255, 255, 255, 255));
+        assert_eq!(RGBA::from_u32(buffer.at(56, 8)), RGBA::new(0, 0, 0, 255));
+
+        // Moving by half of NDC diagonally shifts the triangle to the top-right corner, the command has no colors so the
+        // hook's colors are ignored
+        uniforms.time = 1.0;
+        let buffer = draw(&RasterizationCommand { uniforms, ..command.clone() });
+        assert_eq!(RGBA::from_u32(buffer.at(8, 56)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(buffer.at(40, 24)), RGBA::new(255, 255, 255, 255));
+
+        // With the per-vertex colors the hook's ones are used instead
+        let colors = [Vec4::new(1.0, 0.0, 0.0, 1.0); 3];
+        let buffer = draw(&RasterizationCommand { colors: &colors, ..command.clone() });
+        assert_eq!(RGBA::from_u32(buffer.at(8, 56)), RGBA::new(0, 255, 0, 255));
+    }
+
+    #[test]
+    fn fragment_hook_replaces_texel() {
+        // Pixels to the left of the column given by the seed are red, the others are blue with the time in green
+        fn split(fragment: &HookFragment, uniforms: &Uniforms) -> Vec4 {
+            assert_eq!(fragment.texel, Vec4::new(1.0, 1.0, 1.0, 1.0));
+            if (fragment.x as u32) < uniforms.seed {
+                Vec4::new(1.0, 0.0, 0.0, 1.0)
+            } else {
+                Vec4::new(0.0, uniforms.time, 1.0, 1.0)
+            }
+        }
+        let uniforms = Uniforms { time: 0.5, seed: 10, ..Default::default() };
+        let buffer = draw(&RasterizationCommand {
+            world_positions: &POSITIONS,
+            fragment_hook: Some(split),
+            uniforms,
+            ..Default::default()
+        });
+        assert_eq!(RGBA::from_u32(buffer.at(9, 56)), RGBA::new(255, 0, 0, 255));
+        let color: RGBA = RGBA::from_u32(buffer.at(10, 56));
+        assert_eq!((color.r, color.b), (0, 255));
+        assert!(color.g.abs_diff(128) <= 1, "{:?}", color);
+        assert_eq!(RGBA::from_u32(buffer.at(56, 8)), RGBA::new(0, 0, 0, 255));
+    }
+
+    #[test]
+    fn fragment_hook_receives_texture_coordinates_and_texels() {
+        fn invert(fragment: &HookFragment, _uniforms: &Uniforms) -> Vec4 {
+            let t: Vec4 = fragment.texel;
+            Vec4::new(1.0 - t.x, 1.0 - t.y, fragment.tex_coord.x, 1.0)
+        }
+        let texture = Texture::new(&TextureSource {
+            texels: &[255, 0, 0, 255],
+            width: 1,
+            height: 1,
+            format: TextureFormat::RGBA,
+            ..Default::default()
+        });
+        let buffer = draw(&RasterizationCommand {
+            world_positions: &POSITIONS,
+            tex_coords: &[Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), Vec2::new(0.0, 0.0)],
+            texture: Some(texture),
+            fragment_hook: Some(invert),
+            ..Default::default()
+        });
+        let color: RGBA = RGBA::from_u32(buffer.at(48, 62));
+        assert_eq!((color.r, color.g), (0, 255));
+        assert!(color.b.abs_diff(194) <= 2, "{:?}", color);
+    }
+
+    #[test]
+    fn vertex_animation_replays_frames_before_hook() {
+        // The second frame moves the triangle by half of NDC diagonally, into the top-right corner
+        let shifted: Vec<Vec3> = POSITIONS.iter().map(|&p| p + Vec3::new(1.0, 1.0, 0.0)).collect();
+        let frames: Vec<Vec3> = POSITIONS.iter().copied().chain(shifted).collect();
+        let texture = VertexAnimationTexture::new(3, 2, &frames, &[]);
+        let command = RasterizationCommand {
+            world_positions: &[Vec3::new(0.0, 0.0, 0.0); 3],
+            vertex_animation: Some(VertexAnimation::new(texture.clone())),
+            ..Default::default()
+        };
+        let buffer = draw(&command);
+        assert_eq!(RGBA::from_u32(buffer.at(8, 56)), RGBA::new(255, 255, 255, 255));
+        assert_eq!(RGBA::from_u32(buffer.at(56, 8)), RGBA::new(0, 0, 0, 255));
+
+        let buffer = draw(&RasterizationCommand {
+            vertex_animation: Some(VertexAnimation::new(texture.clone()).at_time(0.5, 2.0)),
+            ..command.clone()
+        });
+        assert_eq!(RGBA::from_u32(buffer.at(8, 56)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(buffer.at(40, 24)), RGBA::new(255, 255, 255, 255));
+
+        // The hook sees the animated positions and moves them back
+        fn unshift(vertex: &mut HookVertex, _uniforms: &Uniforms) {
+            vertex.position -= Vec3::new(1.0, 1.0, 0.0);
+        }
+        let buffer = draw(&RasterizationCommand {
+            vertex_animation: Some(VertexAnimation { frame: 1.0, ..VertexAnimation::new(texture) }),
+            vertex_hook: Some(unshift),
+            ..command
+        });
+        assert_eq!(RGBA::from_u32(buffer.at(8, 56)), RGBA::new(255, 255, 255, 255));
+        assert_eq!(RGBA::from_u32(buffer.at(56, 8)), RGBA::new(0, 0, 0, 255));
+    }
+
+    #[test]
+    fn skinning_moves_vertices_by_clip_pose() {
+        // A single joint bound at the origin, which the clip moves by half of NDC diagonally over a second
+        let skeleton = Skeleton::from_rest_pose(&[("root", None, JointTransform::default())]);
+        let clip = AnimationClip {
+            name: "shift".to_string(),
+            duration: 1.0,
+            tracks: vec![JointTrack {
+                joint: 0,
+                translations: vec![
+                    Keyframe { time: 0.0, value: Vec3::new(0.0, 0.0, 0.0) },
+                    Keyframe { time: 1.0, value: Vec3::new(1.0, 1.0, 0.0) },
+                ],
+                ..Default::default()
+            }],
+        };
+        let mut pose = Pose::default();
+        let mut palette: Vec<Mat34> = Vec::new();
+        let joints = [[0u16; 4]; 3];
+        let weights = [Vec4::new(1.0, 0.0, 0.0, 0.0); 3];
+        clip.sample(&skeleton, 0.0, false, &mut pose);
+        skeleton.palette(&pose, &mut palette);
+        let buffer = draw(&RasterizationCommand {
+            world_positions: &POSITIONS,
+            skinning: Some(Skinning::new(&palette, &joints, &weights)),
+            ..Default::default()
+        });
+        assert_eq!(RGBA::from_u32(buffer.at(8, 56)), RGBA::new(255, 255, 255, 255));
+        assert_eq!(RGBA::from_u32(buffer.at(56, 8)), RGBA::new(0, 0, 0, 255));
+
+        clip.sample(&skeleton, 1.0, false, &mut pose);
+        skeleton.palette(&pose, &mut palette);
+        let buffer = draw(&RasterizationCommand {
+            world_positions: &POSITIONS,
+            skinning: Some(Skinning::new(&palette, &joints, &weights)),
+            ..Default::default()
+        });
+        assert_eq!(RGBA::from_u32(buffer.at(8, 56)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(buffer.at(40, 24)), RGBA::new(255, 255, 255, 255));
+    }
+
+    #[test]
+    fn morph_targets_blend_before_skinning() {
+        // The target moves the triangle by half of NDC diagonally at its full weight
+        let deltas = [Vec3::new(1.0, 1.0, 0.0); 3];
+        let targets = [MorphTarget { position_deltas: &deltas, normal_deltas: &[] }];
+        let command = RasterizationCommand {
+            world_positions: &POSITIONS,
+            morph_targets: Some(MorphTargets::new(&targets, &[0.0])),
+            ..Default::default()
+        };
+        let buffer = draw(&command);
+        assert_eq!(RGBA::from_u32(buffer.at(8, 56)), RGBA::new(255, 255, 255, 255));
+        assert_eq!(RGBA::from_u32(buffer.at(56, 8)), RGBA::new(0, 0, 0, 255));
+
+        let buffer =
+            draw(&RasterizationCommand { morph_targets: Some(MorphTargets::new(&targets, &[1.0])), ..command.clone() });
+        assert_eq!(RGBA::from_u32(buffer.at(8, 56)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(buffer.at(40, 24)), RGBA::new(255, 255, 255, 255));
+
+        // The skinning sees the blended positions and moves them back
+        let palette = [Mat34::translate(Vec3::new(-1.0, -1.0, 0.0))];
+        let joints = [[0u16; 4]; 3];
+        let weights = [Vec4::new(1.0, 0.0, 0.0, 0.0); 3];
+        let buffer = draw(&RasterizationCommand {
+            morph_targets: Some(MorphTargets::new(&targets, &[1.0])),
+            skinning: Some(Skinning::new(&palette, &joints, &weights)),
+            ..command
+        });
+        assert_eq!(RGBA::from_u32(buffer.at(8, 56)), RGBA::new(255, 255, 255, 255));
+        assert_eq!(RGBA::from_u32(buffer.at(56, 8)), RGBA::new(0, 0, 0, 255));
+    }
+
+    #[test]
+    fn displacement_lifts_vertices_before_hook() {
+        // A flat heightmap of the full height lifts the triangle by half of NDC
+        let texture = Texture::new(&TextureSource {
+            texels: &[255],
+            width: 1,
+            height: 1,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        });
+        let command = RasterizationCommand {
+            world_positions: &POSITIONS,
+            tex_coords: &[Vec2::new(0.5, 0.5); 3],
+            displacement: Some(HeightmapDisplacement::new(texture.clone())),
+            ..Default::default()
+        };
+        let buffer = draw(&command);
+        assert_eq!(RGBA::from_u32(buffer.at(8, 56)), RGBA::new(0, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(buffer.at(8, 24)), RGBA::new(255, 255, 255, 255));
+
+        // The hook sees the displaced positions and moves them back
+        fn lower(vertex: &mut HookVertex, _uniforms: &Uniforms) {
+            vertex.position -= Vec3::new(0.0, 1.0, 0.0);
+        }
+        let buffer = draw(&RasterizationCommand { vertex_hook: Some(lower), ..command.clone() });
+        assert_eq!(RGBA::from_u32(buffer.at(8, 56)), RGBA::new(255, 255, 255, 255));
+
+        // Without the texture coordinates the displacement is skipped
+        let buffer = draw(&RasterizationCommand { tex_coords: &[], ..command });
+        assert_eq!(RGBA::from_u32(buffer.at(8, 56)), RGBA::new(255, 255, 255, 255));
+    }
+}
+
+#[cfg(test)]
+mod tests_depth_f32 {
+    use super::*;
+
+    // A quad covering [-size, size] in XY at the given Z
+    fn quad(z: f32, size: f32) -> [Vec3; 6] {
+        [
+            Vec3::new(-size, -size, z),
+            Vec3::new(size, -size, z),
+            Vec3::new(size, size, z),
+            Vec3::new(-size, -size, z),
+            Vec3::new(size, size, z),
+            Vec3::new(-size, size, z),
+        ]
+    }
+
+    fn command<'a>(positions: &'a [Vec3], color: Vec4, projection: Mat44) -> RasterizationCommand<'a> {
+        RasterizationCommand {
+            world_positions: positions,
+            color,
+            projection,
+            culling: CullMode::None,
+            ..Default::default()
+        }
+    }
+
+    // Draws the commands into a 64x64 color buffer with either of the depth buffers, returns the colors
+    fn draw(commands: &[RasterizationCommand], float_depth: bool) -> TiledBuffer<u32, 64, 64> {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(64, 64);
+        let mut depth_buffer_f32 = TiledBuffer::<f32, 64, 64>::new(64, 64);
+        let mut framebuffer = Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: if float_depth { None } else { Some(&mut depth_buffer) },
+            depth_buffer_f32: if float_depth { Some(&mut depth_buffer_f32) } else { None },
+            ..Default::default()
+        };
+        framebuffer.clear_all(&ClearValues::default());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        for command in commands {
+            rasterizer.commit(command);
+        }
+        rasterizer.draw(&mut framebuffer);
+        color_buffer
+    }
+
+    const RED: Vec4 = Vec4 { x: 1.0, y: 0.0, z: 0.0, w: 1.0 };
+    const GREEN: Vec4 = Vec4 { x: 0.0, y: 1.0, z: 0.0, w: 1.0 };
+
+    #[test]
+    fn encoding_is_reversed() {
+        assert_eq!(encode_depth_f32(-1.0), DEPTH_F32_NEAR);
+        assert_eq!(encode_depth_f32(1.0), DEPTH_F32_FAR);
+        assert_eq!(encode_depth_f32(0.0), 0.5);
+        assert_eq!(decode_depth_f32(encode_depth_f32(0.25)), 0.25);
+    }
+
+    #[test]
+    fn nearer_fragments_win_and_write_reversed_depth() {
+        let near = quad(-0.5, 1.0);
+        let far = quad(0.5, 0.5);
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        let mut depth_buffer = TiledBuffer::<f32, 64, 64>::new(64, 64);
+        let mut framebuffer = Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer_f32: Some(&mut depth_buffer),
+            ..Default::default()
+        };
+        framebuffer.clear_all(&ClearValues::default());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit(&command(&near, GREEN, Mat44::identity()));
+        rasterizer.commit(&command(&far, RED, Mat44::identity()));
+        rasterizer.draw(&mut framebuffer);
+        assert_eq!(RGBA::from_u32(color_buffer.at(32, 32)), RGBA::new(0, 255, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(2, 2)), RGBA::new(0, 255, 0, 255));
+        assert!((depth_buffer.at(32, 32) - encode_depth_f32(-0.5)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn depth_tests_keep_their_meaning() {
+        let near = quad(-0.5, 1.0);
+        let far = quad(0.5, 1.0);
+        for (depth_test, expected) in [
+            (DepthTest::Less, RGBA::new(0, 255, 0, 255)),
+            (DepthTest::Greater, RGBA::new(255, 0, 0, 255)),
+            (DepthTest::Always, RGBA::new(255, 0, 0, 255)),
+            (DepthTest::Never, RGBA::new(0, 255, 0, 255)),
+        ] {
+            let commands = [
+                command(&near, GREEN, Mat44::identity()),
+                RasterizationCommand { depth_test, ..command(&far, RED, Mat44::identity()) },
+            ];
+            assert_eq!(RGBA::from_u32(draw(&commands, true).at(32, 32)), expected, "{:?}", depth_test);
+        }
+    }
+
+    #[test]
+    fn resolves_distant_surfaces_which_fight_in_16_bits() {
+        // Two walls half a unit apart, 500 units away from the camera: both are in the same 16-bit depth step
+        let projection = Mat44::perspective(0.1, 1000.0, std::f32::consts::FRAC_PI_2, 1.0);
+        let back = quad(-500.5, 600.0);
+        let front = quad(-500.0, 600.0);
+        let commands = [command(&back, RED, projection), command(&front, GREEN, projection)];
+        let count_green = |buffer: &TiledBuffer<u32, 64, 64>| -> usize {
+            let mut count: usize = 0;
+            for y in 0..64 {
+                for x in 0..64 {
+                    count += (RGBA::from_u32(buffer.at(x, y)) == RGBA::new(0, 255, 0, 255)) as usize;
+                }
+            }
+            count
+        };
+        assert!(count_green(&draw(&commands, false)) < 64 * 64);
+        assert_eq!(count_green(&draw(&commands, true)), 64 * 64);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_both_depth_buffers() {
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(64, 64);
+        let mut depth_buffer_f32 = TiledBuffer::<f32, 64, 64>::new(64, 64);
+        let mut framebuffer = Framebuffer {
+            depth_buffer: Some(&mut depth_buffer),
+            depth_buffer_f32: Some(&mut depth_buffer_f32),
+            ..Default::default()
+        };
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.draw(&mut framebuffer);
+    }
+
+    #[test]
+    fn resolves_distant_surfaces_which_fight_in_ndc_floats() {
+        // Two walls a unit apart, 10000 units away from the camera: their NDC depths round to the same float
+        let projection = Mat44::perspective(0.01, 100000.0, std::f32::consts::FRAC_PI_2, 1.0);
+        let ndc_depth = |z: f32| -> f32 {
+            let clip: Vec4 = projection * Vec4::new(0.0, 0.0, z, 1.0);
+            clip.z / clip.w
+        };
+        assert_eq!(encode_depth_f32(ndc_depth(-10001.0)), encode_depth_f32(ndc_depth(-10000.0)));
+
+        let depths = |z: f32| -> TiledBuffer<f32, 64, 64> {
+            let wall = quad(z, 12000.0);
+            let mut depth_buffer = TiledBuffer::<f32, 64, 64>::new(64, 64);
+            let mut framebuffer = Framebuffer { depth_buffer_f32: Some(&mut depth_buffer), ..Default::default() };
+            framebuffer.clear_all(&ClearValues::default());
+            let mut rasterizer = Rasterizer::new();
+            rasterizer.setup(Viewport::new(0, 0, 64, 64));
+            rasterizer.commit(&command(&wall, GREEN, projection));
+            rasterizer.draw(&mut framebuffer);
+            depth_buffer
+        };
+        // The reversed depths are 0.5 * (w - z) / w, i.e. 0.01 * (1 / 10000 - 1 / 10001) ~= 1e-10 apart
+        let (front, back) = (depths(-10000.0), depths(-10001.0));
+        for y in 0..64 {
+            for x in 0..64 {
+                let separation: f32 = front.at(x, y) - back.at(x, y);
+                assert!(separation > 0.5e-10 && separation < 2.0e-10, "{}, {}: {:e}", x, y, separation);
+            }
+        }
+    }
+
+    #[test]
+    fn orthographic_projection_keeps_ndc_depth() {
+        let projection = Mat44::orthographic(-1.0, 1.0, -1.0, 1.0, 0.0, 10.0);
+        let near = quad(-2.0, 1.0);
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        let mut depth_buffer = TiledBuffer::<f32, 64, 64>::new(64, 64);
+        let mut framebuffer = Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer_f32: Some(&mut depth_buffer),
+            ..Default::default()
+        };
+        framebuffer.clear_all(&ClearValues::default());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit(&command(&near, GREEN, projection));
+        rasterizer.draw(&mut framebuffer);
+        assert!((depth_buffer.at(32, 32) - encode_depth_f32(-0.6)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn matches_16_bit_depth_on_regular_scenes() {
+        let projection = Mat44::perspective(0.5, 50.0, std::f32::consts::FRAC_PI_2, 1.0);
+        let a = [Vec3::new(-3.0, -3.0, -2.0), Vec3::new(3.0, -1.0, -6.0), Vec3::new(0.0, 3.0, -4.0)];
+        let b = [Vec3::new(-3.0, 2.0, -6.0), Vec3::new(3.0, 2.0, -2.0), Vec3::new(0.0, -3.0, -4.0)];
+        let c = quad(-5.0, 4.0);
+        let commands = [
+            command(&c, Vec4::new(0.0, 0.0, 1.0, 1.0), projection),
+            command(&a, RED, projection),
+            command(&b, GREEN, projection),
+        ];
+        let reference = draw(&commands, false);
+        let float = draw(&commands, true);
+        let mut mismatches: usize = 0;
+        for y in 0..64 {
+            for x in 0..64 {
+                mismatches += (reference.at(x, y) != float.at(x, y)) as usize;
+            }
+        }
+        // Only the pixels right on the intersections can differ
+        assert!(mismatches < 16, "{}", mismatches);
+    }
+}
+
+#[cfg(test)]
+mod tests_depth_bias {
+    use super::*;
+
+    // A wall receding from the camera to the right
+    const WALL: [Vec3; 6] = [
+        Vec3 { x: -2.0, y: -2.0, z: -1.5 },
+        Vec3 { x: 2.0, y: -2.0, z: -6.0 },
+        Vec3 { x: 2.0, y: 2.0, z: -6.0 },
+        Vec3 { x: -2.0, y: -2.0, z: -1.5 },
+        Vec3 { x: 2.0, y: 2.0, z: -6.0 },
+        Vec3 { x: -2.0, y: 2.0, z: -1.5 },
+    ];
+
+    // Draws the wall in red and then the same wall in green with the given bias, returns the share of the wall's pixels
+    // that ended up green
+    fn green_share(depth_bias: DepthBias, float_depth: bool) -> f32 {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(64, 64);
+        let mut depth_buffer_f32 = TiledBuffer::<f32, 64, 64>::new(64, 64);
+        let mut framebuffer = Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: if float_depth { None } else { Some(&mut depth_buffer) },
+            depth_buffer_f32: if float_depth { Some(&mut depth_buffer_f32) } else { None },
+            ..Default::default()
+        };
+        framebuffer.clear_all(&ClearValues::default());
+        let projection = Mat44::perspective(0.5, 100.0, std::f32::consts::FRAC_PI_2, 1.0);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        let wall = RasterizationCommand {
+            world_positions: &WALL,
+            projection,
+            culling: CullMode::None,
+            color: Vec4::new(1.0, 0.0, 0.0, 1.0),
+            ..Default::default()
+        };
+        rasterizer.commit(&wall);
+        rasterizer.commit(&RasterizationCommand { color: Vec4::new(0.0, 1.0, 0.0, 1.0), depth_bias, ..wall.clone() });
+        rasterizer.draw(&mut framebuffer);
+        let (mut red, mut green): (usize, usize) = (0, 0);
+        for y in 0..64 {
+            for x in 0..64 {
+                let color: RGBA = RGBA::from_u32(color_buffer.at(x, y));
+                red += (color == RGBA::new(255, 0, 0, 255)) as usize;
+                green += (color == RGBA::new(0, 255, 0, 255)) as usize;
+            }
+        }
+        assert!(red + green > 64 * 64 / 4);
+        green as f32 / (red + green) as f32
+    }
+
+    #[test]
+    fn coplanar_geometry_is_hidden_without_bias() {
+        assert_eq!(green_share(DepthBias::default(), false), 0.0);
+        assert_eq!(green_share(DepthBias::default(), true), 0.0);
+    }
+
+    #[test]
+    fn negative_bias_pulls_coplanar_geometry_in_front() {
+        assert_eq!(green_share(DepthBias::new(-1.0, -1.0), false), 1.0);
+        assert_eq!(green_share(DepthBias::new(-1.0, -1.0), true), 1.0);
+    }
+
+    #[test]
+    fn positive_bias_pushes_geometry_behind() {
+        assert_eq!(green_share(DepthBias::new(1.0, 1.0), false), 0.0);
+        assert_eq!(green_share(DepthBias::new(1.0, 1.0), true), 0.0);
+    }
+
+    #[test]
+    fn offset_follows_the_slope() {
+        let bias = DepthBias::new(2.0, 0.5);
+        assert_eq!(bias.offset(4.0, -8.0, 1.0), 6.0);
+        assert_eq!(bias.offset(0.0, 0.0, 0.25), 0.5);
+        assert!(DepthBias::default().is_zero());
+    }
+}
+
+#[cfg(test)]
+mod tests_extended_alpha_blending {
+    use super::*;
+
+    // Blends a red triangle over a red background in every combination of the intensities and the alpha, and compares
+    // the result against the expected formula of non-premultiplied colors in [0, 1]
+    fn check(mode: AlphaBlendingMode, premultiply: bool, expected: impl Fn(f32, f32, f32) -> f32) {
+        let mut rasterizer = Rasterizer::new();
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(1u16, 1u16);
+        let pos = [Vec3::new(0.0, 1.0, 0.0), Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0)];
+        for background in (0..=255).step_by(51) {
+            for foreground in (0..=255).step_by(51) {
+                for alpha in (0..=255).step_by(51) {
+                    let (d, s, a): (f32, f32, f32) =
+                        (background as f32 / 255.0, foreground as f32 / 255.0, alpha as f32 / 255.0);
+                    rasterizer.setup(Viewport::new(0, 0, 1u16, 1u16));
+                    rasterizer.commit(&RasterizationCommand {
+                        world_positions: &pos,
+                        color: Vec4::new(if premultiply { s * a } else { s }, 0.0, 0.0, a),
+                        alpha_blending: mode,
+                        ..Default::default()
+                    });
+                    color_buffer.fill(RGBA::new(background as u8, 0, 0, 255).to_u32());
+                    rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+                    let expected: u8 = (expected(s, a, d) * 255.0 + 0.5).clamp(0.0, 255.0) as u8;
+                    assert_rgba_eq!(RGBA::from_u32(color_buffer.at(0, 0)), RGBA::new(expected, 0, 0, 255), 2);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blending_multiply() {
+        check(AlphaBlendingMode::Multiply, false, |s, a, d| s * a * d + (1.0 - a) * d);
+    }
+
+    #[test]
+    fn blending_screen() {
+        check(AlphaBlendingMode::Screen, false, |s, a, d| s * a + (1.0 - s * a) * d);
+    }
+
+    #[test]
+    fn blending_subtract() {
+        check(AlphaBlendingMode::Subtract, false, |s, a, d| d - s * a);
+    }
+
+    #[test]
+    fn blending_min() {
+        check(AlphaBlendingMode::Min, false, |s, a, d| a * s.min(d) + (1.0 - a) * d);
+    }
+
+    #[test]
+    fn blending_max() {
+        check(AlphaBlendingMode::Max, false, |s, a, d| a * s.max(d) + (1.0 - a) * d);
+    }
+
+    #[test]
+    fn blending_premultiplied_normal() {
+        check(AlphaBlendingMode::PremultipliedNormal, true, |s, a, d| s * a + (1.0 - a) * d);
+    }
+
+    #[test]
+    fn blending_into_hdr_buffer() {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(1u16, 1u16);
+        color_buffer.fill(encode_rgb9e5(2.0, 0.5, 0.25));
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 1u16, 1u16));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &[Vec3::new(0.0, 1.0, 0.0), Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0)],
+            color: Vec4::new(0.5, 1.0, 0.0, 1.0),
+            alpha_blending: AlphaBlendingMode::Multiply,
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            color_format: ColorBufferFormat::Rgb9e5,
+            ..Default::default()
+        });
+        let color: Vec3 = decode_rgb9e5(color_buffer.at(0, 0));
+        assert!((color.x - 1.0).abs() < 0.01);
+        assert!((color.y - 0.5).abs() < 0.01);
+        assert!(color.z.abs() < 0.01);
+    }
+
+    #[test]
+    fn blending_fullscreen() {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(4u16, 4u16);
+        color_buffer.fill(RGBA::new(200, 100, 50, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 4u16, 4u16));
+        rasterizer.commit_fullscreen(&FullscreenCommand {
+            color: Vec4::new(0.5, 0.5, 0.5, 1.0),
+            alpha_blending: AlphaBlendingMode::Subtract,
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        assert_rgba_eq!(RGBA::from_u32(color_buffer.at(3, 3)), RGBA::new(72, 0, 0, 255), 2);
+    }
+}
+
+#[cfg(test)]
+mod tests_color_write_mask {
+    use super::*;
+
+    const TRIANGLE: [Vec3; 3] = [Vec3::new(0.0, 1.0, 0.0), Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0)];
+
+    fn draw(
+        color_buffer: &mut TiledBuffer<u32, 64, 64>,
+        depth_buffer: &mut TiledBuffer<u16, 64, 64>,
+        command: &RasterizationCommand,
+    ) {
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 1u16, 1u16));
+        rasterizer.commit(command);
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(color_buffer),
+            depth_buffer: Some(depth_buffer),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn no_channels_lay_down_depth_only() {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(1u16, 1u16);
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(1u16, 1u16);
+        color_buffer.fill(RGBA::new(10, 20, 30, 40).to_u32());
+        depth_buffer.fill(DEPTH_FAR);
+        draw(
+            &mut color_buffer,
+            &mut depth_buffer,
+            &RasterizationCommand {
+                world_positions: &TRIANGLE,
+                color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+                color_write_mask: ColorWriteMask::NONE,
+                ..Default::default()
+            },
+        );
+        assert_eq!(RGBA::from_u32(color_buffer.at(0, 0)), RGBA::new(10, 20, 30, 40));
+        assert!(depth_buffer.at(0, 0) < DEPTH_FAR);
+    }
+
+    #[test]
+    fn masked_out_channels_keep_their_values() {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(1u16, 1u16);
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(1u16, 1u16);
+        color_buffer.fill(RGBA::new(10, 20, 30, 40).to_u32());
+        depth_buffer.fill(DEPTH_FAR);
+        draw(
+            &mut color_buffer,
+            &mut depth_buffer,
+            &RasterizationCommand {
+                world_positions: &TRIANGLE,
+                color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+                color_write_mask: ColorWriteMask::new(false, true, false, true),
+                ..Default::default()
+            },
+        );
+        assert_eq!(RGBA::from_u32(color_buffer.at(0, 0)), RGBA::new(10, 255, 30, 255));
+    }
+
+    #[test]
+    fn masked_blending_writes_the_blended_channels() {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(1u16, 1u16);
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(1u16, 1u16);
+        color_buffer.fill(RGBA::new(100, 100, 100, 255).to_u32());
+        depth_buffer.fill(DEPTH_FAR);
+        for alpha_blending in [AlphaBlendingMode::Additive, AlphaBlendingMode::Multiply] {
+            draw(
+                &mut color_buffer,
+                &mut depth_buffer,
+                &RasterizationCommand {
+                    world_positions: &TRIANGLE,
+                    color: Vec4::new(0.5, 0.5, 0.5, 1.0),
+                    alpha_blending,
+                    color_write_mask: ColorWriteMask::new(true, false, false, true),
+                    depth_test: DepthTest::Always,
+                    ..Default::default()
+                },
+            );
+        }
+        // (100 + 128) * 0.5 into the red channel only
+        assert_rgba_eq!(RGBA::from_u32(color_buffer.at(0, 0)), RGBA::new(114, 100, 100, 255), 2);
+    }
+
+    #[test]
+    fn masked_hdr_channels_keep_their_values() {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(1u16, 1u16);
+        color_buffer.fill(encode_rgb9e5(2.0, 4.0, 8.0));
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 1u16, 1u16));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &TRIANGLE,
+            color: Vec4::new(0.5, 0.5, 0.5, 1.0),
+            color_write_mask: ColorWriteMask::new(false, false, true, false),
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            color_format: ColorBufferFormat::Rgb9e5,
+            ..Default::default()
+        });
+        let color: Vec3 = decode_rgb9e5(color_buffer.at(0, 0));
+        assert!((color.x - 2.0).abs() < 0.05 && (color.y - 4.0).abs() < 0.05 && (color.z - 0.5).abs() < 0.05);
+    }
+}
+
+#[cfg(test)]
+mod tests_tint {
+    use super::*;
+
+    const TRIANGLE: [Vec3; 3] = [Vec3::new(0.0, 1.0, 0.0), Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, -1.0, 0.0)];
+
+    fn draw(background: RGBA, command: &RasterizationCommand) -> RGBA {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(1u16, 1u16);
+        color_buffer.fill(background.to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 1u16, 1u16));
+        rasterizer.commit(command);
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        RGBA::from_u32(color_buffer.at(0, 0))
+    }
+
+    fn texture() -> std::sync::Arc<Texture> {
+        Texture::new(&TextureSource {
+            texels: &[200u8, 100, 50],
+            width: 1,
+            height: 1,
+            format: TextureFormat::RGB,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn multiply_applies_after_sampling() {
+        let tex_coords = [Vec2::new(0.5, 0.0), Vec2::new(0.0, 1.0), Vec2::new(1.0, 1.0)];
+        let command = RasterizationCommand {
+            world_positions: &TRIANGLE,
+            tex_coords: &tex_coords,
+            texture: Some(texture()),
+            tint: ColorTint::new(Vec4::new(0.5, 1.0, 0.0, 1.0), Vec4::new(0.0, 0.0, 0.0, 0.0)),
+            ..Default::default()
+        };
+        assert_rgba_eq!(draw(RGBA::new(0, 0, 0, 255), &command), RGBA::new(100, 100, 0, 255), 2);
+    }
+
+    #[test]
+    fn add_flashes_white() {
+        let tex_coords = [Vec2::new(0.5, 0.0), Vec2::new(0.0, 1.0), Vec2::new(1.0, 1.0)];
+        let command = RasterizationCommand {
+            world_positions: &TRIANGLE,
+            tex_coords: &tex_coords,
+            texture: Some(texture()),
+            tint: ColorTint::new(Vec4::new(1.0, 1.0, 1.0, 1.0), Vec4::new(0.5, 0.5, 0.5, 0.0)),
+            ..Default::default()
+        };
+        assert_rgba_eq!(draw(RGBA::new(0, 0, 0, 255), &command), RGBA::new(255, 228, 178, 255), 2);
+    }
+
+    #[test]
+    fn multiplied_alpha_fades_blended_commands() {
+        let command = RasterizationCommand {
+            world_positions: &TRIANGLE,
+            color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            alpha_blending: AlphaBlendingMode::Normal,
+            tint: ColorTint::new(Vec4::new(1.0, 1.0, 1.0, 0.25), Vec4::new(0.0, 0.0, 0.0, 0.0)),
+            ..Default::default()
+        };
+        assert_rgba_eq!(draw(RGBA::new(0, 0, 0, 255), &command), RGBA::new(64, 64, 64, 255), 2);
+    }
+
+    #[test]
+    fn tint_in_hdr() {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(1u16, 1u16);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 1u16, 1u16));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &TRIANGLE,
+            color: Vec4::new(0.5, 0.5, 0.5, 1.0),
+            tint: ColorTint::new(Vec4::new(4.0, 2.0, 1.0, 1.0), Vec4::new(0.0, 0.0, 1.0, 0.0)),
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            color_format: ColorBufferFormat::Rgb9e5,
+            ..Default::default()
+        });
+        let color: Vec3 = decode_rgb9e5(color_buffer.at(0, 0));
+        assert!((color.x - 2.0).abs() < 0.05 && (color.y - 1.0).abs() < 0.05 && (color.z - 1.5).abs() < 0.05);
+    }
+}
+
+#[cfg(test)]
+mod tests_fog {
+    use super::*;
+
+    // Draws a white wall facing the camera at the distance over a black background, returns the center pixel
+    fn draw_at(distance: f32, fog: Option<Fog>, alpha_blending: AlphaBlendingMode, color: Vec4) -> RGBA {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(8u16, 8u16);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let z: f32 = -distance;
+        let d: f32 = distance;
+        let positions: [Vec3; 6] = [
+            Vec3::new(-d, -d, z),
+            Vec3::new(d, -d, z),
+            Vec3::new(d, d, z),
+            Vec3::new(-d, -d, z),
+            Vec3::new(d, d, z),
+            Vec3::new(-d, d, z),
+        ];
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 8u16, 8u16));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &positions,
+            projection: Mat44::perspective(0.1, 100.0, std::f32::consts::FRAC_PI_2, 1.0),
+            color,
+            alpha_blending,
+            fog,
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        RGBA::from_u32(color_buffer.at(4, 4))
+    }
+
+    const WHITE: Vec4 = Vec4::new(1.0, 1.0, 1.0, 1.0);
+
+    #[test]
+    fn factors() {
+        let black: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+        let linear = Fog::new(FogMode::Linear { start: 10.0, end: 20.0 }, black);
+        assert_eq!(linear.factor(5.0), 1.0);
+        assert_eq!(linear.factor(15.0), 0.5);
+        assert_eq!(linear.factor(25.0), 0.0);
+        let exp = Fog::new(FogMode::Exp { density: 0.1 }, black);
+        assert!((exp.factor(10.0) - (-1.0f32).exp()).abs() < 1e-6);
+        let exp2 = Fog::new(FogMode::Exp2 { density: 0.1 }, black);
+        assert!((exp2.factor(20.0) - (-4.0f32).exp()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn no_fog() {
+        assert_rgba_eq!(draw_at(10.0, None, AlphaBlendingMode::None, WHITE), RGBA::new(255, 255, 255, 255), 1);
+    }
+
+    #[test]
+    fn linear_fog_by_view_depth() {
+        let fog = Some(Fog::new(FogMode::Linear { start: 0.0, end: 20.0 }, Vec3::new(0.0, 0.0, 1.0)));
+        assert_rgba_eq!(draw_at(5.0, fog, AlphaBlendingMode::None, WHITE), RGBA::new(191, 191, 255, 255), 2);
+        assert_rgba_eq!(draw_at(10.0, fog, AlphaBlendingMode::None, WHITE), RGBA::new(128, 128, 255, 255), 2);
+        assert_rgba_eq!(draw_at(30.0, fog, AlphaBlendingMode::None, WHITE), RGBA::new(0, 0, 255, 255), 2);
+    }
+
+    #[test]
+    fn exponential_fog() {
+        let black: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+        let exp = Some(Fog::new(FogMode::Exp { density: 0.1 }, black));
+        let exp2 = Some(Fog::new(FogMode::Exp2 { density: 0.1 }, black));
+        assert_rgba_eq!(draw_at(10.0, exp, AlphaBlendingMode::None, WHITE), RGBA::new(94, 94, 94, 255), 2);
+        assert_rgba_eq!(draw_at(10.0, exp2, AlphaBlendingMode::None, WHITE), RGBA::new(94, 94, 94, 255), 2);
+        assert_rgba_eq!(draw_at(5.0, exp2, AlphaBlendingMode::None, WHITE), RGBA::new(199, 199, 199, 255), 2);
+    }
+
+    #[test]
+    fn fog_respects_alpha() {
+        // Half of the fogged color blends over the background
+        let fog = Some(Fog::new(FogMode::Linear { start: 0.0, end: 20.0 }, Vec3::new(0.0, 0.0, 1.0)));
+        let color: Vec4 = Vec4::new(1.0, 1.0, 1.0, 0.5);
+        assert_rgba_eq!(draw_at(10.0, fog, AlphaBlendingMode::Normal, color), RGBA::new(64, 64, 128, 255), 2);
+    }
+}
+
+#[cfg(test)]
+mod tests_vertex_lighting {
+    use super::*;
+
+    // Draws a wall facing the camera over a black background, split into the left and the right halves.
+    // Returns the pixels at the left edge, at the center and at the right edge.
+    fn draw(lighting: VertexLighting, normals: &[Vec3], color: Vec4) -> (RGBA, RGBA, RGBA) {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(16u16, 16u16);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let positions: [Vec3; 6] = [
+            Vec3::new(-10.0, -10.0, -10.0),
+            Vec3::new(10.0, -10.0, -10.0),
+            Vec3::new(10.0, 10.0, -10.0),
+            Vec3::new(-10.0, -10.0, -10.0),
+            Vec3::new(10.0, 10.0, -10.0),
+            Vec3::new(-10.0, 10.0, -10.0),
+        ];
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 16u16, 16u16));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &positions,
+            normals,
+            projection: Mat44::perspective(0.1, 100.0, std::f32::consts::FRAC_PI_2, 1.0),
+            color,
+            lighting: Some(lighting),
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        (
+            RGBA::from_u32(color_buffer.at(0, 8)),
+            RGBA::from_u32(color_buffer.at(8, 8)),
+            RGBA::from_u32(color_buffer.at(15, 8)),
+        )
+    }
+
+    const WHITE: Vec4 = Vec4::new(1.0, 1.0, 1.0, 1.0);
+
+    #[test]
+    fn ambient_only() {
+        let material = LightingMaterial { ambient: Vec3::new(0.25, 0.5, 1.0), ..Default::default() };
+        let (_, center, _) = draw(VertexLighting { lights: &[], material }, &[], WHITE);
+        assert_rgba_eq!(center, RGBA::new(64, 128, 255, 255), 2);
+    }
+
+    #[test]
+    fn directional_light_by_face_normals() {
+        let head_on = [Light::Directional { direction: Vec3::new(0.0, 0.0, -1.0), color: Vec3::new(1.0, 1.0, 1.0) }];
+        let lighting = VertexLighting { lights: &head_on, ..Default::default() };
+        let (left, center, right) = draw(lighting, &[], Vec4::new(1.0, 0.5, 0.0, 1.0));
+        assert_rgba_eq!(center, RGBA::new(255, 128, 0, 255), 2);
+        assert_eq!(left, center);
+        assert_eq!(right, center);
+
+        // At 60 degrees from the normal
+        let direction: Vec3 = Vec3::new(3.0f32.sqrt() / 2.0, 0.0, -0.5);
+        let oblique = [Light::Directional { direction, color: Vec3::new(1.0, 1.0, 1.0) }];
+        let lighting = VertexLighting { lights: &oblique, ..Default::default() };
+        let (_, center, _) = draw(lighting, &[], WHITE);
+        assert_rgba_eq!(center, RGBA::new(128, 128, 128, 255), 2);
+
+        // From behind
+        let behind = [Light::Directional { direction: Vec3::new(0.0, 0.0, 1.0), color: Vec3::new(1.0, 1.0, 1.0) }];
+        let lighting = VertexLighting { lights: &behind, ..Default::default() };
+        let (_, center, _) = draw(lighting, &[], WHITE);
+        assert_rgba_eq!(center, RGBA::new(0, 0, 0, 255), 2);
+    }
+
+    #[test]
+    fn point_light_is_interpolated_between_vertices() {
+        // Close to the left edge, out of reach of the right one
+        let lamp =
+            [Light::Point { position: Vec3::new(-10.0, 0.0, -5.0), color: Vec3::new(1.0, 1.0, 1.0), range: 20.0 }];
+        let normals: [Vec3; 6] = [Vec3::new(0.0, 0.0, 1.0); 6];
+        let (left, center, right) = draw(VertexLighting { lights: &lamp, ..Default::default() }, &normals, WHITE);
+        assert!(left.r > center.r);
+        assert!(center.r > right.r);
+        assert_rgba_eq!(right, RGBA::new(0, 0, 0, 255), 2);
+    }
+
+    #[test]
+    fn specular_highlight() {
+        // The light is head-on, but the highlight is evaluated at the corners, which the camera sees at an angle:
+        // the half vector there is 27 degrees off the normal
+        let head_on = [Light::Directional { direction: Vec3::new(0.0, 0.0, -1.0), color: Vec3::new(1.0, 1.0, 1.0) }];
+        let material = LightingMaterial {
+            diffuse: Vec3::new(0.0, 0.0, 0.0),
+            specular: Vec3::new(0.5, 0.5, 0.5),
+            shininess: 1.0,
+            ..Default::default()
+        };
+        let normals: [Vec3; 6] = [Vec3::new(0.0, 0.0, 1.0); 6];
+        let (_, center, _) =
+            draw(VertexLighting { lights: &head_on, material }, &normals, Vec4::new(1.0, 0.0, 0.0, 1.0));
+        assert_rgba_eq!(center, RGBA::new(113, 113, 113, 255), 2);
+    }
+}
+
+#[cfg(test)]
+mod tests_mesh_materials {
+    use super::*;
+
+    // A quad covering the viewport, split into the top-left and the bottom-right triangles with materials 0 and 1
+    fn quad() -> std::sync::Arc<MeshData> {
+        let mut mesh = MeshData {
+            positions: vec![
+                Vec3::new(-1.0, -1.0, 0.0),
+                Vec3::new(1.0, -1.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(-1.0, 1.0, 0.0),
+            ],
+            indices: vec![0, 2, 3, 0, 1, 2],
+            ..Default::default()
+        };
+        mesh.sections.push(MeshDataSection {
+            name: "top-left".to_string(),
+            start_index: 0,
+            num_triangles: 1,
+            material_index: 0,
+        });
+        mesh.sections.push(MeshDataSection {
+            name: "bottom-right".to_string(),
+            start_index: 3,
+            num_triangles: 1,
+            material_index: 1,
+        });
+        mesh.into_shared()
+    }
+
+    fn draw(mesh: &MeshData, materials: &[Material]) -> (TiledBuffer<u32, 64, 64>, RasterizerStatistics) {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(16u16, 16u16);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 16u16, 16u16));
+        rasterizer.commit_mesh_with_materials(mesh, materials, &Transforms::default());
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        (color_buffer, rasterizer.statistics())
+    }
+
+    fn material(color: Vec4) -> Material {
+        Material { color, ..Default::default() }
+    }
+
+    #[test]
+    fn sections_are_drawn_with_their_materials() {
+        let red: Material = material(Vec4::new(1.0, 0.0, 0.0, 1.0));
+        let green: Material = material(Vec4::new(0.0, 1.0, 0.0, 1.0));
+        let (buffer, stats) = draw(&quad(), &[red, green]);
+        assert_eq!(RGBA::from_u32(buffer.at(2, 8)), RGBA::new(255, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(buffer.at(13, 8)), RGBA::new(0, 255, 0, 255));
+        assert_eq!(stats.committed_triangles, 2);
+    }
+
+    #[test]
+    fn missing_materials_are_default() {
+        let red: Material = material(Vec4::new(1.0, 0.0, 0.0, 1.0));
+        let (buffer, _) = draw(&quad(), &[red]);
+        assert_eq!(RGBA::from_u32(buffer.at(2, 8)), RGBA::new(255, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(buffer.at(13, 8)), RGBA::new(255, 255, 255, 255));
+    }
+
+    #[test]
+    fn mesh_without_sections_uses_first_material() {
+        let mut mesh: MeshData = (*quad()).clone();
+        mesh.sections.clear();
+        let blue: Material = material(Vec4::new(0.0, 0.0, 1.0, 1.0));
+        let (buffer, _) = draw(&mesh, &[blue]);
+        assert_eq!(RGBA::from_u32(buffer.at(2, 8)), RGBA::new(0, 0, 255, 255));
+        assert_eq!(RGBA::from_u32(buffer.at(13, 8)), RGBA::new(0, 0, 255, 255));
+    }
+}
+
+#[cfg(test)]
+mod tests_reflection {
+    use super::*;
+    use std::sync::Arc;
+
+    fn solid(color: RGBA) -> Arc<Texture> {
+        let texels: [u8; 3] = [color.r, color.g, color.b];
+        Texture::new(&TextureSource {
+            texels: &texels,
+            width: 1,
+            height: 1,
+            format: TextureFormat::RGB,
+            ..Default::default()
+        })
+    }
+
+    // A cube map with distinct faces: +X red, -X cyan, +Y green, -Y magenta, +Z blue, -Z yellow
+    fn cube_map() -> EnvironmentMap {
+        EnvironmentMap::Cube(Arc::new(CubeMap::new([
+            solid(RGBA::new(255, 0, 0, 255)),
+            solid(RGBA::new(0, 255, 255, 255)),
+            solid(RGBA::new(0, 255, 0, 255)),
+            solid(RGBA::new(255, 0, 255, 255)),
+            solid(RGBA::new(0, 0, 255, 255)),
+            solid(RGBA::new(255, 255, 0, 255)),
+        ])))
+    }
+
+    // Draws a wall facing the camera, which looks along -Z from the origin, and returns the center pixel
+    fn draw(reflection: Option<Reflection>, color: Vec4) -> RGBA {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(8u16, 8u16);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let positions: [Vec3; 6] = [
+            Vec3::new(-10.0, -10.0, -10.0),
+            Vec3::new(10.0, -10.0, -10.0),
+            Vec3::new(10.0, 10.0, -10.0),
+            Vec3::new(-10.0, -10.0, -10.0),
+            Vec3::new(10.0, 10.0, -10.0),
+            Vec3::new(-10.0, 10.0, -10.0),
+        ];
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 8u16, 8u16));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &positions,
+            projection: Mat44::perspective(0.1, 100.0, std::f32::consts::FRAC_PI_2, 1.0),
+            color,
+            reflection,
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        RGBA::from_u32(color_buffer.at(4, 4))
+    }
+
+    const WHITE: Vec4 = Vec4::new(1.0, 1.0, 1.0, 1.0);
+
+    #[test]
+    fn no_reflection() {
+        assert_eq!(draw(None, WHITE), RGBA::new(255, 255, 255, 255));
+    }
+
+    #[test]
+    fn cube_map_reflects_back_at_camera() {
+        let reflection = Reflection::new(cube_map(), 1.0, Vec3::new(0.0, 0.0, 0.0));
+        assert_rgba_eq!(draw(Some(reflection), WHITE), RGBA::new(0, 0, 255, 255), 2);
+    }
+
+    #[test]
+    fn reflection_follows_camera_position() {
+        // Seen from far to the left, the wall reflects the +X side
+        let reflection = Reflection::new(cube_map(), 1.0, Vec3::new(-100.0, 0.0, -9.0));
+        assert_rgba_eq!(draw(Some(reflection), WHITE), RGBA::new(255, 0, 0, 255), 2);
+    }
+
+    #[test]
+    fn reflectivity_mixes_with_color() {
+        let environment = EnvironmentMap::Sphere(solid(RGBA::new(0, 0, 255, 255)));
+        let reflection = Reflection::new(environment, 0.5, Vec3::new(0.0, 0.0, 0.0));
+        let color: Vec4 = Vec4::new(1.0, 0.0, 0.0, 1.0);
+        assert_rgba_eq!(draw(Some(reflection), color), RGBA::new(128, 0, 128, 255), 2);
+    }
+}
+
+#[cfg(test)]
+mod tests_scheduled_vertices {
+    use super::*;
+
+    const QUAD_POSITIONS: [Vec3; 4] =
+        [Vec3::new(-0.5, -0.5, 0.0), Vec3::new(0.5, -0.5, 0.0), Vec3::new(0.5, 0.5, 0.0), Vec3::new(-0.5, 0.5, 0.0)];
+    const QUAD_NORMALS: [Vec3; 4] = [Vec3::new(0.0, 0.0, 1.0); 4];
+    const QUAD_INDICES: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+    fn quad() -> RasterizationCommand<'static> {
+        RasterizationCommand {
+            world_positions: &QUAD_POSITIONS,
+            normals: &QUAD_NORMALS,
+            indices: &QUAD_INDICES,
+            ..Default::default()
+        }
+    }
+
+    fn draw(
+        commands: &[RasterizationCommand],
+        guard_band_clipping: bool,
+        debug_coloring: bool,
+    ) -> (TiledBuffer<u32, 64, 64>, RasterizerStatistics) {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(32u16, 32u16);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 32u16, 32u16));
+        rasterizer.set_guard_band_clipping(guard_band_clipping);
+        rasterizer.set_debug_coloring(debug_coloring);
+        for command in commands {
+            rasterizer.commit(command);
+        }
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        (color_buffer, rasterizer.statistics())
+    }
+
+    #[test]
+    fn clipped_fans_share_vertices() {
+        let positions: [Vec3; 3] = [Vec3::new(-3.0, -1.0, 0.0), Vec3::new(3.0, -1.0, 0.0), Vec3::new(0.0, 3.0, 0.0)];
+        let command = RasterizationCommand { world_positions: &positions, ..Default::default() };
+        let (clipped, stats) = draw(std::slice::from_ref(&command), false, false);
+        assert_eq!(stats.clipped_triangles, 1);
+        assert!(stats.scheduled_triangles >= 2);
+        assert!(stats.scheduled_vertices < stats.scheduled_triangles * 3);
+
+        // Rasterized without clipping, the triangle covers the same pixels
+        let (unclipped, stats) = draw(std::slice::from_ref(&command), true, false);
+        assert_eq!(stats.clipped_triangles, 0);
+        assert_eq!(clipped.as_flat_buffer().elems, unclipped.as_flat_buffer().elems);
+    }
+
+    #[test]
+    fn adjacent_triangles_share_vertices() {
+        let (indexed_buffer, stats) = draw(&[quad()], true, false);
+        assert_eq!(stats.scheduled_triangles, 2);
+        assert_eq!(stats.scheduled_vertices, 4);
+
+        // The same vertices of a non-indexed command are found as well
+        let positions: Vec<Vec3> = QUAD_INDICES.iter().map(|&i| QUAD_POSITIONS[i as usize]).collect();
+        let normals: Vec<Vec3> = QUAD_INDICES.iter().map(|&i| QUAD_NORMALS[i as usize]).collect();
+        let flat = RasterizationCommand { world_positions: &positions, normals: &normals, ..Default::default() };
+        let (flat_buffer, stats) = draw(&[flat], true, false);
+        assert_eq!(stats.scheduled_vertices, 4);
+        assert_eq!(indexed_buffer.as_flat_buffer().elems, flat_buffer.as_flat_buffer().elems);
+    }
+
+    #[test]
+    fn commands_dont_share_vertices() {
+        let other = RasterizationCommand { color: Vec4::new(1.0, 0.0, 0.0, 1.0), ..quad() };
+        let (buffer, stats) = draw(&[quad(), other], true, false);
+        assert_eq!(stats.scheduled_vertices, 8);
+        assert_eq!(RGBA::from_u32(buffer.at(16, 16)), RGBA::new(255, 0, 0, 255));
+    }
+
+    #[test]
+    fn debug_coloring_keeps_triangles_apart() {
+        let command = RasterizationCommand { color: Vec4::new(0.5, 0.5, 0.5, 1.0), ..quad() };
+        let (buffer, _) = draw(&[command], true, true);
+        // The right and the left halves of the quad split by its diagonal
+        assert_ne!(buffer.at(22, 16), buffer.at(10, 16));
+        assert_eq!(buffer.at(22, 16), buffer.at(22, 14));
+    }
+}
+
+#[cfg(test)]
+mod tests_tile_splitting {
+    use super::*;
+
+    // A grid of small depth-tested triangles with varying colors and depths, all within a single tile
+    fn grid() -> (Vec<Vec3>, Vec<Vec4>) {
+        let mut positions: Vec<Vec3> = Vec::new();
+        let mut colors: Vec<Vec4> = Vec::new();
+        for y in 0..24 {
+            for x in 0..24 {
+                let (x0, y0) = (-0.95 + x as f32 * 0.08, -0.95 + y as f32 * 0.08);
+                let z: f32 = ((x * 7 + y * 13) % 17) as f32 / 17.0 - 0.5;
+                positions.push(Vec3::new(x0, y0, z));
+                positions.push(Vec3::new(x0 + 0.11, y0, z));
+                positions.push(Vec3::new(x0, y0 + 0.11, z));
+                let color = Vec4::new(x as f32 / 24.0, y as f32 / 24.0, z + 0.5, 1.0);
+                colors.extend_from_slice(&[color, color, color]);
+            }
+        }
+        (positions, colors)
+    }
+
+    fn draw(threshold: usize, multithreading: bool) -> (TiledBuffer<u32, 64, 64>, RasterizerStatistics) {
+        let (positions, colors) = grid();
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(64, 64);
+        depth_buffer.fill(u16::MAX);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.set_multithreading(multithreading);
+        rasterizer.set_tile_splitting_threshold(threshold);
+        rasterizer.setup(Viewport::new(0, 0, 64, 64));
+        rasterizer.commit(&RasterizationCommand { world_positions: &positions, colors: &colors, ..Default::default() });
+        rasterizer.commit_fill(&FillCommand {
+            min: Vec2::new(20.0, 20.0),
+            max: Vec2::new(44.0, 44.0),
+            paint: FillPaint::Solid(Vec4::new(1.0, 1.0, 1.0, 0.5)),
+            alpha_blending: AlphaBlendingMode::Normal,
+            ..Default::default()
+        });
+        rasterizer.commit(&RasterizationCommand { world_positions: &positions, colors: &colors, ..Default::default() });
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: Some(&mut depth_buffer),
+            ..Default::default()
+        });
+        (color_buffer, rasterizer.statistics())
+    }
+
+    #[test]
+    fn split_tile_matches_whole_tile() {
+        let (whole, whole_stats) = draw(usize::MAX, true);
+        assert_eq!(whole_stats.split_tiles, 0);
+        for multithreading in [true, false] {
+            let (split, split_stats) = draw(64, multithreading);
+            assert_eq!(split_stats.split_tiles, 1);
+            assert_eq!(split_stats.binned_triangles, whole_stats.binned_triangles);
+            assert_eq!(split_stats.fragments_drawn, whole_stats.fragments_drawn);
+            assert_eq!(split.as_flat_buffer().elems, whole.as_flat_buffer().elems);
+        }
+    }
+
+    #[test]
+    fn tiles_below_threshold_are_not_split() {
+        let (_, stats) = draw(Rasterizer::DEFAULT_TILE_SPLITTING_THRESHOLD, true);
+        assert!(stats.binned_triangles < Rasterizer::DEFAULT_TILE_SPLITTING_THRESHOLD);
+        assert_eq!(stats.split_tiles, 0);
+    }
+
+    #[test]
+    fn split_tiles_are_deterministic() {
+        let (positions, colors) = grid();
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(150, 100);
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(150, 100);
+        depth_buffer.fill(u16::MAX);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.set_tile_splitting_threshold(16);
+        rasterizer.setup(Viewport::new(10, 5, 140, 100));
+        rasterizer.commit(&RasterizationCommand { world_positions: &positions, colors: &colors, ..Default::default() });
+        let mismatches = rasterizer.draw_checking_determinism(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: Some(&mut depth_buffer),
+            ..Default::default()
+        });
+        assert!(mismatches.is_empty(), "{:?}", mismatches);
+        assert!(rasterizer.statistics().split_tiles > 0);
+    }
+}
+
+#[cfg(test)]
+mod tests_lightmap {
+    use super::*;
+    use std::sync::Arc;
+
+    // A texture of the given size with each mip level filled with its own gray
+    fn gray_levels(size: u32, levels: &[u8]) -> Arc<Texture> {
+        let level = |n: usize| -> Vec<u8> {
+            let side: usize = (size >> n).max(1) as usize;
+            vec![levels[n.min(levels.len() - 1)]; side * side * 3]
+        };
+        let mips: Vec<Vec<u8>> = (1..=size.trailing_zeros() as usize).map(level).collect();
+        let mip_refs: Vec<&[u8]> = mips.iter().map(|mip| mip.as_slice()).collect();
+        Texture::new(&TextureSource {
+            texels: &level(0),
+            width: size,
+            height: size,
+            format: TextureFormat::RGB,
+            mips: &mip_refs,
+            ..Default::default()
+        })
+    }
+
+    fn solid(color: RGBA) -> Arc<Texture> {
+        let texels: [u8; 3] = [color.r, color.g, color.b];
+        Texture::new(&TextureSource {
+            texels: &texels,
+            width: 1,
+            height: 1,
+            format: TextureFormat::RGB,
+            ..Default::default()
+        })
+    }
+
+    // Draws a quad covering the 8x8 viewport with the texture coordinates spanning [0, 1] and the secondary ones
+    // spanning [0, span2], returns the center pixel
+    fn draw(texture: Option<Arc<Texture>>, lightmap: Option<Arc<Texture>>, color: Vec4, span2: f32) -> RGBA {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(8u16, 8u16);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let positions: [Vec3; 4] = [
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(-1.0, 1.0, 0.0),
+        ];
+        let tex_coords: [Vec2; 4] =
+            [Vec2::new(0.0, 1.0), Vec2::new(1.0, 1.0), Vec2::new(1.0, 0.0), Vec2::new(0.0, 0.0)];
+        let tex_coords2: [Vec2; 4] = tex_coords.map(|tc| tc * span2);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 8u16, 8u16));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &positions,
+            tex_coords: &tex_coords,
+            tex_coords2: &tex_coords2,
+            indices: &[0, 1, 2, 0, 2, 3],
+            color,
+            texture,
+            lightmap,
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        RGBA::from_u32(color_buffer.at(4, 4))
+    }
+
+    const WHITE: Vec4 = Vec4::new(1.0, 1.0, 1.0, 1.0);
+
+    #[test]
+    fn lightmap_modulates_texel() {
+        let texture = solid(RGBA::new(200, 100, 50, 255));
+        let lightmap = solid(RGBA::new(128, 255, 0, 255));
+        assert_rgba_eq!(draw(Some(texture), Some(lightmap), WHITE, 1.0), RGBA::new(100, 100, 0, 255), 1);
+    }
+
+    #[test]
+    fn lightmap_without_texture_modulates_color() {
+        let lightmap = solid(RGBA::new(128, 128, 128, 255));
+        assert_rgba_eq!(
+            draw(None, Some(lightmap), Vec4::new(1.0, 0.5, 1.0, 1.0), 1.0),
+            RGBA::new(128, 64, 128, 255),
+            1
+        );
+    }
+
+    #[test]
+    fn no_lightmap() {
+        let texture = solid(RGBA::new(200, 100, 50, 255));
+        assert_rgba_eq!(draw(Some(texture), None, WHITE, 1.0), RGBA::new(200, 100, 50, 255), 0);
+    }
+
+    #[test]
+    fn lightmap_mip_follows_secondary_coordinates() {
+        // The texture is minified 2 times, while the half of the lightmap covering the quad is sampled at its top level
+        let texture = gray_levels(16, &[255, 255, 0]);
+        let lightmap = gray_levels(16, &[255, 128, 0]);
+        assert_rgba_eq!(draw(Some(texture), Some(lightmap), WHITE, 0.5), RGBA::new(255, 255, 255, 255), 1);
+
+        // The texture is sampled at its top level, while the lightmap is minified 2 times
+        let texture = gray_levels(8, &[255, 0]);
+        let lightmap = gray_levels(8, &[255, 128, 0]);
+        assert_rgba_eq!(draw(Some(texture), Some(lightmap), WHITE, 2.0), RGBA::new(128, 128, 128, 255), 1);
+    }
+}
+
+#[cfg(test)]
+mod tests_render_to_texture {
+    use super::*;
+    use std::sync::Arc;
+
+    // Draws a quad covering the viewport with the texture coordinates of its corners, counter-clockwise from the
+    // bottom-left one
+    fn draw_quad(
+        target: &mut TiledBuffer<u32, 64, 64>,
+        color: Vec4,
+        texture: Option<Arc<Texture>>,
+        tex_coords: [Vec2; 4],
+    ) {
+        let positions: [Vec3; 4] = [
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(-1.0, 1.0, 0.0),
+        ];
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, target.width(), target.height()));
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &positions,
+            tex_coords: &tex_coords,
+            indices: &[0, 1, 2, 0, 2, 3],
+            color,
+            texture,
+            ..Default::default()
+        });
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(target), ..Default::default() });
+    }
+
+    #[test]
+    fn second_pass_samples_mirrored_first_pass() {
+        // The first pass: red on the left, green on the right
+        let mut first = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        first.fill(RGBA::new(0, 255, 0, 255).to_u32());
+        let mut left = TiledBuffer::<u32, 64, 64>::new(32, 64);
+        draw_quad(&mut left, Vec4::new(1.0, 0.0, 0.0, 1.0), None, [Vec2::new(0.0, 0.0); 4]);
+        for y in 0..64 {
+            for x in 0..32 {
+                *first.at_mut(x, y) = left.at(x, y);
+            }
+        }
+        let texture: Arc<Texture> = Texture::from_color_buffer(&first, MipGeneration::Box).unwrap();
+
+        // The second pass mirrors it horizontally, like a mirror does
+        let mirrored: [Vec2; 4] = [Vec2::new(1.0, 1.0), Vec2::new(0.0, 1.0), Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0)];
+        let mut second = TiledBuffer::<u32, 64, 64>::new(64, 64);
+        draw_quad(&mut second, Vec4::new(1.0, 1.0, 1.0, 1.0), Some(texture.clone()), mirrored);
+        assert_rgba_eq!(RGBA::from_u32(second.at(8, 32)), RGBA::new(0, 255, 0, 255), 1);
+        assert_rgba_eq!(RGBA::from_u32(second.at(56, 32)), RGBA::new(255, 0, 0, 255), 1);
+
+        // The next frame of the first pass updates the same texture once the second pass has released it
+        let mut texture: Texture = Arc::into_inner(texture).unwrap();
+        first.fill(RGBA::new(0, 0, 255, 255).to_u32());
+        texture.update_from_color_buffer(&first, MipGeneration::Box).unwrap();
+        draw_quad(&mut second, Vec4::new(1.0, 1.0, 1.0, 1.0), Some(Arc::new(texture)), mirrored);
+        assert_rgba_eq!(RGBA::from_u32(second.at(8, 32)), RGBA::new(0, 0, 255, 255), 1);
+    }
+}
+
+#[cfg(test)]
+mod tests_decal {
+    use super::*;
+    use std::sync::Arc;
+
+    const GRAY: RGBA = RGBA { r: 128, g: 128, b: 128, a: 255 };
+    const RED: RGBA = RGBA { r: 255, g: 0, b: 0, a: 255 };
+
+    struct Buffers {
+        color: TiledBuffer<u32, 64, 64>,
+        depth: TiledBuffer<u16, 64, 64>,
+        normals: TiledBuffer<u32, 64, 64>,
+    }
+
+    impl Buffers {
+        fn new() -> Self {
+            let mut buffers = Buffers {
+                color: TiledBuffer::<u32, 64, 64>::new(16, 16),
+                depth: TiledBuffer::<u16, 64, 64>::new(16, 16),
+                normals: TiledBuffer::<u32, 64, 64>::new(16, 16),
+            };
+            buffers.color.fill(RGBA::new(0, 0, 0, 255).to_u32());
+            buffers.depth.fill(DEPTH_FAR);
+            buffers.normals.fill(NORMAL_NONE.to_u32());
+            buffers
+        }
+
+        fn framebuffer(&mut self) -> Framebuffer<'_> {
+            Framebuffer {
+                color_buffer: Some(&mut self.color),
+                depth_buffer: Some(&mut self.depth),
+                normal_buffer: Some(&mut self.normals),
+                ..Default::default()
+            }
+        }
+
+        fn at(&self, x: u16, y: u16) -> RGBA {
+            RGBA::from_u32(self.color.at(x, y))
+        }
+    }
+
+    // Commits a gray quad covering the viewport at the given depth, with the given normal
+    fn commit_quad(rasterizer: &mut Rasterizer, z: f32, normal: Vec3, color: Vec4) {
+        let positions: [Vec3; 4] =
+            [Vec3::new(-1.0, -1.0, z), Vec3::new(1.0, -1.0, z), Vec3::new(1.0, 1.0, z), Vec3::new(-1.0, 1.0, z)];
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &positions,
+            normals: &[normal; 4],
+            indices: &[0, 1, 2, 0, 2, 3],
+            color,
+            ..Default::default()
+        });
+    }
+
+    fn draw(z: f32, normal: Vec3, decal: &DecalCommand) -> Buffers {
+        let mut buffers = Buffers::new();
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 16, 16));
+        commit_quad(&mut rasterizer, z, normal, Vec4::new(0.5, 0.5, 0.5, 1.0));
+        rasterizer.commit_decal(decal);
+        rasterizer.draw(&mut buffers.framebuffer());
+        buffers
+    }
+
+    fn red_decal(projector: Mat44) -> DecalCommand {
+        DecalCommand { projector, color: Vec4::new(1.0, 0.0, 0.0, 1.0), ..Default::default() }
+    }
+
+    #[test]
+    fn paints_only_inside_the_box() {
+        let buffers = draw(0.0, Vec3::new(0.0, 0.0, -1.0), &red_decal(Mat44::scale_uniform(4.0)));
+        assert_rgba_eq!(buffers.at(7, 7), RED, 1);
+        assert_rgba_eq!(buffers.at(8, 8), RED, 1);
+        assert_rgba_eq!(buffers.at(5, 8), GRAY, 1);
+        assert_rgba_eq!(buffers.at(8, 10), GRAY, 1);
+        assert_rgba_eq!(buffers.at(0, 0), GRAY, 1);
+    }
+
+    #[test]
+    fn skips_pixels_without_geometry() {
+        let mut buffers = Buffers::new();
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 16, 16));
+        rasterizer.commit_decal(&red_decal(Mat44::identity()));
+        rasterizer.draw(&mut buffers.framebuffer());
+        assert_rgba_eq!(buffers.at(8, 8), RGBA::new(0, 0, 0, 255), 0);
+    }
+
+    #[test]
+    fn skips_surfaces_outside_the_depth_range() {
+        let projector: Mat44 = Mat44::scale_non_uniform(Vec3::new(1.0, 1.0, 4.0));
+        let inside = draw(0.0, Vec3::new(0.0, 0.0, -1.0), &red_decal(projector));
+        assert_rgba_eq!(inside.at(8, 8), RED, 1);
+        let behind = draw(0.8, Vec3::new(0.0, 0.0, -1.0), &red_decal(projector));
+        assert_rgba_eq!(behind.at(8, 8), GRAY, 1);
+    }
+
+    #[test]
+    fn rejects_surfaces_facing_away() {
+        let facing_away = draw(0.0, Vec3::new(0.0, 0.0, 1.0), &red_decal(Mat44::identity()));
+        assert_rgba_eq!(facing_away.at(8, 8), GRAY, 1);
+        let perpendicular = draw(0.0, Vec3::new(1.0, 0.0, 0.0), &red_decal(Mat44::identity()));
+        assert_rgba_eq!(perpendicular.at(8, 8), RED, 1);
+        let strict =
+            draw(0.0, Vec3::new(1.0, 0.0, 0.0), &DecalCommand { min_facing: 0.5, ..red_decal(Mat44::identity()) });
+        assert_rgba_eq!(strict.at(8, 8), GRAY, 1);
+        let disabled =
+            draw(0.0, Vec3::new(0.0, 0.0, 1.0), &DecalCommand { min_facing: -1.0, ..red_decal(Mat44::identity()) });
+        assert_rgba_eq!(disabled.at(8, 8), RED, 1);
+    }
+
+    #[test]
+    fn maps_texture_from_top_left() {
+        let texels: [u8; 12] = [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+        let texture: Arc<Texture> = Texture::new(&TextureSource {
+            texels: &texels,
+            width: 2,
+            height: 2,
+            format: TextureFormat::RGB,
+            ..Default::default()
+        });
+        let decal =
+            DecalCommand { texture: Some(texture), sampling_filter: SamplerFilter::Nearest, ..Default::default() };
+        let buffers = draw(0.0, Vec3::new(0.0, 0.0, -1.0), &decal);
+        assert_rgba_eq!(buffers.at(2, 2), RGBA::new(255, 0, 0, 255), 1);
+        assert_rgba_eq!(buffers.at(13, 2), RGBA::new(0, 255, 0, 255), 1);
+        assert_rgba_eq!(buffers.at(2, 13), RGBA::new(0, 0, 255, 255), 1);
+        assert_rgba_eq!(buffers.at(13, 13), RGBA::new(255, 255, 255, 255), 1);
+    }
+
+    #[test]
+    fn blends_with_alpha() {
+        let decal = DecalCommand { color: Vec4::new(1.0, 0.0, 0.0, 0.5), ..Default::default() };
+        let buffers = draw(0.0, Vec3::new(0.0, 0.0, -1.0), &decal);
+        assert_rgba_eq!(buffers.at(8, 8), RGBA::new(191, 64, 64, 255), 2);
+    }
+
+    #[test]
+    fn is_ordered_with_other_commands() {
+        let mut buffers = Buffers::new();
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 16, 16));
+        commit_quad(&mut rasterizer, 0.0, Vec3::new(0.0, 0.0, -1.0), Vec4::new(0.5, 0.5, 0.5, 1.0));
+        rasterizer.commit_decal(&red_decal(Mat44::identity()));
+        commit_quad(&mut rasterizer, -0.5, Vec3::new(0.0, 0.0, -1.0), Vec4::new(0.0, 0.0, 1.0, 1.0));
+        rasterizer.draw(&mut buffers.framebuffer());
+        assert_rgba_eq!(buffers.at(8, 8), RGBA::new(0, 0, 255, 255), 1);
+    }
+
+    #[test]
+    fn works_with_float_depth() {
+        let mut color = TiledBuffer::<u32, 64, 64>::new(16, 16);
+        let mut depth = TiledBuffer::<f32, 64, 64>::new(16, 16);
+        color.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        depth.fill(DEPTH_F32_FAR);
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 16, 16));
+        commit_quad(&mut rasterizer, 0.0, Vec3::new(0.0, 0.0, -1.0), Vec4::new(0.5, 0.5, 0.5, 1.0));
+        rasterizer.commit_decal(&red_decal(Mat44::scale_uniform(4.0)));
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut color),
+            depth_buffer_f32: Some(&mut depth),
+            ..Default::default()
+        });
+        assert_rgba_eq!(RGBA::from_u32(color.at(8, 8)), RED, 1);
+        assert_rgba_eq!(RGBA::from_u32(color.at(0, 0)), GRAY, 1);
+    }
+
+    #[test]
+    fn is_projected_into_each_view() {
+        let mut buffers = Buffers::new();
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.set_retain_geometry(true);
+        rasterizer.setup(Viewport::new(0, 0, 16, 16));
+        commit_quad(&mut rasterizer, 0.0, Vec3::new(0.0, 0.0, -1.0), Vec4::new(0.5, 0.5, 0.5, 1.0));
+        rasterizer.commit_decal(&red_decal(Mat44::scale_uniform(4.0)));
+        let mut views = [
+            ViewTarget::new(Viewport::new(0, 0, 8, 16), Mat44::identity(), Mat44::identity()),
+            ViewTarget::new(Viewport::new(8, 0, 16, 16), Mat44::identity(), Mat44::identity()),
+        ];
+        rasterizer.draw_views(&mut buffers.framebuffer(), &mut views);
+        assert_rgba_eq!(buffers.at(3, 8), RED, 1);
+        assert_rgba_eq!(buffers.at(11, 8), RED, 1);
+        assert_rgba_eq!(buffers.at(0, 8), GRAY, 1);
+        assert_rgba_eq!(buffers.at(8, 8), GRAY, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests_allocations {
+    use super::*;
+
+    // Draws a frame of the given number of quads spread over a 256x256 viewport, returns its statistics
+    fn draw_frame(rasterizer: &mut Rasterizer, quads: usize) -> RasterizerStatistics {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(256, 256);
+        let mut depth_buffer = TiledBuffer::<u16, 64, 64>::new(256, 256);
+        depth_buffer.fill(DEPTH_FAR);
+        rasterizer.setup(Viewport::new(0, 0, 256, 256));
+        for i in 0..quads {
+            let x: f32 = (i % 8) as f32 * 0.25 - 1.0;
+            let y: f32 = (i / 8 % 8) as f32 * 0.25 - 1.0;
+            let positions: [Vec3; 4] = [
+                Vec3::new(x, y, 0.0),
+                Vec3::new(x + 0.2, y, 0.0),
+                Vec3::new(x + 0.2, y + 0.2, 0.0),
+                Vec3::new(x, y + 0.2, 0.0),
+            ];
+            rasterizer.commit(&RasterizationCommand {
+                world_positions: &positions,
+                indices: &[0, 1, 2, 0, 2, 3],
+                color: Vec4::new(1.0, 0.0, 0.0, 1.0),
+                ..Default::default()
+            });
+        }
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut color_buffer),
+            depth_buffer: Some(&mut depth_buffer),
+            ..Default::default()
+        });
+        rasterizer.statistics()
+    }
+
+    #[test]
+    fn first_frame_allocates() {
+        let mut rasterizer = Rasterizer::new();
+        let stats: RasterizerStatistics = draw_frame(&mut rasterizer, 16);
+        assert!(stats.allocations > 0);
+        assert!(stats.allocated_bytes > 0);
+        assert_eq!(stats.reserved_bytes, stats.allocated_bytes);
+    }
+
+    #[test]
+    fn steady_state_doesnt_allocate() {
+        for multithreading in [false, true] {
+            let mut rasterizer = Rasterizer::new();
+            rasterizer.set_multithreading(multithreading);
+            let warm_up: RasterizerStatistics = draw_frame(&mut rasterizer, 64);
+            for _ in 0..3 {
+                let stats: RasterizerStatistics = draw_frame(&mut rasterizer, 64);
+                assert_eq!(stats.allocations, 0);
+                assert_eq!(stats.allocated_bytes, 0);
+                assert_eq!(stats.reserved_bytes, warm_up.reserved_bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn smaller_frame_keeps_high_water_mark() {
+        let mut rasterizer = Rasterizer::new();
+        let large: RasterizerStatistics = draw_frame(&mut rasterizer, 64);
+        let small: RasterizerStatistics = draw_frame(&mut rasterizer, 4);
+        assert_eq!(small.allocations, 0);
+        assert_eq!(small.reserved_bytes, large.reserved_bytes);
+    }
+
+    #[test]
+    fn larger_frame_allocates_again() {
+        let mut rasterizer = Rasterizer::new();
+        let small: RasterizerStatistics = draw_frame(&mut rasterizer, 4);
+        let large: RasterizerStatistics = draw_frame(&mut rasterizer, 64);
+        assert!(large.allocations > 0);
+        assert_eq!(large.reserved_bytes, small.reserved_bytes + large.allocated_bytes);
+    }
+
+    #[test]
+    fn split_tiles_allocate_temporary_buffers() {
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.set_tile_splitting_threshold(1);
+        draw_frame(&mut rasterizer, 64);
+        let stats: RasterizerStatistics = draw_frame(&mut rasterizer, 64);
+        assert!(stats.split_tiles > 0);
+        assert!(stats.allocations > 0);
+    }
+}
+
+#[cfg(test)]
+mod tests_deterministic_scheduling {
+    use super::*;
+    use nih::testing::*;
+
+    // A large command of overlapping depth-tested triangles, enough to be committed in chunks and to overload the tiles
+    fn commit_triangles(rasterizer: &mut Rasterizer) {
+        let mut positions: Vec<Vec3> = Vec::new();
+        let mut colors: Vec<Vec4> = Vec::new();
+        for i in 0..6000 {
+            let (x, y) = ((i % 77) as f32 / 40.0 - 0.95, (i / 77) as f32 / 40.0 - 0.95);
+            let z: f32 = ((i * 37) % 101) as f32 / 101.0 - 0.5;
+            positions.extend_from_slice(&[Vec3::new(x, y, z), Vec3::new(x + 0.1, y, z), Vec3::new(x, y + 0.1, z)]);
+            let color = Vec4::new(x * 0.5 + 0.5, y * 0.5 + 0.5, z + 0.5, 1.0);
+            colors.extend_from_slice(&[color, color, color]);
+        }
+        rasterizer.commit(&RasterizationCommand { world_positions: &positions, colors: &colors, ..Default::default() });
+    }
+
+    fn options() -> CaptureOptions {
+        CaptureOptions { depth: true, ..CaptureOptions::new(200, 150) }
+    }
+
+    #[test]
+    fn statistics_dont_depend_on_multithreading() {
+        let mut single = Rasterizer::new();
+        single.set_multithreading(false);
+        single.set_deterministic_scheduling(true);
+        single.set_tile_splitting_threshold(64);
+        let expected_frame: CapturedFrame = capture_frame_with(&mut single, &options(), commit_triangles);
+        let expected: RasterizerStatistics = single.statistics();
+        assert!(expected.split_tiles > 0);
+        // The fragments are counted only in Debug builds
+        assert_eq!(expected.fragments_drawn > 0, cfg!(debug_assertions));
+
+        for _ in 0..3 {
+            let mut parallel = Rasterizer::new();
+            parallel.set_deterministic_scheduling(true);
+            parallel.set_tile_splitting_threshold(64);
+            let frame: CapturedFrame = capture_frame_with(&mut parallel, &options(), commit_triangles);
+            assert_eq!(parallel.statistics(), expected);
+            assert_eq!(frame.color.unwrap().elems, expected_frame.color.as_ref().unwrap().elems);
+        }
+    }
+
+    #[test]
+    fn repeated_frames_have_same_statistics() {
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.set_deterministic_scheduling(true);
+        capture_frame_with(&mut rasterizer, &options(), commit_triangles);
+        capture_frame_with(&mut rasterizer, &options(), commit_triangles);
+        let first: RasterizerStatistics = rasterizer.statistics();
+        capture_frame_with(&mut rasterizer, &options(), commit_triangles);
+        assert_eq!(rasterizer.statistics(), first);
+    }
+}
+
+#[cfg(test)]
+mod tests_recording {
+    use super::*;
+    use nih::testing::*;
+    use std::sync::Arc;
+
+    fn gradient_texture() -> Arc<Texture> {
+        let texels: Vec<u8> = (0..16 * 16)
+            .flat_map(|i| [(i % 16 * 16) as u8, (i / 16 * 16) as u8, 128])
+            .collect();
+        Texture::new(&TextureSource {
+            texels: &texels,
+            width: 16,
+            height: 16,
+            format: TextureFormat::RGB,
+            ..Default::default()
+        })
+    }
+
+    // Commits a frame of a textured instanced quad over a fill and a translucent fullscreen tint
+    fn commit_frame(rasterizer: &mut Rasterizer, texture: &Arc<Texture>) {
+        let positions: [Vec3; 4] = [
+            Vec3::new(-0.5, -0.5, 0.0),
+            Vec3::new(0.5, -0.5, 0.0),
+            Vec3::new(0.5, 0.5, 0.0),
+            Vec3::new(-0.5, 0.5, 0.0),
+        ];
+        let tex_coords: [Vec2; 4] =
+            [Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), Vec2::new(1.0, 1.0), Vec2::new(0.0, 1.0)];
+        let instance_models: [Mat34; 2] =
+            [Mat34::translate(Vec3::new(-0.4, 0.0, 0.0)), Mat34::translate(Vec3::new(0.4, 0.2, 0.0))];
+        rasterizer.commit_fill(&FillCommand {
+            min: Vec2::new(8.0, 8.0),
+            max: Vec2::new(88.0, 56.0),
+            corner_radius: 6.0,
+            paint: FillPaint::Solid(Vec4::new(0.2, 0.4, 0.2, 1.0)),
+            ..Default::default()
+        });
+        rasterizer.commit(&RasterizationCommand {
+            world_positions: &positions,
+            tex_coords: &tex_coords,
+            indices: &[0, 1, 2, 0, 2, 3],
+            texture: Some(texture.clone()),
+            sampling_filter: SamplerFilter::Bilinear,
+            instance_models: &instance_models,
+            ..Default::default()
+        });
+        rasterizer.commit_fullscreen(&FullscreenCommand {
+            color: Vec4::new(1.0, 0.0, 0.0, 0.25),
+            texture: None,
+            sampling_filter: SamplerFilter::Nearest,
+            alpha_blending: AlphaBlendingMode::Normal,
+        });
+    }
+
+    // The colors of the recorded frame committed anew
+    fn replay(frame: &RecordedFrame, textures: &[Arc<Texture>]) -> Vec<u32> {
+        let options = CaptureOptions::new(96, 64);
+        let replayed = capture_frame(&options, |rasterizer| frame.commit_to(rasterizer, textures).unwrap());
+        replayed.color.unwrap().elems
+    }
+
+    #[test]
+    fn replay_matches_recorded_frame() {
+        let texture: Arc<Texture> = gradient_texture();
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.set_recording(true);
+        let options = CaptureOptions::new(96, 64);
+        let commit = |rasterizer: &mut Rasterizer| commit_frame(rasterizer, &texture);
+        let expected: Vec<u32> = capture_frame_with(&mut rasterizer, &options, commit)
+            .color
+            .unwrap()
+            .elems;
+        let frame: RecordedFrame = rasterizer.take_recorded_frame().unwrap();
+        // The instances are recorded as the separate commands
+        assert_eq!(frame.commands_num(), 4);
+        assert_eq!((frame.skipped_commands, frame.incomplete_commands), (0, 0));
+
+        let textures: Vec<Arc<Texture>> = frame.textures().unwrap();
+        assert!(Arc::ptr_eq(&textures[0], &texture));
+        assert_eq!(replay(&frame, &textures), expected);
+
+        // The loaded frame needs the textures to be supplied
+        let loaded: RecordedFrame = RecordedFrame::from_bytes(&frame.to_bytes()).unwrap();
+        assert!(loaded.textures().is_none());
+        assert_eq!(replay(&loaded, &[texture]), expected);
+    }
+
+    #[test]
+    fn recording_starts_over_with_setup() {
+        let texture: Arc<Texture> = gradient_texture();
+        let mut rasterizer = Rasterizer::new();
+        assert!(rasterizer.take_recorded_frame().is_none());
+        rasterizer.set_recording(true);
+        for _ in 0..2 {
+            rasterizer.setup(Viewport::new(0, 0, 96, 64));
+            commit_frame(&mut rasterizer, &texture);
+        }
+        rasterizer.commit_stroke(&StrokeCommand {
+            path: &Path::from_polyline(&[Vec2::new(0.0, 0.0), Vec2::new(50.0, 50.0)], false),
+            ..Default::default()
+        });
+        let frame: RecordedFrame = rasterizer.take_recorded_frame().unwrap();
+        assert_eq!(frame.viewport, Viewport::new(0, 0, 96, 64));
+        assert_eq!(frame.commands_num(), 4);
+        assert_eq!(frame.skipped_commands, 1);
+        assert_eq!(rasterizer.take_recorded_frame().unwrap().commands_num(), 0);
+    }
+
+    #[test]
+    fn replay_validates_textures() {
+        let texture: Arc<Texture> = gradient_texture();
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.set_recording(true);
+        rasterizer.setup(Viewport::new(0, 0, 96, 64));
+        commit_frame(&mut rasterizer, &texture);
+        let frame: RecordedFrame = rasterizer.take_recorded_frame().unwrap();
+
+        let mut replayed = Rasterizer::new();
+        assert_eq!(frame.commit_to(&mut replayed, &[]), Err(RecordingError::MissingTexture(0)));
+        let other = Texture::new(&TextureSource {
+            texels: &[255; 4 * 4],
+            width: 4,
+            height: 4,
+            format: TextureFormat::Grayscale,
+            ..Default::default()
+        });
+        assert_eq!(frame.commit_to(&mut replayed, &[other]), Err(RecordingError::TextureMismatch(0)));
+    }
+}
+
+#[cfg(test)]
+mod tests_frame_graph {
+    use super::*;
+    use nih::testing::*;
+
+    // Two overlapping triangles, the green one is nearer and is committed last
+    fn commit_scene(rasterizer: &mut Rasterizer, prepass: bool) {
+        let triangles: [([Vec3; 3], Vec4); 2] = [
+            (
+                [Vec3::new(-0.9, -0.9, 0.5), Vec3::new(0.6, -0.7, 0.5), Vec3::new(-0.2, 0.9, 0.5)],
+                Vec4::new(1.0, 0.0, 0.0, 1.0),
+            ),
+            (
+                [Vec3::new(-0.4, -0.8, -0.5), Vec3::new(0.9, 0.0, -0.5), Vec3::new(0.1, 0.8, -0.5)],
+                Vec4::new(0.0, 1.0, 0.0, 1.0),
+            ),
+        ];
+        for (world_positions, color) in &triangles {
+            rasterizer.commit(&RasterizationCommand {
+                world_positions,
+                color: *color,
+                color_write_mask: if prepass {
+                    ColorWriteMask::NONE
+                } else {
+                    ColorWriteMask::default()
+                },
+                depth_test: if prepass { DepthTest::Less } else { DepthTest::LEqual },
+                depth_write: prepass,
+                ..Default::default()
+            });
+        }
+    }
+
+    // The passes composed by hand: the prepass and the main pass drawn as a single frame, then the FXAA over its colors
+    fn draw_by_hand() -> Vec<u32> {
+        let options = CaptureOptions { depth: true, ..CaptureOptions::new(100, 70) };
+        let frame: CapturedFrame = capture_frame(&options, |rasterizer| {
+            commit_scene(rasterizer, true);
+            commit_scene(rasterizer, false);
+        });
+        let mut color = TiledBuffer::<u32, 64, 64>::from_flat_buffer(&frame.color.unwrap());
+        let mut chain = PostProcessChain::new();
+        chain.add(Fxaa::default());
+        chain.run(&mut Framebuffer { color_buffer: Some(&mut color), ..Default::default() });
+        color.as_flat_buffer().elems
+    }
+
+    #[test]
+    fn graph_matches_passes_composed_by_hand() {
+        let mut graph = FrameGraph::new(100, 70);
+        let color: GraphBufferId = graph.create_buffer(GraphBufferKind::Color(ColorBufferFormat::Rgba8), true);
+        let depth: GraphBufferId = graph.create_buffer(GraphBufferKind::Depth, true);
+        let (mut prepass_rasterizer, mut main_rasterizer) = (Rasterizer::new(), Rasterizer::new());
+        let mut passes = vec![
+            GraphPass::draw(
+                "prepass",
+                GraphTarget { depth: Some(depth), ..Default::default() },
+                &[],
+                |framebuffer, _| {
+                    prepass_rasterizer.setup(Viewport::new(0, 0, 100, 70));
+                    commit_scene(&mut prepass_rasterizer, true);
+                    prepass_rasterizer.draw(framebuffer);
+                },
+            ),
+            GraphPass::draw(
+                "main",
+                GraphTarget { color: Some(color), depth: Some(depth), ..Default::default() },
+                &[],
+                |framebuffer, _| {
+                    main_rasterizer.setup(Viewport::new(0, 0, 100, 70));
+                    commit_scene(&mut main_rasterizer, false);
+                    main_rasterizer.draw(framebuffer);
+                },
+            ),
+            GraphPass::tiles("fxaa", GraphTarget { color: Some(color), ..Default::default() }, Fxaa::default()),
+        ];
+        assert_eq!(graph.schedule(&passes), Ok(vec![vec![0], vec![1], vec![2]]));
+        let expected: Vec<u32> = draw_by_hand();
+        for _ in 0..2 {
+            graph.execute(&mut passes).unwrap();
+            assert_eq!(graph.buffer_u32(color).as_flat_buffer().elems, expected);
+        }
+        drop(passes);
+        // Both triangles are visible, the green one is in front
+        let colors: Vec<RGBA> = graph
+            .buffer_u32(color)
+            .as_flat_buffer()
+            .elems
+            .iter()
+            .map(|&c| RGBA::from_u32(c))
+            .collect();
+        assert!(colors.contains(&RGBA::new(255, 0, 0, 255)));
+        assert!(colors.contains(&RGBA::new(0, 255, 0, 255)));
+        assert_eq!(RGBA::from_u32(graph.buffer_u32(color).at(55, 35)), RGBA::new(0, 255, 0, 255));
+    }
+}
+
+#[cfg(test)]
+mod tests_pipelined {
+    use super::*;
+    use nih::testing::*;
+
+    // A grid of quads moving to the right with the frame's number
+    fn commit_frame(rasterizer: &mut Rasterizer, frame: usize) {
+        for i in 0..64 {
+            let x: f32 = -0.9 + (i % 8) as f32 * 0.22 + frame as f32 * 0.01;
+            let y: f32 = -0.9 + (i / 8) as f32 * 0.22;
+            rasterizer.commit(&RasterizationCommand {
+                world_positions: &[
+                    Vec3::new(x, y, 0.0),
+                    Vec3::new(x + 0.15, y, 0.0),
+                    Vec3::new(x + 0.15, y + 0.15, 0.0),
+                    Vec3::new(x, y + 0.15, 0.0),
+                ],
+                indices: &[0, 1, 2, 0, 2, 3],
+                color: Vec4::new(i as f32 / 64.0, 1.0 - i as f32 / 64.0, frame as f32 * 0.1, 1.0),
+                ..Default::default()
+            });
+        }
+    }
+
+    #[test]
+    fn frames_are_drawn_while_next_ones_are_committed() {
+        let mut pipeline = PipelinedRasterizer::new();
+        // Two sets of targets, one is drawn into while the other one is presented
+        let mut spare: Vec<FrameTargets> = (0..2)
+            .map(|_| FrameTargets {
+                color_buffer: Some(TiledBuffer::new(160, 120)),
+                depth_buffer: Some(TiledBuffer::new(160, 120)),
+                ..Default::default()
+            })
+            .collect();
+        let mut presented: Vec<(Buffer<u32>, RasterizerStatistics)> = Vec::new();
+        for frame in 0..6 {
+            pipeline.rasterizer().setup(Viewport::new(0, 0, 160, 120));
+            commit_frame(pipeline.rasterizer(), frame);
+            if let Some(drawn) = pipeline.sync() {
+                presented.push((drawn.targets.color_buffer.as_ref().unwrap().as_flat_buffer(), drawn.statistics));
+                spare.push(drawn.targets);
+            }
+            let mut targets: FrameTargets = spare.remove(0);
+            targets.framebuffer().clear_all(&ClearValues::default());
+            pipeline.submit(targets);
+            assert!(pipeline.is_drawing());
+        }
+        let drawn: DrawnFrame = pipeline.sync().unwrap();
+        presented.push((drawn.targets.color_buffer.unwrap().as_flat_buffer(), drawn.statistics));
+        assert!(!pipeline.is_drawing());
+
+        assert_eq!(presented.len(), 6);
+        let options = CaptureOptions { depth: true, ..CaptureOptions::new(160, 120) };
+        for (frame, (color, statistics)) in presented.iter().enumerate() {
+            let mut rasterizer = Rasterizer::new();
+            let expected: CapturedFrame =
+                capture_frame_with(&mut rasterizer, &options, |rasterizer| commit_frame(rasterizer, frame));
+            assert_eq!(color.elems, expected.color.unwrap().elems);
+            // The pipeline's rasterizers are reused, so their memory depends on the previous frames
+            let expected_statistics = RasterizerStatistics {
+                allocations: statistics.allocations,
+                allocated_bytes: statistics.allocated_bytes,
+                reserved_bytes: statistics.reserved_bytes,
+                ..rasterizer.statistics()
+            };
+            assert_eq!(*statistics, expected_statistics);
+            assert_eq!(statistics.committed_triangles, 128);
+        }
+    }
 }
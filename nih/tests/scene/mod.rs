@@ -0,0 +1,406 @@
+// A composed "sponza-like" scene shared by the scene tests and the benchmarks: a procedural atrium with a tiled floor,
+// colonnades, galleries, banners and crates, lit by the sun with a shadow map and the sky, drawn deferred into an HDR
+// buffer and resolved by tone mapping.
+// The frame is put together from the rasterizer's building blocks only, the same way an application would do it:
+//   1. the shadow pass renders the level's depth from the sun's point of view;
+//   2. the geometry pass draws the sky as a fullscreen fill and the level's albedo, normals and depth;
+//   3. the lighting pass shades every pixel into the RGB9E5 buffer, looking up the shadow map;
+//   4. the post pass tone-maps the HDR buffer into the displayable one.
+#![allow(dead_code)]
+
+use nih::math::*;
+use nih::render::*;
+use std::sync::Arc;
+
+pub const SHADOW_MAP_SIZE: u16 = 1024;
+
+// Indices of the materials referenced by the level's sections.
+const MATERIAL_FLOOR: usize = 0;
+const MATERIAL_STONE: usize = 1;
+const MATERIAL_BANNER: usize = 2;
+const MATERIAL_WOOD: usize = 3;
+
+pub struct Scene {
+    pub level: MeshData,
+    pub materials: Vec<Arc<Texture>>,
+    pub sky: Arc<Texture>,
+
+    // The direction the sunlight travels in, normalized.
+    pub sun_direction: Vec3,
+    pub sun_color: Vec3,
+    pub ambient_color: Vec3,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Camera {
+    pub position: Vec3,
+    pub target: Vec3,
+    pub fov_y: f32,
+}
+
+impl Camera {
+    // The fixed camera the golden images are taken from: at the atrium's entrance, looking along the colonnade.
+    pub fn fixed() -> Self {
+        Self {
+            position: Vec3::new(-2.0, 3.0, 13.0),
+            target: Vec3::new(1.5, 2.5, -6.0),
+            fov_y: std::f32::consts::PI / 3.0,
+        }
+    }
+}
+
+// The buffers of a frame, kept between the frames to avoid reallocations.
+pub struct Frame {
+    pub width: u16,
+    pub height: u16,
+    pub albedo: TiledBuffer<u32, 64, 64>,
+    pub normals: TiledBuffer<u32, 64, 64>,
+    pub depth: TiledBuffer<u16, 64, 64>,
+    pub hdr: TiledBuffer<u32, 64, 64>,
+    pub ldr: TiledBuffer<u32, 64, 64>,
+    pub shadow_map: TiledBuffer<u16, 64, 64>,
+    pub rasterizer: Rasterizer,
+}
+
+impl Frame {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            width,
+            height,
+            albedo: TiledBuffer::new(width, height),
+            normals: TiledBuffer::new(width, height),
+            depth: TiledBuffer::new(width, height),
+            hdr: TiledBuffer::new(width, height),
+            ldr: TiledBuffer::new(width, height),
+            shadow_map: TiledBuffer::new(SHADOW_MAP_SIZE, SHADOW_MAP_SIZE),
+            rasterizer: Rasterizer::new(),
+        }
+    }
+}
+
+// Builds the world-to-camera transform of a camera at the eye looking at the target, with +Y being up.
+pub fn look_at(eye: Vec3, target: Vec3) -> Mat44 {
+    let forward: Vec3 = (target - eye).normalized();
+    let right: Vec3 = cross(forward, Vec3::new(0.0, 1.0, 0.0)).normalized();
+    let up: Vec3 = cross(right, forward);
+    let back: Vec3 = -forward;
+    let t: Vec3 = Vec3::new(-dot(right, eye), -dot(up, eye), -dot(back, eye));
+    Mat34([
+        right.x, right.y, right.z, t.x, //
+        up.x, up.y, up.z, t.y, //
+        back.x, back.y, back.z, t.z, //
+    ])
+    .as_mat44()
+}
+
+// Appends an axis-aligned box as a separate section with the given material.
+// The texture coordinates are in world units scaled by tex_scale, so the textures tile uniformly over the faces.
+fn add_box(mesh: &mut MeshData, min: Vec3, max: Vec3, material_index: usize, tex_scale: f32) {
+    let start_index: usize = mesh.indices.len();
+    let faces: [(Vec3, Vec3, Vec3); 6] = [
+        (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0)),
+        (Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 1.0, 0.0)),
+        (Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)),
+        (Vec3::new(0.0, -1.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0)),
+        (Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+        (Vec3::new(0.0, 0.0, -1.0), Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+    ];
+    let center: Vec3 = (min + max) * 0.5;
+    let half: Vec3 = (max - min) * 0.5;
+    for (normal, u_axis, v_axis) in faces {
+        let base: u32 = mesh.positions.len() as u32;
+        let face_center: Vec3 = center + normal * dot(half, normal * normal);
+        let u_extent: f32 = dot(half, u_axis * u_axis);
+        let v_extent: f32 = dot(half, v_axis * v_axis);
+        for (su, sv) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+            let position: Vec3 = face_center + u_axis * (su * u_extent) + v_axis * (sv * v_extent);
+            mesh.positions.push(position);
+            mesh.normals.push(normal);
+            mesh.tex_coords
+                .push(Vec2::new(dot(position, u_axis) * tex_scale, -dot(position, v_axis) * tex_scale));
+        }
+        mesh.indices
+            .extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+    mesh.sections.push(MeshDataSection {
+        start_index,
+        num_triangles: (mesh.indices.len() - start_index) / 3,
+        material_index,
+        ..Default::default()
+    });
+}
+
+// The level: a 24x24 courtyard enclosed by walls, with two colonnades carrying the galleries, banners hanging between
+// the columns and a few crates on the floor.
+fn build_level() -> MeshData {
+    let mut mesh = MeshData::default();
+    add_box(&mut mesh, Vec3::new(-12.0, -0.5, -12.0), Vec3::new(12.0, 0.0, 12.0), MATERIAL_FLOOR, 0.25);
+    add_box(&mut mesh, Vec3::new(-12.0, 0.0, -12.5), Vec3::new(12.0, 10.0, -12.0), MATERIAL_STONE, 0.25);
+    add_box(&mut mesh, Vec3::new(-12.5, 0.0, -12.0), Vec3::new(-12.0, 10.0, 12.0), MATERIAL_STONE, 0.25);
+    add_box(&mut mesh, Vec3::new(12.0, 0.0, -12.0), Vec3::new(12.5, 10.0, 12.0), MATERIAL_STONE, 0.25);
+    for side in [-1.0f32, 1.0] {
+        let x: f32 = side * 5.0;
+        for i in 0..6 {
+            let z: f32 = -10.0 + i as f32 * 4.0;
+            add_box(&mut mesh, Vec3::new(x - 0.4, 0.0, z - 0.4), Vec3::new(x + 0.4, 6.0, z + 0.4), MATERIAL_STONE, 0.5);
+            if i % 2 == 1 {
+                add_box(
+                    &mut mesh,
+                    Vec3::new(x - 0.05, 3.0, z + 0.6),
+                    Vec3::new(x + 0.05, 5.8, z + 3.4),
+                    MATERIAL_BANNER,
+                    0.35,
+                );
+            }
+        }
+        // The gallery on top of the colonnade and its parapet
+        let (inner, outer): (f32, f32) = (x - side * 0.5, side * 12.0);
+        add_box(
+            &mut mesh,
+            Vec3::new(inner.min(outer), 6.0, -11.0),
+            Vec3::new(inner.max(outer), 6.6, 11.0),
+            MATERIAL_STONE,
+            0.25,
+        );
+        add_box(
+            &mut mesh,
+            Vec3::new(inner.min(inner + side * 0.3), 6.6, -11.0),
+            Vec3::new(inner.max(inner + side * 0.3), 7.6, 11.0),
+            MATERIAL_STONE,
+            0.25,
+        );
+    }
+    add_box(&mut mesh, Vec3::new(-1.5, 0.0, -2.0), Vec3::new(0.0, 1.5, -0.5), MATERIAL_WOOD, 0.6);
+    add_box(&mut mesh, Vec3::new(0.2, 0.0, -2.5), Vec3::new(1.2, 1.0, -1.5), MATERIAL_WOOD, 0.6);
+    add_box(&mut mesh, Vec3::new(-1.0, 1.5, -1.7), Vec3::new(-0.2, 2.3, -0.9), MATERIAL_WOOD, 0.6);
+    mesh.aabb = AABB::from_points(&mesh.positions);
+    mesh
+}
+
+// A deterministic per-texel hash in [0, 1].
+fn hash(x: u32, y: u32, seed: u32) -> f32 {
+    let mut h: u32 = x.wrapping_mul(0x8da6b343) ^ y.wrapping_mul(0xd8163841) ^ seed.wrapping_mul(0xcb1ab31f);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1e995);
+    h ^= h >> 15;
+    (h & 0xFFFF) as f32 / 65535.0
+}
+
+fn procedural_texture(size: u32, texel: impl Fn(u32, u32) -> Vec3) -> Arc<Texture> {
+    let mut texels: Vec<u8> = Vec::with_capacity((size * size * 3) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let color: Vec3 = texel(x, y).clamped(0.0, 1.0) * 255.0;
+            texels.extend_from_slice(&[color.x as u8, color.y as u8, color.z as u8]);
+        }
+    }
+    Texture::new(&TextureSource {
+        texels: &texels,
+        width: size,
+        height: size,
+        format: TextureFormat::RGB,
+        ..Default::default()
+    })
+}
+
+fn build_materials() -> Vec<Arc<Texture>> {
+    let floor = procedural_texture(64, |x, y| {
+        // 2x2 marble tiles with grout lines
+        let light: bool = ((x / 32) + (y / 32)) % 2 == 0;
+        let base: Vec3 = if light {
+            Vec3::new(0.85, 0.82, 0.75)
+        } else {
+            Vec3::new(0.35, 0.3, 0.28)
+        };
+        let grout: bool = x % 32 == 0 || y % 32 == 0;
+        if grout {
+            Vec3::new(0.2, 0.2, 0.2)
+        } else {
+            base * (0.9 + 0.1 * hash(x, y, 1))
+        }
+    });
+    let stone = procedural_texture(64, |x, y| {
+        // Running bond bricks, 32x16 texels each
+        let row: u32 = y / 16;
+        let shifted_x: u32 = x + if row % 2 == 1 { 16 } else { 0 };
+        let mortar: bool = y % 16 == 0 || shifted_x.is_multiple_of(32);
+        let tint: f32 = 0.85 + 0.15 * hash(shifted_x / 32, row, 2);
+        if mortar {
+            Vec3::new(0.45, 0.43, 0.4)
+        } else {
+            Vec3::new(0.76, 0.68, 0.55) * tint * (0.92 + 0.08 * hash(x, y, 3))
+        }
+    });
+    let banner = procedural_texture(64, |x, _| {
+        // Red with a golden stripe
+        if (26..38).contains(&x) {
+            Vec3::new(0.85, 0.65, 0.15)
+        } else {
+            Vec3::new(0.6, 0.05, 0.05)
+        }
+    });
+    let wood = procedural_texture(64, |x, y| {
+        // Planks with the grain along V
+        let plank: u32 = x / 16;
+        let grain: f32 = 0.85 + 0.15 * ((y as f32 * 0.4 + hash(plank, 0, 4) * 10.0).sin() * 0.5 + 0.5);
+        if x % 16 == 0 {
+            Vec3::new(0.2, 0.12, 0.05)
+        } else {
+            Vec3::new(0.55, 0.36, 0.18) * grain
+        }
+    });
+    vec![floor, stone, banner, wood]
+}
+
+// A vertical gradient from the zenith to the horizon, stretched over the viewport.
+fn build_sky() -> Arc<Texture> {
+    procedural_texture(64, |_, y| {
+        let t: f32 = y as f32 / 63.0;
+        Vec3::new(0.25, 0.45, 0.85) * (1.0 - t) + Vec3::new(0.75, 0.85, 0.95) * t
+    })
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self {
+            level: build_level(),
+            materials: build_materials(),
+            sky: build_sky(),
+            sun_direction: Vec3::new(-0.45, -1.0, -0.35).normalized(),
+            sun_color: Vec3::new(3.0, 2.8, 2.5),
+            ambient_color: Vec3::new(0.25, 0.3, 0.4),
+        }
+    }
+
+    pub fn triangles_num(&self) -> usize {
+        self.level.indices.len() / 3
+    }
+
+    // The sun's view and orthographic projection, covering the whole level.
+    fn sun_view_projection(&self) -> Mat44 {
+        let center: Vec3 = (self.level.aabb.min + self.level.aabb.max) * 0.5;
+        let radius: f32 = (self.level.aabb.max - self.level.aabb.min).length() * 0.5;
+        let view: Mat44 = look_at(center - self.sun_direction * (radius * 2.0), center);
+        let projection: Mat44 = Mat44::orthographic(-radius, radius, -radius, radius, radius, radius * 3.0);
+        projection * view
+    }
+
+    fn commit_level(&self, rasterizer: &mut Rasterizer, view: Mat44, projection: Mat44, textured: bool) {
+        for section in &self.level.sections {
+            let indices: &[u32] =
+                &self.level.indices[section.start_index..section.start_index + section.num_triangles * 3];
+            rasterizer.commit(&RasterizationCommand {
+                world_positions: &self.level.positions,
+                normals: &self.level.normals,
+                tex_coords: &self.level.tex_coords,
+                indices,
+                view,
+                projection,
+                culling: CullMode::CW,
+                texture: if textured {
+                    Some(self.materials[section.material_index].clone())
+                } else {
+                    None
+                },
+                sampling_filter: SamplerFilter::Trilinear,
+                opacity: OpacityHint::Opaque,
+                ..Default::default()
+            });
+        }
+    }
+
+    // Renders the scene from the camera into frame.ldr, going through all the passes.
+    pub fn render(&self, frame: &mut Frame, camera: &Camera) {
+        let (width, height): (u16, u16) = (frame.width, frame.height);
+        let rasterizer: &mut Rasterizer = &mut frame.rasterizer;
+        rasterizer.set_depth_sorting(true);
+
+        // 1. Shadow pass
+        let sun_view_projection: Mat44 = self.sun_view_projection();
+        frame.shadow_map.fill(DEPTH_FAR);
+        rasterizer.setup(Viewport::new(0, 0, SHADOW_MAP_SIZE, SHADOW_MAP_SIZE));
+        self.commit_level(rasterizer, Mat44::identity(), sun_view_projection, false);
+        rasterizer.draw(&mut Framebuffer { depth_buffer: Some(&mut frame.shadow_map), ..Default::default() });
+
+        // 2. Geometry pass
+        let view: Mat44 = look_at(camera.position, camera.target);
+        let projection: Mat44 = Mat44::perspective(0.1, 100.0, camera.fov_y, width as f32 / height as f32);
+        frame.depth.fill(DEPTH_FAR);
+        frame.normals.fill(0);
+        rasterizer.setup(Viewport::new(0, 0, width, height));
+        rasterizer.commit_fullscreen(&FullscreenCommand {
+            texture: Some(self.sky.clone()),
+            sampling_filter: SamplerFilter::Bilinear,
+            ..Default::default()
+        });
+        self.commit_level(rasterizer, view, projection, true);
+        rasterizer.draw(&mut Framebuffer {
+            color_buffer: Some(&mut frame.albedo),
+            depth_buffer: Some(&mut frame.depth),
+            normal_buffer: Some(&mut frame.normals),
+            ..Default::default()
+        });
+
+        // 3. Lighting pass
+        let inverse_view_projection: Mat44 = (projection * view).inverse();
+        let to_sun: Vec3 = -self.sun_direction;
+        for y in 0..height {
+            for x in 0..width {
+                let albedo: RGBA = RGBA::from_u32(frame.albedo.at(x, y));
+                let albedo: Vec3 = Vec3::new(albedo.r as f32, albedo.g as f32, albedo.b as f32) / 255.0;
+                let depth: u16 = frame.depth.at(x, y);
+                let radiance: Vec3 = if depth == DEPTH_FAR {
+                    // The sky is emissive
+                    albedo * 1.5
+                } else {
+                    let ndc: Vec4 = Vec4::new(
+                        (x as f32 + 0.5) / width as f32 * 2.0 - 1.0,
+                        1.0 - (y as f32 + 0.5) / height as f32 * 2.0,
+                        decode_depth(depth),
+                        1.0,
+                    );
+                    let world: Vec4 = inverse_view_projection * ndc;
+                    let world: Vec3 = world.xyz() / world.w;
+                    let encoded: RGBA = RGBA::from_u32(frame.normals.at(x, y));
+                    let normal: Vec3 = (Vec3::new(encoded.r as f32, encoded.g as f32, encoded.b as f32)
+                        - Vec3::new(127.5, 127.5, 127.5))
+                        / 127.5;
+                    let n_dot_l: f32 = dot(normal, to_sun).max(0.0);
+                    let lit: f32 = if n_dot_l > 0.0 {
+                        self.sun_visibility(&frame.shadow_map, sun_view_projection, world)
+                    } else {
+                        0.0
+                    };
+                    albedo * (self.sun_color * (n_dot_l * lit) + self.ambient_color)
+                };
+                *frame.hdr.at_mut(x, y) = encode_rgb9e5(radiance.x, radiance.y, radiance.z);
+            }
+        }
+
+        // 4. Post pass
+        tone_map(
+            &frame.hdr,
+            &mut frame.ldr,
+            &ToneMapping { operator: ToneMappingOperator::Aces, exposure: 0.6, ..Default::default() },
+        );
+    }
+
+    // The fraction of the sun visible from the world position, filtered over 2x2 shadow map texels.
+    fn sun_visibility(&self, shadow_map: &TiledBuffer<u16, 64, 64>, sun_view_projection: Mat44, world: Vec3) -> f32 {
+        const BIAS: f32 = 150.0;
+        let clip: Vec4 = sun_view_projection * world.as_point4();
+        let size: f32 = SHADOW_MAP_SIZE as f32;
+        let sx: f32 = (clip.x * 0.5 + 0.5) * size - 0.5;
+        let sy: f32 = (0.5 - clip.y * 0.5) * size - 0.5;
+        let depth: f32 = (clip.z * 0.5 + 0.5) * 65535.0 - BIAS;
+        let mut visible: f32 = 0.0;
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let tx: u16 = (sx.floor() as i32 + dx).clamp(0, SHADOW_MAP_SIZE as i32 - 1) as u16;
+            let ty: u16 = (sy.floor() as i32 + dy).clamp(0, SHADOW_MAP_SIZE as i32 - 1) as u16;
+            if depth <= shadow_map.at(tx, ty) as f32 {
+                visible += 0.25;
+            }
+        }
+        visible
+    }
+}
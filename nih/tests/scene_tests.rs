@@ -0,0 +1,57 @@
+mod scene;
+
+use nih::render::*;
+use nih::testing::*;
+use scene::Camera;
+use scene::*;
+use std::path::Path;
+
+fn reference_path(reference: &str) -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/reference_images/scene/")
+        .join(reference)
+}
+
+// Compares the frame against the reference, saves it next to the reference if they differ or if there's no reference
+// yet. The scene consists of many textured triangles, so a few pixels along the edges are allowed to differ.
+fn assert_against_reference(frame: &TiledBuffer<u32, 64, 64>, reference: &str) {
+    let tolerance = Tolerance { mismatched_pixels: 16, ..Default::default() };
+    assert_colors_against_reference(&frame.as_flat_buffer(), reference_path(reference), &tolerance);
+}
+
+#[test]
+fn atrium_from_fixed_camera() {
+    let scene = Scene::new();
+    let mut frame = Frame::new(320, 180);
+    scene.render(&mut frame, &Camera::fixed());
+    assert_against_reference(&frame.ldr, "atrium.png");
+
+    // Every triangle of the level is committed, the back faces and the ones outside the view are culled
+    let statistics: RasterizerStatistics = frame.rasterizer.statistics();
+    assert_eq!(statistics.committed_triangles, scene.triangles_num());
+    assert!(statistics.scheduled_triangles < statistics.committed_triangles);
+    // The level is textured, so its triangles crossing the viewport's sides are clipped regardless of the guard band
+    assert!(statistics.side_clipped_triangles > 0);
+    assert!(statistics.side_clipped_triangles <= statistics.clipped_triangles);
+}
+
+#[test]
+fn atrium_is_deterministic() {
+    let scene = Scene::new();
+    let mut frame = Frame::new(200, 120);
+    scene.render(&mut frame, &Camera::fixed());
+    let first: Vec<u32> = frame.ldr.values().to_vec();
+    scene.render(&mut frame, &Camera::fixed());
+    assert!(first == frame.ldr.values());
+}
+
+#[test]
+fn atrium_single_threaded_matches_parallel() {
+    let scene = Scene::new();
+    let mut frame = Frame::new(200, 120);
+    scene.render(&mut frame, &Camera::fixed());
+    let parallel: Vec<u32> = frame.ldr.values().to_vec();
+    frame.rasterizer.set_multithreading(false);
+    scene.render(&mut frame, &Camera::fixed());
+    assert!(parallel == frame.ldr.values());
+}
@@ -0,0 +1,272 @@
+// A paint backend for egui: draws the tessellated egui output with nih's software rasterizer.
+// Every egui mesh becomes a RasterizationCommand with per-vertex colors, the clip rectangle as the scissor and the
+// Normal alpha blending, so the UI is ordered with whatever else is committed into the same rasterizer.
+//
+// Typical frame:
+//   raw_input.max_texture_side = Some(nih_egui::MAX_TEXTURE_SIDE);
+//   let output = ctx.run(raw_input, |ctx| { ... });
+//   painter.update_textures(&output.textures_delta);
+//   let primitives = ctx.tessellate(output.shapes, output.pixels_per_point);
+//   painter.paint(&mut rasterizer, &primitives, output.pixels_per_point);
+//   rasterizer.draw(&mut framebuffer);
+use egui::epaint::{ImageData, ImageDelta, Primitive};
+use egui::{ClippedPrimitive, TextureFilter, TextureId, TexturesDelta};
+use nih::math::*;
+use nih::render::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// nih's samplers support textures up to 1024x1024, egui must be told to keep its font atlas within that limit.
+pub const MAX_TEXTURE_SIDE: usize = 1024;
+
+// An egui texture: the unmultiplied RGBA texels as egui sees them and the nih texture they are uploaded into.
+struct PainterTexture {
+    width: usize,
+    height: usize,
+    texels: Vec<u8>,
+    texture: Arc<Texture>,
+
+    // nih textures are square with power-of-two sides, the image occupies the top-left part of the texture
+    region: TextureRegion,
+    sampling_filter: SamplerFilter,
+}
+
+#[derive(Default)]
+pub struct Painter {
+    textures: HashMap<TextureId, PainterTexture>,
+
+    // Scratch buffers reused between the meshes
+    positions: Vec<Vec3>,
+    tex_coords: Vec<Vec2>,
+    colors: Vec<Vec4>,
+}
+
+impl Painter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Uploads the new and the updated textures and frees the unused ones.
+    // Must be called before paint() with the textures delta of the same frame.
+    pub fn update_textures(&mut self, delta: &TexturesDelta) {
+        for (id, image_delta) in &delta.set {
+            self.set_texture(*id, image_delta);
+        }
+        for id in &delta.free {
+            self.textures.remove(id);
+        }
+    }
+
+    // Commits the clipped primitives into the rasterizer, which must have been set up with the viewport the UI is
+    // drawn into. egui's positions are in points relative to the viewport's top-left corner, they are scaled by
+    // pixels_per_point.
+    // Paint callbacks are not supported and are skipped, so are the meshes with unknown textures.
+    pub fn paint(&mut self, rasterizer: &mut Rasterizer, primitives: &[ClippedPrimitive], pixels_per_point: f32) {
+        let viewport: Viewport = rasterizer.viewport();
+        let width: f32 = (viewport.xmax - viewport.xmin) as f32;
+        let height: f32 = (viewport.ymax - viewport.ymin) as f32;
+        let projection: Mat44 = Mat44::orthographic(0.0, width, height, 0.0, -1.0, 1.0);
+        for primitive in primitives {
+            let mesh = match &primitive.primitive {
+                Primitive::Mesh(mesh) => mesh,
+                Primitive::Callback(_) => continue,
+            };
+            if mesh.indices.is_empty() {
+                continue;
+            }
+            let Some(texture) = self.textures.get(&mesh.texture_id) else {
+                continue;
+            };
+
+            // The clip rectangle in points -> the scissor in framebuffer pixels
+            let clip = &primitive.clip_rect;
+            let xmin: f32 = (clip.min.x * pixels_per_point).round().clamp(0.0, width);
+            let ymin: f32 = (clip.min.y * pixels_per_point).round().clamp(0.0, height);
+            let xmax: f32 = (clip.max.x * pixels_per_point).round().clamp(0.0, width);
+            let ymax: f32 = (clip.max.y * pixels_per_point).round().clamp(0.0, height);
+            if xmax <= xmin || ymax <= ymin {
+                continue;
+            }
+            let scissor = Viewport::new(
+                viewport.xmin + xmin as u16,
+                viewport.ymin + ymin as u16,
+                viewport.xmin + xmax as u16,
+                viewport.ymin + ymax as u16,
+            );
+
+            self.positions.clear();
+            self.tex_coords.clear();
+            self.colors.clear();
+            for vertex in &mesh.vertices {
+                // egui's colors are premultiplied, while nih's Normal blending expects them not to be
+                let [r, g, b, a] = vertex.color.to_srgba_unmultiplied();
+                self.positions
+                    .push(Vec3::new(vertex.pos.x * pixels_per_point, vertex.pos.y * pixels_per_point, 0.0));
+                self.tex_coords.push(Vec2::new(vertex.uv.x, vertex.uv.y));
+                self.colors
+                    .push(Vec4::new(r as f32, g as f32, b as f32, a as f32) / 255.0);
+            }
+
+            rasterizer.commit(&RasterizationCommand {
+                world_positions: &self.positions,
+                tex_coords: &self.tex_coords,
+                colors: &self.colors,
+                indices: &mesh.indices,
+                projection,
+                texture: Some(texture.texture.clone()),
+                texture_region: Some(texture.region),
+                sampling_filter: texture.sampling_filter,
+                alpha_blending: AlphaBlendingMode::Normal,
+                scissor: Some(scissor),
+                ..Default::default()
+            });
+        }
+    }
+
+    fn set_texture(&mut self, id: TextureId, delta: &ImageDelta) {
+        let [width, height] = delta.image.size();
+        let texels: Vec<u8> = match &delta.image {
+            ImageData::Color(image) => image.pixels.iter().flat_map(|c| c.to_srgba_unmultiplied()).collect(),
+            ImageData::Font(image) => image
+                .srgba_pixels(None)
+                .flat_map(|c| c.to_srgba_unmultiplied())
+                .collect(),
+        };
+        let sampling_filter: SamplerFilter = match delta.options.magnification {
+            TextureFilter::Nearest => SamplerFilter::Nearest,
+            TextureFilter::Linear => SamplerFilter::Bilinear,
+        };
+
+        match delta.pos {
+            // A partial update of an existing texture, e.g. new glyphs added into the font atlas
+            Some([x, y]) => {
+                let Some(existing) = self.textures.get_mut(&id) else {
+                    return;
+                };
+                assert!(x + width <= existing.width && y + height <= existing.height);
+                for row in 0..height {
+                    let src = &texels[row * width * 4..(row + 1) * width * 4];
+                    let dst_offset: usize = ((y + row) * existing.width + x) * 4;
+                    existing.texels[dst_offset..dst_offset + width * 4].copy_from_slice(src);
+                }
+                (existing.texture, existing.region) = upload(existing.width, existing.height, &existing.texels);
+                existing.sampling_filter = sampling_filter;
+            }
+            None => {
+                let (texture, region) = upload(width, height, &texels);
+                self.textures
+                    .insert(id, PainterTexture { width, height, texels, texture, region, sampling_filter });
+            }
+        }
+    }
+}
+
+// Creates the nih texture from the RGBA texels, padding them to a square power-of-two size.
+// Returns the texture and the region occupied by the image.
+fn upload(width: usize, height: usize, texels: &[u8]) -> (Arc<Texture>, TextureRegion) {
+    let side: usize = width.max(height).max(1).next_power_of_two();
+    assert!(
+        side <= MAX_TEXTURE_SIDE,
+        "egui texture {}x{} exceeds the maximum side of {}, set RawInput::max_texture_side",
+        width,
+        height,
+        MAX_TEXTURE_SIDE
+    );
+    let mut padded: Vec<u8> = vec![0u8; side * side * 4];
+    for row in 0..height {
+        padded[row * side * 4..row * side * 4 + width * 4]
+            .copy_from_slice(&texels[row * width * 4..(row + 1) * width * 4]);
+    }
+    // The UI is drawn at its native resolution, so the mips would only cost time on every atlas update
+    let texture = Texture::new_with_options(
+        &TextureSource {
+            texels: &padded,
+            width: side as u32,
+            height: side as u32,
+            format: TextureFormat::RGBA,
+            ..Default::default()
+        },
+        &TextureOptions { mip_generation: MipGeneration::None, ..Default::default() },
+    );
+    let region = TextureRegion {
+        u0: 0.0,
+        v0: 0.0,
+        u1: width as f32 / side as f32,
+        v1: height as f32 / side as f32,
+        clamp: true,
+    };
+    (texture, region)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::epaint::{ColorImage, Mesh};
+    use egui::{Color32, Pos2, Rect};
+
+    fn white_texture_delta() -> TexturesDelta {
+        TexturesDelta {
+            set: vec![(
+                TextureId::default(),
+                ImageDelta::full(ColorImage::new([3, 2], Color32::WHITE), egui::TextureOptions::NEAREST),
+            )],
+            free: vec![],
+        }
+    }
+
+    fn quad(min: Pos2, max: Pos2, color: Color32) -> Mesh {
+        let mut mesh = Mesh::default();
+        mesh.add_colored_rect(Rect::from_min_max(min, max), color);
+        mesh
+    }
+
+    fn draw(painter: &mut Painter, primitives: &[ClippedPrimitive], pixels_per_point: f32) -> TiledBuffer<u32, 64, 64> {
+        let mut color_buffer = TiledBuffer::<u32, 64, 64>::new(100, 100);
+        color_buffer.fill(RGBA::new(0, 0, 0, 255).to_u32());
+        let mut rasterizer = Rasterizer::new();
+        rasterizer.setup(Viewport::new(0, 0, 100, 100));
+        painter.paint(&mut rasterizer, primitives, pixels_per_point);
+        rasterizer.draw(&mut Framebuffer { color_buffer: Some(&mut color_buffer), ..Default::default() });
+        color_buffer
+    }
+
+    #[test]
+    fn textures_are_padded_to_power_of_two() {
+        let mut painter = Painter::new();
+        painter.update_textures(&white_texture_delta());
+        let texture = &painter.textures[&TextureId::default()];
+        assert_eq!(texture.texture.mips[0].width, 4);
+        assert_eq!(texture.region.u1, 0.75);
+        assert_eq!(texture.region.v1, 0.5);
+
+        painter.update_textures(&TexturesDelta { set: vec![], free: vec![TextureId::default()] });
+        assert!(painter.textures.is_empty());
+    }
+
+    #[test]
+    fn meshes_are_clipped_and_blended() {
+        let mut painter = Painter::new();
+        painter.update_textures(&white_texture_delta());
+        let primitives = [
+            ClippedPrimitive {
+                clip_rect: Rect::from_min_max(Pos2::new(0.0, 0.0), Pos2::new(25.0, 50.0)),
+                primitive: Primitive::Mesh(quad(Pos2::new(5.0, 5.0), Pos2::new(45.0, 45.0), Color32::RED)),
+            },
+            ClippedPrimitive {
+                clip_rect: Rect::EVERYTHING,
+                primitive: Primitive::Mesh(quad(
+                    Pos2::new(0.0, 0.0),
+                    Pos2::new(10.0, 10.0),
+                    Color32::from_rgba_unmultiplied(0, 0, 255, 128),
+                )),
+            },
+        ];
+        // 2 pixels per point
+        let color_buffer = draw(&mut painter, &primitives, 2.0);
+        assert_eq!(RGBA::from_u32(color_buffer.at(30, 30)), RGBA::new(255, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(49, 80)), RGBA::new(255, 0, 0, 255));
+        assert_eq!(RGBA::from_u32(color_buffer.at(50, 80)), RGBA::new(0, 0, 0, 255));
+        let blended = RGBA::from_u32(color_buffer.at(15, 15));
+        assert!(blended.r.abs_diff(127) <= 2 && blended.b.abs_diff(128) <= 2, "{:?}", blended);
+    }
+}